@@ -29,13 +29,28 @@ defmodule TripleStore.Backend.RocksDB.NIF do
 
   @skip_compilation System.get_env("RUSTLER_SKIP_COMPILATION") == "1"
 
+  # Write fault injection is compiled in for the test suite only
+  @features if Mix.env() == :test, do: ["fault_injection"], else: []
+
   use Rustler,
     otp_app: :triple_store,
     crate: "rocksdb_nif",
+    features: @features,
     skip_compilation?: @skip_compilation
 
   @type db_ref :: reference()
-  @type column_family :: :id2str | :str2id | :spo | :pos | :osp | :derived | :numeric_range
+  @type column_family ::
+          :id2str
+          | :str2id
+          | :spo
+          | :pos
+          | :osp
+          | :derived
+          | :numeric_range
+          | :gspo
+          | :gpos
+          | :gosp
+          | String.t()
 
   @doc """
   Verifies that the NIF is loaded correctly.
@@ -74,792 +89,3882 @@ defmodule TripleStore.Backend.RocksDB.NIF do
   @spec open(String.t()) :: {:ok, db_ref()} | {:error, {:open_failed, String.t()}}
   def open(_path), do: :erlang.nif_error(:nif_not_loaded)
 
+  @typedoc "Memtable representation for a column family"
+  @type memtable_kind :: :skiplist | :hash_skiplist | :vector
+
+  @typedoc "SST index block layout accepted by `open/2`"
+  @type index_type :: :binary_search | :hash_search | :two_level_index_search
+
+  @typedoc "Per-column-family option accepted by `open/2`"
+  @type cf_open_option ::
+          {:memtable, memtable_kind()}
+          | {:prefix_length, pos_integer()}
+          | {:merge_operator, :int64_add}
+          | {:index_type, index_type()}
+          | {:format_version, 0..6}
+          | {:enable_user_timestamps, boolean()}
+
+  @typedoc "Database-wide option accepted by `open/2`"
+  @type db_open_option :: {:atomic_flush, boolean()}
+
   @doc """
-  Closes the database and releases all resources.
+  Opens a RocksDB database with per-column-family option overrides.
 
-  After calling close, the database handle is no longer valid.
-  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+  Uses the same tuned defaults as `open/1`, then applies the given overrides
+  to individual column families.
 
-  ## Arguments
-  - `db_ref` - The database reference to close
+  ## Memtable Representations
 
-  ## Returns
-  - `:ok` on success
-  - `{:error, :already_closed}` if already closed
+  - `:skiplist` (default) - Ordered and supports concurrent writes. Good at
+    both point lookups and range scans; the right choice for index CFs.
+  - `:hash_skiplist` - Hashes keys by prefix into buckets of skiplists.
+    Faster point lookups within a prefix (e.g. `:str2id`), but scans across
+    prefixes are expensive. Requires a prefix extractor: index CFs have one,
+    other CFs need `{:prefix_length, n}`.
+  - `:vector` - Unsorted append-only vector, sorted on flush. Fastest for
+    bulk inserts, slow for reads served from the memtable.
 
-  ## Examples
+  Selecting `:hash_skiplist` or `:vector` for any CF disables concurrent
+  memtable writes for the whole database.
 
-      iex> {:ok, db} = TripleStore.Backend.RocksDB.NIF.open("/tmp/test_db")
-      iex> TripleStore.Backend.RocksDB.NIF.close(db)
-      :ok
+  ## Merge Operators
 
-  """
-  @spec close(db_ref()) :: :ok | {:error, :already_closed}
-  def close(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+  - `{:merge_operator, :int64_add}` - Treats values as little-endian i64 and
+    sums merge operands. A CF must have a merge operator for `merge/4`.
 
-  @doc """
-  Flushes the Write-Ahead Log (WAL) to disk.
+  ## Table Format
 
-  This ensures all buffered writes are persisted to the WAL. When `sync` is `true`,
-  it also calls fsync to ensure data is physically written to storage.
+  `{:index_type, kind}` selects the SST index block layout:
 
-  Use this after bulk loading with `sync: false` to ensure all data is durable
-  before considering the load complete.
+  - `:binary_search` (default) - Compact, binary-searched index. Suits the
+    index CFs (`:spo`, `:pos`, `:osp`): range scans seek once and then read
+    sequentially, so a faster seek buys little.
+  - `:hash_search` - Hash lookup on the key prefix before the binary search.
+    Speeds up point lookups, but requires a prefix extractor like
+    `:hash_skiplist` does.
+  - `:two_level_index_search` - Partitioned index loaded on demand. Keeps
+    index memory bounded for large point-lookup CFs such as `:str2id` and
+    `:id2str`.
+
+  `{:format_version, n}` sets the block-based table format version (0..6).
+  Newer versions store indexes and filters more compactly, but their files
+  can't be read by older RocksDB releases.
+
+  `{:enable_user_timestamps, true}` is reserved for RocksDB user timestamps on
+  `:derived` (see `put_ts/5`); the linked RocksDB doesn't support them yet, so
+  it returns `{:error, :timestamps_unsupported}`.
+
+  ## Atomic Flush
+
+  `atomic_flush: true` is a database-wide option given next to the column
+  family entries. It makes RocksDB flush the memtables of all column families
+  together, both for automatic flushes and for `sync_dir/1`, so a crash can't
+  leave `:spo` flushed but `:pos` not. Writes that go through the WAL (every
+  write this module issues today) are replayed on recovery either way; atomic
+  flush is what keeps the indexes in agreement for writes made with the WAL
+  disabled, since those only survive through flushed SST files.
 
   ## Arguments
-  - `db_ref` - The database reference
-  - `sync` - When `true`, calls fsync after flushing (fully durable). When `false`,
-    only flushes to OS buffer cache.
+  - `path` - Path to the database directory
+  - `cf_options` - List of `{cf, [cf_open_option]}` tuples and
+    `db_open_option` tuples
 
   ## Returns
-  - `:ok` on success
-  - `{:error, :already_closed}` if database is closed
-  - `{:error, {:flush_failed, reason}}` on failure
+  - `{:ok, db_ref}` on success
+  - `{:error, {:invalid_cf, cf}}` if a column family is unknown
+  - `{:error, {:invalid_option, option}}` if an option or value is not recognized
+  - `{:error, :prefix_extractor_required}` if `:hash_skiplist` or `:hash_search`
+    is used without a prefix extractor
+  - `{:error, :timestamps_unsupported}` if user timestamps are requested
+  - `{:error, {:open_failed, reason}}` on failure
 
   ## Examples
 
-      iex> {:ok, db} = NIF.open("/tmp/test_db")
-      iex> NIF.write_batch(db, [{:spo, "key", "value"}], false)
-      :ok
-      iex> NIF.flush_wal(db, true)
-      :ok
+      iex> {:ok, db} = NIF.open("/tmp/test_db", str2id: [memtable: :hash_skiplist, prefix_length: 4])
+      iex> is_reference(db)
+      true
+
+      iex> {:ok, db} = NIF.open("/tmp/test_db", atomic_flush: true)
+      iex> is_reference(db)
+      true
 
   """
-  @spec flush_wal(db_ref(), boolean()) :: :ok | {:error, term()}
-  def flush_wal(_db_ref, _sync), do: :erlang.nif_error(:nif_not_loaded)
+  @spec open(String.t(), [{column_family(), [cf_open_option()]} | db_open_option()]) ::
+          {:ok, db_ref()} | {:error, term()}
+  def open(_path, _cf_options), do: :erlang.nif_error(:nif_not_loaded)
+
+  @typedoc "Settings accepted by `open_with_options/2`"
+  @type open_settings :: %{
+          optional(:block_cache_bytes) => non_neg_integer(),
+          optional(:write_buffer_bytes) => pos_integer(),
+          optional(:max_open_files) => integer(),
+          optional(:compression) => compression(),
+          optional(:cf_options) => %{optional(column_family()) => cf_settings()},
+          optional(atom()) => term()
+        }
+
+  @typedoc "Compression algorithm accepted by `open_with_options/2`"
+  @type compression :: :none | :snappy | :lz4 | :zstd
+
+  @typedoc "Per-column-family settings in the `:cf_options` map of `open_with_options/2`"
+  @type cf_settings :: %{
+          optional(:compression) => compression(),
+          optional(:bloom_filter_bits) => non_neg_integer(),
+          optional(atom()) => term()
+        }
 
   @doc """
-  Sets RocksDB options on all column families at runtime.
-
-  This allows dynamic reconfiguration of RocksDB settings without restarting.
-  Options are passed as a list of {key, value} tuples where both key and value
-  are strings.
-
-  ## Mutable Options (subset)
-  - `"level0_file_num_compaction_trigger"` - Files in L0 to trigger compaction
-  - `"level0_slowdown_writes_trigger"` - Files in L0 to slow down writes
-  - `"level0_stop_writes_trigger"` - Files in L0 to stop writes
-  - `"target_file_size_base"` - Target file size in bytes
-  - `"max_bytes_for_level_base"` - Maximum bytes in base level
-  - `"write_buffer_size"` - Size of write buffer (for new memtables)
-  - `"max_write_buffer_number"` - Maximum number of write buffers
-  - `"disable_auto_compactions"` - Disable automatic compactions ("true"/"false")
+  Opens a RocksDB database with tunable resource settings.
+
+  Every key is optional, and unknown keys are ignored so new settings can be
+  added without breaking callers. Everything not covered by a key matches
+  `open/1`, which behaves like `open_with_options(path, %{})`.
+
+  ## Settings
+
+  - `:block_cache_bytes` - Capacity of a single LRU block cache shared by all
+    column families. Without it RocksDB gives each column family its own 8MB
+    cache, so this is the way to cap block cache memory.
+  - `:write_buffer_bytes` - Memtable size per column family before it flushes.
+  - `:max_open_files` - Limit on open SST file handles; `-1` keeps them all open.
+  - `:compression` - `:none`, `:snappy`, `:lz4` (default) or `:zstd`. Applies
+    to every level except L0, which stays uncompressed for write speed.
+  - `:cf_options` - Map from column family to a map of settings for that
+    column family alone (unknown keys are ignored here too):
+    - `:compression` - Overrides the database-wide compression, e.g. `:zstd`
+      for the large string blobs in `:id2str`.
+    - `:bloom_filter_bits` - Bloom filter bits per key; `0` disables the
+      filter.
+
+    Column families not in the map keep their defaults: 14 bits per key for
+    `:id2str` and `:str2id`, 12 bits for the index CFs and no filter for
+    `:derived`.
 
   ## Arguments
-  - `db_ref` - The database reference
-  - `options` - List of {key, value} tuples as strings
+  - `path` - Path to the database directory
+  - `opts` - Map of settings
 
   ## Returns
-  - `:ok` on success
-  - `{:error, :already_closed}` if database is closed
-  - `{:error, {:set_options_failed, reason}}` on failure
+  - `{:ok, db_ref}` on success
+  - `{:error, {:invalid_option, key}}` if a known key has an invalid value
+  - `{:error, {:invalid_cf, cf}}` if `:cf_options` names an unknown column family
+  - `{:error, {:open_failed, reason}}` on failure
 
   ## Examples
 
-      iex> {:ok, db} = NIF.open("/tmp/test_db")
-      iex> NIF.set_options(db, [{"level0_file_num_compaction_trigger", "16"}])
+      iex> {:ok, db} = NIF.open_with_options("/tmp/test_db", %{block_cache_bytes: 64 * 1024 * 1024, compression: :zstd})
+      iex> NIF.put(db, :spo, "key", "value")
       :ok
 
+      iex> NIF.open_with_options("/tmp/test_db", %{
+      ...>   cf_options: %{id2str: %{compression: :zstd}, derived: %{bloom_filter_bits: 10}}
+      ...> })
+      {:ok, db}
+
   """
-  @spec set_options(db_ref(), [{String.t(), String.t()}]) :: :ok | {:error, term()}
-  def set_options(_db_ref, _options), do: :erlang.nif_error(:nif_not_loaded)
+  @spec open_with_options(String.t(), open_settings()) :: {:ok, db_ref()} | {:error, term()}
+  def open_with_options(_path, _opts), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Returns the path of the database.
+  Opens a database and verifies its on-disk schema version.
+
+  The version lives in a reserved `__schema_version__` key in RocksDB's
+  `default` column family (8-byte big-endian). It deliberately does not live
+  in `derived` or any other triple-keyed column family: those are scanned and
+  decoded as triples (`TripleStore.Reasoner.DerivedStore` streams all of
+  `derived`, `TripleStore.Health` counts its entries), so a reserved key there
+  would surface as a bogus triple. A fresh database is stamped with `expected_version`;
+  an existing database must carry the same version or it is closed again and
+  an error is returned. This prevents accidentally opening an old on-disk
+  format with incompatible code.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
 
   ## Arguments
-  - `db_ref` - The database reference
+  - `path` - Path to the database directory
+  - `expected_version` - Schema version the caller understands
 
   ## Returns
-  - `{:ok, path}` with the database path
-  """
-  @spec get_path(db_ref()) :: {:ok, String.t()}
-  def get_path(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+  - `{:ok, db_ref}` on success
+  - `{:error, {:schema_mismatch, found, expected}}` if the stored version differs
+    (`found` is the raw binary if the stored value is not a valid version)
+  - `{:error, {:open_failed, reason}}` on failure
 
-  @doc """
-  Lists all column families in the database.
+  ## Examples
+
+      iex> {:ok, db} = NIF.open_versioned("/tmp/test_db", 2)
+      iex> NIF.close(db)
+      iex> NIF.open_versioned("/tmp/test_db", 3)
+      {:error, {:schema_mismatch, 2, 3}}
 
-  ## Returns
-  - List of column family atoms: `[:id2str, :str2id, :spo, :pos, :osp, :derived, :numeric_range]`
   """
-  @spec list_column_families :: [column_family()]
-  def list_column_families, do: :erlang.nif_error(:nif_not_loaded)
+  @spec open_versioned(String.t(), non_neg_integer()) ::
+          {:ok, db_ref()}
+          | {:error, {:schema_mismatch, non_neg_integer() | binary(), non_neg_integer()}}
+          | {:error, {:open_failed, String.t()}}
+  def open_versioned(_path, _expected_version), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Checks if the database is open.
+  Opens a database exposing only the listed column families.
+
+  Intended for lightweight tools, such as a dictionary inspector that only
+  touches `:id2str` and `:str2id`. The listed CFs get the same tuned options
+  as `open/1`; any operation against another column family returns
+  `{:error, {:cf_not_opened, cf}}`.
+
+  RocksDB only allows a read-write open when every column family on disk is
+  opened. Existing CFs outside the list, including unknown ones, are opened
+  with default options and hidden. A fresh database is created with only the
+  listed CFs; a later `open/1` adds the rest.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
 
   ## Arguments
-  - `db_ref` - The database reference
+  - `path` - Path to the database directory
+  - `cfs` - Column families to expose
 
   ## Returns
-  - `true` if open, `false` if closed
-  """
-  @spec is_open(db_ref()) :: boolean()
-  # credo:disable-for-next-line Credo.Check.Readability.PredicateFunctionNames
-  def is_open(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+  - `{:ok, db_ref}` on success
+  - `{:error, {:invalid_cf, cf}}` if a column family is unknown
+  - `{:error, {:open_failed, reason}}` on failure
 
-  # ============================================================================
-  # Key-Value Operations
-  # ============================================================================
+  ## Examples
+
+      iex> {:ok, db} = NIF.open_cfs("/tmp/test_db", [:id2str, :str2id])
+      iex> NIF.get(db, :spo, "key")
+      {:error, {:cf_not_opened, :spo}}
+
+  """
+  @spec open_cfs(String.t(), [column_family()]) ::
+          {:ok, db_ref()} | {:error, {:invalid_cf, atom()} | {:open_failed, String.t()}}
+  def open_cfs(_path, _cfs), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Gets a value from a column family.
+  Opens a RocksDB database with extra, caller-named column families.
+
+  The listed column families are created if missing and opened with default
+  options next to the built-in ones, which keep their tuned options. Any
+  operation that takes a column family accepts these by name binary, and
+  the built-in ones keep working with their atoms (or their names as
+  binaries). A name that no open database uses returns
+  `{:error, {:invalid_cf, name}}`; names are forgotten once every database
+  using them is closed or has dropped them.
+
+  RocksDB only allows a read-write open when every column family on disk is
+  opened, so a database holding extra column families must be reopened with
+  `open_with_cfs/2` (or `open_cfs/2`, which hides them) rather than `open/1`.
 
   Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
 
   ## Arguments
-  - `db_ref` - The database reference
-  - `cf` - The column family atom
-  - `key` - The key as a binary
+  - `path` - Path to the database directory
+  - `cf_names` - Names of the extra column families
 
   ## Returns
-  - `{:ok, value}` if found
-  - `:not_found` if key doesn't exist
-  - `{:error, :already_closed}` if database is closed
-  - `{:error, {:invalid_cf, cf}}` if column family is invalid
-  - `{:error, {:get_failed, reason}}` on other errors
+  - `{:ok, db_ref}` on success
+  - `{:error, {:invalid_cf, name}}` if a name is empty or `"default"`
+  - `{:error, {:open_failed, reason}}` on failure
 
   ## Examples
 
-      iex> {:ok, db} = NIF.open("/tmp/test_db")
-      iex> NIF.put(db, :id2str, "key1", "value1")
+      iex> {:ok, db} = NIF.open_with_cfs("/tmp/test_db", ["tenant_a"])
+      iex> NIF.put(db, "tenant_a", "key", "value")
       :ok
-      iex> NIF.get(db, :id2str, "key1")
-      {:ok, "value1"}
-      iex> NIF.get(db, :id2str, "nonexistent")
-      :not_found
+      iex> NIF.get(db, "tenant_b", "key")
+      {:error, {:invalid_cf, "tenant_b"}}
 
   """
-  @spec get(db_ref(), column_family(), binary()) ::
-          {:ok, binary()} | :not_found | {:error, term()}
-  def get(_db_ref, _cf, _key), do: :erlang.nif_error(:nif_not_loaded)
+  @spec open_with_cfs(String.t(), [String.t()]) ::
+          {:ok, db_ref()} | {:error, {:invalid_cf, String.t()} | {:open_failed, String.t()}}
+  def open_with_cfs(_path, _cf_names), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Puts a key-value pair into a column family.
+  Opens a database whose `:derived` entries expire after `ttl_seconds`.
+
+  Meant to keep stale inferences from piling up after the source data
+  changes. Only `:derived` expires; the indexes, the dictionary and every
+  other column family stay permanent. RocksDB's own TTL databases apply one
+  TTL to every column family, so instead values written to `:derived`
+  through the handle are prefixed with their write time (8 bytes, big-endian
+  Unix seconds) and a compaction filter drops the ones older than the TTL.
+  Reads strip the prefix, so callers see the values they wrote.
+
+  Expiry is lazy: an entry is only removed when compaction rewrites the
+  file holding it, so reads can still return expired entries until then.
+  `compact_range/4` forces it.
+
+  Always reopen such a database with `open_with_ttl/2`: other opens return
+  the stamped `:derived` values as stored, and values they write carry no
+  stamp and never expire. `:derived` has no merge operator on these handles,
+  so `merge/4` on it returns `{:error, :no_merge_operator}`, and
+  `swap_cf_contents/3` and `ingest_external_file/3` refuse it with
+  `{:error, :ttl_cf}`. `get_ttl/1` returns the TTL.
 
   Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
 
   ## Arguments
-  - `db_ref` - The database reference
-  - `cf` - The column family atom
-  - `key` - The key as a binary
-  - `value` - The value as a binary
+  - `path` - Path to the database directory
+  - `ttl_seconds` - Age in seconds after which `:derived` entries may expire
 
   ## Returns
-  - `:ok` on success
-  - `{:error, :already_closed}` if database is closed
-  - `{:error, {:invalid_cf, cf}}` if column family is invalid
-  - `{:error, {:put_failed, reason}}` on other errors
+  - `{:ok, db_ref}` on success
+  - `{:error, {:invalid_option, 0}}` if `ttl_seconds` is 0
+  - `{:error, {:open_failed, reason}}` on failure
 
   ## Examples
 
-      iex> {:ok, db} = NIF.open("/tmp/test_db")
-      iex> NIF.put(db, :id2str, "key1", "value1")
-      :ok
+      iex> {:ok, db} = NIF.open_with_ttl("/tmp/test_db", 86_400)
+      iex> NIF.get_ttl(db)
+      {:ok, 86_400}
 
   """
-  @spec put(db_ref(), column_family(), binary(), binary()) :: :ok | {:error, term()}
-  def put(_db_ref, _cf, _key, _value), do: :erlang.nif_error(:nif_not_loaded)
+  @spec open_with_ttl(String.t(), pos_integer()) ::
+          {:ok, db_ref()} | {:error, {:invalid_option, 0} | {:open_failed, String.t()}}
+  def open_with_ttl(_path, _ttl_seconds), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Deletes a key from a column family.
+  Opens a database that lives entirely in memory.
 
-  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+  Backed by RocksDB's in-memory `Env`: SST files, the WAL and the manifest
+  never touch disk (the bindings only create an empty placeholder directory
+  under the system temp dir), and the data is discarded once the database is
+  closed and all its iterators and snapshots are gone. Every call returns an
+  independent, empty database with the same tuned column families as
+  `open/1`, and all read, write, iterator and snapshot functions work
+  unchanged. Meant for tests that shouldn't need temp-dir cleanup.
 
-  ## Arguments
-  - `db_ref` - The database reference
-  - `cf` - The column family atom
-  - `key` - The key to delete
+  `get_path/1` returns `{:ok, ":memory:"}`. `sync_dir/1` and `flush_wal/2`
+  succeed without fsyncing, and `effective_options/2`, which reads the
+  OPTIONS file from the database directory, returns `{:error, :in_memory}`.
 
   ## Returns
-  - `:ok` on success (even if key didn't exist)
-  - `{:error, :already_closed}` if database is closed
-  - `{:error, {:invalid_cf, cf}}` if column family is invalid
-  - `{:error, {:delete_failed, reason}}` on other errors
+  - `{:ok, db_ref}` on success
+  - `{:error, {:open_failed, reason}}` on failure
 
   ## Examples
 
-      iex> {:ok, db} = NIF.open("/tmp/test_db")
-      iex> NIF.put(db, :id2str, "key1", "value1")
-      :ok
-      iex> NIF.delete(db, :id2str, "key1")
-      :ok
-      iex> NIF.get(db, :id2str, "key1")
-      :not_found
+      iex> {:ok, db} = NIF.open_memory()
+      iex> NIF.get_path(db)
+      {:ok, ":memory:"}
 
   """
-  @spec delete(db_ref(), column_family(), binary()) :: :ok | {:error, term()}
-  def delete(_db_ref, _cf, _key), do: :erlang.nif_error(:nif_not_loaded)
+  @spec open_memory() :: {:ok, db_ref()} | {:error, {:open_failed, String.t()}}
+  def open_memory, do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Checks if a key exists in a column family.
-
-  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
-  More efficient than `get/3` when you only need to check existence.
+  Opens an existing database as a read-only instance.
+
+  Uses RocksDB's read-only mode with the same tuned options as `open/1`, so
+  secondary nodes can read the store while the primary keeps writing. The
+  handle sees the data as of the time it was opened. Every built-in column
+  family present on disk is opened; one the database predates (such as the
+  quad indexes of an older store) returns `{:error, {:cf_not_opened, cf}}`. Write functions (`put/4`, `delete/3`, `delete_range/4`, the batches,
+  `merge/4`, `put_quad/4`, `swap_cf_contents/3`, `transaction_begin/1`,
+  `ingest_external_file/3` and the column family management functions) return
+  `{:error, :read_only}` without touching the database, so the invariant is
+  enforced here rather than by callers.
 
   ## Arguments
-  - `db_ref` - The database reference
-  - `cf` - The column family atom
-  - `key` - The key to check
+  - `path` - Path to an existing database directory
 
   ## Returns
-  - `{:ok, true}` if key exists
-  - `{:ok, false}` if key doesn't exist
-  - `{:error, :already_closed}` if database is closed
-  - `{:error, {:invalid_cf, cf}}` if column family is invalid
-  - `{:error, {:get_failed, reason}}` on other errors
+  - `{:ok, db_ref}` on success
+  - `{:error, {:open_failed, reason}}` if the database doesn't exist or can't be opened
 
   ## Examples
 
-      iex> {:ok, db} = NIF.open("/tmp/test_db")
-      iex> NIF.put(db, :id2str, "key1", "value1")
-      :ok
-      iex> NIF.exists(db, :id2str, "key1")
-      {:ok, true}
-      iex> NIF.exists(db, :id2str, "nonexistent")
-      {:ok, false}
+      iex> {:ok, db} = NIF.open_read_only("/tmp/test_db")
+      iex> NIF.put(db, :spo, "key", "value")
+      {:error, :read_only}
 
   """
-  @spec exists(db_ref(), column_family(), binary()) :: {:ok, boolean()} | {:error, term()}
-  def exists(_db_ref, _cf, _key), do: :erlang.nif_error(:nif_not_loaded)
-
-  # ============================================================================
-  # Batch Operations
-  # ============================================================================
-
-  @type put_operation :: {column_family(), binary(), binary()}
-  @type delete_operation :: {column_family(), binary()}
-  @type mixed_put :: {:put, column_family(), binary(), binary()}
-  @type mixed_delete :: {:delete, column_family(), binary()}
+  @spec open_read_only(String.t()) :: {:ok, db_ref()} | {:error, {:open_failed, String.t()}}
+  def open_read_only(_path), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Atomically writes multiple key-value pairs to column families.
+  Opens a secondary instance that follows a primary database.
 
-  Uses RocksDB WriteBatch for atomic commit - either all operations succeed
-  or none do. Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+  Uses RocksDB's secondary mode. Unlike `open_read_only/1`, the handle can
+  catch up with the primary while it keeps writing and compacting, which suits
+  reporting nodes sharing the directory. It takes no lock on `primary_path` and
+  keeps its own files under `secondary_path`, which must differ from the
+  primary's directory.
+
+  The handle sees the data as of the open until `try_catch_up_with_primary/1`
+  is called. As with `open_read_only/1`, only the column families present on
+  disk are opened. The same write functions as on an `open_read_only/1` handle
+  return `{:error, :read_only}`.
 
   ## Arguments
-  - `db_ref` - The database reference
-  - `operations` - List of `{cf, key, value}` tuples
-  - `sync` - When `true`, forces an fsync after the write. When `false`,
-    the write is buffered in the OS (WAL still provides durability).
-    Use `false` for bulk loading to improve performance.
+  - `primary_path` - Path of the primary database directory
+  - `secondary_path` - Directory for the secondary instance's own files
 
   ## Returns
-  - `:ok` on success
-  - `{:error, :already_closed}` if database is closed
-  - `{:error, {:invalid_cf, cf}}` if column family is invalid
-  - `{:error, {:batch_failed, reason}}` on other errors
+  - `{:ok, db_ref}` on success
+  - `{:error, {:open_failed, reason}}` if the primary doesn't exist or can't be opened
 
   ## Examples
 
-      iex> {:ok, db} = NIF.open("/tmp/test_db")
-      iex> operations = [
-      ...>   {:id2str, "key1", "value1"},
-      ...>   {:id2str, "key2", "value2"},
-      ...>   {:str2id, "value1", "key1"}
-      ...> ]
-      iex> NIF.write_batch(db, operations, true)
-      :ok
+      iex> {:ok, db} = NIF.open_secondary("/tmp/test_db", "/tmp/test_db_secondary")
+      iex> NIF.put(db, :spo, "key", "value")
+      {:error, :read_only}
 
   """
-  @spec write_batch(db_ref(), [put_operation()], boolean()) :: :ok | {:error, term()}
-  def write_batch(_db_ref, _operations, _sync), do: :erlang.nif_error(:nif_not_loaded)
+  @spec open_secondary(String.t(), String.t()) ::
+          {:ok, db_ref()} | {:error, {:open_failed, String.t()}}
+  def open_secondary(_primary_path, _secondary_path), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Atomically deletes multiple keys from column families.
+  Pulls the primary's newer writes into a secondary instance.
 
-  Uses RocksDB WriteBatch for atomic commit - either all operations succeed
-  or none do. Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+  Replays the primary's changes since the open or the last catch-up, so reads
+  on the handle see them afterwards. Iterators and snapshots created earlier
+  keep their old view.
 
   ## Arguments
-  - `db_ref` - The database reference
-  - `operations` - List of `{cf, key}` tuples
-  - `sync` - When `true`, forces an fsync after the write. When `false`,
-    the write is buffered in the OS (WAL still provides durability).
-    Use `false` for bulk loading to improve performance.
+  - `db_ref` - Handle from `open_secondary/2`
 
   ## Returns
   - `:ok` on success
   - `{:error, :already_closed}` if database is closed
-  - `{:error, {:invalid_cf, cf}}` if column family is invalid
-  - `{:error, {:batch_failed, reason}}` on other errors
+  - `{:error, :not_secondary}` if the handle wasn't opened with `open_secondary/2`
+  - `{:error, {:catch_up_failed, reason}}` if the primary's changes can't be replayed
 
   ## Examples
 
-      iex> {:ok, db} = NIF.open("/tmp/test_db")
-      iex> NIF.write_batch(db, [{:id2str, "key1", "value1"}, {:id2str, "key2", "value2"}], true)
-      :ok
-      iex> NIF.delete_batch(db, [{:id2str, "key1"}, {:id2str, "key2"}], true)
+      iex> {:ok, secondary} = NIF.open_secondary("/tmp/test_db", "/tmp/test_db_secondary")
+      iex> NIF.try_catch_up_with_primary(secondary)
       :ok
 
   """
-  @spec delete_batch(db_ref(), [delete_operation()], boolean()) :: :ok | {:error, term()}
-  def delete_batch(_db_ref, _operations, _sync), do: :erlang.nif_error(:nif_not_loaded)
+  @spec try_catch_up_with_primary(db_ref()) ::
+          :ok
+          | {:error, :already_closed | :not_secondary | {:catch_up_failed, String.t()}}
+  def try_catch_up_with_primary(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Atomically performs mixed put and delete operations.
-
-  Uses RocksDB WriteBatch for atomic commit - either all operations succeed
-  or none do. This is essential for maintaining consistency when updating
-  multiple indices (SPO, POS, OSP) for a single triple.
+  Closes the database and releases all resources.
 
+  After calling close, the database handle is no longer valid.
   Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
 
   ## Arguments
-  - `db_ref` - The database reference
-  - `operations` - List of operations:
-    - `{:put, cf, key, value}` for puts
-    - `{:delete, cf, key}` for deletes
-  - `sync` - When `true`, forces an fsync after the write. When `false`,
-    the write is buffered in the OS (WAL still provides durability).
-    Use `false` for bulk loading to improve performance.
+  - `db_ref` - The database reference to close
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if already closed
+
+  ## Examples
+
+      iex> {:ok, db} = TripleStore.Backend.RocksDB.NIF.open("/tmp/test_db")
+      iex> TripleStore.Backend.RocksDB.NIF.close(db)
+      :ok
+
+  """
+  @spec close(db_ref()) :: :ok | {:error, :already_closed}
+  def close(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Closes the database, treating an already-closed database as success.
+
+  Behaves like `close/1`, except that closing twice returns `:ok` instead of
+  `{:error, :already_closed}`. Use it in supervisor shutdown paths where the
+  order in which owners close the handle isn't guaranteed; use `close/1` when
+  a double close should be detected.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference to close
+
+  ## Returns
+  - `:ok` whether the database was open or already closed
+
+  ## Examples
+
+      iex> TripleStore.Backend.RocksDB.NIF.close_idempotent(db)
+      :ok
+      iex> TripleStore.Backend.RocksDB.NIF.close_idempotent(db)
+      :ok
+
+  """
+  @spec close_idempotent(db_ref()) :: :ok
+  def close_idempotent(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Deletes a database and all of its files, including RocksDB's internal ones.
+
+  Removes the SST files, WAL, manifest, options files, info logs and `LOCK`,
+  so nothing is left behind between test runs. Destroying a path that holds
+  no database succeeds.
+
+  The caller must `close/1` every handle on the path first. The NIF doesn't
+  track open handles, but RocksDB has to take the `LOCK` file to destroy the
+  database, so while any handle is open (including one kept alive after
+  `close/1` by an iterator or snapshot) the call fails with
+  `{:error, {:destroy_failed, reason}}` and nothing is deleted.
+
+  `opts` is accepted for symmetry with `open_with_options/2`; no key affects
+  destroy today and all keys are ignored.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `path` - Path to the database directory
+  - `opts` - Map of settings
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, {:destroy_failed, reason}}` if the database is still open or its
+    files can't be removed
+  - `{:error, {:invalid_option, opts}}` if `opts` isn't a map
+
+  ## Examples
+
+      iex> :ok = NIF.close(db)
+      iex> NIF.destroy("/tmp/test_db", %{})
+      :ok
+
+  """
+  @spec destroy(String.t(), map()) ::
+          :ok | {:error, {:destroy_failed, String.t()} | {:invalid_option, term()}}
+  def destroy(_path, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Flushes the Write-Ahead Log (WAL) to disk.
+
+  This ensures all buffered writes are persisted to the WAL. When `sync` is `true`,
+  it also calls fsync to ensure data is physically written to storage.
+
+  Use this after bulk loading with `sync: false` to ensure all data is durable
+  before considering the load complete.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `sync` - When `true`, calls fsync after flushing (fully durable). When `false`,
+    only flushes to OS buffer cache.
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:flush_failed, reason}}` on failure
+
+  ## Examples
+
+      iex> {:ok, db} = NIF.open("/tmp/test_db")
+      iex> NIF.write_batch(db, [{:spo, "key", "value"}], false)
+      :ok
+      iex> NIF.flush_wal(db, true)
+      :ok
+
+  """
+  @spec flush_wal(db_ref(), boolean()) :: :ok | {:error, term()}
+  def flush_wal(_db_ref, _sync), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Makes everything written so far durable in a single call.
+
+  Flushes the memtables of all column families to SST files, syncs the WAL
+  with fsync, and then fsyncs the database directory. Use it for transactional
+  checkpoints, e.g. right after a critical commit.
+
+  This is expensive: the memtable flush writes new SST files and every fsync
+  waits on the storage device, so expect milliseconds to seconds of latency.
+  It is not needed after every write; use `flush_wal/2` or synced writes for
+  that.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:flush_failed, reason}}` on failure
+
+  ## Examples
+
+      iex> NIF.write_batch(db, [{:spo, "key", "value"}], false)
+      :ok
+      iex> NIF.sync_dir(db)
+      :ok
+
+  """
+  @spec sync_dir(db_ref()) :: :ok | {:error, term()}
+  def sync_dir(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Flushes the memtables of every column family to SST files.
+
+  Unlike `sync_dir/1`, the WAL and the database directory are not fsynced.
+  Use it before test teardown or before taking an external filesystem
+  snapshot, so the data lives in SST files rather than only in the WAL.
+
+  `flush/1` waits for the flush to finish. `flush/2` with `wait` set to
+  `false` returns as soon as the flush is scheduled.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `wait` - Whether to wait for the flush to finish (default: `true`)
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:flush_failed, reason}}` on failure
+
+  ## Examples
+
+      iex> NIF.put(db, :spo, "key", "value")
+      :ok
+      iex> NIF.flush(db)
+      :ok
+
+  """
+  @spec flush(db_ref()) :: :ok | {:error, term()}
+  def flush(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec flush(db_ref(), boolean()) :: :ok | {:error, term()}
+  def flush(_db_ref, _wait), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Flushes the memtable of a single column family to SST files.
+
+  Behaves like `flush/2` for one column family; `wait` defaults to `true`.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `wait` - Whether to wait for the flush to finish (default: `true`)
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:flush_failed, reason}}` on failure
+
+  ## Examples
+
+      iex> NIF.flush_cf(db, :spo)
+      :ok
+
+  """
+  @spec flush_cf(db_ref(), column_family()) :: :ok | {:error, term()}
+  def flush_cf(_db_ref, _cf), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec flush_cf(db_ref(), column_family(), boolean()) :: :ok | {:error, term()}
+  def flush_cf(_db_ref, _cf, _wait), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Sets RocksDB options on all column families at runtime.
+
+  This allows dynamic reconfiguration of RocksDB settings without restarting.
+  Options are passed as a list of {key, value} tuples where both key and value
+  are strings.
+
+  ## Mutable Options (subset)
+  - `"level0_file_num_compaction_trigger"` - Files in L0 to trigger compaction
+  - `"level0_slowdown_writes_trigger"` - Files in L0 to slow down writes
+  - `"level0_stop_writes_trigger"` - Files in L0 to stop writes
+  - `"target_file_size_base"` - Target file size in bytes
+  - `"max_bytes_for_level_base"` - Maximum bytes in base level
+  - `"write_buffer_size"` - Size of write buffer (for new memtables)
+  - `"max_write_buffer_number"` - Maximum number of write buffers
+  - `"disable_auto_compactions"` - Disable automatic compactions ("true"/"false")
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `options` - List of {key, value} tuples as strings
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:set_options_failed, reason}}` on failure
+
+  ## Examples
+
+      iex> {:ok, db} = NIF.open("/tmp/test_db")
+      iex> NIF.set_options(db, [{"level0_file_num_compaction_trigger", "16"}])
+      :ok
+
+  """
+  @spec set_options(db_ref(), [{String.t(), String.t()}]) :: :ok | {:error, term()}
+  def set_options(_db_ref, _options), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Reads back the RocksDB options in effect for a column family.
+
+  Useful to confirm that `open/2` overrides and `set_options/2` calls took
+  effect rather than being silently defaulted. Values come from the newest
+  `OPTIONS-*` file, which RocksDB rewrites on open and after every successful
+  `set_options/2`. Databases from `open_memory/0` keep that file in memory,
+  out of reach, and return `{:error, :in_memory}`.
+
+  Keys use the same names as `set_options/2`. Numeric values are integers;
+  all others are RocksDB's string form, e.g. `"kLZ4Compression"`,
+  `"kCompactionStyleLevel"` or `"false"`. Reported keys: `write_buffer_size`,
+  `max_write_buffer_number`, `compression`, `bottommost_compression`,
+  `compaction_style`, `level0_file_num_compaction_trigger`,
+  `level0_slowdown_writes_trigger`, `level0_stop_writes_trigger`,
+  `target_file_size_base`, `max_bytes_for_level_base`,
+  `disable_auto_compactions`, `prefix_extractor`, `block_size`, `index_type`,
+  `format_version`, `filter_policy` and `cache_index_and_filter_blocks`.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+
+  ## Returns
+  - `{:ok, options}` map of option name to value
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:cf_not_opened, cf}}` if the column family wasn't opened
+  - `{:error, :in_memory}` for an in-memory database
+  - `{:error, {:options_failed, reason}}` if the options file can't be read
+
+  ## Examples
+
+      iex> {:ok, opts} = NIF.effective_options(db, :spo)
+      iex> opts["block_size"]
+      8192
+
+  """
+  @spec effective_options(db_ref(), column_family()) ::
+          {:ok, %{String.t() => integer() | String.t()}} | {:error, term()}
+  def effective_options(_db_ref, _cf), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the path of the database.
+
+  ## Arguments
+  - `db_ref` - The database reference
+
+  ## Returns
+  - `{:ok, path}` with the database path
+  """
+  @spec get_path(db_ref()) :: {:ok, String.t()}
+  def get_path(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the TTL of `:derived` entries, as given to `open_with_ttl/2`.
+
+  ## Arguments
+  - `db_ref` - The database reference
+
+  ## Returns
+  - `{:ok, ttl_seconds}` for a database opened with `open_with_ttl/2`
+  - `{:ok, nil}` if `:derived` entries don't expire
+  - `{:error, :already_closed}` if database is closed
+  """
+  @spec get_ttl(db_ref()) :: {:ok, pos_integer() | nil} | {:error, :already_closed}
+  def get_ttl(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Lists all column families in the database.
+
+  ## Returns
+  - List of column family atoms: `[:id2str, :str2id, :spo, :pos, :osp, :derived, :numeric_range,
+    :gspo, :gpos, :gosp]`
+
+  The triple indexes (`:spo`, `:pos`, `:osp`) hold the default graph; the
+  quad indexes (`:gspo`, `:gpos`, `:gosp`) hold named graphs.
+  """
+  @spec list_column_families :: [column_family()]
+  def list_column_families, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Checks if the database is open.
+
+  ## Arguments
+  - `db_ref` - The database reference
+
+  ## Returns
+  - `true` if open, `false` if closed
+  """
+  @spec is_open(db_ref()) :: boolean()
+  # credo:disable-for-next-line Credo.Check.Readability.PredicateFunctionNames
+  def is_open(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the number of iterators currently open on the database.
+
+  Counts iterators from `prefix_iterator/3` and `prefix_iterator_filtered/4`
+  that have been neither closed with `iterator_close/1` nor garbage-collected.
+  Handy for spotting code paths that forget to close their iterators.
+
+  ## Arguments
+  - `db_ref` - The database reference
+
+  ## Returns
+  - `{:ok, count}` on success
+  - `{:error, :already_closed}` if database is closed
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
+      iex> NIF.open_iterator_count(db)
+      {:ok, 1}
+
+  """
+  @spec open_iterator_count(db_ref()) :: {:ok, non_neg_integer()} | {:error, :already_closed}
+  def open_iterator_count(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Verifies the database is usable end-to-end.
+
+  Writes a probe key under a reserved prefix in RocksDB's `default` column
+  family, reads it back and deletes it. The probe deliberately does not go to
+  `derived` or any other triple-keyed column family: those are scanned and
+  decoded as triples, so a probe left behind by a crash would surface as a
+  bogus triple. Unlike `is_open/1`, this confirms that writes and reads
+  actually succeed. Safe to call concurrently; the probe key is never left behind.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+
+  ## Returns
+  - `{:ok, latency_micros}` if the round trip succeeded
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:health_check_failed, reason}}` if any step failed
+
+  ## Examples
+
+      iex> {:ok, db} = NIF.open("/tmp/test_db")
+      iex> {:ok, micros} = NIF.health_check(db)
+      iex> is_integer(micros)
+      true
+
+  """
+  @spec health_check(db_ref()) :: {:ok, non_neg_integer()} | {:error, term()}
+  def health_check(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ============================================================================
+  # Key-Value Operations
+  # ============================================================================
+
+  @doc """
+  Gets a value from a column family.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `key` - The key as a binary
+
+  ## Returns
+  - `{:ok, value}` if found
+  - `:not_found` if key doesn't exist
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:get_failed, reason}}` on other errors
+
+  ## Examples
+
+      iex> {:ok, db} = NIF.open("/tmp/test_db")
+      iex> NIF.put(db, :id2str, "key1", "value1")
+      :ok
+      iex> NIF.get(db, :id2str, "key1")
+      {:ok, "value1"}
+      iex> NIF.get(db, :id2str, "nonexistent")
+      :not_found
+
+  """
+  @spec get(db_ref(), column_family(), binary()) ::
+          {:ok, binary()} | :not_found | {:error, term()}
+  def get(_db_ref, _cf, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Gets a value from a column family without copying it.
+
+  `get/3` copies each value twice on its way to Elixir. Here the value stays
+  pinned where RocksDB holds it and the returned binary points straight at
+  that memory, which roughly halves the memory traffic for large values such
+  as long `:id2str` strings. For small values the extra resource allocation
+  outweighs the copy, so prefer `get/3` there.
+
+  The pinned memory is freed only when the binary is garbage-collected. Until
+  then it keeps its block cache block alive, and the database too if it is
+  closed in the meantime. Use `:binary.copy/1` before holding on to a small
+  slice of a large value for a long time.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `key` - The key as a binary
+
+  ## Returns
+  - `{:ok, value}` if found
+  - `:not_found` if key doesn't exist
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:get_failed, reason}}` on other errors
+
+  ## Examples
+
+      iex> NIF.put(db, :id2str, <<1::64>>, large_string)
+      :ok
+      iex> {:ok, ^large_string} = NIF.get_pinned(db, :id2str, <<1::64>>)
+
+  """
+  @spec get_pinned(db_ref(), column_family(), binary()) ::
+          {:ok, binary()} | :not_found | {:error, term()}
+  def get_pinned(_db_ref, _cf, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Gets multiple values from a column family in one call.
+
+  Uses RocksDB's `multi_get_cf`, so memtable, cache and disk accesses are
+  batched and the NIF boundary is crossed once for the whole list. Absent keys
+  keep their slot as `nil`, so the results can be zipped against `keys`.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `keys` - List of keys as binaries
+
+  ## Returns
+  - `{:ok, values}` with a value binary or `nil` per key, in key order
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:get_failed, reason}}` if any read fails
+
+  ## Examples
+
+      iex> NIF.put(db, :str2id, "a", "1")
+      iex> NIF.multi_get(db, :str2id, ["a", "missing"])
+      {:ok, ["1", nil]}
+
+  """
+  @spec multi_get(db_ref(), column_family(), [binary()]) ::
+          {:ok, [binary() | nil]} | {:error, term()}
+  def multi_get(_db_ref, _cf, _keys), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Gets values for `{cf, key}` pairs across column families in one call.
+
+  Saves a NIF round trip per term when a lookup chains column families, e.g.
+  `:str2id` followed by `:spo`. Requests are grouped per column family and
+  each group is read with one `multi_get_cf`; results come back aligned with
+  `requests`, with `nil` for absent keys.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `requests` - List of `{cf, key}` tuples
+
+  ## Returns
+  - `{:ok, values}` with a value binary or `nil` per request, in request order
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` for the first invalid column family
+  - `{:error, {:get_failed, reason}}` if any read fails
+
+  ## Examples
+
+      iex> NIF.put(db, :str2id, "term", "id1")
+      iex> NIF.put(db, :spo, "id1", "")
+      iex> NIF.multi_get_cf(db, [{:str2id, "term"}, {:spo, "id1"}, {:spo, "id2"}])
+      {:ok, ["id1", "", nil]}
+
+  """
+  @spec multi_get_cf(db_ref(), [{column_family(), binary()}]) ::
+          {:ok, [binary() | nil]} | {:error, term()}
+  def multi_get_cf(_db_ref, _requests), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Puts a key-value pair into a column family.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `key` - The key as a binary
+  - `value` - The value as a binary
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, :read_only}` if the database was opened read-only
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:put_failed, reason}}` on other errors
+
+  ## Examples
+
+      iex> {:ok, db} = NIF.open("/tmp/test_db")
+      iex> NIF.put(db, :id2str, "key1", "value1")
+      :ok
+
+  """
+  @spec put(db_ref(), column_family(), binary(), binary()) :: :ok | {:error, term()}
+  def put(_db_ref, _cf, _key, _value), do: :erlang.nif_error(:nif_not_loaded)
+
+  @typedoc """
+  Per-call write options for `put/5`, `write_batch/3`, `delete_batch/3` and
+  `mixed_batch/3`.
+
+  A boolean is the `:sync` flag on its own. In the map both keys default to
+  `false` and unknown keys are ignored:
+
+  - `:sync` - fsync the WAL before returning, so an acknowledged write
+    survives a machine crash. Use for user-facing writes.
+  - `:disable_wal` - skip the write-ahead log entirely. Fastest, but every
+    write not yet flushed to an SST file is lost if the process crashes, so
+    only use it for bulk loads that can be re-run from their source.
+  """
+  @type write_options ::
+          boolean()
+          | %{
+              optional(:sync) => boolean(),
+              optional(:disable_wal) => boolean(),
+              optional(atom()) => term()
+            }
+
+  @doc """
+  Puts a key-value pair into a column family with per-call write options.
+
+  Same as `put/4`, which uses the defaults (`sync: false`,
+  `disable_wal: false`). See `t:write_options/0` for the options and the
+  durability trade-off of each.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `key` - The key as a binary
+  - `value` - The value as a binary
+  - `options` - Write options
+
+  ## Returns
+  - Same as `put/4`
+  - `{:error, {:invalid_option, key}}` if an option is not a boolean
+
+  ## Examples
+
+      iex> NIF.put(db, :id2str, "key1", "value1", %{sync: true})
+      :ok
+      iex> NIF.put(db, :spo, "key2", "", %{disable_wal: true})
+      :ok
+
+  """
+  @spec put(db_ref(), column_family(), binary(), binary(), write_options()) ::
+          :ok | {:error, term()}
+  def put(_db_ref, _cf, _key, _value, _options), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Puts a key-value pair only if the key is absent from the column family.
+
+  Meant for allocators such as the string interner, which must not replace an
+  ID that another process already assigned, without a separate read first.
+
+  The check and the write run under the database's commit lock, so concurrent
+  `put_if_absent/4` calls and transaction commits are serialized: for a given
+  key exactly one call returns `{:ok, :written}` and the others get its value.
+  `put/4`, batches and merges don't take that lock, so a concurrent one can
+  land between the check and the write and be overwritten.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `key` - The key as a binary
+  - `value` - The value as a binary
+
+  ## Returns
+  - `{:ok, :written}` if the key was absent and `value` was written
+  - `{:ok, {:exists, existing}}` with the stored value if the key was present
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, :read_only}` if the database was opened read-only
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:get_failed, reason}}` if the existing value can't be read
+  - `{:error, {:put_failed, reason}}` if the write fails
+
+  ## Examples
+
+      iex> NIF.put_if_absent(db, :str2id, "term", <<1::64>>)
+      {:ok, :written}
+      iex> NIF.put_if_absent(db, :str2id, "term", <<2::64>>)
+      {:ok, {:exists, <<1::64>>}}
+
+  """
+  @spec put_if_absent(db_ref(), column_family(), binary(), binary()) ::
+          {:ok, :written | {:exists, binary()}} | {:error, term()}
+  def put_if_absent(_db_ref, _cf, _key, _value), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Merges a value into a key using the column family's merge operator.
+
+  Only column families opened with a merge operator (see `open/2`) accept
+  merges. Without one, RocksDB would store operands it cannot fold and reads
+  would fail or return undefined results, so the call is rejected instead.
+
+  `:derived` always has the `:int64_add` operator, so it can hold atomic
+  counters such as reference counts for interned strings: concurrent merges
+  never lose an update, and `get/3` returns the folded sum as an 8-byte
+  little-endian i64. Merging into a key that holds a non-counter value makes
+  reads of that key fail.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `key` - The key as a binary
+  - `value` - The merge operand as an 8-byte little-endian i64, or an integer
+    delta that is encoded that way
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, :read_only}` if the database was opened read-only
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:cf_not_opened, cf}}` if the column family wasn't opened
+  - `{:error, {:invalid_operand, value}}` if `value` is neither an integer nor
+    an 8-byte binary. `:int64_add` can't fold such an operand, so storing it
+    would make every later read and compaction of the key fail
+  - `{:error, :no_merge_operator}` if the CF has no merge operator installed
+  - `{:error, {:merge_failed, reason}}` on other errors
+
+  ## Examples
+
+      iex> {:ok, db} = NIF.open("/tmp/test_db")
+      iex> NIF.merge(db, :derived, "counter", 1)
+      :ok
+      iex> NIF.merge(db, :derived, "counter", <<2::64-little-signed>>)
+      :ok
+      iex> NIF.get(db, :derived, "counter")
+      {:ok, <<3::64-little-signed>>}
+
+  """
+  @spec merge(db_ref(), column_family(), binary(), binary() | integer()) :: :ok | {:error, term()}
+  def merge(_db_ref, _cf, _key, _value), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Deletes a key from a column family.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `key` - The key to delete
+
+  ## Returns
+  - `:ok` on success (even if key didn't exist)
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, :read_only}` if the database was opened read-only
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:delete_failed, reason}}` on other errors
+
+  ## Examples
+
+      iex> {:ok, db} = NIF.open("/tmp/test_db")
+      iex> NIF.put(db, :id2str, "key1", "value1")
+      :ok
+      iex> NIF.delete(db, :id2str, "key1")
+      :ok
+      iex> NIF.get(db, :id2str, "key1")
+      :not_found
+
+  """
+  @spec delete(db_ref(), column_family(), binary()) :: :ok | {:error, term()}
+  def delete(_db_ref, _cf, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Deletes every key in `[start_key, end_key)` from a column family.
+
+  Writes a single range tombstone instead of one tombstone per key, which
+  makes retracting a dense prefix, such as every `spo` entry of a subject,
+  about as cheap as a single delete.
+
+  The deleted entries keep using disk space until compaction rewrites the
+  files that hold them; use `compact_range/4` over the same range if the
+  space is needed back immediately.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `start_key` - First key to delete (inclusive)
+  - `end_key` - Key to stop at (exclusive)
+
+  ## Returns
+  - `:ok` on success (even if no key was in the range)
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, :read_only}` if the database was opened read-only
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:delete_failed, reason}}` if `end_key` sorts before `start_key` or on other errors
+
+  ## Examples
+
+      iex> subject = <<42::64>>
+      iex> NIF.delete_range(db, :spo, subject, <<43::64>>)
+      :ok
+
+  """
+  @spec delete_range(db_ref(), column_family(), binary(), binary()) :: :ok | {:error, term()}
+  def delete_range(_db_ref, _cf, _start_key, _end_key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Checks if a key exists in a column family.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+  More efficient than `get/3` when you only need to check existence.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `key` - The key to check
+
+  ## Returns
+  - `{:ok, true}` if key exists
+  - `{:ok, false}` if key doesn't exist
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:get_failed, reason}}` on other errors
+
+  ## Examples
+
+      iex> {:ok, db} = NIF.open("/tmp/test_db")
+      iex> NIF.put(db, :id2str, "key1", "value1")
+      :ok
+      iex> NIF.exists(db, :id2str, "key1")
+      {:ok, true}
+      iex> NIF.exists(db, :id2str, "nonexistent")
+      {:ok, false}
+
+  """
+  @spec exists(db_ref(), column_family(), binary()) :: {:ok, boolean()} | {:error, term()}
+  def exists(_db_ref, _cf, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Cheaply checks whether a key may exist, without reading it from disk.
+
+  Only the memtables and in-memory structures (bloom filters, cached index
+  blocks) are consulted, so no I/O happens. The answer is one-sided:
+
+  - `{:ok, false}` is definite: the key is absent.
+  - `{:ok, true}` is probabilistic: bloom filters have false positives, so
+    the key may still be missing. Confirm with `get/3` or `exists/3`.
+
+  Unlike `exists/3`, which always reads the key, this is a pre-filter for
+  skipping the read on definite misses, e.g. when probing for triples.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `key` - The key to check
+
+  ## Returns
+  - `{:ok, true}` if the key may exist
+  - `{:ok, false}` if the key definitely doesn't exist
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+
+  ## Examples
+
+      iex> NIF.put(db, :spo, "key1", "")
+      :ok
+      iex> NIF.key_may_exist(db, :spo, "key1")
+      {:ok, true}
+      iex> NIF.key_may_exist(db, :spo, "nonexistent")
+      {:ok, false}
+
+  """
+  @spec key_may_exist(db_ref(), column_family(), binary()) ::
+          {:ok, boolean()} | {:error, term()}
+  def key_may_exist(_db_ref, _cf, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ============================================================================
+  # Batch Operations
+  # ============================================================================
+
+  @type put_operation :: {column_family(), binary(), binary()}
+  @type delete_operation :: {column_family(), binary()}
+  @type mixed_put :: {:put, column_family(), binary(), binary()}
+  @type mixed_delete :: {:delete, column_family(), binary()}
+  @type mixed_delete_range :: {:delete_range, column_family(), binary(), binary()}
+
+  @doc """
+  Atomically writes multiple key-value pairs to column families.
+
+  Uses RocksDB WriteBatch for atomic commit - either all operations succeed
+  or none do. Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `operations` - List of `{cf, key, value}` tuples
+  - `options` - Write options (see `t:write_options/0`). A boolean is the
+    `:sync` flag: when `true`, forces an fsync after the write. When `false`,
+    the write is buffered in the OS (WAL still provides durability).
+    Use `false` for bulk loading to improve performance.
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, :read_only}` if the database was opened read-only
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:invalid_option, option}}` if `options` is invalid
+  - `{:error, {:batch_failed, reason}}` on other errors
+
+  ## Examples
+
+      iex> {:ok, db} = NIF.open("/tmp/test_db")
+      iex> operations = [
+      ...>   {:id2str, "key1", "value1"},
+      ...>   {:id2str, "key2", "value2"},
+      ...>   {:str2id, "value1", "key1"}
+      ...> ]
+      iex> NIF.write_batch(db, operations, true)
+      :ok
+
+  """
+  @spec write_batch(db_ref(), [put_operation()], write_options()) :: :ok | {:error, term()}
+  def write_batch(_db_ref, _operations, _options), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Atomically deletes multiple keys from column families.
+
+  Uses RocksDB WriteBatch for atomic commit - either all operations succeed
+  or none do. Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `operations` - List of `{cf, key}` tuples
+  - `options` - Write options (see `t:write_options/0`). A boolean is the
+    `:sync` flag: when `true`, forces an fsync after the write. When `false`,
+    the write is buffered in the OS (WAL still provides durability).
+    Use `false` for bulk loading to improve performance.
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, :read_only}` if the database was opened read-only
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:invalid_option, option}}` if `options` is invalid
+  - `{:error, {:batch_failed, reason}}` on other errors
+
+  ## Examples
+
+      iex> {:ok, db} = NIF.open("/tmp/test_db")
+      iex> NIF.write_batch(db, [{:id2str, "key1", "value1"}, {:id2str, "key2", "value2"}], true)
+      :ok
+      iex> NIF.delete_batch(db, [{:id2str, "key1"}, {:id2str, "key2"}], true)
+      :ok
+
+  """
+  @spec delete_batch(db_ref(), [delete_operation()], write_options()) ::
+          :ok | {:error, term()}
+  def delete_batch(_db_ref, _operations, _options), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Atomically performs mixed put, delete and range delete operations.
+
+  Uses RocksDB WriteBatch for atomic commit - either all operations succeed
+  or none do. This is essential for maintaining consistency when updating
+  multiple indices (SPO, POS, OSP) for a single triple.
+
+  `{:delete_range, cf, start_key, end_key}` removes every key in
+  `[start_key, end_key)` with a single range tombstone, so all of a subject's
+  triples and derived entries can be retracted in the same atomic batch
+  without reading them first. Operations apply in list order, so a put that
+  follows a range delete covering its key is kept.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `operations` - List of operations:
+    - `{:put, cf, key, value}` for puts
+    - `{:delete, cf, key}` for deletes
+    - `{:delete_range, cf, start_key, end_key}` for range deletes
+  - `options` - Write options (see `t:write_options/0`). A boolean is the
+    `:sync` flag: when `true`, forces an fsync after the write. When `false`,
+    the write is buffered in the OS (WAL still provides durability).
+    Use `false` for bulk loading to improve performance.
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, :read_only}` if the database was opened read-only
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:invalid_operation, op}}` if operation type is invalid
+  - `{:error, {:invalid_operation, :delete_range}}` if a range ends before it
+    starts; nothing is written
+  - `{:error, {:invalid_option, option}}` if `options` is invalid
+  - `{:error, {:batch_failed, reason}}` on other errors
+
+  ## Examples
+
+      iex> {:ok, db} = NIF.open("/tmp/test_db")
+      iex> operations = [
+      ...>   {:put, :spo, "s1p1o1", ""},
+      ...>   {:put, :pos, "p1o1s1", ""},
+      ...>   {:delete, :spo, "old_key"},
+      ...>   {:delete, :pos, "old_key2"}
+      ...> ]
+      iex> NIF.mixed_batch(db, operations, true)
+      :ok
+
+  """
+  @spec mixed_batch(
+          db_ref(),
+          [mixed_put() | mixed_delete() | mixed_delete_range()],
+          write_options()
+        ) :: :ok | {:error, term()}
+  def mixed_batch(_db_ref, _operations, _options), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Swaps the contents of two column families.
+
+  RocksDB cannot rename column families, so the swap goes through SST
+  ingestion: both sides are read from one snapshot and streamed into one SST
+  file per column family, holding the new entries plus deletes for the keys
+  that go away. Each file is ingested in one atomic step, so readers of
+  `to_cf` see either its old or its new contents, never a partially swapped
+  state. `from_cf` receives the previous contents of `to_cf` right after.
+  On a database from `open_memory/0` both column families are swapped with
+  a single in-memory WriteBatch instead.
+
+  Typical use: rebuild all materialized inferences into a staging CF, then
+  swap it into `:derived` and clear the staging CF.
+
+  Other calls on the handle keep running during the swap. Writes to either
+  column family made while it runs are not part of it: one to a swapped key is
+  overwritten, one to any other key is kept.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `from_cf` - Column family holding the freshly built data
+  - `to_cf` - Column family to replace
+
+  ## Returns
+  - `{:ok, count}` with the number of entries now in `to_cf`
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, :read_only}` if the database was opened read-only
+  - `{:error, {:invalid_cf, cf}}` if a column family is invalid
+  - `{:error, :same_cf}` if both column families are the same
+  - `{:error, :ttl_cf}` if either is `:derived` on a database opened with
+    `open_with_ttl/2`
+  - `{:error, {:batch_failed, reason}}` on read or write errors
+  - `{:error, {:ingest_failed, reason}}` if RocksDB rejects an SST file; if
+    only the one for `from_cf` is rejected, `to_cf` is already replaced
+
+  ## Examples
+
+      iex> NIF.swap_cf_contents(db, :numeric_range, :derived)
+      {:ok, 1200}
+
+  """
+  @spec swap_cf_contents(db_ref(), column_family(), column_family()) ::
+          {:ok, non_neg_integer()} | {:error, term()}
+  def swap_cf_contents(_db_ref, _from_cf, _to_cf), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Atomically writes one quad to the three named-graph indexes.
+
+  Keys are encoded by the caller with the 8-byte graph ID first (`g|s|p|o`,
+  `g|p|o|s`, `g|o|s|p`), so a prefix scan on the graph ID lists every quad in
+  that graph. Values are empty, as for the triple indexes. Default-graph
+  triples stay in `:spo`, `:pos` and `:osp`.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `gspo_key` - Key for the `:gspo` index
+  - `gpos_key` - Key for the `:gpos` index
+  - `gosp_key` - Key for the `:gosp` index
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, :read_only}` if the database was opened read-only
+  - `{:error, {:cf_not_opened, cf}}` if a quad index was not opened
+  - `{:error, {:batch_failed, reason}}` on write errors
+
+  ## Examples
+
+      iex> NIF.put_quad(db, <<g::64, s::64, p::64, o::64>>, <<g::64, p::64, o::64, s::64>>,
+      ...>   <<g::64, o::64, s::64, p::64>>)
+      :ok
+
+  """
+  @spec put_quad(db_ref(), binary(), binary(), binary()) ::
+          :ok
+          | {:error,
+             :already_closed
+             | :read_only
+             | {:cf_not_opened, column_family()}
+             | {:batch_failed, term()}}
+  def put_quad(_db_ref, _gspo_key, _gpos_key, _gosp_key), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ============================================================================
+  # Column Family Management
+  # ============================================================================
+
+  @doc """
+  Creates a column family on an open database without reopening it.
+
+  Meant for transient column families, such as the staging area of a bulk
+  reindex job. The new column family can be used right away by its name
+  binary. It is not reopened automatically: a later open must list it in
+  `open_with_cfs/2`, or it must be dropped first.
+
+  `opts` accepts the per-CF settings of `open_with_options/2`:
+  `:compression` and `:bloom_filter_bits`. Unknown keys are ignored.
+
+  RocksDB needs exclusive access to change the set of column families, so
+  other calls on the handle wait meanwhile. Calls already running on the
+  handle are given up to a second to finish. If an iterator, snapshot or
+  pinned value is still open on the database after that, it returns
+  `{:error, :db_in_use}`; the call can be retried once they are released.
+
+  ## Arguments
+  - `db_ref` - Database reference from `open/1`
+  - `name` - Name of the new column family
+  - `opts` - Map of settings
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, :read_only}` if the database was opened read-only
+  - `{:error, {:invalid_cf, name}}` if the name is empty or `"default"`
+  - `{:error, {:invalid_option, key}}` if a setting has an invalid value
+  - `{:error, :db_in_use}` while iterators, snapshots or pinned values hold the database (retryable)
+  - `{:error, {:create_cf_failed, reason}}` if RocksDB rejects it, e.g. because it exists
+
+  ## Examples
+
+      iex> NIF.create_column_family(db, "reindex_tmp", %{compression: :none})
+      :ok
+      iex> NIF.put(db, "reindex_tmp", "key", "value")
+      :ok
+
+  """
+  @spec create_column_family(db_ref(), String.t(), map()) ::
+          :ok
+          | {:error,
+             :already_closed
+             | :read_only
+             | :db_in_use
+             | {:invalid_cf, String.t()}
+             | {:invalid_option, term()}
+             | {:create_cf_failed, String.t()}}
+  def create_column_family(_db_ref, _name, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Drops a column family and all of its data from an open database.
+
+  Only column families outside the built-in set can be dropped, i.e. the ones
+  from `open_with_cfs/2` or `create_column_family/3`. Same exclusive access as
+  `create_column_family/3`.
+
+  ## Arguments
+  - `db_ref` - Database reference from `open/1`
+  - `cf` - Name of the column family
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, :read_only}` if the database was opened read-only
+  - `{:error, :protected_cf}` for a built-in column family
+  - `{:error, {:invalid_cf, cf}}` if the column family is unknown
+  - `{:error, {:cf_not_opened, cf}}` if this handle didn't open it
+  - `{:error, :db_in_use}` while iterators, snapshots or pinned values hold the database (retryable)
+  - `{:error, {:drop_cf_failed, reason}}` if RocksDB rejects it
+
+  ## Examples
+
+      iex> NIF.drop_column_family(db, "reindex_tmp")
+      :ok
+      iex> NIF.drop_column_family(db, :spo)
+      {:error, :protected_cf}
+
+  """
+  @spec drop_column_family(db_ref(), column_family()) ::
+          :ok
+          | {:error,
+             :already_closed
+             | :read_only
+             | :protected_cf
+             | :db_in_use
+             | {:invalid_cf, term()}
+             | {:cf_not_opened, column_family()}
+             | {:drop_cf_failed, String.t()}}
+  def drop_column_family(_db_ref, _cf), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ============================================================================
+  # SST Ingestion
+  # ============================================================================
+
+  @doc """
+  Loads externally generated SST files into a column family.
+
+  The files must be written with RocksDB's `SstFileWriter`: keys sorted and
+  unique within each file. Ingestion bypasses the memtable and WAL; RocksDB
+  copies (or, with `move_files: true`, hard-links) the files into the database
+  and places each at the lowest level its key range fits into.
+
+  Key-range requirements enforced by RocksDB:
+
+  - The files of one call must not overlap each other; otherwise the whole
+    call fails with `{:error, {:ingest_failed, reason}}`.
+  - Overlap with data already in the column family is allowed and the ingested
+    values win, but the files then land on a higher level and any overlapping
+    memtable is flushed first, which makes the call slower. Offline-built
+    ranges that don't overlap live data are the fast path.
+
+  All files of one call are ingested atomically.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - Database reference from `open/1`
+  - `cf` - Column family to ingest into
+  - `file_paths` - Paths of the SST files
+  - `opts` - Optional map; `move_files: true` moves the files instead of
+    copying them, so the originals must not be touched afterwards. Unknown
+    keys are ignored.
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, :read_only}` if the database was opened read-only
+  - `{:error, {:invalid_cf, cf}}` if the column family is unknown
+  - `{:error, {:cf_not_opened, cf}}` if the column family was not opened
+  - `{:error, {:invalid_option, key}}` if an option value is invalid
+  - `{:error, :ttl_cf}` for `:derived` on a database opened with
+    `open_with_ttl/2`
+  - `{:error, {:ingest_failed, reason}}` if RocksDB rejects the files
+
+  ## Examples
+
+      iex> NIF.ingest_external_file(db, :spo, ["/data/spo_000.sst", "/data/spo_001.sst"])
+      :ok
+      iex> NIF.ingest_external_file(db, :spo, ["/data/spo_002.sst"], %{move_files: true})
+      :ok
+
+  """
+  @spec ingest_external_file(db_ref(), column_family(), [String.t()]) ::
+          :ok
+          | {:error,
+             :already_closed
+             | :read_only
+             | {:invalid_cf, term()}
+             | {:cf_not_opened, column_family()}
+             | :ttl_cf
+             | {:ingest_failed, String.t()}}
+  def ingest_external_file(_db_ref, _cf, _file_paths), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec ingest_external_file(db_ref(), column_family(), [String.t()], %{
+          optional(:move_files) => boolean()
+        }) ::
+          :ok
+          | {:error,
+             :already_closed
+             | :read_only
+             | {:invalid_cf, term()}
+             | {:cf_not_opened, column_family()}
+             | {:invalid_option, term()}
+             | :ttl_cf
+             | {:ingest_failed, String.t()}}
+  def ingest_external_file(_db_ref, _cf, _file_paths, _opts),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  # ============================================================================
+  # Iterator Operations
+  # ============================================================================
+
+  @type iterator_ref :: reference()
+
+  @doc """
+  Creates a prefix iterator for a column family.
+
+  The iterator returns all key-value pairs where the key starts with the given prefix.
+  The iterator must be closed with `iterator_close/1` when done, or it will be
+  automatically closed when garbage collected.
+
+  The iterator reads from a snapshot taken when it is created, so writes made
+  afterwards never show up in it, also not after `iterator_seek/2`,
+  `iterator_prev/1`, `iterator_reset/1` and the other repositioning calls
+  rebuild it. Create a new iterator to see them. The same applies to the
+  iterators of `range_iterator/5` and `bounded_iterator/4`.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `prefix` - The prefix to iterate over (can be empty for full scan)
+
+  ## Returns
+  - `{:ok, iterator_ref}` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+
+  ## Examples
+
+      iex> {:ok, db} = NIF.open("/tmp/test_db")
+      iex> NIF.put(db, :spo, "s1p1o1", "")
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
+      iex> {:ok, key, _value} = NIF.iterator_next(iter)
+      iex> key
+      "s1p1o1"
+
+  """
+  @spec prefix_iterator(db_ref(), column_family(), binary()) ::
+          {:ok, iterator_ref()} | {:error, term()}
+  def prefix_iterator(_db_ref, _cf, _prefix), do: :erlang.nif_error(:nif_not_loaded)
+
+  @typedoc "Iteration order accepted by `prefix_iterator/4`"
+  @type iterator_direction :: :forward | :reverse
+
+  @typedoc "Options accepted by `prefix_iterator/4` in place of a direction"
+  @type prefix_iterator_opts :: [direction: iterator_direction(), upper_bound: binary()]
+
+  @doc """
+  Creates a prefix iterator walking in the given direction, or with options.
+
+  `:forward` behaves exactly like `prefix_iterator/3`. With `:reverse` the
+  iterator starts at the last key matching the prefix and each
+  `iterator_next/1` steps to the next smaller key, stopping once keys no longer
+  start with the prefix. Descending scans can then stream instead of collecting
+  and reversing the whole range.
+
+  On a reverse iterator `iterator_seek/2` positions at the largest key less than
+  or equal to the target, and `iterator_reset/1` rewinds to the last key of the
+  prefix.
+
+  Instead of a direction, a keyword list can be given:
+
+  - `:direction` - `:forward` (default) or `:reverse`
+  - `:upper_bound` - Exclusive key to stop at, such as a pagination cursor.
+    The prefix check still applies; the bound is also handed to RocksDB as
+    its iterate upper bound, so blocks past it are never read. It is kept
+    when `iterator_seek/2` or `iterator_reset/1` rebuild the iterator. A
+    reverse iterator with an upper bound starts at the last key below it.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `prefix` - The prefix to iterate over (can be empty for full scan)
+  - `opts` - `:forward`, `:reverse` or a keyword list
+
+  ## Returns
+  - `{:ok, iterator_ref}` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:invalid_direction, direction}}` if direction is not recognized
+  - `{:error, {:invalid_option, name}}` if an option has the wrong type
+
+  ## Examples
+
+      iex> NIF.put(db, :spo, "s1p1o1", "")
+      iex> NIF.put(db, :spo, "s1p2o2", "")
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1", :reverse)
+      iex> NIF.iterator_collect(iter)
+      {:ok, [{"s1p2o2", ""}, {"s1p1o1", ""}]}
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1", upper_bound: "s1p2")
+      iex> NIF.iterator_collect(iter)
+      {:ok, [{"s1p1o1", ""}]}
+
+  """
+  @spec prefix_iterator(
+          db_ref(),
+          column_family(),
+          binary(),
+          iterator_direction() | prefix_iterator_opts()
+        ) ::
+          {:ok, iterator_ref()} | {:error, term()}
+  def prefix_iterator(_db_ref, _cf, _prefix, _direction),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Creates a prefix iterator that only yields entries whose value starts with
+  `value_prefix`.
+
+  Values are filtered inside the NIF, so non-matching entries never cross the
+  NIF boundary and no binaries are allocated for them. This is useful for
+  sparse scans such as looking for a marker byte in `:derived`. The key prefix
+  bound works exactly as in `prefix_iterator/3`, and the returned iterator is
+  used with the usual `iterator_*` functions.
+
+  An empty `value_prefix` matches every entry.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `prefix` - The key prefix to iterate over (can be empty for full scan)
+  - `value_prefix` - Required prefix of each yielded value
+
+  ## Returns
+  - `{:ok, iterator_ref}` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+
+  ## Examples
+
+      iex> NIF.put(db, :derived, "k1", <<1, "a">>)
+      iex> NIF.put(db, :derived, "k2", <<2, "b">>)
+      iex> {:ok, iter} = NIF.prefix_iterator_filtered(db, :derived, "k", <<1>>)
+      iex> NIF.iterator_collect(iter)
+      {:ok, [{"k1", <<1, "a">>}]}
+
+  """
+  @spec prefix_iterator_filtered(db_ref(), column_family(), binary(), binary()) ::
+          {:ok, iterator_ref()} | {:error, term()}
+  def prefix_iterator_filtered(_db_ref, _cf, _prefix, _value_prefix),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Creates a prefix iterator positioned strictly after `after_key`.
+
+  The first `iterator_next/1` returns the smallest key within `prefix` that is
+  greater than `after_key`, whether or not `after_key` itself is stored. Use it
+  for pagination by passing the last key of the previous page. If `after_key`
+  sorts before `prefix`, iteration starts at the beginning of the prefix.
+  `iterator_reset/1` rewinds to the start of the prefix, not to `after_key`.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `prefix` - The key prefix to iterate over
+  - `after_key` - Exclusive lower bound for returned keys
+
+  ## Returns
+  - `{:ok, iterator_ref}` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.prefix_iterator_after(db, :spo, "page", "page:10")
+      iex> NIF.iterator_next(iter)
+      {:ok, "page:11", "value"}
+
+  """
+  @spec prefix_iterator_after(db_ref(), column_family(), binary(), binary()) ::
+          {:ok, iterator_ref()} | {:error, term()}
+  def prefix_iterator_after(_db_ref, _cf, _prefix, _after_key),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Creates an iterator over the key range `[lower, upper)`.
+
+  The bounds are handed to RocksDB, which stops at `upper` by itself and can
+  skip SST files outside the range instead of checking every key against a
+  prefix. Prefer it over `prefix_iterator/3` for large scans and for
+  ranges spanning several prefixes. `iterator_reset/1` rewinds to `lower`, and
+  `iterator_next_reason/1` reports `:prefix_boundary` when keys exist at or
+  after `upper`.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `lower` - Inclusive lower bound
+  - `upper` - Exclusive upper bound
+
+  ## Returns
+  - `{:ok, iterator_ref}` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, :invalid_range}` if `lower` is greater than `upper`
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.bounded_iterator(db, :spo, "s1", "s3")
+      iex> NIF.iterator_collect(iter)
+      {:ok, [{"s1p1o1", ""}, {"s2p1o1", ""}]}
+
+  """
+  @spec bounded_iterator(db_ref(), column_family(), binary(), binary()) ::
+          {:ok, iterator_ref()} | {:error, term()}
+  def bounded_iterator(_db_ref, _cf, _lower, _upper),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @typedoc "Option accepted by `range_iterator/5`"
+  @type range_iterator_option :: {:inclusive_end, boolean()}
+
+  @doc """
+  Creates an iterator over the keys from `start_key` up to `end_key`.
+
+  Works like `bounded_iterator/4`: the range is set as RocksDB lower and upper
+  bounds and stored on the iterator in place of a prefix. Use it for range
+  filters over sortable key encodings, such as scanning `:spo` from `S1P1` up
+  to `S1P9`. `end_key` is exclusive unless `inclusive_end: true` is given.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `start_key` - Inclusive first key
+  - `end_key` - Last key, exclusive by default
+  - `opts` - Keyword list of options:
+    - `:inclusive_end` - Also yield `end_key` itself (default: `false`)
+
+  ## Returns
+  - `{:ok, iterator_ref}` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, :invalid_range}` if `start_key` is greater than `end_key`
+  - `{:error, {:invalid_option, name}}` if an option value has the wrong type
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.range_iterator(db, :spo, "s1", "s2p1o1", inclusive_end: true)
+      iex> NIF.iterator_collect(iter)
+      {:ok, [{"s1p1o1", ""}, {"s2p1o1", ""}]}
+
+  """
+  @spec range_iterator(db_ref(), column_family(), binary(), binary(), [range_iterator_option()]) ::
+          {:ok, iterator_ref()} | {:error, term()}
+  def range_iterator(_db_ref, _cf, _start_key, _end_key, _opts),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Gets the next key-value pair from the iterator.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `iter_ref` - The iterator reference
+
+  ## Returns
+  - `{:ok, key, value}` if there's a next item with matching prefix
+  - `:iterator_end` if the iterator is exhausted or prefix no longer matches
+  - `{:error, :iterator_closed}` if iterator was closed
+  - `{:error, {:iterator_failed, reason}}` on error
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
+      iex> {:ok, key, value} = NIF.iterator_next(iter)
+      iex> NIF.iterator_next(iter)
+      :iterator_end
+
+  """
+  @spec iterator_next(iterator_ref()) ::
+          {:ok, binary(), binary()} | :iterator_end | {:error, term()}
+  def iterator_next(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Gets the next key-value pair from the iterator, reporting why iteration ended.
+
+  Behaves like `iterator_next/1`, but distinguishes hitting the prefix bound
+  from running off the end of the column family. Useful for debugging and for
+  join logic that needs to know whether more data exists beyond the prefix.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `iter_ref` - The iterator reference
+
+  ## Returns
+  - `{:ok, key, value}` if there's a next item with matching prefix
+  - `{:iterator_end, :prefix_boundary}` if the next key no longer matches the prefix
+  - `{:iterator_end, :cf_end}` if the column family has no more keys
+  - `{:error, :iterator_closed}` if iterator was closed
+  - `{:error, {:iterator_failed, reason}}` on error
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
+      iex> {:ok, _key, _value} = NIF.iterator_next_reason(iter)
+      iex> NIF.iterator_next_reason(iter)
+      {:iterator_end, :prefix_boundary}
+
+  """
+  @spec iterator_next_reason(iterator_ref()) ::
+          {:ok, binary(), binary()}
+          | {:iterator_end, :prefix_boundary | :cf_end}
+          | {:error, term()}
+  def iterator_next_reason(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Gets up to `max_count` key-value pairs from the iterator in one call.
+
+  Applies the prefix, bounds and value filter exactly as `iterator_next/1`
+  and stops early at the boundary, so consuming an iterator in batches yields
+  the same entries as calling `iterator_next/1` repeatedly, with one NIF call
+  per batch instead of per entry. Larger batches raise throughput; smaller
+  ones return the first rows sooner.
+
+  `:more` only means the batch filled up; the following batch may still be
+  empty with `:end`.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `iter_ref` - The iterator reference
+  - `max_count` - Maximum number of entries to return
+
+  ## Returns
+  - `{:ok, [{key, value}, ...], :more}` if the batch filled up
+  - `{:ok, [{key, value}, ...], :end}` if the iterator is exhausted
+  - `{:error, :iterator_closed}` if iterator was closed
+  - `{:error, {:iterator_failed, reason}}` on error
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
+      iex> NIF.iterator_next_batch(iter, 2)
+      {:ok, [{"s1p1o1", ""}, {"s1p1o2", ""}], :more}
+      iex> NIF.iterator_next_batch(iter, 2)
+      {:ok, [{"s1p2o1", ""}], :end}
+
+  """
+  @spec iterator_next_batch(iterator_ref(), non_neg_integer()) ::
+          {:ok, [{binary(), binary()}], :more | :end} | {:error, term()}
+  def iterator_next_batch(_iter_ref, _max_count), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Skips up to `n` entries in one call, for SPARQL `OFFSET`.
+
+  Advances exactly like `n` calls to `iterator_next/1`, without copying the
+  skipped keys and values back: the prefix, bounds and value filter apply,
+  filtered entries don't count, and skipping stops at the boundary. The next
+  `iterator_next/1` returns the entry after the last one skipped.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `iter_ref` - The iterator reference
+  - `n` - Maximum number of entries to skip
+
+  ## Returns
+  - `{:ok, skipped}` where `skipped < n` only if the iterator ran out first
+  - `{:error, :iterator_closed}` if iterator was closed
+  - `{:error, {:iterator_failed, reason}}` on error
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
+      iex> NIF.iterator_skip(iter, 100)
+      {:ok, 100}
+      iex> {:ok, _key, _value} = NIF.iterator_next(iter)
+
+  """
+  @spec iterator_skip(iterator_ref(), non_neg_integer()) ::
+          {:ok, non_neg_integer()} | {:error, term()}
+  def iterator_skip(_iter_ref, _n), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Steps the iterator back to the entry before the last one returned.
+
+  Moves against the iterator's direction: towards smaller keys for forward
+  iterators, towards larger keys for reverse ones. Together with
+  `iterator_next/1` it behaves like a cursor: after `iterator_next/1` returned
+  `b`, `iterator_prev/1` returns the entry before `b`, and the following
+  `iterator_next/1` returns `b` again.
+
+  - After `iterator_seek/2` it returns the entry before the seek target.
+  - On a fresh or reset iterator there is nothing before the start, so it
+    returns `:iterator_end`.
+  - Running off either end leaves the cursor there: `iterator_prev/1` after
+    `iterator_next/1` returned `:iterator_end` returns the last entry, and
+    `iterator_next/1` after `iterator_prev/1` returned `:iterator_end`
+    returns the first.
+
+  The prefix, bounds and value filter apply exactly as for `iterator_next/1`,
+  so stepping back out of the prefix returns `:iterator_end`.
+
+  RocksDB iterators only move one way, so changing direction rebuilds the
+  underlying iterator from the current key. Consecutive steps in the same
+  direction cost the same as `iterator_next/1`.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `iter_ref` - The iterator reference
+
+  ## Returns
+  - `{:ok, key, value}` for the previous entry
+  - `:iterator_end` if there is no previous entry within the prefix or bounds
+  - `{:error, :iterator_closed}` if iterator was closed
+  - `{:error, {:iterator_failed, reason}}` on error
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
+      iex> {:ok, "s1a", _} = NIF.iterator_next(iter)
+      iex> {:ok, "s1b", _} = NIF.iterator_next(iter)
+      iex> {:ok, "s1a", _} = NIF.iterator_prev(iter)
+      iex> NIF.iterator_prev(iter)
+      :iterator_end
+
+  """
+  @spec iterator_prev(iterator_ref()) ::
+          {:ok, binary(), binary()} | :iterator_end | {:error, term()}
+  def iterator_prev(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Seeks the iterator to a specific key.
+
+  After seeking, the iterator will return keys >= target that match the prefix.
+  This is essential for Leapfrog Triejoin in Phase 3.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `iter_ref` - The iterator reference
+  - `target` - The key to seek to
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :iterator_closed}` if iterator was closed
+  - `{:error, :already_closed}` if database was closed
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
+      iex> NIF.iterator_seek(iter, "s2")
+      :ok
+      iex> {:ok, key, _value} = NIF.iterator_next(iter)
+      iex> key >= "s2"
+      true
+
+  """
+  @spec iterator_seek(iterator_ref(), binary()) :: :ok | {:error, term()}
+  def iterator_seek(_iter_ref, _target), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Seeks to `target` and returns the first key `>= target` in one call.
+
+  This is the seek step of Leapfrog Triejoin: the smallest key `>= target`
+  within the iterator's prefix, or none. Unlike `iterator_seek/2`, the landed
+  entry is consumed and its key returned, saving a follow-up
+  `iterator_next/1`; the next `iterator_next/1` returns the entry after it.
+  Keys outside the prefix are never returned, and filtered iterators skip
+  entries as `iterator_next/1` does.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `iter_ref` - The iterator reference
+  - `target` - The key to seek to
+
+  ## Returns
+  - `{:ok, key}` for the first key `>= target` within the prefix
+  - `:iterator_end` if there is no such key
+  - `{:error, :iterator_closed}` if iterator was closed
+  - `{:error, {:iterator_failed, reason}}` on error
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s")
+      iex> NIF.iterator_seek_ge(iter, "s2")
+      {:ok, "s2p1o1"}
+
+  """
+  @spec iterator_seek_ge(iterator_ref(), binary()) ::
+          {:ok, binary()} | :iterator_end | {:error, term()}
+  def iterator_seek_ge(_iter_ref, _target), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Seeks to the largest key `<= target`, for descending scans.
+
+  The counterpart of `iterator_seek/2`, using RocksDB's `SeekForPrev`. The
+  next step towards smaller keys returns the largest key `<= target` and keeps
+  descending from there. On forward iterators that step is `iterator_prev/1`,
+  as if the iterator had just stepped back past `target`, and
+  `iterator_next/1` returns the keys after `target`. On reverse iterators it
+  is `iterator_next/1`, so this is the same as `iterator_seek/2`.
+
+  The prefix and bounds still apply: with no key `<= target` inside them,
+  e.g. for a target below the prefix, the next step returns `:iterator_end`.
+  A target past them lands on their last key.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `iter_ref` - The iterator reference
+  - `target` - The key to seek to
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :iterator_closed}` if iterator was closed
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s")
+      iex> NIF.iterator_seek_for_prev(iter, "s2")
+      :ok
+      iex> NIF.iterator_prev(iter)
+      {:ok, "s1p1o1", ""}
+
+  """
+  @spec iterator_seek_for_prev(iterator_ref(), binary()) :: :ok | {:error, term()}
+  def iterator_seek_for_prev(_iter_ref, _target), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Rewinds an iterator to the start of its prefix.
+
+  The iterator is rebuilt exactly as `prefix_iterator/3` created it, from the
+  same snapshot, so the next `iterator_next/1` returns the smallest key with
+  the prefix as of the iterator's creation. Useful after
+  seeking around during a join, without closing and reopening the iterator.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `iter_ref` - The iterator reference
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :iterator_closed}` if iterator was closed
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
+      iex> NIF.iterator_seek(iter, "s1p9")
+      iex> NIF.iterator_reset(iter)
+      :ok
+
+  """
+  @spec iterator_reset(iterator_ref()) :: :ok | {:error, :iterator_closed}
+  def iterator_reset(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Moves an iterator to the first key of the whole column family.
+
+  Unlike `iterator_reset/1`, this ignores the iterator's prefix: it positions
+  at the absolute start of the column family, as `iterator_seek/2` to its
+  smallest key would. The following `iterator_next/1` and `iterator_prev/1`
+  still apply the prefix check, so they return `:iterator_end` right away if
+  the prefix doesn't cover that key. Meant for full-CF iterators (empty
+  prefix). Bounded iterators stay within their bounds.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `iter_ref` - The iterator reference
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :iterator_closed}` if iterator was closed
+  - `{:error, {:iterator_failed, reason}}` on error
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :derived, "")
+      iex> NIF.iterator_seek_to_first(iter)
+      :ok
+      iex> NIF.iterator_next(iter)
+      {:ok, "a", "1"}
+
+  """
+  @spec iterator_seek_to_first(iterator_ref()) ::
+          :ok | {:error, :iterator_closed | {:iterator_failed, String.t()}}
+  def iterator_seek_to_first(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Moves an iterator to the last key of the whole column family.
+
+  The counterpart of `iterator_seek_to_first/1`, with the same prefix caveats.
+  The next `iterator_next/1` returns the largest key of the column family, so
+  `iterator_seek_to_last/1` followed by `iterator_next/1` on a full-CF
+  iterator answers "give me the max key".
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `iter_ref` - The iterator reference
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :iterator_closed}` if iterator was closed
+  - `{:error, {:iterator_failed, reason}}` on error
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :derived, "")
+      iex> NIF.iterator_seek_to_last(iter)
+      :ok
+      iex> NIF.iterator_next(iter)
+      {:ok, "z", "26"}
+
+  """
+  @spec iterator_seek_to_last(iterator_ref()) ::
+          :ok | {:error, :iterator_closed | {:iterator_failed, String.t()}}
+  def iterator_seek_to_last(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Closes the iterator and releases resources.
+
+  ## Arguments
+  - `iter_ref` - The iterator reference
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :iterator_closed}` if already closed
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
+      iex> NIF.iterator_close(iter)
+      :ok
+      iex> NIF.iterator_close(iter)
+      {:error, :iterator_closed}
+
+  """
+  @spec iterator_close(iterator_ref()) :: :ok | {:error, :iterator_closed}
+  def iterator_close(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Collects all remaining key-value pairs from an iterator into a list.
+
+  This is a convenience function that returns all matching entries.
+  Useful for small result sets where streaming isn't needed.
+  The iterator position advances to the end after this call.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `iter_ref` - The iterator reference
+
+  ## Returns
+  - `{:ok, [{key, value}, ...]}` with all remaining entries
+  - `{:error, :iterator_closed}` if iterator was closed
+  - `{:error, {:iterator_failed, reason}}` on error
+
+  ## Examples
+
+      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
+      iex> {:ok, results} = NIF.iterator_collect(iter)
+      iex> length(results)
+      3
+
+  """
+  @spec iterator_collect(iterator_ref()) ::
+          {:ok, [{binary(), binary()}]} | {:error, term()}
+  def iterator_collect(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ============================================================================
+  # Stream Wrapper
+  # ============================================================================
+
+  @doc """
+  Creates an Elixir Stream from a prefix iterator.
+
+  This wraps the iterator in a lazy Stream that automatically handles
+  iteration and cleanup. The iterator is closed when the stream is
+  fully consumed or when the stream is garbage collected.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `prefix` - The prefix to iterate over (can be empty for full scan)
+
+  ## Returns
+  - `{:ok, Stream.t()}` on success
+  - `{:error, term()}` on failure
+
+  ## Examples
+
+      iex> {:ok, stream} = NIF.prefix_stream(db, :spo, "s1")
+      iex> Enum.take(stream, 5)
+      [{"s1p1o1", ""}, {"s1p1o2", ""}, ...]
+
+  """
+  @spec prefix_stream(db_ref(), column_family(), binary()) ::
+          {:ok, Enumerable.t()} | {:error, term()}
+  def prefix_stream(db_ref, cf, prefix) do
+    case prefix_iterator(db_ref, cf, prefix) do
+      {:ok, iter} ->
+        stream =
+          Stream.resource(
+            fn -> iter end,
+            &stream_next/1,
+            fn iter -> iterator_close(iter) end
+          )
+
+        {:ok, stream}
+
+      error ->
+        error
+    end
+  end
+
+  defp stream_next(iter) do
+    case iterator_next(iter) do
+      {:ok, key, value} -> {[{key, value}], iter}
+      :iterator_end -> {:halt, iter}
+      {:error, _} -> {:halt, iter}
+    end
+  end
+
+  # ============================================================================
+  # Index Scan Operations
+  # ============================================================================
+
+  @doc """
+  Scans a subject's `:spo` entries and groups object IDs by predicate.
+
+  `:spo` keys are sorted predicate-then-object within a subject, so grouping
+  is a single linear pass in Rust. Useful for star-shaped queries and
+  property-table construction without Elixir-side grouping.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `subject_prefix` - The encoded subject ID (e.g. `<<s::64-big>>`)
+  - `id_width` - Width in bytes of each ID in the index keys (`8` normally)
+  - `max` - Maximum total number of objects returned across all predicates
+
+  ## Returns
+  - `{:ok, %{pred_id => [obj_id, ...]}, more?}` where `more?` is `true` when
+    entries beyond `max` were not returned
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_id_width, width}}` if width is not in `1..8`
+  - `{:error, {:malformed_key, length}}` if a key has an unexpected length
+  - `{:error, {:iterator_failed, reason}}` on read errors
+
+  ## Examples
+
+      iex> NIF.put(db, :spo, <<1::64, 2::64, 3::64>>, "")
+      iex> NIF.put(db, :spo, <<1::64, 2::64, 4::64>>, "")
+      iex> NIF.scan_subject_grouped(db, <<1::64>>, 8, 100)
+      {:ok, %{2 => [3, 4]}, false}
+
+  """
+  @spec scan_subject_grouped(db_ref(), binary(), pos_integer(), non_neg_integer()) ::
+          {:ok, %{non_neg_integer() => [non_neg_integer()]}, boolean()} | {:error, term()}
+  def scan_subject_grouped(_db_ref, _subject_prefix, _id_width, _max),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Aggregates the values under a prefix without returning them.
+
+  The reduction runs in Rust, so values never cross the NIF boundary. Useful
+  for counter-style derived data.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `prefix` - The key prefix to scan
+  - `opts` - Map with the `:op` to apply:
+    - `:count` - Number of entries under the prefix
+    - `:sum_u64` - Sum of all values, each read as a little-endian u64
+
+  ## Returns
+  - `{:ok, result}` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:invalid_option, :op}}` if `:op` is missing or unknown
+  - `{:error, :bad_value_width}` if a value isn't 8 bytes in `:sum_u64` mode
+  - `{:error, :overflow}` if the sum doesn't fit in a u64
+  - `{:error, {:iterator_failed, reason}}` on read errors
+
+  ## Examples
+
+      iex> NIF.scan_aggregate(db, :derived, "counter:", %{op: :sum_u64})
+      {:ok, 42}
+
+  """
+  @spec scan_aggregate(db_ref(), column_family(), binary(), %{op: :count | :sum_u64}) ::
+          {:ok, non_neg_integer()} | {:error, term()}
+  def scan_aggregate(_db_ref, _cf, _prefix, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Counts the keys under a prefix without returning them.
+
+  Returns the same number as `iterator_collect/1` followed by `length/1`, but
+  no binaries are allocated: keys are compared in place on a raw RocksDB
+  iterator and values are never read out. Stops at the first key outside the
+  prefix, like `iterator_next/1`. Meant for cardinality estimation of BGP
+  patterns.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `prefix` - The key prefix to count (empty counts the whole column family)
+
+  ## Returns
+  - `{:ok, count}` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:cf_not_opened, cf}}` if the column family was not opened
+  - `{:error, {:iterator_failed, reason}}` on read errors
+
+  ## Examples
+
+      iex> NIF.prefix_count(db, :spo, <<subject_id::64-big>>)
+      {:ok, 17}
+
+  """
+  @spec prefix_count(db_ref(), column_family(), binary()) ::
+          {:ok, non_neg_integer()} | {:error, term()}
+  def prefix_count(_db_ref, _cf, _prefix), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ============================================================================
+  # Snapshot Operations
+  # ============================================================================
+
+  @type snapshot_ref :: reference()
+  @type snapshot_iterator_ref :: reference()
+
+  @doc """
+  Creates a snapshot of the database for consistent point-in-time reads.
+
+  A snapshot provides a consistent view of the database at the time of creation.
+  All reads using the snapshot will see the same data, regardless of subsequent
+  writes to the database. This is essential for transaction isolation.
+
+  The snapshot must be released with `release_snapshot/1` when done, or it will
+  be automatically released when garbage collected.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+
+  ## Returns
+  - `{:ok, snapshot_ref}` on success
+  - `{:error, :already_closed}` if database is closed
+
+  ## Examples
+
+      iex> {:ok, db} = NIF.open("/tmp/test_db")
+      iex> NIF.put(db, :spo, "key1", "value1")
+      iex> {:ok, snap} = NIF.snapshot(db)
+      iex> NIF.put(db, :spo, "key1", "value2")  # Update after snapshot
+      iex> NIF.snapshot_get(snap, :spo, "key1")
+      {:ok, "value1"}  # Still sees old value
+
+  """
+  @spec snapshot(db_ref()) :: {:ok, snapshot_ref()} | {:error, term()}
+  def snapshot(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Gets a value from a column family using a snapshot.
+
+  This provides point-in-time consistent reads - the value returned
+  is what existed at the time the snapshot was created.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `snapshot_ref` - The snapshot reference
+  - `cf` - The column family atom
+  - `key` - The key as a binary
+
+  ## Returns
+  - `{:ok, value}` if found
+  - `:not_found` if key doesn't exist at snapshot time
+  - `{:error, :snapshot_released}` if snapshot was released
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:get_failed, reason}}` on other errors
+
+  ## Examples
+
+      iex> {:ok, snap} = NIF.snapshot(db)
+      iex> NIF.snapshot_get(snap, :spo, "key1")
+      {:ok, "value1"}
+
+  """
+  @spec snapshot_get(snapshot_ref(), column_family(), binary()) ::
+          {:ok, binary()} | :not_found | {:error, term()}
+  def snapshot_get(_snapshot_ref, _cf, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Gets multiple values from a column family using a snapshot.
+
+  All keys are read in one batch against the snapshot's point-in-time view,
+  so writes committed after the snapshot was taken are never observed. This
+  is the consistent batch probe used by the join executor.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `snapshot_ref` - The snapshot reference
+  - `cf` - The column family atom
+  - `keys` - List of keys as binaries
+
+  ## Returns
+  - `{:ok, results}` with one `{:ok, value}` or `:not_found` per key, in key order
+  - `{:error, :snapshot_released}` if snapshot was released
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:get_failed, reason}}` if any read fails
+
+  ## Examples
+
+      iex> {:ok, snap} = NIF.snapshot(db)
+      iex> NIF.multi_get_snapshot(snap, :spo, ["key1", "missing"])
+      {:ok, [{:ok, "value1"}, :not_found]}
+
+  """
+  @spec multi_get_snapshot(snapshot_ref(), column_family(), [binary()]) ::
+          {:ok, [{:ok, binary()} | :not_found]} | {:error, term()}
+  def multi_get_snapshot(_snapshot_ref, _cf, _keys), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Creates a prefix iterator over a snapshot.
+
+  The iterator returns all key-value pairs where the key starts with the given prefix,
+  using the consistent view from the snapshot.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `snapshot_ref` - The snapshot reference
+  - `cf` - The column family atom
+  - `prefix` - The prefix to iterate over (can be empty for full scan)
+
+  ## Returns
+  - `{:ok, iterator_ref}` on success
+  - `{:error, :snapshot_released}` if snapshot was released
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+
+  ## Examples
+
+      iex> {:ok, snap} = NIF.snapshot(db)
+      iex> {:ok, iter} = NIF.snapshot_prefix_iterator(snap, :spo, "s1")
+      iex> {:ok, key, value} = NIF.snapshot_iterator_next(iter)
+
+  """
+  @spec snapshot_prefix_iterator(snapshot_ref(), column_family(), binary()) ::
+          {:ok, snapshot_iterator_ref()} | {:error, term()}
+  def snapshot_prefix_iterator(_snapshot_ref, _cf, _prefix),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Gets the next key-value pair from a snapshot iterator.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `iter_ref` - The snapshot iterator reference
+
+  ## Returns
+  - `{:ok, key, value}` if there's a next item with matching prefix
+  - `:iterator_end` if the iterator is exhausted or prefix no longer matches
+  - `{:error, :iterator_closed}` if iterator was closed
+  - `{:error, {:iterator_failed, reason}}` on error
+
+  """
+  @spec snapshot_iterator_next(snapshot_iterator_ref()) ::
+          {:ok, binary(), binary()} | :iterator_end | {:error, term()}
+  def snapshot_iterator_next(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Closes a snapshot iterator and releases resources.
+
+  ## Arguments
+  - `iter_ref` - The snapshot iterator reference
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :iterator_closed}` if already closed
+
+  """
+  @spec snapshot_iterator_close(snapshot_iterator_ref()) :: :ok | {:error, :iterator_closed}
+  def snapshot_iterator_close(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Collects all remaining key-value pairs from a snapshot iterator into a list.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `iter_ref` - The snapshot iterator reference
+
+  ## Returns
+  - `{:ok, [{key, value}, ...]}` with all remaining entries
+  - `{:error, :iterator_closed}` if iterator was closed
+  - `{:error, {:iterator_failed, reason}}` on error
+
+  """
+  @spec snapshot_iterator_collect(snapshot_iterator_ref()) ::
+          {:ok, [{binary(), binary()}]} | {:error, term()}
+  def snapshot_iterator_collect(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Releases a snapshot and frees resources.
+
+  After calling release, the snapshot handle is no longer valid.
+
+  ## Arguments
+  - `snapshot_ref` - The snapshot reference
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :snapshot_released}` if already released
+
+  ## Examples
+
+      iex> {:ok, snap} = NIF.snapshot(db)
+      iex> NIF.release_snapshot(snap)
+      :ok
+      iex> NIF.release_snapshot(snap)
+      {:error, :snapshot_released}
+
+  """
+  @spec release_snapshot(snapshot_ref()) :: :ok | {:error, :snapshot_released}
+  def release_snapshot(_snapshot_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Creates an Elixir Stream from a snapshot prefix iterator.
+
+  This wraps the snapshot iterator in a lazy Stream that automatically handles
+  iteration and cleanup. The iterator is closed when the stream is fully consumed.
+
+  ## Arguments
+  - `snapshot_ref` - The snapshot reference
+  - `cf` - The column family atom
+  - `prefix` - The prefix to iterate over (can be empty for full scan)
+
+  ## Returns
+  - `{:ok, Stream.t()}` on success
+  - `{:error, term()}` on failure
+
+  ## Examples
+
+      iex> {:ok, snap} = NIF.snapshot(db)
+      iex> {:ok, stream} = NIF.snapshot_stream(snap, :spo, "s1")
+      iex> Enum.to_list(stream)
+      [{"s1p1o1", ""}, {"s1p1o2", ""}]
+
+  """
+  @spec snapshot_stream(snapshot_ref(), column_family(), binary()) ::
+          {:ok, Enumerable.t()} | {:error, term()}
+  def snapshot_stream(snapshot_ref, cf, prefix) do
+    case snapshot_prefix_iterator(snapshot_ref, cf, prefix) do
+      {:ok, iter} ->
+        stream =
+          Stream.resource(
+            fn -> iter end,
+            &snapshot_stream_next/1,
+            fn iter -> snapshot_iterator_close(iter) end
+          )
+
+        {:ok, stream}
+
+      error ->
+        error
+    end
+  end
+
+  defp snapshot_stream_next(iter) do
+    case snapshot_iterator_next(iter) do
+      {:ok, key, value} -> {[{key, value}], iter}
+      :iterator_end -> {:halt, iter}
+      {:error, _} -> {:halt, iter}
+    end
+  end
+
+  # ============================================================================
+  # Transactions
+  # ============================================================================
+
+  @type txn_ref :: reference()
+
+  @doc """
+  Begins a transaction.
+
+  Writes are buffered until `transaction_commit/1`. Every key the transaction
+  writes, or reads with `transaction_get_for_update/3`, is locked until the
+  transaction commits, rolls back or is garbage-collected. Another
+  transaction that writes or `transaction_get_for_update/3`s a locked key
+  waits up to a second for it and then gets `{:error, :busy}`, so no other
+  transaction can change a locked key in the meantime.
+
+  Plain (non-transactional) writes take no locks. The locked keys are re-read
+  on commit, and if a plain write changed one, the commit returns
+  `{:error, :busy}` and writes nothing; a plain write that restores the key's
+  original value before the commit is not detected. Otherwise all buffered
+  writes are applied as one atomic batch.
+
+  ## Arguments
+  - `db_ref` - The database reference
+
+  ## Returns
+  - `{:ok, txn_ref}` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, :read_only}` if the database was opened read-only
+
+  ## Examples
+
+      iex> {:ok, txn} = NIF.transaction_begin(db)
+      iex> {:ok, <<id::64>>} = NIF.transaction_get_for_update(txn, :derived, "next_id")
+      iex> NIF.transaction_put(txn, :derived, "next_id", <<id + 1::64>>)
+      :ok
+      iex> NIF.transaction_commit(txn)
+      :ok
+
+  """
+  @spec transaction_begin(db_ref()) :: {:ok, txn_ref()} | {:error, :already_closed | :read_only}
+  def transaction_begin(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Buffers a put in a transaction and locks the key.
+
+  ## Arguments
+  - `txn_ref` - The transaction reference
+  - `cf` - The column family atom
+  - `key` - The key as a binary
+  - `value` - The value as a binary
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :transaction_closed}` if the transaction was committed or rolled back
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:cf_not_opened, cf}}` if the column family wasn't opened
+  - `{:error, :busy}` if another transaction holds the key's lock for over a second
+  - `{:error, {:put_failed, reason}}` on failure
+
+  """
+  @spec transaction_put(txn_ref(), column_family(), binary(), binary()) :: :ok | {:error, term()}
+  def transaction_put(_txn_ref, _cf, _key, _value), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Buffers a delete in a transaction and locks the key.
+
+  ## Arguments
+  - `txn_ref` - The transaction reference
+  - `cf` - The column family atom
+  - `key` - The key as a binary
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :transaction_closed}` if the transaction was committed or rolled back
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:cf_not_opened, cf}}` if the column family wasn't opened
+  - `{:error, :busy}` if another transaction holds the key's lock for over a second
+  - `{:error, {:delete_failed, reason}}` on failure
+
+  """
+  @spec transaction_delete(txn_ref(), column_family(), binary()) :: :ok | {:error, term()}
+  def transaction_delete(_txn_ref, _cf, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Reads a key within a transaction.
+
+  Sees the transaction's own buffered writes. The key is not locked, so
+  concurrent changes to it don't fail the commit; use
+  `transaction_get_for_update/3` for read-modify-write.
+
+  ## Arguments
+  - `txn_ref` - The transaction reference
+  - `cf` - The column family atom
+  - `key` - The key as a binary
+
+  ## Returns
+  - `{:ok, value}` if found
+  - `:not_found` if key doesn't exist
+  - `{:error, :transaction_closed}` if the transaction was committed or rolled back
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:cf_not_opened, cf}}` if the column family wasn't opened
+  - `{:error, {:get_failed, reason}}` on failure
+
+  """
+  @spec transaction_get(txn_ref(), column_family(), binary()) ::
+          {:ok, binary()} | :not_found | {:error, term()}
+  def transaction_get(_txn_ref, _cf, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Reads a key within a transaction and locks it.
+
+  Like RocksDB's `GetForUpdate`: other transactions can't write or lock the
+  key until this one finishes, and if a plain write changes it before this
+  transaction commits, the commit returns `{:error, :busy}`. Use it for
+  read-modify-write such as bumping the term-ID counter.
+
+  ## Arguments
+  - `txn_ref` - The transaction reference
+  - `cf` - The column family atom
+  - `key` - The key as a binary
+
+  ## Returns
+  Same as `transaction_get/3`, plus `{:error, :busy}` if another transaction
+  holds the key's lock for over a second.
+
+  """
+  @spec transaction_get_for_update(txn_ref(), column_family(), binary()) ::
+          {:ok, binary()} | :not_found | {:error, term()}
+  def transaction_get_for_update(_txn_ref, _cf, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Commits a transaction.
+
+  The transaction is finished whatever the outcome; retry a conflicting
+  transaction by beginning a new one.
+
+  ## Arguments
+  - `txn_ref` - The transaction reference
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :busy}` if a plain write changed a locked key
+  - `{:error, :transaction_closed}` if the transaction was committed or rolled back
+  - `{:error, {:batch_failed, reason}}` on write failure
+
+  """
+  @spec transaction_commit(txn_ref()) :: :ok | {:error, term()}
+  def transaction_commit(_txn_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Discards a transaction's buffered writes and releases its locks.
+
+  ## Arguments
+  - `txn_ref` - The transaction reference
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :transaction_closed}` if the transaction was committed or rolled back
+
+  """
+  @spec transaction_rollback(txn_ref()) :: :ok | {:error, :transaction_closed}
+  def transaction_rollback(_txn_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ============================================================================
+  # TransactionDB
+  # ============================================================================
+
+  @type txn_db_ref :: reference()
+  @type txn_db_transaction_ref :: reference()
+
+  @doc """
+  Opens a RocksDB `TransactionDB` at the given path with the TripleStore
+  column families.
+
+  Unlike the emulated transactions of `transaction_begin/1`, RocksDB itself
+  locks every key a transaction writes or reads with `txn_get/3`, and detects
+  writes committed by others since the transaction began. The handle only
+  supports `begin_transaction/1` and `close_transactional/1`.
+
+  ## Arguments
+  - `path` - Path to the database directory
+
+  ## Returns
+  - `{:ok, txn_db_ref}` on success
+  - `{:error, {:open_failed, reason}}` on failure
+
+  ## Examples
+
+      iex> {:ok, db} = NIF.open_transactional("/tmp/txn_db")
+      iex> {:ok, txn} = NIF.begin_transaction(db)
+      iex> NIF.txn_put(txn, :str2id, "term", <<1::64>>)
+      :ok
+      iex> NIF.commit(txn)
+      :ok
+
+  """
+  @spec open_transactional(String.t()) ::
+          {:ok, txn_db_ref()} | {:error, {:open_failed, String.t()}}
+  def open_transactional(_path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Closes a database opened with `open_transactional/1`.
+
+  Transactions still open keep the database alive until they are committed,
+  rolled back or garbage-collected.
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if already closed
+
+  """
+  @spec close_transactional(txn_db_ref()) :: :ok | {:error, :already_closed}
+  def close_transactional(_txn_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Begins a transaction on a database opened with `open_transactional/1`.
+
+  The transaction sees a snapshot taken when it begins: `txn_put/4`,
+  `txn_delete/3` and `txn_get/3` lock their key and return `{:error, :busy}`
+  if another transaction holds it for over a second or if a write committed
+  since then changed it. Writes stay invisible to others until `commit/1`.
+
+  ## Arguments
+  - `txn_db_ref` - The handle from `open_transactional/1`
+
+  ## Returns
+  - `{:ok, txn_ref}` on success
+  - `{:error, :already_closed}` if database is closed
+
+  """
+  @spec begin_transaction(txn_db_ref()) ::
+          {:ok, txn_db_transaction_ref()} | {:error, :already_closed}
+  def begin_transaction(_txn_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Writes a key in a `begin_transaction/1` transaction and locks it.
+
+  ## Arguments
+  - `txn_ref` - The transaction from `begin_transaction/1`
+  - `cf` - The column family atom
+  - `key` - The key as a binary
+  - `value` - The value as a binary
 
   ## Returns
   - `:ok` on success
-  - `{:error, :already_closed}` if database is closed
+  - `{:error, :busy}` if another transaction holds the key's lock for over a
+    second, or a write committed since the transaction began changed the key
+  - `{:error, :transaction_closed}` if the transaction was committed or rolled back
   - `{:error, {:invalid_cf, cf}}` if column family is invalid
-  - `{:error, {:invalid_operation, op}}` if operation type is invalid
-  - `{:error, {:batch_failed, reason}}` on other errors
+  - `{:error, {:cf_not_opened, cf}}` if the column family wasn't opened
+  - `{:error, {:put_failed, reason}}` on other errors
 
-  ## Examples
+  """
+  @spec txn_put(txn_db_transaction_ref(), column_family(), binary(), binary()) ::
+          :ok | {:error, term()}
+  def txn_put(_txn_ref, _cf, _key, _value), do: :erlang.nif_error(:nif_not_loaded)
 
-      iex> {:ok, db} = NIF.open("/tmp/test_db")
-      iex> operations = [
-      ...>   {:put, :spo, "s1p1o1", ""},
-      ...>   {:put, :pos, "p1o1s1", ""},
-      ...>   {:delete, :spo, "old_key"},
-      ...>   {:delete, :pos, "old_key2"}
-      ...> ]
-      iex> NIF.mixed_batch(db, operations, true)
-      :ok
+  @doc """
+  Deletes a key in a `begin_transaction/1` transaction and locks it.
+
+  ## Returns
+  Same as `txn_put/4`, with `{:error, {:delete_failed, reason}}` on other errors.
+
+  """
+  @spec txn_delete(txn_db_transaction_ref(), column_family(), binary()) ::
+          :ok | {:error, term()}
+  def txn_delete(_txn_ref, _cf, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Reads a key in a `begin_transaction/1` transaction and locks it.
+
+  Sees the transaction's own writes. Like RocksDB's `GetForUpdate`, the key
+  stays locked until the transaction finishes, so read-modify-write such as
+  allocating the next term ID is safe.
+
+  ## Returns
+  - `{:ok, value}` if found
+  - `:not_found` if key doesn't exist
+  - `{:error, :busy}`, `{:error, :transaction_closed}`, `{:error, {:invalid_cf, cf}}`
+    or `{:error, {:cf_not_opened, cf}}` as for `txn_put/4`
+  - `{:error, {:get_failed, reason}}` on other errors
+
+  """
+  @spec txn_get(txn_db_transaction_ref(), column_family(), binary()) ::
+          {:ok, binary()} | :not_found | {:error, term()}
+  def txn_get(_txn_ref, _cf, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Commits a `begin_transaction/1` transaction and releases its locks.
+
+  The transaction is finished whatever the outcome; retry a conflicting
+  transaction by beginning a new one.
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :busy}` if RocksDB reports a conflict
+  - `{:error, :transaction_closed}` if the transaction was committed or rolled back
+  - `{:error, {:commit_failed, reason}}` on other errors
+
+  """
+  @spec commit(txn_db_transaction_ref()) :: :ok | {:error, term()}
+  def commit(_txn_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Discards a `begin_transaction/1` transaction's writes and releases its locks.
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :transaction_closed}` if the transaction was committed or rolled back
+  - `{:error, {:rollback_failed, reason}}` on failure
 
   """
-  @spec mixed_batch(db_ref(), [mixed_put() | mixed_delete()], boolean()) :: :ok | {:error, term()}
-  def mixed_batch(_db_ref, _operations, _sync), do: :erlang.nif_error(:nif_not_loaded)
+  @spec rollback(txn_db_transaction_ref()) :: :ok | {:error, term()}
+  def rollback(_txn_ref), do: :erlang.nif_error(:nif_not_loaded)
 
   # ============================================================================
-  # Iterator Operations
+  # Compaction Control
   # ============================================================================
 
-  @type iterator_ref :: reference()
+  @typedoc "Background compaction state returned by `compaction_status/1`"
+  @type compaction_status :: %{
+          running_compactions: non_neg_integer(),
+          pending_compaction_bytes: non_neg_integer()
+        }
 
   @doc """
-  Creates a prefix iterator for a column family.
+  Reports the state of background compactions.
 
-  The iterator returns all key-value pairs where the key starts with the given prefix.
-  The iterator must be closed with `iterator_close/1` when done, or it will be
-  automatically closed when garbage collected.
+  ## Arguments
+  - `db_ref` - The database reference
+
+  ## Returns
+  - `{:ok, status}` where `status` has:
+    - `:running_compactions` - Compactions currently executing
+    - `:pending_compaction_bytes` - Estimated bytes compaction still has to
+      rewrite, summed over all column families
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:compaction_failed, reason}}` if a property can't be read
+
+  ## Examples
+
+      iex> NIF.compaction_status(db)
+      {:ok, %{running_compactions: 0, pending_compaction_bytes: 0}}
+
+  """
+  @spec compaction_status(db_ref()) :: {:ok, compaction_status()} | {:error, term()}
+  def compaction_status(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Disables automatic compactions on all column families.
+
+  Use during incidents to relieve IO pressure, e.g. in a query storm. Writes
+  keep piling up in L0 meanwhile, so leaving compactions off for long ends in
+  write stalls; call `enable_compactions/1` once the pressure is gone.
+
+  ## Arguments
+  - `db_ref` - The database reference
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:set_options_failed, reason}}` on failure
+  """
+  @spec disable_compactions(db_ref()) :: :ok | {:error, term()}
+  def disable_compactions(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Re-enables automatic compactions on all column families.
+
+  Only flips the option: RocksDB then schedules the compactions skipped while
+  they were disabled in the background, and this returns right away. Use
+  `compact_all/1` or `compact_range/4` to force the catch-up synchronously.
 
   Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
 
   ## Arguments
   - `db_ref` - The database reference
-  - `cf` - The column family atom
-  - `prefix` - The prefix to iterate over (can be empty for full scan)
 
   ## Returns
-  - `{:ok, iterator_ref}` on success
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:set_options_failed, reason}}` if the option can't be changed
+  """
+  @spec enable_compactions(db_ref()) :: :ok | {:error, term()}
+  def enable_compactions(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns RocksDB's estimate of the bytes compaction still has to rewrite.
+
+  Summed over all opened column families. A value that keeps growing means
+  compaction is falling behind the write rate, and loaders should throttle
+  before RocksDB starts stalling writes on its own. Pair it with
+  `write_stall_stats/1` to see whether a stall is already happening.
+
+  ## Arguments
+  - `db_ref` - The database reference
+
+  ## Returns
+  - `{:ok, bytes}` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:compaction_failed, reason}}` if the property can't be read
+
+  ## Examples
+
+      iex> NIF.pending_compaction_bytes(db)
+      {:ok, 0}
+
+  """
+  @spec pending_compaction_bytes(db_ref()) :: {:ok, non_neg_integer()} | {:error, term()}
+  def pending_compaction_bytes(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @typedoc "Write stall signals returned by `write_stall_stats/1`"
+  @type write_stall_stats :: %{
+          write_stopped: boolean(),
+          delayed_write_rate: non_neg_integer(),
+          pending_compaction_bytes: non_neg_integer(),
+          max_l0_files: non_neg_integer()
+        }
+
+  @doc """
+  Reports the signals RocksDB uses to stall writes.
+
+  Together with the pending compaction estimate this gives the write
+  scheduler a complete backpressure signal: throttle as the pending bytes or
+  L0 file count climb, and back off hard once RocksDB is already delaying or
+  stopping writes.
+
+  ## Arguments
+  - `db_ref` - The database reference
+
+  ## Returns
+  - `{:ok, stats}` where `stats` has:
+    - `:write_stopped` - Whether writes are currently stopped outright
+    - `:delayed_write_rate` - Throttled write rate in bytes/sec while writes
+      are being slowed down, `0` otherwise
+    - `:pending_compaction_bytes` - Same value as `pending_compaction_bytes/1`
+    - `:max_l0_files` - Largest L0 file count across column families; RocksDB
+      slows writes once it reaches `level0_slowdown_writes_trigger`
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:compaction_failed, reason}}` if a property can't be read
+
+  ## Examples
+
+      iex> NIF.write_stall_stats(db)
+      {:ok, %{write_stopped: false, delayed_write_rate: 0, pending_compaction_bytes: 0, max_l0_files: 1}}
+
+  """
+  @spec write_stall_stats(db_ref()) :: {:ok, write_stall_stats()} | {:error, term()}
+  def write_stall_stats(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compacts a column family, forcing its bottommost level to be rewritten.
+
+  A default manual compaction pushes upper-level data down but may leave files
+  already in the last level untouched, so tombstones that have settled there
+  alongside the values they delete are never purged. This variant always
+  rewrites the bottommost level, dropping both, which is the step that
+  actually reclaims space after large deletes.
+
+  It is not sufficient when the deleted entries are still visible to someone:
+  an open snapshot, iterator or transaction keeps them alive through the
+  compaction, so release those first. It also rewrites the entire bottommost
+  level, so on a large column family with few deletes it costs about as much
+  as a full compaction; prefer it after bulk deletes, not routinely.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - Column family atom
+
+  ## Returns
+  - `:ok` on success
   - `{:error, :already_closed}` if database is closed
   - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:cf_not_opened, cf}}` if column family was not opened
+  - `{:error, {:compact_failed, reason}}` if the compaction failed
 
   ## Examples
 
-      iex> {:ok, db} = NIF.open("/tmp/test_db")
-      iex> NIF.put(db, :spo, "s1p1o1", "")
-      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
-      iex> {:ok, key, _value} = NIF.iterator_next(iter)
-      iex> key
-      "s1p1o1"
+      iex> NIF.compact_bottommost(db, :spo)
+      :ok
 
   """
-  @spec prefix_iterator(db_ref(), column_family(), binary()) ::
-          {:ok, iterator_ref()} | {:error, term()}
-  def prefix_iterator(_db_ref, _cf, _prefix), do: :erlang.nif_error(:nif_not_loaded)
+  @spec compact_bottommost(db_ref(), column_family()) :: :ok | {:error, term()}
+  def compact_bottommost(_db_ref, _cf), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Gets the next key-value pair from the iterator.
+  Compacts the keys of a column family between `start_key` and `end_key`.
+
+  Rewrites the SST files overlapping the range down the levels, removing the
+  read amplification left behind by bulk loads and purging deleted entries.
+  Pass `nil` for either bound to leave the range open on that side;
+  `compact_range(db, cf, nil, nil)` compacts the whole column family. Reads
+  and writes keep working while it runs.
 
   Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
 
   ## Arguments
-  - `iter_ref` - The iterator reference
+  - `db_ref` - The database reference
+  - `cf` - Column family atom
+  - `start_key` - First key to compact, or `nil` for unbounded
+  - `end_key` - Last key to compact, or `nil` for unbounded
 
   ## Returns
-  - `{:ok, key, value}` if there's a next item with matching prefix
-  - `:iterator_end` if the iterator is exhausted or prefix no longer matches
-  - `{:error, :iterator_closed}` if iterator was closed
-  - `{:error, {:iterator_failed, reason}}` on error
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:cf_not_opened, cf}}` if column family was not opened
+  - `{:error, {:compact_failed, reason}}` if the compaction failed
 
   ## Examples
 
-      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
-      iex> {:ok, key, value} = NIF.iterator_next(iter)
-      iex> NIF.iterator_next(iter)
-      :iterator_end
+      iex> NIF.compact_range(db, :spo, "a", "m")
+      :ok
+
+      iex> NIF.compact_range(db, :spo, nil, nil)
+      :ok
 
   """
-  @spec iterator_next(iterator_ref()) ::
-          {:ok, binary(), binary()} | :iterator_end | {:error, term()}
-  def iterator_next(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+  @spec compact_range(db_ref(), column_family(), binary() | nil, binary() | nil) ::
+          :ok | {:error, term()}
+  def compact_range(_db_ref, _cf, _start_key, _end_key),
+    do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Seeks the iterator to a specific key.
+  Fully compacts every opened column family, one after another.
 
-  After seeking, the iterator will return keys >= target that match the prefix.
-  This is essential for Leapfrog Triejoin in Phase 3.
+  Intended for maintenance jobs after bulk loads or large deletes. It rewrites
+  all data, so expect it to take a while on large databases.
 
   Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
 
   ## Arguments
-  - `iter_ref` - The iterator reference
-  - `target` - The key to seek to
+  - `db_ref` - The database reference
 
   ## Returns
   - `:ok` on success
-  - `{:error, :iterator_closed}` if iterator was closed
-  - `{:error, :already_closed}` if database was closed
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:compact_failed, reason}}` if a compaction failed
 
   ## Examples
 
-      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
-      iex> NIF.iterator_seek(iter, "s2")
+      iex> NIF.compact_all(db)
       :ok
-      iex> {:ok, key, _value} = NIF.iterator_next(iter)
-      iex> key >= "s2"
-      true
 
   """
-  @spec iterator_seek(iterator_ref(), binary()) :: :ok | {:error, term()}
-  def iterator_seek(_iter_ref, _target), do: :erlang.nif_error(:nif_not_loaded)
+  @spec compact_all(db_ref()) :: :ok | {:error, term()}
+  def compact_all(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @typedoc "Details of a `subscribe_events/2` message: new SST files at `level`"
+  @type storage_event_details :: %{
+          level: non_neg_integer(),
+          files: pos_integer(),
+          bytes: non_neg_integer()
+        }
+
+  @doc """
+  Subscribes a process to flush and compaction completion events.
+
+  The subscriber receives
+
+      {:rocksdb_event, :flush_completed | :compaction_completed, cf, details}
+
+  with `details` as in `t:storage_event_details/0`.
+
+  This is poll-based, not a push listener: the RocksDB bindings don't expose
+  RocksDB's `EventListener`, so while a database has subscribers one
+  background thread compares its live SST files every 200 ms. New files at level 0 are reported as a completed flush,
+  files that appear or move at a deeper level as a completed compaction.
+  Events are therefore delayed by up to one interval, completions within an
+  interval are coalesced into one event per column family and level, and a
+  compaction that writes no output is not reported.
+
+  Subscribing the same process twice has no further effect. The subscription
+  ends with `unsubscribe_events/2`, when the subscriber exits or when the
+  database is closed. Column families can still be created and dropped while
+  processes are subscribed.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `pid` - Process to notify
+
+  ## Returns
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+
+  ## Examples
+
+      iex> :ok = NIF.subscribe_events(db, self())
+      iex> NIF.put(db, :spo, "key", "value")
+      iex> NIF.sync_dir(db)
+      iex> receive do
+      ...>   {:rocksdb_event, :flush_completed, :spo, %{level: 0}} -> :flushed
+      ...> end
+      :flushed
+
+  """
+  @spec subscribe_events(db_ref(), pid()) :: :ok | {:error, :already_closed}
+  def subscribe_events(_db_ref, _pid), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Ends a `subscribe_events/2` subscription.
+
+  Events already sent stay in the subscriber's mailbox.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `pid` - Subscribed process
+
+  ## Returns
+  - `:ok` on success, also if `pid` was not subscribed
+  - `{:error, :already_closed}` if database is closed
+
+  ## Examples
+
+      iex> :ok = NIF.subscribe_events(db, self())
+      iex> NIF.unsubscribe_events(db, self())
+      :ok
+
+  """
+  @spec unsubscribe_events(db_ref(), pid()) :: :ok | {:error, :already_closed}
+  def unsubscribe_events(_db_ref, _pid), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ============================================================================
+  # Size Estimation
+  # ============================================================================
+
+  @doc """
+  Estimates the on-disk bytes of each key range in a column family.
+
+  Each range is a `{start_key, end_key}` tuple with `end_key` exclusive. The
+  estimate is the total size of the live SST files overlapping the range, so
+  data still in the memtables is not counted and a file straddling a range
+  boundary counts in full.
+
+  This is a best-effort estimate meant for comparing candidate scans in the
+  query planner; it is cheap because nothing is read, but it is not exact.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - Column family atom
+  - `ranges` - List of `{start_key, end_key}` tuples
+
+  ## Returns
+  - `{:ok, [bytes, ...]}` with one estimate per range, in order
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:cf_not_opened, cf}}` if column family was not opened
+  - `{:error, {:estimate_failed, reason}}` if the file metadata can't be read
+
+  ## Examples
+
+      iex> NIF.approximate_sizes(db, :spo, [{<<1::64>>, <<2::64>>}, {<<2::64>>, <<3::64>>}])
+      {:ok, [4_194_304, 0]}
+
+  """
+  @spec approximate_sizes(db_ref(), column_family(), [{binary(), binary()}]) ::
+          {:ok, [non_neg_integer()]} | {:error, term()}
+  def approximate_sizes(_db_ref, _cf, _ranges), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns RocksDB's estimate of the number of keys in a column family.
+
+  Reads the `rocksdb.estimate-num-keys` property, which adds up the entries of
+  the memtables and SST files without collapsing overwrites or applying
+  deletes. This is a best-effort estimate: it can be off in either direction,
+  especially after heavy overwrites or deletes that compaction has not yet
+  merged away.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - Column family atom
+
+  ## Returns
+  - `{:ok, count}` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:cf_not_opened, cf}}` if column family was not opened
+  - `{:error, {:estimate_failed, reason}}` if the property can't be read
+
+  ## Examples
+
+      iex> NIF.estimate_num_keys(db, :spo)
+      {:ok, 1_250_000}
+
+  """
+  @spec estimate_num_keys(db_ref(), column_family()) ::
+          {:ok, non_neg_integer()} | {:error, term()}
+  def estimate_num_keys(_db_ref, _cf), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ============================================================================
+  # Properties
+  # ============================================================================
+
+  @doc """
+  Reads a RocksDB property of a column family as a string.
+
+  Any property RocksDB knows can be read, such as `"rocksdb.stats"`,
+  `"rocksdb.cur-size-all-mem-tables"` or `"rocksdb.num-files-at-level0"`,
+  which makes this the escape hatch for surfacing internal stats in telemetry.
+
+  ## Arguments
+  - `db_ref` - The database reference
+  - `cf` - Column family atom
+  - `name` - Property name
+
+  ## Returns
+  - `{:ok, value}` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:cf_not_opened, cf}}` if column family was not opened
+  - `{:error, :unknown_property}` if RocksDB does not know the property
+
+  ## Examples
+
+      iex> NIF.get_property(db, :spo, "rocksdb.num-files-at-level0")
+      {:ok, "2"}
+
+  """
+  @spec get_property(db_ref(), column_family(), String.t()) ::
+          {:ok, String.t()} | {:error, term()}
+  def get_property(_db_ref, _cf, _name), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Closes the iterator and releases resources.
+  Reads a numeric RocksDB property of a column family as an integer.
+
+  Only properties with an integer value can be read this way; string
+  properties such as `"rocksdb.stats"` return `{:error, :unknown_property}`
+  and need `get_property/3`.
 
   ## Arguments
-  - `iter_ref` - The iterator reference
+  - `db_ref` - The database reference
+  - `cf` - Column family atom
+  - `name` - Property name
 
   ## Returns
-  - `:ok` on success
-  - `{:error, :iterator_closed}` if already closed
+  - `{:ok, integer}` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:error, {:cf_not_opened, cf}}` if column family was not opened
+  - `{:error, :unknown_property}` if the property is unknown or not numeric
 
   ## Examples
 
-      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
-      iex> NIF.iterator_close(iter)
-      :ok
-      iex> NIF.iterator_close(iter)
-      {:error, :iterator_closed}
+      iex> NIF.get_int_property(db, :spo, "rocksdb.cur-size-all-mem-tables")
+      {:ok, 2048}
 
   """
-  @spec iterator_close(iterator_ref()) :: :ok | {:error, :iterator_closed}
-  def iterator_close(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+  @spec get_int_property(db_ref(), column_family(), String.t()) ::
+          {:ok, non_neg_integer()} | {:error, term()}
+  def get_int_property(_db_ref, _cf, _name), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ============================================================================
+  # Change Feed
+  # ============================================================================
 
   @doc """
-  Collects all remaining key-value pairs from an iterator into a list.
+  Reads triple additions and removals from the write-ahead log.
 
-  This is a convenience function that returns all matching entries.
-  Useful for small result sets where streaming isn't needed.
-  The iterator position advances to the end after this call.
+  Replays the writes logged since `since_seq`, keeps only puts and deletes on
+  the `spo` column family, and decodes each key into subject, predicate and
+  object IDs. Changes to other column families are ignored, as are merges and
+  range deletions. Feed the returned `next_seq` into the next call to consume
+  the log incrementally, e.g. for downstream index or cache maintenance.
+
+  The WAL only covers changes RocksDB hasn't discarded yet: obsolete WAL files
+  are deleted after memtable flushes unless WAL retention is configured. A
+  consumer that falls too far behind gets `{:error, {:wal_failed, reason}}` and
+  has to resynchronize from a snapshot.
 
   Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
 
   ## Arguments
-  - `iter_ref` - The iterator reference
+  - `db_ref` - The database reference
+  - `since_seq` - First sequence number to include (`0` for everything retained)
+  - `id_width` - Bytes per ID in spo keys (8 for the standard 64-bit encoding)
 
   ## Returns
-  - `{:ok, [{key, value}, ...]}` with all remaining entries
-  - `{:error, :iterator_closed}` if iterator was closed
-  - `{:error, {:iterator_failed, reason}}` on error
+  - `{:ok, changes, next_seq}` with changes as `{:added | :removed, s, p, o}`
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_id_width, width}}` if `id_width` is not in 1..8
+  - `{:error, {:malformed_key, length}}` for an spo key of the wrong length
+  - `{:error, {:wal_failed, reason}}` if the WAL can't be read
 
   ## Examples
 
-      iex> {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
-      iex> {:ok, results} = NIF.iterator_collect(iter)
-      iex> length(results)
-      3
+      iex> {:ok, changes, next_seq} = NIF.triple_changes_since(db, 0, 8)
+      iex> changes
+      [{:added, 1, 2, 3}]
 
   """
-  @spec iterator_collect(iterator_ref()) ::
-          {:ok, [{binary(), binary()}]} | {:error, term()}
-  def iterator_collect(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+  @spec triple_changes_since(db_ref(), non_neg_integer(), pos_integer()) ::
+          {:ok, [{:added | :removed, non_neg_integer(), non_neg_integer(), non_neg_integer()}],
+           non_neg_integer()}
+          | {:error, term()}
+  def triple_changes_since(_db_ref, _since_seq, _id_width),
+    do: :erlang.nif_error(:nif_not_loaded)
 
   # ============================================================================
-  # Stream Wrapper
+  # User Timestamps
   # ============================================================================
 
   @doc """
-  Creates an Elixir Stream from a prefix iterator.
+  Puts a key-value pair stamped with a logical timestamp.
 
-  This wraps the iterator in a lazy Stream that automatically handles
-  iteration and cleanup. The iterator is closed when the stream is
-  fully consumed or when the stream is garbage collected.
+  Intended for "as of" reads of derived data via RocksDB user timestamps.
+  Timestamps are unsigned 64-bit integers, stored by RocksDB as 8 bytes
+  (little-endian) appended to each key. Real support sits behind the NIF's
+  `user_timestamps` cargo feature, which the linked RocksDB binding can't
+  provide yet, so the call returns `{:error, :timestamps_unsupported}` once
+  its arguments are validated.
 
   ## Arguments
   - `db_ref` - The database reference
   - `cf` - The column family atom
-  - `prefix` - The prefix to iterate over (can be empty for full scan)
+  - `key` - The key as a binary
+  - `value` - The value as a binary
+  - `timestamp` - Logical write time
 
   ## Returns
-  - `{:ok, Stream.t()}` on success
-  - `{:error, term()}` on failure
+  - `{:error, :timestamps_unsupported}` with the current RocksDB build
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
 
   ## Examples
 
-      iex> {:ok, stream} = NIF.prefix_stream(db, :spo, "s1")
-      iex> Enum.take(stream, 5)
-      [{"s1p1o1", ""}, {"s1p1o2", ""}, ...]
+      iex> NIF.put_ts(db, :derived, "key", "value", 42)
+      {:error, :timestamps_unsupported}
 
   """
-  @spec prefix_stream(db_ref(), column_family(), binary()) ::
-          {:ok, Enumerable.t()} | {:error, term()}
-  def prefix_stream(db_ref, cf, prefix) do
-    case prefix_iterator(db_ref, cf, prefix) do
-      {:ok, iter} ->
-        stream =
-          Stream.resource(
-            fn -> iter end,
-            &stream_next/1,
-            fn iter -> iterator_close(iter) end
-          )
-
-        {:ok, stream}
-
-      error ->
-        error
-    end
-  end
-
-  defp stream_next(iter) do
-    case iterator_next(iter) do
-      {:ok, key, value} -> {[{key, value}], iter}
-      :iterator_end -> {:halt, iter}
-      {:error, _} -> {:halt, iter}
-    end
-  end
-
-  # ============================================================================
-  # Snapshot Operations
-  # ============================================================================
-
-  @type snapshot_ref :: reference()
-  @type snapshot_iterator_ref :: reference()
+  @spec put_ts(db_ref(), column_family(), binary(), binary(), non_neg_integer()) ::
+          :ok | {:error, term()}
+  def put_ts(_db_ref, _cf, _key, _value, _timestamp), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Creates a snapshot of the database for consistent point-in-time reads.
-
-  A snapshot provides a consistent view of the database at the time of creation.
-  All reads using the snapshot will see the same data, regardless of subsequent
-  writes to the database. This is essential for transaction isolation.
-
-  The snapshot must be released with `release_snapshot/1` when done, or it will
-  be automatically released when garbage collected.
+  Reads a key as of a logical timestamp.
 
-  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+  Counterpart of `put_ts/5`, with the same timestamp encoding. Always returns
+  `{:error, :timestamps_unsupported}` with the current RocksDB build.
 
   ## Arguments
   - `db_ref` - The database reference
+  - `cf` - The column family atom
+  - `key` - The key as a binary
+  - `read_timestamp` - Logical time to read at
 
   ## Returns
-  - `{:ok, snapshot_ref}` on success
+  - `{:error, :timestamps_unsupported}` with the current RocksDB build
   - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_cf, cf}}` if column family is invalid
 
   ## Examples
 
-      iex> {:ok, db} = NIF.open("/tmp/test_db")
-      iex> NIF.put(db, :spo, "key1", "value1")
-      iex> {:ok, snap} = NIF.snapshot(db)
-      iex> NIF.put(db, :spo, "key1", "value2")  # Update after snapshot
-      iex> NIF.snapshot_get(snap, :spo, "key1")
-      {:ok, "value1"}  # Still sees old value
+      iex> NIF.get_ts(db, :derived, "key", 42)
+      {:error, :timestamps_unsupported}
 
   """
-  @spec snapshot(db_ref()) :: {:ok, snapshot_ref()} | {:error, term()}
-  def snapshot(_db_ref), do: :erlang.nif_error(:nif_not_loaded)
+  @spec get_ts(db_ref(), column_family(), binary(), non_neg_integer()) ::
+          {:ok, binary()} | :not_found | {:error, term()}
+  def get_ts(_db_ref, _cf, _key, _read_timestamp), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ============================================================================
+  # Fault Injection (testing only)
+  # ============================================================================
 
   @doc """
-  Gets a value from a column family using a snapshot.
+  Forces subsequent `put/4` and `write_batch/3` calls to fail.
 
-  This provides point-in-time consistent reads - the value returned
-  is what existed at the time the snapshot was created.
+  **Strictly for testing.** The NIF is only compiled in when the crate is built
+  with the `fault_injection` Cargo feature, which this module enables in the
+  `:test` environment only. In other builds calling it raises, and writes are
+  never affected.
 
-  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+  Failing writes return `{:error, {:put_failed, "injected"}}` without touching
+  the database, which lets tests exercise error-handling and retry branches.
 
   ## Arguments
-  - `snapshot_ref` - The snapshot reference
-  - `cf` - The column family atom
-  - `key` - The key as a binary
+  - `db_ref` - The database reference
+  - `mode` - `{:count, n}` fails the next `n` writes, `:always` fails writes
+    until cleared, `:clear` stops injecting faults
 
   ## Returns
-  - `{:ok, value}` if found
-  - `:not_found` if key doesn't exist at snapshot time
-  - `{:error, :snapshot_released}` if snapshot was released
-  - `{:error, {:invalid_cf, cf}}` if column family is invalid
-  - `{:error, {:get_failed, reason}}` on other errors
+  - `:ok` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_option, mode}}` if the mode is not recognized
 
   ## Examples
 
-      iex> {:ok, snap} = NIF.snapshot(db)
-      iex> NIF.snapshot_get(snap, :spo, "key1")
-      {:ok, "value1"}
+      iex> NIF.inject_write_fault(db, {:count, 1})
+      :ok
+      iex> NIF.put(db, :spo, "key", "value")
+      {:error, {:put_failed, "injected"}}
+      iex> NIF.put(db, :spo, "key", "value")
+      :ok
 
   """
-  @spec snapshot_get(snapshot_ref(), column_family(), binary()) ::
-          {:ok, binary()} | :not_found | {:error, term()}
-  def snapshot_get(_snapshot_ref, _cf, _key), do: :erlang.nif_error(:nif_not_loaded)
+  @spec inject_write_fault(db_ref(), {:count, non_neg_integer()} | :always | :clear) ::
+          :ok | {:error, term()}
+  def inject_write_fault(_db_ref, _mode), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ============================================================================
+  # Backups and Checkpoints
+  # ============================================================================
 
   @doc """
-  Creates a prefix iterator over a snapshot.
+  Creates a new hot backup of the database in `backup_path`.
 
-  The iterator returns all key-value pairs where the key starts with the given prefix,
-  using the consistent view from the snapshot.
+  Uses RocksDB's `BackupEngine`, so the database stays open and writable while
+  files are copied. Memtables are flushed first, so the backup holds everything
+  written before the call. Backups are incremental: SST files already in
+  `backup_path` from earlier backups are shared rather than copied again. Only
+  one backup operation should use a given `backup_path` at a time.
 
   Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
 
   ## Arguments
-  - `snapshot_ref` - The snapshot reference
-  - `cf` - The column family atom
-  - `prefix` - The prefix to iterate over (can be empty for full scan)
+  - `db_ref` - The database reference
+  - `backup_path` - Backup directory, created if it doesn't exist
 
   ## Returns
-  - `{:ok, iterator_ref}` on success
-  - `{:error, :snapshot_released}` if snapshot was released
-  - `{:error, {:invalid_cf, cf}}` if column family is invalid
+  - `{:ok, backup_id}` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:backup_failed, reason}}` on failure
 
   ## Examples
 
-      iex> {:ok, snap} = NIF.snapshot(db)
-      iex> {:ok, iter} = NIF.snapshot_prefix_iterator(snap, :spo, "s1")
-      iex> {:ok, key, value} = NIF.snapshot_iterator_next(iter)
+      iex> NIF.create_backup(db, "/backups/triple_store")
+      {:ok, 1}
 
   """
-  @spec snapshot_prefix_iterator(snapshot_ref(), column_family(), binary()) ::
-          {:ok, snapshot_iterator_ref()} | {:error, term()}
-  def snapshot_prefix_iterator(_snapshot_ref, _cf, _prefix),
-    do: :erlang.nif_error(:nif_not_loaded)
+  @spec create_backup(db_ref(), Path.t()) :: {:ok, pos_integer()} | {:error, term()}
+  def create_backup(_db_ref, _backup_path), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Gets the next key-value pair from a snapshot iterator.
+  Lists the backups stored in `backup_path`, oldest first.
 
-  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
+  Each entry is `{backup_id, timestamp, size}` with `timestamp` in Unix
+  seconds and `size` in bytes.
 
   ## Arguments
-  - `iter_ref` - The snapshot iterator reference
+  - `backup_path` - Backup directory
 
   ## Returns
-  - `{:ok, key, value}` if there's a next item with matching prefix
-  - `:iterator_end` if the iterator is exhausted or prefix no longer matches
-  - `{:error, :iterator_closed}` if iterator was closed
-  - `{:error, {:iterator_failed, reason}}` on error
+  - `{:ok, [{backup_id, timestamp, size}]}` on success
+  - `{:error, {:backup_failed, reason}}` if the directory can't be opened
+
+  ## Examples
+
+      iex> NIF.list_backups("/backups/triple_store")
+      {:ok, [{1, 1760000000, 52_341}, {2, 1760003600, 61_020}]}
 
   """
-  @spec snapshot_iterator_next(snapshot_iterator_ref()) ::
-          {:ok, binary(), binary()} | :iterator_end | {:error, term()}
-  def snapshot_iterator_next(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+  @spec list_backups(Path.t()) ::
+          {:ok, [{pos_integer(), integer(), non_neg_integer()}]} | {:error, term()}
+  def list_backups(_backup_path), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Closes a snapshot iterator and releases resources.
+  Deletes all but the newest `keep_count` backups in `backup_path`.
+
+  Files still shared with the remaining backups are kept.
 
   ## Arguments
-  - `iter_ref` - The snapshot iterator reference
+  - `backup_path` - Backup directory
+  - `keep_count` - Number of most recent backups to keep
 
   ## Returns
   - `:ok` on success
-  - `{:error, :iterator_closed}` if already closed
+  - `{:error, {:backup_failed, reason}}` on failure
+
+  ## Examples
+
+      iex> NIF.purge_old_backups("/backups/triple_store", 7)
+      :ok
 
   """
-  @spec snapshot_iterator_close(snapshot_iterator_ref()) :: :ok | {:error, :iterator_closed}
-  def snapshot_iterator_close(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+  @spec purge_old_backups(Path.t(), non_neg_integer()) :: :ok | {:error, term()}
+  def purge_old_backups(_backup_path, _keep_count), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Collects all remaining key-value pairs from a snapshot iterator into a list.
+  Restores a backup from `backup_path` into `target_db_path`.
+
+  `backup_id` selects the backup to restore; `0` or `:latest` restores the
+  newest one. The target must not exist or must be an empty directory, so an
+  existing database is never overwritten by accident. The restored database
+  is not opened; pass the returned path to `open/1`.
 
   Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
 
   ## Arguments
-  - `iter_ref` - The snapshot iterator reference
+  - `backup_path` - Backup directory
+  - `target_db_path` - Directory to restore the database into
+  - `backup_id` - Backup id from `create_backup/2` or `list_backups/1`, or
+    `0`/`:latest`
 
   ## Returns
-  - `{:ok, [{key, value}, ...]}` with all remaining entries
-  - `{:error, :iterator_closed}` if iterator was closed
-  - `{:error, {:iterator_failed, reason}}` on error
+  - `{:ok, target_db_path}` on success
+  - `{:error, {:restore_failed, reason}}` if the target is not empty, the
+    backup doesn't exist, or the restore fails
+  - `{:error, {:invalid_option, backup_id}}` if `backup_id` is not an id or `:latest`
+
+  ## Examples
+
+      iex> {:ok, path} = NIF.restore_from_backup("/backups/triple_store", "/data/restored", :latest)
+      iex> {:ok, db} = NIF.open(path)
 
   """
-  @spec snapshot_iterator_collect(snapshot_iterator_ref()) ::
-          {:ok, [{binary(), binary()}]} | {:error, term()}
-  def snapshot_iterator_collect(_iter_ref), do: :erlang.nif_error(:nif_not_loaded)
+  @spec restore_from_backup(Path.t(), Path.t(), non_neg_integer() | :latest) ::
+          {:ok, Path.t()} | {:error, term()}
+  def restore_from_backup(_backup_path, _target_db_path, _backup_id),
+    do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Releases a snapshot and frees resources.
+  Creates a checkpoint of the database at `checkpoint_path`.
 
-  After calling release, the snapshot handle is no longer valid.
+  A checkpoint is an openable copy whose SST files are hard links to the live
+  ones when both are on the same filesystem, so it is near-instant and uses
+  little extra space. Use it for test fixtures and for seeding read replicas.
+  The source database stays fully usable during and after the call.
+  `checkpoint_path` must not exist yet; RocksDB creates it.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
 
   ## Arguments
-  - `snapshot_ref` - The snapshot reference
+  - `db_ref` - The database reference
+  - `checkpoint_path` - Directory to create
 
   ## Returns
   - `:ok` on success
-  - `{:error, :snapshot_released}` if already released
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:checkpoint_failed, reason}}` if the path exists or the checkpoint fails
 
   ## Examples
 
-      iex> {:ok, snap} = NIF.snapshot(db)
-      iex> NIF.release_snapshot(snap)
-      :ok
-      iex> NIF.release_snapshot(snap)
-      {:error, :snapshot_released}
+      iex> :ok = NIF.create_checkpoint(db, "/tmp/fixture_db")
+      iex> {:ok, fork} = NIF.open("/tmp/fixture_db")
 
   """
-  @spec release_snapshot(snapshot_ref()) :: :ok | {:error, :snapshot_released}
-  def release_snapshot(_snapshot_ref), do: :erlang.nif_error(:nif_not_loaded)
+  @spec create_checkpoint(db_ref(), Path.t()) :: :ok | {:error, term()}
+  def create_checkpoint(_db_ref, _checkpoint_path), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ============================================================================
+  # Export Operations
+  # ============================================================================
 
   @doc """
-  Creates an Elixir Stream from a snapshot prefix iterator.
+  Exports the whole database to an N-Triples file from a consistent snapshot.
 
-  This wraps the snapshot iterator in a lazy Stream that automatically handles
-  iteration and cleanup. The iterator is closed when the stream is fully consumed.
+  Takes a snapshot, scans the `:spo` index and resolves every subject,
+  predicate and object ID through `:id2str` (inline-encoded integers, decimals
+  and datetimes are decoded directly). Writers are not blocked; triples
+  written after the export starts are not included.
+
+  Resolved terms are cached in a bounded cache, so repeated IDs are only looked
+  up once without letting memory grow with the size of the dump.
+
+  Uses dirty CPU scheduler to prevent blocking BEAM schedulers.
 
   ## Arguments
-  - `snapshot_ref` - The snapshot reference
-  - `cf` - The column family atom
-  - `prefix` - The prefix to iterate over (can be empty for full scan)
+  - `db_ref` - The database reference
+  - `output_path` - File to write (created or truncated)
+  - `id_width` - Width in bytes of each ID in the index keys (`8` for the
+    standard 64-bit encoding)
 
   ## Returns
-  - `{:ok, Stream.t()}` on success
-  - `{:error, term()}` on failure
+  - `{:ok, triple_count}` on success
+  - `{:error, :already_closed}` if database is closed
+  - `{:error, {:invalid_id_width, width}}` if width is not in `1..8`
+  - `{:error, {:malformed_key, length}}` if an `:spo` key has an unexpected length
+  - `{:error, {:unresolved_id, id}}` if an ID has no dictionary entry
+  - `{:error, {:export_failed, reason}}` on read or I/O errors
+
+  On error the partially written file is removed.
 
   ## Examples
 
-      iex> {:ok, snap} = NIF.snapshot(db)
-      iex> {:ok, stream} = NIF.snapshot_stream(snap, :spo, "s1")
-      iex> Enum.to_list(stream)
-      [{"s1p1o1", ""}, {"s1p1o2", ""}]
+      iex> NIF.export_snapshot_ntriples(db, "/tmp/dump.nt", 8)
+      {:ok, 1024}
 
   """
-  @spec snapshot_stream(snapshot_ref(), column_family(), binary()) ::
-          {:ok, Enumerable.t()} | {:error, term()}
-  def snapshot_stream(snapshot_ref, cf, prefix) do
-    case snapshot_prefix_iterator(snapshot_ref, cf, prefix) do
-      {:ok, iter} ->
-        stream =
-          Stream.resource(
-            fn -> iter end,
-            &snapshot_stream_next/1,
-            fn iter -> snapshot_iterator_close(iter) end
-          )
-
-        {:ok, stream}
-
-      error ->
-        error
-    end
-  end
-
-  defp snapshot_stream_next(iter) do
-    case snapshot_iterator_next(iter) do
-      {:ok, key, value} -> {[{key, value}], iter}
-      :iterator_end -> {:halt, iter}
-      {:error, _} -> {:halt, iter}
-    end
-  end
+  @spec export_snapshot_ntriples(db_ref(), Path.t(), pos_integer()) ::
+          {:ok, non_neg_integer()} | {:error, term()}
+  def export_snapshot_ntriples(_db_ref, _output_path, _id_width),
+    do: :erlang.nif_error(:nif_not_loaded)
 end
@@ -103,7 +103,7 @@ defmodule TripleStore.SPARQL.Parser do
       {:error, {:parse_error, "Query exceeds maximum size of #{@max_query_size} bytes"}}
     else
       start_time = System.monotonic_time()
-      result = NIF.parse_query(sparql)
+      result = sparql |> NIF.parse_query() |> drop_error_position()
       duration = System.monotonic_time() - start_time
 
       :telemetry.execute(
@@ -165,10 +165,18 @@ defmodule TripleStore.SPARQL.Parser do
     if byte_size(sparql) > @max_query_size do
       {:error, {:parse_error, "Query exceeds maximum size of #{@max_query_size} bytes"}}
     else
-      NIF.parse_update(sparql)
+      sparql |> NIF.parse_update() |> drop_error_position()
     end
   end
 
+  # The NIF attaches a %{line, column, offset} map to syntax errors; callers
+  # of parse/1 and parse_update/1 only see the message, and
+  # parse_with_details/1 recovers the position from it.
+  defp drop_error_position({:error, {:parse_error, message, _position}}),
+    do: {:error, {:parse_error, message}}
+
+  defp drop_error_position(result), do: result
+
   @doc """
   Parses a SPARQL UPDATE string, raising on error.
 
@@ -246,6 +254,9 @@ defmodule TripleStore.SPARQL.Parser do
         {:ok, ast} ->
           {:ok, ast}
 
+        {:error, {:parse_error, raw_message, _position}} ->
+          {:error, build_error_details(raw_message, sparql, :query)}
+
         {:error, {:parse_error, raw_message}} ->
           {:error, build_error_details(raw_message, sparql, :query)}
       end
@@ -295,6 +306,9 @@ defmodule TripleStore.SPARQL.Parser do
         {:ok, ast} ->
           {:ok, ast}
 
+        {:error, {:parse_error, raw_message, _position}} ->
+          {:error, build_error_details(raw_message, sparql, :update)}
+
         {:error, {:parse_error, raw_message}} ->
           {:error, build_error_details(raw_message, sparql, :update)}
       end
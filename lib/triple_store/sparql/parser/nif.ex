@@ -37,15 +37,29 @@ defmodule TripleStore.SPARQL.Parser.NIF do
   @spec nif_loaded :: String.t()
   def nif_loaded, do: :erlang.nif_error(:nif_not_loaded)
 
+  @typedoc "Location of a syntax error reported by `parse_query/1` and `parse_update/1`"
+  @type parse_position :: %{
+          line: pos_integer(),
+          column: pos_integer(),
+          offset: non_neg_integer()
+        }
+
   @doc """
   Parses a SPARQL query string into an AST.
 
+  SPARQL-star quoted triples (`<< ?s ?p ?o >>`) are encoded as
+  `{:triple_term, subject, predicate, object}`, nesting as deeply as the
+  query does.
+
   ## Arguments
   - `sparql` - The SPARQL query string to parse
 
   ## Returns
   - `{:ok, ast}` on success where ast is the Elixir representation
-  - `{:error, {:parse_error, message}}` on parse failure
+  - `{:error, {:parse_error, message, %{line: l, column: c, offset: o}}}` on a
+    syntax error, with 1-based `line` and `column` (in characters) and the
+    0-based byte `offset` of the failing token
+  - `{:error, {:parse_error, message}}` on failures without a position
 
   ## Examples
 
@@ -54,9 +68,76 @@ defmodule TripleStore.SPARQL.Parser.NIF do
       :select
 
   """
-  @spec parse_query(String.t()) :: {:ok, term()} | {:error, {:parse_error, String.t()}}
+  @spec parse_query(String.t()) ::
+          {:ok, term()}
+          | {:error, {:parse_error, String.t()} | {:parse_error, String.t(), parse_position()}}
   def parse_query(_sparql), do: :erlang.nif_error(:nif_not_loaded)
 
+  @doc """
+  Parses a SPARQL query string, resolving relative IRIs against `base_iri`.
+
+  A `BASE` declaration in the query still overrides it.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+  - `base_iri` - Absolute IRI used to resolve relative IRIs
+
+  ## Returns
+  - Same as `parse_query/1`
+  - `{:error, {:parse_error, "invalid base IRI"}}` if `base_iri` is not an
+    absolute IRI
+
+  ## Examples
+
+      iex> {:ok, ast} = TripleStore.SPARQL.Parser.NIF.parse_query("SELECT ?s WHERE { ?s <p> ?o }", "http://example.org/")
+      iex> inspect(ast) =~ ~s({:named_node, "http://example.org/p"})
+      true
+
+  """
+  @spec parse_query(String.t(), String.t()) ::
+          {:ok, term()}
+          | {:error, {:parse_error, String.t()} | {:parse_error, String.t(), parse_position()}}
+  def parse_query(_sparql, _base_iri), do: :erlang.nif_error(:nif_not_loaded)
+
+  @typedoc "Options accepted by `parse_query/3`"
+  @type query_opts :: [decode_literals: boolean()]
+
+  @doc """
+  Parses a SPARQL query string with an optional base IRI and options.
+
+  ## Options
+  - `:decode_literals` - when `true`, literals of the datatypes
+    `decode_literal/1` supports (`xsd:integer` and its derived types,
+    `xsd:decimal`, `xsd:double`, `xsd:float`, `xsd:boolean` and
+    `xsd:dateTime`) are encoded as
+    `{:literal, :typed_value, native, datatype}`, with `native` as returned by
+    `decode_literal/1`. Literals whose lexical form is invalid for their
+    datatype keep the `{:literal, :typed, value, datatype}` encoding.
+    Default `false`.
+
+  ASTs containing `:typed_value` literals are not accepted by
+  `query_to_sparql/1`.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+  - `base_iri` - Absolute IRI used to resolve relative IRIs, or `nil`
+  - `opts` - Keyword list of options
+
+  ## Returns
+  - Same as `parse_query/2`
+
+  ## Examples
+
+      iex> {:ok, ast} = TripleStore.SPARQL.Parser.NIF.parse_query("SELECT * WHERE { ?s ?p 42 }", nil, decode_literals: true)
+      iex> inspect(ast) =~ ~s({:literal, :typed_value, 42, "http://www.w3.org/2001/XMLSchema#integer"})
+      true
+
+  """
+  @spec parse_query(String.t(), String.t() | nil, query_opts()) ::
+          {:ok, term()}
+          | {:error, {:parse_error, String.t()} | {:parse_error, String.t(), parse_position()}}
+  def parse_query(_sparql, _base_iri, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
   @doc """
   Parses a SPARQL UPDATE string into an AST.
 
@@ -65,7 +146,10 @@ defmodule TripleStore.SPARQL.Parser.NIF do
 
   ## Returns
   - `{:ok, ast}` on success where ast is the Elixir representation
-  - `{:error, {:parse_error, message}}` on parse failure
+  - `{:error, {:parse_error, message, %{line: l, column: c, offset: o}}}` on a
+    syntax error, with 1-based `line` and `column` (in characters) and the
+    0-based byte `offset` of the failing token
+  - `{:error, {:parse_error, message}}` on failures without a position
 
   ## Examples
 
@@ -74,6 +158,1042 @@ defmodule TripleStore.SPARQL.Parser.NIF do
       :update
 
   """
-  @spec parse_update(String.t()) :: {:ok, term()} | {:error, {:parse_error, String.t()}}
+  @spec parse_update(String.t()) ::
+          {:ok, term()}
+          | {:error, {:parse_error, String.t()} | {:parse_error, String.t(), parse_position()}}
   def parse_update(_sparql), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses a SPARQL UPDATE string, resolving relative IRIs against `base_iri`.
+
+  A `BASE` declaration in the update still overrides it.
+
+  ## Arguments
+  - `sparql` - The SPARQL UPDATE string to parse
+  - `base_iri` - Absolute IRI used to resolve relative IRIs
+
+  ## Returns
+  - Same as `parse_update/1`
+  - `{:error, {:parse_error, "invalid base IRI"}}` if `base_iri` is not an
+    absolute IRI
+
+  ## Examples
+
+      iex> {:ok, ast} = TripleStore.SPARQL.Parser.NIF.parse_update("INSERT DATA { <s> <p> <o> }", "http://example.org/")
+      iex> inspect(ast) =~ ~s({:named_node, "http://example.org/s"})
+      true
+
+  """
+  @spec parse_update(String.t(), String.t()) ::
+          {:ok, term()}
+          | {:error, {:parse_error, String.t()} | {:parse_error, String.t(), parse_position()}}
+  def parse_update(_sparql, _base_iri), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses a SPARQL ASK query into a compact form for boolean evaluation.
+
+  Returns only the graph pattern and dataset, without the `{:ask, props}`
+  wrapper used by `parse_query/1`. Other query forms are rejected so callers
+  know they used the wrong entry point.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+
+  ## Returns
+  - `{:ok, {pattern, dataset}}` on success (`dataset` is `nil` when absent)
+  - `{:error, {:wrong_operation, form}}` for `:select`, `:construct` or `:describe`
+  - `{:error, {:parse_error, message}}` on parse failure
+
+  ## Examples
+
+      iex> {:ok, {pattern, nil}} = TripleStore.SPARQL.Parser.NIF.parse_ask("ASK { ?s ?p ?o }")
+      iex> elem(pattern, 0)
+      :bgp
+
+      iex> TripleStore.SPARQL.Parser.NIF.parse_ask("SELECT * WHERE { ?s ?p ?o }")
+      {:error, {:wrong_operation, :select}}
+
+  """
+  @spec parse_ask(String.t()) ::
+          {:ok, {term(), term() | nil}}
+          | {:error, {:wrong_operation, :select | :construct | :describe}}
+          | {:error, {:parse_error, String.t()}}
+  def parse_ask(_sparql), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Detects whether a string is a SELECT, CONSTRUCT, ASK, DESCRIBE or UPDATE.
+
+  Parses the string but skips building the Elixir AST, so it is cheap to
+  call when routing large requests. Query syntax is tried first, then
+  update syntax.
+
+  ## Arguments
+  - `sparql` - The SPARQL query or update string
+
+  ## Returns
+  - `{:ok, form}` where `form` is `:select`, `:construct`, `:ask`,
+    `:describe` or `:update`
+  - `{:error, {:parse_error, message}}` if it is neither a query nor an
+    update; the message comes from whichever grammar got further
+
+  ## Examples
+
+      iex> TripleStore.SPARQL.Parser.NIF.query_form("ASK { ?s ?p ?o }")
+      {:ok, :ask}
+
+      iex> TripleStore.SPARQL.Parser.NIF.query_form("CLEAR ALL")
+      {:ok, :update}
+
+  """
+  @spec query_form(String.t()) ::
+          {:ok, :select | :construct | :ask | :describe | :update}
+          | {:error, {:parse_error, String.t()}}
+  def query_form(_sparql), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses a SPARQL query and reports timing and AST size for profiling.
+
+  Returns the same AST as `parse_query/1` together with a stats map. Use it to
+  find pathologically large queries whose term construction dominates; the
+  plain `parse_query/1` remains the fast path.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+
+  ## Returns
+  - `{:ok, ast, stats}` on success, where `stats` contains:
+    - `:parse_micros` - Time spent in the spargebra parser
+    - `:encode_micros` - Time spent converting the algebra into Elixir terms
+    - `:node_count` - Number of AST nodes produced during conversion
+  - `{:error, {:parse_error, message}}` on parse failure
+
+  ## Examples
+
+      iex> {:ok, {:select, _}, stats} = TripleStore.SPARQL.Parser.NIF.parse_query_profiled("SELECT ?s WHERE { ?s ?p ?o }")
+      iex> stats.node_count > 0
+      true
+
+  """
+  @spec parse_query_profiled(String.t()) ::
+          {:ok, term(),
+           %{
+             parse_micros: non_neg_integer(),
+             encode_micros: non_neg_integer(),
+             node_count: non_neg_integer()
+           }}
+          | {:error, {:parse_error, String.t()}}
+  def parse_query_profiled(_sparql), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses a SPARQL query with size and nesting limits.
+
+  Intended for public endpoints. Inputs larger than `max_bytes`, or whose
+  braces, parentheses and brackets nest deeper than `max_depth`, are rejected
+  before parsing, so the parser never recurses on them. AST conversion is
+  aborted once graph patterns or expressions nest deeper than `max_depth`.
+  Omitted keys leave that dimension unlimited.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+  - `limits` - Map with `:max_bytes` and/or `:max_depth`
+
+  ## Returns
+  - `{:ok, ast}` on success
+  - `{:error, {:limit_exceeded, :size}}` if the query exceeds `max_bytes`
+  - `{:error, {:limit_exceeded, :depth}}` if nesting exceeds `max_depth`
+  - `{:error, {:parse_error, message}}` on parse failure
+
+  ## Examples
+
+      iex> TripleStore.SPARQL.Parser.NIF.parse_query_limited("SELECT * WHERE { ?s ?p ?o }", %{max_bytes: 10})
+      {:error, {:limit_exceeded, :size}}
+
+  """
+  @spec parse_query_limited(String.t(), %{
+          optional(:max_bytes) => non_neg_integer(),
+          optional(:max_depth) => non_neg_integer()
+        }) ::
+          {:ok, term()}
+          | {:error, {:limit_exceeded, :size | :depth}}
+          | {:error, {:parse_error, String.t()}}
+  def parse_query_limited(_sparql, _limits), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses a SPARQL query, rejecting federated queries.
+
+  Use this on endpoints that must never contact external services. Any
+  `SERVICE` clause fails the parse, wherever it appears: in the main pattern,
+  nested in OPTIONAL/UNION/MINUS/subqueries, or inside a `FILTER EXISTS` /
+  `NOT EXISTS` expression. `SERVICE SILENT` is rejected as well.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+
+  ## Returns
+  - `{:ok, ast}` - Same AST as `parse_query/1`
+  - `{:error, :service_not_allowed}` if the query contains `SERVICE`
+  - `{:error, {:parse_error, message}}` on parse failure
+
+  ## Examples
+
+      iex> TripleStore.SPARQL.Parser.NIF.parse_query_no_service(
+      ...>   "SELECT * WHERE { SERVICE <http://remote.example/sparql> { ?s ?p ?o } }"
+      ...> )
+      {:error, :service_not_allowed}
+
+  """
+  @spec parse_query_no_service(String.t()) ::
+          {:ok, term()} | {:error, :service_not_allowed | {:parse_error, String.t()}}
+  def parse_query_no_service(_sparql), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses a SPARQL query and rejects RDF term-position violations that the
+  SPARQL grammar allows.
+
+  The underlying parser already rejects literals and blank nodes as
+  predicates and as `GRAPH` names, so those never reach this check. The
+  grammar does allow any term as a subject, so queries with literal subjects
+  parse but can never match stored data or produce valid RDF. This function
+  adds exactly two checks:
+
+  - `{:literal_subject, literal}` - a literal subject in a triple pattern or
+    property path pattern anywhere in the WHERE clause (including OPTIONAL,
+    UNION, MINUS, subqueries and `EXISTS`)
+  - `{:literal_template_subject, literal}` - a literal subject in a
+    CONSTRUCT template, which would produce an invalid triple
+
+  The first violation is reported: the CONSTRUCT template is checked before
+  the WHERE clause.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+
+  ## Returns
+  - `{:ok, ast}` - Same AST as `parse_query/1`
+  - `{:error, {:term_position, detail}}` on the first violation
+  - `{:error, {:parse_error, message}}` on parse failure
+
+  ## Examples
+
+      iex> TripleStore.SPARQL.Parser.NIF.parse_query_strict(~s(SELECT * WHERE { "x" ?p ?o }))
+      {:error, {:term_position, {:literal_subject, {:literal, :simple, "x"}}}}
+
+  """
+  @spec parse_query_strict(String.t()) ::
+          {:ok, term()}
+          | {:error,
+             {:term_position, {:literal_subject | :literal_template_subject, rdf_term()}}
+             | {:parse_error, String.t()}}
+  def parse_query_strict(_sparql), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Computes the variable dependencies of every OPTIONAL (LeftJoin) in a query.
+
+  For each LeftJoin returns `{required_vars, optional_vars, shared_vars}`:
+  the variables in scope on the required side, those in scope on the optional
+  side (including variables used by a FILTER inside the OPTIONAL), and their
+  intersection, which are the join keys. Nested OPTIONALs are listed after the
+  OPTIONAL that contains them.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+
+  ## Returns
+  - `{:ok, [{required_vars, optional_vars, shared_vars}, ...]}` with variable
+    names sorted alphabetically
+  - `{:error, {:parse_error, message}}` on parse failure
+
+  ## Examples
+
+      iex> TripleStore.SPARQL.Parser.NIF.optional_dependencies("SELECT * WHERE { ?s ?p ?o OPTIONAL { ?s ?q ?x } }")
+      {:ok, [{["o", "p", "s"], ["q", "s", "x"], ["s"]}]}
+
+  """
+  @spec optional_dependencies(String.t()) ::
+          {:ok, [{[String.t()], [String.t()], [String.t()]}]}
+          | {:error, {:parse_error, String.t()}}
+  def optional_dependencies(_sparql), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Lists the variables a query mentions.
+
+  `:all` holds every variable written anywhere in the query, including
+  FILTER/BIND expressions, `EXISTS` patterns, subqueries and the CONSTRUCT
+  template. Variables generated internally for aggregates and
+  `DESCRIBE <iri>` are not included, and blank nodes are never variables.
+  `:projected` is the SELECT or DESCRIBE projection in order, and `[]` for
+  ASK and CONSTRUCT.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+
+  ## Returns
+  - `{:ok, %{projected: [name, ...], all: [name, ...]}}` on success, with
+    `:all` sorted
+  - `{:error, {:parse_error, message}}` on parse failure
+
+  ## Examples
+
+      iex> TripleStore.SPARQL.Parser.NIF.query_variables("SELECT ?s WHERE { ?s ?p ?o FILTER(?o > ?min) }")
+      {:ok, %{projected: ["s"], all: ["min", "o", "p", "s"]}}
+
+  """
+  @spec query_variables(String.t()) ::
+          {:ok, %{projected: [String.t()], all: [String.t()]}}
+          | {:error, {:parse_error, String.t()}}
+  def query_variables(_sparql), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Lists every IRI a query mentions, for namespace discovery.
+
+  Includes IRIs from the dataset clauses, CONSTRUCT template, triple and
+  property path patterns, `GRAPH`/`SERVICE` names, VALUES, expressions,
+  custom function and aggregate IRIs, and literal datatypes (simple and
+  language-tagged literals have none). Prefixed names and `a` come back
+  expanded.
+
+  Each IRI is listed once, in roughly the order it is written; within a
+  group, patterns come before FILTER, BIND and ORDER BY expressions.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+
+  ## Returns
+  - `{:ok, [iri, ...]}` on success
+  - `{:error, {:parse_error, message}}` on parse failure
+
+  ## Examples
+
+      iex> TripleStore.SPARQL.Parser.NIF.query_iris(
+      ...>   "PREFIX foaf: <http://xmlns.com/foaf/0.1/> SELECT * WHERE { ?s a foaf:Person ; foaf:name ?n }"
+      ...> )
+      {:ok,
+       [
+         "http://www.w3.org/1999/02/22-rdf-syntax-ns#type",
+         "http://xmlns.com/foaf/0.1/Person",
+         "http://xmlns.com/foaf/0.1/name"
+       ]}
+
+  """
+  @spec query_iris(String.t()) :: {:ok, [String.t()]} | {:error, {:parse_error, String.t()}}
+  def query_iris(_sparql), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Lists the HAVING clauses of a query and its subqueries.
+
+  The parser has no HAVING node: a HAVING condition is a `:filter` directly
+  over the `:group` built for GROUP BY (or for an aggregating query without
+  one). A WHERE-clause FILTER never wraps a group directly, so every such
+  filter is reported here as `{:having, expr, group}`. `parse_query/1` keeps
+  the original nesting; this is just a tagged view of it.
+
+  Aggregates in `expr` appear as the generated variables the group binds
+  them to; look them up in the group's aggregate list.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+
+  ## Returns
+  - `{:ok, [{:having, expr, group}, ...]}` on success, outermost query first
+  - `{:error, {:parse_error, message}}` on parse failure
+
+  ## Examples
+
+      iex> {:ok, [{:having, expr, {:group, _, _, _}}]} =
+      ...>   TripleStore.SPARQL.Parser.NIF.query_having(
+      ...>     "SELECT ?s WHERE { ?s ?p ?o } GROUP BY ?s HAVING (?s != <http://example.org/a>)"
+      ...>   )
+      iex> elem(expr, 0)
+      :not
+
+  """
+  @spec query_having(String.t()) ::
+          {:ok, [{:having, term(), term()}]} | {:error, {:parse_error, String.t()}}
+  def query_having(_sparql), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Flattens the basic graph pattern triples of a query, for index planning.
+
+  Triples come in the same pre-order as the `triple_patterns` of
+  `analyze_query/1`. Each carries `in_optional` (on the optional side of an
+  OPTIONAL) and `in_union` (in a UNION branch) flags, set at any depth.
+  Triples under GRAPH, MINUS, SERVICE and subqueries are included with no
+  marker of their own; property paths and EXISTS patterns are not included.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+
+  ## Returns
+  - `{:ok, [{{subject, predicate, object}, %{in_optional: boolean(), in_union: boolean()}}, ...]}`
+    on success
+  - `{:error, {:parse_error, message}}` on parse failure
+
+  ## Examples
+
+      iex> TripleStore.SPARQL.Parser.NIF.query_triple_patterns("SELECT * WHERE { ?s ?p ?o OPTIONAL { ?o ?q ?r } }")
+      {:ok,
+       [
+         {{{:variable, "s"}, {:variable, "p"}, {:variable, "o"}}, %{in_optional: false, in_union: false}},
+         {{{:variable, "o"}, {:variable, "q"}, {:variable, "r"}}, %{in_optional: true, in_union: false}}
+       ]}
+
+  """
+  @spec query_triple_patterns(String.t()) ::
+          {:ok, [{{term(), term(), term()}, %{in_optional: boolean(), in_union: boolean()}}]}
+          | {:error, {:parse_error, String.t()}}
+  def query_triple_patterns(_sparql), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Checks that a query only calls custom functions from an allow-list.
+
+  Every custom function call and custom aggregate in the query, including
+  those inside EXISTS patterns and subqueries, must have its IRI in
+  `allowed_iris`. Built-in functions are always allowed, and so are the XSD
+  casts SPARQL defines: `xsd:boolean`, `xsd:integer`, `xsd:decimal`,
+  `xsd:float`, `xsd:double`, `xsd:string` and `xsd:dateTime`. Intended as a
+  gate in front of the query executor.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+  - `allowed_iris` - IRIs of the custom functions the query may call
+
+  ## Returns
+  - `:ok` if every custom function is allowed
+  - `{:error, {:forbidden_function, iri}}` for the first disallowed one
+  - `{:error, {:parse_error, message}}` on parse failure
+
+  ## Examples
+
+      iex> TripleStore.SPARQL.Parser.NIF.validate_functions(
+      ...>   "SELECT * WHERE { ?s ?p ?o FILTER(<http://example.org/f>(?o)) }",
+      ...>   []
+      ...> )
+      {:error, {:forbidden_function, "http://example.org/f"}}
+
+  """
+  @spec validate_functions(String.t(), [String.t()]) ::
+          :ok
+          | {:error, {:forbidden_function, String.t()}}
+          | {:error, {:parse_error, String.t()}}
+  def validate_functions(_sparql, _allowed_iris), do: :erlang.nif_error(:nif_not_loaded)
+
+  @typedoc "Executor plan hint for a property path, see `property_path_plan/1`"
+  @type path_hint ::
+          {:fixed, {:named_node, String.t()}}
+          | {:inverse, path_hint()}
+          | {:transitive, path_hint(), :star | :plus}
+          | {:optional, path_hint()}
+          | {:seq, [path_hint()]}
+          | {:alt, [path_hint()]}
+          | {:negated, [{:named_node, String.t()}]}
+
+  @doc """
+  Compiles the property path patterns of a query into executor plan hints.
+
+  Each path pattern becomes `{subject, hint, object}`, in pre-order. The hint
+  pre-digests the path algebra so the executor can dispatch directly:
+
+  - `{:fixed, predicate}` - a single predicate
+  - `{:inverse, hint}` - `^path`
+  - `{:transitive, hint, :star | :plus}` - `path*` / `path+`, evaluated with
+    the transitive-closure operator
+  - `{:optional, hint}` - `path?`
+  - `{:seq, hints}` / `{:alt, hints}` - flattened sequences and alternatives
+  - `{:negated, predicates}` - `!(a|b)`
+
+  Plain predicates, top-level sequences and inverses are already rewritten into
+  basic graph patterns by the parser, so they only show up nested inside other
+  path operators.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+
+  ## Returns
+  - `{:ok, [{subject, hint, object}, ...]}` on success
+  - `{:error, {:parse_error, message}}` on parse failure
+
+  ## Examples
+
+      iex> TripleStore.SPARQL.Parser.NIF.property_path_plan("SELECT * WHERE { ?s <http://ex.org/p>+ ?o }")
+      {:ok, [{{:variable, "s"}, {:transitive, {:fixed, {:named_node, "http://ex.org/p"}}, :plus}, {:variable, "o"}}]}
+
+  """
+  @spec property_path_plan(String.t()) ::
+          {:ok, [{term(), path_hint(), term()}]}
+          | {:error, {:parse_error, String.t()}}
+  def property_path_plan(_sparql), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Computes a 64-bit structural fingerprint of a query for result caching.
+
+  The query is normalized to its algebra and variables and blank nodes are
+  renamed by order of first appearance before hashing (FNV-1a). Queries that
+  differ only in variable names, prefixes or whitespace get the same hash;
+  structurally different queries differ with overwhelming probability.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+
+  ## Returns
+  - `{:ok, <<hash::64>>}` on success
+  - `{:error, {:parse_error, message}}` on parse failure
+
+  ## Examples
+
+      iex> {:ok, a} = TripleStore.SPARQL.Parser.NIF.query_hash("SELECT ?s WHERE { ?s ?p ?o }")
+      iex> {:ok, b} = TripleStore.SPARQL.Parser.NIF.query_hash("SELECT ?x WHERE {?x ?y ?z}")
+      iex> a == b
+      true
+
+  """
+  @spec query_hash(String.t()) :: {:ok, <<_::64>>} | {:error, {:parse_error, String.t()}}
+  def query_hash(_sparql), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Computes a stable 32-byte structural signature of a query for plan caching.
+
+  Like `query_hash/1`, variables and blank nodes are renamed by order of
+  first appearance in the algebra, so variable names, blank node labels,
+  prefixes and whitespace don't matter. In addition, commutative operands are
+  sorted by their own (rename-independent) form before hashing with SHA-256:
+
+  - the triples of a basic graph pattern
+  - the operands of chains of joined groups (`{A} {B}`) and of UNIONs,
+    regardless of how they are nested
+  - the operands of `&&` and `||` chains, of `=`, `sameTerm`, `+` and `*`,
+    and the list of `IN`/`NOT IN`
+
+  Operands that differ only in how their variables link to the rest of the
+  query sort as equal and keep their written order, so reordering them can
+  still change the signature. Everything else is significant: OPTIONAL and
+  MINUS sides, FILTER and BIND placement, projection order, solution
+  modifiers, CONSTRUCT templates, GROUP BY order and literal lexical forms.
+  `SELECT *` projects in variable name order, so renamings that change that
+  order change its signature.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+
+  ## Returns
+  - `{:ok, <<signature::256>>}` on success
+  - `{:error, {:parse_error, message}}` on parse failure
+
+  ## Examples
+
+      iex> {:ok, a} = TripleStore.SPARQL.Parser.NIF.query_signature("SELECT ?s WHERE { ?s ?p ?o . ?o ?q 1 }")
+      iex> {:ok, b} = TripleStore.SPARQL.Parser.NIF.query_signature("SELECT ?x WHERE { ?y ?q 1 . ?x ?p ?y }")
+      iex> a == b
+      true
+
+  """
+  @spec query_signature(String.t()) :: {:ok, <<_::256>>} | {:error, {:parse_error, String.t()}}
+  def query_signature(_sparql), do: :erlang.nif_error(:nif_not_loaded)
+
+  @typedoc "Result of `analyze_query/1`"
+  @type query_analysis :: %{
+          form: :select | :construct | :ask | :describe,
+          variables: [String.t()],
+          projected: [String.t()],
+          triple_patterns: [{term(), term(), term()}],
+          named_graphs: [String.t()],
+          modifiers: %{
+            distinct: boolean(),
+            reduced: boolean(),
+            order_by: [{:asc | :desc, term()}],
+            limit: non_neg_integer() | nil,
+            offset: non_neg_integer()
+          },
+          warnings: [{:unbound_projection, String.t()} | {:cartesian_product, [term()]}],
+          optional_dependencies: [{[String.t()], [String.t()], [String.t()]}],
+          property_paths: [{term(), path_hint(), term()}],
+          hash: <<_::64>>
+        }
+
+  @doc """
+  Parses a query once and returns the analysis the query pipeline needs.
+
+  Cheaper than calling the individual analysis functions one by one. The
+  `optional_dependencies`, `property_paths` and `hash` entries are identical
+  to the results of `optional_dependencies/1`, `property_path_plan/1` and
+  `query_hash/1`; terms use the same encoding as `parse_query/1`.
+
+  - `form` - query form
+  - `variables` - variables in scope in the WHERE clause, sorted
+  - `projected` - SELECT/DESCRIBE projection in order (`[]` otherwise)
+  - `triple_patterns` - all basic graph pattern triples, in pre-order
+  - `named_graphs` - IRIs from `FROM NAMED` and `GRAPH <iri>`, sorted
+  - `modifiers` - DISTINCT/REDUCED flags, ORDER BY conditions, LIMIT and OFFSET
+  - `warnings` - `{:unbound_projection, var}` for projected variables the
+    WHERE clause never binds, `{:cartesian_product, triples}` for each basic
+    graph pattern whose triples share no variables
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+
+  ## Returns
+  - `{:ok, analysis}` on success
+  - `{:error, {:parse_error, message}}` on parse failure
+
+  ## Examples
+
+      iex> {:ok, analysis} = TripleStore.SPARQL.Parser.NIF.analyze_query("SELECT ?s WHERE { ?s ?p ?o } LIMIT 10")
+      iex> {analysis.form, analysis.projected, analysis.modifiers.limit}
+      {:select, ["s"], 10}
+
+  """
+  @spec analyze_query(String.t()) :: {:ok, query_analysis()} | {:error, {:parse_error, String.t()}}
+  def analyze_query(_sparql), do: :erlang.nif_error(:nif_not_loaded)
+
+  @typedoc "Variable-sharing graph returned by `join_graph/1`"
+  @type join_graph :: %{
+          patterns: [term()],
+          edges: [{non_neg_integer(), non_neg_integer(), [String.t()]}]
+        }
+
+  @doc """
+  Computes the variable-sharing graph between a query's triple patterns.
+
+  This is the input to join ordering: patterns are nodes, and an edge joins
+  two patterns that can be evaluated with a shared variable as join key.
+
+  `patterns` is the flattened triple-pattern list in the same order as the
+  `triple_patterns` of `analyze_query/1`, so indices line up. Each edge
+  `{i, j, shared_vars}` has `i < j`, and `shared_vars` are the shared
+  variable names, sorted. Edges are sorted by `{i, j}`. Only variables count
+  as join keys; property path patterns are not part of the list.
+
+  ## Arguments
+  - `sparql` - The SPARQL query string to parse
+
+  ## Returns
+  - `{:ok, %{patterns: patterns, edges: edges}}` on success
+  - `{:error, {:parse_error, message}}` on parse failure
+
+  ## Examples
+
+      iex> {:ok, graph} = TripleStore.SPARQL.Parser.NIF.join_graph(
+      ...>   "SELECT * WHERE { ?a <http://ex.org/p> ?b . ?b <http://ex.org/p> ?c }"
+      ...> )
+      iex> graph.edges
+      [{0, 1, ["b"]}]
+
+  """
+  @spec join_graph(String.t()) :: {:ok, join_graph()} | {:error, {:parse_error, String.t()}}
+  def join_graph(_sparql), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ===========================================================================
+  # RDF Document Parsing
+  # ===========================================================================
+
+  @typedoc "Options accepted by `parse_turtle/2` and `parse_ntriples/2`"
+  @type parse_opts :: [scope_blank_nodes: boolean()]
+
+  @doc """
+  Parses a Turtle document into triples.
+
+  Terms use the same encoding as `parse_query/1`.
+
+  ## Blank node scoping
+
+  Blank node labels are scoped to the document passed in a single call: every
+  occurrence of `_:a` (and every `[]` reference to the same node) in that
+  document decodes to the same `{:blank_node, id}`. By default labels are
+  returned as written, so `_:a` from two different documents would be treated
+  as the same node once both are stored.
+
+  With `scope_blank_nodes: true`, each label is rewritten to
+  `"<prefix>_<label>"`, where `<prefix>` is a random 128-bit hex string drawn
+  once per call. Labels stay linked within the document and never collide
+  with blank nodes from another call.
+
+  ## Arguments
+  - `data` - Turtle document as a binary
+  - `opts` - Keyword list of options (`:scope_blank_nodes`, default `false`)
+
+  ## Returns
+  - `{:ok, triples}` with a list of `{subject, predicate, object}` tuples
+  - `{:error, {:parse_error, {line, message}}}` on the first syntax error,
+    where `line` is 1-based
+
+  ## Examples
+
+      iex> TripleStore.SPARQL.Parser.NIF.parse_turtle("<http://ex.org/s> <http://ex.org/p> \"o\" .")
+      {:ok, [{{:named_node, "http://ex.org/s"}, {:named_node, "http://ex.org/p"}, {:literal, :simple, "o"}}]}
+
+  """
+  @spec parse_turtle(binary(), parse_opts()) ::
+          {:ok, [{rdf_term(), rdf_term(), rdf_term()}]}
+          | {:error, {:parse_error, {pos_integer(), String.t()}}}
+  def parse_turtle(_data, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses an N-Triples document into triples.
+
+  Uses the same term encoding, blank node scoping rule and options as
+  `parse_turtle/2`.
+
+  ## Arguments
+  - `data` - N-Triples document as a binary
+  - `opts` - Keyword list of options (`:scope_blank_nodes`, default `false`)
+
+  ## Returns
+  - `{:ok, triples}` with a list of `{subject, predicate, object}` tuples
+  - `{:error, {:parse_error, {line, message}}}` on the first syntax error,
+    where `line` is 1-based
+
+  """
+  @spec parse_ntriples(binary(), parse_opts()) ::
+          {:ok, [{rdf_term(), rdf_term(), rdf_term()}]}
+          | {:error, {:parse_error, {pos_integer(), String.t()}}}
+  def parse_ntriples(_data, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses an N-Quads document into quads.
+
+  Subject, predicate and object use the same encoding as `parse_turtle/2`.
+  The graph is `{:named_node, iri}`, `{:blank_node, id}`, or `:default` for
+  statements without a graph label, matching the input of
+  `serialize_nquads/1`.
+
+  ## Options
+  - `:on_error` - `:abort` (default) stops at the first syntax error. `:skip`
+    drops invalid lines and collects their errors as `{line, message}`
+    tuples (1-based line) in document order.
+
+  ## Arguments
+  - `data` - N-Quads document as a binary
+  - `opts` - Map of options
+
+  ## Returns
+  - `{:ok, quads}` when aborting on errors
+  - `{:ok, quads, errors}` when skipping errors
+  - `{:error, {:parse_error, {line, message}}}` on the first syntax error
+    when aborting
+
+  """
+  @spec parse_nquads(binary(), %{optional(:on_error) => :abort | :skip}) ::
+          {:ok, [{rdf_term(), rdf_term(), rdf_term(), graph_term()}]}
+          | {:ok, [{rdf_term(), rdf_term(), rdf_term(), graph_term()}], [{pos_integer(), String.t()}]}
+          | {:error, {:parse_error, {pos_integer(), String.t()}}}
+  def parse_nquads(_data, _opts \\ %{}), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ===========================================================================
+  # Streaming Turtle Parsing
+  # ===========================================================================
+
+  @doc """
+  Parses a Turtle document incrementally, sending triples to `pid` in chunks.
+
+  Use this instead of `parse_turtle/2` for documents too large to hold as one
+  list. The call returns as soon as the input is opened; parsing continues on
+  a background thread and delivers these messages to `pid`:
+
+  - `{:turtle_chunk, ref, triples}` - up to `chunk_size` triples
+  - `{:turtle_done, ref, total}` - after the last chunk, with the triple count
+  - `{:turtle_error, ref, {line, message}}` - syntax error at a 1-based line;
+    triples parsed before the error are delivered first
+  - `{:turtle_error, ref, {:io_error, message}}` - read failure
+  - `{:turtle_error, ref, {:internal_error, message}}` - the parser thread
+    crashed; chunks sent before it stay delivered
+
+  Exactly one `:turtle_done` or `:turtle_error` message ends the stream.
+  Parsing stops early if `pid` exits.
+
+  ## Backpressure
+
+  The parser does not wait for the consumer. Memory on the parser side is
+  bounded by `chunk_size`, but chunks the consumer has not yet handled queue
+  up in its mailbox. Consumers that do slow work per chunk should hand it off
+  (or use a small `chunk_size` and process chunks promptly) so the mailbox
+  does not grow with the document.
+
+  ## Arguments
+  - `source` - The document as a binary, or `{:file, path}` to read a file
+  - `pid` - Process receiving the messages
+  - `chunk_size` - Maximum number of triples per chunk (positive integer;
+    values above 100_000 are clamped to it)
+
+  ## Returns
+  - `{:ok, ref}` - Parsing started; `ref` tags every message
+  - `{:error, {:io_error, reason}}` - The file could not be opened, or the
+    parser thread could not be started
+
+  ## Examples
+
+      {:ok, ref} = TripleStore.SPARQL.Parser.NIF.parse_turtle_stream({:file, "big.ttl"}, self(), 10_000)
+
+      receive do
+        {:turtle_chunk, ^ref, triples} -> ...
+        {:turtle_done, ^ref, total} -> ...
+        {:turtle_error, ^ref, reason} -> ...
+      end
+
+  """
+  @spec parse_turtle_stream(binary() | {:file, Path.t()}, pid(), pos_integer()) ::
+          {:ok, reference()} | {:error, {:io_error, String.t()}}
+  def parse_turtle_stream(_source, _pid, _chunk_size), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ===========================================================================
+  # Expression Serialization
+  # ===========================================================================
+
+  @doc """
+  Serializes an expression term back to SPARQL syntax.
+
+  Takes the FILTER/BIND expression encoding produced by `parse_query/1` and
+  emits only the parentheses needed to preserve the tree, using SPARQL's
+  precedence levels (loosest first): `||`, `&&`, relational (`=`, `<`, `IN`,
+  ...), additive, multiplicative, unary, primary.
+
+  - An operand is wrapped only when it binds looser than its position needs.
+  - Binary arithmetic and boolean operators are left-associative, so a right
+    operand at the same level is wrapped: `?a - (?b - ?c)`.
+  - Relational operators do not chain, so both operands must bind tighter.
+  - Unary operators take a primary expression: `-(?a + ?b)`, `!(?a = ?b)`.
+
+  Parsing the output yields the same expression term. Note that `!=` and
+  `NOT IN` are encoded as `:not` around `:equal` / `:in_expr`, so they come
+  back as `!(?a = ?b)` and `!(?a IN (...))`.
+
+  `EXISTS` / `NOT EXISTS` are not supported since they embed a graph pattern.
+
+  ## Arguments
+  - `expr` - Expression term
+
+  ## Returns
+  - `{:ok, sparql}` on success
+  - `{:error, {:invalid_expression, term}}` with the first sub-term that
+    cannot be serialized
+
+  ## Examples
+
+      iex> TripleStore.SPARQL.Parser.NIF.serialize_expression(
+      ...>   {:multiply, {:add, {:variable, "a"}, {:variable, "b"}}, {:variable, "c"}}
+      ...> )
+      {:ok, "(?a + ?b) * ?c"}
+
+  """
+  @spec serialize_expression(term()) :: {:ok, String.t()} | {:error, {:invalid_expression, term()}}
+  def serialize_expression(_expr), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ===========================================================================
+  # Query Serialization
+  # ===========================================================================
+
+  @doc """
+  Serializes a query AST back to a SPARQL string.
+
+  The inverse of `parse_query/1` for SELECT, CONSTRUCT, ASK and DESCRIBE:
+  parsing the output yields the same AST, so a query can be rewritten in
+  Elixir (e.g. wrapping the pattern in a `:slice` to add a LIMIT) and sent on
+  as text. IRIs and prefixed names come back as full IRIs, and literals in
+  their canonical `"value"^^<datatype>` form.
+
+  GROUP BY, HAVING, aggregates and `(expr AS ?v)` projections are written
+  back as such, also in subqueries. The variables the parser generates for
+  aggregates and `DESCRIBE <iri>` get random names on every parse, so for
+  those queries the reparsed AST matches up to these names. Rewritten ASTs
+  that don't keep the parser's nesting (`:slice` > `:distinct` > `:project` >
+  `:order_by`) serialize to an equivalent query that parses back as a
+  different tree.
+
+  ## Arguments
+  - `ast` - Query AST as returned by `parse_query/1`
+
+  ## Returns
+  - `{:ok, sparql}` on success
+  - `{:error, {:invalid_ast, term}}` with the first sub-term that cannot be
+    decoded
+
+  ## Examples
+
+      iex> {:ok, ast} = TripleStore.SPARQL.Parser.NIF.parse_query("SELECT ?s WHERE { ?s ?p ?o } LIMIT 10")
+      iex> TripleStore.SPARQL.Parser.NIF.query_to_sparql(ast)
+      {:ok, "SELECT ?s WHERE { ?s ?p ?o . } LIMIT 10"}
+
+  """
+  @spec query_to_sparql(term()) :: {:ok, String.t()} | {:error, {:invalid_ast, term()}}
+  def query_to_sparql(_ast), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Serializes an UPDATE AST back to a SPARQL string.
+
+  The inverse of `parse_update/1` for every operation it encodes (INSERT DATA,
+  DELETE DATA, DELETE/INSERT WHERE, LOAD, CLEAR, CREATE and DROP): parsing
+  the output yields the same AST, so templates can be rewritten in Elixir
+  and sent on as text. GROUP BY and aggregate subqueries in a WHERE clause
+  serialize to an equivalent but differently nested tree. `WITH <g>` is written as `USING <g>`, with the templates
+  already carrying `GRAPH <g>`; both parse to the same AST.
+
+  ## Arguments
+  - `ast` - UPDATE AST as returned by `parse_update/1`
+
+  ## Returns
+  - `{:ok, sparql}` on success
+  - `{:error, {:invalid_ast, term}}` with the first sub-term that cannot be
+    decoded
+
+  ## Examples
+
+      iex> {:ok, ast} = TripleStore.SPARQL.Parser.NIF.parse_update("CLEAR SILENT GRAPH <http://example.org/g>")
+      iex> TripleStore.SPARQL.Parser.NIF.update_to_sparql(ast)
+      {:ok, "CLEAR SILENT GRAPH <http://example.org/g>"}
+
+  """
+  @spec update_to_sparql(term()) :: {:ok, String.t()} | {:error, {:invalid_ast, term()}}
+  def update_to_sparql(_ast), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ===========================================================================
+  # Literal Decoding
+  # ===========================================================================
+
+  @typedoc """
+  Decoded `xsd:dateTime`: the date and time as an Erlang calendar datetime,
+  microseconds, and the timezone offset in minutes (`nil` if absent)
+  """
+  @type xsd_datetime ::
+          {:datetime, :calendar.datetime(), 0..999_999, integer() | nil}
+
+  @doc """
+  Converts a typed literal to a native Elixir value.
+
+  Centralizes XSD value-space conversion for filter evaluation:
+
+  - `xsd:boolean` - `true` / `false` (lexical forms `true`, `false`, `1`, `0`)
+  - `xsd:integer` and its derived types (`xsd:long`, `xsd:int`, ...,
+    `xsd:unsignedByte`) - integer, checked against the type's range
+  - `xsd:double`, `xsd:float`, `xsd:decimal` - float. `xsd:float` is rounded to
+    single precision and `xsd:decimal` is approximated. `INF`, `-INF` and `NaN`
+    have no BEAM float, so they decode to `:inf`, `:neg_inf` and `:nan`
+  - `xsd:dateTime` - `t:xsd_datetime/0`. `24:00:00` becomes midnight of the
+    next day and fractional seconds are truncated to microseconds
+
+  Leading and trailing whitespace in the lexical form is ignored.
+
+  ## Arguments
+  - `literal` - `{:literal, :typed, value, datatype}` term
+
+  ## Returns
+  - `{:ok, native}` on success
+  - `{:error, :unsupported_datatype}` for any other datatype, and for simple
+    or language-tagged literals
+  - `{:error, {:invalid_lexical_form, value}}` if the value is not valid for
+    its datatype (including integers beyond 128 bits)
+  - `{:error, {:invalid_term, term}}` if the term is not a literal
+
+  ## Examples
+
+      iex> TripleStore.SPARQL.Parser.NIF.decode_literal(
+      ...>   {:literal, :typed, "42", "http://www.w3.org/2001/XMLSchema#integer"}
+      ...> )
+      {:ok, 42}
+
+      iex> TripleStore.SPARQL.Parser.NIF.decode_literal(
+      ...>   {:literal, :typed, "2024-01-15T10:30:00.5Z", "http://www.w3.org/2001/XMLSchema#dateTime"}
+      ...> )
+      {:ok, {:datetime, {{2024, 1, 15}, {10, 30, 0}}, 500_000, 0}}
+
+  """
+  @spec decode_literal(rdf_term()) ::
+          {:ok, boolean() | integer() | float() | :inf | :neg_inf | :nan | xsd_datetime()}
+          | {:error, :unsupported_datatype | {:invalid_lexical_form, String.t()} | {:invalid_term, term()}}
+  def decode_literal(_literal), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ===========================================================================
+  # N-Triples Serialization
+  # ===========================================================================
+
+  @typedoc "RDF term in the encoding produced by the parser"
+  @type rdf_term ::
+          {:named_node, String.t()}
+          | {:blank_node, String.t()}
+          | {:literal, :simple, String.t()}
+          | {:literal, :typed, String.t(), String.t()}
+          | {:literal, :language_tagged, String.t(), String.t()}
+          | {:triple_term, rdf_term(), {:named_node, String.t()}, rdf_term()}
+
+  @doc """
+  Serializes triples to an N-Triples document.
+
+  Terms use the same encoding the parser produces. Input order and duplicate
+  triples are preserved.
+
+  ## Arguments
+  - `triples` - List of `{subject, predicate, object}` tuples
+
+  ## Returns
+  - `{:ok, ntriples}` on success
+  - `{:error, {:invalid_term, term}}` if a term cannot be serialized
+
+  ## Examples
+
+      iex> TripleStore.SPARQL.Parser.NIF.serialize_ntriples([
+      ...>   {{:named_node, "http://ex.org/s"}, {:named_node, "http://ex.org/p"}, {:literal, :simple, "o"}}
+      ...> ])
+      {:ok, "<http://ex.org/s> <http://ex.org/p> \"o\" .\n"}
+
+  """
+  @spec serialize_ntriples([{rdf_term(), rdf_term(), rdf_term()}]) ::
+          {:ok, String.t()} | {:error, {:invalid_term, term()}}
+  def serialize_ntriples(_triples), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Serializes triples to an N-Triples document with options.
+
+  ## Options
+  - `:dedup` - When `true`, skips triples already written (first occurrence
+    wins). Useful for CONSTRUCT/DESCRIBE results, which often repeat triples.
+    The dedup set grows only with the number of distinct triples. Defaults to
+    `false`, which preserves input order and duplicates.
+
+  ## Arguments
+  - `triples` - List of `{subject, predicate, object}` tuples
+  - `opts` - Keyword list of options
+
+  ## Returns
+  - `{:ok, ntriples, count}` where `count` is the number of triples written
+    (the unique count when deduplicating)
+  - `{:error, {:invalid_term, term}}` if a term cannot be serialized
+
+  """
+  @spec serialize_ntriples([{rdf_term(), rdf_term(), rdf_term()}], dedup: boolean()) ::
+          {:ok, String.t(), non_neg_integer()} | {:error, {:invalid_term, term()}}
+  def serialize_ntriples(_triples, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  # ===========================================================================
+  # N-Quads Serialization
+  # ===========================================================================
+
+  @typedoc "Graph of a quad: a named node, a blank node (parsing only) or the default graph"
+  @type graph_term :: {:named_node, String.t()} | {:blank_node, String.t()} | :default
+
+  @doc """
+  Serializes quads to an N-Quads document.
+
+  Terms use the same encoding the parser produces; the fourth element names
+  the graph, with `:default` for the default graph. Terms are validated and
+  written in canonical N-Quads form, so parsing the output and serializing it
+  again yields the same document. Input order and duplicates are preserved.
+
+  ## Arguments
+  - `quads` - List of `{subject, predicate, object, graph}` tuples
+
+  ## Returns
+  - `{:ok, nquads}` on success
+  - `{:error, {:invalid_term, term}}` if a subject, predicate or object is invalid
+  - `{:error, {:invalid_graph, graph}}` if a graph is not a named node or `:default`
+
+  ## Examples
+
+      iex> TripleStore.SPARQL.Parser.NIF.serialize_nquads([
+      ...>   {{:named_node, "http://ex.org/s"}, {:named_node, "http://ex.org/p"}, {:literal, :simple, "o"},
+      ...>    {:named_node, "http://ex.org/g"}}
+      ...> ])
+      {:ok, "<http://ex.org/s> <http://ex.org/p> \"o\" <http://ex.org/g> .\n"}
+
+  """
+  @spec serialize_nquads([{rdf_term(), rdf_term(), rdf_term(), graph_term()}]) ::
+          {:ok, String.t()}
+          | {:error, {:invalid_term, term()} | {:invalid_graph, term()}}
+  def serialize_nquads(_quads), do: :erlang.nif_error(:nif_not_loaded)
 end
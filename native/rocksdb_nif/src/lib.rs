@@ -4,16 +4,46 @@
 //! Elixir application. All I/O operations use dirty CPU schedulers to prevent
 //! blocking the BEAM schedulers.
 
-use rocksdb::{BlockBasedOptions, ColumnFamilyDescriptor, DBIteratorWithThreadMode, IteratorMode, Options, ReadOptions, SliceTransform, SnapshotWithThreadMode, WriteBatch, WriteOptions, DB};
-use rustler::{Binary, Encoder, Env, ListIterator, NewBinary, NifResult, Resource, ResourceArc, Term};
-use std::sync::{Arc, Mutex, RwLock};
+use arc_swap::{ArcSwapOption, Guard};
+use rocksdb::backup::{BackupEngine, BackupEngineOptions, RestoreOptions};
+use rocksdb::checkpoint::Checkpoint;
+use rocksdb::{
+    BlockBasedIndexType, BlockBasedOptions, BottommostLevelCompaction, Cache,
+    ColumnFamilyDescriptor, CompactOptions, CompactionDecision, DBIteratorWithThreadMode,
+    DBPinnableSlice, ErrorKind, FlushOptions, IngestExternalFileOptions, IteratorMode,
+    MemtableFactory, MergeOperands, Options, ReadOptions, SliceTransform, SnapshotWithThreadMode,
+    SstFileWriter, Transaction, TransactionDB, TransactionDBOptions, TransactionOptions,
+    WriteBatch, WriteOptions, DB,
+};
+use rustler::{
+    Binary, Encoder, Env, ListIterator, LocalPid, MapIterator, NewBinary, NifResult, OwnedEnv,
+    Resource, ResourceArc, Term,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError, Weak};
+use std::time::Duration;
 
 /// Column family names used by TripleStore
-const CF_NAMES: [&str; 7] = ["id2str", "str2id", "spo", "pos", "osp", "derived", "numeric_range"];
+/// The triple indexes (spo/pos/osp) hold the default graph; the quad indexes
+/// (gspo/gpos/gosp) hold named graphs, keyed by graph ID first.
+const CF_NAMES: [&str; 10] = [
+    "id2str",
+    "str2id",
+    "spo",
+    "pos",
+    "osp",
+    "derived",
+    "numeric_range",
+    "gspo",
+    "gpos",
+    "gosp",
+];
 
 /// Column families that use prefix extraction (8-byte prefix = first component ID)
 /// These CFs benefit from prefix bloom filters and native prefix iteration.
-const PREFIX_CFS: [&str; 4] = ["spo", "pos", "osp", "numeric_range"];
+const PREFIX_CFS: [&str; 7] = ["spo", "pos", "osp", "numeric_range", "gspo", "gpos", "gosp"];
 
 /// Prefix length in bytes (64-bit ID = 8 bytes)
 const PREFIX_LENGTH: usize = 8;
@@ -24,7 +54,7 @@ const DICTIONARY_CFS: [&str; 2] = ["id2str", "str2id"];
 
 /// Index column families - prefix scans, range queries
 /// Optimized with: 12 bits/key bloom (0.09% FPR), 8KB blocks
-const INDEX_CFS: [&str; 4] = ["spo", "pos", "osp", "numeric_range"];
+const INDEX_CFS: [&str; 7] = ["spo", "pos", "osp", "numeric_range", "gspo", "gpos", "gosp"];
 
 /// Derived column family - bulk writes, sequential reads
 /// Optimized with: no bloom filter, 32KB blocks
@@ -53,15 +83,47 @@ const DERIVED_BLOCK_SIZE: usize = 32 * 1024; // 32KB
 struct SharedDb {
     db: DB,
     path: String,
+    /// Backed by RocksDB's in-memory Env (`open_memory`); nothing is on disk
+    in_memory: bool,
+    /// Column families opened with a merge operator; `merge` is rejected elsewhere
+    merge_cfs: HashSet<CfName>,
+    /// Column families exposed to callers; others return `cf_not_opened`
+    opened_cfs: HashSet<CfName>,
+    /// Seconds after which `derived` entries expire (`open_with_ttl`); their
+    /// values then carry a write-time stamp, see `stamp`
+    derived_ttl: Option<u64>,
+    /// Number of live IteratorRefs (not yet closed or garbage-collected)
+    open_iterators: AtomicUsize,
+    /// Serializes transaction conflict checks and `put_if_absent` checks with
+    /// their writes
+    commit_lock: Mutex<()>,
+    /// Keys locked by open transactions
+    txn_locks: TxnLocks,
+    /// `subscribe_events` subscribers, shared with their poller thread
+    events: Arc<Mutex<EventFeed>>,
+    /// Injected write failures still to deliver; negative means until cleared
+    #[cfg(feature = "fault_injection")]
+    write_faults: std::sync::atomic::AtomicI64,
 }
 
 /// Database reference wrapper for safe cross-NIF-boundary passing.
-/// Uses RwLock<Option<Arc<SharedDb>>> so that:
-/// - close() sets the Option to None (marking as closed for new operations)
+/// Uses ArcSwapOption<SharedDb> so that:
+/// - NIFs load the database with one atomic load instead of taking a lock
+/// - close() swaps in None (marking as closed for new operations)
 /// - But the Arc<SharedDb> may still exist in iterators/snapshots
 /// - The actual DB is only dropped when the last Arc is dropped
 pub struct DbRef {
-    inner: RwLock<Option<Arc<SharedDb>>>,
+    inner: ArcSwapOption<SharedDb>,
+    /// Held by close and by operations that check the database out of `inner`
+    exclusive: Mutex<()>,
+    /// Set by close, so a load that finds `inner` empty can tell a closed
+    /// database from a checked-out one
+    closed: AtomicBool,
+    /// Opened with `open_read_only` or `open_secondary`; write NIFs reject it
+    /// without taking the lock
+    read_only: bool,
+    /// Opened with `open_secondary`; can catch up with its primary
+    secondary: bool,
 }
 
 #[rustler::resource_impl]
@@ -76,17 +138,311 @@ pub struct IteratorRef {
     /// This is safe because SharedDb is only dropped when all Arc references are dropped,
     /// and we hold one here.
     iterator: Mutex<Option<DBIteratorWithThreadMode<'static, DB>>>,
+    /// Snapshot taken when the iterator was created. The RocksDB iterators
+    /// rebuilt by seeks, resets and `iterator_prev` read from it too, so the
+    /// iterator keeps one point-in-time view. Declared before `db` so it is
+    /// released first.
+    snapshot: SnapshotWithThreadMode<'static, DB>,
     /// Direct reference to the shared database - keeps the DB alive even after close()
     db: Arc<SharedDb>,
     /// The prefix used for this iterator (for bounds checking)
     prefix: Vec<u8>,
     /// Column family name for this iterator
     cf_name: String,
+    /// Entries whose value doesn't start with this are skipped (empty matches all)
+    value_prefix: Vec<u8>,
+    /// `[lower, upper)` key range enforced by RocksDB for bounded iterators;
+    /// the prefix is empty in that case
+    bounds: Option<(Vec<u8>, Vec<u8>)>,
+    /// Walks the prefix from its last key down to its first
+    reverse: bool,
+    /// Exclusive key a prefix iterator also stops at, never past the end of
+    /// the prefix. Set on the read options so RocksDB skips blocks beyond it.
+    upper_bound: Option<Vec<u8>>,
+    /// Position `iterator_prev` steps back from. Always locked after `iterator`.
+    cursor: Mutex<IteratorCursor>,
 }
 
 #[rustler::resource_impl]
 impl Resource for IteratorRef {}
 
+/// Where an `IteratorRef` stands, so it can change direction.
+#[derive(Default)]
+struct IteratorCursor {
+    position: CursorPosition,
+    /// Whether the RocksDB iterator currently runs against the iterator's
+    /// direction, after `iterator_prev`
+    stepping_back: bool,
+}
+
+/// Cursor position, in the iterator's own order.
+#[derive(Default)]
+enum CursorPosition {
+    /// Before the first entry: fresh, reset, or `iterator_prev` ran out
+    #[default]
+    Start,
+    /// On the last key returned, or the `iterator_seek` target
+    At(Vec<u8>),
+    /// Past the last entry: the iterator ran out
+    End,
+}
+
+impl IteratorCursor {
+    /// Records `key` as the position, reusing the buffer.
+    fn record(&mut self, key: &[u8]) {
+        match &mut self.position {
+            CursorPosition::At(position) => {
+                position.clear();
+                position.extend_from_slice(key);
+            }
+            _ => self.position = CursorPosition::At(key.to_vec()),
+        }
+    }
+
+    /// Records that the current direction ran out of entries.
+    fn exhausted(&mut self) {
+        self.position = if self.stepping_back {
+            CursorPosition::Start
+        } else {
+            CursorPosition::End
+        };
+    }
+
+    /// Sets the position after the RocksDB iterator was rebuilt in the
+    /// iterator's own direction, at `target` or at the start.
+    fn restart(&mut self, target: Option<&[u8]>) {
+        self.position = match target {
+            Some(key) => CursorPosition::At(key.to_vec()),
+            None => CursorPosition::Start,
+        };
+        self.stepping_back = false;
+    }
+}
+
+impl IteratorRef {
+    /// Wraps a new iterator and counts it as open on its database.
+    fn new(
+        iterator: DBIteratorWithThreadMode<'static, DB>,
+        snapshot: SnapshotWithThreadMode<'static, DB>,
+        db: Arc<SharedDb>,
+        prefix: Vec<u8>,
+        cf_name: String,
+        value_prefix: Vec<u8>,
+        reverse: bool,
+    ) -> Self {
+        db.open_iterators.fetch_add(1, Ordering::Relaxed);
+        IteratorRef {
+            iterator: Mutex::new(Some(iterator)),
+            snapshot,
+            db,
+            prefix,
+            cf_name,
+            value_prefix,
+            bounds: None,
+            reverse,
+            upper_bound: None,
+            cursor: Mutex::new(IteratorCursor::default()),
+        }
+    }
+
+    /// Sets the upper bound of a prefix iterator, as built by `prefix_upper_bound`.
+    fn with_upper_bound(mut self, upper_bound: Option<Vec<u8>>) -> Self {
+        self.upper_bound = upper_bound;
+        self
+    }
+
+    /// Sets the `[lower, upper)` range of a bounded iterator, whose prefix is empty.
+    fn with_bounds(mut self, bounds: (Vec<u8>, Vec<u8>)) -> Self {
+        self.bounds = Some(bounds);
+        self
+    }
+
+    /// Read options for rebuilding this iterator, matching how it was created.
+    fn read_options(&self) -> ReadOptions {
+        self.pinned(match &self.bounds {
+            Some((lower, upper)) => bounded_read_options(lower, upper),
+            None if self.reverse => self.capped(reverse_prefix_read_options(&self.prefix)),
+            None => self.capped(prefix_read_options(&self.cf_name, &self.prefix)),
+        })
+    }
+
+    /// Makes read options read from the snapshot the iterator was created with.
+    fn pinned(&self, mut read_opts: ReadOptions) -> ReadOptions {
+        read_opts.set_snapshot(&self.snapshot);
+        read_opts
+    }
+
+    /// Applies the upper bound, if any, to prefix read options.
+    fn capped(&self, mut read_opts: ReadOptions) -> ReadOptions {
+        if let Some(upper) = &self.upper_bound {
+            read_opts.set_iterate_upper_bound(upper.clone());
+        }
+        read_opts
+    }
+
+    /// Position the iterator starts from: the lower bound or the prefix, or
+    /// the last key of the prefix for reverse iterators.
+    fn start_mode(&self) -> IteratorMode<'_> {
+        match &self.bounds {
+            Some((lower, _)) => IteratorMode::From(lower, rocksdb::Direction::Forward),
+            // The upper bound of the read options makes End the last prefix key
+            None if self.reverse => IteratorMode::End,
+            None => IteratorMode::From(&self.prefix, rocksdb::Direction::Forward),
+        }
+    }
+
+    /// Whether `key` belongs to this iterator: within the prefix and below
+    /// the upper bound, or within `[lower, upper)` for bounded iterators.
+    /// RocksDB already stops at the bounds; the comparison keeps this from
+    /// depending on them alone.
+    fn covers(&self, key: &[u8]) -> bool {
+        match &self.bounds {
+            Some((lower, upper)) => lower.as_slice() <= key && key < upper.as_slice(),
+            None => {
+                key.starts_with(&self.prefix)
+                    && self.upper_bound.as_deref().is_none_or(|upper| key < upper)
+            }
+        }
+    }
+
+    /// Direction `iterator_seek` steps in from its target.
+    fn direction(&self) -> rocksdb::Direction {
+        if self.reverse {
+            rocksdb::Direction::Reverse
+        } else {
+            rocksdb::Direction::Forward
+        }
+    }
+
+    /// Direction `iterator_prev` steps in, against `direction`.
+    fn back_direction(&self) -> rocksdb::Direction {
+        if self.reverse {
+            rocksdb::Direction::Forward
+        } else {
+            rocksdb::Direction::Reverse
+        }
+    }
+
+    /// Builds an iterator walking in `direction` from `position`: strictly
+    /// past the key for `At`, otherwise from the end of the prefix or bounds
+    /// `direction` starts at. `None` if the column family is gone.
+    fn step_from(
+        &self,
+        position: &CursorPosition,
+        direction: rocksdb::Direction,
+    ) -> Option<DBIteratorWithThreadMode<'static, DB>> {
+        let cf_handle = self.db.cf_handle(&self.cf_name)?;
+
+        let new_iterator = match (position, direction) {
+            (CursorPosition::At(key), rocksdb::Direction::Forward) => {
+                // The smallest key after `key` is `key` followed by 0
+                let mut after = key.clone();
+                after.push(0);
+                self.db.db.iterator_cf_opt(
+                    cf_handle,
+                    self.read_options(),
+                    IteratorMode::From(&after, rocksdb::Direction::Forward),
+                )
+            }
+            (CursorPosition::At(key), rocksdb::Direction::Reverse) => {
+                // An exclusive upper bound at `key` makes End the key before it
+                let lower = match &self.bounds {
+                    Some((lower, _)) => lower.as_slice(),
+                    None => self.prefix.as_slice(),
+                };
+                self.db.db.iterator_cf_opt(
+                    cf_handle,
+                    self.pinned(bounded_read_options(lower, key)),
+                    IteratorMode::End,
+                )
+            }
+            (_, rocksdb::Direction::Forward) => self.db.db.iterator_cf_opt(
+                cf_handle,
+                self.full_range_read_options(),
+                IteratorMode::Start,
+            ),
+            (_, rocksdb::Direction::Reverse) => self.db.db.iterator_cf_opt(
+                cf_handle,
+                self.full_range_read_options(),
+                IteratorMode::End,
+            ),
+        };
+
+        // SAFETY: We keep the SharedDb alive via Arc, so the iterator remains valid.
+        // The Arc<SharedDb> is stored in IteratorRef and keeps the DB alive.
+        let static_iterator: DBIteratorWithThreadMode<'static, DB> =
+            unsafe { std::mem::transmute(new_iterator) };
+        Some(static_iterator)
+    }
+
+    /// Read options that make RocksDB enforce the whole prefix or bounds in
+    /// either direction.
+    fn full_range_read_options(&self) -> ReadOptions {
+        self.pinned(match &self.bounds {
+            Some((lower, upper)) => bounded_read_options(lower, upper),
+            None => self.capped(reverse_prefix_read_options(&self.prefix)),
+        })
+    }
+
+    /// Builds an iterator walking towards smaller keys from the largest key
+    /// `<= target` within the prefix or bounds, like RocksDB's `SeekForPrev`.
+    /// `None` if the column family is gone.
+    fn seek_for_prev(&self, target: &[u8]) -> Option<DBIteratorWithThreadMode<'static, DB>> {
+        let cf_handle = self.db.cf_handle(&self.cf_name)?;
+
+        let new_iterator = self.db.db.iterator_cf_opt(
+            cf_handle,
+            self.full_range_read_options(),
+            IteratorMode::From(target, rocksdb::Direction::Reverse),
+        );
+
+        // SAFETY: We keep the SharedDb alive via Arc, so the iterator remains valid.
+        // The Arc<SharedDb> is stored in IteratorRef and keeps the DB alive.
+        let static_iterator: DBIteratorWithThreadMode<'static, DB> =
+            unsafe { std::mem::transmute(new_iterator) };
+        Some(static_iterator)
+    }
+
+    /// Turns `iterator` back to this iterator's own direction if `iterator_prev`
+    /// reversed it, continuing after the cursor position. Returns `false` if
+    /// the column family is gone.
+    fn resume(
+        &self,
+        iterator: &mut DBIteratorWithThreadMode<'static, DB>,
+        cursor: &mut IteratorCursor,
+    ) -> bool {
+        if !cursor.stepping_back {
+            return true;
+        }
+        match self.step_from(&cursor.position, self.direction()) {
+            Some(new_iterator) => *iterator = new_iterator,
+            None => return false,
+        }
+        cursor.stepping_back = false;
+        true
+    }
+}
+
+/// Takes the snapshot an `IteratorRef` reads from.
+fn iterator_snapshot(shared_db: &SharedDb) -> SnapshotWithThreadMode<'static, DB> {
+    // SAFETY: The IteratorRef stores the snapshot next to the Arc<SharedDb> and
+    // releases it first, so the DB outlives the snapshot.
+    unsafe { std::mem::transmute(shared_db.db.snapshot()) }
+}
+
+impl Drop for IteratorRef {
+    /// Iterators garbage-collected without `iterator_close` still count as closed.
+    fn drop(&mut self) {
+        let still_open = match self.iterator.get_mut() {
+            Ok(iterator) => iterator.is_some(),
+            Err(poisoned) => poisoned.into_inner().is_some(),
+        };
+        if still_open {
+            self.db.open_iterators.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
 /// Snapshot reference wrapper for point-in-time consistent reads.
 /// Stores the snapshot along with a reference to the database to keep it alive.
 pub struct SnapshotRef {
@@ -108,22 +464,333 @@ pub struct SnapshotIteratorRef {
     /// SAFETY: The Arc<SharedDb> keeps the actual database alive for the iterator's lifetime.
     iterator: Mutex<Option<DBIteratorWithThreadMode<'static, DB>>>,
     /// Direct reference to the shared database - keeps the DB alive even after close()
-    /// Prefixed with _ because it's used for Drop semantics, not directly read.
-    _db: Arc<SharedDb>,
+    db: Arc<SharedDb>,
     /// The prefix used for this iterator (for bounds checking)
     prefix: Vec<u8>,
-    /// Column family name for this iterator (kept for potential future debugging)
-    _cf_name: String,
+    /// Column family name for this iterator, to strip `open_with_ttl` stamps
+    cf_name: String,
 }
 
 #[rustler::resource_impl]
 impl Resource for SnapshotIteratorRef {}
 
+/// Column family and key of a transaction's pending write or tracked key.
+type TxnKey = (CfName, Vec<u8>);
+
+/// Numbers transactions, to tell the holders of `TxnLocks` apart.
+static NEXT_TXN_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Buffered state of an open transaction.
+struct TxnState {
+    id: u64,
+    /// Pending writes (`None` is a delete), applied as one batch on commit
+    writes: HashMap<TxnKey, Option<Vec<u8>>>,
+    /// Committed value of each written or locked key when first touched; the
+    /// transaction holds the `TxnLocks` lock of each of these keys
+    tracked: HashMap<TxnKey, Option<Vec<u8>>>,
+}
+
+impl TxnState {
+    fn new() -> Self {
+        TxnState {
+            id: NEXT_TXN_ID.fetch_add(1, Ordering::Relaxed),
+            writes: HashMap::new(),
+            tracked: HashMap::new(),
+        }
+    }
+}
+
+/// How long a transaction waits for a key locked by another transaction.
+const TXN_LOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Keys locked by open transactions, with the id of the transaction holding
+/// each. A lock is held from the first write or `get_for_update` of the key
+/// until the transaction commits, rolls back or is garbage-collected.
+#[derive(Default)]
+struct TxnLocks {
+    held: Mutex<HashMap<TxnKey, u64>>,
+    released: Condvar,
+}
+
+impl TxnLocks {
+    /// Locks a key for transaction `txn_id`, waiting up to `TXN_LOCK_TIMEOUT`
+    /// for another transaction to release it. Returns false on timeout.
+    fn acquire(&self, key: &TxnKey, txn_id: u64) -> bool {
+        let deadline = std::time::Instant::now() + TXN_LOCK_TIMEOUT;
+        let mut held = self.held.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            match held.get(key) {
+                Some(holder) if *holder != txn_id => {}
+                _ => {
+                    held.insert(key.clone(), txn_id);
+                    return true;
+                }
+            }
+            let now = std::time::Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            held = self
+                .released
+                .wait_timeout(held, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    /// Releases the locks transaction `txn_id` holds on `keys`.
+    fn release<'k>(&self, txn_id: u64, keys: impl IntoIterator<Item = &'k TxnKey>) {
+        let mut held = self.held.lock().unwrap_or_else(PoisonError::into_inner);
+        for key in keys {
+            if held.get(key) == Some(&txn_id) {
+                held.remove(key);
+            }
+        }
+        drop(held);
+        self.released.notify_all();
+    }
+}
+
+/// Transaction reference for read-modify-write.
+pub struct TransactionRef {
+    /// Buffered state; None once committed or rolled back
+    state: Mutex<Option<TxnState>>,
+    /// Direct reference to the shared database - keeps the DB alive even after close()
+    db: Arc<SharedDb>,
+}
+
+impl Drop for TransactionRef {
+    fn drop(&mut self) {
+        // A transaction dropped without commit or rollback still holds its locks
+        let state = self.state.get_mut().unwrap_or_else(PoisonError::into_inner);
+        if let Some(state) = state.take() {
+            self.db.txn_locks.release(state.id, state.tracked.keys());
+        }
+    }
+}
+
+#[rustler::resource_impl]
+impl Resource for TransactionRef {}
+
+/// Database opened with `open_transactional`, backed by RocksDB's
+/// `TransactionDB`. Stays alive as long as any transaction references it.
+struct SharedTxnDb {
+    db: TransactionDB,
+}
+
+/// Handle returned by `open_transactional`.
+/// close_transactional() swaps in None; open transactions keep the database
+/// alive until they finish.
+pub struct TxnDbRef {
+    inner: ArcSwapOption<SharedTxnDb>,
+}
+
+#[rustler::resource_impl]
+impl Resource for TxnDbRef {}
+
+/// Transaction from `begin_transaction`.
+pub struct TxnDbTransactionRef {
+    /// The RocksDB transaction; None once committed or rolled back. Declared
+    /// before `db` so it is destroyed first.
+    /// SAFETY: The Arc<SharedTxnDb> keeps the database alive for the transaction's lifetime.
+    txn: Mutex<Option<Transaction<'static, TransactionDB>>>,
+    /// Direct reference to the shared database - keeps the DB alive even after close
+    db: Arc<SharedTxnDb>,
+}
+
+#[rustler::resource_impl]
+impl Resource for TxnDbTransactionRef {}
+
+/// A value read with `get_pinned`, handed to Elixir as a resource binary.
+pub struct PinnedValue {
+    /// The pinned value. Declared before `_db` so it is released first.
+    /// SAFETY: The Arc<SharedDb> keeps the database alive for the slice's lifetime.
+    slice: DBPinnableSlice<'static>,
+    /// Bytes of `slice` before the value, i.e. the `open_with_ttl` stamp
+    offset: usize,
+    /// Direct reference to the shared database - keeps the DB alive even after close()
+    _db: Arc<SharedDb>,
+}
+
+#[rustler::resource_impl]
+impl Resource for PinnedValue {}
+
+impl SharedDb {
+    /// Returns the handle of a column family opened on this instance.
+    fn cf_handle(&self, name: &str) -> Option<&rocksdb::ColumnFamily> {
+        if self.opened_cfs.contains(name) {
+            self.db.cf_handle(name)
+        } else {
+            None
+        }
+    }
+
+    /// Names of the column families opened on this instance: the built-in
+    /// ones in `CF_NAMES` order, then any `open_with_cfs` ones sorted by name.
+    fn opened_cf_names(&self) -> impl Iterator<Item = &str> + '_ {
+        let mut user_cfs: Vec<&str> = self
+            .opened_cfs
+            .iter()
+            .filter(|name| matches!(name, CfName::User(_)))
+            .map(|name| &**name)
+            .collect();
+        user_cfs.sort_unstable();
+        CF_NAMES
+            .iter()
+            .copied()
+            .filter(move |name| self.opened_cfs.contains(*name))
+            .chain(user_cfs)
+    }
+
+    /// Drops every `subscribe_events` subscriber and stops the poller.
+    fn stop_event_pollers(&self) {
+        self.event_feed().close();
+    }
+
+    fn event_feed(&self) -> MutexGuard<'_, EventFeed> {
+        self.events.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Whether values in `cf_name` carry a write-time stamp: `derived` on a
+    /// database opened with `open_with_ttl`.
+    fn stamps(&self, cf_name: &str) -> bool {
+        self.derived_ttl.is_some() && cf_name == DERIVED_CF
+    }
+
+    /// Prefixes a value written to `cf_name` with the current time, as
+    /// `TTL_STAMP_LEN` big-endian bytes of Unix seconds, if the CF expires
+    /// entries. The compaction filter installed by `cf_options_for` reads it.
+    fn stamp<'v>(&self, cf_name: &str, value: &'v [u8]) -> std::borrow::Cow<'v, [u8]> {
+        if !self.stamps(cf_name) {
+            return std::borrow::Cow::Borrowed(value);
+        }
+        let mut stamped = Vec::with_capacity(TTL_STAMP_LEN + value.len());
+        stamped.extend_from_slice(&unix_seconds().to_be_bytes());
+        stamped.extend_from_slice(value);
+        std::borrow::Cow::Owned(stamped)
+    }
+
+    /// Strips the stamp `stamp` added from a value read from `cf_name`.
+    fn unstamp<'v>(&self, cf_name: &str, value: &'v [u8]) -> &'v [u8] {
+        if self.stamps(cf_name) {
+            value.get(TTL_STAMP_LEN..).unwrap_or(value)
+        } else {
+            value
+        }
+    }
+}
+
+impl Drop for SharedDb {
+    fn drop(&mut self) {
+        self.stop_event_pollers();
+        // Release this database's column family names before pruning them
+        self.opened_cfs.clear();
+        self.merge_cfs.clear();
+        forget_unused_cf_names();
+    }
+}
+
 impl DbRef {
-    fn new(db: DB, path: String) -> Self {
+    fn new(
+        db: DB,
+        path: String,
+        merge_cfs: HashSet<CfName>,
+        opened_cfs: HashSet<CfName>,
+        in_memory: bool,
+        derived_ttl: Option<u64>,
+    ) -> Self {
         DbRef {
-            inner: RwLock::new(Some(Arc::new(SharedDb { db, path }))),
+            inner: ArcSwapOption::from_pointee(SharedDb {
+                db,
+                path,
+                in_memory,
+                merge_cfs,
+                opened_cfs,
+                derived_ttl,
+                open_iterators: AtomicUsize::new(0),
+                commit_lock: Mutex::new(()),
+                txn_locks: TxnLocks::default(),
+                events: Arc::default(),
+                #[cfg(feature = "fault_injection")]
+                write_faults: std::sync::atomic::AtomicI64::new(0),
+            }),
+            exclusive: Mutex::new(()),
+            closed: AtomicBool::new(false),
+            read_only: false,
+            secondary: false,
+        }
+    }
+
+    /// Loads the open database, or `None` once it is closed.
+    ///
+    /// A single atomic load, unless an exclusive operation has the database
+    /// checked out: then this waits until it is put back.
+    fn load(&self) -> Guard<Option<Arc<SharedDb>>> {
+        let guard = self.inner.load();
+        if guard.is_some() || self.closed.load(Ordering::Acquire) {
+            return guard;
+        }
+        drop(guard);
+        drop(
+            self.exclusive
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        self.inner.load()
+    }
+
+    /// Takes the database out of `inner` until the checkout is dropped, or
+    /// `None` if it is closed.
+    ///
+    /// NIFs called on the handle meanwhile wait in `load`. Calls that loaded
+    /// the database earlier finish on their own reference.
+    fn check_out(&self) -> Option<CheckedOutDb<'_>> {
+        let lock = self
+            .exclusive
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let shared_db = self.inner.swap(None)?;
+        Some(CheckedOutDb {
+            db_ref: self,
+            shared_db: Some(shared_db),
+            _lock: lock,
+        })
+    }
+
+    /// Marks the database closed and takes this handle's reference to it.
+    fn close(&self) -> Option<Arc<SharedDb>> {
+        let _lock = self
+            .exclusive
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.closed.store(true, Ordering::Release);
+        self.inner.swap(None)
+    }
+}
+
+/// Database taken out of a `DbRef` by `check_out`; put back on drop.
+struct CheckedOutDb<'a> {
+    db_ref: &'a DbRef,
+    shared_db: Option<Arc<SharedDb>>,
+    _lock: MutexGuard<'a, ()>,
+}
+
+impl std::ops::Deref for CheckedOutDb<'_> {
+    type Target = SharedDb;
+
+    fn deref(&self) -> &SharedDb {
+        self.shared_db.as_ref().expect("checked out until dropped")
+    }
+}
+
+impl Drop for CheckedOutDb<'_> {
+    fn drop(&mut self) {
+        // `exclusive_db` detaches the event poller from the database
+        if let Some(shared_db) = &self.shared_db {
+            shared_db.event_feed().attach(shared_db);
         }
+        // Stored before `_lock` is released, so waiting loads find it
+        self.db_ref.inner.store(self.shared_db.take());
     }
 }
 
@@ -134,6 +801,7 @@ mod atoms {
         error,
         not_found,
         already_closed,
+        read_only,
         // Column family atoms
         id2str,
         str2id,
@@ -142,28 +810,153 @@ mod atoms {
         osp,
         derived,
         numeric_range,
+        gspo,
+        gpos,
+        gosp,
         // Error types
         open_failed,
         close_failed,
+        destroy_failed,
+        not_secondary,
+        catch_up_failed,
         invalid_cf,
+        cf_not_opened,
         get_failed,
         put_failed,
         delete_failed,
         batch_failed,
         invalid_operation,
-        // Operation types for batch - these map to Elixir atoms :put and :delete
+        // Operation types for batch - these map to Elixir atoms :put, :delete and :delete_range
         put,
         delete,
+        delete_range,
         // Iterator atoms
         iterator_end,
         iterator_failed,
         iterator_closed,
+        // Iterator exhaustion reasons
+        prefix_boundary,
+        cf_end,
+        invalid_range,
+        // Batched iteration atoms; `end` is a Rust keyword
+        more,
+        iterator_exhausted = "end",
+        // Iterator direction atoms
+        forward,
+        reverse,
+        invalid_direction,
+        // Range iterator option atoms
+        inclusive_end,
+        // Prefix iterator option atoms
+        direction,
+        upper_bound,
         // Snapshot atoms
         snapshot_released,
         // Flush atoms
         flush_failed,
+        // Backup atoms
+        backup_failed,
+        restore_failed,
+        latest,
+        checkpoint_failed,
         // SetOptions atoms
         set_options_failed,
+        options_failed,
+        in_memory,
+        // Export atoms
+        export_failed,
+        invalid_id_width,
+        malformed_key,
+        unresolved_id,
+        // Open option atoms
+        invalid_option,
+        // Write option atoms
+        sync,
+        disable_wal,
+        atomic_flush,
+        block_cache_bytes,
+        write_buffer_bytes,
+        max_open_files,
+        compression,
+        none,
+        snappy,
+        lz4,
+        zstd,
+        cf_options,
+        bloom_filter_bits,
+        memtable,
+        prefix_length,
+        skiplist,
+        hash_skiplist,
+        vector,
+        prefix_extractor_required,
+        merge_operator,
+        int64_add,
+        index_type,
+        binary_search,
+        hash_search,
+        two_level_index_search,
+        format_version,
+        enable_user_timestamps,
+        // Schema version atoms
+        schema_mismatch,
+        // Conditional write atoms
+        written,
+        exists,
+        // Swap atoms
+        same_cf,
+        // TTL atoms
+        ttl_cf,
+        // SST ingestion atoms
+        ingest_failed,
+        move_files,
+        // Column family management atoms
+        create_cf_failed,
+        drop_cf_failed,
+        protected_cf,
+        db_in_use,
+        // Merge atoms
+        no_merge_operator,
+        merge_failed,
+        invalid_operand,
+        // Health check atoms
+        health_check_failed,
+        // Compaction atoms
+        running_compactions,
+        pending_compaction_bytes,
+        compaction_failed,
+        compact_failed,
+        write_stopped,
+        delayed_write_rate,
+        max_l0_files,
+        // Size estimation atoms
+        estimate_failed,
+        // Property atoms
+        unknown_property,
+        // Storage event atoms
+        rocksdb_event,
+        flush_completed,
+        compaction_completed,
+        level,
+        files,
+        bytes,
+        // Aggregate atoms
+        op,
+        count,
+        sum_u64,
+        bad_value_width,
+        overflow,
+        // Change feed atoms
+        wal_failed,
+        added,
+        removed,
+        // User timestamp atoms
+        timestamps_unsupported,
+        // Transaction atoms
+        busy,
+        transaction_closed,
+        commit_failed,
+        rollback_failed,
     }
 }
 
@@ -184,11 +977,113 @@ fn cf_atom_to_name(cf_atom: rustler::Atom) -> Option<&'static str> {
         Some("derived")
     } else if cf_atom == atoms::numeric_range() {
         Some("numeric_range")
+    } else if cf_atom == atoms::gspo() {
+        Some("gspo")
+    } else if cf_atom == atoms::gpos() {
+        Some("gpos")
+    } else if cf_atom == atoms::gosp() {
+        Some("gosp")
     } else {
         None
     }
 }
 
+/// Name of a column family: one of `CF_NAMES`, or a caller-named one shared
+/// with the `USER_CF_NAMES` registry.
+#[derive(Clone, Debug)]
+enum CfName {
+    Builtin(&'static str),
+    User(Arc<str>),
+}
+
+impl std::ops::Deref for CfName {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            CfName::Builtin(name) => name,
+            CfName::User(name) => name,
+        }
+    }
+}
+
+// Equality and hashing go by the name alone, so sets of `CfName` can be
+// queried with a `&str`
+impl PartialEq for CfName {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for CfName {}
+
+impl std::hash::Hash for CfName {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl std::borrow::Borrow<str> for CfName {
+    fn borrow(&self) -> &str {
+        self
+    }
+}
+
+impl std::fmt::Display for CfName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self)
+    }
+}
+
+/// Names of the column families opened by `open_with_cfs` or
+/// `create_column_family` on a database that still uses them. Each distinct
+/// name is stored once; `forget_unused_cf_names` drops the ones nothing else
+/// references any more.
+static USER_CF_NAMES: OnceLock<Mutex<HashSet<Arc<str>>>> = OnceLock::new();
+
+fn user_cf_names() -> MutexGuard<'static, HashSet<Arc<str>>> {
+    USER_CF_NAMES
+        .get_or_init(|| Mutex::new(HashSet::new()))
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Returns the shared copy of a column family name, registering it if new.
+fn intern_cf_name(name: &str) -> CfName {
+    if let Some(known) = CF_NAMES.iter().find(|known| **known == name) {
+        return CfName::Builtin(known);
+    }
+    let mut names = user_cf_names();
+    match names.get(name) {
+        Some(interned) => CfName::User(interned.clone()),
+        None => {
+            let interned: Arc<str> = Arc::from(name);
+            names.insert(interned.clone());
+            CfName::User(interned)
+        }
+    }
+}
+
+/// Forgets the user column family names only the registry still holds, i.e.
+/// the ones every database has dropped or been closed with.
+fn forget_unused_cf_names() {
+    user_cf_names().retain(|name| Arc::strong_count(name) > 1);
+}
+
+/// Resolves a column family given as an atom (the built-in ones) or as a
+/// name binary (built-in or opened through `open_with_cfs`).
+/// Returns None if no open database uses such a column family.
+fn cf_term_to_name(cf: Term) -> Option<CfName> {
+    if let Ok(atom) = cf.decode::<rustler::Atom>() {
+        return cf_atom_to_name(atom).map(CfName::Builtin);
+    }
+    let name = std::str::from_utf8(cf.decode::<Binary>().ok()?.as_slice()).ok()?;
+    if let Some(known) = CF_NAMES.iter().find(|known| **known == name) {
+        return Some(CfName::Builtin(known));
+    }
+    user_cf_names().get(name).cloned().map(CfName::User)
+}
+
 /// Placeholder function to verify NIF loads correctly.
 /// Returns the string "rocksdb_nif" to confirm the NIF is operational.
 #[rustler::nif]
@@ -209,1404 +1104,8228 @@ fn nif_loaded() -> &'static str {
 /// * `{:error, reason}` on failure
 #[rustler::nif(schedule = "DirtyCpu")]
 fn open(env: Env, path: String) -> NifResult<Term> {
-    let mut opts = Options::default();
-    opts.create_if_missing(true);
-    opts.create_missing_column_families(true);
-
-    // Create column family descriptors with tuned settings per access pattern
-    let cf_descriptors: Vec<ColumnFamilyDescriptor> = CF_NAMES
-        .iter()
-        .map(|name| {
-            let mut cf_opts = Options::default();
-            let mut block_opts = BlockBasedOptions::default();
-
-            // Configure based on column family type
-            if DICTIONARY_CFS.contains(name) {
-                // Dictionary CFs: Point lookups, high read frequency
-                // - 14 bits/key bloom filter (~0.01% FPR)
-                // - 2KB blocks (small for point lookups)
-                // - Full-key bloom filter (not prefix-based)
-                block_opts.set_bloom_filter(DICTIONARY_BLOOM_BITS as f64, false);
-                block_opts.set_block_size(DICTIONARY_BLOCK_SIZE);
-                // Cache index and filter blocks for fast point lookups
-                block_opts.set_cache_index_and_filter_blocks(true);
-                block_opts.set_pin_l0_filter_and_index_blocks_in_cache(true);
-                // Optimize filters for hits (dictionary lookups usually succeed)
-                block_opts.set_optimize_filters_for_memory(true);
-            } else if INDEX_CFS.contains(name) {
-                // Index CFs: Prefix scans, range queries
-                // - 12 bits/key bloom filter (~0.09% FPR)
-                // - 8KB blocks (balanced for prefix scans)
-                // - Prefix bloom via SliceTransform
-                block_opts.set_bloom_filter(INDEX_BLOOM_BITS as f64, false);
-                block_opts.set_block_size(INDEX_BLOCK_SIZE);
-                // Cache index and filter blocks
-                block_opts.set_cache_index_and_filter_blocks(true);
-                block_opts.set_pin_l0_filter_and_index_blocks_in_cache(true);
-                // Configure prefix extractor for index column families
-                cf_opts.set_prefix_extractor(SliceTransform::create_fixed_prefix(PREFIX_LENGTH));
-                // Enable prefix bloom filter in memtable for faster lookups
-                cf_opts.set_memtable_prefix_bloom_ratio(0.1);
-            } else if *name == DERIVED_CF {
-                // Derived CF: Bulk writes, sequential reads
-                // - No bloom filter (sequential access doesn't benefit)
-                // - 32KB blocks (large for sequential reads)
-                block_opts.set_block_size(DERIVED_BLOCK_SIZE);
-                // Don't cache filter/index blocks (not used much)
-                block_opts.set_cache_index_and_filter_blocks(false);
-            }
-
-            // Apply block-based options to column family
-            cf_opts.set_block_based_table_factory(&block_opts);
-
-            // Configure compression: LZ4 for all CFs (fast, reasonable ratio)
-            // L0 has no compression for write speed, other levels use LZ4
-            cf_opts.set_compression_type(rocksdb::DBCompressionType::Lz4);
-            cf_opts.set_compression_per_level(&[
-                rocksdb::DBCompressionType::None,  // L0: no compression (short-lived)
-                rocksdb::DBCompressionType::Lz4,   // L1
-                rocksdb::DBCompressionType::Lz4,   // L2
-                rocksdb::DBCompressionType::Lz4,   // L3
-                rocksdb::DBCompressionType::Lz4,   // L4
-                rocksdb::DBCompressionType::Lz4,   // L5
-                rocksdb::DBCompressionType::Lz4,   // L6
-            ]);
-
-            ColumnFamilyDescriptor::new(*name, cf_opts)
-        })
-        .collect();
-
-    match DB::open_cf_descriptors(&opts, &path, cf_descriptors) {
-        Ok(db) => {
-            let db_ref = ResourceArc::new(DbRef::new(db, path));
-            Ok((atoms::ok(), db_ref).encode(env))
-        }
-        Err(e) => Ok((atoms::error(), (atoms::open_failed(), e.to_string())).encode(env)),
-    }
+    Ok(open_with_cf_options(
+        env,
+        path,
+        &HashMap::new(),
+        &DbOpenOptions::default(),
+    ))
 }
 
-/// Closes the database and releases the main reference.
+/// Opens a RocksDB database with per-column-family option overrides.
 ///
-/// After calling close, the database handle is no longer valid for new operations.
-/// Subsequent operations on DbRef will return `{:error, :already_closed}`.
+/// Accepts the same defaults as `open/1` and lets callers override options for
+/// individual column families. Supported per-CF options:
 ///
-/// IMPORTANT: Existing iterators and snapshots will continue to work after close()
-/// because they hold their own Arc<SharedDb> reference. The actual database is only
-/// dropped when the last reference (including any active iterators/snapshots) is dropped.
-/// This prevents use-after-free bugs.
+/// - `{:memtable, kind}` - Memtable representation:
+///   - `:skiplist` (default) - Ordered, supports concurrent writes and efficient
+///     range scans. Best general-purpose choice.
+///   - `:hash_skiplist` - Hash buckets keyed by prefix, each holding a skiplist.
+///     Faster point lookups within a prefix, but scans across prefixes are slow
+///     and it requires a prefix extractor.
+///   - `:vector` - Append-only vector sorted on flush. Very fast bulk inserts,
+///     but reads from the memtable are slow; suits write-once bulk loads.
+/// - `{:prefix_length, n}` - Fixed-length prefix extractor for the CF. Index CFs
+///   already use an 8-byte prefix.
+/// - `{:merge_operator, :int64_add}` - Installs an associative merge operator that
+///   treats values as little-endian i64 and sums merge operands. Required for `merge`.
+/// - `{:index_type, kind}` - SST index block layout:
+///   - `:binary_search` (default) - Compact index, binary-searched. Right for the
+///     index CFs (`spo`, `pos`, `osp`), whose range scans seek once and then read
+///     sequentially.
+///   - `:hash_search` - Adds a hash lookup on the key prefix in front of the
+///     binary search. Speeds up point lookups in prefix-heavy CFs but requires a
+///     prefix extractor.
+///   - `:two_level_index_search` - Partitioned index loaded on demand. Keeps
+///     memory bounded for large point-lookup CFs such as `str2id` and `id2str`.
+/// - `{:format_version, n}` - Block-based table format version (0..=6). Newer
+///   versions encode indexes and filters more compactly; files written with a
+///   newer version can't be read by older RocksDB releases.
+/// - `{:enable_user_timestamps, true}` - `derived` only. Reserved for RocksDB
+///   user timestamps; see `put_ts` for why it currently returns
+///   `{:error, :timestamps_unsupported}`.
+///
+/// Non-skiplist memtables do not support concurrent memtable writes, so that
+/// DB option is disabled when any CF selects one.
+///
+/// Database-wide options are given as top-level `{option, value}` entries:
+///
+/// - `{:atomic_flush, true}` - Flushes the memtables of all column families
+///   together, both for automatic flushes and for `sync_dir`. Without it each CF
+///   flushes independently, so a crash can leave `spo` flushed but `pos` not.
+///   With the WAL enabled (all current writes) recovery replays the missing
+///   writes either way; atomic flush is what keeps the indexes consistent for
+///   writes made with the WAL disabled, which are only recovered from SST files.
 ///
 /// # Arguments
-/// * `db_ref` - The database reference to close
+/// * `path` - Path to the database directory
+/// * `cf_options` - List of `{cf, [{option, value}]}` tuples and database-wide
+///   `{option, value}` tuples
 ///
 /// # Returns
-/// * `:ok` on success
-/// * `{:error, :already_closed}` if already closed
-#[rustler::nif(schedule = "DirtyCpu")]
-fn close(env: Env, db_ref: ResourceArc<DbRef>) -> NifResult<Term> {
-    let mut guard = db_ref
-        .inner
-        .write()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+/// * `{:ok, db_ref}` on success
+/// * `{:error, {:invalid_cf, cf}}` if a column family is unknown
+/// * `{:error, {:invalid_option, option}}` if an option or value is not recognized
+/// * `{:error, :prefix_extractor_required}` if `:hash_skiplist` or `:hash_search` is
+///   selected for a CF without a prefix extractor
+/// * `{:error, :timestamps_unsupported}` if user timestamps are requested
+/// * `{:error, {:open_failed, reason}}` on failure
+#[rustler::nif(name = "open", schedule = "DirtyCpu")]
+fn open_with_cf_overrides<'a>(
+    env: Env<'a>,
+    path: String,
+    cf_options: Vec<(rustler::Atom, Term<'a>)>,
+) -> NifResult<Term<'a>> {
+    let mut overrides: HashMap<&'static str, CfOpenOptions> = HashMap::new();
+    let mut db_options = DbOpenOptions::default();
+
+    for (cf, value) in cf_options {
+        if cf == atoms::atomic_flush() {
+            match value.decode::<bool>() {
+                Ok(enabled) => db_options.atomic_flush = enabled,
+                Err(_) => return Ok((atoms::error(), (atoms::invalid_option(), cf)).encode(env)),
+            }
+            continue;
+        }
 
-    if guard.is_none() {
-        return Ok((atoms::error(), atoms::already_closed()).encode(env));
+        let cf_name = match cf_atom_to_name(cf) {
+            Some(name) => name,
+            None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+        };
+        let options: Vec<(rustler::Atom, Term<'a>)> = value.decode()?;
+
+        let entry = overrides.entry(cf_name).or_default();
+        for (key, value) in options {
+            if key == atoms::memtable() {
+                match value
+                    .decode::<rustler::Atom>()
+                    .ok()
+                    .and_then(memtable_kind_from_atom)
+                {
+                    Some(kind) => entry.memtable = Some(kind),
+                    None => return Ok((atoms::error(), (atoms::invalid_option(), key)).encode(env)),
+                }
+            } else if key == atoms::merge_operator() {
+                match value.decode::<rustler::Atom>() {
+                    Ok(op) if op == atoms::int64_add() => {
+                        entry.merge_operator = Some(MergeOperatorKind::Int64Add)
+                    }
+                    _ => return Ok((atoms::error(), (atoms::invalid_option(), key)).encode(env)),
+                }
+            } else if key == atoms::prefix_length() {
+                match value.decode::<usize>() {
+                    Ok(len) if len > 0 => entry.prefix_length = Some(len),
+                    _ => return Ok((atoms::error(), (atoms::invalid_option(), key)).encode(env)),
+                }
+            } else if key == atoms::index_type() {
+                match value
+                    .decode::<rustler::Atom>()
+                    .ok()
+                    .and_then(index_kind_from_atom)
+                {
+                    Some(kind) => entry.index_type = Some(kind),
+                    None => return Ok((atoms::error(), (atoms::invalid_option(), key)).encode(env)),
+                }
+            } else if key == atoms::format_version() {
+                match value.decode::<i32>() {
+                    Ok(version) if (0..=MAX_FORMAT_VERSION).contains(&version) => {
+                        entry.format_version = Some(version)
+                    }
+                    _ => return Ok((atoms::error(), (atoms::invalid_option(), key)).encode(env)),
+                }
+            } else if key == atoms::enable_user_timestamps() {
+                match value.decode::<bool>() {
+                    // See `put_ts`: the linked RocksDB binding has no timestamp support
+                    Ok(true) if cf_name == DERIVED_CF => {
+                        return Ok((atoms::error(), atoms::timestamps_unsupported()).encode(env))
+                    }
+                    Ok(false) => {}
+                    _ => return Ok((atoms::error(), (atoms::invalid_option(), key)).encode(env)),
+                }
+            } else {
+                return Ok((atoms::error(), (atoms::invalid_option(), key)).encode(env));
+            }
+        }
+
+        let has_prefix_extractor = INDEX_CFS.contains(&cf_name) || entry.prefix_length.is_some();
+        let needs_prefix_extractor = entry.memtable == Some(MemtableKind::HashSkipList)
+            || entry.index_type == Some(IndexKind::Hash);
+        if needs_prefix_extractor && !has_prefix_extractor {
+            return Ok((atoms::error(), atoms::prefix_extractor_required()).encode(env));
+        }
     }
 
-    // Remove our reference. The actual DB may still be alive if iterators/snapshots
-    // hold Arc<SharedDb> references. The DB is only dropped when the last Arc is dropped.
-    *guard = None;
-    Ok(atoms::ok().encode(env))
+    Ok(open_with_cf_options(env, path, &overrides, &db_options))
 }
 
-/// Returns the path of the database.
+/// Opens a RocksDB database with tunable resource settings.
+///
+/// `opts` is a map; every key is optional and unknown keys are ignored so the
+/// set of settings can grow without breaking callers:
+///
+/// - `block_cache_bytes` - Capacity of one LRU block cache shared by all column
+///   families, instead of RocksDB's default 8MB cache per column family.
+/// - `write_buffer_bytes` - Memtable size per column family before it is flushed.
+/// - `max_open_files` - Limit on open SST file handles (`-1` keeps all open).
+/// - `compression` - `:none`, `:snappy`, `:lz4` (default) or `:zstd`, applied to
+///   every level except L0, which stays uncompressed for write speed.
+/// - `cf_options` - Map from CF atom to a map of settings for that CF alone:
+///   `compression` (as above, overriding the database-wide value) and
+///   `bloom_filter_bits` (bits per key, `0` disables the filter). CFs not in the
+///   map keep their defaults: 14 bits for `id2str`/`str2id`, 12 bits for the
+///   index CFs and no filter for `derived`. Unknown keys are ignored here too.
+///
+/// All other settings match `open/1`, which is equivalent to passing `%{}`.
+///
+/// # Arguments
+/// * `path` - Path to the database directory
+/// * `opts` - Map of settings
+///
+/// # Returns
+/// * `{:ok, db_ref}` on success
+/// * `{:error, {:invalid_option, key}}` if a known key has an invalid value
+/// * `{:error, {:invalid_cf, cf}}` if `cf_options` names an unknown column family
+/// * `{:error, {:open_failed, reason}}` on failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn open_with_options<'a>(env: Env<'a>, path: String, opts: Term<'a>) -> NifResult<Term<'a>> {
+    let mut db_options = DbOpenOptions::default();
+    let mut overrides: HashMap<&'static str, CfOpenOptions> = HashMap::new();
+
+    for (key, value) in opts.decode::<MapIterator>()? {
+        let key = match key.decode::<rustler::Atom>() {
+            Ok(key) => key,
+            Err(_) => continue,
+        };
+
+        let valid = if key == atoms::block_cache_bytes() {
+            value
+                .decode::<usize>()
+                .map(|bytes| db_options.block_cache_bytes = Some(bytes))
+                .is_ok()
+        } else if key == atoms::write_buffer_bytes() {
+            match value.decode::<usize>() {
+                Ok(bytes) if bytes > 0 => {
+                    db_options.write_buffer_bytes = Some(bytes);
+                    true
+                }
+                _ => false,
+            }
+        } else if key == atoms::max_open_files() {
+            value
+                .decode::<i32>()
+                .map(|files| db_options.max_open_files = Some(files))
+                .is_ok()
+        } else if key == atoms::compression() {
+            match value
+                .decode::<rustler::Atom>()
+                .ok()
+                .and_then(compression_from_atom)
+            {
+                Some(compression) => {
+                    db_options.compression = Some(compression);
+                    true
+                }
+                None => false,
+            }
+        } else if key == atoms::cf_options() {
+            let cf_maps = match value.decode::<MapIterator>() {
+                Ok(cf_maps) => cf_maps,
+                Err(_) => return Ok((atoms::error(), (atoms::invalid_option(), key)).encode(env)),
+            };
+            for (cf, cf_settings) in cf_maps {
+                let cf_name = match cf.decode::<rustler::Atom>().ok().and_then(cf_atom_to_name) {
+                    Some(name) => name,
+                    None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+                };
+                let entry = overrides.entry(cf_name).or_default();
+                if let Err(bad_key) = apply_cf_settings(entry, cf, cf_settings) {
+                    return Ok((atoms::error(), (atoms::invalid_option(), bad_key)).encode(env));
+                }
+            }
+            true
+        } else {
+            true
+        };
+
+        if !valid {
+            return Ok((atoms::error(), (atoms::invalid_option(), key)).encode(env));
+        }
+    }
+
+    Ok(open_with_cf_options(env, path, &overrides, &db_options))
+}
+
+/// Applies one column family's `cf_options` map from `open_with_options`.
+/// Unknown keys are ignored. Returns the offending key for invalid values, or
+/// `cf` if the settings aren't a map.
+fn apply_cf_settings<'a>(
+    entry: &mut CfOpenOptions,
+    cf: Term<'a>,
+    settings: Term<'a>,
+) -> Result<(), Term<'a>> {
+    let settings = settings.decode::<MapIterator>().map_err(|_| cf)?;
+
+    for (key, value) in settings {
+        let key_atom = match key.decode::<rustler::Atom>() {
+            Ok(atom) => atom,
+            Err(_) => continue,
+        };
+
+        if key_atom == atoms::compression() {
+            let compression = value
+                .decode::<rustler::Atom>()
+                .ok()
+                .and_then(compression_from_atom);
+            entry.compression = Some(compression.ok_or(key)?);
+        } else if key_atom == atoms::bloom_filter_bits() {
+            match value.decode::<i32>() {
+                Ok(bits) if bits >= 0 => entry.bloom_filter_bits = Some(bits),
+                _ => return Err(key),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn compression_from_atom(atom: rustler::Atom) -> Option<rocksdb::DBCompressionType> {
+    if atom == atoms::none() {
+        Some(rocksdb::DBCompressionType::None)
+    } else if atom == atoms::snappy() {
+        Some(rocksdb::DBCompressionType::Snappy)
+    } else if atom == atoms::lz4() {
+        Some(rocksdb::DBCompressionType::Lz4)
+    } else if atom == atoms::zstd() {
+        Some(rocksdb::DBCompressionType::Zstd)
+    } else {
+        None
+    }
+}
+
+/// Reserved key in the `default` CF holding the on-disk schema version. The
+/// triple-keyed CFs are scanned as triples, so it must not live in one of them.
+const SCHEMA_VERSION_KEY: &[u8] = b"__schema_version__";
+
+/// Opens a database and verifies its on-disk schema version.
+///
+/// Reads the reserved `__schema_version__` key from the `default` CF. A fresh
+/// database (key absent) is stamped with `expected_version`; an existing one
+/// must carry the same version, otherwise the database is closed again and
+/// an error is returned. This catches accidental use of an old on-disk format.
+///
+/// The version is stored as an 8-byte big-endian integer.
+///
+/// # Arguments
+/// * `path` - Path to the database directory
+/// * `expected_version` - Schema version the caller understands
+///
+/// # Returns
+/// * `{:ok, db_ref}` on success
+/// * `{:error, {:schema_mismatch, found, expected}}` if versions differ (`found`
+///   is the raw binary if the stored value is not a valid version)
+/// * `{:error, {:open_failed, reason}}` on failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn open_versioned(env: Env, path: String, expected_version: u64) -> NifResult<Term> {
+    let db_ref = match open_db_ref(path, &HashMap::new(), &DbOpenOptions::default()) {
+        Ok(db_ref) => db_ref,
+        Err(e) => return Ok((atoms::error(), (atoms::open_failed(), e.to_string())).encode(env)),
+    };
+
+    let check = {
+        let guard = db_ref.load();
+        let shared_db = guard.as_ref().expect("freshly opened database");
+
+        match shared_db.db.get(SCHEMA_VERSION_KEY) {
+            Ok(None) => shared_db
+                .db
+                .put(SCHEMA_VERSION_KEY, expected_version.to_be_bytes())
+                .map_err(|e| (atoms::open_failed(), e.to_string()).encode(env)),
+            Ok(Some(stored)) => match <[u8; 8]>::try_from(stored.as_slice()) {
+                Ok(bytes) if u64::from_be_bytes(bytes) == expected_version => Ok(()),
+                Ok(bytes) => Err((
+                    atoms::schema_mismatch(),
+                    u64::from_be_bytes(bytes),
+                    expected_version,
+                )
+                    .encode(env)),
+                Err(_) => {
+                    let mut found = NewBinary::new(env, stored.len());
+                    found.as_mut_slice().copy_from_slice(&stored);
+                    Err((
+                        atoms::schema_mismatch(),
+                        Binary::from(found),
+                        expected_version,
+                    )
+                        .encode(env))
+                }
+            },
+            Err(e) => Err((atoms::open_failed(), e.to_string()).encode(env)),
+        }
+    };
+
+    match check {
+        Ok(()) => Ok((atoms::ok(), ResourceArc::new(db_ref)).encode(env)),
+        // Dropping db_ref here closes the database again
+        Err(reason) => Ok((atoms::error(), reason).encode(env)),
+    }
+}
+
+/// Opens a database exposing only the listed column families.
+///
+/// Meant for lightweight tools that touch a few CFs, such as a dictionary
+/// inspector that only needs `id2str` and `str2id`. Only the listed CFs get
+/// the tuned options (bloom filters, block settings) of `open/1`; operations
+/// against any other known CF return `{:error, {:cf_not_opened, cf}}`.
+///
+/// RocksDB refuses a read-write open unless every column family on disk is
+/// opened. On-disk CFs outside the requested set, including ones this NIF
+/// doesn't know about, are therefore opened with plain default options and
+/// kept hidden. A fresh database is created with just the requested CFs;
+/// a later `open/1` creates the rest.
+///
+/// # Arguments
+/// * `path` - Path to the database directory
+/// * `cfs` - List of column family atoms to expose
+///
+/// # Returns
+/// * `{:ok, db_ref}` on success
+/// * `{:error, {:invalid_cf, cf}}` if a column family is unknown
+/// * `{:error, {:open_failed, reason}}` on failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn open_cfs(env: Env, path: String, cfs: Vec<rustler::Atom>) -> NifResult<Term> {
+    let mut requested: HashSet<&'static str> = HashSet::new();
+    for cf in cfs {
+        match cf_atom_to_name(cf) {
+            Some(name) => {
+                requested.insert(name);
+            }
+            None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+        }
+    }
+
+    match open_subset_db_ref(path, requested) {
+        Ok(db_ref) => Ok((atoms::ok(), ResourceArc::new(db_ref)).encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::open_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// Opens the database with caller-named column families next to the built-in ones.
+///
+/// Every name in `cf_names` is created if missing and opened with default
+/// options alongside the built-in column families. Operations that take a
+/// column family accept these by name binary (for example `"tenant_a"`),
+/// while the built-in ones keep working with their atoms. A binary naming a
+/// column family that no open database uses returns
+/// `{:error, {:invalid_cf, name}}`; one opened only on another open handle
+/// returns `{:error, {:cf_not_opened, name}}`.
+///
+/// # Arguments
+/// * `path` - Path to the database directory
+/// * `cf_names` - List of extra column family names
+///
+/// # Returns
+/// * `{:ok, db_ref}` on success
+/// * `{:error, {:invalid_cf, name}}` if a name is empty or is `"default"`
+/// * `{:error, {:open_failed, reason}}` on failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn open_with_cfs(env: Env, path: String, cf_names: Vec<String>) -> NifResult<Term> {
+    let mut user_cfs: Vec<CfName> = Vec::new();
+    for name in &cf_names {
+        if name.is_empty() || name == rocksdb::DEFAULT_COLUMN_FAMILY_NAME {
+            return Ok((atoms::error(), (atoms::invalid_cf(), name)).encode(env));
+        }
+        let name = intern_cf_name(name);
+        if matches!(name, CfName::User(_)) && !user_cfs.contains(&name) {
+            user_cfs.push(name);
+        }
+    }
+
+    let db_options = DbOpenOptions {
+        user_cfs,
+        ..Default::default()
+    };
+    Ok(open_with_cf_options(
+        env,
+        path,
+        &HashMap::new(),
+        &db_options,
+    ))
+}
+
+/// Opens a database whose `derived` entries expire after `ttl_seconds`.
+///
+/// rocksdb 0.22's `DB::open_cf_descriptors_with_ttl` gives every column
+/// family the same TTL, which would expire the triple indexes and the
+/// dictionary too. Instead, values written to `derived` through this handle
+/// are prefixed with their write time (8 bytes, big-endian Unix seconds) and
+/// a compaction filter on `derived` drops entries older than the TTL. Reads
+/// strip the prefix again; all other column families are unchanged and never
+/// expire.
+///
+/// Expiry is lazy: entries are only dropped when compaction rewrites the SST
+/// file holding them, so reads can still return expired values until then.
+/// `compact_range` forces it.
+///
+/// As with RocksDB's own TTL databases, always reopen such a database with
+/// `open_with_ttl`: other opens return the stamped values of `derived` as
+/// they are, and values written by them carry no stamp and never expire.
+/// `derived` has no merge operator on these handles, since merge operands
+/// can't carry a stamp, so `merge` on it returns `{:error, :no_merge_operator}`.
+///
+/// # Arguments
+/// * `path` - Path to the database directory
+/// * `ttl_seconds` - Age in seconds after which `derived` entries may expire
+///
+/// # Returns
+/// * `{:ok, db_ref}` on success
+/// * `{:error, {:invalid_option, ttl_seconds}}` if `ttl_seconds` is 0
+/// * `{:error, {:open_failed, reason}}` on failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn open_with_ttl(env: Env, path: String, ttl_seconds: u64) -> NifResult<Term> {
+    if ttl_seconds == 0 {
+        return Ok((atoms::error(), (atoms::invalid_option(), ttl_seconds)).encode(env));
+    }
+
+    let db_options = DbOpenOptions {
+        derived_ttl: Some(ttl_seconds),
+        ..Default::default()
+    };
+    Ok(open_with_cf_options(
+        env,
+        path,
+        &HashMap::new(),
+        &db_options,
+    ))
+}
+
+/// Path reported by `get_path` for in-memory databases.
+const MEMORY_PATH: &str = ":memory:";
+
+/// Directory in-memory databases are opened at. The mem env only supports
+/// absolute paths, and the bindings create the directory on the real
+/// filesystem before opening, so it lives under the temp dir and stays empty.
+fn memory_db_dir() -> std::path::PathBuf {
+    std::env::temp_dir().join("triple_store_memory")
+}
+
+/// Opens a database that lives entirely in memory.
+///
+/// Uses RocksDB's in-memory `Env`, so SST files, the WAL and the manifest never
+/// touch disk (only an empty placeholder directory under the system temp dir is
+/// created), and everything is discarded when the database is dropped. Every
+/// column family gets the same tuned options as `open/1`, and all read, write,
+/// iterator and snapshot operations work unchanged. Each call returns an
+/// independent, empty database. Meant for tests.
+///
+/// `get_path` returns `":memory:"`. `sync_dir` skips the directory fsync, and
+/// `effective_options`, which reads the OPTIONS file from the database
+/// directory, returns `{:error, :in_memory}`.
+///
+/// # Returns
+/// * `{:ok, db_ref}` on success
+/// * `{:error, {:open_failed, reason}}` on failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn open_memory(env: Env) -> NifResult<Term> {
+    let db_options = DbOpenOptions {
+        in_memory: true,
+        ..Default::default()
+    };
+    Ok(open_with_cf_options(
+        env,
+        MEMORY_PATH.to_string(),
+        &HashMap::new(),
+        &db_options,
+    ))
+}
+
+/// Opens an existing database as a read-only instance.
+///
+/// Uses RocksDB's read-only mode (`DB::open_cf_descriptors_read_only`) with
+/// the same tuned options as `open/1`, so a secondary process can read the
+/// store while the primary keeps writing. Every built-in column family present
+/// on disk is opened; ones the database predates (such as the quad indexes of
+/// an older store) can't be created read-only and return
+/// `{:error, {:cf_not_opened, cf}}`.
+/// The instance sees the data as of the time it was opened. Write NIFs
+/// (`put`, `delete`, `delete_range`, the batches, `merge`, `put_quad`,
+/// `swap_cf_contents`, `transaction_begin`, `ingest_external_file` and the
+/// column family management NIFs) on the returned handle return
+/// `{:error, :read_only}` without touching the database.
+///
+/// # Arguments
+/// * `path` - Path to an existing database directory
+///
+/// # Returns
+/// * `{:ok, db_ref}` on success
+/// * `{:error, {:open_failed, reason}}` if the database doesn't exist or can't be opened
+#[rustler::nif(schedule = "DirtyCpu")]
+fn open_read_only(env: Env, path: String) -> NifResult<Term> {
+    let db_options = DbOpenOptions {
+        read_only: true,
+        ..Default::default()
+    };
+    Ok(open_with_cf_options(
+        env,
+        path,
+        &HashMap::new(),
+        &db_options,
+    ))
+}
+
+/// Opens a secondary instance that follows a primary database.
+///
+/// Uses RocksDB's secondary mode (`DB::open_cf_descriptors_as_secondary`).
+/// Unlike `open_read_only`, the instance doesn't need the primary's files to
+/// stay put: it can catch up with the primary while it keeps writing and
+/// compacting, which suits a reporting node sharing the directory. It takes
+/// no lock on `primary_path`, and keeps its own info log under
+/// `secondary_path`, which must differ from the primary's directory.
+///
+/// The instance sees the data as of the open until `try_catch_up_with_primary`
+/// replays the primary's newer MANIFEST and WAL entries. Column families get
+/// the same tuned options as `open/1`; as with `open_read_only`, only those
+/// present on disk are opened. The same write NIFs as on an `open_read_only`
+/// handle return `{:error, :read_only}`.
+///
+/// # Arguments
+/// * `primary_path` - Path of the primary database directory
+/// * `secondary_path` - Directory for the secondary instance's own files
+///
+/// # Returns
+/// * `{:ok, db_ref}` on success
+/// * `{:error, {:open_failed, reason}}` if the primary doesn't exist or can't be opened
+#[rustler::nif(schedule = "DirtyCpu")]
+fn open_secondary(env: Env, primary_path: String, secondary_path: String) -> NifResult<Term> {
+    let db_options = DbOpenOptions {
+        secondary_path: Some(secondary_path),
+        ..Default::default()
+    };
+    Ok(open_with_cf_options(
+        env,
+        primary_path,
+        &HashMap::new(),
+        &db_options,
+    ))
+}
+
+/// Pulls the primary's newer writes into a secondary instance.
+///
+/// Replays the primary's MANIFEST and WAL changes since the open or the last
+/// catch-up, so reads on the handle see them afterwards. Iterators and
+/// snapshots created earlier keep their old view. Call it on demand or on a
+/// timer; each call only reads what changed.
+///
+/// # Arguments
+/// * `db_ref` - Handle from `open_secondary`
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, :not_secondary}` if the handle wasn't opened with `open_secondary`
+/// * `{:error, {:catch_up_failed, reason}}` if RocksDB can't replay the primary's changes
+#[rustler::nif(schedule = "DirtyCpu")]
+fn try_catch_up_with_primary(env: Env, db_ref: ResourceArc<DbRef>) -> NifResult<Term> {
+    if !db_ref.secondary {
+        return Ok((atoms::error(), atoms::not_secondary()).encode(env));
+    }
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    match shared_db.db.try_catch_up_with_primary() {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::catch_up_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// Opens the database with tuned options for `requested` and hidden default-option
+/// handles for every other CF already on disk.
+fn open_subset_db_ref(
+    path: String,
+    requested: HashSet<&'static str>,
+) -> Result<DbRef, rocksdb::Error> {
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+
+    // Listing fails when nothing exists at `path` yet, so there is nothing extra to open
+    let on_disk = DB::list_cf(&opts, &path).unwrap_or_default();
+
+    let default_overrides = CfOpenOptions::default();
+    let mut cf_descriptors: Vec<ColumnFamilyDescriptor> = CF_NAMES
+        .iter()
+        .filter(|name| requested.contains(*name))
+        .map(|name| {
+            ColumnFamilyDescriptor::new(
+                *name,
+                cf_options_for(name, &default_overrides, &DbOpenOptions::default(), None),
+            )
+        })
+        .collect();
+    cf_descriptors.extend(
+        on_disk
+            .iter()
+            .filter(|name| {
+                name.as_str() != rocksdb::DEFAULT_COLUMN_FAMILY_NAME
+                    && !requested.contains(name.as_str())
+            })
+            .map(|name| ColumnFamilyDescriptor::new(name, Options::default())),
+    );
+
+    let merge_cfs: HashSet<CfName> = requested
+        .iter()
+        .filter(|name| {
+            merge_operator_for(name, &default_overrides, &DbOpenOptions::default()).is_some()
+        })
+        .map(|name| CfName::Builtin(name))
+        .collect();
+    let opened_cfs = requested.into_iter().map(CfName::Builtin).collect();
+
+    let db = DB::open_cf_descriptors(&opts, &path, cf_descriptors)?;
+    Ok(DbRef::new(db, path, merge_cfs, opened_cfs, false, None))
+}
+
+/// Memtable representations selectable per column family.
+#[derive(Clone, Copy, PartialEq)]
+enum MemtableKind {
+    SkipList,
+    HashSkipList,
+    Vector,
+}
+
+/// Merge operators selectable per column family.
+#[derive(Clone, Copy, PartialEq)]
+enum MergeOperatorKind {
+    Int64Add,
+}
+
+/// SST index block layouts selectable per column family.
+#[derive(Clone, Copy, PartialEq)]
+enum IndexKind {
+    Binary,
+    Hash,
+    TwoLevel,
+}
+
+/// Database-wide options accepted by `open/2`.
+#[derive(Default)]
+struct DbOpenOptions {
+    atomic_flush: bool,
+    /// Use RocksDB's in-memory Env instead of the filesystem (`open_memory`)
+    in_memory: bool,
+    /// Open as a read-only instance (`open_read_only`)
+    read_only: bool,
+    /// Open as a secondary instance keeping its own files here (`open_secondary`)
+    secondary_path: Option<String>,
+    /// Shared LRU block cache capacity (`open_with_options`)
+    block_cache_bytes: Option<usize>,
+    /// Per-CF memtable size (`open_with_options`)
+    write_buffer_bytes: Option<usize>,
+    /// Open SST file handle limit (`open_with_options`)
+    max_open_files: Option<i32>,
+    /// Compression for L1 and below in every CF (`open_with_options`)
+    compression: Option<rocksdb::DBCompressionType>,
+    /// Column families opened alongside `CF_NAMES` (`open_with_cfs`)
+    user_cfs: Vec<CfName>,
+    /// Seconds after which `derived` entries expire (`open_with_ttl`)
+    derived_ttl: Option<u64>,
+}
+
+/// Per-column-family overrides applied on top of the tuned defaults.
+#[derive(Default)]
+struct CfOpenOptions {
+    memtable: Option<MemtableKind>,
+    prefix_length: Option<usize>,
+    merge_operator: Option<MergeOperatorKind>,
+    index_type: Option<IndexKind>,
+    format_version: Option<i32>,
+    compression: Option<rocksdb::DBCompressionType>,
+    /// Bloom filter bits per key; 0 disables the filter
+    bloom_filter_bits: Option<i32>,
+}
+
+/// Newest block-based table format version supported by the bundled RocksDB
+const MAX_FORMAT_VERSION: i32 = 6;
+
+/// Hash skiplist parameters (RocksDB defaults)
+const HASH_SKIPLIST_BUCKETS: usize = 1_000_000;
+const HASH_SKIPLIST_HEIGHT: i32 = 4;
+const HASH_SKIPLIST_BRANCHING: i32 = 4;
+
+fn memtable_kind_from_atom(atom: rustler::Atom) -> Option<MemtableKind> {
+    if atom == atoms::skiplist() {
+        Some(MemtableKind::SkipList)
+    } else if atom == atoms::hash_skiplist() {
+        Some(MemtableKind::HashSkipList)
+    } else if atom == atoms::vector() {
+        Some(MemtableKind::Vector)
+    } else {
+        None
+    }
+}
+
+fn index_kind_from_atom(atom: rustler::Atom) -> Option<IndexKind> {
+    if atom == atoms::binary_search() {
+        Some(IndexKind::Binary)
+    } else if atom == atoms::hash_search() {
+        Some(IndexKind::Hash)
+    } else if atom == atoms::two_level_index_search() {
+        Some(IndexKind::TwoLevel)
+    } else {
+        None
+    }
+}
+
+/// Opens the database with the tuned per-CF defaults plus any overrides.
+fn open_with_cf_options<'a>(
+    env: Env<'a>,
+    path: String,
+    overrides: &HashMap<&'static str, CfOpenOptions>,
+    db_options: &DbOpenOptions,
+) -> Term<'a> {
+    match open_db_ref(path, overrides, db_options) {
+        Ok(db_ref) => (atoms::ok(), ResourceArc::new(db_ref)).encode(env),
+        Err(e) => (atoms::error(), (atoms::open_failed(), e.to_string())).encode(env),
+    }
+}
+
+/// Opens the database and wraps it in a `DbRef`.
+fn open_db_ref(
+    path: String,
+    overrides: &HashMap<&'static str, CfOpenOptions>,
+    db_options: &DbOpenOptions,
+) -> Result<DbRef, rocksdb::Error> {
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    opts.set_atomic_flush(db_options.atomic_flush);
+    if let Some(files) = db_options.max_open_files {
+        opts.set_max_open_files(files);
+    }
+    if db_options.secondary_path.is_some() {
+        // Secondary instances must keep every table file open
+        opts.set_max_open_files(-1);
+    }
+    if db_options.in_memory {
+        // Options keep the Env alive for as long as the DB
+        opts.set_env(&rocksdb::Env::mem_env()?);
+    }
+
+    // Only the skiplist memtable supports concurrent memtable writes
+    let needs_serial_memtable_writes = overrides.values().any(|o| {
+        matches!(
+            o.memtable,
+            Some(MemtableKind::HashSkipList | MemtableKind::Vector)
+        )
+    });
+    if needs_serial_memtable_writes {
+        opts.set_allow_concurrent_memtable_write(false);
+    }
+
+    // One cache for all CFs, so the configured capacity bounds the total
+    let block_cache = db_options.block_cache_bytes.map(Cache::new_lru_cache);
+
+    let mut cf_names: Vec<CfName> = CF_NAMES
+        .iter()
+        .map(|name| CfName::Builtin(name))
+        .chain(db_options.user_cfs.iter().cloned())
+        .collect();
+    if db_options.read_only || db_options.secondary_path.is_some() {
+        // These modes can't create column families, so only open the ones on
+        // disk; a database from before the quad indexes has no gspo/gpos/gosp
+        let on_disk = DB::list_cf(&opts, &path)?;
+        cf_names.retain(|name| on_disk.iter().any(|existing| existing.as_str() == &**name));
+    }
+
+    // Create column family descriptors with tuned settings per access pattern
+    let default_overrides = CfOpenOptions::default();
+    let cf_descriptors: Vec<ColumnFamilyDescriptor> = cf_names
+        .iter()
+        .map(|name| {
+            let overrides = overrides.get(&**name).unwrap_or(&default_overrides);
+            let cf_opts = cf_options_for(name, overrides, db_options, block_cache.as_ref());
+            ColumnFamilyDescriptor::new(&**name, cf_opts)
+        })
+        .collect();
+
+    let merge_cfs: HashSet<CfName> = cf_names
+        .iter()
+        .filter(|name| matches!(name, CfName::Builtin(_)))
+        .filter(|name| {
+            merge_operator_for(
+                name,
+                overrides.get(&***name).unwrap_or(&default_overrides),
+                db_options,
+            )
+            .is_some()
+        })
+        .cloned()
+        .collect();
+
+    let db = if db_options.in_memory {
+        DB::open_cf_descriptors(&opts, memory_db_dir(), cf_descriptors)?
+    } else if let Some(secondary_path) = &db_options.secondary_path {
+        DB::open_cf_descriptors_as_secondary(
+            &opts,
+            path.as_str(),
+            secondary_path.as_str(),
+            cf_descriptors,
+        )?
+    } else if db_options.read_only {
+        DB::open_cf_descriptors_read_only(&opts, &path, cf_descriptors, false)?
+    } else {
+        DB::open_cf_descriptors(&opts, &path, cf_descriptors)?
+    };
+    let mut db_ref = DbRef::new(
+        db,
+        path,
+        merge_cfs,
+        cf_names.into_iter().collect(),
+        db_options.in_memory,
+        db_options.derived_ttl,
+    );
+    db_ref.read_only = db_options.read_only || db_options.secondary_path.is_some();
+    db_ref.secondary = db_options.secondary_path.is_some();
+    Ok(db_ref)
+}
+
+/// Builds the options for a single column family.
+fn cf_options_for(
+    name: &str,
+    overrides: &CfOpenOptions,
+    db_options: &DbOpenOptions,
+    block_cache: Option<&Cache>,
+) -> Options {
+    let mut cf_opts = Options::default();
+    let mut block_opts = BlockBasedOptions::default();
+
+    // Configure based on column family type
+    if DICTIONARY_CFS.contains(&name) {
+        // Dictionary CFs: Point lookups, high read frequency
+        // - 14 bits/key bloom filter (~0.01% FPR)
+        // - 2KB blocks (small for point lookups)
+        // - Full-key bloom filter (not prefix-based)
+        let bloom_bits = overrides.bloom_filter_bits.unwrap_or(DICTIONARY_BLOOM_BITS);
+        block_opts.set_bloom_filter(bloom_bits as f64, false);
+        block_opts.set_block_size(DICTIONARY_BLOCK_SIZE);
+        // Cache index and filter blocks for fast point lookups
+        block_opts.set_cache_index_and_filter_blocks(true);
+        block_opts.set_pin_l0_filter_and_index_blocks_in_cache(true);
+        // Optimize filters for hits (dictionary lookups usually succeed)
+        block_opts.set_optimize_filters_for_memory(true);
+    } else if INDEX_CFS.contains(&name) {
+        // Index CFs: Prefix scans, range queries
+        // - 12 bits/key bloom filter (~0.09% FPR)
+        // - 8KB blocks (balanced for prefix scans)
+        // - Prefix bloom via SliceTransform
+        let bloom_bits = overrides.bloom_filter_bits.unwrap_or(INDEX_BLOOM_BITS);
+        block_opts.set_bloom_filter(bloom_bits as f64, false);
+        block_opts.set_block_size(INDEX_BLOCK_SIZE);
+        // Cache index and filter blocks
+        block_opts.set_cache_index_and_filter_blocks(true);
+        block_opts.set_pin_l0_filter_and_index_blocks_in_cache(true);
+        // Configure prefix extractor for index column families
+        cf_opts.set_prefix_extractor(SliceTransform::create_fixed_prefix(PREFIX_LENGTH));
+        // Enable prefix bloom filter in memtable for faster lookups
+        cf_opts.set_memtable_prefix_bloom_ratio(0.1);
+    } else if name == DERIVED_CF {
+        // Derived CF: Bulk writes, sequential reads
+        // - No bloom filter unless requested (sequential access doesn't benefit)
+        // - 32KB blocks (large for sequential reads)
+        if let Some(bloom_bits) = overrides.bloom_filter_bits {
+            block_opts.set_bloom_filter(bloom_bits as f64, false);
+        }
+        block_opts.set_block_size(DERIVED_BLOCK_SIZE);
+        // Don't cache filter/index blocks (not used much)
+        block_opts.set_cache_index_and_filter_blocks(false);
+    }
+
+    match overrides.index_type {
+        Some(IndexKind::Binary) => block_opts.set_index_type(BlockBasedIndexType::BinarySearch),
+        Some(IndexKind::Hash) => block_opts.set_index_type(BlockBasedIndexType::HashSearch),
+        Some(IndexKind::TwoLevel) => {
+            block_opts.set_index_type(BlockBasedIndexType::TwoLevelIndexSearch)
+        }
+        None => {}
+    }
+    if let Some(version) = overrides.format_version {
+        block_opts.set_format_version(version);
+    }
+    if let Some(cache) = block_cache {
+        block_opts.set_block_cache(cache);
+    }
+
+    // Apply block-based options to column family
+    cf_opts.set_block_based_table_factory(&block_opts);
+
+    // Configure compression: LZ4 for all CFs by default (fast, reasonable ratio)
+    // L0 has no compression for write speed, other levels use the chosen type
+    let compression = overrides
+        .compression
+        .or(db_options.compression)
+        .unwrap_or(rocksdb::DBCompressionType::Lz4);
+    cf_opts.set_compression_type(compression);
+    cf_opts.set_compression_per_level(&[
+        rocksdb::DBCompressionType::None, // L0: no compression (short-lived)
+        compression,                      // L1
+        compression,                      // L2
+        compression,                      // L3
+        compression,                      // L4
+        compression,                      // L5
+        compression,                      // L6
+    ]);
+
+    if let Some(bytes) = db_options.write_buffer_bytes {
+        cf_opts.set_write_buffer_size(bytes);
+    }
+
+    if let Some(len) = overrides.prefix_length {
+        cf_opts.set_prefix_extractor(SliceTransform::create_fixed_prefix(len));
+    }
+
+    match overrides.memtable {
+        Some(MemtableKind::HashSkipList) => {
+            cf_opts.set_memtable_factory(MemtableFactory::HashSkipList {
+                bucket_count: HASH_SKIPLIST_BUCKETS,
+                height: HASH_SKIPLIST_HEIGHT,
+                branching_factor: HASH_SKIPLIST_BRANCHING,
+            });
+        }
+        Some(MemtableKind::Vector) => cf_opts.set_memtable_factory(MemtableFactory::Vector),
+        Some(MemtableKind::SkipList) | None => {}
+    }
+
+    if let Some(MergeOperatorKind::Int64Add) = merge_operator_for(name, overrides, db_options) {
+        cf_opts.set_merge_operator_associative("int64_add", int64_add_merge);
+    }
+
+    if let (Some(ttl), DERIVED_CF) = (db_options.derived_ttl, name) {
+        cf_opts.set_compaction_filter("derived_ttl", move |_level, _key, value| {
+            match value.get(..TTL_STAMP_LEN) {
+                Some(stamp) if is_expired(stamp, ttl) => CompactionDecision::Remove,
+                _ => CompactionDecision::Keep,
+            }
+        });
+    }
+
+    cf_opts
+}
+
+/// Merge operator installed on a column family. `derived` always gets
+/// `int64_add` so reference counters can be updated with `merge` instead of
+/// a racy read-modify-write, except with `open_with_ttl`: merge operands
+/// can't carry a write-time stamp, so there `derived` has none.
+fn merge_operator_for(
+    name: &str,
+    overrides: &CfOpenOptions,
+    db_options: &DbOpenOptions,
+) -> Option<MergeOperatorKind> {
+    if name == DERIVED_CF {
+        db_options
+            .derived_ttl
+            .is_none()
+            .then_some(MergeOperatorKind::Int64Add)
+    } else {
+        overrides.merge_operator
+    }
+}
+
+/// Length of the write-time stamp on `derived` values with `open_with_ttl`.
+const TTL_STAMP_LEN: usize = 8;
+
+/// Current time in whole seconds since the Unix epoch.
+fn unix_seconds() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Whether an entry stamped with `stamp` (see `SharedDb::stamp`) is more
+/// than `ttl` seconds old.
+fn is_expired(stamp: &[u8], ttl: u64) -> bool {
+    let written = u64::from_be_bytes(stamp.try_into().expect("stamp is TTL_STAMP_LEN bytes"));
+    written.saturating_add(ttl) < unix_seconds()
+}
+
+/// Associative merge operator summing little-endian i64 values.
+/// A missing base value counts as zero; malformed operands fail the merge.
+fn int64_add_merge(
+    _key: &[u8],
+    existing: Option<&[u8]>,
+    operands: &MergeOperands,
+) -> Option<Vec<u8>> {
+    let decode = |bytes: &[u8]| -> Option<i64> { Some(i64::from_le_bytes(bytes.try_into().ok()?)) };
+
+    let mut total = match existing {
+        Some(bytes) => decode(bytes)?,
+        None => 0,
+    };
+    for operand in operands.iter() {
+        total = total.wrapping_add(decode(operand)?);
+    }
+
+    Some(total.to_le_bytes().to_vec())
+}
+
+/// Closes the database and releases the main reference.
+///
+/// After calling close, the database handle is no longer valid for new operations.
+/// Subsequent operations on DbRef will return `{:error, :already_closed}`.
+///
+/// IMPORTANT: Existing iterators and snapshots will continue to work after close()
+/// because they hold their own Arc<SharedDb> reference. The actual database is only
+/// dropped when the last reference (including any active iterators/snapshots) is dropped.
+/// This prevents use-after-free bugs.
+///
+/// # Arguments
+/// * `db_ref` - The database reference to close
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if already closed
+#[rustler::nif(schedule = "DirtyCpu")]
+fn close(env: Env, db_ref: ResourceArc<DbRef>) -> NifResult<Term> {
+    // Remove our reference. The actual DB may still be alive if iterators/snapshots
+    // hold Arc<SharedDb> references. The DB is only dropped when the last Arc is dropped.
+    match db_ref.close() {
+        Some(shared_db) => {
+            shared_db.stop_event_pollers();
+            Ok(atoms::ok().encode(env))
+        }
+        None => Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    }
+}
+
+/// Closes the database, treating an already-closed database as success.
+///
+/// Same as `close`, but a second close returns `:ok` instead of
+/// `{:error, :already_closed}`. Intended for shutdown paths where several
+/// owners may race to close the same handle.
+///
+/// # Arguments
+/// * `db_ref` - The database reference to close
+///
+/// # Returns
+/// * `:ok` whether the database was open or already closed
+#[rustler::nif(schedule = "DirtyCpu")]
+fn close_idempotent(env: Env, db_ref: ResourceArc<DbRef>) -> NifResult<Term> {
+    // Dropping our Arc<SharedDb> (if any) closes the DB once iterators/snapshots are gone
+    if let Some(shared_db) = db_ref.close() {
+        shared_db.stop_event_pollers();
+    }
+    Ok(atoms::ok().encode(env))
+}
+
+/// Deletes a database and all of its files, including RocksDB's internal ones
+/// (WAL, manifest, options files, info logs and `LOCK`).
+///
+/// The database must be closed first. The NIF has no registry of open
+/// handles, so it can't refuse by itself; instead RocksDB takes the `LOCK`
+/// file, which fails while any handle on the path is open (including
+/// handles kept alive by iterators or snapshots after `close`), and that is
+/// returned as `{:destroy_failed, reason}`. Destroying a path that holds no
+/// database succeeds.
+///
+/// `opts` is accepted for symmetry with `open_with_options`; no setting
+/// affects where the files live today, so every key is ignored.
+///
+/// # Arguments
+/// * `path` - Path to the database directory
+/// * `opts` - Map of settings
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, {:destroy_failed, reason}}` if the database is still open or
+///   its files can't be removed
+/// * `{:error, {:invalid_option, opts}}` if `opts` isn't a map
+#[rustler::nif(schedule = "DirtyCpu")]
+fn destroy<'a>(env: Env<'a>, path: String, opts: Term<'a>) -> NifResult<Term<'a>> {
+    if opts.decode::<MapIterator>().is_err() {
+        return Ok((atoms::error(), (atoms::invalid_option(), opts)).encode(env));
+    }
+
+    match DB::destroy(&Options::default(), &path) {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::destroy_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// Returns the path of the database.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+///
+/// # Returns
+/// * `{:ok, path}` with the database path
+/// * `{:error, :already_closed}` if database is closed
+#[rustler::nif(schedule = "DirtyCpu")]
+fn get_path(env: Env, db_ref: ResourceArc<DbRef>) -> NifResult<Term> {
+    let guard = db_ref.load();
+
+    match guard.as_ref() {
+        Some(shared_db) => Ok((atoms::ok(), shared_db.path.clone()).encode(env)),
+        None => Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    }
+}
+
+/// Returns the TTL of `derived` entries the database was opened with.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+///
+/// # Returns
+/// * `{:ok, ttl_seconds}` for a database opened with `open_with_ttl`
+/// * `{:ok, nil}` if `derived` entries don't expire
+/// * `{:error, :already_closed}` if database is closed
+#[rustler::nif(schedule = "DirtyCpu")]
+fn get_ttl(env: Env, db_ref: ResourceArc<DbRef>) -> NifResult<Term> {
+    let guard = db_ref.load();
+
+    match guard.as_ref() {
+        Some(shared_db) => Ok((atoms::ok(), shared_db.derived_ttl).encode(env)),
+        None => Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    }
+}
+
+/// Lists all column families in the database.
+///
+/// # Returns
+/// * List of column family name atoms
+#[rustler::nif]
+fn list_column_families(env: Env) -> NifResult<Term> {
+    let cf_atoms: Vec<Term> = vec![
+        atoms::id2str().encode(env),
+        atoms::str2id().encode(env),
+        atoms::spo().encode(env),
+        atoms::pos().encode(env),
+        atoms::osp().encode(env),
+        atoms::derived().encode(env),
+        atoms::numeric_range().encode(env),
+        atoms::gspo().encode(env),
+        atoms::gpos().encode(env),
+        atoms::gosp().encode(env),
+    ];
+    Ok(cf_atoms.encode(env))
+}
+
+/// Returns the number of iterators currently open on the database.
+///
+/// Counts `prefix_iterator`/`prefix_iterator_filtered` iterators that have been
+/// neither closed with `iterator_close` nor garbage-collected. Useful for
+/// catching Elixir code that forgets to close its iterators.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+///
+/// # Returns
+/// * `{:ok, count}` on success
+/// * `{:error, :already_closed}` if database is closed
+#[rustler::nif(schedule = "DirtyCpu")]
+fn open_iterator_count(env: Env, db_ref: ResourceArc<DbRef>) -> NifResult<Term> {
+    let guard = db_ref.load();
+
+    match guard.as_ref() {
+        Some(db) => Ok((atoms::ok(), db.open_iterators.load(Ordering::Relaxed)).encode(env)),
+        None => Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    }
+}
+
+/// Checks if the database is open.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+///
+/// # Returns
+/// * `true` if open, `false` if closed
+#[rustler::nif(schedule = "DirtyCpu")]
+fn is_open(db_ref: ResourceArc<DbRef>) -> NifResult<bool> {
+    let guard = db_ref.load();
+    Ok(guard.is_some())
+}
+
+/// Reserved key prefix for health-check probes in the `default` CF, which holds
+/// no triples, so a probe left behind by a crash is never decoded as one.
+const HEALTH_CHECK_PREFIX: &[u8] = b"\x00__health_check__";
+
+/// Distinguishes concurrent health-check probes from each other.
+static HEALTH_CHECK_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Performs a write/read/delete round trip to verify the database is usable.
+///
+/// Writes a probe key under a reserved prefix in the `default` CF, reads it
+/// back, and deletes it. Each call uses a unique probe key, so concurrent
+/// calls don't interfere. The probe is deleted even if the read fails.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+///
+/// # Returns
+/// * `{:ok, latency_micros}` if the round trip succeeded
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:health_check_failed, reason}}` if any step failed
+#[rustler::nif(schedule = "DirtyCpu")]
+fn health_check(env: Env, db_ref: ResourceArc<DbRef>) -> NifResult<Term> {
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let probe_id = HEALTH_CHECK_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let mut key = HEALTH_CHECK_PREFIX.to_vec();
+    key.extend_from_slice(&probe_id.to_be_bytes());
+    let value = probe_id.to_le_bytes();
+
+    let started = std::time::Instant::now();
+
+    let round_trip = shared_db
+        .db
+        .put(&key, value)
+        .map_err(|e| format!("put: {}", e))
+        .and_then(|_| {
+            let read = shared_db.db.get(&key);
+            // Always remove the probe, even if the read failed
+            let deleted = shared_db.db.delete(&key);
+
+            match read {
+                Ok(Some(found)) if found == value => {}
+                Ok(Some(_)) => return Err("get: value mismatch".to_string()),
+                Ok(None) => return Err("get: probe key not found".to_string()),
+                Err(e) => return Err(format!("get: {}", e)),
+            }
+            deleted.map_err(|e| format!("delete: {}", e))
+        });
+
+    let latency_micros = started.elapsed().as_micros() as u64;
+
+    match round_trip {
+        Ok(()) => Ok((atoms::ok(), latency_micros).encode(env)),
+        Err(reason) => Ok((atoms::error(), (atoms::health_check_failed(), reason)).encode(env)),
+    }
+}
+
+/// Gets a value from a column family.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom (:id2str, :str2id, :spo, :pos, :osp, :derived)
+/// * `key` - The key as a binary
+///
+/// # Returns
+/// * `{:ok, value}` if found
+/// * `:not_found` if key doesn't exist
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:get_failed, reason}}` on other errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn get<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+
+    match shared_db.db.get_cf(&cf_handle, key.as_slice()) {
+        Ok(Some(value)) => {
+            let value = shared_db.unstamp(&cf_name, &value);
+            let mut binary = NewBinary::new(env, value.len());
+            binary.as_mut_slice().copy_from_slice(value);
+            Ok((atoms::ok(), Binary::from(binary)).encode(env))
+        }
+        Ok(None) => Ok(atoms::not_found().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::get_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// Gets a value from a column family without copying it.
+///
+/// `get` copies the value out of RocksDB into a `Vec` and again into a fresh
+/// binary. Here the value stays pinned where RocksDB holds it (block cache
+/// or memtable copy) and Elixir receives a resource binary pointing at it,
+/// so nothing is copied. Worth it for large values such as long `id2str`
+/// strings; for small values the resource allocation outweighs the copy.
+///
+/// The pinned memory is released when the binary is garbage-collected, so
+/// it keeps its block cache block, and the database after `close`, alive
+/// until then. Copy with `:binary.copy/1` before storing a small part of a
+/// large value for long.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `key` - The key as a binary
+///
+/// # Returns
+/// * `{:ok, value}` if found
+/// * `:not_found` if key doesn't exist
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:get_failed, reason}}` on other errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn get_pinned<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+
+    let pinned = shared_db.db.get_pinned_cf(&cf_handle, key.as_slice());
+    match pinned {
+        Ok(Some(slice)) => {
+            // SAFETY: PinnedValue holds the Arc<SharedDb>, so the database
+            // outlives the slice.
+            let slice: DBPinnableSlice<'static> = unsafe { std::mem::transmute(slice) };
+            let offset = slice.len() - shared_db.unstamp(&cf_name, &slice).len();
+            let pinned = ResourceArc::new(PinnedValue {
+                slice,
+                offset,
+                _db: Arc::clone(shared_db),
+            });
+            let binary = pinned.make_binary(env, |pinned| &pinned.slice[pinned.offset..]);
+            Ok((atoms::ok(), binary).encode(env))
+        }
+        Ok(None) => Ok(atoms::not_found().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::get_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// Gets multiple values from a column family in one call.
+///
+/// All keys are looked up with a single `multi_get_cf`, so RocksDB batches the
+/// memtable, block cache and disk accesses and the NIF boundary is crossed
+/// once instead of once per key. Absent keys keep their slot as `nil`, so the
+/// results can be zipped against the input keys.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `keys` - List of keys as binaries
+///
+/// # Returns
+/// * `{:ok, values}` with a value binary or `nil` per key, in key order
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:get_failed, reason}}` if any read fails
+#[rustler::nif(schedule = "DirtyCpu")]
+fn multi_get<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    keys: Vec<Binary<'a>>,
+) -> NifResult<Term<'a>> {
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+
+    let lookups = keys.iter().map(|key| (cf_handle, key.as_slice()));
+    let mut values = Vec::with_capacity(keys.len());
+
+    for result in shared_db.db.multi_get_cf(lookups) {
+        match result {
+            Ok(Some(value)) => {
+                let value = shared_db.unstamp(&cf_name, &value);
+                let mut binary = NewBinary::new(env, value.len());
+                binary.as_mut_slice().copy_from_slice(value);
+                values.push(Binary::from(binary).encode(env));
+            }
+            Ok(None) => values.push(rustler::types::atom::nil().encode(env)),
+            Err(e) => return Ok((atoms::error(), (atoms::get_failed(), e.to_string())).encode(env)),
+        }
+    }
+
+    Ok((atoms::ok(), values).encode(env))
+}
+
+/// Gets values for `{cf, key}` pairs across column families in one call.
+///
+/// Lets a query resolve e.g. a `str2id` lookup and an `spo` probe in a single
+/// NIF round trip. Requests are grouped per column family, each group is read
+/// with one `multi_get_cf`, and the values are put back in request order.
+/// Absent keys keep their slot as `nil`.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `requests` - List of `{cf, key}` tuples
+///
+/// # Returns
+/// * `{:ok, values}` with a value binary or `nil` per request, in request order
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` for the first invalid column family
+/// * `{:error, {:get_failed, reason}}` if any read fails
+#[rustler::nif(schedule = "DirtyCpu")]
+fn multi_get_cf<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    requests: Vec<(Term<'a>, Binary<'a>)>,
+) -> NifResult<Term<'a>> {
+    // Request indexes per column family, in first-seen order
+    let mut groups: Vec<(Term<'a>, CfName, Vec<usize>)> = Vec::new();
+    for (index, (cf, _)) in requests.iter().enumerate() {
+        let cf_name = match cf_term_to_name(*cf) {
+            Some(name) => name,
+            None => return Ok((atoms::error(), (atoms::invalid_cf(), *cf)).encode(env)),
+        };
+        match groups.iter_mut().find(|(_, name, _)| *name == cf_name) {
+            Some((_, _, indexes)) => indexes.push(index),
+            None => groups.push((*cf, cf_name, vec![index])),
+        }
+    }
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let mut values = vec![rustler::types::atom::nil().encode(env); requests.len()];
+
+    for (cf, cf_name, indexes) in groups {
+        let cf_handle = match shared_db.cf_handle(&cf_name) {
+            Some(cf) => cf,
+            None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+        };
+
+        let lookups = indexes
+            .iter()
+            .map(|&index| (cf_handle, requests[index].1.as_slice()));
+        for (&index, result) in indexes.iter().zip(shared_db.db.multi_get_cf(lookups)) {
+            match result {
+                Ok(Some(value)) => {
+                    let value = shared_db.unstamp(&cf_name, &value);
+                    let mut binary = NewBinary::new(env, value.len());
+                    binary.as_mut_slice().copy_from_slice(value);
+                    values[index] = Binary::from(binary).encode(env);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    return Ok((atoms::error(), (atoms::get_failed(), e.to_string())).encode(env))
+                }
+            }
+        }
+    }
+
+    Ok((atoms::ok(), values).encode(env))
+}
+
+/// Puts a key-value pair into a column family.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `key` - The key as a binary
+/// * `value` - The value as a binary
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, :read_only}` if the database was opened read-only
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:put_failed, reason}}` on other errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn put<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+    value: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    put_value(env, db_ref, cf, key, value, &WriteOptions::default())
+}
+
+/// Puts a key-value pair into a column family with per-call write options.
+///
+/// Same as `put`, with `options` as accepted by `write_options_from_term`.
+///
+/// # Returns
+/// * Same as `put`
+/// * `{:error, {:invalid_option, key}}` if an option is not a boolean
+#[rustler::nif(name = "put", schedule = "DirtyCpu")]
+fn put_with_options<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+    value: Binary<'a>,
+    options: Term<'a>,
+) -> NifResult<Term<'a>> {
+    let write_opts = match write_options_from_term(options) {
+        Ok(write_opts) => write_opts,
+        Err(option) => return Ok((atoms::error(), (atoms::invalid_option(), option)).encode(env)),
+    };
+
+    put_value(env, db_ref, cf, key, value, &write_opts)
+}
+
+/// Shared implementation of `put/4` and `put/5`.
+fn put_value<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+    value: Binary<'a>,
+    write_opts: &WriteOptions,
+) -> NifResult<Term<'a>> {
+    if db_ref.read_only {
+        return Ok((atoms::error(), atoms::read_only()).encode(env));
+    }
+
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+
+    if fault_injection::take_write_fault(shared_db) {
+        return Ok((atoms::error(), (atoms::put_failed(), "injected")).encode(env));
+    }
+
+    let value = shared_db.stamp(&cf_name, value.as_slice());
+    match shared_db
+        .db
+        .put_cf_opt(&cf_handle, key.as_slice(), value, write_opts)
+    {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::put_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// Puts a key-value pair only if the key is absent from the column family.
+///
+/// Meant for allocators such as the string interner, which must not replace
+/// an ID that another process already assigned. The read and the write happen
+/// under the database's commit lock, so concurrent `put_if_absent` calls and
+/// transaction commits are serialized with each other: for a given key exactly
+/// one `put_if_absent` writes and the others see its value. Plain `put`,
+/// batches and merges don't take the lock, so a concurrent one can still land
+/// between the check and the write and be overwritten.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `key` - The key as a binary
+/// * `value` - The value as a binary
+///
+/// # Returns
+/// * `{:ok, :written}` if the key was absent and `value` was written
+/// * `{:ok, {:exists, existing}}` with the stored value if the key was present
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, :read_only}` if the database was opened read-only
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:get_failed, reason}}` if the existing value can't be read
+/// * `{:error, {:put_failed, reason}}` if the write fails
+#[rustler::nif(schedule = "DirtyCpu")]
+fn put_if_absent<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+    value: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    if db_ref.read_only {
+        return Ok((atoms::error(), atoms::read_only()).encode(env));
+    }
+
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    if shared_db.cf_handle(&cf_name).is_none() {
+        return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env));
+    }
+
+    if fault_injection::take_write_fault(shared_db) {
+        return Ok((atoms::error(), (atoms::put_failed(), "injected")).encode(env));
+    }
+
+    match write_if_absent(shared_db, &cf_name, key.as_slice(), value.as_slice()) {
+        Ok(None) => Ok((atoms::ok(), atoms::written()).encode(env)),
+        Ok(Some(existing)) => {
+            let mut binary = NewBinary::new(env, existing.len());
+            binary.as_mut_slice().copy_from_slice(&existing);
+            Ok((atoms::ok(), (atoms::exists(), Binary::from(binary))).encode(env))
+        }
+        Err(error) => Ok((atoms::error(), error).encode(env)),
+    }
+}
+
+/// Writes `value` under the commit lock unless the key already has a value,
+/// which is returned instead. Errors are `(get_failed | put_failed, reason)`.
+fn write_if_absent(
+    shared_db: &SharedDb,
+    cf_name: &str,
+    key: &[u8],
+    value: &[u8],
+) -> Result<Option<Vec<u8>>, (rustler::Atom, String)> {
+    let _commit = shared_db
+        .commit_lock
+        .lock()
+        .map_err(|_| (atoms::put_failed(), "lock poisoned".to_string()))?;
+
+    match read_committed(shared_db, cf_name, key) {
+        Ok(Some(existing)) => return Ok(Some(existing)),
+        Ok(None) => {}
+        Err(e) => return Err((atoms::get_failed(), e.to_string())),
+    }
+
+    let cf_handle = shared_db.cf_handle(cf_name).ok_or_else(|| {
+        (
+            atoms::put_failed(),
+            format!("column family '{}' not found", cf_name),
+        )
+    })?;
+    match shared_db
+        .db
+        .put_cf(&cf_handle, key, shared_db.stamp(cf_name, value))
+    {
+        Ok(()) => Ok(None),
+        Err(e) => Err((atoms::put_failed(), e.to_string())),
+    }
+}
+
+/// Builds write options from the `options` argument of the write NIFs.
+///
+/// A boolean is the `sync` flag on its own. A map may set `:sync` (fsync the
+/// WAL before returning) and `:disable_wal` (skip the WAL entirely); both
+/// default to false and unknown keys are ignored. On error, returns the
+/// offending key, or `options` itself if it is neither a boolean nor a map.
+fn write_options_from_term(options: Term<'_>) -> Result<WriteOptions, Term<'_>> {
+    let mut write_opts = WriteOptions::default();
+
+    if let Ok(sync) = options.decode::<bool>() {
+        write_opts.set_sync(sync);
+        return Ok(write_opts);
+    }
+
+    for (key, value) in options.decode::<MapIterator>().map_err(|_| options)? {
+        let name = match key.decode::<rustler::Atom>() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+
+        if name == atoms::sync() {
+            write_opts.set_sync(value.decode::<bool>().map_err(|_| key)?);
+        } else if name == atoms::disable_wal() {
+            write_opts.disable_wal(value.decode::<bool>().map_err(|_| key)?);
+        }
+    }
+
+    Ok(write_opts)
+}
+
+/// Merges a value into a key using the column family's merge operator.
+///
+/// Only allowed on column families opened with a merge operator (see `open/2`).
+/// Without one RocksDB would silently treat merge operands as corrupt data, so
+/// the call is rejected up front instead. `derived` always has the `int64_add`
+/// operator, so it holds atomic counters: concurrent merges never lose an
+/// update, and `get` returns the folded sum as an 8-byte little-endian i64.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `key` - The key as a binary
+/// * `value` - The merge operand as an 8-byte little-endian i64 binary, or an
+///   integer delta that is encoded that way
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, :read_only}` if the database was opened read-only
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:cf_not_opened, cf}}` if this handle didn't open the column family
+/// * `{:error, {:invalid_operand, value}}` if `value` is neither an integer
+///   nor an 8-byte binary; `int64_add` can't fold it, so storing it would make
+///   every later read and compaction of the key fail
+/// * `{:error, :no_merge_operator}` if the CF has no merge operator installed
+/// * `{:error, {:merge_failed, reason}}` on other errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn merge<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+    value: Term<'a>,
+) -> NifResult<Term<'a>> {
+    if db_ref.read_only {
+        return Ok((atoms::error(), atoms::read_only()).encode(env));
+    }
+
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let operand = match value.decode::<i64>() {
+        Ok(delta) => delta.to_le_bytes().to_vec(),
+        Err(_) => match value.decode::<Binary>() {
+            Ok(bytes) if bytes.len() == 8 => bytes.as_slice().to_vec(),
+            _ => return Ok((atoms::error(), (atoms::invalid_operand(), value)).encode(env)),
+        },
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+
+    if !shared_db.merge_cfs.contains(&*cf_name) {
+        return Ok((atoms::error(), atoms::no_merge_operator()).encode(env));
+    }
+
+    match shared_db.db.merge_cf(&cf_handle, key.as_slice(), operand) {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::merge_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// Deletes a key from a column family.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `key` - The key to delete
+///
+/// # Returns
+/// * `:ok` on success (even if key didn't exist)
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, :read_only}` if the database was opened read-only
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:delete_failed, reason}}` on other errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn delete<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    if db_ref.read_only {
+        return Ok((atoms::error(), atoms::read_only()).encode(env));
+    }
+
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+
+    match shared_db.db.delete_cf(&cf_handle, key.as_slice()) {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::delete_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// Deletes every key in `[start_key, end_key)` from a column family.
+///
+/// Writes a single range tombstone instead of one tombstone per key, so
+/// retracting a dense prefix (e.g. every `spo` entry of a subject) costs the
+/// same as one delete. The space of the deleted entries is only reclaimed
+/// once compaction rewrites the files holding them.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `start_key` - First key to delete (inclusive)
+/// * `end_key` - Key to stop at (exclusive)
+///
+/// # Returns
+/// * `:ok` on success (even if no key was in the range)
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, :read_only}` if the database was opened read-only
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:delete_failed, reason}}` if the range ends before it starts or on other errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn delete_range<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    start_key: Binary<'a>,
+    end_key: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    if db_ref.read_only {
+        return Ok((atoms::error(), atoms::read_only()).encode(env));
+    }
+
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    if end_key.as_slice() < start_key.as_slice() {
+        return Ok((
+            atoms::error(),
+            (atoms::delete_failed(), "end key comes before start key"),
+        )
+            .encode(env));
+    }
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+
+    match shared_db
+        .db
+        .delete_range_cf(&cf_handle, start_key.as_slice(), end_key.as_slice())
+    {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::delete_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// Checks if a key exists in a column family.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `key` - The key to check
+///
+/// # Returns
+/// * `{:ok, true}` if key exists
+/// * `{:ok, false}` if key doesn't exist
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:get_failed, reason}}` on other errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn exists<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+
+    // Check if key exists by attempting to get it
+    match shared_db.db.get_cf(&cf_handle, key.as_slice()) {
+        Ok(Some(_)) => Ok((atoms::ok(), true).encode(env)),
+        Ok(None) => Ok((atoms::ok(), false).encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::get_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// Cheaply checks whether a key may exist, without reading it from disk.
+///
+/// Consults only the memtables and what is already in memory (bloom filters,
+/// cached index blocks), never doing I/O. `false` is definite: the key is
+/// absent. `true` is probabilistic — bloom filters have false positives and
+/// unchecked files count as a maybe — so a following `get` or `exists` is
+/// still required to confirm. Use it to skip the read on definite misses.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `key` - The key to check
+///
+/// # Returns
+/// * `{:ok, true}` if the key may exist
+/// * `{:ok, false}` if the key definitely doesn't exist
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+#[rustler::nif(schedule = "DirtyCpu")]
+fn key_may_exist<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+
+    let may_exist = shared_db.db.key_may_exist_cf(&cf_handle, key.as_slice());
+    Ok((atoms::ok(), may_exist).encode(env))
+}
+
+/// Atomically writes multiple key-value pairs to column families.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `operations` - List of `{cf, key, value}` tuples
+/// * `options` - `sync` boolean, or a map with `:sync` and `:disable_wal`
+///   (see `write_options_from_term`)
+///
+/// For bulk loading, set `sync=false` to improve throughput. Data is still
+/// protected by the WAL, but fsync is deferred to the OS. This can provide
+/// 2-3x throughput improvement for large batch operations. Recoverable bulk
+/// loads can also pass `disable_wal: true`, which loses every write not yet
+/// flushed to an SST file if the process crashes.
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, :read_only}` if the database was opened read-only
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:invalid_option, option}}` if `options` is invalid
+/// * `{:error, {:batch_failed, reason}}` on other errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn write_batch<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    operations: Term<'a>,
+    options: Term<'a>,
+) -> NifResult<Term<'a>> {
+    if db_ref.read_only {
+        return Ok((atoms::error(), atoms::read_only()).encode(env));
+    }
+
+    let write_opts = match write_options_from_term(options) {
+        Ok(write_opts) => write_opts,
+        Err(option) => return Ok((atoms::error(), (atoms::invalid_option(), option)).encode(env)),
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let mut batch = WriteBatch::default();
+
+    // Parse the list of operations
+    let iter: ListIterator = operations
+        .decode()
+        .map_err(|_| rustler::Error::Term(Box::new("expected list")))?;
+
+    for item in iter {
+        // Each item should be a tuple {cf, key, value}
+        let tuple = rustler::types::tuple::get_tuple(item)
+            .map_err(|_| rustler::Error::Term(Box::new("expected tuple")))?;
+
+        if tuple.len() == 3 {
+            // Simple format: {cf, key, value} - treat as put
+            let cf_term = tuple[0];
+            let key: Binary = tuple[1]
+                .decode()
+                .map_err(|_| rustler::Error::Term(Box::new("expected binary for key")))?;
+            let value: Binary = tuple[2]
+                .decode()
+                .map_err(|_| rustler::Error::Term(Box::new("expected binary for value")))?;
+
+            let cf_name = match cf_term_to_name(cf_term) {
+                Some(name) => name,
+                None => return Ok((atoms::error(), (atoms::invalid_cf(), cf_term)).encode(env)),
+            };
+
+            let cf_handle = match shared_db.cf_handle(&cf_name) {
+                Some(cf) => cf,
+                None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf_term)).encode(env)),
+            };
+
+            batch.put_cf(
+                &cf_handle,
+                key.as_slice(),
+                shared_db.stamp(&cf_name, value.as_slice()),
+            );
+        } else if tuple.len() == 4 {
+            // Extended format: {:put, cf, key, value}
+            let op_atom: rustler::Atom = tuple[0]
+                .decode()
+                .map_err(|_| rustler::Error::Term(Box::new("expected atom for operation")))?;
+            let cf_term = tuple[1];
+            let key: Binary = tuple[2]
+                .decode()
+                .map_err(|_| rustler::Error::Term(Box::new("expected binary for key")))?;
+            let value: Binary = tuple[3]
+                .decode()
+                .map_err(|_| rustler::Error::Term(Box::new("expected binary for value")))?;
+
+            if op_atom != atoms::put() {
+                return Ok((atoms::error(), (atoms::invalid_operation(), op_atom)).encode(env));
+            }
+
+            let cf_name = match cf_term_to_name(cf_term) {
+                Some(name) => name,
+                None => return Ok((atoms::error(), (atoms::invalid_cf(), cf_term)).encode(env)),
+            };
+
+            let cf_handle = match shared_db.cf_handle(&cf_name) {
+                Some(cf) => cf,
+                None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf_term)).encode(env)),
+            };
+
+            batch.put_cf(
+                &cf_handle,
+                key.as_slice(),
+                shared_db.stamp(&cf_name, value.as_slice()),
+            );
+        } else {
+            return Ok((atoms::error(), atoms::invalid_operation()).encode(env));
+        }
+    }
+
+    if fault_injection::take_write_fault(shared_db) {
+        return Ok((atoms::error(), (atoms::put_failed(), "injected")).encode(env));
+    }
+
+    match shared_db.db.write_opt(batch, &write_opts) {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::batch_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// Atomically deletes multiple keys from column families.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `operations` - List of `{cf, key}` tuples
+/// * `options` - `sync` boolean, or a map with `:sync` and `:disable_wal`
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, :read_only}` if the database was opened read-only
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:invalid_option, option}}` if `options` is invalid
+/// * `{:error, {:batch_failed, reason}}` on other errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn delete_batch<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    operations: Term<'a>,
+    options: Term<'a>,
+) -> NifResult<Term<'a>> {
+    if db_ref.read_only {
+        return Ok((atoms::error(), atoms::read_only()).encode(env));
+    }
+
+    let write_opts = match write_options_from_term(options) {
+        Ok(write_opts) => write_opts,
+        Err(option) => return Ok((atoms::error(), (atoms::invalid_option(), option)).encode(env)),
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let mut batch = WriteBatch::default();
+
+    // Parse the list of operations
+    let iter: ListIterator = operations
+        .decode()
+        .map_err(|_| rustler::Error::Term(Box::new("expected list")))?;
+
+    for item in iter {
+        // Each item should be a tuple {cf, key}
+        let tuple = rustler::types::tuple::get_tuple(item)
+            .map_err(|_| rustler::Error::Term(Box::new("expected tuple")))?;
+
+        if tuple.len() != 2 {
+            return Ok((atoms::error(), atoms::invalid_operation()).encode(env));
+        }
+
+        let cf_term = tuple[0];
+        let key: Binary = tuple[1]
+            .decode()
+            .map_err(|_| rustler::Error::Term(Box::new("expected binary for key")))?;
+
+        let cf_name = match cf_term_to_name(cf_term) {
+            Some(name) => name,
+            None => return Ok((atoms::error(), (atoms::invalid_cf(), cf_term)).encode(env)),
+        };
+
+        let cf_handle = match shared_db.cf_handle(&cf_name) {
+            Some(cf) => cf,
+            None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf_term)).encode(env)),
+        };
+
+        batch.delete_cf(&cf_handle, key.as_slice());
+    }
+
+    match shared_db.db.write_opt(batch, &write_opts) {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::batch_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// Atomically performs mixed put, delete and range delete operations.
+///
+/// `{:delete_range, cf, start_key, end_key}` removes every key in
+/// `[start_key, end_key)` with a single range tombstone, so a subject's triples
+/// and derived entries can be retracted atomically with the other operations
+/// without reading them first. Operations apply in list order, so a put after
+/// a range delete covering its key survives.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `operations` - List of operations:
+///   - `{:put, cf, key, value}` for puts
+///   - `{:delete, cf, key}` for deletes
+///   - `{:delete_range, cf, start_key, end_key}` for range deletes
+/// * `options` - `sync` boolean, or a map with `:sync` and `:disable_wal`
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, :read_only}` if the database was opened read-only
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:invalid_operation, op}}` if operation type is invalid
+/// * `{:error, {:invalid_operation, :delete_range}}` if a range ends before it starts
+/// * `{:error, {:invalid_option, option}}` if `options` is invalid
+/// * `{:error, {:batch_failed, reason}}` on other errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn mixed_batch<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    operations: Term<'a>,
+    options: Term<'a>,
+) -> NifResult<Term<'a>> {
+    if db_ref.read_only {
+        return Ok((atoms::error(), atoms::read_only()).encode(env));
+    }
+
+    let write_opts = match write_options_from_term(options) {
+        Ok(write_opts) => write_opts,
+        Err(option) => return Ok((atoms::error(), (atoms::invalid_option(), option)).encode(env)),
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let mut batch = WriteBatch::default();
+
+    // Parse the list of operations
+    let iter: ListIterator = operations
+        .decode()
+        .map_err(|_| rustler::Error::Term(Box::new("expected list")))?;
+
+    for item in iter {
+        let tuple = rustler::types::tuple::get_tuple(item)
+            .map_err(|_| rustler::Error::Term(Box::new("expected tuple")))?;
+
+        if tuple.is_empty() {
+            return Ok((atoms::error(), atoms::invalid_operation()).encode(env));
+        }
+
+        let op_atom: rustler::Atom = tuple[0]
+            .decode()
+            .map_err(|_| rustler::Error::Term(Box::new("expected atom for operation")))?;
+
+        if op_atom == atoms::put() {
+            // {:put, cf, key, value}
+            if tuple.len() != 4 {
+                return Ok((atoms::error(), atoms::invalid_operation()).encode(env));
+            }
+
+            let cf_term = tuple[1];
+            let key: Binary = tuple[2]
+                .decode()
+                .map_err(|_| rustler::Error::Term(Box::new("expected binary for key")))?;
+            let value: Binary = tuple[3]
+                .decode()
+                .map_err(|_| rustler::Error::Term(Box::new("expected binary for value")))?;
+
+            let cf_name = match cf_term_to_name(cf_term) {
+                Some(name) => name,
+                None => return Ok((atoms::error(), (atoms::invalid_cf(), cf_term)).encode(env)),
+            };
+
+            let cf_handle = match shared_db.cf_handle(&cf_name) {
+                Some(cf) => cf,
+                None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf_term)).encode(env)),
+            };
+
+            batch.put_cf(
+                &cf_handle,
+                key.as_slice(),
+                shared_db.stamp(&cf_name, value.as_slice()),
+            );
+        } else if op_atom == atoms::delete() {
+            // {:delete, cf, key}
+            if tuple.len() != 3 {
+                return Ok((atoms::error(), atoms::invalid_operation()).encode(env));
+            }
+
+            let cf_term = tuple[1];
+            let key: Binary = tuple[2]
+                .decode()
+                .map_err(|_| rustler::Error::Term(Box::new("expected binary for key")))?;
+
+            let cf_name = match cf_term_to_name(cf_term) {
+                Some(name) => name,
+                None => return Ok((atoms::error(), (atoms::invalid_cf(), cf_term)).encode(env)),
+            };
+
+            let cf_handle = match shared_db.cf_handle(&cf_name) {
+                Some(cf) => cf,
+                None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf_term)).encode(env)),
+            };
+
+            batch.delete_cf(&cf_handle, key.as_slice());
+        } else if op_atom == atoms::delete_range() {
+            // {:delete_range, cf, start_key, end_key}
+            if tuple.len() != 4 {
+                return Ok((atoms::error(), atoms::invalid_operation()).encode(env));
+            }
+
+            let cf_term = tuple[1];
+            let start_key: Binary = tuple[2]
+                .decode()
+                .map_err(|_| rustler::Error::Term(Box::new("expected binary for start key")))?;
+            let end_key: Binary = tuple[3]
+                .decode()
+                .map_err(|_| rustler::Error::Term(Box::new("expected binary for end key")))?;
+
+            let cf_name = match cf_term_to_name(cf_term) {
+                Some(name) => name,
+                None => return Ok((atoms::error(), (atoms::invalid_cf(), cf_term)).encode(env)),
+            };
+
+            let cf_handle = match shared_db.cf_handle(&cf_name) {
+                Some(cf) => cf,
+                None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf_term)).encode(env)),
+            };
+
+            if end_key.as_slice() < start_key.as_slice() {
+                return Ok((atoms::error(), (atoms::invalid_operation(), op_atom)).encode(env));
+            }
+
+            batch.delete_range_cf(&cf_handle, start_key.as_slice(), end_key.as_slice());
+        } else {
+            return Ok((atoms::error(), (atoms::invalid_operation(), op_atom)).encode(env));
+        }
+    }
+
+    match shared_db.db.write_opt(batch, &write_opts) {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::batch_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// A raw key-value pair as returned by a RocksDB iterator.
+type KvPair = (Box<[u8]>, Box<[u8]>);
+
+/// Numbers the temporary directories of `swap_cf_contents`.
+static SWAP_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Swaps the contents of two column families.
+///
+/// RocksDB has no CF rename, so the swap is done with SST ingestion. Both CFs
+/// are read from one snapshot and merged by key into two SST files (one per
+/// CF) holding a put for every key that gets a new value and a delete for
+/// every key that goes away. Each file is then ingested into its CF as a
+/// single atomic step, so readers of `to_cf` see either its old or its new
+/// contents, never a mix; `from_cf` receives the previous contents of `to_cf`
+/// right after, in a second step. The data is streamed to the files, not held
+/// in memory, and the files are written to a temporary directory inside the
+/// database directory that is removed afterwards.
+///
+/// A database opened with `open_memory` can't ingest files from disk, so there
+/// both CFs are swapped with a single WriteBatch built in memory instead.
+///
+/// Other operations on the handle keep running during the swap. Writes made
+/// to either CF while it runs are not part of it: one to a key the swap
+/// replaces is overwritten, one to any other key is kept.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `from_cf` - Column family holding the freshly built data
+/// * `to_cf` - Column family to replace
+///
+/// # Returns
+/// * `{:ok, count}` with the number of entries now in `to_cf`
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, :read_only}` if the database was opened read-only
+/// * `{:error, {:invalid_cf, cf}}` if a column family is invalid
+/// * `{:error, :same_cf}` if both column families are the same
+/// * `{:error, :ttl_cf}` if either is `derived` on a database opened with
+///   `open_with_ttl`
+/// * `{:error, {:batch_failed, reason}}` on read or write errors
+/// * `{:error, {:ingest_failed, reason}}` if RocksDB rejects an SST file; if
+///   only the one for `from_cf` is rejected, `to_cf` is already replaced
+#[rustler::nif(schedule = "DirtyCpu")]
+fn swap_cf_contents<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    from_cf: Term<'a>,
+    to_cf: Term<'a>,
+) -> NifResult<Term<'a>> {
+    if db_ref.read_only {
+        return Ok((atoms::error(), atoms::read_only()).encode(env));
+    }
+
+    let from_name = match cf_term_to_name(from_cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), from_cf)).encode(env)),
+    };
+    let to_name = match cf_term_to_name(to_cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), to_cf)).encode(env)),
+    };
+    if from_name == to_name {
+        return Ok((atoms::error(), atoms::same_cf()).encode(env));
+    }
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let from_handle = match shared_db.cf_handle(&from_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), from_cf)).encode(env)),
+    };
+    let to_handle = match shared_db.cf_handle(&to_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), to_cf)).encode(env)),
+    };
+    // Values are copied as stored, so stamped ones would end up in the other CF
+    if shared_db.stamps(&from_name) || shared_db.stamps(&to_name) {
+        return Ok((atoms::error(), atoms::ttl_cf()).encode(env));
+    }
+
+    let result = if shared_db.in_memory {
+        swap_with_batch(shared_db, from_handle, to_handle)
+    } else {
+        let dir = std::path::Path::new(&shared_db.path).join(format!(
+            "swap-{}.tmp",
+            SWAP_SEQ.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = swap_with_ingest(shared_db, from_handle, to_handle, &dir);
+        let _ = std::fs::remove_dir_all(&dir);
+        result
+    };
+
+    match result {
+        Ok(count) => Ok((atoms::ok(), count).encode(env)),
+        Err(error) => Ok((atoms::error(), error).encode(env)),
+    }
+}
+
+/// Merges the entries of `from_cf` and `to_cf` by key, as read from one
+/// snapshot, and hands `emit` each key with its new value in `to_cf` and in
+/// `from_cf` (`None` to delete it). Returns the number of `from_cf` entries.
+fn merge_swapped_entries(
+    shared_db: &SharedDb,
+    from_handle: &rocksdb::ColumnFamily,
+    to_handle: &rocksdb::ColumnFamily,
+    mut emit: impl FnMut(&[u8], Option<&[u8]>, Option<&[u8]>) -> Result<(), rocksdb::Error>,
+) -> Result<usize, rocksdb::Error> {
+    let snap = shared_db.db.snapshot();
+    let scan = |cf_handle| {
+        let mut read_opts = ReadOptions::default();
+        read_opts.set_snapshot(&snap);
+        read_opts.set_total_order_seek(true);
+        shared_db
+            .db
+            .iterator_cf_opt(cf_handle, read_opts, IteratorMode::Start)
+    };
+    let mut incoming = scan(from_handle);
+    let mut outgoing = scan(to_handle);
+
+    let mut next_in: Option<KvPair> = incoming.next().transpose()?;
+    let mut next_out: Option<KvPair> = outgoing.next().transpose()?;
+    let mut count = 0;
+    loop {
+        let order = match (&next_in, &next_out) {
+            (None, None) => return Ok(count),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (Some((in_key, _)), Some((out_key, _))) => in_key.cmp(out_key),
+        };
+        match order {
+            std::cmp::Ordering::Less => {
+                let (key, value) = next_in.take().expect("checked above");
+                emit(&key, Some(&value), None)?;
+                count += 1;
+                next_in = incoming.next().transpose()?;
+            }
+            std::cmp::Ordering::Greater => {
+                let (key, value) = next_out.take().expect("checked above");
+                emit(&key, None, Some(&value))?;
+                next_out = outgoing.next().transpose()?;
+            }
+            std::cmp::Ordering::Equal => {
+                let (key, in_value) = next_in.take().expect("checked above");
+                let (_, out_value) = next_out.take().expect("checked above");
+                emit(&key, Some(&in_value), Some(&out_value))?;
+                count += 1;
+                next_in = incoming.next().transpose()?;
+                next_out = outgoing.next().transpose()?;
+            }
+        }
+    }
+}
+
+/// `swap_cf_contents` for an in-memory database: one WriteBatch for both CFs.
+fn swap_with_batch(
+    shared_db: &SharedDb,
+    from_handle: &rocksdb::ColumnFamily,
+    to_handle: &rocksdb::ColumnFamily,
+) -> Result<usize, (rustler::Atom, String)> {
+    let batch_failed = |e: rocksdb::Error| (atoms::batch_failed(), e.to_string());
+
+    let mut batch = WriteBatch::default();
+    let count = merge_swapped_entries(
+        shared_db,
+        from_handle,
+        to_handle,
+        |key, to_value, from_value| {
+            for (cf_handle, value) in [(to_handle, to_value), (from_handle, from_value)] {
+                match value {
+                    Some(value) => batch.put_cf(cf_handle, key, value),
+                    None => batch.delete_cf(cf_handle, key),
+                }
+            }
+            Ok(())
+        },
+    )
+    .map_err(batch_failed)?;
+
+    shared_db.db.write(batch).map_err(batch_failed)?;
+    Ok(count)
+}
+
+/// `swap_cf_contents` for an on-disk database: writes one SST file per CF
+/// into `dir` and ingests them, `to_cf` first.
+fn swap_with_ingest(
+    shared_db: &SharedDb,
+    from_handle: &rocksdb::ColumnFamily,
+    to_handle: &rocksdb::ColumnFamily,
+    dir: &std::path::Path,
+) -> Result<usize, (rustler::Atom, String)> {
+    let batch_failed = |e: rocksdb::Error| (atoms::batch_failed(), e.to_string());
+
+    std::fs::create_dir_all(dir).map_err(|e| (atoms::batch_failed(), e.to_string()))?;
+    let to_file = dir.join("to.sst");
+    let from_file = dir.join("from.sst");
+
+    let sst_opts = Options::default();
+    let mut to_writer = SstFileWriter::create(&sst_opts);
+    let mut from_writer = SstFileWriter::create(&sst_opts);
+    to_writer.open(&to_file).map_err(batch_failed)?;
+    from_writer.open(&from_file).map_err(batch_failed)?;
+
+    let mut written = false;
+    let count = merge_swapped_entries(
+        shared_db,
+        from_handle,
+        to_handle,
+        |key, to_value, from_value| {
+            written = true;
+            for (writer, value) in [(&mut to_writer, to_value), (&mut from_writer, from_value)] {
+                match value {
+                    Some(value) => writer.put(key, value)?,
+                    None => writer.delete(key)?,
+                }
+            }
+            Ok(())
+        },
+    )
+    .map_err(batch_failed)?;
+
+    // Both CFs are empty; RocksDB refuses to finish an SST file without entries
+    if !written {
+        return Ok(0);
+    }
+    to_writer.finish().map_err(batch_failed)?;
+    from_writer.finish().map_err(batch_failed)?;
+
+    let mut ingest_opts = IngestExternalFileOptions::default();
+    ingest_opts.set_move_files(true);
+    for (cf_handle, file) in [(to_handle, &to_file), (from_handle, &from_file)] {
+        shared_db
+            .db
+            .ingest_external_file_cf_opts(cf_handle, &ingest_opts, vec![file])
+            .map_err(|e| (atoms::ingest_failed(), e.to_string()))?;
+    }
+    Ok(count)
+}
+
+/// Atomically writes one quad to the three named-graph indexes.
+///
+/// Keys are pre-encoded by the caller with the graph ID as the leading 8 bytes
+/// (`g|s|p|o`, `g|p|o|s`, `g|o|s|p`), so a prefix scan on the graph ID lists
+/// every quad in that graph. Values are empty, as for the triple indexes.
+/// Default-graph triples stay in spo/pos/osp.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `gspo_key` - Key for the gspo index
+/// * `gpos_key` - Key for the gpos index
+/// * `gosp_key` - Key for the gosp index
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, :read_only}` if the database was opened read-only
+/// * `{:error, {:cf_not_opened, cf}}` if a quad index was not opened
+/// * `{:error, {:batch_failed, reason}}` on write errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn put_quad<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    gspo_key: Binary<'a>,
+    gpos_key: Binary<'a>,
+    gosp_key: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    if db_ref.read_only {
+        return Ok((atoms::error(), atoms::read_only()).encode(env));
+    }
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let mut batch = WriteBatch::default();
+    for (cf_atom, key) in [
+        (atoms::gspo(), gspo_key),
+        (atoms::gpos(), gpos_key),
+        (atoms::gosp(), gosp_key),
+    ] {
+        let cf_handle = match cf_atom_to_name(cf_atom).and_then(|name| shared_db.cf_handle(name)) {
+            Some(cf) => cf,
+            None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf_atom)).encode(env)),
+        };
+        batch.put_cf(&cf_handle, key.as_slice(), b"");
+    }
+
+    if fault_injection::take_write_fault(shared_db) {
+        return Ok((atoms::error(), (atoms::batch_failed(), "injected")).encode(env));
+    }
+
+    match shared_db.db.write(batch) {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::batch_failed(), e.to_string())).encode(env)),
+    }
+}
+
+// ============================================================================
+// Column Family Management
+// ============================================================================
+
+/// How long `exclusive_db` waits for other holders of the database to let go.
+const EXCLUSIVE_WAIT: Duration = Duration::from_secs(1);
+
+/// Gives exclusive access to the open database, or the error term to return.
+///
+/// RocksDB needs `&mut DB` to create or drop a column family, so this needs
+/// the only reference to the `SharedDb`; a dropped CF must not disappear
+/// under anyone using it. Calls that loaded the database before the checkout
+/// are given up to `EXCLUSIVE_WAIT` to finish. Iterators, snapshots and pinned
+/// values still open after that make it fail with `db_in_use`, which callers
+/// can retry once they are released. The `subscribe_events` poller is
+/// detached meanwhile and skips its polls.
+fn exclusive_db<'a, 'g>(
+    env: Env<'a>,
+    checkout: &'g mut Option<CheckedOutDb<'_>>,
+) -> Result<&'g mut SharedDb, Term<'a>> {
+    let shared_db = match checkout
+        .as_mut()
+        .and_then(|checkout| checkout.shared_db.as_mut())
+    {
+        Some(shared_db) => shared_db,
+        None => return Err((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    // The poller's weak reference would also rule out `get_mut`
+    shared_db.event_feed().detach();
+    let deadline = std::time::Instant::now() + EXCLUSIVE_WAIT;
+    while Arc::strong_count(shared_db) > 1 && std::time::Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    Arc::get_mut(shared_db).ok_or_else(|| (atoms::error(), atoms::db_in_use()).encode(env))
+}
+
+/// Creates a column family on the open database without reopening it.
+///
+/// Meant for transient CFs such as the staging area of a bulk reindex job.
+/// The new CF is usable right away by name binary, like the ones given to
+/// `open_with_cfs`. It is not reopened automatically: a later open must list
+/// it in `open_with_cfs` (or drop it first). A built-in CF that this handle
+/// did not open (see `open_cfs`) can be created too and gets its tuned options.
+///
+/// `opts` accepts the per-CF settings of `open_with_options`: `compression`
+/// and `bloom_filter_bits`. Unknown keys are ignored.
+///
+/// Checks the database out of the handle, so no other operation on this
+/// handle interleaves.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `name` - Name of the new column family
+/// * `opts` - Map of settings
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, :read_only}` if the database was opened read-only
+/// * `{:error, {:invalid_cf, name}}` if the name is empty or `"default"`
+/// * `{:error, {:invalid_option, key}}` if a setting has an invalid value, or
+///   `{:error, {:invalid_option, name}}` if `opts` isn't a map
+/// * `{:error, :db_in_use}` while iterators, snapshots or pinned values hold the database (retryable)
+/// * `{:error, {:create_cf_failed, reason}}` if RocksDB rejects it, e.g. because it exists
+#[rustler::nif(schedule = "DirtyCpu")]
+fn create_column_family<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    name: String,
+    opts: Term<'a>,
+) -> NifResult<Term<'a>> {
+    if db_ref.read_only {
+        return Ok((atoms::error(), atoms::read_only()).encode(env));
+    }
+    if name.is_empty() || name == rocksdb::DEFAULT_COLUMN_FAMILY_NAME {
+        return Ok((atoms::error(), (atoms::invalid_cf(), name)).encode(env));
+    }
+
+    let mut overrides = CfOpenOptions::default();
+    if let Err(bad_key) = apply_cf_settings(&mut overrides, name.as_str().encode(env), opts) {
+        return Ok((atoms::error(), (atoms::invalid_option(), bad_key)).encode(env));
+    }
+
+    let mut checkout = db_ref.check_out();
+
+    let shared_db = match exclusive_db(env, &mut checkout) {
+        Ok(db) => db,
+        Err(error) => return Ok(error),
+    };
+
+    let cf_name = intern_cf_name(&name);
+    let cf_opts = cf_options_for(&cf_name, &overrides, &DbOpenOptions::default(), None);
+    if let Err(e) = shared_db.db.create_cf(&*cf_name, &cf_opts) {
+        return Ok((atoms::error(), (atoms::create_cf_failed(), e.to_string())).encode(env));
+    }
+
+    if merge_operator_for(&cf_name, &overrides, &DbOpenOptions::default()).is_some() {
+        shared_db.merge_cfs.insert(cf_name.clone());
+    }
+    shared_db.opened_cfs.insert(cf_name);
+    Ok(atoms::ok().encode(env))
+}
+
+/// Drops a column family and all of its data from the open database.
+///
+/// Only column families outside the built-in set can be dropped, i.e. the
+/// ones from `open_with_cfs` or `create_column_family`. The CF's files are
+/// deleted by RocksDB once nothing references them any more.
+///
+/// Checks the database out of the handle, so no other operation on this
+/// handle interleaves.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - Name binary of the column family
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, :read_only}` if the database was opened read-only
+/// * `{:error, :protected_cf}` for a built-in column family
+/// * `{:error, {:invalid_cf, cf}}` if the column family is unknown
+/// * `{:error, {:cf_not_opened, cf}}` if this handle didn't open it
+/// * `{:error, :db_in_use}` while iterators, snapshots or pinned values hold the database (retryable)
+/// * `{:error, {:drop_cf_failed, reason}}` if RocksDB rejects it
+#[rustler::nif(schedule = "DirtyCpu")]
+fn drop_column_family<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+) -> NifResult<Term<'a>> {
+    if db_ref.read_only {
+        return Ok((atoms::error(), atoms::read_only()).encode(env));
+    }
+    let cf_name = match cf_term_to_name(cf) {
+        Some(CfName::Builtin(_)) => return Ok((atoms::error(), atoms::protected_cf()).encode(env)),
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let mut checkout = db_ref.check_out();
+
+    let shared_db = match exclusive_db(env, &mut checkout) {
+        Ok(db) => db,
+        Err(error) => return Ok(error),
+    };
+
+    if !shared_db.opened_cfs.contains(&cf_name) {
+        return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env));
+    }
+    if let Err(e) = shared_db.db.drop_cf(&cf_name) {
+        return Ok((atoms::error(), (atoms::drop_cf_failed(), e.to_string())).encode(env));
+    }
+
+    shared_db.opened_cfs.remove(&cf_name);
+    shared_db.merge_cfs.remove(&cf_name);
+    drop(cf_name);
+    forget_unused_cf_names();
+    Ok(atoms::ok().encode(env))
+}
+
+// ============================================================================
+// SST Ingestion
+// ============================================================================
+
+/// Loads externally written SST files into a column family.
+///
+/// The files (written with RocksDB's `SstFileWriter`, sorted and without
+/// duplicate keys) bypass the memtable and WAL: RocksDB links or copies them
+/// into the database and places each one at the lowest LSM level its key range
+/// fits into. The files must not overlap each other. Keys that overlap data
+/// already in the column family are allowed; the ingested values then win,
+/// but the file has to go to a higher level and any overlapping memtable is
+/// flushed first, which makes the call slower. All files are ingested
+/// atomically: either all of them become visible or none do.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - Column family to ingest into
+/// * `file_paths` - Paths of the SST files
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, :read_only}` if the database was opened read-only
+/// * `{:error, {:invalid_cf, cf}}` if the column family is unknown
+/// * `{:error, {:cf_not_opened, cf}}` if the column family was not opened
+/// * `{:error, :ttl_cf}` for `derived` on a database opened with `open_with_ttl`
+/// * `{:error, {:ingest_failed, reason}}` if RocksDB rejects the files
+#[rustler::nif(schedule = "DirtyCpu")]
+fn ingest_external_file<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    file_paths: Vec<String>,
+) -> NifResult<Term<'a>> {
+    ingest_sst_files(
+        env,
+        db_ref,
+        cf,
+        file_paths,
+        IngestExternalFileOptions::default(),
+    )
+}
+
+/// Same as `ingest_external_file/3`, with an options map.
+///
+/// Options (unknown keys are ignored):
+/// - `move_files` - `true` moves (hard-links) the files into the database
+///   instead of copying them; the originals must then be left alone.
+///   Defaults to `false`.
+///
+/// # Returns
+/// As `ingest_external_file/3`, plus `{:error, {:invalid_option, key}}` for a
+/// bad option value.
+#[rustler::nif(name = "ingest_external_file", schedule = "DirtyCpu")]
+fn ingest_external_file_with_options<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    file_paths: Vec<String>,
+    opts: Term<'a>,
+) -> NifResult<Term<'a>> {
+    let mut ingest_opts = IngestExternalFileOptions::default();
+    let entries = match opts.decode::<MapIterator>() {
+        Ok(entries) => entries,
+        Err(_) => return Ok((atoms::error(), (atoms::invalid_option(), opts)).encode(env)),
+    };
+    for (key, value) in entries {
+        if key.decode::<rustler::Atom>().ok() == Some(atoms::move_files()) {
+            match value.decode::<bool>() {
+                Ok(move_files) => ingest_opts.set_move_files(move_files),
+                Err(_) => return Ok((atoms::error(), (atoms::invalid_option(), key)).encode(env)),
+            }
+        }
+    }
+    ingest_sst_files(env, db_ref, cf, file_paths, ingest_opts)
+}
+
+/// Shared implementation of `ingest_external_file/3` and `ingest_external_file/4`.
+fn ingest_sst_files<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    file_paths: Vec<String>,
+    ingest_opts: IngestExternalFileOptions,
+) -> NifResult<Term<'a>> {
+    if db_ref.read_only {
+        return Ok((atoms::error(), atoms::read_only()).encode(env));
+    }
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+    // The files' values carry no `open_with_ttl` stamp
+    if shared_db.stamps(&cf_name) {
+        return Ok((atoms::error(), atoms::ttl_cf()).encode(env));
+    }
+
+    match shared_db
+        .db
+        .ingest_external_file_cf_opts(&cf_handle, &ingest_opts, file_paths)
+    {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::ingest_failed(), e.to_string())).encode(env)),
+    }
+}
+
+// ============================================================================
+// Iterator Operations
+// ============================================================================
+
+/// Creates a prefix iterator for a column family.
+///
+/// The iterator returns all key-value pairs where the key starts with the given prefix.
+/// The iterator must be closed with `iterator_close` when done.
+///
+/// The iterator reads from a snapshot taken when it is created. Seeks, resets
+/// and `iterator_prev` rebuild it from the same snapshot, so writes made after
+/// its creation never show up in it.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `prefix` - The prefix to iterate over
+///
+/// # Returns
+/// * `{:ok, iterator_ref}` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+#[rustler::nif(schedule = "DirtyCpu")]
+fn prefix_iterator<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    prefix: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    create_prefix_iterator(env, db_ref, cf, prefix.as_slice(), PrefixScan::default())
+}
+
+/// Creates a prefix iterator walking in the given direction, or with options.
+///
+/// With `:forward` this is `prefix_iterator/3`. With `:reverse` the iterator
+/// starts at the last key matching the prefix and `iterator_next` steps toward
+/// smaller keys, stopping once keys no longer start with the prefix; this lets
+/// descending scans (e.g. ORDER BY ... DESC over `pos`) stream instead of
+/// buffering. For reverse iterators `iterator_seek` positions at the largest
+/// key `<= target`, and `iterator_reset` rewinds to the last key of the prefix.
+///
+/// A keyword list takes `{:direction, :forward | :reverse}` and
+/// `{:upper_bound, key}`. The upper bound is an exclusive key the iterator
+/// stops at in addition to the prefix check, for example a pagination cursor.
+/// It is set as the RocksDB iterate upper bound, so blocks past it are never
+/// read, and it is kept when `iterator_seek` or `iterator_reset` rebuild the
+/// iterator. A reverse iterator with an upper bound starts at the last key
+/// below it.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `prefix` - The prefix to iterate over
+/// * `opts` - `:forward`, `:reverse` or a keyword list
+///
+/// # Returns
+/// * `{:ok, iterator_ref}` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:invalid_direction, direction}}` if direction is not recognized
+/// * `{:error, {:invalid_option, name}}` if an option has the wrong type
+#[rustler::nif(name = "prefix_iterator", schedule = "DirtyCpu")]
+fn prefix_iterator_with_direction<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    prefix: Binary<'a>,
+    opts: Term<'a>,
+) -> NifResult<Term<'a>> {
+    let mut scan = PrefixScan::default();
+    let mut direction = atoms::forward();
+
+    match opts.decode::<rustler::Atom>() {
+        Ok(atom) => direction = atom,
+        Err(_) => {
+            for (name, value) in opts.decode::<Vec<(rustler::Atom, Term<'a>)>>()? {
+                let valid = if name == atoms::direction() {
+                    value.decode().map(|atom| direction = atom).is_ok()
+                } else if name == atoms::upper_bound() {
+                    value
+                        .decode::<Binary>()
+                        .map(|upper| scan.upper_bound = Some(upper.as_slice().to_vec()))
+                        .is_ok()
+                } else {
+                    true
+                };
+                if !valid {
+                    return Ok((atoms::error(), (atoms::invalid_option(), name)).encode(env));
+                }
+            }
+        }
+    }
+
+    scan.reverse = if direction == atoms::forward() {
+        false
+    } else if direction == atoms::reverse() {
+        true
+    } else {
+        return Ok((atoms::error(), (atoms::invalid_direction(), direction)).encode(env));
+    };
+
+    create_prefix_iterator(env, db_ref, cf, prefix.as_slice(), scan)
+}
+
+/// Creates a prefix iterator that only yields entries whose value starts with
+/// `value_prefix`.
+///
+/// Filtering happens in Rust, so non-matching entries never cross the NIF
+/// boundary and no binaries are allocated for them. The key prefix bound is
+/// applied exactly as in `prefix_iterator`. An empty `value_prefix` matches
+/// every entry.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `prefix` - The key prefix to iterate over
+/// * `value_prefix` - Required prefix of each yielded value
+///
+/// # Returns
+/// * `{:ok, iterator_ref}` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+#[rustler::nif(schedule = "DirtyCpu")]
+fn prefix_iterator_filtered<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    prefix: Binary<'a>,
+    value_prefix: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    let scan = PrefixScan {
+        value_prefix: value_prefix.as_slice().to_vec(),
+        ..Default::default()
+    };
+    create_prefix_iterator(env, db_ref, cf, prefix.as_slice(), scan)
+}
+
+/// Creates a prefix iterator positioned strictly after `after_key`.
+///
+/// The first `iterator_next` returns the smallest key within `prefix` that is
+/// greater than `after_key`, whether or not `after_key` itself exists. Meant
+/// for pagination: pass the last key of the previous page. If `after_key`
+/// sorts before the prefix, iteration starts at the prefix as usual.
+/// `iterator_reset` rewinds to the start of the prefix, not to `after_key`.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `prefix` - The key prefix to iterate over
+/// * `after_key` - Exclusive lower bound for returned keys
+///
+/// # Returns
+/// * `{:ok, iterator_ref}` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+#[rustler::nif(schedule = "DirtyCpu")]
+fn prefix_iterator_after<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    prefix: Binary<'a>,
+    after_key: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    // after_key followed by a zero byte is the smallest key greater than after_key
+    let mut seek_key = after_key.as_slice().to_vec();
+    seek_key.push(0);
+    let seek_from = if seek_key.as_slice() > prefix.as_slice() {
+        Some(seek_key)
+    } else {
+        None
+    };
+
+    let scan = PrefixScan {
+        seek_from,
+        ..Default::default()
+    };
+    create_prefix_iterator(env, db_ref, cf, prefix.as_slice(), scan)
+}
+
+/// How a prefix iterator walks its prefix.
+#[derive(Default)]
+struct PrefixScan {
+    /// Entries whose value doesn't start with this are skipped (empty matches all)
+    value_prefix: Vec<u8>,
+    /// Starts a forward iterator at a key other than the prefix itself
+    seek_from: Option<Vec<u8>>,
+    /// Exclusive key to stop at before the end of the prefix
+    upper_bound: Option<Vec<u8>>,
+    reverse: bool,
+}
+
+/// The tighter of `upper` and the end of `prefix`, so a reverse iterator
+/// starting at the upper bound never starts outside the prefix.
+fn prefix_upper_bound(prefix: &[u8], upper: Vec<u8>) -> Vec<u8> {
+    match prefix_successor(prefix) {
+        Some(successor) if successor < upper => successor,
+        _ => upper,
+    }
+}
+
+/// Shared implementation of the prefix iterator constructors.
+fn create_prefix_iterator<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    prefix: &[u8],
+    scan: PrefixScan,
+) -> NifResult<Term<'a>> {
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => Arc::clone(db),
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+
+    let prefix_bytes = prefix.to_vec();
+
+    // Configure read options for prefix iteration
+    // For CFs with prefix extractors, we need to handle different prefix lengths:
+    // - If prefix >= PREFIX_LENGTH bytes: use prefix-based iteration for bloom filter benefits
+    // - If prefix < PREFIX_LENGTH bytes: use total_order_seek to avoid bloom filter issues
+    let mut read_opts = ReadOptions::default();
+    if PREFIX_CFS.contains(&&*cf_name) {
+        if prefix_bytes.len() >= PREFIX_LENGTH {
+            // Use prefix-based seek for bloom filter benefits
+            read_opts.set_prefix_same_as_start(true);
+            read_opts.set_total_order_seek(false);
+        } else {
+            // Short prefix: use total_order_seek to avoid incorrect bloom filter behavior
+            read_opts.set_total_order_seek(true);
+        }
+    }
+
+    let upper_bound = scan
+        .upper_bound
+        .map(|upper| prefix_upper_bound(&prefix_bytes, upper));
+    if let Some(upper) = &upper_bound {
+        read_opts.set_iterate_upper_bound(upper.clone());
+    }
+
+    // Seeks and resets rebuild the iterator from the same snapshot
+    let snapshot = iterator_snapshot(&shared_db);
+    read_opts.set_snapshot(&snapshot);
+
+    // Create the iterator with configured read options
+    let start = scan.seek_from.as_deref().unwrap_or(&prefix_bytes);
+    let iterator = if scan.reverse {
+        // The upper bound makes End the last key of the prefix
+        let mut reverse_opts = reverse_prefix_read_options(&prefix_bytes);
+        if let Some(upper) = &upper_bound {
+            reverse_opts.set_iterate_upper_bound(upper.clone());
+        }
+        reverse_opts.set_snapshot(&snapshot);
+        shared_db
+            .db
+            .iterator_cf_opt(&cf_handle, reverse_opts, IteratorMode::End)
+    } else {
+        shared_db.db.iterator_cf_opt(
+            &cf_handle,
+            read_opts,
+            IteratorMode::From(start, rocksdb::Direction::Forward),
+        )
+    };
+
+    // SAFETY: We keep the SharedDb alive via Arc, so the iterator remains valid.
+    // The Arc<SharedDb> is stored in IteratorRef and will keep the DB alive
+    // even if DbRef.close() is called, preventing use-after-free.
+    let static_iterator: DBIteratorWithThreadMode<'static, DB> =
+        unsafe { std::mem::transmute(iterator) };
+
+    let iter_ref = ResourceArc::new(
+        IteratorRef::new(
+            static_iterator,
+            snapshot,
+            shared_db,
+            prefix_bytes,
+            cf_name.to_string(),
+            scan.value_prefix,
+            scan.reverse,
+        )
+        .with_upper_bound(upper_bound),
+    );
+
+    Ok((atoms::ok(), iter_ref).encode(env))
+}
+
+/// Creates an iterator over the key range `[lower, upper)`.
+///
+/// The bounds are set on the RocksDB read options, so RocksDB stops at
+/// `upper` itself and can skip SST files entirely outside the range, instead
+/// of `iterator_next` checking every key against a prefix. Prefer this over
+/// `prefix_iterator` for large ranges and for ranges that span several
+/// prefixes. Works with every iterator operation; `iterator_seek` targets
+/// below `lower` start at `lower`, and `iterator_reset` rewinds to `lower`.
+/// `iterator_next_reason` reports `:prefix_boundary` when keys exist at or
+/// after `upper`.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `lower` - Inclusive lower bound
+/// * `upper` - Exclusive upper bound
+///
+/// # Returns
+/// * `{:ok, iterator_ref}` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, :invalid_range}` if `lower` is greater than `upper`
+#[rustler::nif(schedule = "DirtyCpu")]
+fn bounded_iterator<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    lower: Binary<'a>,
+    upper: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    create_bounded_iterator(
+        env,
+        db_ref,
+        cf,
+        lower.as_slice().to_vec(),
+        upper.as_slice().to_vec(),
+    )
+}
+
+/// Creates an iterator over the keys from `start_key` up to `end_key`.
+///
+/// Like `bounded_iterator`, the range is set as RocksDB lower/upper bounds
+/// and stored on the iterator instead of a prefix, so this can express scans
+/// such as `S1P1` up to `S1P9` over sortable literal keys. `end_key` is
+/// exclusive unless `inclusive_end` is set, in which case the upper bound
+/// becomes the smallest key after `end_key` (`end_key <> <<0>>`).
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `start_key` - Inclusive first key
+/// * `end_key` - Last key, exclusive by default
+/// * `opts` - Keyword options: `{:inclusive_end, boolean}` (default false)
+///
+/// # Returns
+/// * `{:ok, iterator_ref}` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, :invalid_range}` if `start_key` is greater than `end_key`
+/// * `{:error, {:invalid_option, name}}` if an option has the wrong type
+#[rustler::nif(schedule = "DirtyCpu")]
+fn range_iterator<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    start_key: Binary<'a>,
+    end_key: Binary<'a>,
+    opts: Vec<(rustler::Atom, Term<'a>)>,
+) -> NifResult<Term<'a>> {
+    let mut inclusive_end = false;
+    for (name, value) in opts {
+        if name == atoms::inclusive_end() {
+            match value.decode::<bool>() {
+                Ok(flag) => inclusive_end = flag,
+                Err(_) => return Ok((atoms::error(), (atoms::invalid_option(), name)).encode(env)),
+            }
+        }
+    }
+
+    if start_key.as_slice() > end_key.as_slice() {
+        return Ok((atoms::error(), atoms::invalid_range()).encode(env));
+    }
+
+    let mut upper = end_key.as_slice().to_vec();
+    if inclusive_end {
+        upper.push(0);
+    }
+
+    create_bounded_iterator(env, db_ref, cf, start_key.as_slice().to_vec(), upper)
+}
+
+/// Shared implementation of the bounded iterator constructors.
+fn create_bounded_iterator<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    lower: Vec<u8>,
+    upper: Vec<u8>,
+) -> NifResult<Term<'a>> {
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    if lower > upper {
+        return Ok((atoms::error(), atoms::invalid_range()).encode(env));
+    }
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => Arc::clone(db),
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+
+    let bounds = (lower, upper);
+    // Seeks and resets rebuild the iterator from the same snapshot
+    let snapshot = iterator_snapshot(&shared_db);
+    let mut read_opts = bounded_read_options(&bounds.0, &bounds.1);
+    read_opts.set_snapshot(&snapshot);
+    let iterator = shared_db.db.iterator_cf_opt(
+        &cf_handle,
+        read_opts,
+        IteratorMode::From(&bounds.0, rocksdb::Direction::Forward),
+    );
+
+    // SAFETY: We keep the SharedDb alive via Arc, so the iterator remains valid.
+    // The Arc<SharedDb> is stored in IteratorRef and will keep the DB alive
+    // even if DbRef.close() is called, preventing use-after-free.
+    let static_iterator: DBIteratorWithThreadMode<'static, DB> =
+        unsafe { std::mem::transmute(iterator) };
+
+    // An empty prefix matches every key, so the bounds are the only limit
+    let iter_ref = ResourceArc::new(
+        IteratorRef::new(
+            static_iterator,
+            snapshot,
+            shared_db,
+            Vec::new(),
+            cf_name.to_string(),
+            Vec::new(),
+            false,
+        )
+        .with_bounds(bounds),
+    );
+
+    Ok((atoms::ok(), iter_ref).encode(env))
+}
+
+/// Builds read options for walking `prefix` backwards. Prefix seek mode
+/// doesn't support reverse iteration, so the prefix is expressed as bounds.
+fn reverse_prefix_read_options(prefix: &[u8]) -> ReadOptions {
+    let mut read_opts = ReadOptions::default();
+    read_opts.set_total_order_seek(true);
+    read_opts.set_iterate_lower_bound(prefix.to_vec());
+    if let Some(successor) = prefix_successor(prefix) {
+        read_opts.set_iterate_upper_bound(successor);
+    }
+    read_opts
+}
+
+/// Builds read options that make RocksDB enforce `[lower, upper)`.
+/// Total order seek is required since the range may cross prefixes.
+fn bounded_read_options(lower: &[u8], upper: &[u8]) -> ReadOptions {
+    let mut read_opts = ReadOptions::default();
+    read_opts.set_total_order_seek(true);
+    read_opts.set_iterate_lower_bound(lower.to_vec());
+    read_opts.set_iterate_upper_bound(upper.to_vec());
+    read_opts
+}
+
+/// Gets the next key-value pair from the iterator.
+///
+/// # Arguments
+/// * `iter_ref` - The iterator reference
+///
+/// # Returns
+/// * `{:ok, key, value}` if there's a next item with matching prefix
+/// * `:end` if the iterator is exhausted or prefix no longer matches
+/// * `{:error, :iterator_closed}` if iterator was closed
+/// * `{:error, {:iterator_failed, reason}}` on error
+#[rustler::nif(schedule = "DirtyCpu")]
+fn iterator_next<'a>(env: Env<'a>, iter_ref: ResourceArc<IteratorRef>) -> NifResult<Term<'a>> {
+    let mut iter_guard = iter_ref
+        .iterator
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let iterator = match iter_guard.as_mut() {
+        Some(iter) => iter,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    };
+
+    let mut cursor = iter_ref
+        .cursor
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    if !iter_ref.resume(iterator, &mut cursor) {
+        return Ok((atoms::error(), atoms::iterator_closed()).encode(env));
+    }
+
+    loop {
+        match iterator.next() {
+            Some(Ok((key, value))) => {
+                let value = iter_ref.db.unstamp(&iter_ref.cf_name, &value);
+                // Check if key is still within the prefix or bounds
+                if !iter_ref.covers(&key) {
+                    cursor.exhausted();
+                    return Ok(atoms::iterator_end().encode(env));
+                }
+
+                // Skip entries rejected by the value filter without copying them
+                if !value.starts_with(&iter_ref.value_prefix) {
+                    continue;
+                }
+
+                cursor.record(&key);
+
+                let mut key_binary = NewBinary::new(env, key.len());
+                key_binary.as_mut_slice().copy_from_slice(&key);
+
+                let mut value_binary = NewBinary::new(env, value.len());
+                value_binary.as_mut_slice().copy_from_slice(value);
+
+                return Ok((
+                    atoms::ok(),
+                    Binary::from(key_binary),
+                    Binary::from(value_binary),
+                )
+                    .encode(env));
+            }
+            Some(Err(e)) => {
+                return Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env));
+            }
+            None => {
+                cursor.exhausted();
+                return Ok(atoms::iterator_end().encode(env));
+            }
+        }
+    }
+}
+
+/// Gets the next key-value pair from the iterator, reporting why iteration ended.
+///
+/// Behaves like `iterator_next`, but distinguishes the two ways an iterator can
+/// become exhausted: the next key falls outside the iterator's prefix, or the
+/// column family has no more keys at all.
+///
+/// # Arguments
+/// * `iter_ref` - The iterator reference
+///
+/// # Returns
+/// * `{:ok, key, value}` if there's a next item with matching prefix
+/// * `{:iterator_end, :prefix_boundary}` if the next key no longer matches the prefix
+/// * `{:iterator_end, :cf_end}` if the column family is exhausted
+/// * `{:error, :iterator_closed}` if iterator was closed
+/// * `{:error, {:iterator_failed, reason}}` on error
+#[rustler::nif(schedule = "DirtyCpu")]
+fn iterator_next_reason<'a>(
+    env: Env<'a>,
+    iter_ref: ResourceArc<IteratorRef>,
+) -> NifResult<Term<'a>> {
+    let mut iter_guard = iter_ref
+        .iterator
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let iterator = match iter_guard.as_mut() {
+        Some(iter) => iter,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    };
+
+    let mut cursor = iter_ref
+        .cursor
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    if !iter_ref.resume(iterator, &mut cursor) {
+        return Ok((atoms::error(), atoms::iterator_closed()).encode(env));
+    }
+
+    loop {
+        match iterator.next() {
+            Some(Ok((key, value))) => {
+                let value = iter_ref.db.unstamp(&iter_ref.cf_name, &value);
+                if !iter_ref.covers(&key) {
+                    cursor.exhausted();
+                    return Ok((atoms::iterator_end(), atoms::prefix_boundary()).encode(env));
+                }
+
+                if !value.starts_with(&iter_ref.value_prefix) {
+                    continue;
+                }
+
+                cursor.record(&key);
+
+                let mut key_binary = NewBinary::new(env, key.len());
+                key_binary.as_mut_slice().copy_from_slice(&key);
+
+                let mut value_binary = NewBinary::new(env, value.len());
+                value_binary.as_mut_slice().copy_from_slice(value);
+
+                return Ok((
+                    atoms::ok(),
+                    Binary::from(key_binary),
+                    Binary::from(value_binary),
+                )
+                    .encode(env));
+            }
+            Some(Err(e)) => {
+                return Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env));
+            }
+            None => {
+                cursor.exhausted();
+                let cf_name = iter_ref.cf_name.as_str();
+
+                // Reverse iterators stop at the lower bound, so probe below the prefix
+                if iter_ref.reverse {
+                    if has_keys_before(&iter_ref.db, cf_name, &iter_ref.prefix) {
+                        return Ok((atoms::iterator_end(), atoms::prefix_boundary()).encode(env));
+                    }
+                    return Ok((atoms::iterator_end(), atoms::cf_end()).encode(env));
+                }
+
+                // RocksDB stops at the upper bound itself, so probe past it
+                let upper = match &iter_ref.bounds {
+                    Some((_, upper)) => Some(upper),
+                    None => iter_ref.upper_bound.as_ref(),
+                };
+                if let Some(upper) = upper {
+                    if has_keys_from(&iter_ref.db, cf_name, upper) {
+                        return Ok((atoms::iterator_end(), atoms::prefix_boundary()).encode(env));
+                    }
+                    return Ok((atoms::iterator_end(), atoms::cf_end()).encode(env));
+                }
+
+                // With prefix_same_as_start, RocksDB itself stops at the prefix boundary,
+                // so probe past the prefix to tell the two cases apart.
+                let native_prefix_mode =
+                    PREFIX_CFS.contains(&cf_name) && iter_ref.prefix.len() >= PREFIX_LENGTH;
+
+                if native_prefix_mode
+                    && has_keys_after_prefix(&iter_ref.db, cf_name, &iter_ref.prefix)
+                {
+                    return Ok((atoms::iterator_end(), atoms::prefix_boundary()).encode(env));
+                }
+                return Ok((atoms::iterator_end(), atoms::cf_end()).encode(env));
+            }
+        }
+    }
+}
+
+/// Gets up to `max_count` key-value pairs from the iterator in one call.
+///
+/// Applies the prefix, bounds and value filter exactly as `iterator_next`
+/// and stops early at the boundary, so a stream of batches returns the same
+/// entries as a stream of `iterator_next` calls with one NIF call per batch
+/// instead of per entry. Filtered entries don't count towards `max_count`.
+///
+/// `:more` only means the batch is full: the next batch may still be empty.
+///
+/// # Arguments
+/// * `iter_ref` - The iterator reference
+/// * `max_count` - Maximum number of entries to return
+///
+/// # Returns
+/// * `{:ok, [{key, value}, ...], :more}` if the batch filled up
+/// * `{:ok, [{key, value}, ...], :end}` if the iterator is exhausted
+/// * `{:error, :iterator_closed}` if iterator was closed
+/// * `{:error, {:iterator_failed, reason}}` on error
+#[rustler::nif(schedule = "DirtyCpu")]
+fn iterator_next_batch<'a>(
+    env: Env<'a>,
+    iter_ref: ResourceArc<IteratorRef>,
+    max_count: usize,
+) -> NifResult<Term<'a>> {
+    let mut iter_guard = iter_ref
+        .iterator
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let iterator = match iter_guard.as_mut() {
+        Some(iter) => iter,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    };
+
+    let mut cursor = iter_ref
+        .cursor
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    if !iter_ref.resume(iterator, &mut cursor) {
+        return Ok((atoms::error(), atoms::iterator_closed()).encode(env));
+    }
+
+    let mut results: Vec<Term<'a>> = Vec::with_capacity(max_count.min(1024));
+
+    while results.len() < max_count {
+        match iterator.next() {
+            Some(Ok((key, value))) => {
+                let value = iter_ref.db.unstamp(&iter_ref.cf_name, &value);
+                if !iter_ref.covers(&key) {
+                    cursor.exhausted();
+                    return Ok((atoms::ok(), results, atoms::iterator_exhausted()).encode(env));
+                }
+
+                if !value.starts_with(&iter_ref.value_prefix) {
+                    continue;
+                }
+
+                cursor.record(&key);
+
+                let mut key_binary = NewBinary::new(env, key.len());
+                key_binary.as_mut_slice().copy_from_slice(&key);
+
+                let mut value_binary = NewBinary::new(env, value.len());
+                value_binary.as_mut_slice().copy_from_slice(value);
+
+                results.push((Binary::from(key_binary), Binary::from(value_binary)).encode(env));
+            }
+            Some(Err(e)) => {
+                return Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env));
+            }
+            None => {
+                cursor.exhausted();
+                return Ok((atoms::ok(), results, atoms::iterator_exhausted()).encode(env));
+            }
+        }
+    }
+
+    Ok((atoms::ok(), results, atoms::more()).encode(env))
+}
+
+/// Skips up to `n` entries in one call, for SPARQL `OFFSET`.
+///
+/// Advances exactly like `n` calls to `iterator_next` without copying keys or
+/// values back: the prefix, bounds and value filter apply, filtered entries
+/// don't count, and skipping stops at the boundary. The next `iterator_next`
+/// returns the entry after the last one skipped.
+///
+/// # Arguments
+/// * `iter_ref` - The iterator reference
+/// * `n` - Maximum number of entries to skip
+///
+/// # Returns
+/// * `{:ok, skipped}` with `skipped < n` only if the iterator ran out first
+/// * `{:error, :iterator_closed}` if iterator was closed
+/// * `{:error, {:iterator_failed, reason}}` on error
+#[rustler::nif(schedule = "DirtyCpu")]
+fn iterator_skip<'a>(
+    env: Env<'a>,
+    iter_ref: ResourceArc<IteratorRef>,
+    n: u64,
+) -> NifResult<Term<'a>> {
+    let mut iter_guard = iter_ref
+        .iterator
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let iterator = match iter_guard.as_mut() {
+        Some(iter) => iter,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    };
+
+    let mut cursor = iter_ref
+        .cursor
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    if !iter_ref.resume(iterator, &mut cursor) {
+        return Ok((atoms::error(), atoms::iterator_closed()).encode(env));
+    }
+
+    let mut skipped: u64 = 0;
+    while skipped < n {
+        match iterator.next() {
+            Some(Ok((key, value))) => {
+                let value = iter_ref.db.unstamp(&iter_ref.cf_name, &value);
+                if !iter_ref.covers(&key) {
+                    cursor.exhausted();
+                    break;
+                }
+
+                if !value.starts_with(&iter_ref.value_prefix) {
+                    continue;
+                }
+
+                cursor.record(&key);
+                skipped += 1;
+            }
+            Some(Err(e)) => {
+                return Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env));
+            }
+            None => {
+                cursor.exhausted();
+                break;
+            }
+        }
+    }
+
+    Ok((atoms::ok(), skipped).encode(env))
+}
+
+/// Steps the iterator back to the entry before the last one returned.
+///
+/// Moves against the iterator's direction: towards smaller keys for forward
+/// iterators, towards larger keys for reverse ones. After `iterator_next`
+/// returned `b`, `iterator_prev` returns the entry before `b`, and a following
+/// `iterator_next` returns `b` again, like a cursor. After `iterator_seek`
+/// it returns the entry before the target; on a fresh or reset iterator
+/// there is nothing before the start, so it returns `:end`. Running off
+/// either end leaves the cursor there: `iterator_prev` after `:end` returns
+/// the last entry, `iterator_next` after `:end` from `iterator_prev` the first.
+///
+/// RocksDB iterators run in one direction, so the first `iterator_prev` after
+/// moving forward rebuilds the iterator backwards from the last key, and the
+/// next forward call rebuilds it again. Runs of steps in the same direction
+/// cost the same as `iterator_next`. The prefix, bounds and value filter
+/// apply as in `iterator_next`: stepping back out of the prefix returns `:end`.
+///
+/// # Arguments
+/// * `iter_ref` - The iterator reference
+///
+/// # Returns
+/// * `{:ok, key, value}` for the previous entry
+/// * `:end` if there is no previous entry within the prefix or bounds
+/// * `{:error, :iterator_closed}` if iterator was closed
+/// * `{:error, {:iterator_failed, reason}}` on error
+#[rustler::nif(schedule = "DirtyCpu")]
+fn iterator_prev<'a>(env: Env<'a>, iter_ref: ResourceArc<IteratorRef>) -> NifResult<Term<'a>> {
+    let mut iter_guard = iter_ref
+        .iterator
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let iterator = match iter_guard.as_mut() {
+        Some(iter) => iter,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    };
+
+    let mut cursor = iter_ref
+        .cursor
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    if !cursor.stepping_back {
+        // Nothing comes before the start
+        if matches!(cursor.position, CursorPosition::Start) {
+            return Ok(atoms::iterator_end().encode(env));
+        }
+        match iter_ref.step_from(&cursor.position, iter_ref.back_direction()) {
+            Some(new_iterator) => *iterator = new_iterator,
+            None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+        }
+        cursor.stepping_back = true;
+    }
+
+    loop {
+        match iterator.next() {
+            Some(Ok((key, value))) => {
+                let value = iter_ref.db.unstamp(&iter_ref.cf_name, &value);
+                if !iter_ref.covers(&key) {
+                    cursor.exhausted();
+                    return Ok(atoms::iterator_end().encode(env));
+                }
+
+                if !value.starts_with(&iter_ref.value_prefix) {
+                    continue;
+                }
+
+                cursor.record(&key);
+
+                let mut key_binary = NewBinary::new(env, key.len());
+                key_binary.as_mut_slice().copy_from_slice(&key);
+
+                let mut value_binary = NewBinary::new(env, value.len());
+                value_binary.as_mut_slice().copy_from_slice(value);
+
+                return Ok((
+                    atoms::ok(),
+                    Binary::from(key_binary),
+                    Binary::from(value_binary),
+                )
+                    .encode(env));
+            }
+            Some(Err(e)) => {
+                return Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env));
+            }
+            None => {
+                cursor.exhausted();
+                return Ok(atoms::iterator_end().encode(env));
+            }
+        }
+    }
+}
+
+/// Returns the smallest key that is greater than every key starting with `prefix`,
+/// or None if no such key exists (empty prefix or all bytes are 0xFF).
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut successor = prefix.to_vec();
+    while let Some(last) = successor.pop() {
+        if last < u8::MAX {
+            successor.push(last + 1);
+            return Some(successor);
+        }
+    }
+    None
+}
+
+/// Checks whether the column family contains any key sorting after the prefix range.
+fn has_keys_after_prefix(shared_db: &SharedDb, cf_name: &str, prefix: &[u8]) -> bool {
+    match prefix_successor(prefix) {
+        Some(successor) => has_keys_from(shared_db, cf_name, &successor),
+        None => false,
+    }
+}
+
+/// Checks whether the column family contains any key before `end`.
+fn has_keys_before(shared_db: &SharedDb, cf_name: &str, end: &[u8]) -> bool {
+    let cf_handle = match shared_db.cf_handle(cf_name) {
+        Some(cf) => cf,
+        None => return false,
+    };
+
+    let mut read_opts = ReadOptions::default();
+    read_opts.set_total_order_seek(true);
+    read_opts.set_iterate_upper_bound(end.to_vec());
+
+    let mut probe = shared_db
+        .db
+        .iterator_cf_opt(&cf_handle, read_opts, IteratorMode::End);
+
+    matches!(probe.next(), Some(Ok(_)))
+}
+
+/// Checks whether the column family contains any key at or after `start`.
+fn has_keys_from(shared_db: &SharedDb, cf_name: &str, start: &[u8]) -> bool {
+    let cf_handle = match shared_db.cf_handle(cf_name) {
+        Some(cf) => cf,
+        None => return false,
+    };
+
+    let mut read_opts = ReadOptions::default();
+    read_opts.set_total_order_seek(true);
+
+    let mut probe = shared_db.db.iterator_cf_opt(
+        &cf_handle,
+        read_opts,
+        IteratorMode::From(start, rocksdb::Direction::Forward),
+    );
+
+    matches!(probe.next(), Some(Ok(_)))
+}
+
+/// Seeks the iterator to a specific key.
+///
+/// After seeking, the iterator will return keys >= target that match the prefix.
+/// This is essential for Leapfrog Triejoin in Phase 3.
+///
+/// # Arguments
+/// * `iter_ref` - The iterator reference
+/// * `target` - The key to seek to
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :iterator_closed}` if iterator was closed
+#[rustler::nif(schedule = "DirtyCpu")]
+fn iterator_seek<'a>(
+    env: Env<'a>,
+    iter_ref: ResourceArc<IteratorRef>,
+    target: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    let mut iter_guard = iter_ref
+        .iterator
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let iterator = match iter_guard.as_mut() {
+        Some(iter) => iter,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    };
+
+    let mut cursor = iter_ref
+        .cursor
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    // Replace the old iterator
+    match seek_iterator(&iter_ref, target.as_slice()) {
+        Some(new_iterator) => *iterator = new_iterator,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    }
+    cursor.restart(Some(target.as_slice()));
+
+    Ok(atoms::ok().encode(env))
+}
+
+/// Seeks to `target` and returns the first key `>= target`, in one call.
+///
+/// Serves the seek step of Leapfrog Triejoin: "smallest key >= target within
+/// the prefix, or none". Unlike `iterator_seek`, the landed entry is consumed
+/// and its key returned, so no follow-up `iterator_next` is needed; the next
+/// `iterator_next` returns the entry after it. The prefix bound is enforced on
+/// the returned key, and the value filter of `prefix_iterator_filtered`
+/// iterators is applied as in `iterator_next`.
+///
+/// # Arguments
+/// * `iter_ref` - The iterator reference
+/// * `target` - The key to seek to
+///
+/// # Returns
+/// * `{:ok, key}` for the first matching key `>= target`
+/// * `:iterator_end` if no key `>= target` has the prefix
+/// * `{:error, :iterator_closed}` if iterator was closed
+/// * `{:error, {:iterator_failed, reason}}` on error
+#[rustler::nif(schedule = "DirtyCpu")]
+fn iterator_seek_ge<'a>(
+    env: Env<'a>,
+    iter_ref: ResourceArc<IteratorRef>,
+    target: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    let mut iter_guard = iter_ref
+        .iterator
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let iterator = match iter_guard.as_mut() {
+        Some(iter) => iter,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    };
+
+    let mut cursor = iter_ref
+        .cursor
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    match seek_iterator(&iter_ref, target.as_slice()) {
+        Some(new_iterator) => *iterator = new_iterator,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    }
+    cursor.restart(Some(target.as_slice()));
+
+    loop {
+        match iterator.next() {
+            Some(Ok((key, value))) => {
+                let value = iter_ref.db.unstamp(&iter_ref.cf_name, &value);
+                if !iter_ref.covers(&key) {
+                    cursor.exhausted();
+                    return Ok(atoms::iterator_end().encode(env));
+                }
+
+                if !value.starts_with(&iter_ref.value_prefix) {
+                    continue;
+                }
+
+                cursor.record(&key);
+
+                let mut key_binary = NewBinary::new(env, key.len());
+                key_binary.as_mut_slice().copy_from_slice(&key);
+
+                return Ok((atoms::ok(), Binary::from(key_binary)).encode(env));
+            }
+            Some(Err(e)) => {
+                return Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env));
+            }
+            None => {
+                cursor.exhausted();
+                return Ok(atoms::iterator_end().encode(env));
+            }
+        }
+    }
+}
+
+/// Seeks to the largest key `<= target`, for descending scans.
+///
+/// The counterpart of `iterator_seek`, using RocksDB's `SeekForPrev`: the
+/// next step towards smaller keys returns the largest key `<= target` and
+/// keeps descending from there. On forward iterators that step is
+/// `iterator_prev`, as if the iterator had just stepped back past `target`,
+/// and `iterator_next` returns the keys after `target`. On reverse iterators
+/// it is `iterator_next`, so this is the same as `iterator_seek`.
+///
+/// The prefix and bounds still apply: with no key `<= target` inside them,
+/// e.g. for a target below the prefix, the next step returns `:iterator_end`.
+/// A target past them lands on their last key.
+///
+/// # Arguments
+/// * `iter_ref` - The iterator reference
+/// * `target` - The key to seek to
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :iterator_closed}` if iterator was closed
+#[rustler::nif(schedule = "DirtyCpu")]
+fn iterator_seek_for_prev<'a>(
+    env: Env<'a>,
+    iter_ref: ResourceArc<IteratorRef>,
+    target: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    let mut iter_guard = iter_ref
+        .iterator
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let iterator = match iter_guard.as_mut() {
+        Some(iter) => iter,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    };
+
+    let mut cursor = iter_ref
+        .cursor
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    match iter_ref.seek_for_prev(target.as_slice()) {
+        Some(new_iterator) => *iterator = new_iterator,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    }
+    cursor.restart(Some(target.as_slice()));
+    // Forward iterators now run against their direction, as after `iterator_prev`
+    cursor.stepping_back = !iter_ref.reverse;
+
+    Ok(atoms::ok().encode(env))
+}
+
+/// Builds a replacement iterator positioned at `target`, or `None` if the
+/// column family is gone.
+fn seek_iterator(
+    iter_ref: &IteratorRef,
+    target: &[u8],
+) -> Option<DBIteratorWithThreadMode<'static, DB>> {
+    // Access the database directly from our Arc<SharedDb>
+    let cf_handle = iter_ref.db.cf_handle(&iter_ref.cf_name)?;
+
+    // Create new iterator at the seek position with proper read options
+    // Must match the logic in prefix_iterator for prefix extractor compatibility
+    let new_iterator = iter_ref.db.db.iterator_cf_opt(
+        cf_handle,
+        iter_ref.read_options(),
+        IteratorMode::From(target, iter_ref.direction()),
+    );
+
+    // SAFETY: We keep the SharedDb alive via Arc, so the iterator remains valid.
+    // The Arc<SharedDb> is stored in IteratorRef and keeps the DB alive.
+    let static_iterator: DBIteratorWithThreadMode<'static, DB> =
+        unsafe { std::mem::transmute(new_iterator) };
+    Some(static_iterator)
+}
+
+/// Rewinds the iterator to the start of its prefix.
+///
+/// Rebuilds the underlying RocksDB iterator at the stored prefix, exactly as
+/// `prefix_iterator` created it and from the same snapshot, so the next `iterator_next` returns the
+/// smallest key with that prefix (bounded iterators rewind to their lower
+/// bound, reverse iterators to the last key of their prefix). The iterator's own `Arc<SharedDb>` keeps the
+/// database alive, so this works the same way as `iterator_seek` and needs no
+/// `DbRef` lock; the iterator mutex serializes it with concurrent `next` calls.
+///
+/// # Arguments
+/// * `iter_ref` - The iterator reference
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :iterator_closed}` if iterator was closed
+#[rustler::nif(schedule = "DirtyCpu")]
+fn iterator_reset<'a>(env: Env<'a>, iter_ref: ResourceArc<IteratorRef>) -> NifResult<Term<'a>> {
+    let mut iter_guard = iter_ref
+        .iterator
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let iterator = match iter_guard.as_mut() {
+        Some(iter) => iter,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    };
+
+    let mut cursor = iter_ref
+        .cursor
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let cf_handle = match iter_ref.db.cf_handle(&iter_ref.cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    };
+
+    let new_iterator =
+        iter_ref
+            .db
+            .db
+            .iterator_cf_opt(&cf_handle, iter_ref.read_options(), iter_ref.start_mode());
+
+    // SAFETY: We keep the SharedDb alive via Arc, so the iterator remains valid.
+    // The Arc<SharedDb> is stored in IteratorRef and keeps the DB alive.
+    let static_iterator: DBIteratorWithThreadMode<'static, DB> =
+        unsafe { std::mem::transmute(new_iterator) };
+
+    *iterator = static_iterator;
+    cursor.restart(None);
+
+    Ok(atoms::ok().encode(env))
+}
+
+/// Moves the iterator to the first key of the whole column family.
+///
+/// Unlike `iterator_reset`, this ignores the iterator's prefix: the target is
+/// the smallest key in the column family, found with a total-order probe,
+/// and the iterator is then rebuilt at it as `iterator_seek` would. The
+/// following `iterator_next`/`iterator_prev` still apply the prefix check, so
+/// they return `:iterator_end` right away when the prefix doesn't cover that
+/// key. Bounded iterators stay within their bounds, since RocksDB enforces
+/// them on the rebuilt iterator.
+///
+/// # Arguments
+/// * `iter_ref` - The iterator reference
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :iterator_closed}` if iterator was closed
+/// * `{:error, {:iterator_failed, reason}}` on error
+#[rustler::nif(schedule = "DirtyCpu")]
+fn iterator_seek_to_first<'a>(
+    env: Env<'a>,
+    iter_ref: ResourceArc<IteratorRef>,
+) -> NifResult<Term<'a>> {
+    seek_to_cf_edge(env, iter_ref, IteratorMode::Start)
+}
+
+/// Moves the iterator to the last key of the whole column family.
+///
+/// The counterpart of `iterator_seek_to_first`, with the same prefix caveats:
+/// the next `iterator_next` returns the largest key of the column family if
+/// the iterator covers it, and `iterator_prev` steps back from there within
+/// the prefix.
+///
+/// # Arguments
+/// * `iter_ref` - The iterator reference
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :iterator_closed}` if iterator was closed
+/// * `{:error, {:iterator_failed, reason}}` on error
+#[rustler::nif(schedule = "DirtyCpu")]
+fn iterator_seek_to_last<'a>(
+    env: Env<'a>,
+    iter_ref: ResourceArc<IteratorRef>,
+) -> NifResult<Term<'a>> {
+    seek_to_cf_edge(env, iter_ref, IteratorMode::End)
+}
+
+/// Shared implementation of `iterator_seek_to_first` and `iterator_seek_to_last`;
+/// `edge` is `IteratorMode::Start` or `IteratorMode::End`.
+fn seek_to_cf_edge<'a>(
+    env: Env<'a>,
+    iter_ref: ResourceArc<IteratorRef>,
+    edge: IteratorMode<'static>,
+) -> NifResult<Term<'a>> {
+    let mut iter_guard = iter_ref
+        .iterator
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let iterator = match iter_guard.as_mut() {
+        Some(iter) => iter,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    };
+
+    let mut cursor = iter_ref
+        .cursor
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let cf_handle = match iter_ref.db.cf_handle(&iter_ref.cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    };
+
+    let mut read_opts = ReadOptions::default();
+    read_opts.set_total_order_seek(true);
+    let read_opts = iter_ref.pinned(read_opts);
+    let edge_key = match iter_ref
+        .db
+        .db
+        .iterator_cf_opt(cf_handle, read_opts, edge)
+        .next()
+    {
+        Some(Ok((key, _))) => Some(key),
+        Some(Err(e)) => {
+            return Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env))
+        }
+        None => None,
+    };
+
+    // An empty column family leaves nothing to find from any position
+    match seek_iterator(&iter_ref, edge_key.as_deref().unwrap_or_default()) {
+        Some(new_iterator) => *iterator = new_iterator,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    }
+    cursor.restart(edge_key.as_deref());
+
+    Ok(atoms::ok().encode(env))
+}
+
+/// Builds read options for iterating `prefix` in `cf_name`.
+/// Must match the logic in prefix_iterator for prefix extractor compatibility.
+fn prefix_read_options(cf_name: &str, prefix: &[u8]) -> ReadOptions {
+    let mut read_opts = ReadOptions::default();
+    if PREFIX_CFS.contains(&cf_name) {
+        if prefix.len() >= PREFIX_LENGTH {
+            // Use prefix-based seek for bloom filter benefits
+            read_opts.set_prefix_same_as_start(true);
+            read_opts.set_total_order_seek(false);
+        } else {
+            // Short prefix: use total_order_seek to avoid incorrect bloom filter behavior
+            read_opts.set_total_order_seek(true);
+        }
+    }
+    read_opts
+}
+
+/// Closes the iterator and releases resources.
+///
+/// # Arguments
+/// * `iter_ref` - The iterator reference
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :iterator_closed}` if already closed
+#[rustler::nif]
+fn iterator_close<'a>(env: Env<'a>, iter_ref: ResourceArc<IteratorRef>) -> NifResult<Term<'a>> {
+    let mut iter_guard = iter_ref
+        .iterator
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    if iter_guard.is_none() {
+        return Ok((atoms::error(), atoms::iterator_closed()).encode(env));
+    }
+
+    // Drop the iterator
+    *iter_guard = None;
+    iter_ref.db.open_iterators.fetch_sub(1, Ordering::Relaxed);
+
+    Ok(atoms::ok().encode(env))
+}
+
+/// Collects all remaining key-value pairs from an iterator into a list.
+///
+/// This is a convenience function that consumes the iterator and returns
+/// all matching entries. Useful for small result sets where streaming isn't needed.
+///
+/// # Arguments
+/// * `iter_ref` - The iterator reference
+///
+/// # Returns
+/// * `{:ok, [{key, value}, ...]}` with all remaining entries
+/// * `{:error, :iterator_closed}` if iterator was closed
+/// * `{:error, {:iterator_failed, reason}}` on error
+#[rustler::nif(schedule = "DirtyCpu")]
+fn iterator_collect<'a>(env: Env<'a>, iter_ref: ResourceArc<IteratorRef>) -> NifResult<Term<'a>> {
+    let mut iter_guard = iter_ref
+        .iterator
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let iterator = match iter_guard.as_mut() {
+        Some(iter) => iter,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    };
+
+    let mut cursor = iter_ref
+        .cursor
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    if !iter_ref.resume(iterator, &mut cursor) {
+        return Ok((atoms::error(), atoms::iterator_closed()).encode(env));
+    }
+
+    let mut results: Vec<Term<'a>> = Vec::new();
+
+    for result in iterator.by_ref() {
+        match result {
+            Ok((key, value)) => {
+                let value = iter_ref.db.unstamp(&iter_ref.cf_name, &value);
+                // Check if key is still within the prefix or bounds
+                if !iter_ref.covers(&key) {
+                    break;
+                }
+
+                if !value.starts_with(&iter_ref.value_prefix) {
+                    continue;
+                }
+
+                cursor.record(&key);
+
+                let mut key_binary = NewBinary::new(env, key.len());
+                key_binary.as_mut_slice().copy_from_slice(&key);
+
+                let mut value_binary = NewBinary::new(env, value.len());
+                value_binary.as_mut_slice().copy_from_slice(value);
+
+                results.push((Binary::from(key_binary), Binary::from(value_binary)).encode(env));
+            }
+            Err(e) => {
+                return Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env));
+            }
+        }
+    }
+
+    cursor.exhausted();
+
+    Ok((atoms::ok(), results).encode(env))
+}
+
+// ============================================================================
+// Index Scan Operations
+// ============================================================================
+
+/// Scans a subject's `spo` entries and groups object IDs by predicate.
+///
+/// Because `spo` keys are sorted by predicate then object within a subject,
+/// grouping is a single linear pass. The result is directly usable for
+/// property-table construction without grouping on the Elixir side.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `subject_prefix` - The encoded subject ID (key prefix in `spo`)
+/// * `id_width` - Width in bytes of each ID in the index key (1..=8)
+/// * `max` - Maximum total number of objects to return across all predicates
+///
+/// # Returns
+/// * `{:ok, %{pred_id => [obj_id, ...]}, more?}` where `more?` is true when
+///   entries beyond `max` were left unread
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_id_width, id_width}}` if the width is out of range
+/// * `{:error, {:malformed_key, length}}` if a key has an unexpected length
+/// * `{:error, {:iterator_failed, reason}}` on read errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn scan_subject_grouped<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    subject_prefix: Binary<'a>,
+    id_width: usize,
+    max: usize,
+) -> NifResult<Term<'a>> {
+    if id_width == 0 || id_width > 8 {
+        return Ok((atoms::error(), (atoms::invalid_id_width(), id_width)).encode(env));
+    }
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let cf_handle = match shared_db.cf_handle("spo") {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), atoms::spo())).encode(env)),
+    };
+
+    let prefix = subject_prefix.as_slice();
+    let mut read_opts = ReadOptions::default();
+    if prefix.len() >= PREFIX_LENGTH {
+        read_opts.set_prefix_same_as_start(true);
+    } else {
+        read_opts.set_total_order_seek(true);
+    }
+
+    let iterator = shared_db.db.iterator_cf_opt(
+        &cf_handle,
+        read_opts,
+        IteratorMode::From(prefix, rocksdb::Direction::Forward),
+    );
+
+    let mut groups: Vec<(u64, Vec<u64>)> = Vec::new();
+    let mut more = false;
+
+    for (total, item) in iterator.enumerate() {
+        let (key, _value) = match item {
+            Ok(kv) => kv,
+            Err(e) => {
+                return Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env))
+            }
+        };
+
+        if !key.starts_with(prefix) {
+            break;
+        }
+        if key.len() != id_width * 3 {
+            return Ok((atoms::error(), (atoms::malformed_key(), key.len())).encode(env));
+        }
+        if total == max {
+            more = true;
+            break;
+        }
+
+        let predicate = decode_be_id(&key[id_width..id_width * 2]);
+        let object = decode_be_id(&key[id_width * 2..]);
+
+        // Keys are sorted by predicate, so a new predicate always starts a new group
+        match groups.last_mut() {
+            Some((last, objects)) if *last == predicate => objects.push(object),
+            _ => groups.push((predicate, vec![object])),
+        }
+    }
+
+    let pairs: Vec<(Term<'a>, Term<'a>)> = groups
+        .iter()
+        .map(|(predicate, objects)| (predicate.encode(env), objects.encode(env)))
+        .collect();
+    let grouped = Term::map_from_pairs(env, &pairs)?;
+
+    Ok((atoms::ok(), grouped, more).encode(env))
+}
+
+/// Aggregates the values under a prefix without returning them.
+///
+/// Runs the reduction in Rust so values never cross the NIF boundary:
+/// - `:count` - number of entries under the prefix
+/// - `:sum_u64` - sum of all values, each read as a little-endian u64
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `prefix` - The key prefix to scan
+/// * `opts` - Map with the `:op` to apply
+///
+/// # Returns
+/// * `{:ok, result}` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:invalid_option, :op}}` if `:op` is missing or unknown
+/// * `{:error, :bad_value_width}` if a value isn't 8 bytes in `:sum_u64` mode
+/// * `{:error, :overflow}` if the sum doesn't fit in a u64
+/// * `{:error, {:iterator_failed, reason}}` on read errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn scan_aggregate<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    prefix: Binary<'a>,
+    opts: Term<'a>,
+) -> NifResult<Term<'a>> {
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let sum = match opts
+        .map_get(atoms::op().encode(env))
+        .and_then(|op| op.decode::<rustler::Atom>())
+    {
+        Ok(op) if op == atoms::count() => false,
+        Ok(op) if op == atoms::sum_u64() => true,
+        _ => return Ok((atoms::error(), (atoms::invalid_option(), atoms::op())).encode(env)),
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+
+    let prefix = prefix.as_slice();
+    let iterator = shared_db.db.iterator_cf_opt(
+        &cf_handle,
+        prefix_read_options(&cf_name, prefix),
+        IteratorMode::From(prefix, rocksdb::Direction::Forward),
+    );
+
+    let mut result = 0u64;
+    for item in iterator {
+        let (key, value) = match item {
+            Ok(kv) => kv,
+            Err(e) => {
+                return Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env))
+            }
+        };
+
+        if !key.starts_with(prefix) {
+            break;
+        }
+
+        let increment = if sum {
+            match <[u8; 8]>::try_from(shared_db.unstamp(&cf_name, &value)) {
+                Ok(bytes) => u64::from_le_bytes(bytes),
+                Err(_) => return Ok((atoms::error(), atoms::bad_value_width()).encode(env)),
+            }
+        } else {
+            1
+        };
+
+        result = match result.checked_add(increment) {
+            Some(total) => total,
+            None => return Ok((atoms::error(), atoms::overflow()).encode(env)),
+        };
+    }
+
+    Ok((atoms::ok(), result).encode(env))
+}
+
+/// Counts the keys under a prefix without returning them.
+///
+/// Same result as `scan_aggregate` with `:count`, but walks a raw RocksDB
+/// iterator that only compares keys in place: neither keys nor values are
+/// copied, in Rust or into Elixir binaries. Stops at the first key outside
+/// the prefix, like `iterator_next`. Meant for cardinality estimates of a
+/// BGP pattern.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `prefix` - The key prefix to count
+///
+/// # Returns
+/// * `{:ok, count}` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:cf_not_opened, cf}}` if the column family was not opened
+/// * `{:error, {:iterator_failed, reason}}` on read errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn prefix_count<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    prefix: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    if shared_db.cf_handle(&cf_name).is_none() {
+        return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env));
+    }
+
+    match count_prefix(shared_db, &cf_name, prefix.as_slice()) {
+        Ok(count) => Ok((atoms::ok(), count).encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// Number of keys starting with `prefix` in an opened column family.
+fn count_prefix(shared_db: &SharedDb, cf_name: &str, prefix: &[u8]) -> Result<u64, rocksdb::Error> {
+    let Some(cf_handle) = shared_db.cf_handle(cf_name) else {
+        return Ok(0);
+    };
+
+    let mut raw = shared_db
+        .db
+        .raw_iterator_cf_opt(cf_handle, prefix_read_options(cf_name, prefix));
+    raw.seek(prefix);
+
+    let mut count = 0u64;
+    while let Some(key) = raw.key() {
+        if !key.starts_with(prefix) {
+            break;
+        }
+        count += 1;
+        raw.next();
+    }
+    raw.status()?;
+    Ok(count)
+}
+
+// ============================================================================
+// Snapshot Operations
+// ============================================================================
+
+/// Creates a snapshot of the database for consistent point-in-time reads.
+///
+/// A snapshot provides a consistent view of the database at the time of creation.
+/// All reads using the snapshot will see the same data, regardless of subsequent
+/// writes to the database.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+///
+/// # Returns
+/// * `{:ok, snapshot_ref}` on success
+/// * `{:error, :already_closed}` if database is closed
+#[rustler::nif(schedule = "DirtyCpu")]
+fn snapshot<'a>(env: Env<'a>, db_ref: ResourceArc<DbRef>) -> NifResult<Term<'a>> {
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => Arc::clone(db),
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let snap = shared_db.db.snapshot();
+
+    // SAFETY: We keep the SharedDb alive via Arc, so the snapshot remains valid.
+    // The Arc<SharedDb> is stored in SnapshotRef and will keep the DB alive
+    // even if DbRef.close() is called, preventing use-after-free.
+    let static_snapshot: SnapshotWithThreadMode<'static, DB> = unsafe { std::mem::transmute(snap) };
+
+    let snap_ref = ResourceArc::new(SnapshotRef {
+        snapshot: Mutex::new(Some(static_snapshot)),
+        db: shared_db,
+    });
+
+    Ok((atoms::ok(), snap_ref).encode(env))
+}
+
+/// Gets a value from a column family using a snapshot.
+///
+/// This provides point-in-time consistent reads - the value returned
+/// is what existed at the time the snapshot was created.
+///
+/// # Arguments
+/// * `snapshot_ref` - The snapshot reference
+/// * `cf` - The column family atom
+/// * `key` - The key as a binary
+///
+/// # Returns
+/// * `{:ok, value}` if found
+/// * `:not_found` if key doesn't exist
+/// * `{:error, :snapshot_released}` if snapshot was released
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:get_failed, reason}}` on other errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn snapshot_get<'a>(
+    env: Env<'a>,
+    snapshot_ref: ResourceArc<SnapshotRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let snap_guard = snapshot_ref
+        .snapshot
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let snapshot = match snap_guard.as_ref() {
+        Some(snap) => snap,
+        None => return Ok((atoms::error(), atoms::snapshot_released()).encode(env)),
+    };
+
+    // Access the database directly from our Arc<SharedDb>
+    let cf_handle = match snapshot_ref.db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+
+    // Use ReadOptions with snapshot
+    let mut read_opts = ReadOptions::default();
+    read_opts.set_snapshot(snapshot);
+
+    match snapshot_ref
+        .db
+        .db
+        .get_cf_opt(&cf_handle, key.as_slice(), &read_opts)
+    {
+        Ok(Some(value)) => {
+            let value = snapshot_ref.db.unstamp(&cf_name, &value);
+            let mut binary = NewBinary::new(env, value.len());
+            binary.as_mut_slice().copy_from_slice(value);
+            Ok((atoms::ok(), Binary::from(binary)).encode(env))
+        }
+        Ok(None) => Ok(atoms::not_found().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::get_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// Gets multiple values from a column family using a snapshot.
+///
+/// All keys are read against the snapshot's point-in-time view in a single
+/// `multi_get_cf` call, so the batch never observes writes committed after the
+/// snapshot was taken. Intended for consistent batch probes during joins.
+///
+/// # Arguments
+/// * `snapshot_ref` - The snapshot reference
+/// * `cf` - The column family atom
+/// * `keys` - List of keys as binaries
+///
+/// # Returns
+/// * `{:ok, results}` where each result is `{:ok, value}` or `:not_found`, in key order
+/// * `{:error, :snapshot_released}` if snapshot was released
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:get_failed, reason}}` if any read fails
+#[rustler::nif(schedule = "DirtyCpu")]
+fn multi_get_snapshot<'a>(
+    env: Env<'a>,
+    snapshot_ref: ResourceArc<SnapshotRef>,
+    cf: Term<'a>,
+    keys: Vec<Binary<'a>>,
+) -> NifResult<Term<'a>> {
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let snap_guard = snapshot_ref
+        .snapshot
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let snapshot = match snap_guard.as_ref() {
+        Some(snap) => snap,
+        None => return Ok((atoms::error(), atoms::snapshot_released()).encode(env)),
+    };
+
+    let cf_handle = match snapshot_ref.db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+
+    let mut read_opts = ReadOptions::default();
+    read_opts.set_snapshot(snapshot);
+
+    let lookups = keys.iter().map(|key| (cf_handle, key.as_slice()));
+    let mut results = Vec::with_capacity(keys.len());
+
+    for result in snapshot_ref.db.db.multi_get_cf_opt(lookups, &read_opts) {
+        match result {
+            Ok(Some(value)) => {
+                let value = snapshot_ref.db.unstamp(&cf_name, &value);
+                let mut binary = NewBinary::new(env, value.len());
+                binary.as_mut_slice().copy_from_slice(value);
+                results.push((atoms::ok(), Binary::from(binary)).encode(env));
+            }
+            Ok(None) => results.push(atoms::not_found().encode(env)),
+            Err(e) => return Ok((atoms::error(), (atoms::get_failed(), e.to_string())).encode(env)),
+        }
+    }
+
+    Ok((atoms::ok(), results).encode(env))
+}
+
+/// Creates a prefix iterator over a snapshot.
+///
+/// The iterator returns all key-value pairs where the key starts with the given prefix,
+/// using the consistent view from the snapshot.
+///
+/// # Arguments
+/// * `snapshot_ref` - The snapshot reference
+/// * `cf` - The column family atom
+/// * `prefix` - The prefix to iterate over
+///
+/// # Returns
+/// * `{:ok, iterator_ref}` on success
+/// * `{:error, :snapshot_released}` if snapshot was released
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+#[rustler::nif(schedule = "DirtyCpu")]
+fn snapshot_prefix_iterator<'a>(
+    env: Env<'a>,
+    snapshot_ref: ResourceArc<SnapshotRef>,
+    cf: Term<'a>,
+    prefix: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let snap_guard = snapshot_ref
+        .snapshot
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let snapshot = match snap_guard.as_ref() {
+        Some(snap) => snap,
+        None => return Ok((atoms::error(), atoms::snapshot_released()).encode(env)),
+    };
+
+    // Access the database directly from our Arc<SharedDb>
+    let cf_handle = match snapshot_ref.db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+
+    let prefix_bytes = prefix.as_slice().to_vec();
+
+    // Create read options with snapshot and prefix bounds
+    let mut read_opts = ReadOptions::default();
+    read_opts.set_snapshot(snapshot);
+
+    // Configure prefix iteration based on CF type and prefix length
+    if PREFIX_CFS.contains(&&*cf_name) {
+        if prefix_bytes.len() >= PREFIX_LENGTH {
+            // Use prefix-based seek for bloom filter benefits
+            read_opts.set_prefix_same_as_start(true);
+            read_opts.set_total_order_seek(false);
+        } else {
+            // Short prefix: use total_order_seek to avoid incorrect bloom filter behavior
+            read_opts.set_total_order_seek(true);
+        }
+    }
+
+    // Create the iterator with snapshot
+    let iterator = snapshot_ref.db.db.iterator_cf_opt(
+        &cf_handle,
+        read_opts,
+        IteratorMode::From(&prefix_bytes, rocksdb::Direction::Forward),
+    );
+
+    // SAFETY: We keep the SharedDb alive via Arc, so the iterator remains valid.
+    // The Arc<SharedDb> is stored in SnapshotIteratorRef and will keep the DB alive
+    // even if DbRef.close() is called, preventing use-after-free.
+    let static_iterator: DBIteratorWithThreadMode<'static, DB> =
+        unsafe { std::mem::transmute(iterator) };
+
+    let iter_ref = ResourceArc::new(SnapshotIteratorRef {
+        iterator: Mutex::new(Some(static_iterator)),
+        db: Arc::clone(&snapshot_ref.db),
+        prefix: prefix_bytes,
+        cf_name: cf_name.to_string(),
+    });
+
+    Ok((atoms::ok(), iter_ref).encode(env))
+}
+
+/// Gets the next key-value pair from a snapshot iterator.
+///
+/// # Arguments
+/// * `iter_ref` - The snapshot iterator reference
+///
+/// # Returns
+/// * `{:ok, key, value}` if there's a next item with matching prefix
+/// * `:iterator_end` if the iterator is exhausted or prefix no longer matches
+/// * `{:error, :iterator_closed}` if iterator was closed
+/// * `{:error, {:iterator_failed, reason}}` on error
+#[rustler::nif(schedule = "DirtyCpu")]
+fn snapshot_iterator_next<'a>(
+    env: Env<'a>,
+    iter_ref: ResourceArc<SnapshotIteratorRef>,
+) -> NifResult<Term<'a>> {
+    let mut iter_guard = iter_ref
+        .iterator
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let iterator = match iter_guard.as_mut() {
+        Some(iter) => iter,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    };
+
+    match iterator.next() {
+        Some(Ok((key, value))) => {
+            let value = iter_ref.db.unstamp(&iter_ref.cf_name, &value);
+            // Check if key still has the prefix
+            if !key.starts_with(&iter_ref.prefix) {
+                return Ok(atoms::iterator_end().encode(env));
+            }
+
+            let mut key_binary = NewBinary::new(env, key.len());
+            key_binary.as_mut_slice().copy_from_slice(&key);
+
+            let mut value_binary = NewBinary::new(env, value.len());
+            value_binary.as_mut_slice().copy_from_slice(value);
+
+            Ok((
+                atoms::ok(),
+                Binary::from(key_binary),
+                Binary::from(value_binary),
+            )
+                .encode(env))
+        }
+        Some(Err(e)) => Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env)),
+        None => Ok(atoms::iterator_end().encode(env)),
+    }
+}
+
+/// Closes a snapshot iterator and releases resources.
+///
+/// # Arguments
+/// * `iter_ref` - The snapshot iterator reference
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :iterator_closed}` if already closed
+#[rustler::nif]
+fn snapshot_iterator_close<'a>(
+    env: Env<'a>,
+    iter_ref: ResourceArc<SnapshotIteratorRef>,
+) -> NifResult<Term<'a>> {
+    let mut iter_guard = iter_ref
+        .iterator
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    if iter_guard.is_none() {
+        return Ok((atoms::error(), atoms::iterator_closed()).encode(env));
+    }
+
+    // Drop the iterator
+    *iter_guard = None;
+
+    Ok(atoms::ok().encode(env))
+}
+
+/// Collects all remaining key-value pairs from a snapshot iterator into a list.
+///
+/// # Arguments
+/// * `iter_ref` - The snapshot iterator reference
+///
+/// # Returns
+/// * `{:ok, [{key, value}, ...]}` with all remaining entries
+/// * `{:error, :iterator_closed}` if iterator was closed
+/// * `{:error, {:iterator_failed, reason}}` on error
+#[rustler::nif(schedule = "DirtyCpu")]
+fn snapshot_iterator_collect<'a>(
+    env: Env<'a>,
+    iter_ref: ResourceArc<SnapshotIteratorRef>,
+) -> NifResult<Term<'a>> {
+    let mut iter_guard = iter_ref
+        .iterator
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    let iterator = match iter_guard.as_mut() {
+        Some(iter) => iter,
+        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    };
+
+    let mut results: Vec<Term<'a>> = Vec::new();
+
+    for result in iterator.by_ref() {
+        match result {
+            Ok((key, value)) => {
+                let value = iter_ref.db.unstamp(&iter_ref.cf_name, &value);
+                // Check if key still has the prefix
+                if !key.starts_with(&iter_ref.prefix) {
+                    break;
+                }
+
+                let mut key_binary = NewBinary::new(env, key.len());
+                key_binary.as_mut_slice().copy_from_slice(&key);
+
+                let mut value_binary = NewBinary::new(env, value.len());
+                value_binary.as_mut_slice().copy_from_slice(value);
+
+                results.push((Binary::from(key_binary), Binary::from(value_binary)).encode(env));
+            }
+            Err(e) => {
+                return Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env));
+            }
+        }
+    }
+
+    Ok((atoms::ok(), results).encode(env))
+}
+
+/// Releases a snapshot and frees resources.
+///
+/// After calling release, the snapshot handle is no longer valid.
+///
+/// # Arguments
+/// * `snapshot_ref` - The snapshot reference
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :snapshot_released}` if already released
+#[rustler::nif]
+fn release_snapshot<'a>(
+    env: Env<'a>,
+    snapshot_ref: ResourceArc<SnapshotRef>,
+) -> NifResult<Term<'a>> {
+    let mut snap_guard = snapshot_ref
+        .snapshot
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+
+    if snap_guard.is_none() {
+        return Ok((atoms::error(), atoms::snapshot_released()).encode(env));
+    }
+
+    // Drop the snapshot
+    *snap_guard = None;
+
+    Ok(atoms::ok().encode(env))
+}
+
+/// Flushes the Write-Ahead Log (WAL) to disk.
+///
+/// This ensures all buffered writes are persisted to the WAL. When `sync` is true,
+/// it also calls fsync to ensure data is physically written to storage.
+///
+/// Use this after bulk loading with sync=false to ensure all data is durable
+/// before considering the load complete.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `sync` - When true, calls fsync after flushing (fully durable). When false,
+///            only flushes to OS buffer cache.
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:flush_failed, reason}}` on failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn flush_wal<'a>(env: Env<'a>, db_ref: ResourceArc<DbRef>, sync: bool) -> NifResult<Term<'a>> {
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    // The in-memory Env can't fsync its WAL, and there is nothing to make durable
+    match shared_db.db.flush_wal(sync && !shared_db.in_memory) {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::flush_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// Makes everything written so far durable in one call.
+///
+/// Flushes the memtables of every column family to SST files, syncs the WAL
+/// with fsync, and finally fsyncs the database directory so newly created
+/// files are durable too. Intended for transactional checkpoints, e.g. after
+/// a critical commit.
+///
+/// This is expensive: a memtable flush writes new SST files and the fsyncs wait
+/// for the storage device, so latency is typically milliseconds to seconds
+/// depending on memtable size and hardware. It is unnecessary after every
+/// write; use `sync` writes or `flush_wal/2` for per-write durability.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:flush_failed, reason}}` on failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn sync_dir<'a>(env: Env<'a>, db_ref: ResourceArc<DbRef>) -> NifResult<Term<'a>> {
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    match sync_all(shared_db) {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(reason) => Ok((atoms::error(), (atoms::flush_failed(), reason)).encode(env)),
+    }
+}
+
+fn sync_all(shared_db: &SharedDb) -> Result<(), String> {
+    let cfs = shared_db
+        .opened_cf_names()
+        .map(|cf_name| {
+            shared_db
+                .cf_handle(cf_name)
+                .ok_or_else(|| format!("column family '{}' not found", cf_name))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // A single flush call covers every CF, so with atomic flush enabled the
+    // memtables of all CFs are persisted together
+    shared_db
+        .db
+        .flush_cfs_opt(&cfs, &FlushOptions::default())
+        .map_err(|e| e.to_string())?;
+
+    // An in-memory database has no WAL file or directory to fsync
+    if shared_db.in_memory {
+        return Ok(());
+    }
+
+    shared_db.db.flush_wal(true).map_err(|e| e.to_string())?;
+
+    std::fs::File::open(&shared_db.path)
+        .and_then(|dir| dir.sync_all())
+        .map_err(|e| format!("failed to fsync directory: {}", e))
+}
+
+/// Flushes the memtables of every column family to SST files, waiting for
+/// the flush to finish.
+///
+/// Unlike `sync_dir`, the WAL and the database directory are not fsynced.
+/// Use it before test teardown or before taking an external filesystem
+/// snapshot, so the data is in SST files rather than only in the WAL.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:flush_failed, reason}}` on failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn flush<'a>(env: Env<'a>, db_ref: ResourceArc<DbRef>) -> NifResult<Term<'a>> {
+    flush_memtables(env, db_ref, None, true)
+}
+
+/// Flushes the memtables of every column family to SST files.
+///
+/// With `wait` false the call returns once the flush is scheduled instead of
+/// when it has finished (`FlushOptions::set_wait`).
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `wait` - Whether to wait for the flush to finish
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:flush_failed, reason}}` on failure
+#[rustler::nif(name = "flush", schedule = "DirtyCpu")]
+fn flush_with_wait<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    wait: bool,
+) -> NifResult<Term<'a>> {
+    flush_memtables(env, db_ref, None, wait)
+}
+
+/// Flushes the memtable of a single column family to SST files, waiting
+/// for the flush to finish.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:flush_failed, reason}}` on failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn flush_cf<'a>(env: Env<'a>, db_ref: ResourceArc<DbRef>, cf: Term<'a>) -> NifResult<Term<'a>> {
+    flush_memtables(env, db_ref, Some(cf), true)
+}
+
+/// Flushes the memtable of a single column family, optionally without
+/// waiting for the flush to finish.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+/// * `wait` - Whether to wait for the flush to finish
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:flush_failed, reason}}` on failure
+#[rustler::nif(name = "flush_cf", schedule = "DirtyCpu")]
+fn flush_cf_with_wait<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    wait: bool,
+) -> NifResult<Term<'a>> {
+    flush_memtables(env, db_ref, Some(cf), wait)
+}
+
+/// Shared implementation of `flush` and `flush_cf`; `cf` of `None` flushes
+/// every opened column family in one call.
+fn flush_memtables<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Option<Term<'a>>,
+    wait: bool,
+) -> NifResult<Term<'a>> {
+    let cf_name = match cf {
+        Some(cf) => match cf_term_to_name(cf) {
+            Some(name) => Some((cf, name)),
+            None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+        },
+        None => None,
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let cf_handles = match cf_name {
+        Some((cf, name)) => match shared_db.cf_handle(&name) {
+            Some(handle) => vec![handle],
+            None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+        },
+        None => shared_db
+            .opened_cf_names()
+            .filter_map(|name| shared_db.cf_handle(name))
+            .collect(),
+    };
+
+    let mut flush_opts = FlushOptions::default();
+    flush_opts.set_wait(wait);
+
+    match shared_db.db.flush_cfs_opt(&cf_handles, &flush_opts) {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::flush_failed(), e.to_string())).encode(env)),
+    }
+}
+
+/// Sets options on all column families at runtime.
+///
+/// This allows dynamic reconfiguration of RocksDB settings without restarting.
+/// Options are passed as a list of {key, value} tuples where both key and value
+/// are strings.
+///
+/// # Mutable Options (subset)
+/// - "level0_file_num_compaction_trigger" - Files in L0 to trigger compaction
+/// - "level0_slowdown_writes_trigger" - Files in L0 to slow down writes
+/// - "level0_stop_writes_trigger" - Files in L0 to stop writes
+/// - "target_file_size_base" - Target file size in bytes
+/// - "max_bytes_for_level_base" - Maximum bytes in base level
+/// - "write_buffer_size" - Size of write buffer (for new memtables)
+/// - "max_write_buffer_number" - Maximum number of write buffers
+/// - "disable_auto_compactions" - Disable automatic compactions ("true"/"false")
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `options` - List of {key, value} tuples as strings
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:set_options_failed, reason}}` on failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn set_options<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    options: Vec<(String, String)>,
+) -> NifResult<Term<'a>> {
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    // Convert options to the format expected by RocksDB: &[(&str, &str)]
+    let opts: Vec<(&str, &str)> = options
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+
+    // Apply options to all opened column families
+    for cf_name in shared_db.opened_cf_names() {
+        let cf = match shared_db.cf_handle(cf_name) {
+            Some(cf) => cf,
+            None => {
+                return Ok((
+                    atoms::error(),
+                    (
+                        atoms::set_options_failed(),
+                        format!("column family '{}' not found", cf_name),
+                    ),
+                )
+                    .encode(env))
+            }
+        };
+
+        if let Err(e) = shared_db.db.set_options_cf(&cf, &opts) {
+            return Ok((
+                atoms::error(),
+                (
+                    atoms::set_options_failed(),
+                    format!("failed to set options on '{}': {}", cf_name, e),
+                ),
+            )
+                .encode(env));
+        }
+    }
+
+    Ok(atoms::ok().encode(env))
+}
+
+/// Column family options reported by `effective_options`.
+const EFFECTIVE_CF_OPTIONS: [&str; 12] = [
+    "write_buffer_size",
+    "max_write_buffer_number",
+    "compression",
+    "bottommost_compression",
+    "compaction_style",
+    "level0_file_num_compaction_trigger",
+    "level0_slowdown_writes_trigger",
+    "level0_stop_writes_trigger",
+    "target_file_size_base",
+    "max_bytes_for_level_base",
+    "disable_auto_compactions",
+    "prefix_extractor",
+];
+
+/// Block-based table options reported by `effective_options`.
+const EFFECTIVE_TABLE_OPTIONS: [&str; 5] = [
+    "block_size",
+    "index_type",
+    "format_version",
+    "filter_policy",
+    "cache_index_and_filter_blocks",
+];
+
+/// Reads back the options actually in effect for a column family.
+///
+/// The rocksdb crate has no getter for live options, so they are read from
+/// the newest `OPTIONS-*` file in the database directory. RocksDB rewrites
+/// that file on open and after every successful `set_options`, so it
+/// reflects the live configuration, including values RocksDB sanitized. An
+/// in-memory database keeps that file in its mem env, out of reach, so it
+/// returns `{:error, :in_memory}`.
+///
+/// Keys are option names as accepted by `set_options`. Numeric values are
+/// returned as integers; everything else (e.g. `"kLZ4Compression"`,
+/// `"kCompactionStyleLevel"`, `"false"`) as RocksDB's string form.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - The column family atom
+///
+/// # Returns
+/// * `{:ok, %{option => value}}` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:cf_not_opened, cf}}` if the column family wasn't opened
+/// * `{:error, :in_memory}` for a database opened with `open_memory`
+/// * `{:error, {:options_failed, reason}}` if the options file can't be read
+#[rustler::nif(schedule = "DirtyCpu")]
+fn effective_options<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+) -> NifResult<Term<'a>> {
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    if shared_db.cf_handle(&cf_name).is_none() {
+        return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env));
+    }
+    if shared_db.in_memory {
+        return Ok((atoms::error(), atoms::in_memory()).encode(env));
+    }
+
+    let options = match read_cf_options_file(&shared_db.path, &cf_name) {
+        Ok(options) => options,
+        Err(reason) => return Ok((atoms::error(), (atoms::options_failed(), reason)).encode(env)),
+    };
+
+    let pairs: Vec<(Term<'a>, Term<'a>)> = options
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value.parse::<i64>() {
+                Ok(number) => number.encode(env),
+                Err(_) => value.encode(env),
+            };
+            (key.encode(env), value)
+        })
+        .collect();
+
+    Ok((atoms::ok(), Term::map_from_pairs(env, &pairs)?).encode(env))
+}
+
+/// Extracts the reported options for `cf_name` from the newest OPTIONS file.
+fn read_cf_options_file(path: &str, cf_name: &str) -> Result<Vec<(&'static str, String)>, String> {
+    let mut newest: Option<(u64, std::path::PathBuf)> = None;
+    for entry in std::fs::read_dir(path).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let file_name = entry.file_name();
+        let number = file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix("OPTIONS-"))
+            .and_then(|suffix| suffix.parse::<u64>().ok());
+        if let Some(number) = number {
+            if newest.as_ref().is_none_or(|(best, _)| number > *best) {
+                newest = Some((number, entry.path()));
+            }
+        }
+    }
+
+    let (_, options_path) = newest.ok_or_else(|| "no OPTIONS file found".to_string())?;
+    let contents = std::fs::read_to_string(options_path).map_err(|e| e.to_string())?;
+
+    let cf_section = format!("[CFOptions \"{}\"]", cf_name);
+    let table_section = format!("[TableOptions/BlockBasedTable \"{}\"]", cf_name);
+    let mut wanted: &[&'static str] = &[];
+    let mut options = Vec::new();
+
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            wanted = if line == cf_section {
+                &EFFECTIVE_CF_OPTIONS
+            } else if line == table_section {
+                &EFFECTIVE_TABLE_OPTIONS
+            } else {
+                &[]
+            };
+        } else if let Some((key, value)) = line.split_once('=') {
+            if let Some(name) = wanted.iter().find(|name| **name == key) {
+                options.push((*name, value.to_string()));
+            }
+        }
+    }
+
+    if options.is_empty() {
+        return Err(format!(
+            "column family '{}' not found in OPTIONS file",
+            cf_name
+        ));
+    }
+    Ok(options)
+}
+
+// ============================================================================
+// Transactions
+// ============================================================================
+
+/// Begins a transaction.
+///
+/// The database is opened as a plain `DB`, so transactions are implemented
+/// here rather than through RocksDB's `TransactionDB`. Writes are buffered,
+/// and every key the transaction writes or reads with
+/// `transaction_get_for_update` is locked until the transaction commits, rolls
+/// back or is garbage-collected. Another transaction touching a locked key
+/// that way waits up to a second for it, then gets `{:error, :busy}`, so no
+/// other transaction can change a locked key in the meantime.
+///
+/// Writes made outside transactions take no locks. To catch those, the locked
+/// keys are re-read on commit; if any changed since the transaction first
+/// touched it, the commit fails with `{:error, :busy}` and nothing is written.
+/// Such a write that restores the key's original value before the commit goes
+/// unnoticed. Otherwise the buffered writes are applied as one atomic batch.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+///
+/// # Returns
+/// * `{:ok, txn_ref}` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, :read_only}` if the database was opened read-only
+#[rustler::nif(schedule = "DirtyCpu")]
+fn transaction_begin<'a>(env: Env<'a>, db_ref: ResourceArc<DbRef>) -> NifResult<Term<'a>> {
+    if db_ref.read_only {
+        return Ok((atoms::error(), atoms::read_only()).encode(env));
+    }
+
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => Arc::clone(db),
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let txn_ref = ResourceArc::new(TransactionRef {
+        state: Mutex::new(Some(TxnState::new())),
+        db: shared_db,
+    });
+
+    Ok((atoms::ok(), txn_ref).encode(env))
+}
+
+/// Buffers a put in a transaction and locks the key.
+///
+/// # Arguments
+/// * `txn_ref` - The transaction reference
+/// * `cf` - The column family atom
+/// * `key` - The key as a binary
+/// * `value` - The value as a binary
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :transaction_closed}` if the transaction was committed or rolled back
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:cf_not_opened, cf}}` if the column family wasn't opened
+/// * `{:error, :busy}` if another transaction holds the key's lock for over a second
+/// * `{:error, {:put_failed, reason}}` if the key can't be read for tracking
+#[rustler::nif(schedule = "DirtyCpu")]
+fn transaction_put<'a>(
+    env: Env<'a>,
+    txn_ref: ResourceArc<TransactionRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+    value: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    transaction_write(
+        env,
+        &txn_ref,
+        cf,
+        key.as_slice(),
+        Some(value.as_slice().to_vec()),
+    )
+}
+
+/// Buffers a delete in a transaction and locks the key.
+///
+/// # Arguments
+/// * `txn_ref` - The transaction reference
+/// * `cf` - The column family atom
+/// * `key` - The key as a binary
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :transaction_closed}` if the transaction was committed or rolled back
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:cf_not_opened, cf}}` if the column family wasn't opened
+/// * `{:error, :busy}` if another transaction holds the key's lock for over a second
+/// * `{:error, {:delete_failed, reason}}` if the key can't be read for tracking
+#[rustler::nif(schedule = "DirtyCpu")]
+fn transaction_delete<'a>(
+    env: Env<'a>,
+    txn_ref: ResourceArc<TransactionRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    transaction_write(env, &txn_ref, cf, key.as_slice(), None)
+}
+
+/// Reads a key within a transaction, seeing the transaction's own writes.
+///
+/// The key is not locked, so concurrent changes to it don't fail the commit;
+/// use `transaction_get_for_update` for read-modify-write.
+///
+/// # Arguments
+/// * `txn_ref` - The transaction reference
+/// * `cf` - The column family atom
+/// * `key` - The key as a binary
+///
+/// # Returns
+/// * `{:ok, value}` if found
+/// * `:not_found` if key doesn't exist
+/// * `{:error, :transaction_closed}` if the transaction was committed or rolled back
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:cf_not_opened, cf}}` if the column family wasn't opened
+/// * `{:error, {:get_failed, reason}}` on other errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn transaction_get<'a>(
+    env: Env<'a>,
+    txn_ref: ResourceArc<TransactionRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    transaction_read(env, &txn_ref, cf, key.as_slice(), false)
+}
+
+/// Reads a key within a transaction and locks it.
+///
+/// Equivalent of RocksDB's `GetForUpdate`: other transactions can't write or
+/// lock the key until this one finishes, and if a write outside transactions
+/// changes it before this transaction commits, the commit returns
+/// `{:error, :busy}`. This makes read-modify-write (e.g. bumping the term-ID
+/// counter) safe among transactions.
+///
+/// # Arguments
+/// * `txn_ref` - The transaction reference
+/// * `cf` - The column family atom
+/// * `key` - The key as a binary
+///
+/// # Returns
+/// * `{:ok, value}` if found
+/// * `:not_found` if key doesn't exist
+/// * `{:error, :transaction_closed}` if the transaction was committed or rolled back
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:cf_not_opened, cf}}` if the column family wasn't opened
+/// * `{:error, :busy}` if another transaction holds the key's lock for over a second
+/// * `{:error, {:get_failed, reason}}` on other errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn transaction_get_for_update<'a>(
+    env: Env<'a>,
+    txn_ref: ResourceArc<TransactionRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    transaction_read(env, &txn_ref, cf, key.as_slice(), true)
+}
+
+/// Commits a transaction.
+///
+/// The transaction is finished afterwards whatever the outcome; retry a
+/// conflicting transaction by beginning a new one.
 ///
 /// # Arguments
-/// * `db_ref` - The database reference
+/// * `txn_ref` - The transaction reference
 ///
 /// # Returns
-/// * `{:ok, path}` with the database path
-/// * `{:error, :already_closed}` if database is closed
+/// * `:ok` on success
+/// * `{:error, :busy}` if a tracked key changed since the transaction read or wrote it
+/// * `{:error, :transaction_closed}` if the transaction was committed or rolled back
+/// * `{:error, {:batch_failed, reason}}` on write failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn transaction_commit<'a>(
+    env: Env<'a>,
+    txn_ref: ResourceArc<TransactionRef>,
+) -> NifResult<Term<'a>> {
+    let state = {
+        let mut guard = txn_ref
+            .state
+            .lock()
+            .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+        match guard.take() {
+            Some(state) => state,
+            None => return Ok((atoms::error(), atoms::transaction_closed()).encode(env)),
+        }
+    };
+
+    if fault_injection::take_write_fault(&txn_ref.db) {
+        txn_ref.db.txn_locks.release(state.id, state.tracked.keys());
+        return Ok((atoms::error(), (atoms::batch_failed(), "injected")).encode(env));
+    }
+
+    match commit_txn(&txn_ref.db, state) {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(TxnError::Busy) => Ok((atoms::error(), atoms::busy()).encode(env)),
+        Err(TxnError::Failed(reason)) => {
+            Ok((atoms::error(), (atoms::batch_failed(), reason)).encode(env))
+        }
+    }
+}
+
+/// Discards a transaction's buffered writes and releases its locks.
+///
+/// # Arguments
+/// * `txn_ref` - The transaction reference
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :transaction_closed}` if the transaction was committed or rolled back
 #[rustler::nif]
-fn get_path(env: Env, db_ref: ResourceArc<DbRef>) -> NifResult<Term> {
-    let guard = db_ref
-        .inner
-        .read()
+fn transaction_rollback<'a>(
+    env: Env<'a>,
+    txn_ref: ResourceArc<TransactionRef>,
+) -> NifResult<Term<'a>> {
+    let mut guard = txn_ref
+        .state
+        .lock()
         .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
 
-    match guard.as_ref() {
-        Some(shared_db) => Ok((atoms::ok(), shared_db.path.clone()).encode(env)),
-        None => Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    match guard.take() {
+        Some(state) => {
+            txn_ref.db.txn_locks.release(state.id, state.tracked.keys());
+            Ok(atoms::ok().encode(env))
+        }
+        None => Ok((atoms::error(), atoms::transaction_closed()).encode(env)),
     }
 }
 
-/// Lists all column families in the database.
+/// Resolves the column family of a transaction operation.
+fn transaction_cf<'a>(
+    env: Env<'a>,
+    txn_ref: &TransactionRef,
+    cf: Term<'a>,
+) -> Result<CfName, Term<'a>> {
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Err((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
+    if txn_ref.db.cf_handle(&cf_name).is_none() {
+        return Err((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env));
+    }
+    Ok(cf_name)
+}
+
+/// Buffers a put (`Some`) or delete (`None`) in a transaction.
+fn transaction_write<'a>(
+    env: Env<'a>,
+    txn_ref: &TransactionRef,
+    cf: Term<'a>,
+    key: &[u8],
+    value: Option<Vec<u8>>,
+) -> NifResult<Term<'a>> {
+    let cf_name = match transaction_cf(env, txn_ref, cf) {
+        Ok(name) => name,
+        Err(error) => return Ok(error),
+    };
+
+    let mut guard = txn_ref
+        .state
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+    let state = match guard.as_mut() {
+        Some(state) => state,
+        None => return Ok((atoms::error(), atoms::transaction_closed()).encode(env)),
+    };
+
+    match track_txn_key(&txn_ref.db, state, &cf_name, key) {
+        Ok(()) => {}
+        Err(TxnError::Busy) => return Ok((atoms::error(), atoms::busy()).encode(env)),
+        Err(TxnError::Failed(reason)) => {
+            let tag = if value.is_some() {
+                atoms::put_failed()
+            } else {
+                atoms::delete_failed()
+            };
+            return Ok((atoms::error(), (tag, reason)).encode(env));
+        }
+    }
+    state.writes.insert((cf_name, key.to_vec()), value);
+
+    Ok(atoms::ok().encode(env))
+}
+
+/// Reads a key in a transaction, optionally tracking it for conflicts.
+fn transaction_read<'a>(
+    env: Env<'a>,
+    txn_ref: &TransactionRef,
+    cf: Term<'a>,
+    key: &[u8],
+    for_update: bool,
+) -> NifResult<Term<'a>> {
+    let cf_name = match transaction_cf(env, txn_ref, cf) {
+        Ok(name) => name,
+        Err(error) => return Ok(error),
+    };
+
+    let mut guard = txn_ref
+        .state
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+    let state = match guard.as_mut() {
+        Some(state) => state,
+        None => return Ok((atoms::error(), atoms::transaction_closed()).encode(env)),
+    };
+
+    if for_update {
+        match track_txn_key(&txn_ref.db, state, &cf_name, key) {
+            Ok(()) => {}
+            Err(TxnError::Busy) => return Ok((atoms::error(), atoms::busy()).encode(env)),
+            Err(TxnError::Failed(reason)) => {
+                return Ok((atoms::error(), (atoms::get_failed(), reason)).encode(env))
+            }
+        }
+    }
+
+    // The transaction's own writes take precedence over committed data
+    let value = match state.writes.get(&(cf_name.clone(), key.to_vec())) {
+        Some(pending) => pending.clone(),
+        None => match read_committed(&txn_ref.db, &cf_name, key) {
+            Ok(value) => value,
+            Err(e) => return Ok((atoms::error(), (atoms::get_failed(), e.to_string())).encode(env)),
+        },
+    };
+
+    match value {
+        Some(value) => {
+            let mut binary = NewBinary::new(env, value.len());
+            binary.as_mut_slice().copy_from_slice(&value);
+            Ok((atoms::ok(), Binary::from(binary)).encode(env))
+        }
+        None => Ok(atoms::not_found().encode(env)),
+    }
+}
+
+/// Locks a key and records its committed value the first time a transaction
+/// touches it.
+fn track_txn_key(
+    shared_db: &SharedDb,
+    state: &mut TxnState,
+    cf_name: &CfName,
+    key: &[u8],
+) -> Result<(), TxnError> {
+    let txn_key = (cf_name.clone(), key.to_vec());
+    if state.tracked.contains_key(&txn_key) {
+        return Ok(());
+    }
+    if !shared_db.txn_locks.acquire(&txn_key, state.id) {
+        return Err(TxnError::Busy);
+    }
+    match read_committed(shared_db, cf_name, key) {
+        Ok(value) => {
+            state.tracked.insert(txn_key, value);
+            Ok(())
+        }
+        Err(e) => {
+            // Not tracked, so nothing else would release the lock
+            shared_db.txn_locks.release(state.id, [&txn_key]);
+            Err(TxnError::Failed(e.to_string()))
+        }
+    }
+}
+
+/// Reads the latest committed value of a key, without its `open_with_ttl` stamp.
+fn read_committed(
+    shared_db: &SharedDb,
+    cf_name: &str,
+    key: &[u8],
+) -> Result<Option<Vec<u8>>, rocksdb::Error> {
+    match shared_db.cf_handle(cf_name) {
+        Some(cf) => Ok(shared_db
+            .db
+            .get_cf(&cf, key)?
+            .map(|value| shared_db.unstamp(cf_name, &value).to_vec())),
+        None => Ok(None),
+    }
+}
+
+/// Why a transaction operation or commit was refused.
+#[derive(Debug, PartialEq)]
+enum TxnError {
+    /// Another transaction holds the key's lock, or a tracked key changed
+    /// since the transaction first touched it
+    Busy,
+    /// Reading a tracked key or writing the batch failed
+    Failed(String),
+}
+
+/// Validates a transaction's tracked keys and applies its writes atomically,
+/// then releases its locks.
+fn commit_txn(shared_db: &SharedDb, state: TxnState) -> Result<(), TxnError> {
+    let result = apply_txn(shared_db, &state);
+    shared_db.txn_locks.release(state.id, state.tracked.keys());
+    result
+}
+
+fn apply_txn(shared_db: &SharedDb, state: &TxnState) -> Result<(), TxnError> {
+    let _commit = shared_db
+        .commit_lock
+        .lock()
+        .map_err(|_| TxnError::Failed("lock poisoned".to_string()))?;
+
+    for ((cf_name, key), seen) in &state.tracked {
+        let current =
+            read_committed(shared_db, cf_name, key).map_err(|e| TxnError::Failed(e.to_string()))?;
+        if current != *seen {
+            return Err(TxnError::Busy);
+        }
+    }
+
+    let mut batch = WriteBatch::default();
+    for ((cf_name, key), value) in &state.writes {
+        let cf = shared_db
+            .cf_handle(cf_name)
+            .ok_or_else(|| TxnError::Failed(format!("column family '{}' not found", cf_name)))?;
+        match value {
+            Some(value) => batch.put_cf(&cf, key, shared_db.stamp(cf_name, value)),
+            None => batch.delete_cf(&cf, key),
+        }
+    }
+
+    shared_db
+        .db
+        .write(batch)
+        .map_err(|e| TxnError::Failed(e.to_string()))
+}
+
+// ============================================================================
+// TransactionDB
+// ============================================================================
+
+/// Opens a RocksDB `TransactionDB` at the given path with the TripleStore
+/// column families.
+///
+/// Unlike the emulated transactions of `transaction_begin`, RocksDB itself
+/// locks every key a transaction writes or reads with `txn_get`, and detects
+/// writes committed by others since the transaction began. The handle only
+/// supports the `begin_transaction` family of NIFs.
+///
+/// # Arguments
+/// * `path` - Path to the database directory
 ///
 /// # Returns
-/// * List of column family name atoms
-#[rustler::nif]
-fn list_column_families(env: Env) -> NifResult<Term> {
-    let cf_atoms: Vec<Term> = vec![
-        atoms::id2str().encode(env),
-        atoms::str2id().encode(env),
-        atoms::spo().encode(env),
-        atoms::pos().encode(env),
-        atoms::osp().encode(env),
-        atoms::derived().encode(env),
-        atoms::numeric_range().encode(env),
-    ];
-    Ok(cf_atoms.encode(env))
+/// * `{:ok, txn_db_ref}` on success
+/// * `{:error, {:open_failed, reason}}` on failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn open_transactional(env: Env, path: String) -> NifResult<Term> {
+    match open_txn_db(&path) {
+        Ok(db) => {
+            let txn_db_ref = ResourceArc::new(TxnDbRef {
+                inner: ArcSwapOption::from_pointee(db),
+            });
+            Ok((atoms::ok(), txn_db_ref).encode(env))
+        }
+        Err(e) => Ok((atoms::error(), (atoms::open_failed(), e.to_string())).encode(env)),
+    }
 }
 
-/// Checks if the database is open.
+/// Opens the `TransactionDB` with the tuned options of each column family.
+fn open_txn_db(path: &str) -> Result<SharedTxnDb, rocksdb::Error> {
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+
+    let lock_timeout_ms = TXN_LOCK_TIMEOUT.as_millis() as i64;
+    let mut txn_db_opts = TransactionDBOptions::default();
+    txn_db_opts.set_txn_lock_timeout(lock_timeout_ms);
+    txn_db_opts.set_default_lock_timeout(lock_timeout_ms);
+
+    let default_overrides = CfOpenOptions::default();
+    let cf_descriptors: Vec<ColumnFamilyDescriptor> = CF_NAMES
+        .iter()
+        .map(|name| {
+            ColumnFamilyDescriptor::new(
+                *name,
+                cf_options_for(name, &default_overrides, &DbOpenOptions::default(), None),
+            )
+        })
+        .collect();
+
+    let db = TransactionDB::open_cf_descriptors(&opts, &txn_db_opts, path, cf_descriptors)?;
+    Ok(SharedTxnDb { db })
+}
+
+/// Closes a database opened with `open_transactional`.
+///
+/// Transactions still open keep the database alive until they are committed,
+/// rolled back or garbage-collected.
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if already closed
+#[rustler::nif(schedule = "DirtyCpu")]
+fn close_transactional(env: Env, txn_db_ref: ResourceArc<TxnDbRef>) -> NifResult<Term> {
+    match txn_db_ref.inner.swap(None) {
+        Some(_) => Ok(atoms::ok().encode(env)),
+        None => Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    }
+}
+
+/// Begins a transaction on a database opened with `open_transactional`.
+///
+/// The transaction sees a snapshot taken when it begins: `txn_put`, `txn_delete`
+/// and `txn_get` lock their key and fail with `{:error, :busy}` if another
+/// transaction holds it for over a second or if a write committed since then
+/// changed it. Writes stay invisible to others until `commit`.
 ///
 /// # Arguments
-/// * `db_ref` - The database reference
+/// * `txn_db_ref` - The handle from `open_transactional`
 ///
 /// # Returns
-/// * `true` if open, `false` if closed
-#[rustler::nif]
-fn is_open(db_ref: ResourceArc<DbRef>) -> NifResult<bool> {
-    let guard = db_ref
-        .inner
-        .read()
+/// * `{:ok, txn_ref}` on success
+/// * `{:error, :already_closed}` if database is closed
+#[rustler::nif(schedule = "DirtyCpu")]
+fn begin_transaction(env: Env, txn_db_ref: ResourceArc<TxnDbRef>) -> NifResult<Term> {
+    let shared_db = match txn_db_ref.inner.load_full() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    let txn = begin_txn_db_transaction(&shared_db);
+
+    // SAFETY: We keep the SharedTxnDb alive via Arc, so the transaction remains
+    // valid. The Arc is stored next to it in TxnDbTransactionRef and dropped
+    // after it.
+    let static_txn: Transaction<'static, TransactionDB> = unsafe { std::mem::transmute(txn) };
+
+    let txn_ref = ResourceArc::new(TxnDbTransactionRef {
+        txn: Mutex::new(Some(static_txn)),
+        db: shared_db,
+    });
+
+    Ok((atoms::ok(), txn_ref).encode(env))
+}
+
+/// Starts a RocksDB transaction that validates its writes against the
+/// snapshot taken when it begins.
+fn begin_txn_db_transaction(shared_db: &SharedTxnDb) -> Transaction<'_, TransactionDB> {
+    let mut txn_opts = TransactionOptions::default();
+    txn_opts.set_snapshot(true);
+    txn_opts.set_deadlock_detect(true);
+    shared_db
+        .db
+        .transaction_opt(&WriteOptions::default(), &txn_opts)
+}
+
+/// Whether a TransactionDB error means the key was locked or changed by
+/// another writer, so the caller should retry the transaction.
+fn is_txn_conflict(error: &rocksdb::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::Busy | ErrorKind::TimedOut | ErrorKind::TryAgain
+    )
+}
+
+/// Runs an operation on an open TransactionDB transaction, mapping conflicts
+/// to `{:error, :busy}` and other failures to `{:error, {tag, reason}}`.
+fn with_txn_db_transaction<'a, T>(
+    env: Env<'a>,
+    txn_ref: &TxnDbTransactionRef,
+    cf: Term<'a>,
+    tag: rustler::Atom,
+    op: impl FnOnce(
+        &Transaction<'static, TransactionDB>,
+        &rocksdb::ColumnFamily,
+    ) -> Result<T, rocksdb::Error>,
+) -> NifResult<Result<T, Term<'a>>> {
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok(Err((atoms::error(), (atoms::invalid_cf(), cf)).encode(env))),
+    };
+    let cf_handle = match txn_ref.db.db.cf_handle(&cf_name) {
+        Some(handle) => handle,
+        None => {
+            return Ok(Err(
+                (atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)
+            ))
+        }
+    };
+
+    let guard = txn_ref
+        .txn
+        .lock()
         .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
-    Ok(guard.is_some())
+    let txn = match guard.as_ref() {
+        Some(txn) => txn,
+        None => {
+            return Ok(Err(
+                (atoms::error(), atoms::transaction_closed()).encode(env)
+            ))
+        }
+    };
+
+    match op(txn, cf_handle) {
+        Ok(value) => Ok(Ok(value)),
+        Err(e) if is_txn_conflict(&e) => Ok(Err((atoms::error(), atoms::busy()).encode(env))),
+        Err(e) => Ok(Err((atoms::error(), (tag, e.to_string())).encode(env))),
+    }
+}
+
+/// Writes a key in a TransactionDB transaction and locks it.
+///
+/// # Arguments
+/// * `txn_ref` - The transaction from `begin_transaction`
+/// * `cf` - The column family atom
+/// * `key` - The key as a binary
+/// * `value` - The value as a binary
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :busy}` if another transaction holds the key's lock for over a
+///   second, or a write committed since the transaction began changed the key
+/// * `{:error, :transaction_closed}` if the transaction was committed or rolled back
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:cf_not_opened, cf}}` if the column family wasn't opened
+/// * `{:error, {:put_failed, reason}}` on other errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn txn_put<'a>(
+    env: Env<'a>,
+    txn_ref: ResourceArc<TxnDbTransactionRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+    value: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    let result = with_txn_db_transaction(env, &txn_ref, cf, atoms::put_failed(), |txn, cf| {
+        txn.put_cf(cf, key.as_slice(), value.as_slice())
+    })?;
+    Ok(result
+        .map(|()| atoms::ok().encode(env))
+        .unwrap_or_else(|error| error))
+}
+
+/// Deletes a key in a TransactionDB transaction and locks it.
+///
+/// # Arguments
+/// * `txn_ref` - The transaction from `begin_transaction`
+/// * `cf` - The column family atom
+/// * `key` - The key as a binary
+///
+/// # Returns
+/// Same as `txn_put`, with `{:error, {:delete_failed, reason}}` on other errors.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn txn_delete<'a>(
+    env: Env<'a>,
+    txn_ref: ResourceArc<TxnDbTransactionRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    let result = with_txn_db_transaction(env, &txn_ref, cf, atoms::delete_failed(), |txn, cf| {
+        txn.delete_cf(cf, key.as_slice())
+    })?;
+    Ok(result
+        .map(|()| atoms::ok().encode(env))
+        .unwrap_or_else(|error| error))
+}
+
+/// Reads a key in a TransactionDB transaction and locks it.
+///
+/// Sees the transaction's own writes. Like RocksDB's `GetForUpdate`, the key
+/// stays locked until the transaction finishes, so read-modify-write such as
+/// allocating the next term ID is safe.
+///
+/// # Arguments
+/// * `txn_ref` - The transaction from `begin_transaction`
+/// * `cf` - The column family atom
+/// * `key` - The key as a binary
+///
+/// # Returns
+/// * `{:ok, value}` if found
+/// * `:not_found` if key doesn't exist
+/// * `{:error, :busy}`, `{:error, :transaction_closed}`, `{:error, {:invalid_cf, cf}}`
+///   or `{:error, {:cf_not_opened, cf}}` as for `txn_put`
+/// * `{:error, {:get_failed, reason}}` on other errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn txn_get<'a>(
+    env: Env<'a>,
+    txn_ref: ResourceArc<TxnDbTransactionRef>,
+    cf: Term<'a>,
+    key: Binary<'a>,
+) -> NifResult<Term<'a>> {
+    let result = with_txn_db_transaction(env, &txn_ref, cf, atoms::get_failed(), |txn, cf| {
+        txn.get_for_update_cf(cf, key.as_slice(), true)
+    })?;
+    match result {
+        Ok(Some(value)) => {
+            let mut binary = NewBinary::new(env, value.len());
+            binary.as_mut_slice().copy_from_slice(&value);
+            Ok((atoms::ok(), Binary::from(binary)).encode(env))
+        }
+        Ok(None) => Ok(atoms::not_found().encode(env)),
+        Err(error) => Ok(error),
+    }
+}
+
+/// Commits a TransactionDB transaction and releases its locks.
+///
+/// The transaction is finished whatever the outcome; retry a conflicting
+/// transaction by beginning a new one.
+///
+/// # Arguments
+/// * `txn_ref` - The transaction from `begin_transaction`
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :busy}` if RocksDB reports a conflict
+/// * `{:error, :transaction_closed}` if the transaction was committed or rolled back
+/// * `{:error, {:commit_failed, reason}}` on other errors
+#[rustler::nif(schedule = "DirtyCpu")]
+fn commit<'a>(env: Env<'a>, txn_ref: ResourceArc<TxnDbTransactionRef>) -> NifResult<Term<'a>> {
+    let txn = txn_ref
+        .txn
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?
+        .take();
+
+    match txn.map(Transaction::commit) {
+        Some(Ok(())) => Ok(atoms::ok().encode(env)),
+        Some(Err(e)) if is_txn_conflict(&e) => Ok((atoms::error(), atoms::busy()).encode(env)),
+        Some(Err(e)) => Ok((atoms::error(), (atoms::commit_failed(), e.to_string())).encode(env)),
+        None => Ok((atoms::error(), atoms::transaction_closed()).encode(env)),
+    }
+}
+
+/// Discards a TransactionDB transaction's writes and releases its locks.
+///
+/// # Arguments
+/// * `txn_ref` - The transaction from `begin_transaction`
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :transaction_closed}` if the transaction was committed or rolled back
+/// * `{:error, {:rollback_failed, reason}}` on failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn rollback<'a>(env: Env<'a>, txn_ref: ResourceArc<TxnDbTransactionRef>) -> NifResult<Term<'a>> {
+    let txn = txn_ref
+        .txn
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?
+        .take();
+
+    match txn.map(|txn| txn.rollback()) {
+        Some(Ok(())) => Ok(atoms::ok().encode(env)),
+        Some(Err(e)) => Ok((atoms::error(), (atoms::rollback_failed(), e.to_string())).encode(env)),
+        None => Ok((atoms::error(), atoms::transaction_closed()).encode(env)),
+    }
 }
 
-/// Gets a value from a column family.
+// ============================================================================
+// Compaction Control
+// ============================================================================
+
+/// Reports the state of background compactions.
+///
+/// `running_compactions` is the number of compactions currently executing
+/// across the whole database; `pending_compaction_bytes` is RocksDB's estimate
+/// of the bytes compaction still has to rewrite, summed over all column families.
 ///
 /// # Arguments
 /// * `db_ref` - The database reference
-/// * `cf` - The column family atom (:id2str, :str2id, :spo, :pos, :osp, :derived)
-/// * `key` - The key as a binary
 ///
 /// # Returns
-/// * `{:ok, value}` if found
-/// * `:not_found` if key doesn't exist
+/// * `{:ok, %{running_compactions: n, pending_compaction_bytes: bytes}}` on success
 /// * `{:error, :already_closed}` if database is closed
-/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
-/// * `{:error, {:get_failed, reason}}` on other errors
+/// * `{:error, {:compaction_failed, reason}}` if a property can't be read
 #[rustler::nif(schedule = "DirtyCpu")]
-fn get<'a>(
-    env: Env<'a>,
-    db_ref: ResourceArc<DbRef>,
-    cf: rustler::Atom,
-    key: Binary<'a>,
-) -> NifResult<Term<'a>> {
-    let cf_name = match cf_atom_to_name(cf) {
-        Some(name) => name,
-        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
-    };
-
-    let guard = db_ref
-        .inner
-        .read()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+fn compaction_status<'a>(env: Env<'a>, db_ref: ResourceArc<DbRef>) -> NifResult<Term<'a>> {
+    let guard = db_ref.load();
 
     let shared_db = match guard.as_ref() {
         Some(db) => db,
         None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
     };
 
-    let cf_handle = match shared_db.db.cf_handle(cf_name) {
-        Some(cf) => cf,
-        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
-    };
-
-    match shared_db.db.get_cf(&cf_handle, key.as_slice()) {
-        Ok(Some(value)) => {
-            let mut binary = NewBinary::new(env, value.len());
-            binary.as_mut_slice().copy_from_slice(&value);
-            Ok((atoms::ok(), Binary::from(binary)).encode(env))
+    let status = shared_db
+        .db
+        .property_int_value("rocksdb.num-running-compactions")
+        .map_err(|e| e.to_string())
+        .and_then(|running| {
+            let pending = total_pending_compaction_bytes(shared_db)?;
+            Ok((running.unwrap_or(0), pending))
+        });
+
+    match status {
+        Ok((running, pending)) => {
+            let pairs = [
+                (
+                    atoms::running_compactions().encode(env),
+                    running.encode(env),
+                ),
+                (
+                    atoms::pending_compaction_bytes().encode(env),
+                    pending.encode(env),
+                ),
+            ];
+            Ok((atoms::ok(), Term::map_from_pairs(env, &pairs)?).encode(env))
         }
-        Ok(None) => Ok(atoms::not_found().encode(env)),
-        Err(e) => Ok((atoms::error(), (atoms::get_failed(), e.to_string())).encode(env)),
+        Err(reason) => Ok((atoms::error(), (atoms::compaction_failed(), reason)).encode(env)),
     }
 }
 
-/// Puts a key-value pair into a column family.
+/// Disables automatic compactions on all column families.
+///
+/// Relieves IO pressure during query storms. Writes keep landing in L0, so
+/// leaving compactions disabled for long eventually triggers write stalls;
+/// call `enable_compactions` once the pressure is gone.
 ///
 /// # Arguments
 /// * `db_ref` - The database reference
-/// * `cf` - The column family atom
-/// * `key` - The key as a binary
-/// * `value` - The value as a binary
 ///
 /// # Returns
 /// * `:ok` on success
 /// * `{:error, :already_closed}` if database is closed
-/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
-/// * `{:error, {:put_failed, reason}}` on other errors
+/// * `{:error, {:set_options_failed, reason}}` on failure
 #[rustler::nif(schedule = "DirtyCpu")]
-fn put<'a>(
-    env: Env<'a>,
-    db_ref: ResourceArc<DbRef>,
-    cf: rustler::Atom,
-    key: Binary<'a>,
-    value: Binary<'a>,
-) -> NifResult<Term<'a>> {
-    let cf_name = match cf_atom_to_name(cf) {
-        Some(name) => name,
-        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
-    };
+fn disable_compactions<'a>(env: Env<'a>, db_ref: ResourceArc<DbRef>) -> NifResult<Term<'a>> {
+    toggle_auto_compactions(env, &db_ref, true)
+}
 
-    let guard = db_ref
-        .inner
-        .read()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+/// Re-enables automatic compactions on all column families.
+///
+/// Only flips the option: RocksDB then schedules the compactions skipped while
+/// they were disabled in the background, and this returns right away. Use
+/// `compact_all` or `compact_range` to force the catch-up synchronously.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+///
+/// # Returns
+/// * `:ok` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:set_options_failed, reason}}` if the option can't be changed
+#[rustler::nif(schedule = "DirtyCpu")]
+fn enable_compactions<'a>(env: Env<'a>, db_ref: ResourceArc<DbRef>) -> NifResult<Term<'a>> {
+    toggle_auto_compactions(env, &db_ref, false)
+}
+
+fn toggle_auto_compactions<'a>(env: Env<'a>, db_ref: &DbRef, disable: bool) -> NifResult<Term<'a>> {
+    let guard = db_ref.load();
 
     let shared_db = match guard.as_ref() {
         Some(db) => db,
         None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
     };
 
-    let cf_handle = match shared_db.db.cf_handle(cf_name) {
-        Some(cf) => cf,
-        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
-    };
-
-    match shared_db.db.put_cf(&cf_handle, key.as_slice(), value.as_slice()) {
-        Ok(()) => Ok(atoms::ok().encode(env)),
-        Err(e) => Ok((atoms::error(), (atoms::put_failed(), e.to_string())).encode(env)),
+    let value = if disable { "true" } else { "false" };
+    for cf_name in shared_db.opened_cf_names() {
+        let result = match shared_db.cf_handle(cf_name) {
+            Some(cf) => shared_db
+                .db
+                .set_options_cf(&cf, &[("disable_auto_compactions", value)])
+                .map_err(|e| e.to_string()),
+            None => Err(format!("column family '{}' not found", cf_name)),
+        };
+        if let Err(reason) = result {
+            return Ok((atoms::error(), (atoms::set_options_failed(), reason)).encode(env));
+        }
     }
+
+    Ok(atoms::ok().encode(env))
 }
 
-/// Deletes a key from a column family.
+/// Returns RocksDB's estimate of the bytes compaction still has to rewrite.
+///
+/// Summed over all opened column families. A value that keeps growing means
+/// compaction is falling behind the write rate; loaders should throttle
+/// before RocksDB starts stalling writes itself (see `write_stall_stats`).
 ///
 /// # Arguments
 /// * `db_ref` - The database reference
-/// * `cf` - The column family atom
-/// * `key` - The key to delete
 ///
 /// # Returns
-/// * `:ok` on success (even if key didn't exist)
+/// * `{:ok, bytes}` on success
 /// * `{:error, :already_closed}` if database is closed
-/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
-/// * `{:error, {:delete_failed, reason}}` on other errors
+/// * `{:error, {:compaction_failed, reason}}` if the property can't be read
 #[rustler::nif(schedule = "DirtyCpu")]
-fn delete<'a>(
-    env: Env<'a>,
-    db_ref: ResourceArc<DbRef>,
-    cf: rustler::Atom,
-    key: Binary<'a>,
-) -> NifResult<Term<'a>> {
-    let cf_name = match cf_atom_to_name(cf) {
-        Some(name) => name,
-        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
-    };
-
-    let guard = db_ref
-        .inner
-        .read()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+fn pending_compaction_bytes<'a>(env: Env<'a>, db_ref: ResourceArc<DbRef>) -> NifResult<Term<'a>> {
+    let guard = db_ref.load();
 
     let shared_db = match guard.as_ref() {
         Some(db) => db,
         None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
     };
 
-    let cf_handle = match shared_db.db.cf_handle(cf_name) {
-        Some(cf) => cf,
-        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
-    };
-
-    match shared_db.db.delete_cf(&cf_handle, key.as_slice()) {
-        Ok(()) => Ok(atoms::ok().encode(env)),
-        Err(e) => Ok((atoms::error(), (atoms::delete_failed(), e.to_string())).encode(env)),
+    match total_pending_compaction_bytes(shared_db) {
+        Ok(pending) => Ok((atoms::ok(), pending).encode(env)),
+        Err(reason) => Ok((atoms::error(), (atoms::compaction_failed(), reason)).encode(env)),
     }
 }
 
-/// Checks if a key exists in a column family.
+/// Reports the signals RocksDB uses to stall writes.
+///
+/// * `write_stopped` - whether writes are currently stopped outright
+/// * `delayed_write_rate` - the throttled write rate in bytes/sec while
+///   writes are being slowed down, `0` when they are not
+/// * `pending_compaction_bytes` - same value as `pending_compaction_bytes/1`
+/// * `max_l0_files` - largest L0 file count across column families; RocksDB
+///   slows writes once this reaches `level0_slowdown_writes_trigger`
+///
+/// Together with the pending byte estimate this lets a loader back off before
+/// RocksDB stalls, and see when it already has.
 ///
 /// # Arguments
 /// * `db_ref` - The database reference
-/// * `cf` - The column family atom
-/// * `key` - The key to check
 ///
 /// # Returns
-/// * `{:ok, true}` if key exists
-/// * `{:ok, false}` if key doesn't exist
+/// * `{:ok, %{write_stopped: bool, delayed_write_rate: n, pending_compaction_bytes: n, max_l0_files: n}}`
 /// * `{:error, :already_closed}` if database is closed
-/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
-/// * `{:error, {:get_failed, reason}}` on other errors
+/// * `{:error, {:compaction_failed, reason}}` if a property can't be read
 #[rustler::nif(schedule = "DirtyCpu")]
-fn exists<'a>(
-    env: Env<'a>,
-    db_ref: ResourceArc<DbRef>,
-    cf: rustler::Atom,
-    key: Binary<'a>,
-) -> NifResult<Term<'a>> {
-    let cf_name = match cf_atom_to_name(cf) {
-        Some(name) => name,
-        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
-    };
-
-    let guard = db_ref
-        .inner
-        .read()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+fn write_stall_stats<'a>(env: Env<'a>, db_ref: ResourceArc<DbRef>) -> NifResult<Term<'a>> {
+    let guard = db_ref.load();
 
     let shared_db = match guard.as_ref() {
         Some(db) => db,
         None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
     };
 
-    let cf_handle = match shared_db.db.cf_handle(cf_name) {
-        Some(cf) => cf,
-        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    let db_property = |name: &str| {
+        shared_db
+            .db
+            .property_int_value(name)
+            .map(|value| value.unwrap_or(0))
+            .map_err(|e| e.to_string())
     };
 
-    // Check if key exists by attempting to get it
-    match shared_db.db.get_cf(&cf_handle, key.as_slice()) {
-        Ok(Some(_)) => Ok((atoms::ok(), true).encode(env)),
-        Ok(None) => Ok((atoms::ok(), false).encode(env)),
-        Err(e) => Ok((atoms::error(), (atoms::get_failed(), e.to_string())).encode(env)),
+    let stats = db_property("rocksdb.is-write-stopped").and_then(|stopped| {
+        let delayed_rate = db_property("rocksdb.actual-delayed-write-rate")?;
+        let pending = total_pending_compaction_bytes(shared_db)?;
+        let mut max_l0_files = 0u64;
+        for cf_name in shared_db.opened_cf_names() {
+            let cf = shared_db
+                .cf_handle(cf_name)
+                .ok_or_else(|| format!("column family '{}' not found", cf_name))?;
+            let l0_files = shared_db
+                .db
+                .property_int_value_cf(&cf, "rocksdb.num-files-at-level0")
+                .map_err(|e| e.to_string())?
+                .unwrap_or(0);
+            max_l0_files = max_l0_files.max(l0_files);
+        }
+        Ok((stopped != 0, delayed_rate, pending, max_l0_files))
+    });
+
+    match stats {
+        Ok((stopped, delayed_rate, pending, max_l0_files)) => {
+            let pairs = [
+                (atoms::write_stopped().encode(env), stopped.encode(env)),
+                (
+                    atoms::delayed_write_rate().encode(env),
+                    delayed_rate.encode(env),
+                ),
+                (
+                    atoms::pending_compaction_bytes().encode(env),
+                    pending.encode(env),
+                ),
+                (atoms::max_l0_files().encode(env), max_l0_files.encode(env)),
+            ];
+            Ok((atoms::ok(), Term::map_from_pairs(env, &pairs)?).encode(env))
+        }
+        Err(reason) => Ok((atoms::error(), (atoms::compaction_failed(), reason)).encode(env)),
     }
 }
 
-/// Atomically writes multiple key-value pairs to column families.
+/// Compacts a column family, forcing the bottommost level to be rewritten.
+///
+/// A default manual compaction pushes upper-level data down but may leave
+/// files already in the last level untouched, so tombstones and the values
+/// they shadow that have both reached it are never purged. Forcing the
+/// bottommost level rewrites those files and drops them, which is what
+/// reclaims space after large deletes once they have settled.
+///
+/// This is not sufficient when the deleted entries are still needed: an open
+/// snapshot, iterator or transaction that can see them keeps them alive
+/// through the compaction. It also rewrites the whole bottommost level, so on
+/// a large column family with few deletes it costs about as much as a full
+/// compaction.
+///
+/// The binding does not return the compaction status, so failures are
+/// detected through the database's background error count.
 ///
 /// # Arguments
 /// * `db_ref` - The database reference
-/// * `operations` - List of `{cf, key, value}` tuples
-/// * `sync` - Whether to sync to disk (true = fsync after write, false = defer to OS)
-///
-/// For bulk loading, set `sync=false` to improve throughput. Data is still
-/// protected by the WAL, but fsync is deferred to the OS. This can provide
-/// 2-3x throughput improvement for large batch operations.
+/// * `cf` - Column family atom
 ///
 /// # Returns
 /// * `:ok` on success
 /// * `{:error, :already_closed}` if database is closed
 /// * `{:error, {:invalid_cf, cf}}` if column family is invalid
-/// * `{:error, {:batch_failed, reason}}` on other errors
+/// * `{:error, {:cf_not_opened, cf}}` if column family was not opened
+/// * `{:error, {:compact_failed, reason}}` if the compaction failed
 #[rustler::nif(schedule = "DirtyCpu")]
-fn write_batch<'a>(
+fn compact_bottommost<'a>(
     env: Env<'a>,
     db_ref: ResourceArc<DbRef>,
-    operations: Term<'a>,
-    sync: bool,
+    cf: Term<'a>,
 ) -> NifResult<Term<'a>> {
-    let guard = db_ref
-        .inner
-        .read()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+    let guard = db_ref.load();
 
     let shared_db = match guard.as_ref() {
         Some(db) => db,
         None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
     };
 
-    let mut batch = WriteBatch::default();
-
-    // Parse the list of operations
-    let iter: ListIterator = operations
-        .decode()
-        .map_err(|_| rustler::Error::Term(Box::new("expected list")))?;
-
-    for item in iter {
-        // Each item should be a tuple {cf, key, value}
-        let tuple = rustler::types::tuple::get_tuple(item)
-            .map_err(|_| rustler::Error::Term(Box::new("expected tuple")))?;
-
-        if tuple.len() == 3 {
-            // Simple format: {cf, key, value} - treat as put
-            let cf_atom: rustler::Atom = tuple[0]
-                .decode()
-                .map_err(|_| rustler::Error::Term(Box::new("expected atom for cf")))?;
-            let key: Binary = tuple[1]
-                .decode()
-                .map_err(|_| rustler::Error::Term(Box::new("expected binary for key")))?;
-            let value: Binary = tuple[2]
-                .decode()
-                .map_err(|_| rustler::Error::Term(Box::new("expected binary for value")))?;
-
-            let cf_name = match cf_atom_to_name(cf_atom) {
-                Some(name) => name,
-                None => return Ok((atoms::error(), (atoms::invalid_cf(), cf_atom)).encode(env)),
-            };
-
-            let cf_handle = match shared_db.db.cf_handle(cf_name) {
-                Some(cf) => cf,
-                None => return Ok((atoms::error(), (atoms::invalid_cf(), cf_atom)).encode(env)),
-            };
-
-            batch.put_cf(&cf_handle, key.as_slice(), value.as_slice());
-        } else if tuple.len() == 4 {
-            // Extended format: {:put, cf, key, value}
-            let op_atom: rustler::Atom = tuple[0]
-                .decode()
-                .map_err(|_| rustler::Error::Term(Box::new("expected atom for operation")))?;
-            let cf_atom: rustler::Atom = tuple[1]
-                .decode()
-                .map_err(|_| rustler::Error::Term(Box::new("expected atom for cf")))?;
-            let key: Binary = tuple[2]
-                .decode()
-                .map_err(|_| rustler::Error::Term(Box::new("expected binary for key")))?;
-            let value: Binary = tuple[3]
-                .decode()
-                .map_err(|_| rustler::Error::Term(Box::new("expected binary for value")))?;
-
-            if op_atom != atoms::put() {
-                return Ok((atoms::error(), (atoms::invalid_operation(), op_atom)).encode(env));
-            }
-
-            let cf_name = match cf_atom_to_name(cf_atom) {
-                Some(name) => name,
-                None => return Ok((atoms::error(), (atoms::invalid_cf(), cf_atom)).encode(env)),
-            };
-
-            let cf_handle = match shared_db.db.cf_handle(cf_name) {
-                Some(cf) => cf,
-                None => return Ok((atoms::error(), (atoms::invalid_cf(), cf_atom)).encode(env)),
-            };
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
 
-            batch.put_cf(&cf_handle, key.as_slice(), value.as_slice());
-        } else {
-            return Ok((atoms::error(), atoms::invalid_operation()).encode(env));
-        }
-    }
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
 
-    // Create WriteOptions with sync setting
-    let mut write_opts = WriteOptions::default();
-    write_opts.set_sync(sync);
+    let mut opts = CompactOptions::default();
+    opts.set_bottommost_level_compaction(BottommostLevelCompaction::Force);
 
-    match shared_db.db.write_opt(batch, &write_opts) {
+    match run_compaction(shared_db, || {
+        shared_db
+            .db
+            .compact_range_cf_opt::<&[u8], &[u8]>(&cf_handle, None, None, &opts)
+    }) {
         Ok(()) => Ok(atoms::ok().encode(env)),
-        Err(e) => Ok((atoms::error(), (atoms::batch_failed(), e.to_string())).encode(env)),
+        Err(reason) => Ok((atoms::error(), (atoms::compact_failed(), reason)).encode(env)),
     }
 }
 
-/// Atomically deletes multiple keys from column families.
+/// Compacts the keys of a column family in `[start_key, end_key]`.
+///
+/// Rewrites the overlapping SST files of the range down the levels, which
+/// removes the read amplification left by bulk loads and purges deleted
+/// entries. `nil` for either bound means the range is open on that side, so
+/// `compact_range(db, cf, nil, nil)` compacts the whole column family. The
+/// database stays usable while compacting.
+///
+/// The binding does not return the compaction status, so failures are
+/// detected through the database's background error count.
 ///
 /// # Arguments
 /// * `db_ref` - The database reference
-/// * `operations` - List of `{cf, key}` tuples
-/// * `sync` - Whether to sync to disk (true = fsync after write, false = defer to OS)
+/// * `cf` - Column family atom
+/// * `start_key` - First key to compact, or `nil`
+/// * `end_key` - Last key to compact, or `nil`
 ///
 /// # Returns
 /// * `:ok` on success
 /// * `{:error, :already_closed}` if database is closed
 /// * `{:error, {:invalid_cf, cf}}` if column family is invalid
-/// * `{:error, {:batch_failed, reason}}` on other errors
+/// * `{:error, {:cf_not_opened, cf}}` if column family was not opened
+/// * `{:error, {:compact_failed, reason}}` if the compaction failed
 #[rustler::nif(schedule = "DirtyCpu")]
-fn delete_batch<'a>(
+fn compact_range<'a>(
     env: Env<'a>,
     db_ref: ResourceArc<DbRef>,
-    operations: Term<'a>,
-    sync: bool,
+    cf: Term<'a>,
+    start_key: Option<Binary<'a>>,
+    end_key: Option<Binary<'a>>,
 ) -> NifResult<Term<'a>> {
-    let guard = db_ref
-        .inner
-        .read()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+    let guard = db_ref.load();
 
     let shared_db = match guard.as_ref() {
         Some(db) => db,
         None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
     };
 
-    let mut batch = WriteBatch::default();
-
-    // Parse the list of operations
-    let iter: ListIterator = operations
-        .decode()
-        .map_err(|_| rustler::Error::Term(Box::new("expected list")))?;
-
-    for item in iter {
-        // Each item should be a tuple {cf, key}
-        let tuple = rustler::types::tuple::get_tuple(item)
-            .map_err(|_| rustler::Error::Term(Box::new("expected tuple")))?;
-
-        if tuple.len() != 2 {
-            return Ok((atoms::error(), atoms::invalid_operation()).encode(env));
-        }
-
-        let cf_atom: rustler::Atom = tuple[0]
-            .decode()
-            .map_err(|_| rustler::Error::Term(Box::new("expected atom for cf")))?;
-        let key: Binary = tuple[1]
-            .decode()
-            .map_err(|_| rustler::Error::Term(Box::new("expected binary for key")))?;
-
-        let cf_name = match cf_atom_to_name(cf_atom) {
-            Some(name) => name,
-            None => return Ok((atoms::error(), (atoms::invalid_cf(), cf_atom)).encode(env)),
-        };
-
-        let cf_handle = match shared_db.db.cf_handle(cf_name) {
-            Some(cf) => cf,
-            None => return Ok((atoms::error(), (atoms::invalid_cf(), cf_atom)).encode(env)),
-        };
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
 
-        batch.delete_cf(&cf_handle, key.as_slice());
-    }
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
 
-    // Create WriteOptions with sync setting
-    let mut write_opts = WriteOptions::default();
-    write_opts.set_sync(sync);
+    let start = start_key.as_ref().map(|key| key.as_slice());
+    let end = end_key.as_ref().map(|key| key.as_slice());
 
-    match shared_db.db.write_opt(batch, &write_opts) {
+    match run_compaction(shared_db, || {
+        shared_db.db.compact_range_cf(&cf_handle, start, end)
+    }) {
         Ok(()) => Ok(atoms::ok().encode(env)),
-        Err(e) => Ok((atoms::error(), (atoms::batch_failed(), e.to_string())).encode(env)),
+        Err(reason) => Ok((atoms::error(), (atoms::compact_failed(), reason)).encode(env)),
     }
 }
 
-/// Atomically performs mixed put and delete operations.
+/// Fully compacts every opened column family, one after another.
+///
+/// Meant for maintenance jobs after bulk loads or large deletes; it rewrites
+/// all data, so it is slow on large databases.
 ///
 /// # Arguments
 /// * `db_ref` - The database reference
-/// * `operations` - List of operations:
-///   - `{:put, cf, key, value}` for puts
-///   - `{:delete, cf, key}` for deletes
-/// * `sync` - Whether to sync to disk (true = fsync after write, false = defer to OS)
 ///
 /// # Returns
 /// * `:ok` on success
 /// * `{:error, :already_closed}` if database is closed
-/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
-/// * `{:error, {:invalid_operation, op}}` if operation type is invalid
-/// * `{:error, {:batch_failed, reason}}` on other errors
+/// * `{:error, {:compact_failed, reason}}` if a compaction failed
 #[rustler::nif(schedule = "DirtyCpu")]
-fn mixed_batch<'a>(
-    env: Env<'a>,
-    db_ref: ResourceArc<DbRef>,
-    operations: Term<'a>,
-    sync: bool,
-) -> NifResult<Term<'a>> {
-    let guard = db_ref
-        .inner
-        .read()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+fn compact_all<'a>(env: Env<'a>, db_ref: ResourceArc<DbRef>) -> NifResult<Term<'a>> {
+    let guard = db_ref.load();
 
     let shared_db = match guard.as_ref() {
         Some(db) => db,
         None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
     };
 
-    let mut batch = WriteBatch::default();
-
-    // Parse the list of operations
-    let iter: ListIterator = operations
-        .decode()
-        .map_err(|_| rustler::Error::Term(Box::new("expected list")))?;
-
-    for item in iter {
-        let tuple = rustler::types::tuple::get_tuple(item)
-            .map_err(|_| rustler::Error::Term(Box::new("expected tuple")))?;
-
-        if tuple.is_empty() {
-            return Ok((atoms::error(), atoms::invalid_operation()).encode(env));
+    let result = run_compaction(shared_db, || {
+        for cf_handle in shared_db
+            .opened_cf_names()
+            .filter_map(|name| shared_db.cf_handle(name))
+        {
+            shared_db
+                .db
+                .compact_range_cf::<&[u8], &[u8]>(&cf_handle, None, None);
         }
+    });
 
-        let op_atom: rustler::Atom = tuple[0]
-            .decode()
-            .map_err(|_| rustler::Error::Term(Box::new("expected atom for operation")))?;
-
-        if op_atom == atoms::put() {
-            // {:put, cf, key, value}
-            if tuple.len() != 4 {
-                return Ok((atoms::error(), atoms::invalid_operation()).encode(env));
-            }
-
-            let cf_atom: rustler::Atom = tuple[1]
-                .decode()
-                .map_err(|_| rustler::Error::Term(Box::new("expected atom for cf")))?;
-            let key: Binary = tuple[2]
-                .decode()
-                .map_err(|_| rustler::Error::Term(Box::new("expected binary for key")))?;
-            let value: Binary = tuple[3]
-                .decode()
-                .map_err(|_| rustler::Error::Term(Box::new("expected binary for value")))?;
-
-            let cf_name = match cf_atom_to_name(cf_atom) {
-                Some(name) => name,
-                None => return Ok((atoms::error(), (atoms::invalid_cf(), cf_atom)).encode(env)),
-            };
-
-            let cf_handle = match shared_db.db.cf_handle(cf_name) {
-                Some(cf) => cf,
-                None => return Ok((atoms::error(), (atoms::invalid_cf(), cf_atom)).encode(env)),
-            };
-
-            batch.put_cf(&cf_handle, key.as_slice(), value.as_slice());
-        } else if op_atom == atoms::delete() {
-            // {:delete, cf, key}
-            if tuple.len() != 3 {
-                return Ok((atoms::error(), atoms::invalid_operation()).encode(env));
-            }
-
-            let cf_atom: rustler::Atom = tuple[1]
-                .decode()
-                .map_err(|_| rustler::Error::Term(Box::new("expected atom for cf")))?;
-            let key: Binary = tuple[2]
-                .decode()
-                .map_err(|_| rustler::Error::Term(Box::new("expected binary for key")))?;
+    match result {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(reason) => Ok((atoms::error(), (atoms::compact_failed(), reason)).encode(env)),
+    }
+}
 
-            let cf_name = match cf_atom_to_name(cf_atom) {
-                Some(name) => name,
-                None => return Ok((atoms::error(), (atoms::invalid_cf(), cf_atom)).encode(env)),
-            };
+/// Runs a manual compaction and reports whether it raised a background error.
+fn run_compaction(shared_db: &SharedDb, compact: impl FnOnce()) -> Result<(), String> {
+    let background_errors = || {
+        shared_db
+            .db
+            .property_int_value("rocksdb.background-errors")
+            .map(|value| value.unwrap_or(0))
+            .map_err(|e| e.to_string())
+    };
 
-            let cf_handle = match shared_db.db.cf_handle(cf_name) {
-                Some(cf) => cf,
-                None => return Ok((atoms::error(), (atoms::invalid_cf(), cf_atom)).encode(env)),
-            };
+    let before = background_errors()?;
+    compact();
 
-            batch.delete_cf(&cf_handle, key.as_slice());
-        } else {
-            return Ok((atoms::error(), (atoms::invalid_operation(), op_atom)).encode(env));
-        }
+    if background_errors()? > before {
+        return Err("background error during compaction".to_string());
     }
+    Ok(())
+}
 
-    // Create WriteOptions with sync setting
-    let mut write_opts = WriteOptions::default();
-    write_opts.set_sync(sync);
-
-    match shared_db.db.write_opt(batch, &write_opts) {
-        Ok(()) => Ok(atoms::ok().encode(env)),
-        Err(e) => Ok((atoms::error(), (atoms::batch_failed(), e.to_string())).encode(env)),
+/// Estimated bytes compaction still has to rewrite, summed over opened column families.
+fn total_pending_compaction_bytes(shared_db: &SharedDb) -> Result<u64, String> {
+    let mut pending = 0u64;
+    for cf_name in shared_db.opened_cf_names() {
+        pending += cf_pending_compaction_bytes(shared_db, cf_name)?;
     }
+    Ok(pending)
+}
+
+/// Estimated bytes compaction still has to rewrite for one column family.
+fn cf_pending_compaction_bytes(shared_db: &SharedDb, cf_name: &str) -> Result<u64, String> {
+    let cf = shared_db
+        .cf_handle(cf_name)
+        .ok_or_else(|| format!("column family '{}' not found", cf_name))?;
+    shared_db
+        .db
+        .property_int_value_cf(&cf, "rocksdb.estimate-pending-compaction-bytes")
+        .map(|value| value.unwrap_or(0))
+        .map_err(|e| e.to_string())
 }
 
 // ============================================================================
-// Iterator Operations
+// Size Estimation
 // ============================================================================
 
-/// Creates a prefix iterator for a column family.
+/// Estimates the on-disk bytes of each key range in a column family.
 ///
-/// The iterator returns all key-value pairs where the key starts with the given prefix.
-/// The iterator must be closed with `iterator_close` when done.
+/// Each range is `{start_key, end_key}` with `end_key` exclusive. The estimate
+/// is the total size of the live SST files whose key span overlaps the range:
+/// data still in the memtables is not counted, and a file straddling a range
+/// boundary counts in full. Best-effort only — good enough to compare the
+/// selectivity of candidate scans, not to report exact sizes.
 ///
 /// # Arguments
 /// * `db_ref` - The database reference
-/// * `cf` - The column family atom
-/// * `prefix` - The prefix to iterate over
+/// * `cf` - Column family atom
+/// * `ranges` - List of `{start_key, end_key}` tuples
 ///
 /// # Returns
-/// * `{:ok, iterator_ref}` on success
+/// * `{:ok, [bytes, ...]}` with one estimate per range, in order
 /// * `{:error, :already_closed}` if database is closed
 /// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:cf_not_opened, cf}}` if column family was not opened
+/// * `{:error, {:estimate_failed, reason}}` if the file metadata can't be read
 #[rustler::nif(schedule = "DirtyCpu")]
-fn prefix_iterator<'a>(
+fn approximate_sizes<'a>(
     env: Env<'a>,
     db_ref: ResourceArc<DbRef>,
-    cf: rustler::Atom,
-    prefix: Binary<'a>,
+    cf: Term<'a>,
+    ranges: Vec<(Binary<'a>, Binary<'a>)>,
 ) -> NifResult<Term<'a>> {
-    let cf_name = match cf_atom_to_name(cf) {
-        Some(name) => name,
-        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
-    };
-
-    let guard = db_ref
-        .inner
-        .read()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+    let guard = db_ref.load();
 
     let shared_db = match guard.as_ref() {
-        Some(db) => Arc::clone(db),
+        Some(db) => db,
         None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
     };
 
-    let cf_handle = match shared_db.db.cf_handle(cf_name) {
-        Some(cf) => cf,
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
         None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
     };
 
-    let prefix_bytes = prefix.as_slice().to_vec();
-
-    // Configure read options for prefix iteration
-    // For CFs with prefix extractors, we need to handle different prefix lengths:
-    // - If prefix >= PREFIX_LENGTH bytes: use prefix-based iteration for bloom filter benefits
-    // - If prefix < PREFIX_LENGTH bytes: use total_order_seek to avoid bloom filter issues
-    let mut read_opts = ReadOptions::default();
-    if PREFIX_CFS.contains(&cf_name) {
-        if prefix_bytes.len() >= PREFIX_LENGTH {
-            // Use prefix-based seek for bloom filter benefits
-            read_opts.set_prefix_same_as_start(true);
-            read_opts.set_total_order_seek(false);
-        } else {
-            // Short prefix: use total_order_seek to avoid incorrect bloom filter behavior
-            read_opts.set_total_order_seek(true);
-        }
+    if shared_db.cf_handle(&cf_name).is_none() {
+        return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env));
     }
 
-    // Create the iterator with configured read options
-    let iterator = shared_db.db.iterator_cf_opt(
-        &cf_handle,
-        read_opts,
-        IteratorMode::From(&prefix_bytes, rocksdb::Direction::Forward),
-    );
-
-    // SAFETY: We keep the SharedDb alive via Arc, so the iterator remains valid.
-    // The Arc<SharedDb> is stored in IteratorRef and will keep the DB alive
-    // even if DbRef.close() is called, preventing use-after-free.
-    let static_iterator: DBIteratorWithThreadMode<'static, DB> = unsafe {
-        std::mem::transmute(iterator)
-    };
-
-    let iter_ref = ResourceArc::new(IteratorRef {
-        iterator: Mutex::new(Some(static_iterator)),
-        db: shared_db,
-        prefix: prefix_bytes,
-        cf_name: cf_name.to_string(),
-    });
+    let ranges: Vec<(&[u8], &[u8])> = ranges
+        .iter()
+        .map(|(start, end)| (start.as_slice(), end.as_slice()))
+        .collect();
 
-    Ok((atoms::ok(), iter_ref).encode(env))
+    match approximate_range_bytes(shared_db, &cf_name, &ranges) {
+        Ok(sizes) => Ok((atoms::ok(), sizes).encode(env)),
+        Err(reason) => Ok((atoms::error(), (atoms::estimate_failed(), reason)).encode(env)),
+    }
 }
 
-/// Gets the next key-value pair from the iterator.
+/// Returns RocksDB's estimate of the number of keys in a column family.
+///
+/// Reads the `rocksdb.estimate-num-keys` property, which sums entry counts of
+/// the memtables and SST files without deduplicating overwrites or applying
+/// deletes. Best-effort only: it can be off in either direction, especially
+/// right after heavy overwrites or deletes that compaction has not yet merged.
 ///
 /// # Arguments
-/// * `iter_ref` - The iterator reference
+/// * `db_ref` - The database reference
+/// * `cf` - Column family atom
 ///
 /// # Returns
-/// * `{:ok, key, value}` if there's a next item with matching prefix
-/// * `:end` if the iterator is exhausted or prefix no longer matches
-/// * `{:error, :iterator_closed}` if iterator was closed
-/// * `{:error, {:iterator_failed, reason}}` on error
+/// * `{:ok, count}` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:cf_not_opened, cf}}` if column family was not opened
+/// * `{:error, {:estimate_failed, reason}}` if the property can't be read
 #[rustler::nif(schedule = "DirtyCpu")]
-fn iterator_next<'a>(env: Env<'a>, iter_ref: ResourceArc<IteratorRef>) -> NifResult<Term<'a>> {
-    let mut iter_guard = iter_ref
-        .iterator
-        .lock()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+fn estimate_num_keys<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+) -> NifResult<Term<'a>> {
+    let guard = db_ref.load();
 
-    let iterator = match iter_guard.as_mut() {
-        Some(iter) => iter,
-        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
     };
 
-    match iterator.next() {
-        Some(Ok((key, value))) => {
-            // Check if key still has the prefix
-            if !key.starts_with(&iter_ref.prefix) {
-                return Ok(atoms::iterator_end().encode(env));
-            }
-
-            let mut key_binary = NewBinary::new(env, key.len());
-            key_binary.as_mut_slice().copy_from_slice(&key);
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
 
-            let mut value_binary = NewBinary::new(env, value.len());
-            value_binary.as_mut_slice().copy_from_slice(&value);
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
 
-            Ok((atoms::ok(), Binary::from(key_binary), Binary::from(value_binary)).encode(env))
-        }
-        Some(Err(e)) => {
-            Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env))
-        }
-        None => Ok(atoms::iterator_end().encode(env)),
+    match shared_db
+        .db
+        .property_int_value_cf(&cf_handle, "rocksdb.estimate-num-keys")
+    {
+        Ok(count) => Ok((atoms::ok(), count.unwrap_or(0)).encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::estimate_failed(), e.to_string())).encode(env)),
     }
 }
 
-/// Seeks the iterator to a specific key.
+/// Bytes of the live SST files in `cf_name` overlapping each `[start, end)` range.
+fn approximate_range_bytes(
+    shared_db: &SharedDb,
+    cf_name: &str,
+    ranges: &[(&[u8], &[u8])],
+) -> Result<Vec<u64>, String> {
+    let files = shared_db.db.live_files().map_err(|e| e.to_string())?;
+    let files: Vec<_> = files
+        .iter()
+        .filter(|file| file.column_family_name == cf_name)
+        .collect();
+
+    let sizes = ranges
+        .iter()
+        .map(|(start, end)| {
+            files
+                .iter()
+                .filter(|file| {
+                    let starts_before_end =
+                        file.start_key.as_deref().is_none_or(|first| first < *end);
+                    let ends_after_start =
+                        file.end_key.as_deref().is_none_or(|last| last >= *start);
+                    starts_before_end && ends_after_start
+                })
+                .map(|file| file.size as u64)
+                .sum()
+        })
+        .collect();
+    Ok(sizes)
+}
+
+// ============================================================================
+// Properties
+// ============================================================================
+
+/// Reads a RocksDB property of a column family as a string.
 ///
-/// After seeking, the iterator will return keys >= target that match the prefix.
-/// This is essential for Leapfrog Triejoin in Phase 3.
+/// Any property RocksDB knows can be read, e.g. `rocksdb.stats`,
+/// `rocksdb.cur-size-all-mem-tables` or `rocksdb.num-files-at-level0`.
 ///
 /// # Arguments
-/// * `iter_ref` - The iterator reference
-/// * `target` - The key to seek to
+/// * `db_ref` - The database reference
+/// * `cf` - Column family atom
+/// * `name` - Property name
 ///
 /// # Returns
-/// * `:ok` on success
-/// * `{:error, :iterator_closed}` if iterator was closed
+/// * `{:ok, value}` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:cf_not_opened, cf}}` if column family was not opened
+/// * `{:error, :unknown_property}` if RocksDB does not know the property
 #[rustler::nif(schedule = "DirtyCpu")]
-fn iterator_seek<'a>(
+fn get_property<'a>(
     env: Env<'a>,
-    iter_ref: ResourceArc<IteratorRef>,
-    target: Binary<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    name: String,
 ) -> NifResult<Term<'a>> {
-    let mut iter_guard = iter_ref
-        .iterator
-        .lock()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+    with_cf_property(env, db_ref, cf, |shared_db, cf_handle| {
+        shared_db
+            .db
+            .property_value_cf(cf_handle, name.as_str())
+            .ok()
+            .flatten()
+            .map(|value| value.encode(env))
+    })
+}
 
-    let iterator = match iter_guard.as_mut() {
-        Some(iter) => iter,
-        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
-    };
+/// Reads a numeric RocksDB property of a column family as an integer.
+///
+/// Only properties with an integer value can be read this way; string
+/// properties such as `rocksdb.stats` report `:unknown_property` and must be
+/// read with `get_property`.
+///
+/// # Arguments
+/// * `db_ref` - The database reference
+/// * `cf` - Column family atom
+/// * `name` - Property name
+///
+/// # Returns
+/// * `{:ok, integer}` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_cf, cf}}` if column family is invalid
+/// * `{:error, {:cf_not_opened, cf}}` if column family was not opened
+/// * `{:error, :unknown_property}` if the property is unknown or not numeric
+#[rustler::nif(schedule = "DirtyCpu")]
+fn get_int_property<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    name: String,
+) -> NifResult<Term<'a>> {
+    with_cf_property(env, db_ref, cf, |shared_db, cf_handle| {
+        shared_db
+            .db
+            .property_int_value_cf(cf_handle, name.as_str())
+            .ok()
+            .flatten()
+            .map(|value| value.encode(env))
+    })
+}
 
-    // Access the database directly from our Arc<SharedDb>
-    let cf_handle = match iter_ref.db.db.cf_handle(&iter_ref.cf_name) {
-        Some(cf) => cf,
-        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+/// Resolves the column family and encodes the property `read` returns.
+///
+/// `read` returns `None` when RocksDB has no such property for the column
+/// family, which becomes `{:error, :unknown_property}`.
+fn with_cf_property<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    read: impl FnOnce(&SharedDb, &rocksdb::ColumnFamily) -> Option<Term<'a>>,
+) -> NifResult<Term<'a>> {
+    let guard = db_ref.load();
+
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
     };
 
-    // Create new iterator at the seek position with proper read options
-    // Must match the logic in prefix_iterator for prefix extractor compatibility
-    let target_bytes = target.as_slice();
-    let cf_name = iter_ref.cf_name.as_str();
+    let cf_name = match cf_term_to_name(cf) {
+        Some(name) => name,
+        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
+    };
 
-    let mut read_opts = ReadOptions::default();
-    if PREFIX_CFS.contains(&cf_name) {
-        if iter_ref.prefix.len() >= PREFIX_LENGTH {
-            // Use prefix-based seek for bloom filter benefits
-            read_opts.set_prefix_same_as_start(true);
-            read_opts.set_total_order_seek(false);
-        } else {
-            // Short prefix: use total_order_seek to avoid incorrect bloom filter behavior
-            read_opts.set_total_order_seek(true);
-        }
+    let cf_handle = match shared_db.cf_handle(&cf_name) {
+        Some(cf) => cf,
+        None => return Ok((atoms::error(), (atoms::cf_not_opened(), cf)).encode(env)),
+    };
+
+    match read(shared_db, cf_handle) {
+        Some(value) => Ok((atoms::ok(), value).encode(env)),
+        None => Ok((atoms::error(), atoms::unknown_property()).encode(env)),
     }
+}
 
-    let new_iterator = iter_ref.db.db.iterator_cf_opt(&cf_handle, read_opts, IteratorMode::From(target_bytes, rocksdb::Direction::Forward));
+// ============================================================================
+// Storage Events
+// ============================================================================
 
-    // SAFETY: We keep the SharedDb alive via Arc, so the iterator remains valid.
-    // The Arc<SharedDb> is stored in IteratorRef and keeps the DB alive.
-    let static_iterator: DBIteratorWithThreadMode<'static, DB> = unsafe {
-        std::mem::transmute(new_iterator)
-    };
+/// How often `subscribe_events` pollers compare the live SST files.
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Live SST files by name, with their column family, level and size.
+type LiveSstFiles = HashMap<String, (String, i32, usize)>;
+
+/// Subscribers of a database's storage events and the state of its poller.
+#[derive(Default)]
+struct EventFeed {
+    /// Database the poller reads; empty while `exclusive_db` needs the only
+    /// reference to it
+    db: Weak<SharedDb>,
+    subscribers: Vec<LocalPid>,
+    /// Whether a poller thread is running for `subscribers`
+    polling: bool,
+    /// Set on close; the poller exits and no one can subscribe any more
+    closed: bool,
+}
 
-    // Replace the old iterator
-    *iterator = static_iterator;
+impl EventFeed {
+    fn attach(&mut self, shared_db: &Arc<SharedDb>) {
+        if self.polling {
+            self.db = Arc::downgrade(shared_db);
+        }
+    }
 
-    Ok(atoms::ok().encode(env))
+    fn detach(&mut self) {
+        self.db = Weak::new();
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        self.subscribers.clear();
+        self.detach();
+    }
 }
 
-/// Closes the iterator and releases resources.
+/// Subscribes a process to flush and compaction completion events.
+///
+/// The linked rust-rocksdb bindings don't expose RocksDB's `EventListener`, so
+/// this is poll-based: one background thread per database compares the live
+/// SST files every 200 ms while it has subscribers. Files that appear at
+/// level 0 are reported as a completed flush, files that appear (or move) at a
+/// deeper level as a completed compaction. Completions within one interval are
+/// coalesced into one event per column family and level, and a compaction that
+/// produces no output (e.g. everything deleted) is not reported. `pid`
+/// receives:
+///
+/// `{:rocksdb_event, :flush_completed | :compaction_completed, cf, %{level: n, files: n, bytes: n}}`
+///
+/// where `files` and `bytes` count the new files at `level`. Subscribing a
+/// process twice has no further effect. A subscription ends with
+/// `unsubscribe_events`, when `pid` exits or when the database is closed; the
+/// poller stops once no subscriber is left.
 ///
 /// # Arguments
-/// * `iter_ref` - The iterator reference
+/// * `db_ref` - The database reference
+/// * `pid` - Process to notify
 ///
 /// # Returns
 /// * `:ok` on success
-/// * `{:error, :iterator_closed}` if already closed
-#[rustler::nif]
-fn iterator_close<'a>(env: Env<'a>, iter_ref: ResourceArc<IteratorRef>) -> NifResult<Term<'a>> {
-    let mut iter_guard = iter_ref
-        .iterator
-        .lock()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+/// * `{:error, :already_closed}` if database is closed
+#[rustler::nif(schedule = "DirtyCpu")]
+fn subscribe_events<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    pid: LocalPid,
+) -> NifResult<Term<'a>> {
+    let guard = db_ref.load();
 
-    if iter_guard.is_none() {
-        return Ok((atoms::error(), atoms::iterator_closed()).encode(env));
-    }
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
 
-    // Drop the iterator
-    *iter_guard = None;
+    let mut feed = shared_db.event_feed();
+    if feed.closed {
+        return Ok((atoms::error(), atoms::already_closed()).encode(env));
+    }
+    if !feed.subscribers.contains(&pid) {
+        feed.subscribers.push(pid);
+    }
+    if !feed.polling {
+        feed.polling = true;
+        feed.attach(shared_db);
+        let known = live_sst_files(shared_db).unwrap_or_default();
+        let events = Arc::clone(&shared_db.events);
+        std::thread::spawn(move || poll_storage_events(events, known));
+    }
 
     Ok(atoms::ok().encode(env))
 }
 
-/// Collects all remaining key-value pairs from an iterator into a list.
-///
-/// This is a convenience function that consumes the iterator and returns
-/// all matching entries. Useful for small result sets where streaming isn't needed.
+/// Ends a `subscribe_events` subscription of a process.
 ///
 /// # Arguments
-/// * `iter_ref` - The iterator reference
+/// * `db_ref` - The database reference
+/// * `pid` - Subscribed process
 ///
 /// # Returns
-/// * `{:ok, [{key, value}, ...]}` with all remaining entries
-/// * `{:error, :iterator_closed}` if iterator was closed
-/// * `{:error, {:iterator_failed, reason}}` on error
+/// * `:ok` on success, also if `pid` was not subscribed
+/// * `{:error, :already_closed}` if database is closed
 #[rustler::nif(schedule = "DirtyCpu")]
-fn iterator_collect<'a>(env: Env<'a>, iter_ref: ResourceArc<IteratorRef>) -> NifResult<Term<'a>> {
-    let mut iter_guard = iter_ref
-        .iterator
-        .lock()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+fn unsubscribe_events<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    pid: LocalPid,
+) -> NifResult<Term<'a>> {
+    let guard = db_ref.load();
 
-    let iterator = match iter_guard.as_mut() {
-        Some(iter) => iter,
-        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
     };
 
-    let mut results: Vec<Term<'a>> = Vec::new();
-
-    for result in iterator.by_ref() {
-        match result {
-            Ok((key, value)) => {
-                // Check if key still has the prefix
-                if !key.starts_with(&iter_ref.prefix) {
-                    break;
-                }
+    shared_db
+        .event_feed()
+        .subscribers
+        .retain(|subscriber| *subscriber != pid);
+    Ok(atoms::ok().encode(env))
+}
 
-                let mut key_binary = NewBinary::new(env, key.len());
-                key_binary.as_mut_slice().copy_from_slice(&key);
+/// Poller loop behind `subscribe_events`. Runs until the feed is closed or has
+/// no subscribers left.
+fn poll_storage_events(events: Arc<Mutex<EventFeed>>, mut known: LiveSstFiles) {
+    let mut msg_env = OwnedEnv::new();
 
-                let mut value_binary = NewBinary::new(env, value.len());
-                value_binary.as_mut_slice().copy_from_slice(&value);
+    loop {
+        std::thread::sleep(EVENT_POLL_INTERVAL);
 
-                results.push((Binary::from(key_binary), Binary::from(value_binary)).encode(env));
+        let (db, subscribers) = {
+            let mut feed = events.lock().unwrap_or_else(PoisonError::into_inner);
+            if feed.closed || feed.subscribers.is_empty() {
+                feed.polling = false;
+                feed.detach();
+                return;
             }
-            Err(e) => {
-                return Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env));
+            (feed.db.upgrade(), feed.subscribers.clone())
+        };
+
+        // Keep the previous snapshot while detached or if RocksDB can't list
+        // files this time
+        let current = match db.as_deref().map(live_sst_files) {
+            Some(Ok(files)) => files,
+            _ => continue,
+        };
+        // Not kept across the sleep, so close and `exclusive_db` aren't held up
+        drop(db);
+
+        let mut gone = Vec::new();
+        for (completed_flush, cf_name, level, files, bytes) in storage_events(&known, &current) {
+            for pid in &subscribers {
+                if gone.contains(pid) {
+                    continue;
+                }
+                let sent = msg_env.send_and_clear(pid, |env| {
+                    let kind = if completed_flush {
+                        atoms::flush_completed()
+                    } else {
+                        atoms::compaction_completed()
+                    };
+                    let cf = rustler::Atom::from_str(env, &cf_name)
+                        .map(|atom| atom.encode(env))
+                        .unwrap_or_else(|_| cf_name.encode(env));
+                    let details = Term::map_from_pairs(
+                        env,
+                        &[
+                            (atoms::level().encode(env), level.encode(env)),
+                            (atoms::files().encode(env), files.encode(env)),
+                            (atoms::bytes().encode(env), bytes.encode(env)),
+                        ],
+                    )
+                    .unwrap_or_else(|_| atoms::error().encode(env));
+                    (atoms::rocksdb_event(), kind, cf, details).encode(env)
+                });
+                if sent.is_err() {
+                    // The subscriber has exited
+                    gone.push(*pid);
+                }
             }
         }
+        if !gone.is_empty() {
+            let mut feed = events.lock().unwrap_or_else(PoisonError::into_inner);
+            feed.subscribers
+                .retain(|subscriber| !gone.contains(subscriber));
+        }
+
+        known = current;
     }
+}
 
-    Ok((atoms::ok(), results).encode(env))
+/// Lists the live SST files of the opened column families.
+fn live_sst_files(shared_db: &SharedDb) -> Result<LiveSstFiles, String> {
+    let files = shared_db.db.live_files().map_err(|e| e.to_string())?;
+    Ok(files
+        .into_iter()
+        .filter(|file| {
+            shared_db
+                .opened_cfs
+                .contains(file.column_family_name.as_str())
+        })
+        .map(|file| (file.name, (file.column_family_name, file.level, file.size)))
+        .collect())
+}
+
+/// Derives `(is_flush, cf, level, files, bytes)` events from the SST files that
+/// are new, or at a new level, in `current` compared to `known`.
+fn storage_events(
+    known: &LiveSstFiles,
+    current: &LiveSstFiles,
+) -> Vec<(bool, String, i32, usize, usize)> {
+    let mut grouped: BTreeMap<(bool, &str, i32), (usize, usize)> = BTreeMap::new();
+
+    for (name, (cf_name, level, size)) in current {
+        let is_flush = match known.get(name) {
+            None => *level == 0,
+            Some((_, known_level, _)) if known_level != level => false,
+            Some(_) => continue,
+        };
+        let entry = grouped
+            .entry((is_flush, cf_name.as_str(), *level))
+            .or_default();
+        entry.0 += 1;
+        entry.1 += size;
+    }
+
+    grouped
+        .into_iter()
+        .map(|((is_flush, cf_name, level), (files, bytes))| {
+            (is_flush, cf_name.to_string(), level, files, bytes)
+        })
+        .collect()
 }
 
 // ============================================================================
-// Snapshot Operations
+// Change Feed
 // ============================================================================
 
-/// Creates a snapshot of the database for consistent point-in-time reads.
+/// WAL record tags, from RocksDB's `ValueType` enum (db/dbformat.h).
+const WAL_DELETION: u8 = 0x0;
+const WAL_VALUE: u8 = 0x1;
+const WAL_MERGE: u8 = 0x2;
+const WAL_LOG_DATA: u8 = 0x3;
+const WAL_CF_DELETION: u8 = 0x4;
+const WAL_CF_VALUE: u8 = 0x5;
+const WAL_CF_MERGE: u8 = 0x6;
+const WAL_SINGLE_DELETION: u8 = 0x7;
+const WAL_CF_SINGLE_DELETION: u8 = 0x8;
+const WAL_NOOP: u8 = 0xD;
+const WAL_CF_RANGE_DELETION: u8 = 0xE;
+const WAL_RANGE_DELETION: u8 = 0xF;
+
+/// Size of the sequence number + count header of a serialized WriteBatch.
+const WRITE_BATCH_HEADER: usize = 12;
+
+/// Kind of a sequence-consuming WAL record.
+#[derive(Debug, PartialEq)]
+enum WalRecordKind {
+    Put,
+    Delete,
+    /// Merges and range deletions: consume a sequence number but aren't decoded
+    Other,
+}
+
+/// One sequence-consuming record of a WAL write batch.
+#[derive(Debug, PartialEq)]
+struct WalRecord<'b> {
+    cf_id: u32,
+    kind: WalRecordKind,
+    key: &'b [u8],
+}
+
+/// Reads triple additions and removals from the WAL.
 ///
-/// A snapshot provides a consistent view of the database at the time of creation.
-/// All reads using the snapshot will see the same data, regardless of subsequent
-/// writes to the database.
+/// Replays the write batches logged since `since_seq`, keeps only puts and
+/// deletes on the `spo` column family, and decodes each key into its subject,
+/// predicate and object IDs. Changes to other column families are ignored, as
+/// are merges and range deletions.
+///
+/// The WAL only covers what RocksDB has not yet deleted: once memtables are
+/// flushed, obsolete WAL files are removed unless WAL retention is configured
+/// (`WAL_ttl_seconds` / `WAL_size_limit_MB`). Consumers that fall too far behind
+/// get `{:error, {:wal_failed, reason}}` and must resynchronize from a snapshot.
 ///
 /// # Arguments
 /// * `db_ref` - The database reference
+/// * `since_seq` - First sequence number to include
+/// * `id_width` - Bytes per ID in spo keys (8 for the standard 64-bit encoding)
 ///
 /// # Returns
-/// * `{:ok, snapshot_ref}` on success
+/// * `{:ok, [{:added | :removed, s, p, o}, ...], next_seq}` where `next_seq` is
+///   the `since_seq` to pass on the next call
 /// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:invalid_id_width, width}}` if `id_width` is not 1..=8
+/// * `{:error, {:malformed_key, length}}` for an spo key of the wrong length
+/// * `{:error, {:wal_failed, reason}}` if the WAL can't be read
 #[rustler::nif(schedule = "DirtyCpu")]
-fn snapshot<'a>(env: Env<'a>, db_ref: ResourceArc<DbRef>) -> NifResult<Term<'a>> {
-    let guard = db_ref
-        .inner
-        .read()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+fn triple_changes_since<'a>(
+    env: Env<'a>,
+    db_ref: ResourceArc<DbRef>,
+    since_seq: u64,
+    id_width: usize,
+) -> NifResult<Term<'a>> {
+    if id_width == 0 || id_width > 8 {
+        return Ok((atoms::error(), (atoms::invalid_id_width(), id_width)).encode(env));
+    }
+
+    let guard = db_ref.load();
 
     let shared_db = match guard.as_ref() {
-        Some(db) => Arc::clone(db),
+        Some(db) => db,
         None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
     };
 
-    let snap = shared_db.db.snapshot();
+    let spo_cf_id = match column_family_id(shared_db, "spo") {
+        Ok(id) => id,
+        Err(reason) => return Ok((atoms::error(), (atoms::wal_failed(), reason)).encode(env)),
+    };
 
-    // SAFETY: We keep the SharedDb alive via Arc, so the snapshot remains valid.
-    // The Arc<SharedDb> is stored in SnapshotRef and will keep the DB alive
-    // even if DbRef.close() is called, preventing use-after-free.
-    let static_snapshot: SnapshotWithThreadMode<'static, DB> = unsafe {
-        std::mem::transmute(snap)
+    let updates = match shared_db.db.get_updates_since(since_seq) {
+        Ok(updates) => updates,
+        Err(e) => return Ok((atoms::error(), (atoms::wal_failed(), e.to_string())).encode(env)),
     };
 
-    let snap_ref = ResourceArc::new(SnapshotRef {
-        snapshot: Mutex::new(Some(static_snapshot)),
-        db: shared_db,
-    });
+    let mut changes: Vec<(rustler::Atom, u64, u64, u64)> = Vec::new();
+    let mut next_seq = since_seq;
 
-    Ok((atoms::ok(), snap_ref).encode(env))
+    for update in updates {
+        let (batch_seq, batch) = match update {
+            Ok(update) => update,
+            Err(e) => return Ok((atoms::error(), (atoms::wal_failed(), e.to_string())).encode(env)),
+        };
+        let records = match decode_wal_batch(batch.data()) {
+            Ok(records) => records,
+            Err(reason) => return Ok((atoms::error(), (atoms::wal_failed(), reason)).encode(env)),
+        };
+
+        for (offset, record) in records.iter().enumerate() {
+            // The first batch may start before since_seq
+            if batch_seq + (offset as u64) < since_seq || record.cf_id != spo_cf_id {
+                continue;
+            }
+            let change = match record.kind {
+                WalRecordKind::Put => atoms::added(),
+                WalRecordKind::Delete => atoms::removed(),
+                WalRecordKind::Other => continue,
+            };
+            if record.key.len() != id_width * 3 {
+                return Ok((atoms::error(), (atoms::malformed_key(), record.key.len())).encode(env));
+            }
+            changes.push((
+                change,
+                decode_be_id(&record.key[..id_width]),
+                decode_be_id(&record.key[id_width..id_width * 2]),
+                decode_be_id(&record.key[id_width * 2..]),
+            ));
+        }
+
+        next_seq = next_seq.max(batch_seq + records.len() as u64);
+    }
+
+    Ok((atoms::ok(), changes, next_seq).encode(env))
 }
 
-/// Gets a value from a column family using a snapshot.
+/// Returns the numeric ID RocksDB uses for a column family in the WAL.
 ///
-/// This provides point-in-time consistent reads - the value returned
-/// is what existed at the time the snapshot was created.
+/// The rocksdb crate doesn't expose it, so it is read back from the encoding
+/// of a throwaway (never written) batch containing a single put to the CF.
+fn column_family_id(shared_db: &SharedDb, cf_name: &str) -> Result<u32, String> {
+    let cf = shared_db
+        .cf_handle(cf_name)
+        .ok_or_else(|| format!("column family '{}' not found", cf_name))?;
+
+    let mut probe = WriteBatch::default();
+    probe.put_cf(&cf, b"", b"");
+
+    match decode_wal_batch(probe.data())?.first() {
+        Some(record) => Ok(record.cf_id),
+        None => Err("failed to encode column family probe".to_string()),
+    }
+}
+
+/// Decodes the sequence-consuming records of a serialized WriteBatch.
+/// Record `i` of the result was assigned sequence number `batch_seq + i`.
+fn decode_wal_batch(data: &[u8]) -> Result<Vec<WalRecord<'_>>, String> {
+    if data.len() < WRITE_BATCH_HEADER {
+        return Err("truncated write batch header".to_string());
+    }
+
+    let mut input = &data[WRITE_BATCH_HEADER..];
+    let mut records = Vec::new();
+
+    while let Some((&tag, rest)) = input.split_first() {
+        input = rest;
+
+        let cf_id = match tag {
+            WAL_CF_DELETION
+            | WAL_CF_VALUE
+            | WAL_CF_MERGE
+            | WAL_CF_SINGLE_DELETION
+            | WAL_CF_RANGE_DELETION => read_varint32(&mut input)?,
+            _ => 0,
+        };
+
+        match tag {
+            WAL_VALUE | WAL_CF_VALUE => {
+                let key = read_length_prefixed(&mut input)?;
+                read_length_prefixed(&mut input)?;
+                records.push(WalRecord {
+                    cf_id,
+                    kind: WalRecordKind::Put,
+                    key,
+                });
+            }
+            WAL_DELETION | WAL_CF_DELETION | WAL_SINGLE_DELETION | WAL_CF_SINGLE_DELETION => {
+                let key = read_length_prefixed(&mut input)?;
+                records.push(WalRecord {
+                    cf_id,
+                    kind: WalRecordKind::Delete,
+                    key,
+                });
+            }
+            WAL_MERGE | WAL_CF_MERGE | WAL_RANGE_DELETION | WAL_CF_RANGE_DELETION => {
+                let key = read_length_prefixed(&mut input)?;
+                read_length_prefixed(&mut input)?;
+                records.push(WalRecord {
+                    cf_id,
+                    kind: WalRecordKind::Other,
+                    key,
+                });
+            }
+            WAL_LOG_DATA => {
+                read_length_prefixed(&mut input)?;
+            }
+            WAL_NOOP => {}
+            _ => return Err(format!("unsupported WAL record type {}", tag)),
+        }
+    }
+
+    Ok(records)
+}
+
+/// Reads a LEB128 varint32 and advances the input.
+fn read_varint32(input: &mut &[u8]) -> Result<u32, String> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let (&byte, rest) = input.split_first().ok_or("truncated varint")?;
+        *input = rest;
+        value |= u32::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("varint too long".to_string())
+}
+
+/// Reads a varint32 length followed by that many bytes and advances the input.
+fn read_length_prefixed<'b>(input: &mut &'b [u8]) -> Result<&'b [u8], String> {
+    let len = read_varint32(input)? as usize;
+    if input.len() < len {
+        return Err("truncated WAL record".to_string());
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(bytes)
+}
+
+// ============================================================================
+// User Timestamps
+// ============================================================================
+
+// Without the `user_timestamps` feature the NIFs below report
+// `{:error, :timestamps_unsupported}`. The linked binding can't back the
+// feature yet, so refuse to build rather than silently keep the stubs.
+#[cfg(feature = "user_timestamps")]
+compile_error!(
+    "`user_timestamps` needs a rocksdb binding with user timestamp support (0.22 has none)"
+);
+
+/// Puts a key-value pair stamped with a logical timestamp.
+///
+/// RocksDB user timestamps need a timestamp-aware comparator on the CF and
+/// timestamp arguments on writes and `ReadOptions`. The linked `rocksdb`
+/// crate (0.22) exposes neither, so until the `user_timestamps` cargo feature
+/// can be enabled this returns `{:error, :timestamps_unsupported}` after
+/// validating its arguments.
+/// Timestamps are u64 values, stored as 8 little-endian bytes to match
+/// RocksDB's built-in timestamp-aware bytewise comparator.
 ///
 /// # Arguments
-/// * `snapshot_ref` - The snapshot reference
+/// * `db_ref` - The database reference
 /// * `cf` - The column family atom
 /// * `key` - The key as a binary
+/// * `value` - The value as a binary
+/// * `timestamp` - Logical write time
 ///
 /// # Returns
-/// * `{:ok, value}` if found
-/// * `:not_found` if key doesn't exist
-/// * `{:error, :snapshot_released}` if snapshot was released
+/// * `{:error, :timestamps_unsupported}` with this RocksDB build
+/// * `{:error, :already_closed}` if database is closed
 /// * `{:error, {:invalid_cf, cf}}` if column family is invalid
-/// * `{:error, {:get_failed, reason}}` on other errors
 #[rustler::nif(schedule = "DirtyCpu")]
-fn snapshot_get<'a>(
+fn put_ts<'a>(
     env: Env<'a>,
-    snapshot_ref: ResourceArc<SnapshotRef>,
-    cf: rustler::Atom,
-    key: Binary<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    _key: Binary<'a>,
+    _value: Binary<'a>,
+    _timestamp: u64,
 ) -> NifResult<Term<'a>> {
-    let cf_name = match cf_atom_to_name(cf) {
-        Some(name) => name,
-        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
-    };
-
-    let snap_guard = snapshot_ref
-        .snapshot
-        .lock()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
-
-    let snapshot = match snap_guard.as_ref() {
-        Some(snap) => snap,
-        None => return Ok((atoms::error(), atoms::snapshot_released()).encode(env)),
-    };
-
-    // Access the database directly from our Arc<SharedDb>
-    let cf_handle = match snapshot_ref.db.db.cf_handle(cf_name) {
-        Some(cf) => cf,
-        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
-    };
-
-    // Use ReadOptions with snapshot
-    let mut read_opts = ReadOptions::default();
-    read_opts.set_snapshot(snapshot);
-
-    match snapshot_ref.db.db.get_cf_opt(&cf_handle, key.as_slice(), &read_opts) {
-        Ok(Some(value)) => {
-            let mut binary = NewBinary::new(env, value.len());
-            binary.as_mut_slice().copy_from_slice(&value);
-            Ok((atoms::ok(), Binary::from(binary)).encode(env))
-        }
-        Ok(None) => Ok(atoms::not_found().encode(env)),
-        Err(e) => Ok((atoms::error(), (atoms::get_failed(), e.to_string())).encode(env)),
-    }
+    timestamps_unsupported(env, &db_ref, cf)
 }
 
-/// Creates a prefix iterator over a snapshot.
+/// Reads a key as of a logical timestamp.
 ///
-/// The iterator returns all key-value pairs where the key starts with the given prefix,
-/// using the consistent view from the snapshot.
+/// Counterpart of `put_ts`; returns `{:error, :timestamps_unsupported}` for
+/// the same reason.
 ///
 /// # Arguments
-/// * `snapshot_ref` - The snapshot reference
+/// * `db_ref` - The database reference
 /// * `cf` - The column family atom
-/// * `prefix` - The prefix to iterate over
+/// * `key` - The key as a binary
+/// * `read_timestamp` - Logical time to read at
 ///
 /// # Returns
-/// * `{:ok, iterator_ref}` on success
-/// * `{:error, :snapshot_released}` if snapshot was released
+/// * `{:error, :timestamps_unsupported}` with this RocksDB build
+/// * `{:error, :already_closed}` if database is closed
 /// * `{:error, {:invalid_cf, cf}}` if column family is invalid
 #[rustler::nif(schedule = "DirtyCpu")]
-fn snapshot_prefix_iterator<'a>(
+fn get_ts<'a>(
     env: Env<'a>,
-    snapshot_ref: ResourceArc<SnapshotRef>,
-    cf: rustler::Atom,
-    prefix: Binary<'a>,
+    db_ref: ResourceArc<DbRef>,
+    cf: Term<'a>,
+    _key: Binary<'a>,
+    _read_timestamp: u64,
 ) -> NifResult<Term<'a>> {
-    let cf_name = match cf_atom_to_name(cf) {
-        Some(name) => name,
-        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
-    };
+    timestamps_unsupported(env, &db_ref, cf)
+}
 
-    let snap_guard = snapshot_ref
-        .snapshot
-        .lock()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+/// Validates the common arguments of the timestamp NIFs and reports that the
+/// linked RocksDB lacks user timestamp support.
+fn timestamps_unsupported<'a>(env: Env<'a>, db_ref: &DbRef, cf: Term<'a>) -> NifResult<Term<'a>> {
+    if cf_term_to_name(cf).is_none() {
+        return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env));
+    }
 
-    let snapshot = match snap_guard.as_ref() {
-        Some(snap) => snap,
-        None => return Ok((atoms::error(), atoms::snapshot_released()).encode(env)),
-    };
+    let guard = db_ref.load();
+    if guard.is_none() {
+        return Ok((atoms::error(), atoms::already_closed()).encode(env));
+    }
 
-    // Access the database directly from our Arc<SharedDb>
-    let cf_handle = match snapshot_ref.db.db.cf_handle(cf_name) {
-        Some(cf) => cf,
-        None => return Ok((atoms::error(), (atoms::invalid_cf(), cf)).encode(env)),
-    };
+    Ok((atoms::error(), atoms::timestamps_unsupported()).encode(env))
+}
 
-    let prefix_bytes = prefix.as_slice().to_vec();
+// ============================================================================
+// Fault Injection (testing only)
+// ============================================================================
 
-    // Create read options with snapshot and prefix bounds
-    let mut read_opts = ReadOptions::default();
-    read_opts.set_snapshot(snapshot);
+/// Write fault injection for exercising Elixir-side error handling.
+///
+/// STRICTLY FOR TESTING. The `inject_write_fault` NIF only exists when the
+/// crate is built with the `fault_injection` feature; without it the hook in
+/// `put`/`write_batch` compiles to a constant `false`.
+mod fault_injection {
+    use super::SharedDb;
+
+    #[cfg(not(feature = "fault_injection"))]
+    #[inline(always)]
+    pub(crate) fn take_write_fault(_shared_db: &SharedDb) -> bool {
+        false
+    }
 
-    // Configure prefix iteration based on CF type and prefix length
-    if PREFIX_CFS.contains(&cf_name) {
-        if prefix_bytes.len() >= PREFIX_LENGTH {
-            // Use prefix-based seek for bloom filter benefits
-            read_opts.set_prefix_same_as_start(true);
-            read_opts.set_total_order_seek(false);
-        } else {
-            // Short prefix: use total_order_seek to avoid incorrect bloom filter behavior
-            read_opts.set_total_order_seek(true);
+    /// Consumes one pending injected fault, returning whether the write must fail.
+    #[cfg(feature = "fault_injection")]
+    pub(crate) fn take_write_fault(shared_db: &SharedDb) -> bool {
+        use std::sync::atomic::Ordering;
+
+        shared_db
+            .write_faults
+            .fetch_update(
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+                |remaining| match remaining {
+                    0 => None,
+                    n if n < 0 => Some(n),
+                    n => Some(n - 1),
+                },
+            )
+            .is_ok()
+    }
+
+    #[cfg(feature = "fault_injection")]
+    mod atoms {
+        rustler::atoms! {
+            count,
+            always,
+            clear,
         }
     }
 
-    // Create the iterator with snapshot
-    let iterator = snapshot_ref.db.db.iterator_cf_opt(
-        &cf_handle,
-        read_opts,
-        IteratorMode::From(&prefix_bytes, rocksdb::Direction::Forward),
-    );
+    /// Forces subsequent `put`/`write_batch` calls to fail with
+    /// `{:error, {:put_failed, "injected"}}`, without touching the database.
+    ///
+    /// STRICTLY FOR TESTING; only available with the `fault_injection` feature.
+    ///
+    /// # Arguments
+    /// * `db_ref` - The database reference
+    /// * `mode` - `{:count, n}` to fail the next `n` writes, `:always` to fail
+    ///   until cleared, or `:clear` to stop injecting faults
+    ///
+    /// # Returns
+    /// * `:ok` on success
+    /// * `{:error, :already_closed}` if database is closed
+    /// * `{:error, {:invalid_option, mode}}` if the mode is not recognized
+    #[cfg(feature = "fault_injection")]
+    #[rustler::nif(schedule = "DirtyCpu")]
+    fn inject_write_fault<'a>(
+        env: rustler::Env<'a>,
+        db_ref: rustler::ResourceArc<super::DbRef>,
+        mode: rustler::Term<'a>,
+    ) -> rustler::NifResult<rustler::Term<'a>> {
+        use rustler::Encoder;
+        use std::sync::atomic::Ordering;
+
+        let guard = db_ref.load();
+
+        let shared_db = match guard.as_ref() {
+            Some(db) => db,
+            None => return Ok((super::atoms::error(), super::atoms::already_closed()).encode(env)),
+        };
 
-    // SAFETY: We keep the SharedDb alive via Arc, so the iterator remains valid.
-    // The Arc<SharedDb> is stored in SnapshotIteratorRef and will keep the DB alive
-    // even if DbRef.close() is called, preventing use-after-free.
-    let static_iterator: DBIteratorWithThreadMode<'static, DB> = unsafe {
-        std::mem::transmute(iterator)
-    };
+        let remaining = match mode.decode::<(rustler::Atom, u32)>() {
+            Ok((tag, n)) if tag == atoms::count() => i64::from(n),
+            _ => match mode.decode::<rustler::Atom>() {
+                Ok(tag) if tag == atoms::always() => -1,
+                Ok(tag) if tag == atoms::clear() => 0,
+                _ => {
+                    return Ok((
+                        super::atoms::error(),
+                        (super::atoms::invalid_option(), mode),
+                    )
+                        .encode(env))
+                }
+            },
+        };
 
-    let iter_ref = ResourceArc::new(SnapshotIteratorRef {
-        iterator: Mutex::new(Some(static_iterator)),
-        _db: Arc::clone(&snapshot_ref.db),
-        prefix: prefix_bytes,
-        _cf_name: cf_name.to_string(),
-    });
+        shared_db.write_faults.store(remaining, Ordering::SeqCst);
+        Ok(super::atoms::ok().encode(env))
+    }
+}
 
-    Ok((atoms::ok(), iter_ref).encode(env))
+// ============================================================================
+// Backups and Checkpoints
+// ============================================================================
+
+/// Opens the backup engine rooted at `backup_path`, creating the directory if needed.
+fn open_backup_engine(backup_path: &str) -> Result<BackupEngine, rocksdb::Error> {
+    let backup_opts = BackupEngineOptions::new(backup_path)?;
+    BackupEngine::open(&backup_opts, &rocksdb::Env::new()?)
 }
 
-/// Gets the next key-value pair from a snapshot iterator.
+/// Creates a new hot backup of the database in `backup_path`.
+///
+/// Uses RocksDB's `BackupEngine`, so the database stays open and writable
+/// while files are copied. Memtables are flushed first, making the backup a
+/// point-in-time copy of everything written before the call without needing
+/// the WAL. Backups are incremental: SST files already present in
+/// `backup_path` from earlier backups are shared, not copied again. Only one
+/// backup operation should use a given `backup_path` at a time.
 ///
 /// # Arguments
-/// * `iter_ref` - The snapshot iterator reference
+/// * `db_ref` - The database reference
+/// * `backup_path` - Backup directory, created if it doesn't exist
 ///
 /// # Returns
-/// * `{:ok, key, value}` if there's a next item with matching prefix
-/// * `:iterator_end` if the iterator is exhausted or prefix no longer matches
-/// * `{:error, :iterator_closed}` if iterator was closed
-/// * `{:error, {:iterator_failed, reason}}` on error
+/// * `{:ok, backup_id}` on success
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:backup_failed, reason}}` on failure
 #[rustler::nif(schedule = "DirtyCpu")]
-fn snapshot_iterator_next<'a>(
+fn create_backup<'a>(
     env: Env<'a>,
-    iter_ref: ResourceArc<SnapshotIteratorRef>,
+    db_ref: ResourceArc<DbRef>,
+    backup_path: String,
 ) -> NifResult<Term<'a>> {
-    let mut iter_guard = iter_ref
-        .iterator
-        .lock()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+    let guard = db_ref.load();
 
-    let iterator = match iter_guard.as_mut() {
-        Some(iter) => iter,
-        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
     };
 
-    match iterator.next() {
-        Some(Ok((key, value))) => {
-            // Check if key still has the prefix
-            if !key.starts_with(&iter_ref.prefix) {
-                return Ok(atoms::iterator_end().encode(env));
-            }
+    let result = open_backup_engine(&backup_path).and_then(|mut engine| {
+        engine.create_new_backup_flush(&shared_db.db, true)?;
+        Ok(engine
+            .get_backup_info()
+            .iter()
+            .map(|info| info.backup_id)
+            .max())
+    });
 
-            let mut key_binary = NewBinary::new(env, key.len());
-            key_binary.as_mut_slice().copy_from_slice(&key);
+    match result {
+        Ok(Some(backup_id)) => Ok((atoms::ok(), backup_id).encode(env)),
+        Ok(None) => Ok((
+            atoms::error(),
+            (atoms::backup_failed(), "backup not recorded"),
+        )
+            .encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::backup_failed(), e.to_string())).encode(env)),
+    }
+}
 
-            let mut value_binary = NewBinary::new(env, value.len());
-            value_binary.as_mut_slice().copy_from_slice(&value);
+/// Lists the backups stored in `backup_path`, oldest first.
+///
+/// # Arguments
+/// * `backup_path` - Backup directory
+///
+/// # Returns
+/// * `{:ok, [{backup_id, timestamp, size}]}` on success; `timestamp` is in Unix
+///   seconds and `size` in bytes
+/// * `{:error, {:backup_failed, reason}}` if the backup directory can't be opened
+#[rustler::nif(schedule = "DirtyCpu")]
+fn list_backups(env: Env, backup_path: String) -> NifResult<Term> {
+    let engine = match open_backup_engine(&backup_path) {
+        Ok(engine) => engine,
+        Err(e) => return Ok((atoms::error(), (atoms::backup_failed(), e.to_string())).encode(env)),
+    };
 
-            Ok((atoms::ok(), Binary::from(key_binary), Binary::from(value_binary)).encode(env))
-        }
-        Some(Err(e)) => {
-            Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env))
-        }
-        None => Ok(atoms::iterator_end().encode(env)),
-    }
+    let mut backups: Vec<(u32, i64, u64)> = engine
+        .get_backup_info()
+        .iter()
+        .map(|info| (info.backup_id, info.timestamp, info.size))
+        .collect();
+    backups.sort_unstable_by_key(|(backup_id, _, _)| *backup_id);
+
+    Ok((atoms::ok(), backups).encode(env))
 }
 
-/// Closes a snapshot iterator and releases resources.
+/// Deletes all but the newest `keep_count` backups in `backup_path`.
+///
+/// Files shared with the remaining backups are kept.
 ///
 /// # Arguments
-/// * `iter_ref` - The snapshot iterator reference
+/// * `backup_path` - Backup directory
+/// * `keep_count` - Number of most recent backups to keep
 ///
 /// # Returns
 /// * `:ok` on success
-/// * `{:error, :iterator_closed}` if already closed
-#[rustler::nif]
-fn snapshot_iterator_close<'a>(
-    env: Env<'a>,
-    iter_ref: ResourceArc<SnapshotIteratorRef>,
-) -> NifResult<Term<'a>> {
-    let mut iter_guard = iter_ref
-        .iterator
-        .lock()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+/// * `{:error, {:backup_failed, reason}}` on failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn purge_old_backups(env: Env, backup_path: String, keep_count: usize) -> NifResult<Term> {
+    let result = open_backup_engine(&backup_path)
+        .and_then(|mut engine| engine.purge_old_backups(keep_count));
 
-    if iter_guard.is_none() {
-        return Ok((atoms::error(), atoms::iterator_closed()).encode(env));
+    match result {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::backup_failed(), e.to_string())).encode(env)),
     }
-
-    // Drop the iterator
-    *iter_guard = None;
-
-    Ok(atoms::ok().encode(env))
 }
 
-/// Collects all remaining key-value pairs from a snapshot iterator into a list.
+/// Restores a backup from `backup_path` into `target_db_path`.
+///
+/// `backup_id` selects the backup; `0` or `:latest` restores the newest one.
+/// The target must not exist or be an empty directory: restoring replaces
+/// the files in it, so a non-empty target is refused instead of silently
+/// overwriting a database. The restored database is closed; open it with
+/// `open/1`.
 ///
 /// # Arguments
-/// * `iter_ref` - The snapshot iterator reference
+/// * `backup_path` - Backup directory
+/// * `target_db_path` - Directory to restore the database into
+/// * `backup_id` - Backup to restore, or `0`/`:latest` for the newest
 ///
 /// # Returns
-/// * `{:ok, [{key, value}, ...]}` with all remaining entries
-/// * `{:error, :iterator_closed}` if iterator was closed
-/// * `{:error, {:iterator_failed, reason}}` on error
+/// * `{:ok, target_db_path}` on success
+/// * `{:error, {:restore_failed, reason}}` if the target is not empty, the
+///   backup doesn't exist, or the restore fails
+/// * `{:error, {:invalid_option, backup_id}}` if `backup_id` is not an id or `:latest`
 #[rustler::nif(schedule = "DirtyCpu")]
-fn snapshot_iterator_collect<'a>(
+fn restore_from_backup<'a>(
     env: Env<'a>,
-    iter_ref: ResourceArc<SnapshotIteratorRef>,
+    backup_path: String,
+    target_db_path: String,
+    backup_id: Term<'a>,
 ) -> NifResult<Term<'a>> {
-    let mut iter_guard = iter_ref
-        .iterator
-        .lock()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
-
-    let iterator = match iter_guard.as_mut() {
-        Some(iter) => iter,
-        None => return Ok((atoms::error(), atoms::iterator_closed()).encode(env)),
+    let backup_id = match backup_id.decode::<u32>() {
+        Ok(0) => None,
+        Ok(id) => Some(id),
+        Err(_) => match backup_id.decode::<rustler::Atom>() {
+            Ok(atom) if atom == atoms::latest() => None,
+            _ => return Ok((atoms::error(), (atoms::invalid_option(), backup_id)).encode(env)),
+        },
     };
 
-    let mut results: Vec<Term<'a>> = Vec::new();
-
-    for result in iterator.by_ref() {
-        match result {
-            Ok((key, value)) => {
-                // Check if key still has the prefix
-                if !key.starts_with(&iter_ref.prefix) {
-                    break;
-                }
-
-                let mut key_binary = NewBinary::new(env, key.len());
-                key_binary.as_mut_slice().copy_from_slice(&key);
+    let target_has_entries = std::fs::read_dir(&target_db_path)
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false);
+    if target_has_entries {
+        let reason = format!("target path is not empty: {}", target_db_path);
+        return Ok((atoms::error(), (atoms::restore_failed(), reason)).encode(env));
+    }
 
-                let mut value_binary = NewBinary::new(env, value.len());
-                value_binary.as_mut_slice().copy_from_slice(&value);
+    let restore_opts = RestoreOptions::default();
+    let result = open_backup_engine(&backup_path).and_then(|mut engine| match backup_id {
+        Some(id) => engine.restore_from_backup(&target_db_path, &target_db_path, &restore_opts, id),
+        None => engine.restore_from_latest_backup(&target_db_path, &target_db_path, &restore_opts),
+    });
 
-                results.push((Binary::from(key_binary), Binary::from(value_binary)).encode(env));
-            }
-            Err(e) => {
-                return Ok((atoms::error(), (atoms::iterator_failed(), e.to_string())).encode(env));
-            }
-        }
+    match result {
+        Ok(()) => Ok((atoms::ok(), target_db_path).encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::restore_failed(), e.to_string())).encode(env)),
     }
-
-    Ok((atoms::ok(), results).encode(env))
 }
 
-/// Releases a snapshot and frees resources.
+/// Creates a checkpoint of the database at `checkpoint_path`.
 ///
-/// After calling release, the snapshot handle is no longer valid.
+/// A checkpoint is an openable copy of the database whose SST files are hard
+/// links to the live ones when both are on the same filesystem, so it is
+/// near-instant and takes little extra space. Useful for test fixtures and
+/// for seeding read replicas. The source database stays fully usable during
+/// and after the call. RocksDB creates `checkpoint_path` itself, so it must
+/// not exist yet.
 ///
 /// # Arguments
-/// * `snapshot_ref` - The snapshot reference
+/// * `db_ref` - The database reference
+/// * `checkpoint_path` - Directory to create; must not exist
 ///
 /// # Returns
 /// * `:ok` on success
-/// * `{:error, :snapshot_released}` if already released
-#[rustler::nif]
-fn release_snapshot<'a>(
+/// * `{:error, :already_closed}` if database is closed
+/// * `{:error, {:checkpoint_failed, reason}}` if the path exists or the checkpoint fails
+#[rustler::nif(schedule = "DirtyCpu")]
+fn create_checkpoint<'a>(
     env: Env<'a>,
-    snapshot_ref: ResourceArc<SnapshotRef>,
+    db_ref: ResourceArc<DbRef>,
+    checkpoint_path: String,
 ) -> NifResult<Term<'a>> {
-    let mut snap_guard = snapshot_ref
-        .snapshot
-        .lock()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+    let guard = db_ref.load();
 
-    if snap_guard.is_none() {
-        return Ok((atoms::error(), atoms::snapshot_released()).encode(env));
+    let shared_db = match guard.as_ref() {
+        Some(db) => db,
+        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    };
+
+    if std::path::Path::new(&checkpoint_path).exists() {
+        let reason = format!("checkpoint path already exists: {}", checkpoint_path);
+        return Ok((atoms::error(), (atoms::checkpoint_failed(), reason)).encode(env));
     }
 
-    // Drop the snapshot
-    *snap_guard = None;
+    match Checkpoint::new(&shared_db.db)
+        .and_then(|checkpoint| checkpoint.create_checkpoint(&checkpoint_path))
+    {
+        Ok(()) => Ok(atoms::ok().encode(env)),
+        Err(e) => Ok((atoms::error(), (atoms::checkpoint_failed(), e.to_string())).encode(env)),
+    }
+}
 
-    Ok(atoms::ok().encode(env))
+// ============================================================================
+// Export Operations
+// ============================================================================
+
+/// Type tag for inline-encoded integers (top 4 bits of a term ID)
+const TYPE_INTEGER: u64 = 0b0100;
+
+/// Type tag for inline-encoded decimals (top 4 bits of a term ID)
+const TYPE_DECIMAL: u64 = 0b0101;
+
+/// Type tag for inline-encoded datetimes (top 4 bits of a term ID)
+const TYPE_DATETIME: u64 = 0b0110;
+
+/// Mask for the 60-bit payload below the type tag
+const ID_PAYLOAD_MASK: u64 = 0x0FFF_FFFF_FFFF_FFFF;
+
+/// Inline decimals pack sign(1) + exponent(11) + mantissa(48)
+const DECIMAL_MANTISSA_BITS: u32 = 48;
+const DECIMAL_EXPONENT_MASK: u64 = 0x7FF;
+const DECIMAL_EXPONENT_BIAS: i64 = 1023;
+
+/// Maximum number of resolved terms cached during an export.
+/// The cache is cleared when full so memory stays bounded on large dumps.
+const EXPORT_CACHE_CAPACITY: usize = 100_000;
+
+const XSD_INTEGER: &str = "http://www.w3.org/2001/XMLSchema#integer";
+const XSD_DECIMAL: &str = "http://www.w3.org/2001/XMLSchema#decimal";
+const XSD_DATETIME: &str = "http://www.w3.org/2001/XMLSchema#dateTime";
+
+/// Failure modes of a snapshot export, mapped to Elixir error tuples by the NIF.
+enum ExportError {
+    Io(String),
+    Read(String),
+    MalformedKey(usize),
+    UnresolvedId(u64),
 }
 
-/// Flushes the Write-Ahead Log (WAL) to disk.
+impl From<std::io::Error> for ExportError {
+    fn from(e: std::io::Error) -> Self {
+        ExportError::Io(e.to_string())
+    }
+}
+
+/// Exports all triples from a consistent snapshot as N-Triples.
 ///
-/// This ensures all buffered writes are persisted to the WAL. When `sync` is true,
-/// it also calls fsync to ensure data is physically written to storage.
+/// Opens a snapshot, scans the `spo` index in key order and resolves each
+/// subject, predicate and object ID through `id2str` (or decodes it inline for
+/// integer, decimal and datetime IDs). Writers are not blocked: the export only
+/// sees data committed before the snapshot was taken.
 ///
-/// Use this after bulk loading with sync=false to ensure all data is durable
-/// before considering the load complete.
+/// Resolved terms are cached to avoid repeated dictionary lookups for hot IDs;
+/// the cache is bounded by `EXPORT_CACHE_CAPACITY`.
 ///
 /// # Arguments
 /// * `db_ref` - The database reference
-/// * `sync` - When true, calls fsync after flushing (fully durable). When false,
-///            only flushes to OS buffer cache.
+/// * `output_path` - File to write the N-Triples to (created or truncated)
+/// * `id_width` - Width in bytes of each ID in the index key (1..=8)
 ///
 /// # Returns
-/// * `:ok` on success
+/// * `{:ok, triple_count}` on success
 /// * `{:error, :already_closed}` if database is closed
-/// * `{:error, {:flush_failed, reason}}` on failure
+/// * `{:error, {:invalid_id_width, id_width}}` if the width is out of range
+/// * `{:error, {:malformed_key, length}}` if an `spo` key has an unexpected length
+/// * `{:error, {:unresolved_id, id}}` if an ID is missing from the dictionary
+/// * `{:error, {:export_failed, reason}}` on read or I/O errors
 #[rustler::nif(schedule = "DirtyCpu")]
-fn flush_wal<'a>(
+fn export_snapshot_ntriples<'a>(
     env: Env<'a>,
     db_ref: ResourceArc<DbRef>,
-    sync: bool,
+    output_path: String,
+    id_width: usize,
 ) -> NifResult<Term<'a>> {
-    let guard = db_ref
-        .inner
-        .read()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+    if id_width == 0 || id_width > 8 {
+        return Ok((atoms::error(), (atoms::invalid_id_width(), id_width)).encode(env));
+    }
 
-    let shared_db = match guard.as_ref() {
-        Some(db) => db,
-        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    // Clone the Arc and release the lock so close() is not blocked by a long export
+    let shared_db = {
+        let guard = db_ref.load();
+
+        match guard.as_ref() {
+            Some(db) => Arc::clone(db),
+            None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+        }
     };
 
-    match shared_db.db.flush_wal(sync) {
-        Ok(()) => Ok(atoms::ok().encode(env)),
-        Err(e) => Ok((atoms::error(), (atoms::flush_failed(), e.to_string())).encode(env)),
+    let result = std::fs::File::create(&output_path)
+        .map_err(ExportError::from)
+        .and_then(|file| {
+            let mut writer = std::io::BufWriter::new(file);
+            let count = write_snapshot_ntriples(&shared_db, &mut writer, id_width)?;
+            writer.flush()?;
+            Ok(count)
+        });
+
+    match result {
+        Ok(count) => Ok((atoms::ok(), count).encode(env)),
+        Err(err) => {
+            // Don't leave a truncated dump behind
+            let _ = std::fs::remove_file(&output_path);
+
+            let reason = match err {
+                ExportError::Io(msg) | ExportError::Read(msg) => {
+                    (atoms::export_failed(), msg).encode(env)
+                }
+                ExportError::MalformedKey(len) => (atoms::malformed_key(), len).encode(env),
+                ExportError::UnresolvedId(id) => (atoms::unresolved_id(), id).encode(env),
+            };
+            Ok((atoms::error(), reason).encode(env))
+        }
+    }
+}
+
+/// Streams every `spo` triple visible in a fresh snapshot to `writer`.
+/// Returns the number of triples written.
+fn write_snapshot_ntriples<W: std::io::Write>(
+    shared_db: &SharedDb,
+    writer: &mut W,
+    id_width: usize,
+) -> Result<u64, ExportError> {
+    let db = &shared_db.db;
+    let spo = shared_db
+        .cf_handle("spo")
+        .ok_or_else(|| ExportError::Read("column family 'spo' not found".to_string()))?;
+    let id2str = shared_db
+        .cf_handle("id2str")
+        .ok_or_else(|| ExportError::Read("column family 'id2str' not found".to_string()))?;
+
+    let snap = db.snapshot();
+
+    let mut scan_opts = ReadOptions::default();
+    scan_opts.set_snapshot(&snap);
+    scan_opts.set_total_order_seek(true);
+
+    let mut lookup_opts = ReadOptions::default();
+    lookup_opts.set_snapshot(&snap);
+
+    let mut cache: std::collections::HashMap<u64, String> = std::collections::HashMap::new();
+    let mut resolve = |id: u64| -> Result<String, ExportError> {
+        if let Some(term) = cache.get(&id) {
+            return Ok(term.clone());
+        }
+
+        let term = match format_inline_term(id) {
+            Some(term) => term,
+            None => {
+                let encoded = db
+                    .get_cf_opt(&id2str, id.to_be_bytes(), &lookup_opts)
+                    .map_err(|e| ExportError::Read(e.to_string()))?
+                    .ok_or(ExportError::UnresolvedId(id))?;
+                format_dictionary_term(&encoded).ok_or(ExportError::UnresolvedId(id))?
+            }
+        };
+
+        if cache.len() >= EXPORT_CACHE_CAPACITY {
+            cache.clear();
+        }
+        cache.insert(id, term.clone());
+        Ok(term)
+    };
+
+    let mut count: u64 = 0;
+    for item in db.iterator_cf_opt(&spo, scan_opts, IteratorMode::Start) {
+        let (key, _value) = item.map_err(|e| ExportError::Read(e.to_string()))?;
+        if key.len() != id_width * 3 {
+            return Err(ExportError::MalformedKey(key.len()));
+        }
+
+        let subject = resolve(decode_be_id(&key[..id_width]))?;
+        let predicate = resolve(decode_be_id(&key[id_width..id_width * 2]))?;
+        let object = resolve(decode_be_id(&key[id_width * 2..]))?;
+
+        writeln!(writer, "{} {} {} .", subject, predicate, object)?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Decodes a big-endian ID of up to 8 bytes.
+fn decode_be_id(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0u64, |acc, b| (acc << 8) | u64::from(*b))
+}
+
+/// Formats an inline-encoded ID (integer, decimal, datetime) as an N-Triples literal.
+/// Returns None for IDs that must be resolved through the dictionary.
+fn format_inline_term(id: u64) -> Option<String> {
+    let payload = id & ID_PAYLOAD_MASK;
+
+    match id >> 60 {
+        TYPE_INTEGER => {
+            // Two's complement in 60-bit space
+            let value = if payload >= 1 << 59 {
+                payload as i64 - (1i64 << 60)
+            } else {
+                payload as i64
+            };
+            Some(format!("\"{}\"^^<{}>", value, XSD_INTEGER))
+        }
+        TYPE_DECIMAL => Some(format!(
+            "\"{}\"^^<{}>",
+            format_inline_decimal(payload),
+            XSD_DECIMAL
+        )),
+        TYPE_DATETIME => Some(format!(
+            "\"{}\"^^<{}>",
+            format_unix_millis(payload),
+            XSD_DATETIME
+        )),
+        _ => None,
+    }
+}
+
+/// Renders an inline decimal payload (sign, biased exponent, coefficient) in
+/// plain decimal notation.
+fn format_inline_decimal(payload: u64) -> String {
+    if payload == 0 {
+        return "0".to_string();
+    }
+
+    let negative = payload >> 59 == 1;
+    let exp =
+        ((payload >> DECIMAL_MANTISSA_BITS) & DECIMAL_EXPONENT_MASK) as i64 - DECIMAL_EXPONENT_BIAS;
+    let coef = payload & ((1u64 << DECIMAL_MANTISSA_BITS) - 1);
+
+    let mut digits = coef.to_string();
+    if exp >= 0 {
+        digits.push_str(&"0".repeat(exp as usize));
+    } else {
+        let scale = (-exp) as usize;
+        if digits.len() <= scale {
+            digits = format!("{}{}", "0".repeat(scale - digits.len() + 1), digits);
+        }
+        digits.insert(digits.len() - scale, '.');
+    }
+
+    if negative {
+        format!("-{}", digits)
+    } else {
+        digits
+    }
+}
+
+/// Formats milliseconds since the Unix epoch as an `xsd:dateTime` in UTC.
+fn format_unix_millis(ms: u64) -> String {
+    let secs = ms / 1000;
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil-from-days conversion (proleptic Gregorian calendar)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60,
+        ms % 1000
+    )
+}
+
+/// Formats an `id2str` value as an N-Triples term.
+///
+/// Encoding: `<<1, iri>>`, `<<2, bnode>>`, `<<3, 0, value>>` (plain literal),
+/// `<<3, 1, datatype, 0, value>>` (typed) and `<<3, 2, lang, 0, value>>` (language-tagged).
+fn format_dictionary_term(encoded: &[u8]) -> Option<String> {
+    match encoded {
+        [1, iri @ ..] => Some(format!("<{}>", escape_ntriples_iri(iri))),
+        [2, label @ ..] => Some(format!("_:{}", ntriples_blank_label(label))),
+        [3, 0, value @ ..] => Some(format!("\"{}\"", escape_ntriples_string(value))),
+        [3, subtype @ (1 | 2), rest @ ..] => {
+            let null = rest.iter().position(|b| *b == 0)?;
+            let (tag, value) = (&rest[..null], &rest[null + 1..]);
+            let literal = escape_ntriples_string(value);
+            let tag = String::from_utf8_lossy(tag);
+
+            if *subtype == 1 {
+                Some(format!(
+                    "\"{}\"^^<{}>",
+                    literal,
+                    escape_ntriples_iri(tag.as_bytes())
+                ))
+            } else {
+                Some(format!("\"{}\"@{}", literal, tag))
+            }
+        }
+        _ => None,
     }
 }
 
-/// Sets options on all column families at runtime.
-///
-/// This allows dynamic reconfiguration of RocksDB settings without restarting.
-/// Options are passed as a list of {key, value} tuples where both key and value
-/// are strings.
-///
-/// # Mutable Options (subset)
-/// - "level0_file_num_compaction_trigger" - Files in L0 to trigger compaction
-/// - "level0_slowdown_writes_trigger" - Files in L0 to slow down writes
-/// - "level0_stop_writes_trigger" - Files in L0 to stop writes
-/// - "target_file_size_base" - Target file size in bytes
-/// - "max_bytes_for_level_base" - Maximum bytes in base level
-/// - "write_buffer_size" - Size of write buffer (for new memtables)
-/// - "max_write_buffer_number" - Maximum number of write buffers
-/// - "disable_auto_compactions" - Disable automatic compactions ("true"/"false")
+/// Escapes an IRI for use inside `<...>` in N-Triples output.
 ///
-/// # Arguments
-/// * `db_ref` - The database reference
-/// * `options` - List of {key, value} tuples as strings
+/// Characters the `IRIREF` production forbids (controls, space, `<`, `>`,
+/// `"`, `{`, `}`, `|`, `^`, backtick and backslash) are written as
+/// `\uXXXX` escapes.
+fn escape_ntriples_iri(iri: &[u8]) -> String {
+    let text = String::from_utf8_lossy(iri);
+    let mut escaped = String::with_capacity(text.len());
+
+    for ch in text.chars() {
+        match ch {
+            '\u{0}'..='\u{20}' | '<' | '>' | '"' | '{' | '}' | '|' | '^' | '`' | '\\' => {
+                escaped.push_str(&format!("\\u{:04X}", ch as u32));
+            }
+            _ => escaped.push(ch),
+        }
+    }
+
+    escaped
+}
+
+/// Returns a blank node label that is valid N-Triples.
 ///
-/// # Returns
-/// * `:ok` on success
-/// * `{:error, :already_closed}` if database is closed
-/// * `{:error, {:set_options_failed, reason}}` on failure
-#[rustler::nif(schedule = "DirtyCpu")]
-fn set_options<'a>(
-    env: Env<'a>,
-    db_ref: ResourceArc<DbRef>,
-    options: Vec<(String, String)>,
-) -> NifResult<Term<'a>> {
-    let guard = db_ref
-        .inner
-        .read()
-        .map_err(|_| rustler::Error::Term(Box::new("lock poisoned")))?;
+/// Labels that already match `BLANK_NODE_LABEL` are kept as they are. Any
+/// other label is re-labelled as `x` followed by the hex of its bytes, so the
+/// same stored label always maps to the same output label.
+fn ntriples_blank_label(label: &[u8]) -> String {
+    if let Ok(text) = std::str::from_utf8(label) {
+        if is_ntriples_blank_label(text) {
+            return text.to_string();
+        }
+    }
 
-    let shared_db = match guard.as_ref() {
-        Some(db) => db,
-        None => return Ok((atoms::error(), atoms::already_closed()).encode(env)),
+    let mut relabelled = String::with_capacity(1 + label.len() * 2);
+    relabelled.push('x');
+    for byte in label {
+        relabelled.push_str(&format!("{:02x}", byte));
+    }
+    relabelled
+}
+
+/// Checks a label against the N-Triples `BLANK_NODE_LABEL` production.
+fn is_ntriples_blank_label(label: &str) -> bool {
+    let mut chars = label.chars();
+    let first_ok = match chars.next() {
+        Some(ch) => is_pn_chars_u(ch) || ch.is_ascii_digit(),
+        None => false,
     };
 
-    // Convert options to the format expected by RocksDB: &[(&str, &str)]
-    let opts: Vec<(&str, &str)> = options
-        .iter()
-        .map(|(k, v)| (k.as_str(), v.as_str()))
-        .collect();
+    first_ok && !label.ends_with('.') && chars.all(|ch| ch == '.' || is_pn_chars(ch))
+}
 
-    // Apply options to all column families
-    for cf_name in CF_NAMES.iter() {
-        let cf = match shared_db.db.cf_handle(cf_name) {
-            Some(cf) => cf,
-            None => {
-                return Ok((
-                    atoms::error(),
-                    (
-                        atoms::set_options_failed(),
-                        format!("column family '{}' not found", cf_name),
-                    ),
-                )
-                .encode(env))
-            }
-        };
+/// `PN_CHARS_U`: `PN_CHARS_BASE`, `_` or `:`.
+fn is_pn_chars_u(ch: char) -> bool {
+    matches!(ch,
+        'A'..='Z'
+        | 'a'..='z'
+        | '_'
+        | ':'
+        | '\u{C0}'..='\u{D6}'
+        | '\u{D8}'..='\u{F6}'
+        | '\u{F8}'..='\u{2FF}'
+        | '\u{370}'..='\u{37D}'
+        | '\u{37F}'..='\u{1FFF}'
+        | '\u{200C}'..='\u{200D}'
+        | '\u{2070}'..='\u{218F}'
+        | '\u{2C00}'..='\u{2FEF}'
+        | '\u{3001}'..='\u{D7FF}'
+        | '\u{F900}'..='\u{FDCF}'
+        | '\u{FDF0}'..='\u{FFFD}'
+        | '\u{10000}'..='\u{EFFFF}')
+}
 
-        if let Err(e) = shared_db.db.set_options_cf(&cf, &opts) {
-            return Ok((
-                atoms::error(),
-                (
-                    atoms::set_options_failed(),
-                    format!("failed to set options on '{}': {}", cf_name, e),
-                ),
-            )
-            .encode(env));
+/// `PN_CHARS`: `PN_CHARS_U`, `-`, digits and the combining ranges.
+fn is_pn_chars(ch: char) -> bool {
+    is_pn_chars_u(ch)
+        || matches!(ch,
+            '-' | '0'..='9' | '\u{B7}' | '\u{300}'..='\u{36F}' | '\u{203F}'..='\u{2040}')
+}
+
+/// Escapes a literal lexical form for N-Triples output.
+fn escape_ntriples_string(value: &[u8]) -> String {
+    let text = String::from_utf8_lossy(value);
+    let mut escaped = String::with_capacity(text.len());
+
+    for ch in text.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(ch),
         }
     }
 
-    Ok(atoms::ok().encode(env))
+    escaped
 }
 
 rustler::init!("Elixir.TripleStore.Backend.RocksDB.NIF");
 
 #[cfg(test)]
 mod tests {
-    use super::CF_NAMES;
-    use rocksdb::{ColumnFamilyDescriptor, Direction, IteratorMode, Options, ReadOptions, WriteBatch, DB};
+    use super::{
+        approximate_range_bytes, begin_txn_db_transaction, commit_txn, count_prefix,
+        decode_wal_batch, format_dictionary_term, format_inline_decimal, format_unix_millis,
+        has_keys_before, intern_cf_name, is_txn_conflict, iterator_snapshot, memory_db_dir,
+        open_backup_engine, open_db_ref, open_subset_db_ref, open_txn_db, prefix_read_options,
+        prefix_successor, prefix_upper_bound, read_cf_options_file, read_committed,
+        reverse_prefix_read_options, seek_iterator, storage_events, swap_with_batch,
+        swap_with_ingest, sync_all, track_txn_key, user_cf_names, write_if_absent, CfName,
+        CfOpenOptions, CursorPosition, DbOpenOptions, IteratorRef, KvPair, LiveSstFiles, SharedDb,
+        TxnError, TxnState, WalRecordKind, CF_NAMES, MEMORY_PATH,
+    };
+    use rocksdb::backup::RestoreOptions;
+    use rocksdb::{
+        ColumnFamilyDescriptor, Direction, IteratorMode, Options, ReadOptions, WriteBatch,
+        WriteOptions, DB,
+    };
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
     use tempfile::TempDir;
 
     fn setup_db() -> (TempDir, DB) {
@@ -1634,6 +9353,65 @@ mod tests {
         }
     }
 
+    #[test]
+    fn prefix_successor_increments_last_byte() {
+        assert_eq!(prefix_successor(b"abc"), Some(b"abd".to_vec()));
+        assert_eq!(prefix_successor(&[0x01, 0xFF]), Some(vec![0x02]));
+        assert_eq!(prefix_successor(&[0xFF, 0xFF]), None);
+        assert_eq!(prefix_successor(b""), None);
+    }
+
+    #[test]
+    fn export_formats_dictionary_and_inline_terms() {
+        assert_eq!(
+            format_dictionary_term(b"\x01http://ex.org/a"),
+            Some("<http://ex.org/a>".to_string())
+        );
+        assert_eq!(format_dictionary_term(b"\x02b1"), Some("_:b1".to_string()));
+        assert_eq!(
+            format_dictionary_term(b"\x03\x00say \"hi\""),
+            Some("\"say \\\"hi\\\"\"".to_string())
+        );
+        assert_eq!(
+            format_dictionary_term(b"\x03\x02en\x00hello"),
+            Some("\"hello\"@en".to_string())
+        );
+        assert_eq!(format_dictionary_term(b"\x03\x01missing-null"), None);
+
+        assert_eq!(
+            format_dictionary_term(b"\x01http://ex.org/a b>\"{c}"),
+            Some("<http://ex.org/a\\u0020b\\u003E\\u0022\\u007Bc\\u007D>".to_string())
+        );
+        assert_eq!(
+            format_dictionary_term(b"\x03\x01http://ex.org/dt x\x00v"),
+            Some("\"v\"^^<http://ex.org/dt\\u0020x>".to_string())
+        );
+        assert_eq!(
+            format_dictionary_term(b"\x02a.b"),
+            Some("_:a.b".to_string())
+        );
+        assert_eq!(
+            format_dictionary_term(b"\x02a b"),
+            Some("_:x612062".to_string())
+        );
+        assert_eq!(
+            format_dictionary_term(b"\x02b."),
+            Some("_:x622e".to_string())
+        );
+        assert_eq!(format_dictionary_term(b"\x02"), Some("_:x".to_string()));
+
+        // 3.14 = coef 314, exp -2 (biased 1021)
+        assert_eq!(format_inline_decimal((1021 << 48) | 314), "3.14");
+        assert_eq!(
+            format_inline_decimal((1 << 59) | (1020 << 48) | 5),
+            "-0.005"
+        );
+        assert_eq!(
+            format_unix_millis(1_705_314_600_123),
+            "2024-01-15T10:30:00.123Z"
+        );
+    }
+
     #[test]
     fn basic_put_get() {
         let (_tmp, db) = setup_db();
@@ -1686,10 +9464,14 @@ mod tests {
         let mut read_opts = ReadOptions::default();
         read_opts.set_snapshot(&snap);
 
-        let result = db.get_cf_opt(&cf, b"key1", &read_opts).expect("get snapshot");
+        let result = db
+            .get_cf_opt(&cf, b"key1", &read_opts)
+            .expect("get snapshot");
         assert_eq!(result, Some(b"v1".to_vec()));
 
-        let result = db.get_cf_opt(&cf, b"key2", &read_opts).expect("get snapshot");
+        let result = db
+            .get_cf_opt(&cf, b"key2", &read_opts)
+            .expect("get snapshot");
         assert_eq!(result, None);
     }
 
@@ -1709,4 +9491,1197 @@ mod tests {
         assert!(db.get_cf(&cf, b"k2").expect("get").is_some());
         assert!(db.get_cf(&cf, b"k3").expect("get").is_some());
     }
+
+    #[test]
+    fn wal_batch_decoding_tracks_column_families() {
+        let (_tmp, db) = setup_db();
+        let spo = db.cf_handle("spo").expect("cf handle");
+        let pos = db.cf_handle("pos").expect("cf handle");
+
+        let mut batch = WriteBatch::default();
+        batch.put_cf(&spo, b"k1", b"v1");
+        batch.put_cf(&pos, b"k2", b"");
+        batch.delete_cf(&spo, b"k1");
+        batch.put(b"k3", b"v3");
+
+        let records = decode_wal_batch(batch.data()).expect("decode");
+        let summary: Vec<(u32, &WalRecordKind, &[u8])> =
+            records.iter().map(|r| (r.cf_id, &r.kind, r.key)).collect();
+
+        let spo_id = records[0].cf_id;
+        let pos_id = records[1].cf_id;
+        assert_ne!(spo_id, pos_id);
+        assert_eq!(
+            summary,
+            vec![
+                (spo_id, &WalRecordKind::Put, &b"k1"[..]),
+                (pos_id, &WalRecordKind::Put, &b"k2"[..]),
+                (spo_id, &WalRecordKind::Delete, &b"k1"[..]),
+                (0, &WalRecordKind::Put, &b"k3"[..]),
+            ]
+        );
+    }
+    #[test]
+    fn subset_open_hides_other_column_families() {
+        let (tmp, mut db) = setup_db();
+        let cf = db.cf_handle("id2str").expect("cf handle");
+        db.put_cf(&cf, b"k1", b"v1").expect("put");
+        db.create_cf("unknown_extra", &Options::default())
+            .expect("create cf");
+        drop(db);
+
+        let path = tmp.path().to_str().expect("utf8 path").to_string();
+        let db_ref =
+            open_subset_db_ref(path, ["id2str"].into_iter().collect()).expect("subset open");
+        let guard = db_ref.load();
+        let shared_db = guard.as_ref().expect("open db");
+
+        let cf = shared_db.cf_handle("id2str").expect("cf handle");
+        assert_eq!(
+            shared_db.db.get_cf(&cf, b"k1").expect("get"),
+            Some(b"v1".to_vec())
+        );
+        assert!(shared_db.cf_handle("spo").is_none());
+        assert!(shared_db.cf_handle("unknown_extra").is_none());
+        assert_eq!(
+            shared_db.opened_cf_names().collect::<Vec<_>>(),
+            vec!["id2str"]
+        );
+    }
+    #[test]
+    fn user_column_families_open_next_to_builtin_ones() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp.path().to_str().expect("utf8 path").to_string();
+        let db_options = DbOpenOptions {
+            user_cfs: vec![intern_cf_name("tenant_b"), intern_cf_name("tenant_a")],
+            ..Default::default()
+        };
+        let db_ref = open_db_ref(path, &Default::default(), &db_options).expect("open");
+        let guard = db_ref.load();
+        let shared_db = guard.as_ref().expect("open db");
+
+        let cf = shared_db.cf_handle("tenant_a").expect("cf handle");
+        shared_db.db.put_cf(&cf, b"k1", b"v1").expect("put");
+        assert_eq!(
+            shared_db.db.get_cf(&cf, b"k1").expect("get"),
+            Some(b"v1".to_vec())
+        );
+
+        let names: Vec<&str> = shared_db.opened_cf_names().collect();
+        assert_eq!(names.len(), CF_NAMES.len() + 2);
+        assert_eq!(&names[CF_NAMES.len()..], ["tenant_a", "tenant_b"]);
+
+        // A repeated name shares one allocation, released once the database closes
+        let owned = String::from("tenant_a");
+        match (intern_cf_name("tenant_a"), intern_cf_name(&owned)) {
+            (CfName::User(first), CfName::User(second)) => assert!(Arc::ptr_eq(&first, &second)),
+            other => panic!("expected user column family names, got {:?}", other),
+        }
+        drop(db_options);
+        drop(guard);
+        drop(db_ref);
+        assert!(!user_cf_names().contains("tenant_a"));
+    }
+    #[test]
+    fn options_file_reports_tuned_cf_options() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp.path().to_str().expect("utf8 path").to_string();
+        let _db_ref =
+            open_db_ref(path.clone(), &Default::default(), &Default::default()).expect("open");
+
+        let options = read_cf_options_file(&path, "spo").expect("read options");
+        let value = |key: &str| {
+            options
+                .iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.as_str())
+        };
+
+        assert_eq!(value("block_size"), Some("8192"));
+        assert_eq!(value("prefix_extractor"), Some("rocksdb.FixedPrefix.8"));
+        assert_eq!(value("compaction_style"), Some("kCompactionStyleLevel"));
+        assert!(read_cf_options_file(&path, "missing").is_err());
+    }
+    #[test]
+    fn open_options_reach_every_cf() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp.path().to_str().expect("utf8 path").to_string();
+        let db_options = DbOpenOptions {
+            block_cache_bytes: Some(1 << 20),
+            write_buffer_bytes: Some(4 << 20),
+            max_open_files: Some(64),
+            compression: Some(rocksdb::DBCompressionType::Zstd),
+            ..Default::default()
+        };
+        let _db_ref = open_db_ref(path.clone(), &Default::default(), &db_options).expect("open");
+
+        for cf in CF_NAMES {
+            let options = read_cf_options_file(&path, cf).expect("read options");
+            let value = |key: &str| {
+                options
+                    .iter()
+                    .find(|(name, _)| *name == key)
+                    .map(|(_, value)| value.as_str())
+            };
+
+            assert_eq!(value("write_buffer_size"), Some("4194304"), "{cf}");
+            assert_eq!(value("compression"), Some("kZSTD"), "{cf}");
+        }
+    }
+    #[test]
+    fn approximate_sizes_only_count_overlapping_files() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp.path().to_str().expect("utf8 path").to_string();
+        let db_ref = open_db_ref(path, &Default::default(), &Default::default()).expect("open");
+        let guard = db_ref.load();
+        let shared_db = guard.as_ref().expect("open db");
+        let cf = shared_db.cf_handle("spo").expect("cf handle");
+
+        for i in 0..1000u32 {
+            let key = format!("a{:04}", i);
+            shared_db
+                .db
+                .put_cf(&cf, key.as_bytes(), [7u8; 64])
+                .expect("put");
+        }
+        shared_db.db.flush_cf(&cf).expect("flush");
+
+        let ranges: [(&[u8], &[u8]); 3] = [(b"a", b"b"), (b"b", b"c"), (b"", b"a0500")];
+        let sizes = approximate_range_bytes(shared_db, "spo", &ranges).expect("sizes");
+        assert!(sizes[0] > 0);
+        assert_eq!(sizes[1], 0);
+        assert_eq!(sizes[2], sizes[0]);
+
+        let other = approximate_range_bytes(shared_db, "pos", &ranges).expect("sizes");
+        assert_eq!(other, vec![0, 0, 0]);
+    }
+    #[test]
+    fn cf_overrides_set_compression_and_bloom_filter_per_cf() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp.path().to_str().expect("utf8 path").to_string();
+        let overrides: HashMap<&'static str, CfOpenOptions> = [
+            (
+                "id2str",
+                CfOpenOptions {
+                    compression: Some(rocksdb::DBCompressionType::Zstd),
+                    ..Default::default()
+                },
+            ),
+            (
+                "derived",
+                CfOpenOptions {
+                    bloom_filter_bits: Some(10),
+                    ..Default::default()
+                },
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let _db_ref = open_db_ref(path.clone(), &overrides, &Default::default()).expect("open");
+
+        let value = |cf: &str, key: &str| {
+            read_cf_options_file(&path, cf)
+                .expect("read options")
+                .into_iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value)
+        };
+
+        assert_eq!(value("id2str", "compression").as_deref(), Some("kZSTD"));
+        assert_eq!(
+            value("spo", "compression").as_deref(),
+            Some("kLZ4Compression")
+        );
+        assert!(value("derived", "filter_policy")
+            .expect("filter policy")
+            .contains("bloomfilter"));
+        assert_eq!(
+            value("derived", "compression").as_deref(),
+            Some("kLZ4Compression")
+        );
+    }
+    #[test]
+    fn derived_counters_fold_concurrent_merges() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp.path().to_str().expect("utf8 path").to_string();
+        let db_ref = open_db_ref(path, &Default::default(), &Default::default()).expect("open");
+        let guard = db_ref.load();
+        let shared_db = guard.as_ref().expect("open db");
+        assert!(shared_db.merge_cfs.contains("derived"));
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    let cf = shared_db.cf_handle("derived").expect("cf handle");
+                    for _ in 0..125 {
+                        shared_db
+                            .db
+                            .merge_cf(cf, b"refcount", 1i64.to_le_bytes())
+                            .expect("merge");
+                    }
+                });
+            }
+        });
+
+        let cf = shared_db.cf_handle("derived").expect("cf handle");
+        let value = shared_db
+            .db
+            .get_cf(cf, b"refcount")
+            .expect("get")
+            .expect("value");
+        assert_eq!(value, 1000i64.to_le_bytes().to_vec());
+    }
+    #[test]
+    fn derived_ttl_expires_only_stale_derived_entries_on_compaction() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp.path().to_str().expect("utf8 path").to_string();
+        let db_options = DbOpenOptions {
+            derived_ttl: Some(60),
+            ..Default::default()
+        };
+        let db_ref = open_db_ref(path, &Default::default(), &db_options).expect("open");
+        let guard = db_ref.load();
+        let shared_db = guard.as_ref().expect("open db");
+        assert!(!shared_db.merge_cfs.contains("derived"));
+        assert!(shared_db.stamps("derived"));
+        assert!(!shared_db.stamps("spo"));
+
+        let derived = shared_db.cf_handle("derived").expect("cf handle");
+        let spo = shared_db.cf_handle("spo").expect("cf handle");
+        let mut stale = 0u64.to_be_bytes().to_vec();
+        stale.extend_from_slice(b"old");
+        shared_db.db.put_cf(derived, b"stale", &stale).expect("put");
+        shared_db
+            .db
+            .put_cf(derived, b"fresh", shared_db.stamp("derived", b"new"))
+            .expect("put");
+        shared_db
+            .db
+            .put_cf(spo, b"triple", shared_db.stamp("spo", b""))
+            .expect("put");
+
+        // Expiry is lazy: the stale entry is still readable until compacted
+        assert_eq!(
+            read_committed(shared_db, "derived", b"stale").expect("get"),
+            Some(b"old".to_vec())
+        );
+
+        shared_db.db.flush_cf(derived).expect("flush");
+        shared_db
+            .db
+            .compact_range_cf(derived, None::<&[u8]>, None::<&[u8]>);
+
+        assert_eq!(
+            read_committed(shared_db, "derived", b"stale").expect("get"),
+            None
+        );
+        assert_eq!(
+            read_committed(shared_db, "derived", b"fresh").expect("get"),
+            Some(b"new".to_vec())
+        );
+        assert_eq!(
+            shared_db.db.get_cf(spo, b"triple").expect("get"),
+            Some(Vec::new())
+        );
+    }
+    #[test]
+    fn restore_recreates_the_backed_up_database() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp
+            .path()
+            .join("db")
+            .to_str()
+            .expect("utf8 path")
+            .to_string();
+        let backup_path = tmp
+            .path()
+            .join("backups")
+            .to_str()
+            .expect("utf8 path")
+            .to_string();
+        let restore_path = tmp
+            .path()
+            .join("restored")
+            .to_str()
+            .expect("utf8 path")
+            .to_string();
+        {
+            let db_ref = open_db_ref(path, &Default::default(), &Default::default()).expect("open");
+            let guard = db_ref.load();
+            let shared_db = guard.as_ref().expect("open db");
+            let cf = shared_db.cf_handle("spo").expect("cf handle");
+            shared_db.db.put_cf(cf, b"key", b"value").expect("put");
+            let mut engine = open_backup_engine(&backup_path).expect("backup engine");
+            engine
+                .create_new_backup_flush(&shared_db.db, true)
+                .expect("backup");
+        }
+
+        let mut engine = open_backup_engine(&backup_path).expect("backup engine");
+        engine
+            .restore_from_latest_backup(&restore_path, &restore_path, &RestoreOptions::default())
+            .expect("restore");
+
+        let restored =
+            open_db_ref(restore_path, &Default::default(), &Default::default()).expect("open");
+        let guard = restored.load();
+        let shared_db = guard.as_ref().expect("open db");
+        let cf = shared_db.cf_handle("spo").expect("cf handle");
+        assert_eq!(
+            shared_db.db.get_cf(cf, b"key").expect("get"),
+            Some(b"value".to_vec())
+        );
+    }
+    #[test]
+    fn backups_are_incremental_and_purgeable() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp
+            .path()
+            .join("db")
+            .to_str()
+            .expect("utf8 path")
+            .to_string();
+        let backup_path = tmp
+            .path()
+            .join("backups")
+            .to_str()
+            .expect("utf8 path")
+            .to_string();
+        let db_ref = open_db_ref(path, &Default::default(), &Default::default()).expect("open");
+        let guard = db_ref.load();
+        let shared_db = guard.as_ref().expect("open db");
+        let cf = shared_db.cf_handle("spo").expect("cf handle");
+
+        let mut engine = open_backup_engine(&backup_path).expect("backup engine");
+        for i in 0..3u8 {
+            shared_db.db.put_cf(cf, [i], b"").expect("put");
+            engine
+                .create_new_backup_flush(&shared_db.db, true)
+                .expect("backup");
+        }
+
+        let ids: Vec<u32> = engine
+            .get_backup_info()
+            .iter()
+            .map(|info| info.backup_id)
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        engine.purge_old_backups(1).expect("purge");
+        let ids: Vec<u32> = engine
+            .get_backup_info()
+            .iter()
+            .map(|info| info.backup_id)
+            .collect();
+        assert_eq!(ids, vec![3]);
+    }
+    #[test]
+    fn atomic_flush_recovers_unlogged_index_writes_together() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp.path().to_str().expect("utf8 path").to_string();
+        let atomic = DbOpenOptions {
+            atomic_flush: true,
+            ..Default::default()
+        };
+
+        {
+            let db_ref = open_db_ref(path.clone(), &Default::default(), &atomic).expect("open");
+            let guard = db_ref.load();
+            let shared_db = guard.as_ref().expect("open db");
+
+            // Without the WAL these writes only survive through the flush
+            let mut batch = WriteBatch::default();
+            for name in ["spo", "pos", "osp"] {
+                let cf = shared_db.cf_handle(name).expect("cf handle");
+                batch.put_cf(cf, b"triple", b"");
+            }
+            let mut write_opts = WriteOptions::default();
+            write_opts.disable_wal(true);
+            shared_db.db.write_opt(batch, &write_opts).expect("write");
+
+            sync_all(shared_db).expect("sync");
+        }
+
+        let db_ref = open_db_ref(path, &Default::default(), &atomic).expect("reopen");
+        let guard = db_ref.load();
+        let shared_db = guard.as_ref().expect("open db");
+        for name in ["spo", "pos", "osp"] {
+            let cf = shared_db.cf_handle(name).expect("cf handle");
+            assert_eq!(
+                shared_db.db.get_cf(cf, b"triple").expect("get"),
+                Some(Vec::new())
+            );
+        }
+    }
+    #[test]
+    fn in_memory_database_stays_off_disk() {
+        let db_options = DbOpenOptions {
+            in_memory: true,
+            ..Default::default()
+        };
+        let first =
+            open_db_ref(MEMORY_PATH.to_string(), &Default::default(), &db_options).expect("open");
+        let second =
+            open_db_ref(MEMORY_PATH.to_string(), &Default::default(), &db_options).expect("open");
+
+        let guard = first.load();
+        let shared_db = guard.as_ref().expect("open db");
+        let cf = shared_db.cf_handle("spo").expect("cf handle");
+        let mut write_opts = WriteOptions::default();
+        write_opts.set_sync(true);
+        shared_db
+            .db
+            .put_cf_opt(cf, b"key", b"value", &write_opts)
+            .expect("put");
+        sync_all(shared_db).expect("sync");
+        assert_eq!(
+            shared_db.db.get_cf(cf, b"key").expect("get"),
+            Some(b"value".to_vec())
+        );
+
+        // Instances are independent and nothing was written to disk
+        let other_guard = second.load();
+        let other = other_guard.as_ref().expect("open db");
+        let other_cf = other.cf_handle("spo").expect("cf handle");
+        assert_eq!(other.db.get_cf(other_cf, b"key").expect("get"), None);
+        assert_eq!(
+            std::fs::read_dir(memory_db_dir())
+                .expect("read dir")
+                .count(),
+            0
+        );
+    }
+    /// Fills osp with `new_*` keys and derived with `old_*` keys, plus a
+    /// `shared` key in both, for the swap tests.
+    fn fill_swap_cfs(shared_db: &SharedDb, n: usize) {
+        let osp = shared_db.cf_handle("osp").expect("cf handle");
+        let derived = shared_db.cf_handle("derived").expect("cf handle");
+        for i in 0..n {
+            shared_db
+                .db
+                .put_cf(osp, format!("new_{:05}", i), b"v")
+                .expect("put");
+            shared_db
+                .db
+                .put_cf(derived, format!("old_{:05}", i), b"v")
+                .expect("put");
+        }
+        shared_db.db.put_cf(osp, b"shared", b"new").expect("put");
+        shared_db
+            .db
+            .put_cf(derived, b"shared", b"old")
+            .expect("put");
+    }
+
+    /// Asserts that the CF holds exactly the `n` keys with `prefix` plus `shared`.
+    fn assert_swapped_cf(
+        shared_db: &SharedDb,
+        cf_name: &str,
+        prefix: &str,
+        shared: &[u8],
+        n: usize,
+    ) {
+        let cf = shared_db.cf_handle(cf_name).expect("cf handle");
+        let entries: Vec<KvPair> = shared_db
+            .db
+            .iterator_cf(cf, IteratorMode::Start)
+            .collect::<Result<_, _>>()
+            .expect("read");
+        assert_eq!(entries.len(), n + 1);
+        assert!(entries[..n]
+            .iter()
+            .all(|(key, _)| key.starts_with(prefix.as_bytes())));
+        assert_eq!(&*entries[n].0, b"shared");
+        assert_eq!(&*entries[n].1, shared);
+    }
+
+    #[test]
+    fn swap_with_ingest_replaces_to_cf_while_readers_run() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp.path().to_str().expect("utf8 path").to_string();
+        let db_ref = open_db_ref(path, &Default::default(), &Default::default()).expect("open");
+        let guard = db_ref.load();
+        let shared_db = guard.as_ref().expect("open db");
+        let n = 20_000;
+        fill_swap_cfs(shared_db, n);
+
+        let done = AtomicBool::new(false);
+        let (count, observed) = std::thread::scope(|scope| {
+            let reader = scope.spawn(|| {
+                let derived = shared_db.cf_handle("derived").expect("cf handle");
+                let mut observed = Vec::new();
+                while !done.load(Ordering::Acquire) {
+                    // An iterator reads from the state at its creation
+                    let keys: Vec<Box<[u8]>> = shared_db
+                        .db
+                        .iterator_cf(derived, IteratorMode::Start)
+                        .map(|item| item.expect("read").0)
+                        .collect();
+                    observed.push(keys);
+                }
+                observed
+            });
+            let osp = shared_db.cf_handle("osp").expect("cf handle");
+            let derived = shared_db.cf_handle("derived").expect("cf handle");
+            let count = swap_with_ingest(shared_db, osp, derived, &tmp.path().join("swap.tmp"))
+                .expect("swap");
+            done.store(true, Ordering::Release);
+            (count, reader.join().expect("reader"))
+        });
+
+        assert_eq!(count, n + 1);
+        assert!(!observed.is_empty());
+        for keys in observed {
+            assert_eq!(keys.len(), n + 1);
+            let prefix: &[u8] = if keys[0].starts_with(b"old_") {
+                b"old_"
+            } else {
+                b"new_"
+            };
+            assert!(keys[..n].iter().all(|key| key.starts_with(prefix)));
+        }
+        assert_swapped_cf(shared_db, "derived", "new_", b"new", n);
+        assert_swapped_cf(shared_db, "osp", "old_", b"old", n);
+    }
+
+    #[test]
+    fn swap_with_batch_swaps_in_memory_cfs() {
+        let db_options = DbOpenOptions {
+            in_memory: true,
+            ..Default::default()
+        };
+        let db_ref =
+            open_db_ref(MEMORY_PATH.to_string(), &Default::default(), &db_options).expect("open");
+        let guard = db_ref.load();
+        let shared_db = guard.as_ref().expect("open db");
+        fill_swap_cfs(shared_db, 100);
+
+        let osp = shared_db.cf_handle("osp").expect("cf handle");
+        let derived = shared_db.cf_handle("derived").expect("cf handle");
+        assert_eq!(swap_with_batch(shared_db, osp, derived), Ok(101));
+        assert_swapped_cf(shared_db, "derived", "new_", b"new", 100);
+        assert_swapped_cf(shared_db, "osp", "old_", b"old", 100);
+
+        // Swapping two empty column families is a no-op
+        let gspo = shared_db.cf_handle("gspo").expect("cf handle");
+        let gpos = shared_db.cf_handle("gpos").expect("cf handle");
+        assert_eq!(swap_with_batch(shared_db, gspo, gpos), Ok(0));
+    }
+
+    #[test]
+    fn read_only_open_reads_alongside_the_primary() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp.path().to_str().expect("utf8 path").to_string();
+        let primary =
+            open_db_ref(path.clone(), &Default::default(), &Default::default()).expect("open");
+        {
+            let guard = primary.load();
+            let shared_db = guard.as_ref().expect("open db");
+            let cf = shared_db.cf_handle("spo").expect("cf handle");
+            shared_db.db.put_cf(cf, b"key", b"value").expect("put");
+        }
+
+        let db_options = DbOpenOptions {
+            read_only: true,
+            ..Default::default()
+        };
+        let reader = open_db_ref(path, &Default::default(), &db_options).expect("open read-only");
+        assert!(reader.read_only);
+
+        let guard = reader.load();
+        let shared_db = guard.as_ref().expect("open db");
+        let cf = shared_db.cf_handle("spo").expect("cf handle");
+        assert_eq!(
+            shared_db.db.get_cf(cf, b"key").expect("get"),
+            Some(b"value".to_vec())
+        );
+        assert!(shared_db.db.put_cf(cf, b"other", b"value").is_err());
+    }
+
+    #[test]
+    fn read_only_and_secondary_opens_accept_a_database_without_quad_cfs() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp
+            .path()
+            .join("primary")
+            .to_str()
+            .expect("utf8 path")
+            .to_string();
+        {
+            // The column families of a store created before the quad indexes
+            let mut opts = Options::default();
+            opts.create_if_missing(true);
+            opts.create_missing_column_families(true);
+            let cf_descriptors = CF_NAMES[..6]
+                .iter()
+                .map(|name| ColumnFamilyDescriptor::new(*name, Options::default()));
+            let db = DB::open_cf_descriptors(&opts, &path, cf_descriptors).expect("open");
+            db.put_cf(db.cf_handle("spo").expect("cf handle"), b"key", b"value")
+                .expect("put");
+        }
+
+        let secondary_path = tmp
+            .path()
+            .join("secondary")
+            .to_str()
+            .expect("utf8 path")
+            .to_string();
+        for db_options in [
+            DbOpenOptions {
+                read_only: true,
+                ..Default::default()
+            },
+            DbOpenOptions {
+                secondary_path: Some(secondary_path),
+                ..Default::default()
+            },
+        ] {
+            let db_ref = open_db_ref(path.clone(), &Default::default(), &db_options).expect("open");
+            let guard = db_ref.load();
+            let shared_db = guard.as_ref().expect("open db");
+            let cf = shared_db.cf_handle("spo").expect("cf handle");
+            assert_eq!(
+                shared_db.db.get_cf(cf, b"key").expect("get"),
+                Some(b"value".to_vec())
+            );
+            assert!(shared_db.cf_handle("gspo").is_none());
+        }
+    }
+
+    #[test]
+    fn write_if_absent_lets_one_concurrent_writer_win() {
+        let db_options = DbOpenOptions {
+            in_memory: true,
+            ..Default::default()
+        };
+        let db_ref =
+            open_db_ref(MEMORY_PATH.to_string(), &Default::default(), &db_options).expect("open");
+        let guard = db_ref.load();
+        let shared_db = guard.as_ref().expect("open db");
+
+        let outcomes: Vec<Option<Vec<u8>>> = std::thread::scope(|scope| {
+            let writers: Vec<_> = (0..8u8)
+                .map(|writer| {
+                    scope.spawn(move || {
+                        write_if_absent(shared_db, "str2id", b"term", &[writer]).expect("write")
+                    })
+                })
+                .collect();
+            writers
+                .into_iter()
+                .map(|writer| writer.join().expect("writer"))
+                .collect()
+        });
+
+        let stored = shared_db
+            .db
+            .get_cf(shared_db.cf_handle("str2id").expect("cf handle"), b"term")
+            .expect("get")
+            .expect("stored");
+        assert_eq!(
+            outcomes.iter().filter(|outcome| outcome.is_none()).count(),
+            1
+        );
+        assert!(outcomes
+            .iter()
+            .flatten()
+            .all(|existing| *existing == stored));
+    }
+    #[test]
+    fn loads_wait_for_a_checked_out_database() {
+        let db_options = DbOpenOptions {
+            in_memory: true,
+            ..Default::default()
+        };
+        let db_ref =
+            open_db_ref(MEMORY_PATH.to_string(), &Default::default(), &db_options).expect("open");
+
+        std::thread::scope(|scope| {
+            let checkout = db_ref.check_out().expect("open db");
+            let reader = scope.spawn(|| db_ref.load().is_some());
+            std::thread::sleep(Duration::from_millis(50));
+            assert!(!reader.is_finished());
+            drop(checkout);
+            assert!(reader.join().expect("reader"));
+        });
+
+        assert!(db_ref.close().is_some());
+        assert!(db_ref.load().is_none());
+        assert!(db_ref.check_out().is_none());
+        assert!(db_ref.close().is_none());
+    }
+    #[test]
+    fn secondary_open_catches_up_with_the_primary() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp
+            .path()
+            .join("primary")
+            .to_str()
+            .expect("utf8 path")
+            .to_string();
+        let secondary_path = tmp
+            .path()
+            .join("secondary")
+            .to_str()
+            .expect("utf8 path")
+            .to_string();
+        let primary =
+            open_db_ref(path.clone(), &Default::default(), &Default::default()).expect("open");
+        let put = |key: &[u8]| {
+            let guard = primary.load();
+            let shared_db = guard.as_ref().expect("open db");
+            let cf = shared_db.cf_handle("spo").expect("cf handle");
+            shared_db.db.put_cf(cf, key, b"value").expect("put");
+        };
+        put(b"before");
+
+        let db_options = DbOpenOptions {
+            secondary_path: Some(secondary_path),
+            ..Default::default()
+        };
+        let secondary =
+            open_db_ref(path, &Default::default(), &db_options).expect("open secondary");
+        assert!(secondary.read_only && secondary.secondary);
+        put(b"after");
+
+        let guard = secondary.load();
+        let shared_db = guard.as_ref().expect("open db");
+        let cf = shared_db.cf_handle("spo").expect("cf handle");
+        assert!(shared_db.db.get_cf(cf, b"before").expect("get").is_some());
+        assert!(shared_db.db.get_cf(cf, b"after").expect("get").is_none());
+        shared_db.db.try_catch_up_with_primary().expect("catch up");
+        assert!(shared_db.db.get_cf(cf, b"after").expect("get").is_some());
+    }
+    #[test]
+    fn reverse_prefix_iteration_stays_within_prefix() {
+        let db_options = DbOpenOptions {
+            in_memory: true,
+            ..Default::default()
+        };
+        let db_ref =
+            open_db_ref(MEMORY_PATH.to_string(), &Default::default(), &db_options).expect("open");
+        let guard = db_ref.load();
+        let shared_db = guard.as_ref().expect("open db");
+        let cf = shared_db.cf_handle("spo").expect("cf handle");
+
+        let prefix = [0u8, 0, 0, 0, 0, 0, 0, 2];
+        for key in [
+            [0u8, 0, 0, 0, 0, 0, 0, 1, 9],
+            [0, 0, 0, 0, 0, 0, 0, 2, 1],
+            [0, 0, 0, 0, 0, 0, 0, 2, 5],
+            [0, 0, 0, 0, 0, 0, 0, 3, 0],
+        ] {
+            shared_db.db.put_cf(cf, key, b"").expect("put");
+        }
+
+        let keys: Vec<Vec<u8>> = shared_db
+            .db
+            .iterator_cf_opt(cf, reverse_prefix_read_options(&prefix), IteratorMode::End)
+            .map(|item| item.expect("next").0.to_vec())
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                vec![0, 0, 0, 0, 0, 0, 0, 2, 5],
+                vec![0, 0, 0, 0, 0, 0, 0, 2, 1]
+            ]
+        );
+
+        assert!(has_keys_before(shared_db, "spo", &prefix));
+        assert!(!has_keys_before(
+            shared_db,
+            "spo",
+            &[0u8, 0, 0, 0, 0, 0, 0, 1]
+        ));
+    }
+    #[test]
+    fn stepping_back_stays_within_prefix() {
+        let db_options = DbOpenOptions {
+            in_memory: true,
+            ..Default::default()
+        };
+        let db_ref =
+            open_db_ref(MEMORY_PATH.to_string(), &Default::default(), &db_options).expect("open");
+        let shared_db = db_ref.load().as_ref().cloned().expect("open db");
+        let cf = shared_db.cf_handle("spo").expect("cf handle");
+
+        let key = |s: u8, o: u8| vec![0u8, 0, 0, 0, 0, 0, 0, s, o];
+        for entry in [key(1, 9), key(2, 1), key(2, 3), key(2, 5), key(3, 0)] {
+            shared_db.db.put_cf(cf, entry, b"").expect("put");
+        }
+
+        let prefix = [0u8, 0, 0, 0, 0, 0, 0, 2];
+        let iterator = shared_db.db.iterator_cf_opt(
+            cf,
+            prefix_read_options("spo", &prefix),
+            IteratorMode::From(&prefix, rocksdb::Direction::Forward),
+        );
+        let iterator: rocksdb::DBIteratorWithThreadMode<'static, rocksdb::DB> =
+            unsafe { std::mem::transmute(iterator) };
+        let iter_ref = IteratorRef::new(
+            iterator,
+            iterator_snapshot(&shared_db),
+            shared_db.clone(),
+            prefix.to_vec(),
+            "spo".to_string(),
+            Vec::new(),
+            false,
+        );
+
+        let walk = |position: CursorPosition, direction: rocksdb::Direction| -> Vec<Vec<u8>> {
+            iter_ref
+                .step_from(&position, direction)
+                .expect("cf handle")
+                .map(|item| item.expect("next").0.to_vec())
+                .take_while(|key| iter_ref.covers(key))
+                .collect()
+        };
+
+        assert_eq!(
+            walk(CursorPosition::At(key(2, 3)), rocksdb::Direction::Reverse),
+            vec![key(2, 1)]
+        );
+        assert_eq!(
+            walk(CursorPosition::At(key(2, 3)), rocksdb::Direction::Forward),
+            vec![key(2, 5)]
+        );
+        assert_eq!(
+            walk(CursorPosition::End, rocksdb::Direction::Reverse),
+            vec![key(2, 5), key(2, 3), key(2, 1)]
+        );
+        assert_eq!(
+            walk(CursorPosition::Start, rocksdb::Direction::Forward),
+            vec![key(2, 1), key(2, 3), key(2, 5)]
+        );
+    }
+    #[test]
+    fn rebuilt_iterators_keep_the_creation_snapshot() {
+        let db_options = DbOpenOptions {
+            in_memory: true,
+            ..Default::default()
+        };
+        let db_ref =
+            open_db_ref(MEMORY_PATH.to_string(), &Default::default(), &db_options).expect("open");
+        let shared_db = db_ref.load().as_ref().cloned().expect("open db");
+        let cf = shared_db.cf_handle("spo").expect("cf handle");
+
+        let key = |s: u8, o: u8| vec![0u8, 0, 0, 0, 0, 0, 0, s, o];
+        for entry in [key(2, 1), key(2, 3)] {
+            shared_db.db.put_cf(cf, entry, b"").expect("put");
+        }
+
+        let prefix = [0u8, 0, 0, 0, 0, 0, 0, 2];
+        let snapshot = iterator_snapshot(&shared_db);
+        let mut read_opts = prefix_read_options("spo", &prefix);
+        read_opts.set_snapshot(&snapshot);
+        let iterator = shared_db.db.iterator_cf_opt(
+            cf,
+            read_opts,
+            IteratorMode::From(&prefix, rocksdb::Direction::Forward),
+        );
+        let iterator: rocksdb::DBIteratorWithThreadMode<'static, rocksdb::DB> =
+            unsafe { std::mem::transmute(iterator) };
+        let iter_ref = IteratorRef::new(
+            iterator,
+            snapshot,
+            shared_db.clone(),
+            prefix.to_vec(),
+            "spo".to_string(),
+            Vec::new(),
+            false,
+        );
+
+        // Writes after the iterator was created stay invisible to every rebuild
+        shared_db.db.put_cf(cf, key(2, 2), b"").expect("put");
+        shared_db.db.delete_cf(cf, key(2, 3)).expect("delete");
+        let keys =
+            |iterator: rocksdb::DBIteratorWithThreadMode<'static, rocksdb::DB>| -> Vec<Vec<u8>> {
+                iterator
+                    .map(|item| item.expect("next").0.to_vec())
+                    .take_while(|key| iter_ref.covers(key))
+                    .collect()
+            };
+
+        assert_eq!(
+            keys(seek_iterator(&iter_ref, &prefix).expect("cf handle")),
+            vec![key(2, 1), key(2, 3)]
+        );
+        assert_eq!(
+            keys(iter_ref.seek_for_prev(&key(2, 9)).expect("cf handle")),
+            vec![key(2, 3), key(2, 1)]
+        );
+        assert_eq!(
+            keys(
+                iter_ref
+                    .step_from(&CursorPosition::At(key(2, 1)), rocksdb::Direction::Forward)
+                    .expect("cf handle")
+            ),
+            vec![key(2, 3)]
+        );
+        assert_eq!(
+            keys(
+                iter_ref
+                    .step_from(&CursorPosition::End, rocksdb::Direction::Reverse)
+                    .expect("cf handle")
+            ),
+            vec![key(2, 3), key(2, 1)]
+        );
+    }
+    #[test]
+    fn upper_bound_survives_seek_and_stepping_back() {
+        let db_options = DbOpenOptions {
+            in_memory: true,
+            ..Default::default()
+        };
+        let db_ref =
+            open_db_ref(MEMORY_PATH.to_string(), &Default::default(), &db_options).expect("open");
+        let shared_db = db_ref.load().as_ref().cloned().expect("open db");
+        let cf = shared_db.cf_handle("spo").expect("cf handle");
+
+        let key = |s: u8, o: u8| vec![0u8, 0, 0, 0, 0, 0, 0, s, o];
+        for entry in [key(2, 1), key(2, 3), key(2, 5), key(3, 0)] {
+            shared_db.db.put_cf(cf, entry, b"").expect("put");
+        }
+
+        let prefix = [0u8, 0, 0, 0, 0, 0, 0, 2];
+        assert_eq!(
+            prefix_upper_bound(&prefix, key(9, 0)),
+            vec![0, 0, 0, 0, 0, 0, 0, 3]
+        );
+        let upper = prefix_upper_bound(&prefix, key(2, 5));
+        assert_eq!(upper, key(2, 5));
+
+        let iterator = shared_db.db.iterator_cf_opt(
+            cf,
+            prefix_read_options("spo", &prefix),
+            IteratorMode::From(&prefix, rocksdb::Direction::Forward),
+        );
+        let iterator: rocksdb::DBIteratorWithThreadMode<'static, rocksdb::DB> =
+            unsafe { std::mem::transmute(iterator) };
+        let iter_ref = IteratorRef::new(
+            iterator,
+            iterator_snapshot(&shared_db),
+            shared_db.clone(),
+            prefix.to_vec(),
+            "spo".to_string(),
+            Vec::new(),
+            false,
+        )
+        .with_upper_bound(Some(upper));
+
+        // RocksDB itself stops at the bound, without the prefix check
+        let keys: Vec<Vec<u8>> = seek_iterator(&iter_ref, &key(2, 2))
+            .expect("cf handle")
+            .map(|item| item.expect("next").0.to_vec())
+            .collect();
+        assert_eq!(keys, vec![key(2, 3)]);
+
+        let keys: Vec<Vec<u8>> = iter_ref
+            .step_from(&CursorPosition::End, rocksdb::Direction::Reverse)
+            .expect("cf handle")
+            .map(|item| item.expect("next").0.to_vec())
+            .collect();
+        assert_eq!(keys, vec![key(2, 3), key(2, 1)]);
+        assert!(!iter_ref.covers(&key(2, 5)));
+
+        let keys: Vec<Vec<u8>> = iter_ref
+            .seek_for_prev(&key(2, 4))
+            .expect("cf handle")
+            .map(|item| item.expect("next").0.to_vec())
+            .collect();
+        assert_eq!(keys, vec![key(2, 3), key(2, 1)]);
+        let keys: Vec<Vec<u8>> = iter_ref
+            .seek_for_prev(&key(9, 0))
+            .expect("cf handle")
+            .map(|item| item.expect("next").0.to_vec())
+            .collect();
+        assert_eq!(keys, vec![key(2, 3), key(2, 1)]);
+        assert_eq!(
+            iter_ref
+                .seek_for_prev(&key(1, 9))
+                .expect("cf handle")
+                .count(),
+            0
+        );
+    }
+    #[test]
+    fn count_prefix_stops_at_the_prefix_boundary() {
+        let db_options = DbOpenOptions {
+            in_memory: true,
+            ..Default::default()
+        };
+        let db_ref =
+            open_db_ref(MEMORY_PATH.to_string(), &Default::default(), &db_options).expect("open");
+        let guard = db_ref.load();
+        let shared_db = guard.as_ref().expect("open db");
+        let cf = shared_db.cf_handle("spo").expect("cf handle");
+
+        let key = |s: u8, o: u8| vec![0u8, 0, 0, 0, 0, 0, 0, s, o];
+        for entry in [key(1, 9), key(2, 1), key(2, 3), key(2, 5), key(3, 0)] {
+            shared_db.db.put_cf(cf, entry, b"value").expect("put");
+        }
+
+        assert_eq!(
+            count_prefix(shared_db, "spo", &[0, 0, 0, 0, 0, 0, 0, 2]).expect("count"),
+            3
+        );
+        assert_eq!(
+            count_prefix(shared_db, "spo", &[0, 0, 0, 0, 0, 0, 0, 4]).expect("count"),
+            0
+        );
+        assert_eq!(count_prefix(shared_db, "spo", &[]).expect("count"), 5);
+    }
+    #[test]
+    fn destroy_waits_for_the_database_to_close() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp.path().join("db");
+        let path = path.to_str().expect("utf8 path").to_string();
+        let db_ref =
+            open_db_ref(path.clone(), &Default::default(), &Default::default()).expect("open");
+
+        assert!(DB::destroy(&Options::default(), &path).is_err());
+
+        drop(db_ref);
+        DB::destroy(&Options::default(), &path).expect("destroy");
+        let leftovers = std::fs::read_dir(&path)
+            .map(|entries| entries.count())
+            .unwrap_or(0);
+        assert_eq!(leftovers, 0);
+    }
+    #[test]
+    fn storage_events_classify_new_and_moved_files() {
+        let file = |cf: &str, level, size| (cf.to_string(), level, size);
+        let known: LiveSstFiles = [
+            ("/000010.sst".to_string(), file("spo", 0, 100)),
+            ("/000011.sst".to_string(), file("spo", 0, 50)),
+            ("/000012.sst".to_string(), file("pos", 1, 70)),
+        ]
+        .into_iter()
+        .collect();
+        let current: LiveSstFiles = [
+            ("/000011.sst".to_string(), file("spo", 1, 50)),
+            ("/000012.sst".to_string(), file("pos", 1, 70)),
+            ("/000013.sst".to_string(), file("spo", 1, 90)),
+            ("/000014.sst".to_string(), file("pos", 0, 10)),
+            ("/000015.sst".to_string(), file("pos", 0, 20)),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            storage_events(&known, &current),
+            vec![
+                (false, "spo".to_string(), 1, 2, 140),
+                (true, "pos".to_string(), 0, 2, 30),
+            ]
+        );
+        assert!(storage_events(&current, &current).is_empty());
+    }
+    #[test]
+    fn transaction_commit_detects_conflicting_writes() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp.path().to_str().expect("utf8 path").to_string();
+        let db_ref = open_db_ref(path, &Default::default(), &Default::default()).expect("open");
+        let guard = db_ref.load();
+        let shared_db = guard.as_ref().expect("open db");
+        let cf = shared_db.cf_handle("derived").expect("cf handle");
+        shared_db.db.put_cf(&cf, b"counter", b"1").expect("put");
+
+        // A write outside transactions takes no lock but fails the commit
+        let mut txn = TxnState::new();
+        track_txn_key(shared_db, &mut txn, &CfName::Builtin("derived"), b"counter").expect("track");
+        txn.writes.insert(
+            (CfName::Builtin("derived"), b"counter".to_vec()),
+            Some(b"2".to_vec()),
+        );
+        shared_db.db.put_cf(&cf, b"counter", b"5").expect("put");
+
+        assert_eq!(commit_txn(shared_db, txn), Err(TxnError::Busy));
+        assert_eq!(
+            shared_db.db.get_cf(&cf, b"counter").expect("get"),
+            Some(b"5".to_vec())
+        );
+    }
+
+    #[test]
+    fn transaction_locks_keep_other_transactions_off_a_key() {
+        let tmp = TempDir::new().expect("temp dir");
+        let path = tmp.path().to_str().expect("utf8 path").to_string();
+        let db_ref = open_db_ref(path, &Default::default(), &Default::default()).expect("open");
+        let guard = db_ref.load();
+        let shared_db = guard.as_ref().expect("open db");
+        let cf = shared_db.cf_handle("derived").expect("cf handle");
+        let counter = CfName::Builtin("derived");
+        shared_db.db.put_cf(&cf, b"counter", b"1").expect("put");
+
+        let mut first = TxnState::new();
+        track_txn_key(shared_db, &mut first, &counter, b"counter").expect("track");
+
+        // A second transaction can't change the key back and forth meanwhile
+        let mut second = TxnState::new();
+        assert_eq!(
+            track_txn_key(shared_db, &mut second, &counter, b"counter"),
+            Err(TxnError::Busy)
+        );
+
+        // Once the first commits, a waiting transaction gets the lock and its value
+        first
+            .writes
+            .insert((counter.clone(), b"counter".to_vec()), Some(b"2".to_vec()));
+        std::thread::scope(|scope| {
+            let waiter = scope.spawn(|| {
+                let mut third = TxnState::new();
+                track_txn_key(shared_db, &mut third, &counter, b"counter").map(|()| third)
+            });
+            std::thread::sleep(Duration::from_millis(50));
+            assert_eq!(commit_txn(shared_db, first), Ok(()));
+
+            let mut third = waiter.join().expect("waiter").expect("track");
+            assert_eq!(third.tracked.values().next(), Some(&Some(b"2".to_vec())));
+            third
+                .writes
+                .insert((counter.clone(), b"counter".to_vec()), Some(b"3".to_vec()));
+            assert_eq!(commit_txn(shared_db, third), Ok(()));
+        });
+        assert_eq!(
+            shared_db.db.get_cf(&cf, b"counter").expect("get"),
+            Some(b"3".to_vec())
+        );
+    }
+
+    #[test]
+    fn transaction_db_reports_conflicts_and_rolls_back() {
+        let tmp = TempDir::new().expect("temp dir");
+        let shared_db = open_txn_db(tmp.path().to_str().expect("utf8 path")).expect("open");
+        let cf = shared_db.db.cf_handle("derived").expect("cf handle");
+
+        // A key locked by one transaction is busy for another
+        let first = begin_txn_db_transaction(&shared_db);
+        let second = begin_txn_db_transaction(&shared_db);
+        first.put_cf(cf, b"counter", b"1").expect("put");
+        let conflict = second.put_cf(cf, b"counter", b"2").expect_err("locked key");
+        assert!(is_txn_conflict(&conflict));
+
+        // A write committed after a transaction began conflicts with it too
+        first.commit().expect("commit");
+        let conflict = second
+            .get_for_update_cf(cf, b"counter", true)
+            .expect_err("changed key");
+        assert!(is_txn_conflict(&conflict));
+        second.rollback().expect("rollback");
+
+        // Rolled back writes never reach the database
+        let third = begin_txn_db_transaction(&shared_db);
+        third.put_cf(cf, b"counter", b"3").expect("put");
+        assert_eq!(
+            third.get_for_update_cf(cf, b"counter", true).expect("get"),
+            Some(b"3".to_vec())
+        );
+        third.rollback().expect("rollback");
+        drop(third);
+        assert_eq!(
+            shared_db.db.get_cf(cf, b"counter").expect("get"),
+            Some(b"1".to_vec())
+        );
+    }
 }
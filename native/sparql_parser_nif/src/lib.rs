@@ -4,18 +4,30 @@
 //! using the spargebra crate from the Oxigraph project. The parser converts
 //! SPARQL query strings into an Elixir-native AST representation.
 
-use rustler::{Encoder, Env, NifResult, Term};
-use spargebra::{GraphUpdateOperation, Query, Update};
+use oxiri::Iri;
+use oxrdf::vocab::xsd;
+use oxttl::{
+    NQuadsParser, NQuadsSerializer, NTriplesParser, TurtleParseError, TurtleParser,
+    TurtleSyntaxError,
+};
+use rustler::env::SavedTerm;
+use rustler::{Binary, Encoder, Env, LocalPid, NewBinary, NifResult, OwnedEnv, Term};
+use sha2::{Digest, Sha256};
 use spargebra::algebra::{
-    AggregateExpression, AggregateFunction, Expression, Function, GraphPattern,
-    GraphTarget, OrderExpression, PropertyPathExpression,
+    AggregateExpression, AggregateFunction, Expression, Function, GraphPattern, GraphTarget,
+    OrderExpression, PropertyPathExpression, QueryDataset,
 };
 use spargebra::term::{
-    BlankNode, GraphName, GraphNamePattern, GroundQuad, GroundQuadPattern,
-    GroundSubject, GroundTerm, GroundTermPattern, Literal, NamedNode, NamedNodePattern,
-    Quad, QuadPattern, Subject, TermPattern, TriplePattern, Variable,
+    BlankNode, GraphName, GraphNamePattern, GroundQuad, GroundQuadPattern, GroundSubject,
+    GroundTerm, GroundTermPattern, GroundTriple, GroundTriplePattern, Literal, NamedNode,
+    NamedNodePattern, Quad, QuadPattern, Subject, TermPattern, Triple, TriplePattern, Variable,
 };
-use oxiri::Iri;
+use spargebra::{GraphUpdateOperation, Query, Update};
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::time::Instant;
 
 /// Atoms for Elixir interop
 mod atoms {
@@ -54,11 +66,13 @@ mod atoms {
         blank_node,
         literal,
         triple,
+        triple_term,
 
         // Literal types
         simple,
         language_tagged,
         typed,
+        typed_value,
 
         // Expression types
         or,
@@ -101,6 +115,35 @@ mod atoms {
 
         // Parse error types
         parse_error,
+        wrong_operation,
+        line,
+        column,
+
+        // Profiling stats keys
+        parse_micros,
+        encode_micros,
+        node_count,
+
+        // Query limits
+        max_bytes,
+        max_depth,
+        limit_exceeded,
+        size,
+        depth,
+
+        // Query restrictions
+        service_not_allowed,
+        term_position,
+        literal_subject,
+        literal_template_subject,
+
+        // Serialization
+        dedup,
+        invalid_term,
+        invalid_graph,
+        default,
+        invalid_expression,
+        invalid_ast,
 
         // Update operation types
         update,
@@ -129,6 +172,250 @@ mod atoms {
         one_or_more,
         zero_or_one,
         negated_property_set,
+        // Property path plan hints
+        fixed,
+        inverse,
+        transitive,
+        star,
+        plus,
+        optional,
+        seq,
+        alt,
+        negated,
+        // Query analysis
+        form,
+        variables,
+        projected,
+        all,
+        having,
+        in_optional,
+        in_union,
+        forbidden_function,
+        triple_patterns,
+        named_graphs,
+        modifiers,
+        warnings,
+        limit,
+        offset,
+        optional_dependencies,
+        property_paths,
+        hash,
+        unbound_projection,
+        cartesian_product,
+        patterns,
+        edges,
+        // Document parsing
+        scope_blank_nodes,
+        on_error,
+        abort,
+        skip,
+        file,
+        io_error,
+        turtle_chunk,
+        turtle_done,
+        turtle_error,
+        internal_error,
+        // Literal decoding
+        decode_literals,
+        unsupported_datatype,
+        invalid_lexical_form,
+        datetime,
+        inf,
+        neg_inf,
+        nan,
+    }
+}
+
+// ===========================================================================
+// Profiling Support
+// ===========================================================================
+
+thread_local! {
+    /// Set by `parse_query_profiled` while it converts the AST, so the other
+    /// entry points don't pay for counting.
+    static COUNT_NODES: Cell<bool> = const { Cell::new(false) };
+    /// Number of AST nodes produced by `*_to_term` conversions on this thread
+    /// while `COUNT_NODES` is set.
+    static NODE_COUNT: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Records one AST node produced during term conversion, if counting.
+#[inline]
+fn count_node() {
+    if COUNT_NODES.with(Cell::get) {
+        NODE_COUNT.with(|count| count.set(count.get() + 1));
+    }
+}
+
+/// Sets `COUNT_NODES` with a fresh `NODE_COUNT` until dropped. Dropping it
+/// clears the flag again, also when the conversion panics.
+struct CountNodes;
+
+impl CountNodes {
+    fn start() -> CountNodes {
+        NODE_COUNT.with(|count| count.set(0));
+        COUNT_NODES.with(|c| c.set(true));
+        CountNodes
+    }
+
+    /// Number of nodes counted since `start`.
+    fn count(&self) -> u64 {
+        NODE_COUNT.with(Cell::get)
+    }
+}
+
+impl Drop for CountNodes {
+    fn drop(&mut self) {
+        COUNT_NODES.with(|c| c.set(false));
+    }
+}
+
+// ===========================================================================
+// Nesting Limits
+// ===========================================================================
+
+thread_local! {
+    /// Current nesting depth of graph pattern / expression conversion.
+    static CONVERSION_DEPTH: Cell<u32> = const { Cell::new(0) };
+    /// Maximum allowed nesting depth; only `parse_query_limited` lowers it.
+    static MAX_CONVERSION_DEPTH: Cell<u32> = const { Cell::new(u32::MAX) };
+    /// Set when a conversion was cut short because the depth limit was hit.
+    static DEPTH_EXCEEDED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Tracks one level of recursion in `graph_pattern_to_term`/`expression_to_term`.
+/// The depth is decremented again when the guard is dropped.
+struct DepthGuard;
+
+impl DepthGuard {
+    /// Enters a nesting level, or flags the limit and returns None when it would
+    /// exceed the maximum depth.
+    fn enter() -> Option<DepthGuard> {
+        let depth = CONVERSION_DEPTH.with(|d| d.get()) + 1;
+        if depth > MAX_CONVERSION_DEPTH.with(|m| m.get()) {
+            DEPTH_EXCEEDED.with(|e| e.set(true));
+            return None;
+        }
+        CONVERSION_DEPTH.with(|d| d.set(depth));
+        Some(DepthGuard)
+    }
+}
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        CONVERSION_DEPTH.with(|d| d.set(d.get().saturating_sub(1)));
+    }
+}
+
+/// Applies a maximum nesting depth to the conversions on this thread until
+/// dropped. Dropping it lifts the limit again, also when a conversion panics,
+/// so the limit never leaks into later calls on the same scheduler thread.
+struct DepthLimit;
+
+impl DepthLimit {
+    fn set(max_depth: u32) -> DepthLimit {
+        CONVERSION_DEPTH.with(|d| d.set(0));
+        DEPTH_EXCEEDED.with(|e| e.set(false));
+        MAX_CONVERSION_DEPTH.with(|m| m.set(max_depth));
+        DepthLimit
+    }
+
+    /// Whether a conversion was cut short by the limit.
+    fn exceeded(&self) -> bool {
+        DEPTH_EXCEEDED.with(|e| e.get())
+    }
+}
+
+impl Drop for DepthLimit {
+    fn drop(&mut self) {
+        MAX_CONVERSION_DEPTH.with(|m| m.set(u32::MAX));
+        DEPTH_EXCEEDED.with(|e| e.set(false));
+        CONVERSION_DEPTH.with(|d| d.set(0));
+    }
+}
+
+/// Deepest nesting of `{}`, `()` and `[]` in a query text, skipping string
+/// literals, IRIs and comments.
+///
+/// The SPARQL parser recurses on each of these, so checking this before
+/// parsing keeps deeply nested input from exhausting the stack.
+fn bracket_depth(sparql: &str) -> u32 {
+    let bytes = sparql.as_bytes();
+    let mut depth: u32 = 0;
+    let mut max_depth: u32 = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' | b'(' | b'[' => {
+                depth += 1;
+                max_depth = max_depth.max(depth);
+            }
+            b'}' | b')' | b']' => depth = depth.saturating_sub(1),
+            b'#' => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'<' => {
+                // An IRI runs to the next `>` without characters IRIs exclude;
+                // otherwise this is the less-than operator
+                let iri_len = bytes[i + 1..]
+                    .iter()
+                    .position(|&b| b == b'>' || b <= b' ' || b"<\"{}|^`\\".contains(&b));
+                if let Some(len) = iri_len.filter(|&len| bytes[i + 1 + len] == b'>') {
+                    i += len + 1;
+                }
+            }
+            quote @ (b'"' | b'\'') => {
+                let long = bytes[i..].starts_with(&[quote; 3]);
+                i += if long { 3 } else { 1 };
+                while i < bytes.len() {
+                    if bytes[i] == b'\\' {
+                        i += 2;
+                        continue;
+                    }
+                    if long && bytes[i..].starts_with(&[quote; 3]) {
+                        i += 2;
+                        break;
+                    }
+                    if !long && (bytes[i] == quote || bytes[i] == b'\n') {
+                        break;
+                    }
+                    i += 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    max_depth
+}
+
+// ===========================================================================
+// Parse Options
+// ===========================================================================
+
+thread_local! {
+    /// Set by `parse_query/3` with `decode_literals: true` so that
+    /// `literal_to_term` emits native values for supported datatypes.
+    static DECODE_LITERALS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Sets `DECODE_LITERALS` until dropped. Dropping it clears the flag again,
+/// also when the conversion panics, so it never leaks into later calls on the
+/// same scheduler thread.
+struct DecodeLiterals;
+
+impl DecodeLiterals {
+    fn set(decode_literals: bool) -> DecodeLiterals {
+        DECODE_LITERALS.with(|d| d.set(decode_literals));
+        DecodeLiterals
+    }
+}
+
+impl Drop for DecodeLiterals {
+    fn drop(&mut self) {
+        DECODE_LITERALS.with(|d| d.set(false));
     }
 }
 
@@ -183,20 +470,76 @@ fn nif_loaded() -> &'static str {
 ///
 /// # Returns
 /// * `{:ok, ast}` on success where ast is the Elixir representation
-/// * `{:error, {:parse_error, message}}` on parse failure
+/// * `{:error, {:parse_error, message, %{line: l, column: c, offset: o}}}` on
+///   a syntax error
+/// * `{:error, {:parse_error, message}}` on failures without a position
 ///
 /// Uses DirtyCpu scheduler as parsing complex queries can take >1ms.
 #[rustler::nif(schedule = "DirtyCpu")]
 fn parse_query<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
-    match Query::parse(sparql, None) {
+    parse_query_term(env, sparql, None)
+}
+
+/// Parses a SPARQL query string, resolving relative IRIs against `base_iri`.
+///
+/// A `BASE` declaration in the query still overrides it.
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+/// * `base_iri` - Absolute IRI used to resolve relative IRIs
+///
+/// # Returns
+/// * Same as `parse_query/1`
+/// * `{:error, {:parse_error, "invalid base IRI"}}` if `base_iri` is not an absolute IRI
+#[rustler::nif(name = "parse_query", schedule = "DirtyCpu")]
+fn parse_query_with_base<'a>(env: Env<'a>, sparql: &str, base_iri: &str) -> NifResult<Term<'a>> {
+    if Iri::parse(base_iri).is_err() {
+        return Ok((atoms::error(), (atoms::parse_error(), "invalid base IRI")).encode(env));
+    }
+    parse_query_term(env, sparql, Some(base_iri))
+}
+
+/// Parses a SPARQL query string with an optional base IRI and options.
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+/// * `base_iri` - Absolute IRI used to resolve relative IRIs, or `nil`
+/// * `opts` - Keyword list of options:
+///   * `decode_literals` - encode literals of the datatypes `decode_literal`
+///     supports as `{:literal, :typed_value, native, datatype}`. Literals
+///     with an invalid lexical form keep the `:typed` encoding. Default `false`
+///
+/// # Returns
+/// * Same as `parse_query/2`
+#[rustler::nif(name = "parse_query", schedule = "DirtyCpu")]
+fn parse_query_with_opts<'a>(
+    env: Env<'a>,
+    sparql: &str,
+    base_iri: Option<&str>,
+    opts: Vec<(rustler::Atom, bool)>,
+) -> NifResult<Term<'a>> {
+    if base_iri.is_some_and(|iri| Iri::parse(iri).is_err()) {
+        return Ok((atoms::error(), (atoms::parse_error(), "invalid base IRI")).encode(env));
+    }
+    let decode_literals = opts
+        .iter()
+        .any(|(key, value)| *key == atoms::decode_literals() && *value);
+
+    let _decode = DecodeLiterals::set(decode_literals);
+    parse_query_term(env, sparql, base_iri)
+}
+
+fn parse_query_term<'a>(env: Env<'a>, sparql: &str, base_iri: Option<&str>) -> NifResult<Term<'a>> {
+    match Query::parse(sparql, base_iri) {
         Ok(query) => {
             let ast = query_to_term(env, &query);
             Ok((atoms::ok(), ast).encode(env))
         }
-        Err(e) => {
-            let error_msg = e.to_string();
-            Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env))
-        }
+        Err(e) => Ok((
+            atoms::error(),
+            parse_error_to_term(env, sparql, e.to_string())?,
+        )
+            .encode(env)),
     }
 }
 
@@ -207,106 +550,625 @@ fn parse_query<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
 ///
 /// # Returns
 /// * `{:ok, ast}` on success where ast is the Elixir representation
-/// * `{:error, {:parse_error, message}}` on parse failure
+/// * `{:error, {:parse_error, message, %{line: l, column: c, offset: o}}}` on
+///   a syntax error
+/// * `{:error, {:parse_error, message}}` on failures without a position
 ///
 /// Uses DirtyCpu scheduler as parsing complex updates can take >1ms.
 #[rustler::nif(schedule = "DirtyCpu")]
 fn parse_update<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
-    match Update::parse(sparql, None) {
+    parse_update_term(env, sparql, None)
+}
+
+/// Parses a SPARQL UPDATE string, resolving relative IRIs against `base_iri`.
+///
+/// A `BASE` declaration in the update still overrides it.
+///
+/// # Arguments
+/// * `sparql` - The SPARQL UPDATE string to parse
+/// * `base_iri` - Absolute IRI used to resolve relative IRIs
+///
+/// # Returns
+/// * Same as `parse_update/1`
+/// * `{:error, {:parse_error, "invalid base IRI"}}` if `base_iri` is not an absolute IRI
+#[rustler::nif(name = "parse_update", schedule = "DirtyCpu")]
+fn parse_update_with_base<'a>(env: Env<'a>, sparql: &str, base_iri: &str) -> NifResult<Term<'a>> {
+    if Iri::parse(base_iri).is_err() {
+        return Ok((atoms::error(), (atoms::parse_error(), "invalid base IRI")).encode(env));
+    }
+    parse_update_term(env, sparql, Some(base_iri))
+}
+
+fn parse_update_term<'a>(
+    env: Env<'a>,
+    sparql: &str,
+    base_iri: Option<&str>,
+) -> NifResult<Term<'a>> {
+    match Update::parse(sparql, base_iri) {
         Ok(update) => {
             let ast = update_to_term(env, &update);
             Ok((atoms::ok(), ast).encode(env))
         }
-        Err(e) => {
-            let error_msg = e.to_string();
-            Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env))
-        }
+        Err(e) => Ok((
+            atoms::error(),
+            parse_error_to_term(env, sparql, e.to_string())?,
+        )
+            .encode(env)),
     }
 }
 
-/// Converts a spargebra Query to an Elixir term.
-fn query_to_term<'a>(env: Env<'a>, query: &Query) -> Term<'a> {
-    match query {
-        Query::Select {
-            dataset,
-            pattern,
-            base_iri,
-        } => {
-            let pattern_term = graph_pattern_to_term(env, pattern);
-            let dataset_term = option_to_term(env, dataset, |e, d| query_dataset_to_term(e, d));
-            let base_term = option_iri_to_term(env, base_iri);
-
-            (
-                atoms::select(),
-                vec![
-                    ("pattern", pattern_term),
-                    ("dataset", dataset_term),
-                    ("base_iri", base_term),
+/// Builds the `{:parse_error, ...}` reason for a failed `parse_query` or
+/// `parse_update`.
+///
+/// spargebra does not expose the error location, but syntax errors are
+/// rendered as `"error at LINE:COLUMN: expected ..."`. When that prefix is
+/// present the reason carries a `%{line, column, offset}` map, with `offset`
+/// the byte position in `sparql`; otherwise (e.g. an invalid base IRI) it is
+/// the plain `{:parse_error, message}` pair.
+fn parse_error_to_term<'a>(env: Env<'a>, sparql: &str, message: String) -> NifResult<Term<'a>> {
+    match parse_error_position(sparql, &message) {
+        Some((line, column, offset)) => {
+            let position = Term::map_from_pairs(
+                env,
+                &[
+                    (atoms::line().encode(env), line.encode(env)),
+                    (atoms::column().encode(env), column.encode(env)),
+                    (atoms::offset().encode(env), offset.encode(env)),
                 ],
-            ).encode(env)
+            )?;
+            Ok((atoms::parse_error(), message, position).encode(env))
         }
-        Query::Construct {
-            template,
-            dataset,
-            pattern,
-            base_iri,
-        } => {
-            let template_term = construct_template_to_term(env, template);
-            let pattern_term = graph_pattern_to_term(env, pattern);
-            let dataset_term = option_to_term(env, dataset, |e, d| query_dataset_to_term(e, d));
-            let base_term = option_iri_to_term(env, base_iri);
+        None => Ok((atoms::parse_error(), message).encode(env)),
+    }
+}
 
-            (
-                atoms::construct(),
-                vec![
-                    ("template", template_term),
-                    ("pattern", pattern_term),
-                    ("dataset", dataset_term),
-                    ("base_iri", base_term),
-                ],
-            ).encode(env)
+/// Extracts the 1-based line and column from a peg error message and maps
+/// them back to a byte offset in `sparql`.
+///
+/// Columns count characters, not bytes, so the offset walks the line's
+/// chars. Returns `None` if the message has no position or it falls outside
+/// the input.
+fn parse_error_position(sparql: &str, message: &str) -> Option<(usize, usize, usize)> {
+    let rest = message.strip_prefix("error at ")?;
+    let (position, _) = rest.split_once(": ")?;
+    let (line, column) = position.split_once(':')?;
+    let line: usize = line.parse().ok()?;
+    let column: usize = column.parse().ok()?;
+    if line == 0 || column == 0 {
+        return None;
+    }
+
+    let line_start = if line == 1 {
+        0
+    } else {
+        sparql.match_indices('\n').nth(line - 2)?.0 + 1
+    };
+    let line_text = &sparql[line_start..];
+    let offset = match line_text.char_indices().nth(column - 1) {
+        Some((index, _)) => line_start + index,
+        // peg reports end-of-input one column past the last char
+        None if line_text.chars().count() == column - 1 => sparql.len(),
+        None => return None,
+    };
+
+    Some((line, column, offset))
+}
+
+/// Parses a SPARQL ASK query into a compact form for boolean evaluation.
+///
+/// ASK queries have no projection or solution modifiers, so instead of the
+/// full `{:ask, props}` wrapper this returns only the pattern and dataset.
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+///
+/// # Returns
+/// * `{:ok, {pattern, dataset}}` on success (`dataset` is `nil` when absent)
+/// * `{:error, {:wrong_operation, form}}` if the query is SELECT, CONSTRUCT or DESCRIBE
+/// * `{:error, {:parse_error, message}}` on parse failure
+///
+/// Uses DirtyCpu scheduler as parsing complex queries can take >1ms.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_ask<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
+    let query = match Query::parse(sparql, None) {
+        Ok(query) => query,
+        Err(e) => {
+            let error_msg = e.to_string();
+            return Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env));
         }
+    };
+
+    match &query {
         Query::Ask {
-            dataset,
-            pattern,
-            base_iri,
+            dataset, pattern, ..
         } => {
             let pattern_term = graph_pattern_to_term(env, pattern);
             let dataset_term = option_to_term(env, dataset, |e, d| query_dataset_to_term(e, d));
-            let base_term = option_iri_to_term(env, base_iri);
+            Ok((atoms::ok(), (pattern_term, dataset_term)).encode(env))
+        }
+        Query::Select { .. } => Ok(wrong_operation(env, atoms::select())),
+        Query::Construct { .. } => Ok(wrong_operation(env, atoms::construct())),
+        Query::Describe { .. } => Ok(wrong_operation(env, atoms::describe())),
+    }
+}
 
-            (
-                atoms::ask(),
-                vec![
-                    ("pattern", pattern_term),
-                    ("dataset", dataset_term),
-                    ("base_iri", base_term),
-                ],
-            ).encode(env)
+/// Builds `{:error, {:wrong_operation, form}}` for form-specific entry points.
+fn wrong_operation<'a>(env: Env<'a>, form: rustler::Atom) -> Term<'a> {
+    (atoms::error(), (atoms::wrong_operation(), form)).encode(env)
+}
+
+/// Detects the form of a SPARQL request without encoding its AST.
+///
+/// Tries the query grammar first and falls back to the update grammar. When
+/// both fail, the error from the parser that got further into the input is
+/// returned, so a broken update doesn't report "expected SELECT".
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query or update string
+///
+/// # Returns
+/// * `{:ok, :select | :construct | :ask | :describe | :update}` on success
+/// * `{:error, {:parse_error, message}}` if neither grammar accepts it
+///
+/// Uses DirtyCpu scheduler as parsing complex queries can take >1ms.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn query_form<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
+    let query_error = match Query::parse(sparql, None) {
+        Ok(query) => return Ok((atoms::ok(), query_form_atom(&query)).encode(env)),
+        Err(e) => e.to_string(),
+    };
+    let update_error = match Update::parse(sparql, None) {
+        Ok(_) => return Ok((atoms::ok(), atoms::update()).encode(env)),
+        Err(e) => e.to_string(),
+    };
+
+    let reached =
+        |message: &str| parse_error_position(sparql, message).map(|(_, _, offset)| offset);
+    let error_msg = if reached(&update_error) > reached(&query_error) {
+        update_error
+    } else {
+        query_error
+    };
+    Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env))
+}
+
+/// Returns the form atom of a parsed query.
+fn query_form_atom(query: &Query) -> rustler::Atom {
+    match query {
+        Query::Select { .. } => atoms::select(),
+        Query::Construct { .. } => atoms::construct(),
+        Query::Ask { .. } => atoms::ask(),
+        Query::Describe { .. } => atoms::describe(),
+    }
+}
+
+/// Parses a SPARQL query and reports parse/encode timing and AST size.
+///
+/// Produces the same AST as `parse_query`, plus a stats map for profiling
+/// queries that are slow to parse or to convert into Elixir terms.
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+///
+/// # Returns
+/// * `{:ok, ast, %{parse_micros: n, encode_micros: n, node_count: n}}` on success
+/// * `{:error, {:parse_error, message}}` on parse failure
+///
+/// Uses DirtyCpu scheduler as parsing complex queries can take >1ms.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_query_profiled<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
+    let parse_started = Instant::now();
+    let query = match Query::parse(sparql, None) {
+        Ok(query) => query,
+        Err(e) => {
+            let error_msg = e.to_string();
+            return Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env));
         }
-        Query::Describe {
-            dataset,
-            pattern,
-            base_iri,
-        } => {
-            let pattern_term = graph_pattern_to_term(env, pattern);
-            let dataset_term = option_to_term(env, dataset, |e, d| query_dataset_to_term(e, d));
-            let base_term = option_iri_to_term(env, base_iri);
+    };
+    let parse_micros = parse_started.elapsed().as_micros() as u64;
+
+    let counter = CountNodes::start();
+    let encode_started = Instant::now();
+    let ast = query_to_term(env, &query);
+    let encode_micros = encode_started.elapsed().as_micros() as u64;
+    let node_count = counter.count();
+    drop(counter);
 
+    let stats = Term::map_from_pairs(
+        env,
+        &[
+            (atoms::parse_micros().encode(env), parse_micros.encode(env)),
             (
-                atoms::describe(),
-                vec![
-                    ("pattern", pattern_term),
-                    ("dataset", dataset_term),
-                    ("base_iri", base_term),
-                ],
-            ).encode(env)
+                atoms::encode_micros().encode(env),
+                encode_micros.encode(env),
+            ),
+            (atoms::node_count().encode(env), node_count.encode(env)),
+        ],
+    )?;
+
+    Ok((atoms::ok(), ast, stats).encode(env))
+}
+
+/// Parses a SPARQL query with size and nesting limits.
+///
+/// Guards a public endpoint against resource-exhaustion queries: the input is
+/// rejected before parsing when it exceeds `max_bytes` or when braces,
+/// parentheses and brackets nest deeper than `max_depth`, and AST conversion
+/// is aborted when graph patterns or expressions nest deeper than `max_depth`.
+/// Either key may be omitted to leave that dimension unlimited.
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+/// * `limits` - Map with `:max_bytes` and/or `:max_depth`
+///
+/// # Returns
+/// * `{:ok, ast}` on success
+/// * `{:error, {:limit_exceeded, :size}}` if the query is larger than `max_bytes`
+/// * `{:error, {:limit_exceeded, :depth}}` if nesting exceeds `max_depth`
+/// * `{:error, {:parse_error, message}}` on parse failure
+///
+/// Uses DirtyCpu scheduler as parsing complex queries can take >1ms.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_query_limited<'a>(env: Env<'a>, sparql: &str, limits: Term<'a>) -> NifResult<Term<'a>> {
+    let max_bytes: Option<usize> = match limits.map_get(atoms::max_bytes()) {
+        Ok(value) => Some(value.decode()?),
+        Err(_) => None,
+    };
+    let max_depth: u32 = match limits.map_get(atoms::max_depth()) {
+        Ok(value) => value.decode()?,
+        Err(_) => u32::MAX,
+    };
+
+    if max_bytes.is_some_and(|max| sparql.len() > max) {
+        return Ok((atoms::error(), (atoms::limit_exceeded(), atoms::size())).encode(env));
+    }
+    // The parser itself recurses on nesting, so deep input is rejected unparsed
+    if bracket_depth(sparql) > max_depth {
+        return Ok((atoms::error(), (atoms::limit_exceeded(), atoms::depth())).encode(env));
+    }
+
+    let query = match Query::parse(sparql, None) {
+        Ok(query) => query,
+        Err(e) => {
+            let error_msg = e.to_string();
+            return Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env));
         }
+    };
+
+    let limit = DepthLimit::set(max_depth);
+    let ast = query_to_term(env, &query);
+    if limit.exceeded() {
+        return Ok((atoms::error(), (atoms::limit_exceeded(), atoms::depth())).encode(env));
     }
+
+    Ok((atoms::ok(), ast).encode(env))
 }
 
-/// Converts a GraphPattern to an Elixir term.
+/// Parses a SPARQL query, rejecting federated queries.
+///
+/// Any `SERVICE` clause makes the query fail, wherever it appears: in the
+/// main pattern, nested in OPTIONAL/UNION/MINUS/subqueries, or inside a
+/// `FILTER EXISTS` / `NOT EXISTS` expression. `SERVICE SILENT` is rejected
+/// too. Queries without `SERVICE` return the same AST as `parse_query`.
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+///
+/// # Returns
+/// * `{:ok, ast}` on success where ast is the Elixir representation
+/// * `{:error, :service_not_allowed}` if the query contains `SERVICE`
+/// * `{:error, {:parse_error, message}}` on parse failure
+///
+/// Uses DirtyCpu scheduler as parsing complex queries can take >1ms.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_query_no_service<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
+    let query = match Query::parse(sparql, None) {
+        Ok(query) => query,
+        Err(e) => {
+            let error_msg = e.to_string();
+            return Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env));
+        }
+    };
+
+    if pattern_uses_service(query_pattern(&query)) {
+        return Ok((atoms::error(), atoms::service_not_allowed()).encode(env));
+    }
+
+    let ast = query_to_term(env, &query);
+    Ok((atoms::ok(), ast).encode(env))
+}
+
+/// Whether a graph pattern, or any expression inside it, contains `SERVICE`.
+fn pattern_uses_service(pattern: &GraphPattern) -> bool {
+    let mut found = false;
+    visit_patterns(pattern, &mut |p| {
+        found |= matches!(p, GraphPattern::Service { .. })
+    });
+    found
+}
+
+/// Calls `f` on a pattern and every pattern nested in it, in pre-order,
+/// including patterns inside `EXISTS` expressions.
+fn visit_patterns<'p>(pattern: &'p GraphPattern, f: &mut dyn FnMut(&'p GraphPattern)) {
+    f(pattern);
+    match pattern {
+        GraphPattern::Bgp { .. } | GraphPattern::Path { .. } | GraphPattern::Values { .. } => {}
+        GraphPattern::Join { left, right }
+        | GraphPattern::Union { left, right }
+        | GraphPattern::Minus { left, right } => {
+            visit_patterns(left, f);
+            visit_patterns(right, f);
+        }
+        GraphPattern::LeftJoin {
+            left,
+            right,
+            expression,
+        } => {
+            visit_patterns(left, f);
+            visit_patterns(right, f);
+            if let Some(expr) = expression {
+                visit_expression_patterns(expr, f);
+            }
+        }
+        GraphPattern::Filter { expr, inner } => {
+            visit_expression_patterns(expr, f);
+            visit_patterns(inner, f);
+        }
+        GraphPattern::Extend {
+            inner, expression, ..
+        } => {
+            visit_patterns(inner, f);
+            visit_expression_patterns(expression, f);
+        }
+        GraphPattern::OrderBy { inner, expression } => {
+            visit_patterns(inner, f);
+            for order in expression {
+                match order {
+                    OrderExpression::Asc(expr) | OrderExpression::Desc(expr) => {
+                        visit_expression_patterns(expr, f)
+                    }
+                }
+            }
+        }
+        GraphPattern::Group {
+            inner, aggregates, ..
+        } => {
+            visit_patterns(inner, f);
+            for (_, aggregate) in aggregates {
+                if let AggregateExpression::FunctionCall { expr, .. } = aggregate {
+                    visit_expression_patterns(expr, f);
+                }
+            }
+        }
+        GraphPattern::Graph { inner, .. }
+        | GraphPattern::Service { inner, .. }
+        | GraphPattern::Project { inner, .. }
+        | GraphPattern::Distinct { inner }
+        | GraphPattern::Reduced { inner }
+        | GraphPattern::Slice { inner, .. } => visit_patterns(inner, f),
+    }
+}
+
+/// Calls `visit_patterns` on every `EXISTS` pattern inside an expression.
+fn visit_expression_patterns<'p>(expr: &'p Expression, f: &mut dyn FnMut(&'p GraphPattern)) {
+    match expr {
+        Expression::NamedNode(_)
+        | Expression::Literal(_)
+        | Expression::Variable(_)
+        | Expression::Bound(_) => {}
+        Expression::Or(a, b)
+        | Expression::And(a, b)
+        | Expression::Equal(a, b)
+        | Expression::SameTerm(a, b)
+        | Expression::Greater(a, b)
+        | Expression::GreaterOrEqual(a, b)
+        | Expression::Less(a, b)
+        | Expression::LessOrEqual(a, b)
+        | Expression::Add(a, b)
+        | Expression::Subtract(a, b)
+        | Expression::Multiply(a, b)
+        | Expression::Divide(a, b) => {
+            visit_expression_patterns(a, f);
+            visit_expression_patterns(b, f);
+        }
+        Expression::UnaryPlus(inner) | Expression::UnaryMinus(inner) | Expression::Not(inner) => {
+            visit_expression_patterns(inner, f)
+        }
+        Expression::In(needle, haystack) => {
+            visit_expression_patterns(needle, f);
+            for item in haystack {
+                visit_expression_patterns(item, f);
+            }
+        }
+        Expression::If(cond, then, otherwise) => {
+            visit_expression_patterns(cond, f);
+            visit_expression_patterns(then, f);
+            visit_expression_patterns(otherwise, f);
+        }
+        Expression::Coalesce(args) | Expression::FunctionCall(_, args) => {
+            for arg in args {
+                visit_expression_patterns(arg, f);
+            }
+        }
+        Expression::Exists(pattern) => visit_patterns(pattern, f),
+    }
+}
+
+/// Parses a SPARQL query and rejects RDF term-position violations that the
+/// SPARQL grammar allows.
+///
+/// spargebra already rejects literals, blank nodes and variables where the
+/// grammar forbids them: predicates must be IRIs or variables, and `GRAPH`
+/// names must be IRIs or variables. The grammar does, however, allow any
+/// term as a subject, so these queries parse but can never match or produce
+/// valid RDF. This adds exactly two checks:
+/// * `{:literal_subject, literal}` - a literal subject in a triple pattern
+///   or property path pattern in WHERE (including OPTIONAL, UNION, MINUS,
+///   subqueries and `EXISTS`)
+/// * `{:literal_template_subject, literal}` - a literal subject in a
+///   CONSTRUCT template, which would produce an invalid triple
+///
+/// The first violation found is reported, template first, then WHERE in
+/// pattern order.
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+///
+/// # Returns
+/// * `{:ok, ast}` on success where ast is the Elixir representation
+/// * `{:error, {:term_position, detail}}` on the first violation
+/// * `{:error, {:parse_error, message}}` on parse failure
+///
+/// Uses DirtyCpu scheduler as parsing complex queries can take >1ms.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_query_strict<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
+    let query = match Query::parse(sparql, None) {
+        Ok(query) => query,
+        Err(e) => {
+            let error_msg = e.to_string();
+            return Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env));
+        }
+    };
+
+    if let Some(detail) = term_position_violation(env, &query) {
+        return Ok((atoms::error(), (atoms::term_position(), detail)).encode(env));
+    }
+
+    let ast = query_to_term(env, &query);
+    Ok((atoms::ok(), ast).encode(env))
+}
+
+/// Returns the first literal-subject violation in a query, if any.
+fn term_position_violation<'a>(env: Env<'a>, query: &Query) -> Option<Term<'a>> {
+    if let Query::Construct { template, .. } = query {
+        for tp in template {
+            if let TermPattern::Literal(literal) = &tp.subject {
+                return Some(
+                    (
+                        atoms::literal_template_subject(),
+                        literal_to_term(env, literal),
+                    )
+                        .encode(env),
+                );
+            }
+        }
+    }
+
+    let mut violation: Option<&Literal> = None;
+    visit_patterns(query_pattern(query), &mut |pattern| {
+        if violation.is_some() {
+            return;
+        }
+        match pattern {
+            GraphPattern::Bgp { patterns } => {
+                violation = patterns.iter().find_map(|tp| match &tp.subject {
+                    TermPattern::Literal(literal) => Some(literal),
+                    _ => None,
+                });
+            }
+            GraphPattern::Path {
+                subject: TermPattern::Literal(literal),
+                ..
+            } => violation = Some(literal),
+            _ => {}
+        }
+    });
+    violation.map(|literal| (atoms::literal_subject(), literal_to_term(env, literal)).encode(env))
+}
+
+/// Converts a spargebra Query to an Elixir term.
+fn query_to_term<'a>(env: Env<'a>, query: &Query) -> Term<'a> {
+    count_node();
+    match query {
+        Query::Select {
+            dataset,
+            pattern,
+            base_iri,
+        } => {
+            let pattern_term = graph_pattern_to_term(env, pattern);
+            let dataset_term = option_to_term(env, dataset, |e, d| query_dataset_to_term(e, d));
+            let base_term = option_iri_to_term(env, base_iri);
+
+            (
+                atoms::select(),
+                vec![
+                    ("pattern", pattern_term),
+                    ("dataset", dataset_term),
+                    ("base_iri", base_term),
+                ],
+            )
+                .encode(env)
+        }
+        Query::Construct {
+            template,
+            dataset,
+            pattern,
+            base_iri,
+        } => {
+            let template_term = construct_template_to_term(env, template);
+            let pattern_term = graph_pattern_to_term(env, pattern);
+            let dataset_term = option_to_term(env, dataset, |e, d| query_dataset_to_term(e, d));
+            let base_term = option_iri_to_term(env, base_iri);
+
+            (
+                atoms::construct(),
+                vec![
+                    ("template", template_term),
+                    ("pattern", pattern_term),
+                    ("dataset", dataset_term),
+                    ("base_iri", base_term),
+                ],
+            )
+                .encode(env)
+        }
+        Query::Ask {
+            dataset,
+            pattern,
+            base_iri,
+        } => {
+            let pattern_term = graph_pattern_to_term(env, pattern);
+            let dataset_term = option_to_term(env, dataset, |e, d| query_dataset_to_term(e, d));
+            let base_term = option_iri_to_term(env, base_iri);
+
+            (
+                atoms::ask(),
+                vec![
+                    ("pattern", pattern_term),
+                    ("dataset", dataset_term),
+                    ("base_iri", base_term),
+                ],
+            )
+                .encode(env)
+        }
+        Query::Describe {
+            dataset,
+            pattern,
+            base_iri,
+        } => {
+            let pattern_term = graph_pattern_to_term(env, pattern);
+            let dataset_term = option_to_term(env, dataset, |e, d| query_dataset_to_term(e, d));
+            let base_term = option_iri_to_term(env, base_iri);
+
+            (
+                atoms::describe(),
+                vec![
+                    ("pattern", pattern_term),
+                    ("dataset", dataset_term),
+                    ("base_iri", base_term),
+                ],
+            )
+                .encode(env)
+        }
+    }
+}
+
+/// Converts a GraphPattern to an Elixir term.
 fn graph_pattern_to_term<'a>(env: Env<'a>, pattern: &GraphPattern) -> Term<'a> {
+    count_node();
+    let _depth = match DepthGuard::enter() {
+        Some(guard) => guard,
+        None => return rustler::types::atom::nil().encode(env),
+    };
     match pattern {
         GraphPattern::Bgp { patterns } => {
             let triple_terms: Vec<Term<'a>> = patterns
@@ -385,10 +1247,8 @@ fn graph_pattern_to_term<'a>(env: Env<'a>, pattern: &GraphPattern) -> Term<'a> {
             aggregates,
         } => {
             let inner_term = graph_pattern_to_term(env, inner);
-            let vars_term: Vec<Term<'a>> = variables
-                .iter()
-                .map(|v| variable_to_term(env, v))
-                .collect();
+            let vars_term: Vec<Term<'a>> =
+                variables.iter().map(|v| variable_to_term(env, v)).collect();
             let aggs_term: Vec<Term<'a>> = aggregates
                 .iter()
                 .map(|(var, agg)| {
@@ -403,10 +1263,8 @@ fn graph_pattern_to_term<'a>(env: Env<'a>, pattern: &GraphPattern) -> Term<'a> {
             variables,
             bindings,
         } => {
-            let vars_term: Vec<Term<'a>> = variables
-                .iter()
-                .map(|v| variable_to_term(env, v))
-                .collect();
+            let vars_term: Vec<Term<'a>> =
+                variables.iter().map(|v| variable_to_term(env, v)).collect();
             let bindings_term: Vec<Term<'a>> = bindings
                 .iter()
                 .map(|row| {
@@ -431,10 +1289,8 @@ fn graph_pattern_to_term<'a>(env: Env<'a>, pattern: &GraphPattern) -> Term<'a> {
         }
         GraphPattern::Project { inner, variables } => {
             let inner_term = graph_pattern_to_term(env, inner);
-            let vars_term: Vec<Term<'a>> = variables
-                .iter()
-                .map(|v| variable_to_term(env, v))
-                .collect();
+            let vars_term: Vec<Term<'a>> =
+                variables.iter().map(|v| variable_to_term(env, v)).collect();
             (atoms::project(), inner_term, vars_term).encode(env)
         }
         GraphPattern::Distinct { inner } => {
@@ -462,6 +1318,7 @@ fn graph_pattern_to_term<'a>(env: Env<'a>, pattern: &GraphPattern) -> Term<'a> {
 
 /// Converts a TriplePattern to an Elixir term.
 fn triple_pattern_to_term<'a>(env: Env<'a>, tp: &TriplePattern) -> Term<'a> {
+    count_node();
     let subject = term_pattern_to_term(env, &tp.subject);
     let predicate = named_node_pattern_to_term(env, &tp.predicate);
     let object = term_pattern_to_term(env, &tp.object);
@@ -470,16 +1327,29 @@ fn triple_pattern_to_term<'a>(env: Env<'a>, tp: &TriplePattern) -> Term<'a> {
 
 /// Converts a TermPattern (subject/object position) to an Elixir term.
 fn term_pattern_to_term<'a>(env: Env<'a>, tp: &TermPattern) -> Term<'a> {
+    count_node();
     match tp {
         TermPattern::NamedNode(nn) => named_node_to_term(env, nn),
         TermPattern::BlankNode(bn) => blank_node_to_term(env, bn),
         TermPattern::Literal(lit) => literal_to_term(env, lit),
         TermPattern::Variable(var) => variable_to_term(env, var),
+        TermPattern::Triple(tp) => quoted_triple_pattern_to_term(env, tp),
     }
 }
 
+/// Converts a quoted triple pattern (`<< s p o >>`, SPARQL-star) to
+/// `{:triple_term, subject, predicate, object}`. Quoted triples nest.
+fn quoted_triple_pattern_to_term<'a>(env: Env<'a>, tp: &TriplePattern) -> Term<'a> {
+    count_node();
+    let subject = term_pattern_to_term(env, &tp.subject);
+    let predicate = named_node_pattern_to_term(env, &tp.predicate);
+    let object = term_pattern_to_term(env, &tp.object);
+    (atoms::triple_term(), subject, predicate, object).encode(env)
+}
+
 /// Converts a NamedNodePattern (predicate position) to an Elixir term.
 fn named_node_pattern_to_term<'a>(env: Env<'a>, nnp: &NamedNodePattern) -> Term<'a> {
+    count_node();
     match nnp {
         NamedNodePattern::NamedNode(nn) => named_node_to_term(env, nn),
         NamedNodePattern::Variable(var) => variable_to_term(env, var),
@@ -488,24 +1358,43 @@ fn named_node_pattern_to_term<'a>(env: Env<'a>, nnp: &NamedNodePattern) -> Term<
 
 /// Converts a GroundTerm to an Elixir term.
 fn ground_term_to_term<'a>(env: Env<'a>, gt: &GroundTerm) -> Term<'a> {
+    count_node();
     match gt {
         GroundTerm::NamedNode(nn) => named_node_to_term(env, nn),
         GroundTerm::Literal(lit) => literal_to_term(env, lit),
+        GroundTerm::Triple(triple) => ground_triple_to_term(env, triple),
     }
 }
 
+/// Converts a quoted triple without variables to `{:triple_term, s, p, o}`.
+fn ground_triple_to_term<'a>(env: Env<'a>, triple: &GroundTriple) -> Term<'a> {
+    count_node();
+    let subject = ground_subject_to_term(env, &triple.subject);
+    let predicate = named_node_to_term(env, &triple.predicate);
+    let object = ground_term_to_term(env, &triple.object);
+    (atoms::triple_term(), subject, predicate, object).encode(env)
+}
+
 /// Converts a NamedNode (IRI) to an Elixir term.
 fn named_node_to_term<'a>(env: Env<'a>, nn: &NamedNode) -> Term<'a> {
+    count_node();
     (atoms::named_node(), nn.as_str()).encode(env)
 }
 
 /// Converts a BlankNode to an Elixir term.
 fn blank_node_to_term<'a>(env: Env<'a>, bn: &BlankNode) -> Term<'a> {
+    count_node();
     (atoms::blank_node(), bn.as_str()).encode(env)
 }
 
 /// Converts a Literal to an Elixir term.
+///
+/// RDF 1.2 base directions (`"text"@en--ltr`) are not encoded: spargebra 0.3
+/// (oxrdf 0.2) has no directional literals and rejects such tags at parse time,
+/// so language-tagged literals stay `{:literal, :language_tagged, value, lang}`.
+/// Extend the tuple with a direction once the parser dependency supports it.
 fn literal_to_term<'a>(env: Env<'a>, lit: &Literal) -> Term<'a> {
+    count_node();
     let value = lit.value();
 
     if let Some(lang) = lit.language() {
@@ -518,6 +1407,12 @@ fn literal_to_term<'a>(env: Env<'a>, lit: &Literal) -> Term<'a> {
         // Check if it's a simple literal (xsd:string)
         if datatype_str == "http://www.w3.org/2001/XMLSchema#string" {
             (atoms::literal(), atoms::simple(), value).encode(env)
+        } else if let Some(native) = DECODE_LITERALS
+            .with(|d| d.get())
+            .then(|| native_literal_value(env, lit))
+            .and_then(NativeValue::decoded)
+        {
+            (atoms::literal(), atoms::typed_value(), native, datatype_str).encode(env)
         } else {
             // Typed literal
             (atoms::literal(), atoms::typed(), value, datatype_str).encode(env)
@@ -527,11 +1422,17 @@ fn literal_to_term<'a>(env: Env<'a>, lit: &Literal) -> Term<'a> {
 
 /// Converts a Variable to an Elixir term.
 fn variable_to_term<'a>(env: Env<'a>, var: &Variable) -> Term<'a> {
+    count_node();
     (atoms::variable(), var.as_str()).encode(env)
 }
 
 /// Converts an Expression to an Elixir term.
 fn expression_to_term<'a>(env: Env<'a>, expr: &Expression) -> Term<'a> {
+    count_node();
+    let _depth = match DepthGuard::enter() {
+        Some(guard) => guard,
+        None => return rustler::types::atom::nil().encode(env),
+    };
     match expr {
         Expression::NamedNode(nn) => named_node_to_term(env, nn),
         Expression::Literal(lit) => literal_to_term(env, lit),
@@ -542,9 +1443,13 @@ fn expression_to_term<'a>(env: Env<'a>, expr: &Expression) -> Term<'a> {
         Expression::Equal(left, right) => binary_expr!(env, atoms::equal(), left, right),
         Expression::SameTerm(left, right) => binary_expr!(env, atoms::same_term(), left, right),
         Expression::Greater(left, right) => binary_expr!(env, atoms::greater(), left, right),
-        Expression::GreaterOrEqual(left, right) => binary_expr!(env, atoms::greater_or_equal(), left, right),
+        Expression::GreaterOrEqual(left, right) => {
+            binary_expr!(env, atoms::greater_or_equal(), left, right)
+        }
         Expression::Less(left, right) => binary_expr!(env, atoms::less(), left, right),
-        Expression::LessOrEqual(left, right) => binary_expr!(env, atoms::less_or_equal(), left, right),
+        Expression::LessOrEqual(left, right) => {
+            binary_expr!(env, atoms::less_or_equal(), left, right)
+        }
         Expression::Add(left, right) => binary_expr!(env, atoms::add(), left, right),
         Expression::Subtract(left, right) => binary_expr!(env, atoms::subtract(), left, right),
         Expression::Multiply(left, right) => binary_expr!(env, atoms::multiply(), left, right),
@@ -564,18 +1469,14 @@ fn expression_to_term<'a>(env: Env<'a>, expr: &Expression) -> Term<'a> {
             (atoms::if_expr(), cond_term, then_term, else_term).encode(env)
         }
         Expression::Coalesce(exprs) => {
-            let expr_terms: Vec<Term<'a>> = exprs
-                .iter()
-                .map(|e| expression_to_term(env, e))
-                .collect();
+            let expr_terms: Vec<Term<'a>> =
+                exprs.iter().map(|e| expression_to_term(env, e)).collect();
             (atoms::coalesce(), expr_terms).encode(env)
         }
         Expression::FunctionCall(func, args) => {
             let func_term = function_to_term(env, func);
-            let arg_terms: Vec<Term<'a>> = args
-                .iter()
-                .map(|a| expression_to_term(env, a))
-                .collect();
+            let arg_terms: Vec<Term<'a>> =
+                args.iter().map(|a| expression_to_term(env, a)).collect();
             (atoms::function_call(), func_term, arg_terms).encode(env)
         }
         Expression::Exists(pattern) => {
@@ -584,10 +1485,8 @@ fn expression_to_term<'a>(env: Env<'a>, expr: &Expression) -> Term<'a> {
         }
         Expression::In(expr, list) => {
             let expr_term = expression_to_term(env, expr);
-            let list_terms: Vec<Term<'a>> = list
-                .iter()
-                .map(|e| expression_to_term(env, e))
-                .collect();
+            let list_terms: Vec<Term<'a>> =
+                list.iter().map(|e| expression_to_term(env, e)).collect();
             (atoms::in_expr(), expr_term, list_terms).encode(env)
         }
     }
@@ -595,6 +1494,7 @@ fn expression_to_term<'a>(env: Env<'a>, expr: &Expression) -> Term<'a> {
 
 /// Converts a Function to an Elixir term.
 fn function_to_term<'a>(env: Env<'a>, func: &Function) -> Term<'a> {
+    count_node();
     let func_name = match func {
         Function::Str => "STR",
         Function::Lang => "LANG",
@@ -642,6 +1542,11 @@ fn function_to_term<'a>(env: Env<'a>, func: &Function) -> Term<'a> {
         Function::IsLiteral => "ISLITERAL",
         Function::IsNumeric => "ISNUMERIC",
         Function::Regex => "REGEX",
+        Function::Triple => "TRIPLE",
+        Function::Subject => "SUBJECT",
+        Function::Predicate => "PREDICATE",
+        Function::Object => "OBJECT",
+        Function::IsTriple => "ISTRIPLE",
         Function::Custom(iri) => {
             return (atoms::custom(), iri.as_str()).encode(env);
         }
@@ -651,12 +1556,17 @@ fn function_to_term<'a>(env: Env<'a>, func: &Function) -> Term<'a> {
 
 /// Converts an AggregateExpression to an Elixir term.
 fn aggregate_expression_to_term<'a>(env: Env<'a>, agg: &AggregateExpression) -> Term<'a> {
+    count_node();
     match agg {
         AggregateExpression::CountSolutions { distinct } => {
             // COUNT(*) or COUNT(DISTINCT *)
             (atoms::count_solutions(), *distinct).encode(env)
         }
-        AggregateExpression::FunctionCall { name, expr, distinct } => {
+        AggregateExpression::FunctionCall {
+            name,
+            expr,
+            distinct,
+        } => {
             let func_term = aggregate_function_to_term(env, name);
             let expr_term = expression_to_term(env, expr);
             (func_term, expr_term, *distinct).encode(env)
@@ -666,6 +1576,7 @@ fn aggregate_expression_to_term<'a>(env: Env<'a>, agg: &AggregateExpression) ->
 
 /// Converts an AggregateFunction to an Elixir term.
 fn aggregate_function_to_term<'a>(env: Env<'a>, func: &AggregateFunction) -> Term<'a> {
+    count_node();
     match func {
         AggregateFunction::Count => atoms::count().encode(env),
         AggregateFunction::Sum => atoms::sum().encode(env),
@@ -680,14 +1591,13 @@ fn aggregate_function_to_term<'a>(env: Env<'a>, func: &AggregateFunction) -> Ter
             };
             (atoms::group_concat(), sep_term).encode(env)
         }
-        AggregateFunction::Custom(iri) => {
-            (atoms::custom(), iri.as_str()).encode(env)
-        }
+        AggregateFunction::Custom(iri) => (atoms::custom(), iri.as_str()).encode(env),
     }
 }
 
 /// Converts an OrderExpression to an Elixir term.
 fn order_expression_to_term<'a>(env: Env<'a>, oe: &OrderExpression) -> Term<'a> {
+    count_node();
     match oe {
         OrderExpression::Asc(expr) => {
             let expr_term = expression_to_term(env, expr);
@@ -703,6 +1613,7 @@ fn order_expression_to_term<'a>(env: Env<'a>, oe: &OrderExpression) -> Term<'a>
 /// Converts a PropertyPath to an Elixir term.
 /// Uses atoms instead of strings for consistent pattern matching in Elixir.
 fn property_path_to_term<'a>(env: Env<'a>, path: &PropertyPathExpression) -> Term<'a> {
+    count_node();
     match path {
         PropertyPathExpression::NamedNode(nn) => named_node_to_term(env, nn),
         // Unary path operations (using macro)
@@ -711,14 +1622,16 @@ fn property_path_to_term<'a>(env: Env<'a>, path: &PropertyPathExpression) -> Ter
         PropertyPathExpression::OneOrMore(inner) => unary_path!(env, atoms::one_or_more(), inner),
         PropertyPathExpression::ZeroOrOne(inner) => unary_path!(env, atoms::zero_or_one(), inner),
         // Binary path operations (using macro)
-        PropertyPathExpression::Sequence(left, right) => binary_path!(env, atoms::sequence(), left, right),
-        PropertyPathExpression::Alternative(left, right) => binary_path!(env, atoms::alternative(), left, right),
+        PropertyPathExpression::Sequence(left, right) => {
+            binary_path!(env, atoms::sequence(), left, right)
+        }
+        PropertyPathExpression::Alternative(left, right) => {
+            binary_path!(env, atoms::alternative(), left, right)
+        }
         // Negated property set (list of named nodes to exclude)
         PropertyPathExpression::NegatedPropertySet(nodes) => {
-            let node_terms: Vec<Term<'a>> = nodes
-                .iter()
-                .map(|nn| named_node_to_term(env, nn))
-                .collect();
+            let node_terms: Vec<Term<'a>> =
+                nodes.iter().map(|nn| named_node_to_term(env, nn)).collect();
             (atoms::negated_property_set(), node_terms).encode(env)
         }
     }
@@ -726,6 +1639,7 @@ fn property_path_to_term<'a>(env: Env<'a>, path: &PropertyPathExpression) -> Ter
 
 /// Converts a QueryDataset to an Elixir term.
 fn query_dataset_to_term<'a>(env: Env<'a>, dataset: &spargebra::algebra::QueryDataset) -> Term<'a> {
+    count_node();
     let default_graphs: Vec<Term<'a>> = dataset
         .default
         .iter()
@@ -734,13 +1648,19 @@ fn query_dataset_to_term<'a>(env: Env<'a>, dataset: &spargebra::algebra::QueryDa
     let named_graphs: Vec<Term<'a>> = dataset
         .named
         .as_ref()
-        .map(|graphs| graphs.iter().map(|nn| named_node_to_term(env, nn)).collect())
+        .map(|graphs| {
+            graphs
+                .iter()
+                .map(|nn| named_node_to_term(env, nn))
+                .collect()
+        })
         .unwrap_or_default();
 
     vec![
         ("default", default_graphs.encode(env)),
         ("named", named_graphs.encode(env)),
-    ].encode(env)
+    ]
+    .encode(env)
 }
 
 /// Helper for optional values.
@@ -772,6 +1692,7 @@ fn option_expression_to_term<'a>(env: Env<'a>, expr: &Option<Expression>) -> Ter
 
 /// Converts a CONSTRUCT template to an Elixir term.
 fn construct_template_to_term<'a>(env: Env<'a>, template: &[TriplePattern]) -> Term<'a> {
+    count_node();
     let triple_terms: Vec<Term<'a>> = template
         .iter()
         .map(|tp| triple_pattern_to_term(env, tp))
@@ -785,6 +1706,7 @@ fn construct_template_to_term<'a>(env: Env<'a>, template: &[TriplePattern]) -> T
 
 /// Converts a spargebra Update to an Elixir term.
 fn update_to_term<'a>(env: Env<'a>, update: &Update) -> Term<'a> {
+    count_node();
     let operations: Vec<Term<'a>> = update
         .operations
         .iter()
@@ -799,24 +1721,20 @@ fn update_to_term<'a>(env: Env<'a>, update: &Update) -> Term<'a> {
             ("operations", operations.encode(env)),
             ("base_iri", base_term),
         ],
-    ).encode(env)
+    )
+        .encode(env)
 }
 
 /// Converts a GraphUpdateOperation to an Elixir term.
 fn graph_update_operation_to_term<'a>(env: Env<'a>, op: &GraphUpdateOperation) -> Term<'a> {
+    count_node();
     match op {
         GraphUpdateOperation::InsertData { data } => {
-            let quads: Vec<Term<'a>> = data
-                .iter()
-                .map(|q| quad_to_term(env, q))
-                .collect();
+            let quads: Vec<Term<'a>> = data.iter().map(|q| quad_to_term(env, q)).collect();
             (atoms::insert_data(), quads).encode(env)
         }
         GraphUpdateOperation::DeleteData { data } => {
-            let quads: Vec<Term<'a>> = data
-                .iter()
-                .map(|q| ground_quad_to_term(env, q))
-                .collect();
+            let quads: Vec<Term<'a>> = data.iter().map(|q| ground_quad_to_term(env, q)).collect();
             (atoms::delete_data(), quads).encode(env)
         }
         GraphUpdateOperation::DeleteInsert {
@@ -844,7 +1762,8 @@ fn graph_update_operation_to_term<'a>(env: Env<'a>, op: &GraphUpdateOperation) -
                     ("using", using_term),
                     ("pattern", pattern_term),
                 ],
-            ).encode(env)
+            )
+                .encode(env)
         }
         GraphUpdateOperation::Load {
             silent,
@@ -860,43 +1779,39 @@ fn graph_update_operation_to_term<'a>(env: Env<'a>, op: &GraphUpdateOperation) -
                     ("source", source_term),
                     ("destination", dest_term),
                 ],
-            ).encode(env)
+            )
+                .encode(env)
         }
         GraphUpdateOperation::Clear { silent, graph } => {
             let graph_term = graph_target_to_term(env, graph);
             (
                 atoms::clear(),
-                vec![
-                    ("silent", silent.encode(env)),
-                    ("graph", graph_term),
-                ],
-            ).encode(env)
+                vec![("silent", silent.encode(env)), ("graph", graph_term)],
+            )
+                .encode(env)
         }
         GraphUpdateOperation::Create { silent, graph } => {
             let graph_term = named_node_to_term(env, graph);
             (
                 atoms::create(),
-                vec![
-                    ("silent", silent.encode(env)),
-                    ("graph", graph_term),
-                ],
-            ).encode(env)
+                vec![("silent", silent.encode(env)), ("graph", graph_term)],
+            )
+                .encode(env)
         }
         GraphUpdateOperation::Drop { silent, graph } => {
             let graph_term = graph_target_to_term(env, graph);
             (
                 atoms::drop(),
-                vec![
-                    ("silent", silent.encode(env)),
-                    ("graph", graph_term),
-                ],
-            ).encode(env)
+                vec![("silent", silent.encode(env)), ("graph", graph_term)],
+            )
+                .encode(env)
         }
     }
 }
 
 /// Converts a Quad to an Elixir term.
 fn quad_to_term<'a>(env: Env<'a>, quad: &Quad) -> Term<'a> {
+    count_node();
     let subject = subject_to_term(env, &quad.subject);
     let predicate = named_node_to_term(env, &quad.predicate);
     let object = spargebra_term_to_elixir_term(env, &quad.object);
@@ -906,6 +1821,7 @@ fn quad_to_term<'a>(env: Env<'a>, quad: &Quad) -> Term<'a> {
 
 /// Converts a GroundQuad to an Elixir term.
 fn ground_quad_to_term<'a>(env: Env<'a>, quad: &GroundQuad) -> Term<'a> {
+    count_node();
     let subject = ground_subject_to_term(env, &quad.subject);
     let predicate = named_node_to_term(env, &quad.predicate);
     let object = ground_term_to_term(env, &quad.object);
@@ -915,6 +1831,7 @@ fn ground_quad_to_term<'a>(env: Env<'a>, quad: &GroundQuad) -> Term<'a> {
 
 /// Converts a QuadPattern to an Elixir term.
 fn quad_pattern_to_term<'a>(env: Env<'a>, quad: &QuadPattern) -> Term<'a> {
+    count_node();
     let subject = term_pattern_to_term(env, &quad.subject);
     let predicate = named_node_pattern_to_term(env, &quad.predicate);
     let object = term_pattern_to_term(env, &quad.object);
@@ -924,6 +1841,7 @@ fn quad_pattern_to_term<'a>(env: Env<'a>, quad: &QuadPattern) -> Term<'a> {
 
 /// Converts a GroundQuadPattern to an Elixir term.
 fn ground_quad_pattern_to_term<'a>(env: Env<'a>, quad: &GroundQuadPattern) -> Term<'a> {
+    count_node();
     let subject = ground_term_pattern_to_term(env, &quad.subject);
     let predicate = named_node_pattern_to_term(env, &quad.predicate);
     let object = ground_term_pattern_to_term(env, &quad.object);
@@ -933,16 +1851,20 @@ fn ground_quad_pattern_to_term<'a>(env: Env<'a>, quad: &GroundQuadPattern) -> Te
 
 /// Converts a Subject to an Elixir term.
 fn subject_to_term<'a>(env: Env<'a>, subject: &Subject) -> Term<'a> {
+    count_node();
     match subject {
         Subject::NamedNode(nn) => named_node_to_term(env, nn),
         Subject::BlankNode(bn) => blank_node_to_term(env, bn),
+        Subject::Triple(triple) => rdf_triple_to_term(env, triple),
     }
 }
 
 /// Converts a GroundSubject to an Elixir term.
 fn ground_subject_to_term<'a>(env: Env<'a>, subject: &GroundSubject) -> Term<'a> {
+    count_node();
     match subject {
         GroundSubject::NamedNode(nn) => named_node_to_term(env, nn),
+        GroundSubject::Triple(triple) => ground_triple_to_term(env, triple),
     }
 }
 
@@ -952,20 +1874,43 @@ fn spargebra_term_to_elixir_term<'a>(env: Env<'a>, term: &spargebra::term::Term)
         spargebra::term::Term::NamedNode(nn) => named_node_to_term(env, nn),
         spargebra::term::Term::BlankNode(bn) => blank_node_to_term(env, bn),
         spargebra::term::Term::Literal(lit) => literal_to_term(env, lit),
+        spargebra::term::Term::Triple(triple) => rdf_triple_to_term(env, triple),
     }
 }
 
+/// Converts a quoted RDF triple to `{:triple_term, subject, predicate, object}`.
+fn rdf_triple_to_term<'a>(env: Env<'a>, triple: &Triple) -> Term<'a> {
+    count_node();
+    let subject = subject_to_term(env, &triple.subject);
+    let predicate = named_node_to_term(env, &triple.predicate);
+    let object = spargebra_term_to_elixir_term(env, &triple.object);
+    (atoms::triple_term(), subject, predicate, object).encode(env)
+}
+
 /// Converts a GroundTermPattern to an Elixir term.
 fn ground_term_pattern_to_term<'a>(env: Env<'a>, term: &GroundTermPattern) -> Term<'a> {
+    count_node();
     match term {
         GroundTermPattern::NamedNode(nn) => named_node_to_term(env, nn),
         GroundTermPattern::Literal(lit) => literal_to_term(env, lit),
         GroundTermPattern::Variable(var) => variable_to_term(env, var),
+        GroundTermPattern::Triple(triple) => ground_triple_pattern_to_term(env, triple),
     }
 }
 
+/// Converts a quoted triple pattern without blank nodes to
+/// `{:triple_term, subject, predicate, object}`.
+fn ground_triple_pattern_to_term<'a>(env: Env<'a>, triple: &GroundTriplePattern) -> Term<'a> {
+    count_node();
+    let subject = ground_term_pattern_to_term(env, &triple.subject);
+    let predicate = named_node_pattern_to_term(env, &triple.predicate);
+    let object = ground_term_pattern_to_term(env, &triple.object);
+    (atoms::triple_term(), subject, predicate, object).encode(env)
+}
+
 /// Converts a GraphName to an Elixir term.
 fn graph_name_to_term<'a>(env: Env<'a>, graph: &GraphName) -> Term<'a> {
+    count_node();
     match graph {
         GraphName::NamedNode(nn) => (atoms::named_graph(), nn.as_str()).encode(env),
         GraphName::DefaultGraph => atoms::default_graph().encode(env),
@@ -974,6 +1919,7 @@ fn graph_name_to_term<'a>(env: Env<'a>, graph: &GraphName) -> Term<'a> {
 
 /// Converts a GraphNamePattern to an Elixir term.
 fn graph_name_pattern_to_term<'a>(env: Env<'a>, graph: &GraphNamePattern) -> Term<'a> {
+    count_node();
     match graph {
         GraphNamePattern::NamedNode(nn) => (atoms::named_graph(), nn.as_str()).encode(env),
         GraphNamePattern::DefaultGraph => atoms::default_graph().encode(env),
@@ -983,6 +1929,7 @@ fn graph_name_pattern_to_term<'a>(env: Env<'a>, graph: &GraphNamePattern) -> Ter
 
 /// Converts a GraphTarget to an Elixir term.
 fn graph_target_to_term<'a>(env: Env<'a>, target: &GraphTarget) -> Term<'a> {
+    count_node();
     match target {
         GraphTarget::NamedNode(nn) => (atoms::named_graph(), nn.as_str()).encode(env),
         GraphTarget::DefaultGraph => atoms::default_graph().encode(env),
@@ -991,4 +1938,4216 @@ fn graph_target_to_term<'a>(env: Env<'a>, target: &GraphTarget) -> Term<'a> {
     }
 }
 
+// ===========================================================================
+// Query Analysis
+// ===========================================================================
+
+/// Computes the variable dependencies of every OPTIONAL (LeftJoin) in a query.
+///
+/// For each LeftJoin, `required_vars` are the variables in scope on the left
+/// (required) side, `optional_vars` are those in scope on the right side plus
+/// any referenced by the OPTIONAL's FILTER, and `shared_vars` is their
+/// intersection, i.e. the join keys. LeftJoins are listed in pre-order, so an
+/// outer OPTIONAL comes before the OPTIONALs nested inside it.
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+///
+/// # Returns
+/// * `{:ok, [{required_vars, optional_vars, shared_vars}, ...]}` with sorted
+///   variable names
+/// * `{:error, {:parse_error, message}}` on parse failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn optional_dependencies<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
+    let query = match Query::parse(sparql, None) {
+        Ok(query) => query,
+        Err(e) => {
+            let error_msg = e.to_string();
+            return Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env));
+        }
+    };
+
+    let pattern = query_pattern(&query);
+
+    let mut dependencies = Vec::new();
+    collect_left_join_dependencies(pattern, &mut dependencies);
+
+    Ok((atoms::ok(), dependencies).encode(env))
+}
+
+/// Returns the WHERE clause algebra of a query, whatever its form.
+fn query_pattern(query: &Query) -> &GraphPattern {
+    match query {
+        Query::Select { pattern, .. }
+        | Query::Construct { pattern, .. }
+        | Query::Ask { pattern, .. }
+        | Query::Describe { pattern, .. } => pattern,
+    }
+}
+
+/// `{required_vars, optional_vars, shared_vars}` for one LeftJoin
+type LeftJoinDependency = (Vec<String>, Vec<String>, Vec<String>);
+
+/// Walks a graph pattern in pre-order, recording the dependencies of each LeftJoin.
+fn collect_left_join_dependencies(pattern: &GraphPattern, out: &mut Vec<LeftJoinDependency>) {
+    match pattern {
+        GraphPattern::LeftJoin {
+            left,
+            right,
+            expression,
+        } => {
+            let required = in_scope_variables(left);
+            let mut optional = in_scope_variables(right);
+            if let Some(expr) = expression {
+                collect_expression_variables(expr, &mut optional);
+            }
+            let shared = required.intersection(&optional).cloned().collect();
+
+            out.push((
+                required.into_iter().collect(),
+                optional.into_iter().collect(),
+                shared,
+            ));
+
+            collect_left_join_dependencies(left, out);
+            collect_left_join_dependencies(right, out);
+        }
+        GraphPattern::Join { left, right }
+        | GraphPattern::Union { left, right }
+        | GraphPattern::Minus { left, right } => {
+            collect_left_join_dependencies(left, out);
+            collect_left_join_dependencies(right, out);
+        }
+        GraphPattern::Filter { inner, .. }
+        | GraphPattern::Graph { inner, .. }
+        | GraphPattern::Extend { inner, .. }
+        | GraphPattern::OrderBy { inner, .. }
+        | GraphPattern::Project { inner, .. }
+        | GraphPattern::Distinct { inner }
+        | GraphPattern::Reduced { inner }
+        | GraphPattern::Slice { inner, .. }
+        | GraphPattern::Group { inner, .. }
+        | GraphPattern::Service { inner, .. } => collect_left_join_dependencies(inner, out),
+        GraphPattern::Bgp { .. } | GraphPattern::Path { .. } | GraphPattern::Values { .. } => {}
+    }
+}
+
+/// Returns the names of the variables in scope for a graph pattern, sorted.
+fn in_scope_variables(pattern: &GraphPattern) -> BTreeSet<String> {
+    let mut variables = BTreeSet::new();
+    pattern.on_in_scope_variable(|var| {
+        variables.insert(var.as_str().to_string());
+    });
+    variables
+}
+
+/// Adds every variable referenced by an expression (including EXISTS patterns).
+fn collect_expression_variables(expr: &Expression, out: &mut BTreeSet<String>) {
+    match expr {
+        Expression::Variable(var) | Expression::Bound(var) => {
+            out.insert(var.as_str().to_string());
+        }
+        Expression::NamedNode(_) | Expression::Literal(_) => {}
+        Expression::Or(a, b)
+        | Expression::And(a, b)
+        | Expression::Equal(a, b)
+        | Expression::SameTerm(a, b)
+        | Expression::Greater(a, b)
+        | Expression::GreaterOrEqual(a, b)
+        | Expression::Less(a, b)
+        | Expression::LessOrEqual(a, b)
+        | Expression::Add(a, b)
+        | Expression::Subtract(a, b)
+        | Expression::Multiply(a, b)
+        | Expression::Divide(a, b) => {
+            collect_expression_variables(a, out);
+            collect_expression_variables(b, out);
+        }
+        Expression::UnaryPlus(inner) | Expression::UnaryMinus(inner) | Expression::Not(inner) => {
+            collect_expression_variables(inner, out);
+        }
+        Expression::In(needle, haystack) => {
+            collect_expression_variables(needle, out);
+            for item in haystack {
+                collect_expression_variables(item, out);
+            }
+        }
+        Expression::If(cond, then, otherwise) => {
+            collect_expression_variables(cond, out);
+            collect_expression_variables(then, out);
+            collect_expression_variables(otherwise, out);
+        }
+        Expression::Coalesce(args) | Expression::FunctionCall(_, args) => {
+            for arg in args {
+                collect_expression_variables(arg, out);
+            }
+        }
+        Expression::Exists(pattern) => out.extend(in_scope_variables(pattern)),
+    }
+}
+
+/// Lists the variables a query mentions.
+///
+/// `all` holds every variable written anywhere in the query: triple and path
+/// patterns, FILTER/BIND/ORDER BY/GROUP BY expressions, `EXISTS` patterns,
+/// VALUES, `GRAPH ?g`, subquery projections and the CONSTRUCT template. The
+/// variables spargebra generates for aggregates and `DESCRIBE <iri>` are left
+/// out, as are blank nodes. `projected` is the SELECT/DESCRIBE projection in
+/// order (`[]` for ASK and CONSTRUCT).
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+///
+/// # Returns
+/// * `{:ok, %{projected: [name, ...], all: [name, ...]}}` on success, `all`
+///   sorted
+/// * `{:error, {:parse_error, message}}` on parse failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn query_variables<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
+    let query = match Query::parse(sparql, None) {
+        Ok(query) => query,
+        Err(e) => {
+            let error_msg = e.to_string();
+            return Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env));
+        }
+    };
+
+    let pattern = query_pattern(&query);
+    let mut all = BTreeSet::new();
+    visit_patterns(pattern, &mut |p| collect_pattern_variables(p, &mut all));
+    if let Query::Construct { template, .. } = &query {
+        for triple in template {
+            collect_triple_variables(triple, &mut all);
+        }
+    }
+    all.retain(|name| variable_written_in(sparql, name));
+
+    // Projection sits under the solution modifiers spargebra wraps around WHERE
+    let mut projected: Vec<String> = Vec::new();
+    let mut body = pattern;
+    if matches!(query, Query::Select { .. } | Query::Describe { .. }) {
+        loop {
+            match body {
+                GraphPattern::Project { variables, .. } => {
+                    projected = variables
+                        .iter()
+                        .map(|v| v.as_str().to_string())
+                        .filter(|name| all.contains(name))
+                        .collect();
+                    break;
+                }
+                GraphPattern::Slice { inner, .. }
+                | GraphPattern::Distinct { inner }
+                | GraphPattern::Reduced { inner }
+                | GraphPattern::OrderBy { inner, .. } => body = inner,
+                _ => break,
+            }
+        }
+    }
+
+    let variables = Term::map_from_pairs(
+        env,
+        &[
+            (atoms::projected().encode(env), projected.encode(env)),
+            (
+                atoms::all().encode(env),
+                all.into_iter().collect::<Vec<_>>().encode(env),
+            ),
+        ],
+    )?;
+    Ok((atoms::ok(), variables).encode(env))
+}
+
+/// Adds the variables a single pattern node mentions. Nested patterns are
+/// reached through `visit_patterns`.
+fn collect_pattern_variables(pattern: &GraphPattern, out: &mut BTreeSet<String>) {
+    let name = |var: &Variable| var.as_str().to_string();
+    match pattern {
+        GraphPattern::Bgp { patterns } => {
+            for triple in patterns {
+                collect_triple_variables(triple, out);
+            }
+        }
+        GraphPattern::Path {
+            subject, object, ..
+        } => {
+            for term in [subject, object] {
+                match term {
+                    TermPattern::Variable(var) => {
+                        out.insert(name(var));
+                    }
+                    TermPattern::Triple(quoted) => collect_triple_variables(quoted, out),
+                    _ => {}
+                }
+            }
+        }
+        GraphPattern::Graph {
+            name: NamedNodePattern::Variable(var),
+            ..
+        }
+        | GraphPattern::Service {
+            name: NamedNodePattern::Variable(var),
+            ..
+        } => {
+            out.insert(name(var));
+        }
+        GraphPattern::Values { variables, .. } | GraphPattern::Project { variables, .. } => {
+            out.extend(variables.iter().map(name))
+        }
+        GraphPattern::LeftJoin {
+            expression: Some(expr),
+            ..
+        }
+        | GraphPattern::Filter { expr, .. } => collect_expression_variables(expr, out),
+        GraphPattern::Extend {
+            variable,
+            expression,
+            ..
+        } => {
+            out.insert(name(variable));
+            collect_expression_variables(expression, out);
+        }
+        GraphPattern::OrderBy { expression, .. } => {
+            for order in expression {
+                match order {
+                    OrderExpression::Asc(expr) | OrderExpression::Desc(expr) => {
+                        collect_expression_variables(expr, out)
+                    }
+                }
+            }
+        }
+        GraphPattern::Group {
+            variables,
+            aggregates,
+            ..
+        } => {
+            out.extend(variables.iter().map(name));
+            for (var, aggregate) in aggregates {
+                out.insert(name(var));
+                if let AggregateExpression::FunctionCall { expr, .. } = aggregate {
+                    collect_expression_variables(expr, out);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Adds the variables of a triple pattern, including quoted triples.
+fn collect_triple_variables(triple: &TriplePattern, out: &mut BTreeSet<String>) {
+    if let NamedNodePattern::Variable(var) = &triple.predicate {
+        out.insert(var.as_str().to_string());
+    }
+    for term in [&triple.subject, &triple.object] {
+        match term {
+            TermPattern::Variable(var) => {
+                out.insert(var.as_str().to_string());
+            }
+            TermPattern::Triple(quoted) => collect_triple_variables(quoted, out),
+            _ => {}
+        }
+    }
+}
+
+/// Whether `?name` or `$name` appears in the query text as a whole token.
+/// spargebra generates hex-named variables for aggregates and `DESCRIBE
+/// <iri>`; those never appear in the source.
+fn variable_written_in(sparql: &str, name: &str) -> bool {
+    let is_name_char = |c: char| c.is_alphanumeric() || c == '_' || c == '\u{B7}';
+    sparql.match_indices(name).any(|(start, _)| {
+        let before = sparql[..start].chars().next_back();
+        let after = sparql[start + name.len()..].chars().next();
+        matches!(before, Some('?') | Some('$')) && !after.is_some_and(is_name_char)
+    })
+}
+
+/// Lists every IRI a query mentions, for namespace discovery.
+///
+/// Covers the dataset clauses, CONSTRUCT template, triple and path patterns,
+/// `GRAPH`/`SERVICE` names, VALUES, expressions, custom function and
+/// aggregate IRIs and literal datatypes. Prefixed names and `a` are reported
+/// expanded. Simple and language-tagged literals contribute no datatype. IRIs
+/// are deduplicated and listed in roughly the order they are written: a
+/// group's patterns come before its FILTER, BIND and ORDER BY expressions.
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+///
+/// # Returns
+/// * `{:ok, [iri, ...]}` on success
+/// * `{:error, {:parse_error, message}}` on parse failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn query_iris<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
+    let query = match Query::parse(sparql, None) {
+        Ok(query) => query,
+        Err(e) => {
+            let error_msg = e.to_string();
+            return Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env));
+        }
+    };
+
+    let mut iris = IriCollector::default();
+    if let Query::Construct { template, .. } = &query {
+        for triple in template {
+            iris.add_triple(triple);
+        }
+    }
+    if let Query::Select {
+        dataset: Some(dataset),
+        ..
+    }
+    | Query::Construct {
+        dataset: Some(dataset),
+        ..
+    }
+    | Query::Ask {
+        dataset: Some(dataset),
+        ..
+    }
+    | Query::Describe {
+        dataset: Some(dataset),
+        ..
+    } = &query
+    {
+        for graph in dataset.default.iter().chain(dataset.named.iter().flatten()) {
+            iris.add(graph);
+        }
+    }
+    iris.add_pattern(query_pattern(&query));
+
+    Ok((atoms::ok(), iris.ordered).encode(env))
+}
+
+/// Deduplicated IRIs in first-seen order.
+#[derive(Default)]
+struct IriCollector {
+    seen: HashSet<String>,
+    ordered: Vec<String>,
+}
+
+impl IriCollector {
+    fn add(&mut self, node: &NamedNode) {
+        if self.seen.insert(node.as_str().to_string()) {
+            self.ordered.push(node.as_str().to_string());
+        }
+    }
+
+    fn add_literal(&mut self, literal: &Literal) {
+        if literal.language().is_none() && literal.datatype() != xsd::STRING {
+            self.add(&literal.datatype().into_owned());
+        }
+    }
+
+    fn add_term_pattern(&mut self, term: &TermPattern) {
+        match term {
+            TermPattern::NamedNode(node) => self.add(node),
+            TermPattern::Literal(literal) => self.add_literal(literal),
+            TermPattern::Triple(triple) => self.add_triple(triple),
+            TermPattern::BlankNode(_) | TermPattern::Variable(_) => {}
+        }
+    }
+
+    fn add_ground_term(&mut self, term: &GroundTerm) {
+        match term {
+            GroundTerm::NamedNode(node) => self.add(node),
+            GroundTerm::Literal(literal) => self.add_literal(literal),
+            GroundTerm::Triple(triple) => self.add_ground_triple(triple),
+        }
+    }
+
+    fn add_ground_triple(&mut self, triple: &GroundTriple) {
+        match &triple.subject {
+            GroundSubject::NamedNode(node) => self.add(node),
+            GroundSubject::Triple(quoted) => self.add_ground_triple(quoted),
+        }
+        self.add(&triple.predicate);
+        self.add_ground_term(&triple.object);
+    }
+
+    fn add_named_node_pattern(&mut self, name: &NamedNodePattern) {
+        if let NamedNodePattern::NamedNode(node) = name {
+            self.add(node);
+        }
+    }
+
+    fn add_triple(&mut self, triple: &TriplePattern) {
+        self.add_term_pattern(&triple.subject);
+        self.add_named_node_pattern(&triple.predicate);
+        self.add_term_pattern(&triple.object);
+    }
+
+    fn add_path(&mut self, path: &PropertyPathExpression) {
+        match path {
+            PropertyPathExpression::NamedNode(node) => self.add(node),
+            PropertyPathExpression::Reverse(inner)
+            | PropertyPathExpression::ZeroOrMore(inner)
+            | PropertyPathExpression::OneOrMore(inner)
+            | PropertyPathExpression::ZeroOrOne(inner) => self.add_path(inner),
+            PropertyPathExpression::Sequence(left, right)
+            | PropertyPathExpression::Alternative(left, right) => {
+                self.add_path(left);
+                self.add_path(right);
+            }
+            PropertyPathExpression::NegatedPropertySet(nodes) => {
+                nodes.iter().for_each(|node| self.add(node))
+            }
+        }
+    }
+
+    /// Walks a pattern with nested patterns before the expressions applied
+    /// to them, matching where they appear in the query text.
+    fn add_pattern(&mut self, pattern: &GraphPattern) {
+        match pattern {
+            GraphPattern::Bgp { patterns } => {
+                patterns.iter().for_each(|triple| self.add_triple(triple))
+            }
+            GraphPattern::Path {
+                subject,
+                path,
+                object,
+            } => {
+                self.add_term_pattern(subject);
+                self.add_path(path);
+                self.add_term_pattern(object);
+            }
+            GraphPattern::Join { left, right }
+            | GraphPattern::Union { left, right }
+            | GraphPattern::Minus { left, right } => {
+                self.add_pattern(left);
+                self.add_pattern(right);
+            }
+            GraphPattern::LeftJoin {
+                left,
+                right,
+                expression,
+            } => {
+                self.add_pattern(left);
+                self.add_pattern(right);
+                if let Some(expr) = expression {
+                    self.add_expression(expr);
+                }
+            }
+            GraphPattern::Filter { expr, inner } => {
+                self.add_pattern(inner);
+                self.add_expression(expr);
+            }
+            GraphPattern::Graph { name, inner } | GraphPattern::Service { name, inner, .. } => {
+                self.add_named_node_pattern(name);
+                self.add_pattern(inner);
+            }
+            GraphPattern::Extend {
+                inner, expression, ..
+            } => {
+                self.add_pattern(inner);
+                self.add_expression(expression);
+            }
+            GraphPattern::Values { bindings, .. } => {
+                for value in bindings.iter().flatten().flatten() {
+                    self.add_ground_term(value);
+                }
+            }
+            GraphPattern::OrderBy { inner, expression } => {
+                self.add_pattern(inner);
+                for order in expression {
+                    match order {
+                        OrderExpression::Asc(expr) | OrderExpression::Desc(expr) => {
+                            self.add_expression(expr)
+                        }
+                    }
+                }
+            }
+            GraphPattern::Group {
+                inner, aggregates, ..
+            } => {
+                self.add_pattern(inner);
+                for (_, aggregate) in aggregates {
+                    if let AggregateExpression::FunctionCall { name, expr, .. } = aggregate {
+                        if let AggregateFunction::Custom(node) = name {
+                            self.add(node);
+                        }
+                        self.add_expression(expr);
+                    }
+                }
+            }
+            GraphPattern::Project { inner, .. }
+            | GraphPattern::Distinct { inner }
+            | GraphPattern::Reduced { inner }
+            | GraphPattern::Slice { inner, .. } => self.add_pattern(inner),
+        }
+    }
+
+    fn add_expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::NamedNode(node) => self.add(node),
+            Expression::Literal(literal) => self.add_literal(literal),
+            Expression::Variable(_) | Expression::Bound(_) => {}
+            Expression::Or(a, b)
+            | Expression::And(a, b)
+            | Expression::Equal(a, b)
+            | Expression::SameTerm(a, b)
+            | Expression::Greater(a, b)
+            | Expression::GreaterOrEqual(a, b)
+            | Expression::Less(a, b)
+            | Expression::LessOrEqual(a, b)
+            | Expression::Add(a, b)
+            | Expression::Subtract(a, b)
+            | Expression::Multiply(a, b)
+            | Expression::Divide(a, b) => {
+                self.add_expression(a);
+                self.add_expression(b);
+            }
+            Expression::UnaryPlus(inner)
+            | Expression::UnaryMinus(inner)
+            | Expression::Not(inner) => self.add_expression(inner),
+            Expression::In(needle, haystack) => {
+                self.add_expression(needle);
+                haystack.iter().for_each(|item| self.add_expression(item));
+            }
+            Expression::If(cond, then, otherwise) => {
+                self.add_expression(cond);
+                self.add_expression(then);
+                self.add_expression(otherwise);
+            }
+            Expression::Coalesce(args) => args.iter().for_each(|arg| self.add_expression(arg)),
+            Expression::FunctionCall(function, args) => {
+                if let Function::Custom(node) = function {
+                    self.add(node);
+                }
+                args.iter().for_each(|arg| self.add_expression(arg));
+            }
+            Expression::Exists(pattern) => self.add_pattern(pattern),
+        }
+    }
+}
+
+/// Lists the HAVING clauses of a query and its subqueries.
+///
+/// spargebra has no HAVING node: `HAVING (cond)` parses to a `Filter` whose
+/// inner pattern is the `Group` built for GROUP BY (or the implicit group of
+/// an aggregating query without one). A WHERE-clause FILTER never sits
+/// directly on a group, since subqueries are wrapped in a projection, so
+/// every filter-over-group is reported as HAVING. The AST from
+/// `parse_query/1` is left untouched; this is only a tagged view of it.
+///
+/// Aggregates in the condition appear as the generated variables the group
+/// binds them to, so resolve them through the group's aggregate list.
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+///
+/// # Returns
+/// * `{:ok, [{:having, expr, group}, ...]}` on success, outermost query first
+/// * `{:error, {:parse_error, message}}` on parse failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn query_having<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
+    let query = match Query::parse(sparql, None) {
+        Ok(query) => query,
+        Err(e) => {
+            let error_msg = e.to_string();
+            return Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env));
+        }
+    };
+
+    let mut clauses = Vec::new();
+    visit_patterns(query_pattern(&query), &mut |pattern| {
+        if let GraphPattern::Filter { expr, inner } = pattern {
+            if matches!(inner.as_ref(), GraphPattern::Group { .. }) {
+                let expr_term = expression_to_term(env, expr);
+                let group_term = graph_pattern_to_term(env, inner);
+                clauses.push((atoms::having(), expr_term, group_term).encode(env));
+            }
+        }
+    });
+
+    Ok((atoms::ok(), clauses).encode(env))
+}
+
+/// Flattens the basic graph pattern triples of a query, for index planning.
+///
+/// Triples are listed in the same pre-order as `analyze_query`'s
+/// `triple_patterns`, each with flags telling whether it sits on the optional
+/// side of an OPTIONAL or in a UNION branch, at any depth. Triples under
+/// `GRAPH`, `MINUS`, `SERVICE` and subqueries are included without a marker of
+/// their own; property paths and `EXISTS` patterns are not included.
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+///
+/// # Returns
+/// * `{:ok, [{{subject, predicate, object}, %{in_optional: bool, in_union: bool}}, ...]}`
+///   on success
+/// * `{:error, {:parse_error, message}}` on parse failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn query_triple_patterns<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
+    let query = match Query::parse(sparql, None) {
+        Ok(query) => query,
+        Err(e) => {
+            let error_msg = e.to_string();
+            return Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env));
+        }
+    };
+
+    let mut triples = Vec::new();
+    collect_flagged_triples(env, query_pattern(&query), false, false, &mut triples)?;
+
+    Ok((atoms::ok(), triples).encode(env))
+}
+
+/// Walks a graph pattern like `collect_bgps`, encoding each triple with its
+/// `in_optional`/`in_union` flags.
+fn collect_flagged_triples<'a>(
+    env: Env<'a>,
+    pattern: &GraphPattern,
+    in_optional: bool,
+    in_union: bool,
+    out: &mut Vec<Term<'a>>,
+) -> NifResult<()> {
+    match pattern {
+        GraphPattern::Bgp { patterns } => {
+            for tp in patterns {
+                let triple = (
+                    term_pattern_to_term(env, &tp.subject),
+                    named_node_pattern_to_term(env, &tp.predicate),
+                    term_pattern_to_term(env, &tp.object),
+                );
+                let flags = Term::map_from_pairs(
+                    env,
+                    &[
+                        (atoms::in_optional().encode(env), in_optional.encode(env)),
+                        (atoms::in_union().encode(env), in_union.encode(env)),
+                    ],
+                )?;
+                out.push((triple, flags).encode(env));
+            }
+        }
+        GraphPattern::LeftJoin { left, right, .. } => {
+            collect_flagged_triples(env, left, in_optional, in_union, out)?;
+            collect_flagged_triples(env, right, true, in_union, out)?;
+        }
+        GraphPattern::Union { left, right } => {
+            collect_flagged_triples(env, left, in_optional, true, out)?;
+            collect_flagged_triples(env, right, in_optional, true, out)?;
+        }
+        GraphPattern::Join { left, right } | GraphPattern::Minus { left, right } => {
+            collect_flagged_triples(env, left, in_optional, in_union, out)?;
+            collect_flagged_triples(env, right, in_optional, in_union, out)?;
+        }
+        GraphPattern::Graph { inner, .. }
+        | GraphPattern::Filter { inner, .. }
+        | GraphPattern::Extend { inner, .. }
+        | GraphPattern::OrderBy { inner, .. }
+        | GraphPattern::Project { inner, .. }
+        | GraphPattern::Distinct { inner }
+        | GraphPattern::Reduced { inner }
+        | GraphPattern::Slice { inner, .. }
+        | GraphPattern::Group { inner, .. }
+        | GraphPattern::Service { inner, .. } => {
+            collect_flagged_triples(env, inner, in_optional, in_union, out)?;
+        }
+        GraphPattern::Path { .. } | GraphPattern::Values { .. } => {}
+    }
+    Ok(())
+}
+
+/// Checks that a query only calls custom functions from an allow-list.
+///
+/// Every custom function call (`Function::Custom`) and custom aggregate in
+/// the WHERE clause, including `EXISTS` patterns and subqueries, must have
+/// its IRI in `allowed_iris`. Built-in functions are always allowed, and so
+/// are the XSD casts SPARQL defines (`xsd:boolean`, `xsd:integer`,
+/// `xsd:decimal`, `xsd:float`, `xsd:double`, `xsd:string` and
+/// `xsd:dateTime`), even though the algebra represents them as custom calls.
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+/// * `allowed_iris` - IRIs of the custom functions the query may call
+///
+/// # Returns
+/// * `:ok` if every custom function is allowed
+/// * `{:error, {:forbidden_function, iri}}` for the first disallowed one, in
+///   pre-order of the patterns
+/// * `{:error, {:parse_error, message}}` on parse failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn validate_functions<'a>(
+    env: Env<'a>,
+    sparql: &str,
+    allowed_iris: Vec<String>,
+) -> NifResult<Term<'a>> {
+    let query = match Query::parse(sparql, None) {
+        Ok(query) => query,
+        Err(e) => {
+            let error_msg = e.to_string();
+            return Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env));
+        }
+    };
+
+    let allowed: HashSet<&str> = allowed_iris.iter().map(String::as_str).collect();
+    let mut forbidden = None;
+    visit_patterns(query_pattern(&query), &mut |pattern| {
+        if forbidden.is_none() {
+            forbidden = pattern_custom_functions(pattern)
+                .into_iter()
+                .find(|iri| !allowed.contains(iri.as_str()));
+        }
+    });
+
+    match forbidden {
+        Some(iri) => Ok((atoms::error(), (atoms::forbidden_function(), iri.as_str())).encode(env)),
+        None => Ok(atoms::ok().encode(env)),
+    }
+}
+
+/// Lists the custom function and aggregate IRIs in the expressions attached
+/// directly to a pattern, in written order.
+fn pattern_custom_functions(pattern: &GraphPattern) -> Vec<&NamedNode> {
+    let mut functions = Vec::new();
+    match pattern {
+        GraphPattern::Filter { expr, .. }
+        | GraphPattern::Extend {
+            expression: expr, ..
+        }
+        | GraphPattern::LeftJoin {
+            expression: Some(expr),
+            ..
+        } => collect_custom_functions(expr, &mut functions),
+        GraphPattern::OrderBy { expression, .. } => {
+            for order in expression {
+                match order {
+                    OrderExpression::Asc(expr) | OrderExpression::Desc(expr) => {
+                        collect_custom_functions(expr, &mut functions)
+                    }
+                }
+            }
+        }
+        GraphPattern::Group { aggregates, .. } => {
+            for (_, aggregate) in aggregates {
+                if let AggregateExpression::FunctionCall { name, expr, .. } = aggregate {
+                    if let AggregateFunction::Custom(iri) = name {
+                        functions.push(iri);
+                    }
+                    collect_custom_functions(expr, &mut functions);
+                }
+            }
+        }
+        _ => {}
+    }
+    functions
+}
+
+/// Casts the SPARQL spec defines, which `validate_functions` treats as built-ins.
+const XSD_CASTS: [oxrdf::NamedNodeRef<'static>; 7] = [
+    xsd::BOOLEAN,
+    xsd::INTEGER,
+    xsd::DECIMAL,
+    xsd::FLOAT,
+    xsd::DOUBLE,
+    xsd::STRING,
+    xsd::DATE_TIME,
+];
+
+/// Collects the custom function IRIs called in an expression, leaving out
+/// `XSD_CASTS`. `EXISTS` patterns are skipped; `visit_patterns` reaches them
+/// on its own.
+fn collect_custom_functions<'p>(expr: &'p Expression, out: &mut Vec<&'p NamedNode>) {
+    match expr {
+        Expression::NamedNode(_)
+        | Expression::Literal(_)
+        | Expression::Variable(_)
+        | Expression::Bound(_)
+        | Expression::Exists(_) => {}
+        Expression::Or(a, b)
+        | Expression::And(a, b)
+        | Expression::Equal(a, b)
+        | Expression::SameTerm(a, b)
+        | Expression::Greater(a, b)
+        | Expression::GreaterOrEqual(a, b)
+        | Expression::Less(a, b)
+        | Expression::LessOrEqual(a, b)
+        | Expression::Add(a, b)
+        | Expression::Subtract(a, b)
+        | Expression::Multiply(a, b)
+        | Expression::Divide(a, b) => {
+            collect_custom_functions(a, out);
+            collect_custom_functions(b, out);
+        }
+        Expression::UnaryPlus(inner) | Expression::UnaryMinus(inner) | Expression::Not(inner) => {
+            collect_custom_functions(inner, out)
+        }
+        Expression::In(needle, haystack) => {
+            collect_custom_functions(needle, out);
+            for item in haystack {
+                collect_custom_functions(item, out);
+            }
+        }
+        Expression::If(cond, then, otherwise) => {
+            collect_custom_functions(cond, out);
+            collect_custom_functions(then, out);
+            collect_custom_functions(otherwise, out);
+        }
+        Expression::Coalesce(args) => {
+            for arg in args {
+                collect_custom_functions(arg, out);
+            }
+        }
+        Expression::FunctionCall(function, args) => {
+            if let Function::Custom(iri) = function {
+                if !XSD_CASTS.contains(&iri.as_ref()) {
+                    out.push(iri);
+                }
+            }
+            for arg in args {
+                collect_custom_functions(arg, out);
+            }
+        }
+    }
+}
+
+/// Compiles every property path pattern in a query into an executor plan hint.
+///
+/// Each `GraphPattern::Path` becomes `{subject, hint, object}`, listed in
+/// pre-order. Hints pre-digest the path algebra so the executor can dispatch
+/// on them directly:
+/// * `{:fixed, {:named_node, iri}}` - a single predicate
+/// * `{:inverse, hint}` - `^path`
+/// * `{:transitive, hint, :star | :plus}` - `path*` / `path+`, run with the
+///   transitive-closure operator
+/// * `{:optional, hint}` - `path?`
+/// * `{:seq, [hint, ...]}` - `a/b/c`, flattened
+/// * `{:alt, [hint, ...]}` - `a|b|c`, flattened
+/// * `{:negated, [{:named_node, iri}, ...]}` - `!(a|b)`
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+///
+/// # Returns
+/// * `{:ok, [{subject, hint, object}, ...]}` on success
+/// * `{:error, {:parse_error, message}}` on parse failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn property_path_plan<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
+    let query = match Query::parse(sparql, None) {
+        Ok(query) => query,
+        Err(e) => {
+            let error_msg = e.to_string();
+            return Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env));
+        }
+    };
+
+    let pattern = query_pattern(&query);
+
+    let mut plans = Vec::new();
+    collect_path_plans(env, pattern, &mut plans);
+
+    Ok((atoms::ok(), plans).encode(env))
+}
+
+/// Walks a graph pattern in pre-order, recording a plan hint for each path pattern.
+fn collect_path_plans<'a>(env: Env<'a>, pattern: &GraphPattern, out: &mut Vec<Term<'a>>) {
+    match pattern {
+        GraphPattern::Path {
+            subject,
+            path,
+            object,
+        } => {
+            let subject_term = term_pattern_to_term(env, subject);
+            let object_term = term_pattern_to_term(env, object);
+            out.push((subject_term, path_plan_hint(env, path), object_term).encode(env));
+        }
+        GraphPattern::Join { left, right }
+        | GraphPattern::LeftJoin { left, right, .. }
+        | GraphPattern::Union { left, right }
+        | GraphPattern::Minus { left, right } => {
+            collect_path_plans(env, left, out);
+            collect_path_plans(env, right, out);
+        }
+        GraphPattern::Filter { inner, .. }
+        | GraphPattern::Graph { inner, .. }
+        | GraphPattern::Extend { inner, .. }
+        | GraphPattern::OrderBy { inner, .. }
+        | GraphPattern::Project { inner, .. }
+        | GraphPattern::Distinct { inner }
+        | GraphPattern::Reduced { inner }
+        | GraphPattern::Slice { inner, .. }
+        | GraphPattern::Group { inner, .. }
+        | GraphPattern::Service { inner, .. } => collect_path_plans(env, inner, out),
+        GraphPattern::Bgp { .. } | GraphPattern::Values { .. } => {}
+    }
+}
+
+/// Converts a property path into its plan hint (see `property_path_plan`).
+fn path_plan_hint<'a>(env: Env<'a>, path: &PropertyPathExpression) -> Term<'a> {
+    match path {
+        PropertyPathExpression::NamedNode(nn) => {
+            (atoms::fixed(), named_node_to_term(env, nn)).encode(env)
+        }
+        PropertyPathExpression::Reverse(inner) => {
+            (atoms::inverse(), path_plan_hint(env, inner)).encode(env)
+        }
+        PropertyPathExpression::ZeroOrMore(inner) => (
+            atoms::transitive(),
+            path_plan_hint(env, inner),
+            atoms::star(),
+        )
+            .encode(env),
+        PropertyPathExpression::OneOrMore(inner) => (
+            atoms::transitive(),
+            path_plan_hint(env, inner),
+            atoms::plus(),
+        )
+            .encode(env),
+        PropertyPathExpression::ZeroOrOne(inner) => {
+            (atoms::optional(), path_plan_hint(env, inner)).encode(env)
+        }
+        PropertyPathExpression::Sequence(..) => {
+            let mut steps = Vec::new();
+            flatten_path_hints(env, path, true, &mut steps);
+            (atoms::seq(), steps).encode(env)
+        }
+        PropertyPathExpression::Alternative(..) => {
+            let mut branches = Vec::new();
+            flatten_path_hints(env, path, false, &mut branches);
+            (atoms::alt(), branches).encode(env)
+        }
+        PropertyPathExpression::NegatedPropertySet(nodes) => {
+            let node_terms: Vec<Term<'a>> =
+                nodes.iter().map(|nn| named_node_to_term(env, nn)).collect();
+            (atoms::negated(), node_terms).encode(env)
+        }
+    }
+}
+
+/// Flattens nested sequences (or alternatives) into a single list of hints.
+fn flatten_path_hints<'a>(
+    env: Env<'a>,
+    path: &PropertyPathExpression,
+    sequence: bool,
+    out: &mut Vec<Term<'a>>,
+) {
+    match path {
+        PropertyPathExpression::Sequence(left, right) if sequence => {
+            flatten_path_hints(env, left, sequence, out);
+            flatten_path_hints(env, right, sequence, out);
+        }
+        PropertyPathExpression::Alternative(left, right) if !sequence => {
+            flatten_path_hints(env, left, sequence, out);
+            flatten_path_hints(env, right, sequence, out);
+        }
+        _ => out.push(path_plan_hint(env, path)),
+    }
+}
+
+/// FNV-1a 64-bit offset basis.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// FNV-1a 64-bit prime.
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Computes a structural fingerprint of a query for result caching.
+///
+/// The query is normalized to its SSE algebra form, then variables and blank
+/// nodes are renamed positionally (`?v0`, `?v1`, ..., `_:b0`, ...) in order
+/// of first appearance. The normalized text is hashed with FNV-1a, which is
+/// stable across builds and platforms. Queries that differ only in variable
+/// names, blank node labels, prefixes or whitespace hash identically.
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+///
+/// # Returns
+/// * `{:ok, <<hash::64>>}` on success (big-endian)
+/// * `{:error, {:parse_error, message}}` on parse failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn query_hash<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
+    let query = match Query::parse(sparql, None) {
+        Ok(query) => query,
+        Err(e) => {
+            let error_msg = e.to_string();
+            return Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env));
+        }
+    };
+
+    Ok((atoms::ok(), query_hash_to_term(env, &query)).encode(env))
+}
+
+/// Hashes the canonicalized SSE form of a query into an 8-byte big-endian binary.
+fn query_hash_to_term<'a>(env: Env<'a>, query: &Query) -> Term<'a> {
+    let normalized = canonicalize_sse(&query.to_sse());
+    let hash = normalized.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    });
+
+    let mut binary = NewBinary::new(env, 8);
+    binary.as_mut_slice().copy_from_slice(&hash.to_be_bytes());
+    Binary::from(binary).encode(env)
+}
+
+/// Renames variables and blank nodes in SSE text to positional names.
+///
+/// Names are assigned in order of first appearance, which also covers the
+/// randomly generated names spargebra uses for anonymous blank nodes and
+/// aggregate results. Quoted strings and IRIs are copied verbatim.
+fn canonicalize_sse(sse: &str) -> String {
+    let mut out = String::with_capacity(sse.len());
+    let mut variables: HashMap<&str, usize> = HashMap::new();
+    let mut blank_nodes: HashMap<&str, usize> = HashMap::new();
+    let bytes = sse.as_bytes();
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let at_boundary = pos == 0 || matches!(bytes[pos - 1], b' ' | b'(');
+        match bytes[pos] {
+            b'"' => {
+                let end = quoted_end(bytes, pos);
+                out.push_str(&sse[pos..end]);
+                pos = end;
+            }
+            // IRIs never contain spaces; `<` and `<=` operators are followed by one
+            b'<' if bytes
+                .get(pos + 1)
+                .is_some_and(|b| !matches!(b, b' ' | b'=')) =>
+            {
+                let end = sse[pos..].find('>').map_or(bytes.len(), |i| pos + i + 1);
+                out.push_str(&sse[pos..end]);
+                pos = end;
+            }
+            b'?' if at_boundary => {
+                let end = sse_token_end(bytes, pos + 1);
+                let next = variables.len();
+                let index = *variables.entry(&sse[pos + 1..end]).or_insert(next);
+                out.push_str(&format!("?v{}", index));
+                pos = end;
+            }
+            b'_' if at_boundary && bytes.get(pos + 1) == Some(&b':') => {
+                let end = sse_token_end(bytes, pos + 2);
+                let next = blank_nodes.len();
+                let index = *blank_nodes.entry(&sse[pos + 2..end]).or_insert(next);
+                out.push_str(&format!("_:b{}", index));
+                pos = end;
+            }
+            _ => {
+                let end = pos + sse[pos..].chars().next().map_or(1, char::len_utf8);
+                out.push_str(&sse[pos..end]);
+                pos = end;
+            }
+        }
+    }
+
+    out
+}
+
+/// Returns the position just past the closing quote of the string starting at `start`.
+fn quoted_end(bytes: &[u8], start: usize) -> usize {
+    let mut pos = start + 1;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\\' => pos += 2,
+            b'"' => return pos + 1,
+            _ => pos += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// Returns the end of a name token (variable or blank node label).
+fn sse_token_end(bytes: &[u8], start: usize) -> usize {
+    bytes[start..]
+        .iter()
+        .position(|b| matches!(b, b' ' | b'(' | b')'))
+        .map_or(bytes.len(), |i| start + i)
+}
+
+/// Computes a 32-byte structural signature of a query for plan caching.
+///
+/// As for `query_hash`, the query is rendered to SSE with variables and blank
+/// nodes renamed positionally, so variable names, blank node labels, prefixes
+/// and whitespace don't matter. Before rendering, commutative operands are put
+/// in a canonical order:
+/// * the triples of a basic graph pattern
+/// * the operands of a chain of joins (`{A} {B}`) or of UNIONs, which is also
+///   re-associated, so `{A} UNION ({B} UNION {C})` matches `{C} UNION {A} UNION {B}`
+/// * the operands of `&&` and `||` chains (re-associated the same way), of
+///   `=`, `sameTerm`, `+` and `*`, and the list of `IN`/`NOT IN`
+///
+/// Operands are ordered by their own canonical SSE, which doesn't depend on
+/// variable names. Operands that differ only in how their variables link to
+/// the rest of the query compare equal and keep their written order, so such
+/// reorderings may still change the signature. Everything else is kept:
+/// OPTIONAL and MINUS sides, FILTER and BIND placement, the projection order
+/// (`SELECT *` projects in variable name order, so renamings that change that
+/// order change the signature), solution modifiers, CONSTRUCT templates,
+/// GROUP BY order and literal lexical forms. The normalized text is hashed
+/// with SHA-256.
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+///
+/// # Returns
+/// * `{:ok, <<signature::256>>}` on success
+/// * `{:error, {:parse_error, message}}` on parse failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn query_signature<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
+    let query = match Query::parse(sparql, None) {
+        Ok(query) => query,
+        Err(e) => {
+            let error_msg = e.to_string();
+            return Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env));
+        }
+    };
+
+    let query = match query {
+        Query::Select {
+            dataset,
+            pattern,
+            base_iri,
+        } => Query::Select {
+            dataset,
+            pattern: normalize_pattern(pattern),
+            base_iri,
+        },
+        Query::Construct {
+            template,
+            dataset,
+            pattern,
+            base_iri,
+        } => Query::Construct {
+            template,
+            dataset,
+            pattern: normalize_pattern(pattern),
+            base_iri,
+        },
+        Query::Describe {
+            dataset,
+            pattern,
+            base_iri,
+        } => Query::Describe {
+            dataset,
+            pattern: normalize_pattern(pattern),
+            base_iri,
+        },
+        Query::Ask {
+            dataset,
+            pattern,
+            base_iri,
+        } => Query::Ask {
+            dataset,
+            pattern: normalize_pattern(pattern),
+            base_iri,
+        },
+    };
+    let digest = Sha256::digest(canonicalize_sse(&query.to_sse()).as_bytes());
+
+    let mut binary = NewBinary::new(env, digest.len());
+    binary.as_mut_slice().copy_from_slice(&digest);
+    Ok((atoms::ok(), Binary::from(binary)).encode(env))
+}
+
+/// Puts the commutative operands of a graph pattern in canonical order, as
+/// described on `query_signature`.
+fn normalize_pattern(pattern: GraphPattern) -> GraphPattern {
+    let normalize = |inner: Box<GraphPattern>| Box::new(normalize_pattern(*inner));
+    match pattern {
+        GraphPattern::Bgp { mut patterns } => {
+            patterns.sort_by_cached_key(|tp| {
+                pattern_sort_key(&GraphPattern::Bgp {
+                    patterns: vec![tp.clone()],
+                })
+            });
+            GraphPattern::Bgp { patterns }
+        }
+        GraphPattern::Join { .. } => {
+            let mut operands = Vec::new();
+            collect_join_operands(pattern, &mut operands);
+            normalize_pattern_chain(operands, |left, right| GraphPattern::Join { left, right })
+        }
+        GraphPattern::Union { .. } => {
+            let mut operands = Vec::new();
+            collect_union_operands(pattern, &mut operands);
+            normalize_pattern_chain(operands, |left, right| GraphPattern::Union { left, right })
+        }
+        GraphPattern::LeftJoin {
+            left,
+            right,
+            expression,
+        } => GraphPattern::LeftJoin {
+            left: normalize(left),
+            right: normalize(right),
+            expression: expression.map(normalize_expression),
+        },
+        GraphPattern::Minus { left, right } => GraphPattern::Minus {
+            left: normalize(left),
+            right: normalize(right),
+        },
+        GraphPattern::Filter { expr, inner } => GraphPattern::Filter {
+            expr: normalize_expression(expr),
+            inner: normalize(inner),
+        },
+        GraphPattern::Extend {
+            inner,
+            variable,
+            expression,
+        } => GraphPattern::Extend {
+            inner: normalize(inner),
+            variable,
+            expression: normalize_expression(expression),
+        },
+        GraphPattern::OrderBy { inner, expression } => GraphPattern::OrderBy {
+            inner: normalize(inner),
+            expression: expression
+                .into_iter()
+                .map(|order| match order {
+                    OrderExpression::Asc(expr) => OrderExpression::Asc(normalize_expression(expr)),
+                    OrderExpression::Desc(expr) => {
+                        OrderExpression::Desc(normalize_expression(expr))
+                    }
+                })
+                .collect(),
+        },
+        GraphPattern::Group {
+            inner,
+            variables,
+            aggregates,
+        } => GraphPattern::Group {
+            inner: normalize(inner),
+            variables,
+            aggregates: aggregates
+                .into_iter()
+                .map(|(variable, aggregate)| match aggregate {
+                    AggregateExpression::FunctionCall {
+                        name,
+                        expr,
+                        distinct,
+                    } => (
+                        variable,
+                        AggregateExpression::FunctionCall {
+                            name,
+                            expr: normalize_expression(expr),
+                            distinct,
+                        },
+                    ),
+                    count @ AggregateExpression::CountSolutions { .. } => (variable, count),
+                })
+                .collect(),
+        },
+        GraphPattern::Graph { name, inner } => GraphPattern::Graph {
+            name,
+            inner: normalize(inner),
+        },
+        GraphPattern::Service {
+            name,
+            inner,
+            silent,
+        } => GraphPattern::Service {
+            name,
+            inner: normalize(inner),
+            silent,
+        },
+        GraphPattern::Project { inner, variables } => GraphPattern::Project {
+            inner: normalize(inner),
+            variables,
+        },
+        GraphPattern::Distinct { inner } => GraphPattern::Distinct {
+            inner: normalize(inner),
+        },
+        GraphPattern::Reduced { inner } => GraphPattern::Reduced {
+            inner: normalize(inner),
+        },
+        GraphPattern::Slice {
+            inner,
+            start,
+            length,
+        } => GraphPattern::Slice {
+            inner: normalize(inner),
+            start,
+            length,
+        },
+        GraphPattern::Path { .. } | GraphPattern::Values { .. } => pattern,
+    }
+}
+
+/// Puts the commutative operands of an expression in canonical order, as
+/// described on `query_signature`.
+fn normalize_expression(expr: Expression) -> Expression {
+    let normalize = |inner: Box<Expression>| Box::new(normalize_expression(*inner));
+    let sorted_pair = |left: Box<Expression>, right: Box<Expression>| {
+        let (left, right) = (normalize(left), normalize(right));
+        if expression_sort_key(&right) < expression_sort_key(&left) {
+            (right, left)
+        } else {
+            (left, right)
+        }
+    };
+    match expr {
+        Expression::NamedNode(_)
+        | Expression::Literal(_)
+        | Expression::Variable(_)
+        | Expression::Bound(_) => expr,
+        Expression::And(..) => {
+            let mut operands = Vec::new();
+            collect_and_operands(expr, &mut operands);
+            normalize_expression_chain(operands, Expression::And)
+        }
+        Expression::Or(..) => {
+            let mut operands = Vec::new();
+            collect_or_operands(expr, &mut operands);
+            normalize_expression_chain(operands, Expression::Or)
+        }
+        Expression::Equal(left, right) => {
+            let (left, right) = sorted_pair(left, right);
+            Expression::Equal(left, right)
+        }
+        Expression::SameTerm(left, right) => {
+            let (left, right) = sorted_pair(left, right);
+            Expression::SameTerm(left, right)
+        }
+        Expression::Add(left, right) => {
+            let (left, right) = sorted_pair(left, right);
+            Expression::Add(left, right)
+        }
+        Expression::Multiply(left, right) => {
+            let (left, right) = sorted_pair(left, right);
+            Expression::Multiply(left, right)
+        }
+        Expression::In(needle, list) => {
+            let mut list: Vec<Expression> = list.into_iter().map(normalize_expression).collect();
+            list.sort_by_cached_key(expression_sort_key);
+            Expression::In(normalize(needle), list)
+        }
+        Expression::Greater(left, right) => Expression::Greater(normalize(left), normalize(right)),
+        Expression::GreaterOrEqual(left, right) => {
+            Expression::GreaterOrEqual(normalize(left), normalize(right))
+        }
+        Expression::Less(left, right) => Expression::Less(normalize(left), normalize(right)),
+        Expression::LessOrEqual(left, right) => {
+            Expression::LessOrEqual(normalize(left), normalize(right))
+        }
+        Expression::Subtract(left, right) => {
+            Expression::Subtract(normalize(left), normalize(right))
+        }
+        Expression::Divide(left, right) => Expression::Divide(normalize(left), normalize(right)),
+        Expression::UnaryPlus(inner) => Expression::UnaryPlus(normalize(inner)),
+        Expression::UnaryMinus(inner) => Expression::UnaryMinus(normalize(inner)),
+        Expression::Not(inner) => Expression::Not(normalize(inner)),
+        Expression::Exists(pattern) => Expression::Exists(Box::new(normalize_pattern(*pattern))),
+        Expression::If(condition, then, otherwise) => {
+            Expression::If(normalize(condition), normalize(then), normalize(otherwise))
+        }
+        Expression::Coalesce(args) => {
+            Expression::Coalesce(args.into_iter().map(normalize_expression).collect())
+        }
+        Expression::FunctionCall(function, args) => Expression::FunctionCall(
+            function,
+            args.into_iter().map(normalize_expression).collect(),
+        ),
+    }
+}
+
+/// Normalizes the operands of a join or UNION chain, sorts them and rebuilds
+/// the chain left-deep with `combine`.
+fn normalize_pattern_chain(
+    operands: Vec<GraphPattern>,
+    combine: fn(Box<GraphPattern>, Box<GraphPattern>) -> GraphPattern,
+) -> GraphPattern {
+    let mut operands: Vec<GraphPattern> = operands.into_iter().map(normalize_pattern).collect();
+    operands.sort_by_cached_key(pattern_sort_key);
+    let mut operands = operands.into_iter();
+    let first = operands.next().unwrap_or_default();
+    operands.fold(first, |left, right| {
+        combine(Box::new(left), Box::new(right))
+    })
+}
+
+/// Normalizes the operands of an `&&` or `||` chain, sorts them and rebuilds
+/// the chain left-deep with `combine`.
+fn normalize_expression_chain(
+    operands: Vec<Expression>,
+    combine: fn(Box<Expression>, Box<Expression>) -> Expression,
+) -> Expression {
+    let mut operands: Vec<Expression> = operands.into_iter().map(normalize_expression).collect();
+    operands.sort_by_cached_key(expression_sort_key);
+    let mut operands = operands.into_iter();
+    let first = operands.next().unwrap_or(Expression::Literal(true.into()));
+    operands.fold(first, |left, right| {
+        combine(Box::new(left), Box::new(right))
+    })
+}
+
+fn collect_join_operands(pattern: GraphPattern, out: &mut Vec<GraphPattern>) {
+    match pattern {
+        GraphPattern::Join { left, right } => {
+            collect_join_operands(*left, out);
+            collect_join_operands(*right, out);
+        }
+        other => out.push(other),
+    }
+}
+
+fn collect_union_operands(pattern: GraphPattern, out: &mut Vec<GraphPattern>) {
+    match pattern {
+        GraphPattern::Union { left, right } => {
+            collect_union_operands(*left, out);
+            collect_union_operands(*right, out);
+        }
+        other => out.push(other),
+    }
+}
+
+fn collect_and_operands(expr: Expression, out: &mut Vec<Expression>) {
+    match expr {
+        Expression::And(left, right) => {
+            collect_and_operands(*left, out);
+            collect_and_operands(*right, out);
+        }
+        other => out.push(other),
+    }
+}
+
+fn collect_or_operands(expr: Expression, out: &mut Vec<Expression>) {
+    match expr {
+        Expression::Or(left, right) => {
+            collect_or_operands(*left, out);
+            collect_or_operands(*right, out);
+        }
+        other => out.push(other),
+    }
+}
+
+/// Sort key of a graph pattern that ignores variable and blank node names:
+/// its SSE form, canonicalized on its own.
+fn pattern_sort_key(pattern: &GraphPattern) -> String {
+    let query = Query::Select {
+        dataset: None,
+        pattern: pattern.clone(),
+        base_iri: None,
+    };
+    canonicalize_sse(&query.to_sse())
+}
+
+/// Sort key of an expression, see `pattern_sort_key`.
+fn expression_sort_key(expr: &Expression) -> String {
+    pattern_sort_key(&GraphPattern::Filter {
+        expr: expr.clone(),
+        inner: Box::default(),
+    })
+}
+
+/// Parses a query once and returns everything the query pipeline needs up front.
+///
+/// Replaces separate calls to `parse_query`, `optional_dependencies`,
+/// `property_path_plan` and `query_hash`; those entries use the same helpers
+/// and match the standalone results. The map contains:
+/// * `form` - `:select`, `:construct`, `:ask` or `:describe`
+/// * `variables` - variables in scope in the WHERE clause, sorted
+/// * `projected` - SELECT/DESCRIBE projection in order (`[]` otherwise)
+/// * `triple_patterns` - every basic graph pattern triple, in pre-order
+/// * `named_graphs` - IRIs from `FROM NAMED` and `GRAPH <iri>`, sorted
+/// * `modifiers` - `%{distinct, reduced, order_by, limit, offset}` from the
+///   solution modifiers wrapped around the WHERE clause
+/// * `warnings` - `{:unbound_projection, var}` for projected variables the
+///   WHERE clause never binds, `{:cartesian_product, triple_patterns}` for
+///   each basic graph pattern whose triples don't share variables
+/// * `optional_dependencies`, `property_paths`, `hash` - as returned by the
+///   standalone functions
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+///
+/// # Returns
+/// * `{:ok, analysis}` on success
+/// * `{:error, {:parse_error, message}}` on parse failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn analyze_query<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
+    let query = match Query::parse(sparql, None) {
+        Ok(query) => query,
+        Err(e) => {
+            let error_msg = e.to_string();
+            return Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env));
+        }
+    };
+
+    let pattern = query_pattern(&query);
+    let form = query_form_atom(&query);
+
+    // Peel the solution modifiers spargebra wraps around the WHERE clause
+    let mut distinct = false;
+    let mut reduced = false;
+    let mut order_by: Vec<Term<'a>> = Vec::new();
+    let mut limit = rustler::types::atom::nil().encode(env);
+    let mut offset = 0usize;
+    let mut projected: Vec<String> = Vec::new();
+    let mut body = pattern;
+    loop {
+        match body {
+            GraphPattern::Slice {
+                inner,
+                start,
+                length,
+            } => {
+                offset = *start;
+                if let Some(length) = length {
+                    limit = length.encode(env);
+                }
+                body = inner;
+            }
+            GraphPattern::Distinct { inner } => {
+                distinct = true;
+                body = inner;
+            }
+            GraphPattern::Reduced { inner } => {
+                reduced = true;
+                body = inner;
+            }
+            GraphPattern::Project { inner, variables } => {
+                projected = variables.iter().map(|v| v.as_str().to_string()).collect();
+                body = inner;
+            }
+            GraphPattern::OrderBy { inner, expression } => {
+                order_by = expression
+                    .iter()
+                    .map(|oe| order_expression_to_term(env, oe))
+                    .collect();
+                body = inner;
+            }
+            _ => break,
+        }
+    }
+
+    let variables = in_scope_variables(body);
+
+    let mut named_graphs = BTreeSet::new();
+    if let Query::Select {
+        dataset: Some(dataset),
+        ..
+    }
+    | Query::Construct {
+        dataset: Some(dataset),
+        ..
+    }
+    | Query::Ask {
+        dataset: Some(dataset),
+        ..
+    }
+    | Query::Describe {
+        dataset: Some(dataset),
+        ..
+    } = &query
+    {
+        for graph in dataset.named.iter().flatten() {
+            named_graphs.insert(graph.as_str().to_string());
+        }
+    }
+
+    let mut bgps = Vec::new();
+    collect_bgps(pattern, &mut bgps, &mut named_graphs);
+
+    let mut warnings: Vec<Term<'a>> = projected
+        .iter()
+        .filter(|var| !variables.contains(*var))
+        .map(|var| (atoms::unbound_projection(), var).encode(env))
+        .collect();
+    let mut triple_patterns = Vec::new();
+    for bgp in bgps {
+        if !is_connected_bgp(bgp) {
+            let triples: Vec<Term<'a>> = bgp
+                .iter()
+                .map(|tp| triple_pattern_to_term(env, tp))
+                .collect();
+            warnings.push((atoms::cartesian_product(), triples).encode(env));
+        }
+        triple_patterns.extend(bgp.iter().map(|tp| triple_pattern_to_term(env, tp)));
+    }
+
+    let mut dependencies = Vec::new();
+    collect_left_join_dependencies(pattern, &mut dependencies);
+    let mut path_plans = Vec::new();
+    collect_path_plans(env, pattern, &mut path_plans);
+
+    let modifiers = Term::map_from_pairs(
+        env,
+        &[
+            (atoms::distinct().encode(env), distinct.encode(env)),
+            (atoms::reduced().encode(env), reduced.encode(env)),
+            (atoms::order_by().encode(env), order_by.encode(env)),
+            (atoms::limit().encode(env), limit),
+            (atoms::offset().encode(env), offset.encode(env)),
+        ],
+    )?;
+
+    let analysis = Term::map_from_pairs(
+        env,
+        &[
+            (atoms::form().encode(env), form.encode(env)),
+            (
+                atoms::variables().encode(env),
+                variables.into_iter().collect::<Vec<_>>().encode(env),
+            ),
+            (atoms::projected().encode(env), projected.encode(env)),
+            (
+                atoms::triple_patterns().encode(env),
+                triple_patterns.encode(env),
+            ),
+            (
+                atoms::named_graphs().encode(env),
+                named_graphs.into_iter().collect::<Vec<_>>().encode(env),
+            ),
+            (atoms::modifiers().encode(env), modifiers),
+            (atoms::warnings().encode(env), warnings.encode(env)),
+            (
+                atoms::optional_dependencies().encode(env),
+                dependencies.encode(env),
+            ),
+            (atoms::property_paths().encode(env), path_plans.encode(env)),
+            (atoms::hash().encode(env), query_hash_to_term(env, &query)),
+        ],
+    )?;
+
+    Ok((atoms::ok(), analysis).encode(env))
+}
+
+/// Walks a graph pattern in pre-order, collecting basic graph patterns and the
+/// IRIs of `GRAPH <iri>` patterns.
+fn collect_bgps<'p>(
+    pattern: &'p GraphPattern,
+    bgps: &mut Vec<&'p [TriplePattern]>,
+    graphs: &mut BTreeSet<String>,
+) {
+    match pattern {
+        GraphPattern::Bgp { patterns } => bgps.push(patterns),
+        GraphPattern::Graph { name, inner } => {
+            if let NamedNodePattern::NamedNode(nn) = name {
+                graphs.insert(nn.as_str().to_string());
+            }
+            collect_bgps(inner, bgps, graphs);
+        }
+        GraphPattern::Join { left, right }
+        | GraphPattern::LeftJoin { left, right, .. }
+        | GraphPattern::Union { left, right }
+        | GraphPattern::Minus { left, right } => {
+            collect_bgps(left, bgps, graphs);
+            collect_bgps(right, bgps, graphs);
+        }
+        GraphPattern::Filter { inner, .. }
+        | GraphPattern::Extend { inner, .. }
+        | GraphPattern::OrderBy { inner, .. }
+        | GraphPattern::Project { inner, .. }
+        | GraphPattern::Distinct { inner }
+        | GraphPattern::Reduced { inner }
+        | GraphPattern::Slice { inner, .. }
+        | GraphPattern::Group { inner, .. }
+        | GraphPattern::Service { inner, .. } => collect_bgps(inner, bgps, graphs),
+        GraphPattern::Path { .. } | GraphPattern::Values { .. } => {}
+    }
+}
+
+/// Whether the triples of a basic graph pattern form one group linked by
+/// shared variables or blank nodes. Variable-free triples count as linked.
+fn is_connected_bgp(patterns: &[TriplePattern]) -> bool {
+    fn add_names(tp: &TriplePattern, names: &mut Vec<String>) {
+        for term in [&tp.subject, &tp.object] {
+            match term {
+                TermPattern::Variable(v) => names.push(format!("?{}", v.as_str())),
+                TermPattern::BlankNode(b) => names.push(format!("_:{}", b.as_str())),
+                TermPattern::Triple(quoted) => add_names(quoted, names),
+                _ => {}
+            }
+        }
+        if let NamedNodePattern::Variable(v) = &tp.predicate {
+            names.push(format!("?{}", v.as_str()));
+        }
+    }
+    let names = |tp: &TriplePattern| -> Vec<String> {
+        let mut names = Vec::new();
+        add_names(tp, &mut names);
+        names
+    };
+
+    let groups: Vec<Vec<String>> = patterns
+        .iter()
+        .map(names)
+        .filter(|n| !n.is_empty())
+        .collect();
+    if groups.len() <= 1 {
+        return true;
+    }
+
+    // Grow one component from the first triple until no more triples join it
+    let mut reached: HashSet<&str> = groups[0].iter().map(String::as_str).collect();
+    let mut joined = vec![false; groups.len()];
+    joined[0] = true;
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (i, group) in groups.iter().enumerate() {
+            if !joined[i] && group.iter().any(|name| reached.contains(name.as_str())) {
+                joined[i] = true;
+                reached.extend(group.iter().map(String::as_str));
+                changed = true;
+            }
+        }
+    }
+    joined.into_iter().all(|j| j)
+}
+
+/// Computes the variable-sharing graph between a query's triple patterns.
+///
+/// `patterns` is the flattened triple-pattern list, in the same pre-order as
+/// `analyze_query`'s `triple_patterns`, so indices line up. Each edge
+/// `{i, j, shared_vars}` (with `i < j`) connects two patterns that share at
+/// least one variable; `shared_vars` are the variable names, sorted. Only
+/// variables count as join keys: blank nodes are scoped to a single basic
+/// graph pattern and property path patterns are not included in the list.
+///
+/// Patterns are indexed by variable in one pass; edges are then derived from
+/// each variable's pattern list and returned sorted by `{i, j}`.
+///
+/// # Arguments
+/// * `sparql` - The SPARQL query string to parse
+///
+/// # Returns
+/// * `{:ok, %{patterns: [...], edges: [{i, j, shared_vars}, ...]}}` on success
+/// * `{:error, {:parse_error, message}}` on parse failure
+#[rustler::nif(schedule = "DirtyCpu")]
+fn join_graph<'a>(env: Env<'a>, sparql: &str) -> NifResult<Term<'a>> {
+    let query = match Query::parse(sparql, None) {
+        Ok(query) => query,
+        Err(e) => {
+            let error_msg = e.to_string();
+            return Ok((atoms::error(), (atoms::parse_error(), error_msg)).encode(env));
+        }
+    };
+
+    let mut bgps = Vec::new();
+    let mut graphs = BTreeSet::new();
+    collect_bgps(query_pattern(&query), &mut bgps, &mut graphs);
+
+    let mut pattern_terms = Vec::new();
+    let mut by_variable: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (index, tp) in bgps.iter().flat_map(|bgp| bgp.iter()).enumerate() {
+        pattern_terms.push(triple_pattern_to_term(env, tp));
+
+        let mut variables: Vec<&str> = Vec::new();
+        for term in [&tp.subject, &tp.object] {
+            if let TermPattern::Variable(v) = term {
+                variables.push(v.as_str());
+            }
+        }
+        if let NamedNodePattern::Variable(v) = &tp.predicate {
+            variables.push(v.as_str());
+        }
+        variables.sort_unstable();
+        variables.dedup();
+        for variable in variables {
+            by_variable.entry(variable).or_default().push(index);
+        }
+    }
+
+    let mut edges: BTreeMap<(usize, usize), Vec<&str>> = BTreeMap::new();
+    for (variable, indices) in &by_variable {
+        for (n, &i) in indices.iter().enumerate() {
+            for &j in &indices[n + 1..] {
+                edges.entry((i, j)).or_default().push(variable);
+            }
+        }
+    }
+
+    // Variables are visited in sorted order, so each shared list is sorted
+    let edge_terms: Vec<Term<'a>> = edges
+        .into_iter()
+        .map(|((i, j), shared)| (i, j, shared).encode(env))
+        .collect();
+
+    let pairs = [
+        (atoms::patterns().encode(env), pattern_terms.encode(env)),
+        (atoms::edges().encode(env), edge_terms.encode(env)),
+    ];
+    Ok((atoms::ok(), Term::map_from_pairs(env, &pairs)?).encode(env))
+}
+
+// ===========================================================================
+// RDF Document Parsing
+// ===========================================================================
+
+/// Parses a Turtle document into triples.
+///
+/// Terms use the same encoding as the SPARQL parser. Blank node labels are
+/// scoped to the document: the same label within one call is the same node.
+/// Labels are kept as written by default, so `_:a` from two separate calls
+/// would collide once stored. With `scope_blank_nodes: true` every label is
+/// rewritten to `<unique>_<label>`, where `<unique>` is a random 128-bit hex
+/// prefix drawn once per call, so blank nodes from different loads never
+/// share an identity.
+///
+/// # Arguments
+/// * `data` - The Turtle document
+/// * `opts` - Keyword list; supports `scope_blank_nodes: boolean`
+///
+/// # Returns
+/// * `{:ok, [{s, p, o}, ...]}` on success
+/// * `{:error, {:parse_error, {line, message}}}` on the first syntax error
+///   (1-based line)
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_turtle<'a>(
+    env: Env<'a>,
+    data: Binary<'a>,
+    opts: Vec<(rustler::Atom, bool)>,
+) -> NifResult<Term<'a>> {
+    let scope = blank_node_scope(&opts);
+    let triples = TurtleParser::new().for_slice(data.as_slice());
+    Ok(collect_parsed_triples(env, triples, scope.as_deref()))
+}
+
+/// Parses an N-Triples document into triples.
+///
+/// Same term encoding, blank node scoping and options as `parse_turtle`.
+///
+/// # Arguments
+/// * `data` - The N-Triples document
+/// * `opts` - Keyword list; supports `scope_blank_nodes: boolean`
+///
+/// # Returns
+/// * `{:ok, [{s, p, o}, ...]}` on success
+/// * `{:error, {:parse_error, {line, message}}}` on the first syntax error
+///   (1-based line)
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_ntriples<'a>(
+    env: Env<'a>,
+    data: Binary<'a>,
+    opts: Vec<(rustler::Atom, bool)>,
+) -> NifResult<Term<'a>> {
+    let scope = blank_node_scope(&opts);
+    let triples = NTriplesParser::new().for_slice(data.as_slice());
+    Ok(collect_parsed_triples(env, triples, scope.as_deref()))
+}
+
+/// Parses an N-Quads document into quads.
+///
+/// Subject, predicate and object use the same encoding as `parse_turtle`. The
+/// graph is `{:named_node, iri}`, `{:blank_node, id}` or `:default` for
+/// statements without a graph label.
+///
+/// With `on_error: :abort` (the default) parsing stops at the first syntax
+/// error. With `on_error: :skip` invalid lines are skipped and their errors
+/// are collected as `{line, message}` in document order.
+///
+/// # Arguments
+/// * `data` - The N-Quads document
+/// * `opts` - Map; supports `on_error: :abort | :skip`
+///
+/// # Returns
+/// * `{:ok, [{s, p, o, graph}, ...]}` when aborting on errors
+/// * `{:ok, [{s, p, o, graph}, ...], [{line, message}, ...]}` when skipping
+/// * `{:error, {:parse_error, {line, message}}}` on the first syntax error
+///   when aborting (1-based line)
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_nquads<'a>(env: Env<'a>, data: Binary<'a>, opts: Term<'a>) -> NifResult<Term<'a>> {
+    let skip_errors = match opts.map_get(atoms::on_error()) {
+        Ok(value) => {
+            let mode: rustler::Atom = value.decode()?;
+            if mode == atoms::skip() {
+                true
+            } else if mode == atoms::abort() {
+                false
+            } else {
+                return Err(rustler::Error::BadArg);
+            }
+        }
+        Err(_) => false,
+    };
+
+    let mut quads = Vec::new();
+    let mut errors = Vec::new();
+    for quad in NQuadsParser::new().for_slice(data.as_slice()) {
+        match quad {
+            Ok(quad) => quads.push(parsed_quad_to_term(env, &quad)),
+            Err(e) if skip_errors => errors.push(syntax_error_to_term(env, &e)),
+            Err(e) => {
+                return Ok((
+                    atoms::error(),
+                    (atoms::parse_error(), syntax_error_to_term(env, &e)),
+                )
+                    .encode(env))
+            }
+        }
+    }
+
+    if skip_errors {
+        Ok((atoms::ok(), quads, errors).encode(env))
+    } else {
+        Ok((atoms::ok(), quads).encode(env))
+    }
+}
+
+/// Encodes a parsed quad as `{s, p, o, graph}`.
+fn parsed_quad_to_term<'a>(env: Env<'a>, quad: &oxrdf::Quad) -> Term<'a> {
+    let graph = match &quad.graph_name {
+        oxrdf::GraphName::NamedNode(node) => named_node_to_term(env, node),
+        oxrdf::GraphName::BlankNode(node) => blank_node_to_term(env, node),
+        oxrdf::GraphName::DefaultGraph => atoms::default().encode(env),
+    };
+    (
+        subject_to_term(env, &quad.subject),
+        named_node_to_term(env, &quad.predicate),
+        spargebra_term_to_elixir_term(env, &quad.object),
+        graph,
+    )
+        .encode(env)
+}
+
+/// Returns the per-call blank node label prefix if `scope_blank_nodes` is set.
+fn blank_node_scope(opts: &[(rustler::Atom, bool)]) -> Option<String> {
+    let scoped = opts
+        .iter()
+        .any(|(key, value)| *key == atoms::scope_blank_nodes() && *value);
+    // A fresh default blank node id is a random 128-bit hex string
+    scoped.then(|| format!("{}_", BlankNode::default().as_str()))
+}
+
+/// Encodes parsed triples, stopping at the first syntax error.
+fn collect_parsed_triples<'a>(
+    env: Env<'a>,
+    triples: impl Iterator<Item = Result<spargebra::term::Triple, TurtleSyntaxError>>,
+    scope: Option<&str>,
+) -> Term<'a> {
+    let mut terms = Vec::new();
+    for triple in triples {
+        match triple {
+            Ok(triple) => terms.push(parsed_triple_to_term(env, &triple, scope)),
+            Err(e) => {
+                return (
+                    atoms::error(),
+                    (atoms::parse_error(), syntax_error_to_term(env, &e)),
+                )
+                    .encode(env)
+            }
+        }
+    }
+    (atoms::ok(), terms).encode(env)
+}
+
+/// Encodes a parsed triple as `{s, p, o}`, applying the blank node scope.
+fn parsed_triple_to_term<'a>(
+    env: Env<'a>,
+    triple: &spargebra::term::Triple,
+    scope: Option<&str>,
+) -> Term<'a> {
+    (
+        parsed_subject_to_term(env, &triple.subject, scope),
+        named_node_to_term(env, &triple.predicate),
+        parsed_object_to_term(env, &triple.object, scope),
+    )
+        .encode(env)
+}
+
+/// Converts a parsed subject, applying the blank node scope.
+fn parsed_subject_to_term<'a>(env: Env<'a>, subject: &Subject, scope: Option<&str>) -> Term<'a> {
+    match subject {
+        Subject::BlankNode(bn) => scoped_blank_node_to_term(env, bn, scope),
+        Subject::Triple(triple) => parsed_triple_term_to_term(env, triple, scope),
+        _ => subject_to_term(env, subject),
+    }
+}
+
+/// Converts a parsed object, applying the blank node scope.
+fn parsed_object_to_term<'a>(
+    env: Env<'a>,
+    object: &spargebra::term::Term,
+    scope: Option<&str>,
+) -> Term<'a> {
+    match object {
+        spargebra::term::Term::BlankNode(bn) => scoped_blank_node_to_term(env, bn, scope),
+        spargebra::term::Term::Triple(triple) => parsed_triple_term_to_term(env, triple, scope),
+        _ => spargebra_term_to_elixir_term(env, object),
+    }
+}
+
+/// Converts a quoted triple (Turtle-star), applying the blank node scope.
+fn parsed_triple_term_to_term<'a>(env: Env<'a>, triple: &Triple, scope: Option<&str>) -> Term<'a> {
+    (
+        atoms::triple_term(),
+        parsed_subject_to_term(env, &triple.subject, scope),
+        named_node_to_term(env, &triple.predicate),
+        parsed_object_to_term(env, &triple.object, scope),
+    )
+        .encode(env)
+}
+
+/// Encodes a blank node, prefixing its label with the scope if there is one.
+fn scoped_blank_node_to_term<'a>(env: Env<'a>, bn: &BlankNode, scope: Option<&str>) -> Term<'a> {
+    match scope {
+        Some(scope) => (atoms::blank_node(), format!("{}{}", scope, bn.as_str())).encode(env),
+        None => blank_node_to_term(env, bn),
+    }
+}
+
+/// Encodes a syntax error as `{line, message}` with a 1-based line.
+fn syntax_error_to_term<'a>(env: Env<'a>, error: &TurtleSyntaxError) -> Term<'a> {
+    (error.location().start.line + 1, error.message()).encode(env)
+}
+
+// ===========================================================================
+// Streaming Turtle Parsing
+// ===========================================================================
+
+/// Source of a streamed Turtle document.
+enum TurtleSource {
+    /// In-memory document, saved in the stream's environment
+    Data(SavedTerm),
+    /// File read incrementally through a buffered reader
+    File(File),
+}
+
+/// Upper bound for `parse_turtle_stream`'s `chunk_size`. Larger values are
+/// clamped, so the chunk buffer allocated up front stays bounded.
+const MAX_TURTLE_CHUNK_SIZE: usize = 100_000;
+
+/// Parses a Turtle document incrementally, sending triples to `pid` in chunks.
+///
+/// The NIF only validates its arguments and opens the file; parsing runs on a
+/// background thread so chunks can be consumed while the document is still
+/// being read. Each chunk is built in its own environment and released once
+/// sent, so memory stays bounded by `chunk_size` on the parser side. There is
+/// no backpressure: the parser never waits for the consumer, so a consumer
+/// slower than the parser accumulates chunks in its mailbox.
+///
+/// Messages sent to `pid`:
+/// * `{:turtle_chunk, ref, triples}` - up to `chunk_size` triples
+/// * `{:turtle_done, ref, total}` - after the last chunk
+/// * `{:turtle_error, ref, {line, message}}` - syntax error (1-based line);
+///   triples parsed before the error are delivered first
+/// * `{:turtle_error, ref, {:io_error, message}}` - read failure
+/// * `{:turtle_error, ref, {:internal_error, message}}` - the parser thread
+///   panicked; triples sent before it stay delivered
+///
+/// Parsing stops early if `pid` is no longer alive.
+///
+/// # Arguments
+/// * `source` - The document as a binary, or `{:file, path}`
+/// * `pid` - Process receiving the messages
+/// * `chunk_size` - Maximum number of triples per chunk (must be positive,
+///   clamped to `MAX_TURTLE_CHUNK_SIZE`)
+///
+/// # Returns
+/// * `{:ok, ref}` - Parsing started; `ref` tags every message
+/// * `{:error, {:io_error, reason}}` - The file could not be opened, or the
+///   parser thread could not be started
+#[rustler::nif(schedule = "DirtyIo")]
+fn parse_turtle_stream<'a>(
+    env: Env<'a>,
+    source: Term<'a>,
+    pid: LocalPid,
+    chunk_size: usize,
+) -> NifResult<Term<'a>> {
+    if chunk_size == 0 {
+        return Err(rustler::Error::BadArg);
+    }
+    let chunk_size = chunk_size.min(MAX_TURTLE_CHUNK_SIZE);
+
+    let stream_env = OwnedEnv::new();
+    let source = if source.decode::<Binary>().is_ok() {
+        TurtleSource::Data(stream_env.save(source))
+    } else {
+        let (tag, path): (rustler::Atom, String) = source.decode()?;
+        if tag != atoms::file() {
+            return Err(rustler::Error::BadArg);
+        }
+        match File::open(&path) {
+            Ok(file) => TurtleSource::File(file),
+            Err(e) => return Ok((atoms::error(), (atoms::io_error(), e.to_string())).encode(env)),
+        }
+    };
+
+    let reference = env.make_ref();
+    let saved_ref = stream_env.save(reference);
+    let spawned = std::thread::Builder::new()
+        .name("turtle_stream".to_string())
+        .spawn(move || stream_turtle(stream_env, saved_ref, source, pid, chunk_size));
+    if let Err(e) = spawned {
+        return Ok((atoms::error(), (atoms::io_error(), e.to_string())).encode(env));
+    }
+
+    Ok((atoms::ok(), reference).encode(env))
+}
+
+/// Background thread body for `parse_turtle_stream`.
+///
+/// A panic while parsing is caught and reported to `pid` as
+/// `{:turtle_error, ref, {:internal_error, message}}`, so the stream always
+/// ends with a done or error message.
+fn stream_turtle(
+    stream_env: OwnedEnv,
+    saved_ref: SavedTerm,
+    source: TurtleSource,
+    pid: LocalPid,
+    chunk_size: usize,
+) {
+    let parsed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        parse_turtle_source(&stream_env, &saved_ref, source, &pid, chunk_size)
+    }));
+    if let Err(panic) = parsed {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|m| m.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "turtle parser panicked".to_string());
+        stream_env.run(|env| {
+            let reference = saved_ref.load(env);
+            let _ = OwnedEnv::new().send_and_clear(&pid, |env| {
+                let reason = (atoms::internal_error(), message);
+                (atoms::turtle_error(), reference.in_env(env), reason)
+            });
+        });
+    }
+}
+
+/// Parses `source` and sends its triples to `pid`, see `send_turtle_chunks`.
+fn parse_turtle_source(
+    stream_env: &OwnedEnv,
+    saved_ref: &SavedTerm,
+    source: TurtleSource,
+    pid: &LocalPid,
+    chunk_size: usize,
+) {
+    stream_env.run(|env| {
+        let reference = saved_ref.load(env);
+        match source {
+            TurtleSource::Data(data) => {
+                if let Ok(data) = data.load(env).decode::<Binary>() {
+                    let triples = TurtleParser::new()
+                        .for_slice(data.as_slice())
+                        .map(|r| r.map_err(TurtleParseError::from));
+                    send_turtle_chunks(reference, triples, pid, chunk_size);
+                }
+            }
+            TurtleSource::File(file) => {
+                let triples = TurtleParser::new().for_reader(BufReader::new(file));
+                send_turtle_chunks(reference, triples, pid, chunk_size);
+            }
+        }
+    });
+}
+
+/// Sends parsed triples to `pid` in chunks, followed by a done or error message.
+fn send_turtle_chunks(
+    reference: Term,
+    triples: impl Iterator<Item = Result<spargebra::term::Triple, TurtleParseError>>,
+    pid: &LocalPid,
+    chunk_size: usize,
+) {
+    let mut msg_env = OwnedEnv::new();
+    let mut chunk = Vec::with_capacity(chunk_size);
+    let mut total: u64 = 0;
+
+    for triple in triples {
+        match triple {
+            Ok(triple) => {
+                chunk.push(triple);
+                if chunk.len() == chunk_size {
+                    total += chunk.len() as u64;
+                    if send_turtle_chunk(&mut msg_env, reference, &chunk, pid).is_err() {
+                        return;
+                    }
+                    chunk.clear();
+                }
+            }
+            Err(e) => {
+                if !chunk.is_empty()
+                    && send_turtle_chunk(&mut msg_env, reference, &chunk, pid).is_err()
+                {
+                    return;
+                }
+                let _ = msg_env.send_and_clear(pid, |env| {
+                    let reason = match &e {
+                        TurtleParseError::Syntax(e) => syntax_error_to_term(env, e),
+                        TurtleParseError::Io(e) => (atoms::io_error(), e.to_string()).encode(env),
+                    };
+                    (atoms::turtle_error(), reference.in_env(env), reason)
+                });
+                return;
+            }
+        }
+    }
+
+    if !chunk.is_empty() {
+        total += chunk.len() as u64;
+        if send_turtle_chunk(&mut msg_env, reference, &chunk, pid).is_err() {
+            return;
+        }
+    }
+    let _ = msg_env.send_and_clear(pid, |env| {
+        (atoms::turtle_done(), reference.in_env(env), total)
+    });
+}
+
+/// Sends one `{:turtle_chunk, ref, triples}` message.
+fn send_turtle_chunk(
+    msg_env: &mut OwnedEnv,
+    reference: Term,
+    chunk: &[spargebra::term::Triple],
+    pid: &LocalPid,
+) -> Result<(), rustler::env::SendError> {
+    msg_env.send_and_clear(pid, |env| {
+        let triples: Vec<Term> = chunk
+            .iter()
+            .map(|t| parsed_triple_to_term(env, t, None))
+            .collect();
+        (atoms::turtle_chunk(), reference.in_env(env), triples)
+    })
+}
+
+// ===========================================================================
+// Expression Serialization
+// ===========================================================================
+
+/// SPARQL operator precedence levels, loosest first. Follows the grammar's
+/// ConditionalOrExpression ... PrimaryExpression chain.
+const PREC_OR: u8 = 1;
+const PREC_AND: u8 = 2;
+const PREC_RELATIONAL: u8 = 3;
+const PREC_ADDITIVE: u8 = 4;
+const PREC_MULTIPLICATIVE: u8 = 5;
+const PREC_UNARY: u8 = 6;
+const PREC_PRIMARY: u8 = 7;
+
+/// Serializes an expression term back to SPARQL syntax.
+///
+/// Takes the encoding produced by `parse_query` for FILTER/BIND expressions
+/// and emits the minimal parentheses needed to preserve the tree: an operand
+/// is wrapped only when it binds looser than its position requires. Binary
+/// arithmetic and boolean operators are left-associative, so a right operand
+/// at the same level is wrapped (`?a - (?b - ?c)`). Relational operators do
+/// not chain, so both operands must bind tighter. Unary operators take a
+/// primary expression, so `-(?a + ?b)` and `!(?a = ?b)` keep their
+/// parentheses. Parsing the output yields the same expression term.
+///
+/// `EXISTS` / `NOT EXISTS` are not supported since they embed a graph pattern.
+///
+/// # Arguments
+/// * `expr` - Expression term
+///
+/// # Returns
+/// * `{:ok, sparql}` on success
+/// * `{:error, {:invalid_expression, term}}` with the first sub-term that
+///   cannot be serialized
+#[rustler::nif]
+fn serialize_expression<'a>(env: Env<'a>, expr: Term<'a>) -> NifResult<Term<'a>> {
+    match render_expression(expr) {
+        Ok((sparql, _)) => Ok((atoms::ok(), sparql).encode(env)),
+        Err(term) => Ok((atoms::error(), (atoms::invalid_expression(), term)).encode(env)),
+    }
+}
+
+/// Returns the symbol and precedence of a binary operator tag.
+fn binary_operator(tag: rustler::Atom) -> Option<(&'static str, u8)> {
+    let op = if tag == atoms::or() {
+        ("||", PREC_OR)
+    } else if tag == atoms::and() {
+        ("&&", PREC_AND)
+    } else if tag == atoms::equal() {
+        ("=", PREC_RELATIONAL)
+    } else if tag == atoms::greater() {
+        (">", PREC_RELATIONAL)
+    } else if tag == atoms::greater_or_equal() {
+        (">=", PREC_RELATIONAL)
+    } else if tag == atoms::less() {
+        ("<", PREC_RELATIONAL)
+    } else if tag == atoms::less_or_equal() {
+        ("<=", PREC_RELATIONAL)
+    } else if tag == atoms::add() {
+        ("+", PREC_ADDITIVE)
+    } else if tag == atoms::subtract() {
+        ("-", PREC_ADDITIVE)
+    } else if tag == atoms::multiply() {
+        ("*", PREC_MULTIPLICATIVE)
+    } else if tag == atoms::divide() {
+        ("/", PREC_MULTIPLICATIVE)
+    } else {
+        return None;
+    };
+    Some(op)
+}
+
+/// Returns the symbol of a unary operator tag.
+fn unary_operator(tag: rustler::Atom) -> Option<&'static str> {
+    if tag == atoms::not() {
+        Some("!")
+    } else if tag == atoms::unary_plus() {
+        Some("+")
+    } else if tag == atoms::unary_minus() {
+        Some("-")
+    } else {
+        None
+    }
+}
+
+/// Renders an expression, returning the text and the precedence of its
+/// outermost operator, or the offending term.
+fn render_expression(term: Term) -> Result<(String, u8), Term> {
+    let elements = rustler::types::tuple::get_tuple(term).map_err(|_| term)?;
+    let tag: rustler::Atom = elements.first().and_then(|t| t.decode().ok()).ok_or(term)?;
+
+    if tag == atoms::named_node() || tag == atoms::literal() {
+        return match decode_rdf_term(term)? {
+            spargebra::term::Term::BlankNode(_) => Err(term),
+            rdf_term => Ok((rdf_term.to_string(), PREC_PRIMARY)),
+        };
+    }
+
+    match elements.len() {
+        2 if tag == atoms::variable() => {
+            let name: String = elements[1].decode().map_err(|_| term)?;
+            Ok((format!("?{}", name), PREC_PRIMARY))
+        }
+        2 if tag == atoms::bound() => {
+            let (variable, _) = render_expression(elements[1])?;
+            Ok((format!("BOUND({})", variable), PREC_PRIMARY))
+        }
+        2 if tag == atoms::coalesce() => {
+            let args = render_arguments(elements[1], term)?;
+            Ok((format!("COALESCE({})", args), PREC_PRIMARY))
+        }
+        2 => {
+            let symbol = unary_operator(tag).ok_or(term)?;
+            let operand = render_operand(elements[1], PREC_PRIMARY)?;
+            Ok((format!("{}{}", symbol, operand), PREC_UNARY))
+        }
+        3 if tag == atoms::same_term() => {
+            let (left, _) = render_expression(elements[1])?;
+            let (right, _) = render_expression(elements[2])?;
+            Ok((format!("sameTerm({}, {})", left, right), PREC_PRIMARY))
+        }
+        3 if tag == atoms::in_expr() => {
+            let needle = render_operand(elements[1], PREC_RELATIONAL + 1)?;
+            let list = render_arguments(elements[2], term)?;
+            Ok((format!("{} IN ({})", needle, list), PREC_RELATIONAL))
+        }
+        3 if tag == atoms::function_call() => {
+            let name = match elements[1].decode::<String>() {
+                Ok(name) => name,
+                Err(_) => {
+                    let (custom, iri): (rustler::Atom, String) =
+                        elements[1].decode().map_err(|_| term)?;
+                    if custom != atoms::custom() {
+                        return Err(term);
+                    }
+                    format!("<{}>", NamedNode::new(iri).map_err(|_| term)?.as_str())
+                }
+            };
+            let args = render_arguments(elements[2], term)?;
+            Ok((format!("{}({})", name, args), PREC_PRIMARY))
+        }
+        3 => {
+            let (symbol, prec) = binary_operator(tag).ok_or(term)?;
+            // Left-associative operators accept a same-level left operand;
+            // relational operators don't chain
+            let left_min = if prec == PREC_RELATIONAL {
+                prec + 1
+            } else {
+                prec
+            };
+            let left = render_operand(elements[1], left_min)?;
+            let right = render_operand(elements[2], prec + 1)?;
+            Ok((format!("{} {} {}", left, symbol, right), prec))
+        }
+        4 if tag == atoms::if_expr() => {
+            let (cond, _) = render_expression(elements[1])?;
+            let (then, _) = render_expression(elements[2])?;
+            let (otherwise, _) = render_expression(elements[3])?;
+            Ok((
+                format!("IF({}, {}, {})", cond, then, otherwise),
+                PREC_PRIMARY,
+            ))
+        }
+        _ => Err(term),
+    }
+}
+
+/// Renders an operand, parenthesizing it if it binds looser than `min_prec`.
+fn render_operand(term: Term, min_prec: u8) -> Result<String, Term> {
+    let (text, prec) = render_expression(term)?;
+    if prec < min_prec {
+        Ok(format!("({})", text))
+    } else {
+        Ok(text)
+    }
+}
+
+/// Renders a list of expressions as comma-separated arguments.
+fn render_arguments<'a>(list: Term<'a>, parent: Term<'a>) -> Result<String, Term<'a>> {
+    let items: Vec<Term> = list.decode().map_err(|_| parent)?;
+    let mut rendered = Vec::with_capacity(items.len());
+    for item in items {
+        rendered.push(render_expression(item)?.0);
+    }
+    Ok(rendered.join(", "))
+}
+
+// ===========================================================================
+// Query Serialization
+// ===========================================================================
+
+/// Serializes a query AST back to a SPARQL string.
+///
+/// Inverse of `parse_query`: the term is decoded into a `spargebra::Query`
+/// and rendered with `render_query`, so `parse_query` on the output yields
+/// the same AST, up to the names of the variables spargebra generates for
+/// aggregates and `DESCRIBE <iri>`, which are random on every parse. IRIs,
+/// variable names, blank node ids and language tags are validated while
+/// decoding.
+///
+/// Rewritten ASTs that don't keep the parser's nesting (e.g. `:slice` above
+/// `:project`) serialize to an equivalent query that parses back as a
+/// different tree.
+///
+/// # Arguments
+/// * `ast` - `{:select | :construct | :ask | :describe, props}` term
+///
+/// # Returns
+/// * `{:ok, sparql}` on success
+/// * `{:error, {:invalid_ast, term}}` with the first sub-term that cannot be
+///   decoded
+#[rustler::nif(schedule = "DirtyCpu")]
+fn query_to_sparql<'a>(env: Env<'a>, ast: Term<'a>) -> NifResult<Term<'a>> {
+    match decode_query(ast) {
+        Ok(query) => Ok((atoms::ok(), render_query(&query)).encode(env)),
+        Err(term) => Ok((atoms::error(), (atoms::invalid_ast(), term)).encode(env)),
+    }
+}
+
+/// Renders a query as SPARQL.
+///
+/// spargebra's `Display` writes grouping as a `{SELECT ... GROUP BY}`
+/// subquery, `(expr AS ?v)` projections as a trailing `BIND`, and the WHERE
+/// clause of ASK, CONSTRUCT and DESCRIBE as a `SELECT *` subquery, all of
+/// which parse back as a different tree. Instead, SELECT queries and
+/// subqueries are written by `select_text` in the form the parser builds
+/// them from. For the other forms the pattern is rendered as a SELECT and its
+/// `SELECT <projection>` head is swapped for the real query form; solution
+/// modifiers stay in place since every form accepts them.
+///
+/// The output is parsed back and compared with the query, modulo generated
+/// variable and blank node names. When they differ, or the pattern doesn't
+/// have a shape the parser produces (a projecting or DISTINCT pattern under
+/// ASK or CONSTRUCT, modifiers in another order), falls back to `Display`.
+fn render_query(query: &Query) -> String {
+    render_query_text(query)
+        .filter(|sparql| {
+            Query::parse(sparql, None).is_ok_and(|reparsed| {
+                canonicalize_sse(&reparsed.to_sse()) == canonicalize_sse(&query.to_sse())
+            })
+        })
+        .unwrap_or_else(|| query.to_string())
+}
+
+fn render_query_text(query: &Query) -> Option<String> {
+    let (head, dataset, pattern, base_iri) = match query {
+        Query::Select {
+            dataset,
+            pattern,
+            base_iri,
+        } => {
+            let mut sparql = String::new();
+            if let Some(base_iri) = base_iri {
+                sparql.push_str(&format!("BASE <{}>\n", base_iri.as_str()));
+            }
+            sparql.push_str(&select_text(pattern, dataset.as_ref()));
+            return Some(sparql);
+        }
+        Query::Construct {
+            template,
+            dataset,
+            pattern,
+            base_iri,
+        } => {
+            let mut head = String::from("CONSTRUCT { ");
+            for triple in template {
+                head.push_str(&format!("{} . ", triple));
+            }
+            head.push('}');
+            (head, dataset, pattern, base_iri)
+        }
+        Query::Ask {
+            dataset,
+            pattern,
+            base_iri,
+        } => (String::from("ASK"), dataset, pattern, base_iri),
+        Query::Describe {
+            dataset,
+            pattern,
+            base_iri,
+        } => (String::from("DESCRIBE"), dataset, pattern, base_iri),
+    };
+
+    let select = select_text(pattern, None);
+    let (projection, body) = select.strip_prefix("SELECT")?.split_once(" WHERE {")?;
+
+    // DESCRIBE keeps its projected variables and IRIs; ASK and CONSTRUCT have none
+    let projection = match query {
+        Query::Describe { .. }
+            if !projection.contains("DISTINCT") && !projection.contains("REDUCED") =>
+        {
+            describe_items(projection)
+        }
+        _ if projection == " *" => String::new(),
+        _ => return None,
+    };
+
+    let mut sparql = String::new();
+    if let Some(base_iri) = base_iri {
+        sparql.push_str(&format!("BASE <{}>\n", base_iri.as_str()));
+    }
+    sparql.push_str(&head);
+    sparql.push_str(&projection);
+    if let Some(dataset) = dataset {
+        sparql.push_str(&dataset.to_string());
+    }
+    sparql.push_str(" WHERE {");
+    sparql.push_str(body);
+    Some(sparql)
+}
+
+/// Writes the `(<iri> AS ?v)` items `select_text` renders for `DESCRIBE
+/// <iri>` back as `<iri>`.
+fn describe_items(projection: &str) -> String {
+    let mut out = String::with_capacity(projection.len());
+    let mut rest = projection;
+    while let Some(start) = rest.find(" (<") {
+        let Some(end) = rest[start..].find("> AS ?").map(|i| start + i + 1) else {
+            break;
+        };
+        out.push_str(&rest[..start + 1]);
+        out.push_str(&rest[start + 2..end]);
+        rest = &rest[end..];
+        rest = &rest[rest.find(')').map_or(rest.len(), |i| i + 1)..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Renders a SELECT pattern in the form `parse_query` builds it from.
+///
+/// Peels the layers the parser wraps around the WHERE clause, outermost
+/// first: LIMIT/OFFSET, DISTINCT/REDUCED, the projection, ORDER BY, the
+/// `extend`s of `(expr AS ?v)` projections, a trailing VALUES, HAVING, the
+/// group and the `extend`s of `GROUP BY (expr AS ?v)`, and writes each back
+/// as its clause. Aggregate variables are written as the aggregates they
+/// stand for. Modifiers nested in another order end up in the WHERE clause
+/// as subqueries.
+fn select_text(pattern: &GraphPattern, dataset: Option<&QueryDataset>) -> String {
+    let mut child = pattern;
+    let (mut start, mut length) = (0, None);
+    if let GraphPattern::Slice {
+        inner,
+        start: s,
+        length: l,
+    } = child
+    {
+        (start, length) = (*s, *l);
+        child = inner;
+    }
+    let mut modifier = "";
+    match child {
+        GraphPattern::Distinct { inner } => (modifier, child) = (" DISTINCT", inner),
+        GraphPattern::Reduced { inner } => (modifier, child) = (" REDUCED", inner),
+        _ => {}
+    }
+    let mut projection = None;
+    if let GraphPattern::Project { inner, variables } = child {
+        projection = Some(variables);
+        child = inner;
+    }
+    let mut order = None;
+    if let GraphPattern::OrderBy { inner, expression } = child {
+        order = Some(expression);
+        child = inner;
+    }
+
+    let projected =
+        projection.map_or_else(Vec::new, |variables| peel_extends(&mut child, variables));
+    let mut values = None;
+    if let GraphPattern::Join { left, right } = child {
+        if matches!(right.as_ref(), GraphPattern::Values { .. }) && is_group(left) {
+            values = Some(right);
+            child = left;
+        }
+    }
+    let mut having = None;
+    if let GraphPattern::Filter { expr, inner } = child {
+        if matches!(inner.as_ref(), GraphPattern::Group { .. }) {
+            having = Some(expr);
+            child = inner;
+        }
+    }
+    let mut group = None;
+    let mut keys = Vec::new();
+    if let GraphPattern::Group {
+        inner,
+        variables,
+        aggregates,
+    } = child
+    {
+        child = inner;
+        keys = peel_extends(&mut child, variables);
+        group = Some((variables, aggregates));
+    }
+    let aggregates: HashMap<&str, String> = group
+        .iter()
+        .flat_map(|(_, aggregates)| aggregates.iter())
+        .map(|(variable, aggregate)| (variable.as_str(), aggregate.to_string()))
+        .collect();
+    let expression = |expr: &Expression| inline_aggregates(&expr.to_string(), &aggregates);
+
+    let mut sparql = String::from("SELECT");
+    sparql.push_str(modifier);
+    match projection {
+        Some(variables) => {
+            for variable in variables {
+                match extend_of(&projected, variable) {
+                    Some(expr) => {
+                        sparql.push_str(&format!(" ({} AS {})", expression(expr), variable))
+                    }
+                    None => sparql.push_str(&format!(" {}", variable)),
+                }
+            }
+        }
+        None => sparql.push_str(" *"),
+    }
+    if let Some(dataset) = dataset {
+        sparql.push_str(&dataset.to_string());
+    }
+    sparql.push_str(&format!(" WHERE {{ {} }}", render_pattern(child)));
+    if let Some((variables, _)) = group.filter(|(variables, _)| !variables.is_empty()) {
+        sparql.push_str(" GROUP BY");
+        for variable in variables {
+            match extend_of(&keys, variable) {
+                Some(expr) => sparql.push_str(&format!(" ({} AS {})", expr, variable)),
+                None => sparql.push_str(&format!(" {}", variable)),
+            }
+        }
+    }
+    if let Some(expr) = having {
+        sparql.push_str(&format!(" HAVING ({})", expression(expr)));
+    }
+    if let Some(order) = order {
+        sparql.push_str(" ORDER BY");
+        for condition in order {
+            match condition {
+                OrderExpression::Asc(expr) => {
+                    sparql.push_str(&format!(" ASC({})", expression(expr)))
+                }
+                OrderExpression::Desc(expr) => {
+                    sparql.push_str(&format!(" DESC({})", expression(expr)))
+                }
+            }
+        }
+    }
+    if let Some(values) = values {
+        sparql.push_str(&format!(" {}", values));
+    }
+    if start > 0 {
+        sparql.push_str(&format!(" OFFSET {}", start));
+    }
+    if let Some(length) = length {
+        sparql.push_str(&format!(" LIMIT {}", length));
+    }
+    sparql
+}
+
+/// Renders a WHERE clause like spargebra's `Display`, except that subqueries
+/// go through `select_text`.
+fn render_pattern(pattern: &GraphPattern) -> String {
+    match pattern {
+        GraphPattern::Join { left, right } => match right.as_ref() {
+            // The second block might be read as a modification of the first one
+            GraphPattern::LeftJoin { .. }
+            | GraphPattern::Minus { .. }
+            | GraphPattern::Extend { .. }
+            | GraphPattern::Filter { .. } => {
+                format!("{} {{ {} }}", render_pattern(left), render_pattern(right))
+            }
+            _ => format!("{} {}", render_pattern(left), render_pattern(right)),
+        },
+        GraphPattern::LeftJoin {
+            left,
+            right,
+            expression: Some(expr),
+        } => {
+            format!(
+                "{} OPTIONAL {{ {} FILTER({}) }}",
+                render_pattern(left),
+                render_pattern(right),
+                expr
+            )
+        }
+        GraphPattern::LeftJoin {
+            left,
+            right,
+            expression: None,
+        } => {
+            format!(
+                "{} OPTIONAL {{ {} }}",
+                render_pattern(left),
+                render_pattern(right)
+            )
+        }
+        GraphPattern::Filter { expr, inner } => {
+            format!("{} FILTER({})", render_pattern(inner), expr)
+        }
+        GraphPattern::Union { left, right } => {
+            format!(
+                "{{ {} }} UNION {{ {} }}",
+                render_pattern(left),
+                render_pattern(right)
+            )
+        }
+        GraphPattern::Graph { name, inner } => {
+            format!("GRAPH {} {{ {} }}", name, render_pattern(inner))
+        }
+        GraphPattern::Extend {
+            inner,
+            variable,
+            expression,
+        } => {
+            format!(
+                "{} BIND({} AS {})",
+                render_pattern(inner),
+                expression,
+                variable
+            )
+        }
+        GraphPattern::Minus { left, right } => format!(
+            "{} MINUS {{ {} }}",
+            render_pattern(left),
+            render_pattern(right)
+        ),
+        GraphPattern::Service {
+            name,
+            inner,
+            silent,
+        } => {
+            let silent = if *silent { " SILENT" } else { "" };
+            format!("SERVICE{} {} {{ {} }}", silent, name, render_pattern(inner))
+        }
+        GraphPattern::Project { .. }
+        | GraphPattern::Distinct { .. }
+        | GraphPattern::Reduced { .. }
+        | GraphPattern::Slice { .. }
+        | GraphPattern::OrderBy { .. } => format!("{{ {} }}", select_text(pattern, None)),
+        GraphPattern::Bgp { .. }
+        | GraphPattern::Path { .. }
+        | GraphPattern::Values { .. }
+        | GraphPattern::Group { .. } => pattern.to_string(),
+    }
+}
+
+/// Peels the chain of `extend`s the parser builds for `(expr AS ?v)` items
+/// of a SELECT or GROUP BY clause, whose variables are listed in `variables`.
+/// The parser applies them in list order, so each one peeled (outermost
+/// first) must come before the previous one in `variables`.
+fn peel_extends<'p>(
+    child: &mut &'p GraphPattern,
+    variables: &[Variable],
+) -> Vec<(&'p Variable, &'p Expression)> {
+    let mut extends = Vec::new();
+    let mut last = variables.len();
+    while let GraphPattern::Extend {
+        inner,
+        variable,
+        expression,
+    } = *child
+    {
+        match variables.iter().position(|v| v == variable) {
+            Some(position) if position < last => last = position,
+            _ => break,
+        }
+        extends.push((variable, expression));
+        *child = inner;
+    }
+    extends
+}
+
+/// The expression a peeled `extend` binds to `variable`.
+fn extend_of<'p>(
+    extends: &[(&Variable, &'p Expression)],
+    variable: &Variable,
+) -> Option<&'p Expression> {
+    extends
+        .iter()
+        .find(|(v, _)| *v == variable)
+        .map(|(_, expr)| *expr)
+}
+
+/// Whether a pattern is a group, possibly under its HAVING filter.
+fn is_group(pattern: &GraphPattern) -> bool {
+    match pattern {
+        GraphPattern::Filter { inner, .. } => matches!(inner.as_ref(), GraphPattern::Group { .. }),
+        pattern => matches!(pattern, GraphPattern::Group { .. }),
+    }
+}
+
+/// Replaces the aggregate variables in rendered SPARQL with the aggregates
+/// they are bound to. Quoted strings and IRIs are copied verbatim.
+fn inline_aggregates(sparql: &str, aggregates: &HashMap<&str, String>) -> String {
+    if aggregates.is_empty() {
+        return sparql.to_string();
+    }
+    let is_name_char = |c: char| c.is_alphanumeric() || c == '_' || c == '\u{B7}';
+    let mut out = String::with_capacity(sparql.len());
+    let bytes = sparql.as_bytes();
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let end = match bytes[pos] {
+            b'"' => quoted_end(bytes, pos),
+            // IRIs never contain spaces; `<` and `<=` operators are followed by one
+            b'<' if bytes
+                .get(pos + 1)
+                .is_some_and(|b| !matches!(b, b' ' | b'=')) =>
+            {
+                sparql[pos..].find('>').map_or(bytes.len(), |i| pos + i + 1)
+            }
+            b'?' => {
+                let end = sparql[pos + 1..]
+                    .find(|c: char| !is_name_char(c))
+                    .map_or(bytes.len(), |i| pos + 1 + i);
+                if let Some(aggregate) = aggregates.get(&sparql[pos + 1..end]) {
+                    out.push_str(aggregate);
+                    pos = end;
+                    continue;
+                }
+                end
+            }
+            _ => pos + sparql[pos..].chars().next().map_or(1, char::len_utf8),
+        };
+        out.push_str(&sparql[pos..end]);
+        pos = end;
+    }
+
+    out
+}
+
+/// Decodes a `{form, props}` query term into a spargebra Query.
+fn decode_query(term: Term) -> Result<Query, Term> {
+    let (form, props): (rustler::Atom, Vec<(String, Term)>) = term.decode().map_err(|_| term)?;
+    let prop = |key: &str| -> Result<Term, Term> {
+        props
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| *v)
+            .ok_or(term)
+    };
+
+    let pattern = decode_graph_pattern(prop("pattern")?)?;
+    let dataset = decode_optional(prop("dataset")?, decode_query_dataset)?;
+    let base_iri = decode_optional(prop("base_iri")?, decode_base_iri)?;
+
+    if form == atoms::select() {
+        Ok(Query::Select {
+            dataset,
+            pattern,
+            base_iri,
+        })
+    } else if form == atoms::construct() {
+        let template = decode_list(prop("template")?, decode_triple_pattern)?;
+        Ok(Query::Construct {
+            template,
+            dataset,
+            pattern,
+            base_iri,
+        })
+    } else if form == atoms::ask() {
+        Ok(Query::Ask {
+            dataset,
+            pattern,
+            base_iri,
+        })
+    } else if form == atoms::describe() {
+        Ok(Query::Describe {
+            dataset,
+            pattern,
+            base_iri,
+        })
+    } else {
+        Err(term)
+    }
+}
+
+/// Decodes a `{:named_node, iri}` base IRI.
+fn decode_base_iri(term: Term) -> Result<Iri<String>, Term> {
+    let node = decode_named_node(term)?;
+    Iri::parse(node.into_string()).map_err(|_| term)
+}
+
+/// Decodes `nil` as `None`, anything else with `f`.
+fn decode_optional<'a, T>(
+    term: Term<'a>,
+    f: impl FnOnce(Term<'a>) -> Result<T, Term<'a>>,
+) -> Result<Option<T>, Term<'a>> {
+    if term.decode::<rustler::Atom>().ok() == Some(rustler::types::atom::nil()) {
+        Ok(None)
+    } else {
+        f(term).map(Some)
+    }
+}
+
+/// Decodes every element of a list with `f`.
+fn decode_list<'a, T>(
+    term: Term<'a>,
+    f: impl Fn(Term<'a>) -> Result<T, Term<'a>>,
+) -> Result<Vec<T>, Term<'a>> {
+    let items: Vec<Term<'a>> = term.decode().map_err(|_| term)?;
+    items.into_iter().map(f).collect()
+}
+
+/// Decodes a `{:default, [...]}, {:named, [...]}` dataset list.
+fn decode_query_dataset(term: Term) -> Result<spargebra::algebra::QueryDataset, Term> {
+    let props: Vec<(String, Term)> = term.decode().map_err(|_| term)?;
+    let graphs = |key: &str| -> Result<Vec<NamedNode>, Term> {
+        let list = props
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| *v)
+            .ok_or(term)?;
+        decode_list(list, decode_named_node)
+    };
+    Ok(spargebra::algebra::QueryDataset {
+        default: graphs("default")?,
+        named: Some(graphs("named")?),
+    })
+}
+
+/// Decodes a graph pattern term.
+fn decode_graph_pattern(term: Term) -> Result<GraphPattern, Term> {
+    let elements = rustler::types::tuple::get_tuple(term).map_err(|_| term)?;
+    let tag: rustler::Atom = elements.first().and_then(|t| t.decode().ok()).ok_or(term)?;
+    let pattern_at = |i: usize| decode_graph_pattern(elements[i]).map(Box::new);
+
+    let pattern = match elements.len() {
+        2 if tag == atoms::bgp() => GraphPattern::Bgp {
+            patterns: decode_list(elements[1], decode_triple_pattern)?,
+        },
+        2 if tag == atoms::distinct() => GraphPattern::Distinct {
+            inner: pattern_at(1)?,
+        },
+        2 if tag == atoms::reduced() => GraphPattern::Reduced {
+            inner: pattern_at(1)?,
+        },
+        3 if tag == atoms::join() => GraphPattern::Join {
+            left: pattern_at(1)?,
+            right: pattern_at(2)?,
+        },
+        3 if tag == atoms::union() => GraphPattern::Union {
+            left: pattern_at(1)?,
+            right: pattern_at(2)?,
+        },
+        3 if tag == atoms::minus() => GraphPattern::Minus {
+            left: pattern_at(1)?,
+            right: pattern_at(2)?,
+        },
+        3 if tag == atoms::filter() => GraphPattern::Filter {
+            expr: decode_expression(elements[1])?,
+            inner: pattern_at(2)?,
+        },
+        3 if tag == atoms::graph() => GraphPattern::Graph {
+            name: decode_named_node_pattern(elements[1])?,
+            inner: pattern_at(2)?,
+        },
+        3 if tag == atoms::values() => {
+            let variables = decode_list(elements[1], decode_variable)?;
+            let bindings = decode_list(elements[2], |row| {
+                let values = decode_list(row, |value| decode_optional(value, decode_ground_term))?;
+                if values.len() == variables.len() {
+                    Ok(values)
+                } else {
+                    Err(row)
+                }
+            })?;
+            GraphPattern::Values {
+                variables,
+                bindings,
+            }
+        }
+        3 if tag == atoms::order_by() => GraphPattern::OrderBy {
+            inner: pattern_at(1)?,
+            expression: decode_list(elements[2], decode_order_expression)?,
+        },
+        3 if tag == atoms::project() => GraphPattern::Project {
+            inner: pattern_at(1)?,
+            variables: decode_list(elements[2], decode_variable)?,
+        },
+        4 if tag == atoms::path() => GraphPattern::Path {
+            subject: decode_term_pattern(elements[1])?,
+            path: decode_property_path(elements[2])?,
+            object: decode_term_pattern(elements[3])?,
+        },
+        4 if tag == atoms::left_join() => GraphPattern::LeftJoin {
+            left: pattern_at(1)?,
+            right: pattern_at(2)?,
+            expression: decode_optional(elements[3], decode_expression)?,
+        },
+        4 if tag == atoms::extend() => GraphPattern::Extend {
+            inner: pattern_at(1)?,
+            variable: decode_variable(elements[2])?,
+            expression: decode_expression(elements[3])?,
+        },
+        4 if tag == atoms::service() => GraphPattern::Service {
+            name: decode_named_node_pattern(elements[1])?,
+            inner: pattern_at(2)?,
+            silent: elements[3].decode().map_err(|_| elements[3])?,
+        },
+        4 if tag == atoms::group() => GraphPattern::Group {
+            inner: pattern_at(1)?,
+            variables: decode_list(elements[2], decode_variable)?,
+            aggregates: decode_list(elements[3], |binding| {
+                let (variable, aggregate): (Term, Term) = binding.decode().map_err(|_| binding)?;
+                Ok((
+                    decode_variable(variable)?,
+                    decode_aggregate_expression(aggregate)?,
+                ))
+            })?,
+        },
+        4 if tag == atoms::slice() => GraphPattern::Slice {
+            inner: pattern_at(1)?,
+            start: elements[2].decode().map_err(|_| elements[2])?,
+            length: decode_optional(elements[3], |t| t.decode().map_err(|_| t))?,
+        },
+        _ => return Err(term),
+    };
+    Ok(pattern)
+}
+
+/// Decodes a `{:triple, s, p, o}` term.
+fn decode_triple_pattern(term: Term) -> Result<TriplePattern, Term> {
+    let (tag, subject, predicate, object): (rustler::Atom, Term, Term, Term) =
+        term.decode().map_err(|_| term)?;
+    if tag != atoms::triple() {
+        return Err(term);
+    }
+    Ok(TriplePattern {
+        subject: decode_term_pattern(subject)?,
+        predicate: decode_named_node_pattern(predicate)?,
+        object: decode_term_pattern(object)?,
+    })
+}
+
+/// Decodes a subject/object position: an RDF term, a variable or a quoted
+/// triple pattern.
+fn decode_term_pattern(term: Term) -> Result<TermPattern, Term> {
+    if let Ok(variable) = decode_variable(term) {
+        return Ok(variable.into());
+    }
+    if let Ok((tag, subject, predicate, object)) =
+        term.decode::<(rustler::Atom, Term, Term, Term)>()
+    {
+        if tag == atoms::triple_term() {
+            return Ok(TriplePattern {
+                subject: decode_term_pattern(subject)?,
+                predicate: decode_named_node_pattern(predicate)?,
+                object: decode_term_pattern(object)?,
+            }
+            .into());
+        }
+    }
+    decode_rdf_term(term).map(Into::into)
+}
+
+/// Decodes a predicate/graph position: an IRI or a variable.
+fn decode_named_node_pattern(term: Term) -> Result<NamedNodePattern, Term> {
+    match decode_variable(term) {
+        Ok(variable) => Ok(variable.into()),
+        Err(_) => decode_named_node(term).map(Into::into),
+    }
+}
+
+/// Decodes a VALUES entry: an IRI, a literal or a quoted triple of those.
+fn decode_ground_term(term: Term) -> Result<GroundTerm, Term> {
+    GroundTerm::try_from(decode_rdf_term(term)?).map_err(|_| term)
+}
+
+/// Decodes a `{:named_node, iri}` term.
+fn decode_named_node(term: Term) -> Result<NamedNode, Term> {
+    match decode_rdf_term(term)? {
+        spargebra::term::Term::NamedNode(node) => Ok(node),
+        _ => Err(term),
+    }
+}
+
+/// Decodes a subject: an IRI, a blank node or a quoted triple.
+fn decode_subject(term: Term) -> Result<Subject, Term> {
+    match decode_rdf_term(term)? {
+        spargebra::term::Term::NamedNode(node) => Ok(node.into()),
+        spargebra::term::Term::BlankNode(node) => Ok(node.into()),
+        spargebra::term::Term::Triple(triple) => Ok(Subject::Triple(triple)),
+        spargebra::term::Term::Literal(_) => Err(term),
+    }
+}
+
+/// Decodes a `{:variable, name}` term.
+fn decode_variable(term: Term) -> Result<Variable, Term> {
+    let (tag, name): (rustler::Atom, String) = term.decode().map_err(|_| term)?;
+    if tag != atoms::variable() {
+        return Err(term);
+    }
+    Variable::new(name).map_err(|_| term)
+}
+
+/// Decodes an expression term.
+fn decode_expression(term: Term) -> Result<Expression, Term> {
+    let elements = rustler::types::tuple::get_tuple(term).map_err(|_| term)?;
+    let tag: rustler::Atom = elements.first().and_then(|t| t.decode().ok()).ok_or(term)?;
+    let expr_at = |i: usize| decode_expression(elements[i]).map(Box::new);
+
+    if tag == atoms::variable() {
+        return decode_variable(term).map(Expression::Variable);
+    }
+    if tag == atoms::named_node() || tag == atoms::literal() {
+        return match decode_rdf_term(term)? {
+            spargebra::term::Term::NamedNode(node) => Ok(Expression::NamedNode(node)),
+            spargebra::term::Term::Literal(literal) => Ok(Expression::Literal(literal)),
+            spargebra::term::Term::BlankNode(_) | spargebra::term::Term::Triple(_) => Err(term),
+        };
+    }
+
+    let expression = match elements.len() {
+        2 if tag == atoms::unary_plus() => Expression::UnaryPlus(expr_at(1)?),
+        2 if tag == atoms::unary_minus() => Expression::UnaryMinus(expr_at(1)?),
+        2 if tag == atoms::not() => Expression::Not(expr_at(1)?),
+        2 if tag == atoms::bound() => Expression::Bound(decode_variable(elements[1])?),
+        2 if tag == atoms::coalesce() => {
+            Expression::Coalesce(decode_list(elements[1], decode_expression)?)
+        }
+        2 if tag == atoms::exists() => {
+            Expression::Exists(Box::new(decode_graph_pattern(elements[1])?))
+        }
+        3 if tag == atoms::in_expr() => {
+            Expression::In(expr_at(1)?, decode_list(elements[2], decode_expression)?)
+        }
+        3 if tag == atoms::function_call() => Expression::FunctionCall(
+            decode_function(elements[1])?,
+            decode_list(elements[2], decode_expression)?,
+        ),
+        3 => {
+            let (left, right) = (expr_at(1)?, expr_at(2)?);
+            if tag == atoms::or() {
+                Expression::Or(left, right)
+            } else if tag == atoms::and() {
+                Expression::And(left, right)
+            } else if tag == atoms::equal() {
+                Expression::Equal(left, right)
+            } else if tag == atoms::same_term() {
+                Expression::SameTerm(left, right)
+            } else if tag == atoms::greater() {
+                Expression::Greater(left, right)
+            } else if tag == atoms::greater_or_equal() {
+                Expression::GreaterOrEqual(left, right)
+            } else if tag == atoms::less() {
+                Expression::Less(left, right)
+            } else if tag == atoms::less_or_equal() {
+                Expression::LessOrEqual(left, right)
+            } else if tag == atoms::add() {
+                Expression::Add(left, right)
+            } else if tag == atoms::subtract() {
+                Expression::Subtract(left, right)
+            } else if tag == atoms::multiply() {
+                Expression::Multiply(left, right)
+            } else if tag == atoms::divide() {
+                Expression::Divide(left, right)
+            } else {
+                return Err(term);
+            }
+        }
+        4 if tag == atoms::if_expr() => Expression::If(expr_at(1)?, expr_at(2)?, expr_at(3)?),
+        _ => return Err(term),
+    };
+    Ok(expression)
+}
+
+/// Decodes a built-in function name or `{:custom, iri}`.
+fn decode_function(term: Term) -> Result<Function, Term> {
+    let name: String = match term.decode() {
+        Ok(name) => name,
+        Err(_) => return decode_custom_iri(term).map(Function::Custom),
+    };
+    let function = match name.as_str() {
+        "STR" => Function::Str,
+        "LANG" => Function::Lang,
+        "LANGMATCHES" => Function::LangMatches,
+        "DATATYPE" => Function::Datatype,
+        "IRI" => Function::Iri,
+        "BNODE" => Function::BNode,
+        "RAND" => Function::Rand,
+        "ABS" => Function::Abs,
+        "CEIL" => Function::Ceil,
+        "FLOOR" => Function::Floor,
+        "ROUND" => Function::Round,
+        "CONCAT" => Function::Concat,
+        "SUBSTR" => Function::SubStr,
+        "STRLEN" => Function::StrLen,
+        "REPLACE" => Function::Replace,
+        "UCASE" => Function::UCase,
+        "LCASE" => Function::LCase,
+        "ENCODE_FOR_URI" => Function::EncodeForUri,
+        "CONTAINS" => Function::Contains,
+        "STRSTARTS" => Function::StrStarts,
+        "STRENDS" => Function::StrEnds,
+        "STRBEFORE" => Function::StrBefore,
+        "STRAFTER" => Function::StrAfter,
+        "YEAR" => Function::Year,
+        "MONTH" => Function::Month,
+        "DAY" => Function::Day,
+        "HOURS" => Function::Hours,
+        "MINUTES" => Function::Minutes,
+        "SECONDS" => Function::Seconds,
+        "TIMEZONE" => Function::Timezone,
+        "TZ" => Function::Tz,
+        "NOW" => Function::Now,
+        "UUID" => Function::Uuid,
+        "STRUUID" => Function::StrUuid,
+        "MD5" => Function::Md5,
+        "SHA1" => Function::Sha1,
+        "SHA256" => Function::Sha256,
+        "SHA384" => Function::Sha384,
+        "SHA512" => Function::Sha512,
+        "STRLANG" => Function::StrLang,
+        "STRDT" => Function::StrDt,
+        "ISIRI" => Function::IsIri,
+        "ISBLANK" => Function::IsBlank,
+        "ISLITERAL" => Function::IsLiteral,
+        "ISNUMERIC" => Function::IsNumeric,
+        "REGEX" => Function::Regex,
+        "TRIPLE" => Function::Triple,
+        "SUBJECT" => Function::Subject,
+        "PREDICATE" => Function::Predicate,
+        "OBJECT" => Function::Object,
+        "ISTRIPLE" => Function::IsTriple,
+        _ => return Err(term),
+    };
+    Ok(function)
+}
+
+/// Decodes a `{:custom, iri}` function or aggregate name.
+fn decode_custom_iri(term: Term) -> Result<NamedNode, Term> {
+    let (tag, iri): (rustler::Atom, String) = term.decode().map_err(|_| term)?;
+    if tag != atoms::custom() {
+        return Err(term);
+    }
+    NamedNode::new(iri).map_err(|_| term)
+}
+
+/// Decodes `{:count_solutions, distinct}` or `{function, expr, distinct}`.
+fn decode_aggregate_expression(term: Term) -> Result<AggregateExpression, Term> {
+    if let Ok((tag, distinct)) = term.decode::<(rustler::Atom, bool)>() {
+        if tag == atoms::count_solutions() {
+            return Ok(AggregateExpression::CountSolutions { distinct });
+        }
+    }
+    let (name, expr, distinct): (Term, Term, bool) = term.decode().map_err(|_| term)?;
+    Ok(AggregateExpression::FunctionCall {
+        name: decode_aggregate_function(name)?,
+        expr: decode_expression(expr)?,
+        distinct,
+    })
+}
+
+/// Decodes an aggregate function atom, `{:group_concat, separator}` or
+/// `{:custom, iri}`.
+fn decode_aggregate_function(term: Term) -> Result<AggregateFunction, Term> {
+    if let Ok(tag) = term.decode::<rustler::Atom>() {
+        return if tag == atoms::count() {
+            Ok(AggregateFunction::Count)
+        } else if tag == atoms::sum() {
+            Ok(AggregateFunction::Sum)
+        } else if tag == atoms::min() {
+            Ok(AggregateFunction::Min)
+        } else if tag == atoms::max() {
+            Ok(AggregateFunction::Max)
+        } else if tag == atoms::avg() {
+            Ok(AggregateFunction::Avg)
+        } else if tag == atoms::sample() {
+            Ok(AggregateFunction::Sample)
+        } else {
+            Err(term)
+        };
+    }
+    if let Ok((tag, separator)) = term.decode::<(rustler::Atom, Term)>() {
+        if tag == atoms::group_concat() {
+            let separator = decode_optional(separator, |t| t.decode::<String>().map_err(|_| t))?;
+            return Ok(AggregateFunction::GroupConcat { separator });
+        }
+    }
+    decode_custom_iri(term).map(AggregateFunction::Custom)
+}
+
+/// Decodes `{:asc, expr}` or `{:desc, expr}`.
+fn decode_order_expression(term: Term) -> Result<OrderExpression, Term> {
+    let (direction, expr): (rustler::Atom, Term) = term.decode().map_err(|_| term)?;
+    if direction == atoms::asc() {
+        Ok(OrderExpression::Asc(decode_expression(expr)?))
+    } else if direction == atoms::desc() {
+        Ok(OrderExpression::Desc(decode_expression(expr)?))
+    } else {
+        Err(term)
+    }
+}
+
+/// Decodes a property path term.
+fn decode_property_path(term: Term) -> Result<PropertyPathExpression, Term> {
+    let elements = rustler::types::tuple::get_tuple(term).map_err(|_| term)?;
+    let tag: rustler::Atom = elements.first().and_then(|t| t.decode().ok()).ok_or(term)?;
+    let path_at = |i: usize| decode_property_path(elements[i]).map(Box::new);
+
+    let path = match elements.len() {
+        2 if tag == atoms::named_node() => {
+            PropertyPathExpression::NamedNode(decode_named_node(term)?)
+        }
+        2 if tag == atoms::reverse() => PropertyPathExpression::Reverse(path_at(1)?),
+        2 if tag == atoms::zero_or_more() => PropertyPathExpression::ZeroOrMore(path_at(1)?),
+        2 if tag == atoms::one_or_more() => PropertyPathExpression::OneOrMore(path_at(1)?),
+        2 if tag == atoms::zero_or_one() => PropertyPathExpression::ZeroOrOne(path_at(1)?),
+        2 if tag == atoms::negated_property_set() => {
+            PropertyPathExpression::NegatedPropertySet(decode_list(elements[1], decode_named_node)?)
+        }
+        3 if tag == atoms::sequence() => PropertyPathExpression::Sequence(path_at(1)?, path_at(2)?),
+        3 if tag == atoms::alternative() => {
+            PropertyPathExpression::Alternative(path_at(1)?, path_at(2)?)
+        }
+        _ => return Err(term),
+    };
+    Ok(path)
+}
+
+// ===========================================================================
+// Update Serialization
+// ===========================================================================
+
+/// Serializes an UPDATE AST back to a SPARQL string.
+///
+/// Inverse of `parse_update`, as `query_to_sparql` is for queries:
+/// `parse_update` on the output yields the same AST, with the same exception
+/// for GROUP BY / aggregate subqueries in a WHERE clause. `WITH <g>` comes
+/// back as `USING <g>`, with the templates already carrying `GRAPH <g>`; the
+/// AST doesn't tell the two apart.
+///
+/// # Arguments
+/// * `ast` - `{:update, props}` term
+///
+/// # Returns
+/// * `{:ok, sparql}` on success
+/// * `{:error, {:invalid_ast, term}}` with the first sub-term that cannot be
+///   decoded
+#[rustler::nif(schedule = "DirtyCpu")]
+fn update_to_sparql<'a>(env: Env<'a>, ast: Term<'a>) -> NifResult<Term<'a>> {
+    match decode_update(ast) {
+        Ok(update) => Ok((atoms::ok(), render_update(&update)).encode(env)),
+        Err(term) => Ok((atoms::error(), (atoms::invalid_ast(), term)).encode(env)),
+    }
+}
+
+/// Renders an update as SPARQL.
+///
+/// spargebra's `Display` wraps some DELETE/INSERT WHERE clauses (e.g. a
+/// top-level `GRAPH` or `BIND`) in a `SELECT *` subquery, which parses back
+/// as an extra `:project`, so those operations are written here with the
+/// WHERE clause taken from a rendered SELECT, as in `render_query`.
+fn render_update(update: &Update) -> String {
+    let mut sparql = String::new();
+    if let Some(base_iri) = &update.base_iri {
+        sparql.push_str(&format!("BASE <{}>\n", base_iri.as_str()));
+    }
+    for (i, operation) in update.operations.iter().enumerate() {
+        if i > 0 {
+            sparql.push_str(" ;\n");
+        }
+        let GraphUpdateOperation::DeleteInsert {
+            delete,
+            insert,
+            using,
+            pattern,
+        } = operation
+        else {
+            sparql.push_str(&operation.to_string());
+            continue;
+        };
+
+        if !delete.is_empty() {
+            sparql.push_str("DELETE {\n");
+            for quad in delete {
+                sparql.push_str(&format!("\t{} .\n", quad));
+            }
+            sparql.push_str("}\n");
+        }
+        if !insert.is_empty() {
+            sparql.push_str("INSERT {\n");
+            for quad in insert {
+                sparql.push_str(&format!("\t{} .\n", quad));
+            }
+            sparql.push_str("}\n");
+        }
+        if let Some(using) = using {
+            for graph in &using.default {
+                sparql.push_str(&format!("USING {}\n", graph));
+            }
+            for graph in using.named.iter().flatten() {
+                sparql.push_str(&format!("USING NAMED {}\n", graph));
+            }
+        }
+
+        // A projecting pattern is a subquery and keeps its SELECT
+        let select = Query::Select {
+            dataset: None,
+            pattern: (**pattern).clone(),
+            base_iri: None,
+        }
+        .to_string();
+        sparql.push_str("WHERE ");
+        match select.strip_prefix("SELECT * WHERE ") {
+            Some(group) => sparql.push_str(group),
+            None => sparql.push_str(&format!("{{ {} }}", select)),
+        }
+    }
+    sparql
+}
+
+/// Decodes an `{:update, props}` term into a spargebra Update.
+fn decode_update(term: Term) -> Result<Update, Term> {
+    let (tag, props): (rustler::Atom, Vec<(String, Term)>) = term.decode().map_err(|_| term)?;
+    if tag != atoms::update() {
+        return Err(term);
+    }
+    let prop = |key: &str| -> Result<Term, Term> {
+        props
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| *v)
+            .ok_or(term)
+    };
+
+    Ok(Update {
+        base_iri: decode_optional(prop("base_iri")?, decode_base_iri)?,
+        operations: decode_list(prop("operations")?, decode_graph_update_operation)?,
+    })
+}
+
+/// Decodes one update operation term.
+fn decode_graph_update_operation(term: Term) -> Result<GraphUpdateOperation, Term> {
+    let (tag, arg): (rustler::Atom, Term) = term.decode().map_err(|_| term)?;
+    if tag == atoms::insert_data() {
+        return Ok(GraphUpdateOperation::InsertData {
+            data: decode_list(arg, decode_data_quad)?,
+        });
+    }
+    if tag == atoms::delete_data() {
+        return Ok(GraphUpdateOperation::DeleteData {
+            data: decode_list(arg, decode_ground_data_quad)?,
+        });
+    }
+
+    let props: Vec<(String, Term)> = arg.decode().map_err(|_| term)?;
+    let prop = |key: &str| -> Result<Term, Term> {
+        props
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| *v)
+            .ok_or(term)
+    };
+    let silent = || -> Result<bool, Term> {
+        let flag = prop("silent")?;
+        flag.decode().map_err(|_| flag)
+    };
+
+    if tag == atoms::delete_insert() {
+        Ok(GraphUpdateOperation::DeleteInsert {
+            delete: decode_list(prop("delete")?, decode_ground_quad_pattern)?,
+            insert: decode_list(prop("insert")?, decode_quad_pattern)?,
+            using: decode_optional(prop("using")?, decode_query_dataset)?,
+            pattern: Box::new(decode_graph_pattern(prop("pattern")?)?),
+        })
+    } else if tag == atoms::load() {
+        Ok(GraphUpdateOperation::Load {
+            silent: silent()?,
+            source: decode_named_node(prop("source")?)?,
+            destination: decode_update_graph(prop("destination")?)?,
+        })
+    } else if tag == atoms::clear() {
+        Ok(GraphUpdateOperation::Clear {
+            silent: silent()?,
+            graph: decode_graph_target(prop("graph")?)?,
+        })
+    } else if tag == atoms::create() {
+        Ok(GraphUpdateOperation::Create {
+            silent: silent()?,
+            graph: decode_named_node(prop("graph")?)?,
+        })
+    } else if tag == atoms::drop() {
+        Ok(GraphUpdateOperation::Drop {
+            silent: silent()?,
+            graph: decode_graph_target(prop("graph")?)?,
+        })
+    } else {
+        Err(term)
+    }
+}
+
+/// Splits a `{:quad, s, p, o, graph}` term.
+fn decode_quad_parts(term: Term) -> Result<(Term, Term, Term, Term), Term> {
+    let (tag, subject, predicate, object, graph): (rustler::Atom, Term, Term, Term, Term) =
+        term.decode().map_err(|_| term)?;
+    if tag != atoms::quad() {
+        return Err(term);
+    }
+    Ok((subject, predicate, object, graph))
+}
+
+/// Decodes an INSERT DATA quad.
+fn decode_data_quad(term: Term) -> Result<Quad, Term> {
+    let (subject, predicate, object, graph) = decode_quad_parts(term)?;
+    Ok(Quad {
+        subject: decode_subject(subject)?,
+        predicate: decode_named_node(predicate)?,
+        object: decode_rdf_term(object)?,
+        graph_name: decode_update_graph(graph)?,
+    })
+}
+
+/// Decodes a DELETE DATA quad, which cannot contain blank nodes.
+fn decode_ground_data_quad(term: Term) -> Result<GroundQuad, Term> {
+    let (subject, predicate, object, graph) = decode_quad_parts(term)?;
+    Ok(GroundQuad {
+        subject: GroundSubject::try_from(decode_subject(subject)?).map_err(|_| subject)?,
+        predicate: decode_named_node(predicate)?,
+        object: decode_ground_term(object)?,
+        graph_name: decode_update_graph(graph)?,
+    })
+}
+
+/// Decodes an INSERT template quad.
+fn decode_quad_pattern(term: Term) -> Result<QuadPattern, Term> {
+    let (subject, predicate, object, graph) = decode_quad_parts(term)?;
+    Ok(QuadPattern {
+        subject: decode_term_pattern(subject)?,
+        predicate: decode_named_node_pattern(predicate)?,
+        object: decode_term_pattern(object)?,
+        graph_name: decode_update_graph_pattern(graph)?,
+    })
+}
+
+/// Decodes a DELETE template quad, which cannot contain blank nodes.
+fn decode_ground_quad_pattern(term: Term) -> Result<GroundQuadPattern, Term> {
+    let (subject, predicate, object, graph) = decode_quad_parts(term)?;
+    Ok(GroundQuadPattern {
+        subject: GroundTermPattern::try_from(decode_term_pattern(subject)?).map_err(|_| subject)?,
+        predicate: decode_named_node_pattern(predicate)?,
+        object: GroundTermPattern::try_from(decode_term_pattern(object)?).map_err(|_| object)?,
+        graph_name: decode_update_graph_pattern(graph)?,
+    })
+}
+
+/// Decodes `{:named_graph, iri}` or `:default_graph`.
+fn decode_update_graph(term: Term) -> Result<GraphName, Term> {
+    if let Ok(atom) = term.decode::<rustler::Atom>() {
+        return if atom == atoms::default_graph() {
+            Ok(GraphName::DefaultGraph)
+        } else {
+            Err(term)
+        };
+    }
+    let (tag, iri): (rustler::Atom, String) = term.decode().map_err(|_| term)?;
+    if tag != atoms::named_graph() {
+        return Err(term);
+    }
+    NamedNode::new(iri).map(Into::into).map_err(|_| term)
+}
+
+/// Decodes a template graph: a graph name or a variable.
+fn decode_update_graph_pattern(term: Term) -> Result<GraphNamePattern, Term> {
+    match decode_variable(term) {
+        Ok(variable) => Ok(variable.into()),
+        Err(_) => decode_update_graph(term).map(Into::into),
+    }
+}
+
+/// Decodes a CLEAR/DROP target: a graph name, `:all_named` or `:all_graphs`.
+fn decode_graph_target(term: Term) -> Result<GraphTarget, Term> {
+    match term.decode::<rustler::Atom>() {
+        Ok(atom) if atom == atoms::all_named() => Ok(GraphTarget::NamedGraphs),
+        Ok(atom) if atom == atoms::all_graphs() => Ok(GraphTarget::AllGraphs),
+        _ => match decode_update_graph(term)? {
+            GraphName::NamedNode(node) => Ok(GraphTarget::NamedNode(node)),
+            GraphName::DefaultGraph => Ok(GraphTarget::DefaultGraph),
+        },
+    }
+}
+
+// ===========================================================================
+// Literal Decoding
+// ===========================================================================
+
+/// Integer datatypes with their value-space bounds (inclusive).
+const INTEGER_DATATYPES: [(oxrdf::NamedNodeRef<'static>, Option<i128>, Option<i128>); 13] = [
+    (xsd::INTEGER, None, None),
+    (xsd::LONG, Some(i64::MIN as i128), Some(i64::MAX as i128)),
+    (xsd::INT, Some(i32::MIN as i128), Some(i32::MAX as i128)),
+    (xsd::SHORT, Some(i16::MIN as i128), Some(i16::MAX as i128)),
+    (xsd::BYTE, Some(i8::MIN as i128), Some(i8::MAX as i128)),
+    (xsd::NON_NEGATIVE_INTEGER, Some(0), None),
+    (xsd::POSITIVE_INTEGER, Some(1), None),
+    (xsd::NON_POSITIVE_INTEGER, None, Some(0)),
+    (xsd::NEGATIVE_INTEGER, None, Some(-1)),
+    (xsd::UNSIGNED_LONG, Some(0), Some(u64::MAX as i128)),
+    (xsd::UNSIGNED_INT, Some(0), Some(u32::MAX as i128)),
+    (xsd::UNSIGNED_SHORT, Some(0), Some(u16::MAX as i128)),
+    (xsd::UNSIGNED_BYTE, Some(0), Some(u8::MAX as i128)),
+];
+
+/// Converts a typed literal to a native Elixir value.
+///
+/// Supported datatypes and their results:
+///
+/// - `xsd:boolean` - `true` / `false` (lexical forms `true`, `false`, `1`, `0`)
+/// - `xsd:integer` and its derived types (`xsd:long`, `xsd:int`, ...,
+///   `xsd:unsignedByte`) - integer, checked against the type's range
+/// - `xsd:double`, `xsd:float`, `xsd:decimal` - float; `xsd:float` values are
+///   rounded to single precision and `xsd:decimal` is approximated. The special
+///   values `INF`, `-INF` and `NaN` map to `:inf`, `:neg_inf` and `:nan`
+/// - `xsd:dateTime` - `{:datetime, {{year, month, day}, {hour, minute, second}},
+///   microsecond, offset}` where `offset` is the timezone offset in minutes or
+///   `nil`. `24:00:00` is normalized to midnight of the next day and fractional
+///   seconds are truncated to microseconds
+///
+/// Leading and trailing whitespace is ignored, as the XSD whitespace facet
+/// requires for these types.
+///
+/// # Arguments
+/// * `literal` - Literal term (`{:literal, :typed, value, datatype}`)
+///
+/// # Returns
+/// * `{:ok, native}` on success
+/// * `{:error, :unsupported_datatype}` for other datatypes, simple and
+///   language-tagged literals
+/// * `{:error, {:invalid_lexical_form, value}}` if the value is not valid for
+///   its datatype (including integers beyond 128 bits)
+/// * `{:error, {:invalid_term, term}}` if the term is not a literal
+#[rustler::nif]
+fn decode_literal<'a>(env: Env<'a>, literal: Term<'a>) -> NifResult<Term<'a>> {
+    let literal = match decode_rdf_term(literal) {
+        Ok(spargebra::term::Term::Literal(literal)) => literal,
+        _ => return Ok((atoms::error(), (atoms::invalid_term(), literal)).encode(env)),
+    };
+
+    match native_literal_value(env, &literal) {
+        NativeValue::Decoded(native) => Ok((atoms::ok(), native).encode(env)),
+        NativeValue::Invalid => Ok((
+            atoms::error(),
+            (atoms::invalid_lexical_form(), literal.value()),
+        )
+            .encode(env)),
+        NativeValue::Unsupported => Ok((atoms::error(), atoms::unsupported_datatype()).encode(env)),
+    }
+}
+
+/// Outcome of converting a literal with `native_literal_value`.
+enum NativeValue<'a> {
+    Decoded(Term<'a>),
+    Invalid,
+    Unsupported,
+}
+
+impl<'a> NativeValue<'a> {
+    fn decoded(self) -> Option<Term<'a>> {
+        match self {
+            NativeValue::Decoded(native) => Some(native),
+            NativeValue::Invalid | NativeValue::Unsupported => None,
+        }
+    }
+}
+
+/// Converts a literal to a native value as described on `decode_literal`.
+fn native_literal_value<'a>(env: Env<'a>, literal: &Literal) -> NativeValue<'a> {
+    let datatype = literal.datatype();
+    let lexical = literal.value().trim_matches([' ', '\t', '\n', '\r']);
+    if literal.language().is_some() || datatype == xsd::STRING {
+        return NativeValue::Unsupported;
+    }
+
+    let decoded = if datatype == xsd::BOOLEAN {
+        match lexical {
+            "true" | "1" => Some(true.encode(env)),
+            "false" | "0" => Some(false.encode(env)),
+            _ => None,
+        }
+    } else if let Some((_, min, max)) = INTEGER_DATATYPES.iter().find(|(dt, _, _)| *dt == datatype)
+    {
+        parse_xsd_integer(lexical)
+            .filter(|value| {
+                min.is_none_or(|min| *value >= min) && max.is_none_or(|max| *value <= max)
+            })
+            .map(|value| value.encode(env))
+    } else if datatype == xsd::DOUBLE {
+        parse_xsd_double(lexical).map(|value| float_to_term(env, value))
+    } else if datatype == xsd::FLOAT {
+        parse_xsd_double(lexical).map(|value| float_to_term(env, value as f32 as f64))
+    } else if datatype == xsd::DECIMAL {
+        parse_xsd_decimal(lexical).map(|value| value.encode(env))
+    } else if datatype == xsd::DATE_TIME {
+        parse_xsd_date_time(lexical).map(|(date, time, microsecond, offset)| {
+            (atoms::datetime(), (date, time), microsecond, offset).encode(env)
+        })
+    } else {
+        return NativeValue::Unsupported;
+    };
+
+    decoded.map_or(NativeValue::Invalid, NativeValue::Decoded)
+}
+
+/// Parses `[+-]?[0-9]+`.
+fn parse_xsd_integer(lexical: &str) -> Option<i128> {
+    let digits = lexical.strip_prefix(['+', '-']).unwrap_or(lexical);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    lexical.parse().ok()
+}
+
+/// Parses `[+-]?([0-9]+(\.[0-9]*)?|\.[0-9]+)`.
+fn parse_xsd_decimal(lexical: &str) -> Option<f64> {
+    let unsigned = lexical.strip_prefix(['+', '-']).unwrap_or(lexical);
+    let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+        return None;
+    }
+    lexical.parse().ok()
+}
+
+/// Parses an `xsd:double` lexical form: a decimal with an optional exponent,
+/// or one of `INF`, `+INF`, `-INF`, `NaN`.
+fn parse_xsd_double(lexical: &str) -> Option<f64> {
+    match lexical {
+        "INF" | "+INF" => return Some(f64::INFINITY),
+        "-INF" => return Some(f64::NEG_INFINITY),
+        "NaN" => return Some(f64::NAN),
+        _ => {}
+    }
+
+    let (mantissa, exponent) = match lexical.find(['e', 'E']) {
+        Some(i) => (&lexical[..i], Some(&lexical[i + 1..])),
+        None => (lexical, None),
+    };
+    parse_xsd_decimal(mantissa)?;
+    if let Some(exponent) = exponent {
+        parse_xsd_integer(exponent)?;
+    }
+    lexical.parse().ok()
+}
+
+/// Encodes a float, mapping the special values to atoms since the BEAM has
+/// no representation for them.
+fn float_to_term(env: Env, value: f64) -> Term {
+    if value.is_nan() {
+        atoms::nan().encode(env)
+    } else if value == f64::INFINITY {
+        atoms::inf().encode(env)
+    } else if value == f64::NEG_INFINITY {
+        atoms::neg_inf().encode(env)
+    } else {
+        value.encode(env)
+    }
+}
+
+type XsdDateTime = ((i64, u32, u32), (u32, u32, u32), u32, Option<i32>);
+
+/// Parses `-?YYYY-MM-DDThh:mm:ss(.s+)?(Z|(+|-)hh:mm)?` into date, time,
+/// microseconds and an optional offset in minutes.
+fn parse_xsd_date_time(lexical: &str) -> Option<XsdDateTime> {
+    let (date, rest) = lexical.split_once('T')?;
+
+    // Years have at least four digits; more are allowed only without a leading zero
+    let (negative, date) = match date.strip_prefix('-') {
+        Some(date) => (true, date),
+        None => (false, date),
+    };
+    let mut date_parts = date.rsplitn(3, '-');
+    let day = fixed_digits(date_parts.next()?, 2)?;
+    let month = fixed_digits(date_parts.next()?, 2)?;
+    let year_digits = date_parts.next()?;
+    if year_digits.len() < 4
+        || (year_digits.len() > 4 && year_digits.starts_with('0'))
+        || !year_digits.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let year: i64 = year_digits.parse().ok()?;
+    let year = if negative { -year } else { year };
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let (time, offset) = match rest.find(['Z', '+', '-']) {
+        Some(i) => (&rest[..i], Some(parse_timezone_offset(&rest[i..])?)),
+        None => (rest, None),
+    };
+    let (clock, fraction) = match time.split_once('.') {
+        Some((clock, fraction)) => (clock, Some(fraction)),
+        None => (time, None),
+    };
+    let mut clock_parts = clock.split(':');
+    let hour = fixed_digits(clock_parts.next()?, 2)?;
+    let minute = fixed_digits(clock_parts.next()?, 2)?;
+    let second = fixed_digits(clock_parts.next()?, 2)?;
+    if clock_parts.next().is_some() || hour > 24 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    let microsecond = match fraction {
+        Some(fraction) if !fraction.is_empty() && fraction.bytes().all(|b| b.is_ascii_digit()) => {
+            let micros = &fraction[..fraction.len().min(6)];
+            micros.parse::<u32>().ok()? * 10u32.pow(6 - micros.len() as u32)
+        }
+        Some(_) => return None,
+        None => 0,
+    };
+
+    if hour == 24 {
+        // 24:00:00 is only valid as the end of the day, i.e. the next midnight
+        if minute != 0 || second != 0 || microsecond != 0 {
+            return None;
+        }
+        let (year, month, day) = next_day(year, month, day);
+        return Some(((year, month, day), (0, 0, 0), 0, offset));
+    }
+
+    Some((
+        (year, month, day),
+        (hour, minute, second),
+        microsecond,
+        offset,
+    ))
+}
+
+/// Parses `Z` or `(+|-)hh:mm` into minutes east of UTC.
+fn parse_timezone_offset(zone: &str) -> Option<i32> {
+    if zone == "Z" {
+        return Some(0);
+    }
+    let sign = if zone.starts_with('-') { -1 } else { 1 };
+    let (hours, minutes) = zone[1..].split_once(':')?;
+    let hours = fixed_digits(hours, 2)?;
+    let minutes = fixed_digits(minutes, 2)?;
+    if minutes > 59 || hours * 60 + minutes > 14 * 60 {
+        return None;
+    }
+    Some(sign * (hours * 60 + minutes) as i32)
+}
+
+/// Parses exactly `len` ASCII digits.
+fn fixed_digits(part: &str, len: usize) -> Option<u32> {
+    if part.len() != len || !part.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    part.parse().ok()
+}
+
+/// Number of days in a month of the proleptic Gregorian calendar.
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn next_day(year: i64, month: u32, day: u32) -> (i64, u32, u32) {
+    if day < days_in_month(year, month) {
+        (year, month, day + 1)
+    } else if month < 12 {
+        (year, month + 1, 1)
+    } else {
+        (year + 1, 1, 1)
+    }
+}
+
+// ===========================================================================
+// N-Triples Serialization
+// ===========================================================================
+
+/// Serializes triples to an N-Triples document.
+///
+/// Each triple is a `{subject, predicate, object}` tuple using the same term
+/// encoding the parser produces (`{:named_node, iri}`, `{:blank_node, id}`,
+/// `{:literal, :simple | :typed | :language_tagged, ...}`). Input order and
+/// duplicates are preserved.
+///
+/// # Arguments
+/// * `triples` - List of `{s, p, o}` tuples
+///
+/// # Returns
+/// * `{:ok, ntriples}` on success
+/// * `{:error, {:invalid_term, term}}` if a term cannot be serialized
+#[rustler::nif(schedule = "DirtyCpu")]
+fn serialize_ntriples<'a>(
+    env: Env<'a>,
+    triples: Vec<(Term<'a>, Term<'a>, Term<'a>)>,
+) -> NifResult<Term<'a>> {
+    match write_ntriples(&triples, false) {
+        Ok((document, _count)) => Ok((atoms::ok(), document).encode(env)),
+        Err(term) => Ok((atoms::error(), (atoms::invalid_term(), term)).encode(env)),
+    }
+}
+
+/// Serializes triples to an N-Triples document with options.
+///
+/// With `dedup: true`, each serialized triple is hashed and repeats are
+/// skipped, keeping the first occurrence. The dedup set grows only with the
+/// number of distinct triples. Without it, behaves like `serialize_ntriples/1`.
+///
+/// # Arguments
+/// * `triples` - List of `{s, p, o}` tuples
+/// * `opts` - Keyword list; supports `dedup: boolean`
+///
+/// # Returns
+/// * `{:ok, ntriples, count}` where `count` is the number of triples written
+/// * `{:error, {:invalid_term, term}}` if a term cannot be serialized
+#[rustler::nif(name = "serialize_ntriples", schedule = "DirtyCpu")]
+fn serialize_ntriples_with_opts<'a>(
+    env: Env<'a>,
+    triples: Vec<(Term<'a>, Term<'a>, Term<'a>)>,
+    opts: Vec<(rustler::Atom, bool)>,
+) -> NifResult<Term<'a>> {
+    let dedup = opts
+        .iter()
+        .any(|(key, value)| *key == atoms::dedup() && *value);
+
+    match write_ntriples(&triples, dedup) {
+        Ok((document, count)) => Ok((atoms::ok(), document, count).encode(env)),
+        Err(term) => Ok((atoms::error(), (atoms::invalid_term(), term)).encode(env)),
+    }
+}
+
+/// Writes triples as N-Triples lines, optionally skipping duplicates.
+/// Returns the document and number of lines written, or the offending term.
+fn write_ntriples<'a>(
+    triples: &[(Term<'a>, Term<'a>, Term<'a>)],
+    dedup: bool,
+) -> Result<(String, usize), Term<'a>> {
+    let mut document = String::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut count = 0;
+
+    for (subject, predicate, object) in triples {
+        let mut line = String::new();
+        write_ntriples_term(&mut line, *subject)?;
+        line.push(' ');
+        write_ntriples_term(&mut line, *predicate)?;
+        line.push(' ');
+        write_ntriples_term(&mut line, *object)?;
+        line.push_str(" .\n");
+
+        if dedup {
+            if seen.contains(&line) {
+                continue;
+            }
+            document.push_str(&line);
+            seen.insert(line);
+        } else {
+            document.push_str(&line);
+        }
+        count += 1;
+    }
+
+    Ok((document, count))
+}
+
+/// Appends one term in N-Triples syntax, or returns the term if it is not a
+/// named node, blank node or literal.
+fn write_ntriples_term<'a>(out: &mut String, term: Term<'a>) -> Result<(), Term<'a>> {
+    let elements = rustler::types::tuple::get_tuple(term).map_err(|_| term)?;
+    let tag: rustler::Atom = elements.first().and_then(|t| t.decode().ok()).ok_or(term)?;
+    let string_at = |i: usize| -> Result<String, Term<'a>> {
+        elements
+            .get(i)
+            .and_then(|t| t.decode::<String>().ok())
+            .ok_or(term)
+    };
+
+    if tag == atoms::named_node() && elements.len() == 2 {
+        out.push('<');
+        out.push_str(&string_at(1)?);
+        out.push('>');
+    } else if tag == atoms::blank_node() && elements.len() == 2 {
+        out.push_str("_:");
+        out.push_str(&string_at(1)?);
+    } else if tag == atoms::triple_term() && elements.len() == 4 {
+        out.push_str("<< ");
+        write_ntriples_term(out, elements[1])?;
+        out.push(' ');
+        write_ntriples_term(out, elements[2])?;
+        out.push(' ');
+        write_ntriples_term(out, elements[3])?;
+        out.push_str(" >>");
+    } else if tag == atoms::literal() && elements.len() >= 3 {
+        let kind: rustler::Atom = elements[1].decode().map_err(|_| term)?;
+        let value = string_at(2)?;
+
+        out.push('"');
+        escape_ntriples_literal(out, &value);
+        out.push('"');
+
+        match elements.len() {
+            3 if kind == atoms::simple() => {}
+            4 if kind == atoms::language_tagged() => {
+                out.push('@');
+                out.push_str(&string_at(3)?);
+            }
+            4 if kind == atoms::typed() => {
+                out.push_str("^^<");
+                out.push_str(&string_at(3)?);
+                out.push('>');
+            }
+            _ => return Err(term),
+        }
+    } else {
+        return Err(term);
+    }
+
+    Ok(())
+}
+
+/// Escapes a literal lexical form for N-Triples output.
+fn escape_ntriples_literal(out: &mut String, value: &str) {
+    for ch in value.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(ch),
+        }
+    }
+}
+
+// ===========================================================================
+// N-Quads Serialization
+// ===========================================================================
+
+/// Serializes quads to an N-Quads document.
+///
+/// Each quad is a `{subject, predicate, object, graph}` tuple where `graph`
+/// is a `{:named_node, iri}` term or `:default`. Terms are validated and
+/// written by oxttl's N-Quads serializer, so output is canonical: parsing the
+/// document back and serializing it again yields the same bytes.
+/// Input order and duplicates are preserved.
+///
+/// # Arguments
+/// * `quads` - List of `{s, p, o, graph}` tuples
+///
+/// # Returns
+/// * `{:ok, nquads}` on success
+/// * `{:error, {:invalid_term, term}}` if a subject, predicate or object is invalid
+/// * `{:error, {:invalid_graph, graph}}` if a graph is not a named node or `:default`
+#[rustler::nif(schedule = "DirtyCpu")]
+fn serialize_nquads<'a>(
+    env: Env<'a>,
+    quads: Vec<(Term<'a>, Term<'a>, Term<'a>, Term<'a>)>,
+) -> NifResult<Term<'a>> {
+    let mut serializer = NQuadsSerializer::new().for_writer(Vec::new());
+
+    for (subject, predicate, object, graph) in quads {
+        let quad = match decode_quad(subject, predicate, object, graph) {
+            Ok(quad) => quad,
+            Err(error) => return Ok((atoms::error(), error).encode(env)),
+        };
+        serializer
+            .serialize_quad(&quad)
+            .map_err(|e| rustler::Error::Term(Box::new(e.to_string())))?;
+    }
+
+    let document = String::from_utf8(serializer.finish())
+        .map_err(|e| rustler::Error::Term(Box::new(e.to_string())))?;
+    Ok((atoms::ok(), document).encode(env))
+}
+
+/// Decodes one quad, returning `{:invalid_term, term}` or
+/// `{:invalid_graph, graph}` for the first bad component.
+fn decode_quad<'a>(
+    subject: Term<'a>,
+    predicate: Term<'a>,
+    object: Term<'a>,
+    graph: Term<'a>,
+) -> Result<oxrdf::Quad, (rustler::Atom, Term<'a>)> {
+    let invalid_term = |term| (atoms::invalid_term(), term);
+
+    let subject = match decode_rdf_term(subject).map_err(invalid_term)? {
+        spargebra::term::Term::NamedNode(node) => Subject::NamedNode(node),
+        spargebra::term::Term::BlankNode(node) => Subject::BlankNode(node),
+        spargebra::term::Term::Triple(triple) => Subject::Triple(triple),
+        spargebra::term::Term::Literal(_) => return Err(invalid_term(subject)),
+    };
+    let predicate = match decode_rdf_term(predicate).map_err(invalid_term)? {
+        spargebra::term::Term::NamedNode(node) => node,
+        _ => return Err(invalid_term(predicate)),
+    };
+    let object = decode_rdf_term(object).map_err(invalid_term)?;
+    let graph_name = decode_graph_name(graph).ok_or((atoms::invalid_graph(), graph))?;
+
+    Ok(oxrdf::Quad::new(subject, predicate, object, graph_name))
+}
+
+/// Decodes `:default` or `{:named_node, iri}` into a graph name.
+fn decode_graph_name(graph: Term) -> Option<oxrdf::GraphName> {
+    if graph.decode::<rustler::Atom>().ok() == Some(atoms::default()) {
+        return Some(oxrdf::GraphName::DefaultGraph);
+    }
+    match decode_rdf_term(graph).ok()? {
+        spargebra::term::Term::NamedNode(node) => Some(oxrdf::GraphName::NamedNode(node)),
+        _ => None,
+    }
+}
+
+/// Decodes an encoded RDF term, validating IRIs, blank node ids and language
+/// tags. Returns the term itself (or the malformed part of a quoted triple)
+/// if it is malformed.
+fn decode_rdf_term(term: Term) -> Result<spargebra::term::Term, Term> {
+    let elements = rustler::types::tuple::get_tuple(term).map_err(|_| term)?;
+    let tag: rustler::Atom = elements.first().and_then(|t| t.decode().ok()).ok_or(term)?;
+    let string_at = |i: usize| -> Result<String, Term> {
+        elements
+            .get(i)
+            .and_then(|t| t.decode::<String>().ok())
+            .ok_or(term)
+    };
+
+    if tag == atoms::triple_term() && elements.len() == 4 {
+        let subject = decode_subject(elements[1])?;
+        let predicate = decode_named_node(elements[2])?;
+        let object = decode_rdf_term(elements[3])?;
+        return Ok(Triple::new(subject, predicate, object).into());
+    }
+
+    if tag == atoms::named_node() && elements.len() == 2 {
+        return NamedNode::new(string_at(1)?)
+            .map(Into::into)
+            .map_err(|_| term);
+    }
+    if tag == atoms::blank_node() && elements.len() == 2 {
+        return BlankNode::new(string_at(1)?)
+            .map(Into::into)
+            .map_err(|_| term);
+    }
+    if tag != atoms::literal() || elements.len() < 3 {
+        return Err(term);
+    }
+
+    let kind: rustler::Atom = elements[1].decode().map_err(|_| term)?;
+    let value = string_at(2)?;
+    let literal = match elements.len() {
+        3 if kind == atoms::simple() => Literal::new_simple_literal(value),
+        4 if kind == atoms::language_tagged() => {
+            Literal::new_language_tagged_literal(value, string_at(3)?).map_err(|_| term)?
+        }
+        4 if kind == atoms::typed() => {
+            Literal::new_typed_literal(value, NamedNode::new(string_at(3)?).map_err(|_| term)?)
+        }
+        _ => return Err(term),
+    };
+    Ok(literal.into())
+}
+
 rustler::init!("Elixir.TripleStore.SPARQL.Parser.NIF");
@@ -8,7 +8,7 @@ defmodule TripleStore.Test.DbPool do
   alias TripleStore.Backend.RocksDB.NIF
   alias TripleStore.TestHelpers
 
-  @column_families [:id2str, :str2id, :spo, :pos, :osp, :derived]
+  @column_families [:id2str, :str2id, :spo, :pos, :osp, :derived, :gspo, :gpos, :gosp]
   @default_pool_size System.schedulers_online()
   @checkout_timeout 30_000
 
@@ -0,0 +1,160 @@
+defmodule TripleStore.Backend.RocksDB.BackupTest do
+  @moduledoc """
+  Tests for RocksDB backup and checkpoint operations.
+  """
+  use TripleStore.PooledDbCase
+
+  setup %{db_path: path} do
+    backup_path = "#{path}_backups"
+    on_exit(fn -> File.rm_rf(backup_path) end)
+    {:ok, backup_path: backup_path}
+  end
+
+  describe "create_backup/2" do
+    test "backs up while the database stays writable", %{db: db, backup_path: backup_path} do
+      :ok = NIF.put(db, :spo, "s1p1o1", "")
+
+      assert {:ok, 1} = NIF.create_backup(db, backup_path)
+      assert :ok = NIF.put(db, :spo, "s2p2o2", "")
+      assert {:ok, 2} = NIF.create_backup(db, backup_path)
+    end
+
+    test "returns error for closed database", %{db_path: path, backup_path: backup_path} do
+      {:ok, db2} = NIF.open("#{path}_closed")
+      NIF.close(db2)
+
+      assert {:error, :already_closed} = NIF.create_backup(db2, backup_path)
+      File.rm_rf("#{path}_closed")
+    end
+  end
+
+  describe "list_backups/1" do
+    test "lists backups oldest first", %{db: db, backup_path: backup_path} do
+      :ok = NIF.put(db, :spo, "key", "value")
+      {:ok, first} = NIF.create_backup(db, backup_path)
+      {:ok, second} = NIF.create_backup(db, backup_path)
+
+      assert {:ok, [{^first, ts1, size1}, {^second, ts2, _}]} = NIF.list_backups(backup_path)
+      assert ts1 <= ts2
+      assert size1 > 0
+    end
+
+    test "is empty for a new backup directory", %{backup_path: backup_path} do
+      assert {:ok, []} = NIF.list_backups(backup_path)
+    end
+  end
+
+  describe "purge_old_backups/2" do
+    test "keeps only the newest backups", %{db: db, backup_path: backup_path} do
+      for i <- 1..3 do
+        :ok = NIF.put(db, :spo, "key#{i}", "")
+        {:ok, ^i} = NIF.create_backup(db, backup_path)
+      end
+
+      assert :ok = NIF.purge_old_backups(backup_path, 2)
+      assert {:ok, [{2, _, _}, {3, _, _}]} = NIF.list_backups(backup_path)
+    end
+  end
+
+  describe "restore_from_backup/3" do
+    setup %{db_path: path} do
+      restore_path = "#{path}_restored"
+      on_exit(fn -> File.rm_rf(restore_path) end)
+      {:ok, restore_path: restore_path}
+    end
+
+    test "restores the latest backup", ctx do
+      %{db: db, backup_path: backup_path, restore_path: restore_path} = ctx
+      :ok = NIF.put(db, :spo, "key", "v1")
+      {:ok, _} = NIF.create_backup(db, backup_path)
+      :ok = NIF.put(db, :spo, "key", "v2")
+      {:ok, _} = NIF.create_backup(db, backup_path)
+
+      assert {:ok, ^restore_path} = NIF.restore_from_backup(backup_path, restore_path, :latest)
+
+      {:ok, restored} = NIF.open(restore_path)
+      assert {:ok, "v2"} = NIF.get(restored, :spo, "key")
+      NIF.close(restored)
+    end
+
+    test "restores a specific backup and treats 0 as latest", ctx do
+      %{db: db, backup_path: backup_path, restore_path: restore_path} = ctx
+      :ok = NIF.put(db, :spo, "key", "v1")
+      {:ok, first} = NIF.create_backup(db, backup_path)
+      :ok = NIF.put(db, :spo, "key", "v2")
+      {:ok, _} = NIF.create_backup(db, backup_path)
+
+      assert {:ok, _} = NIF.restore_from_backup(backup_path, restore_path, first)
+      {:ok, restored} = NIF.open(restore_path)
+      assert {:ok, "v1"} = NIF.get(restored, :spo, "key")
+      NIF.close(restored)
+
+      latest_path = restore_path <> "_latest"
+      assert {:ok, _} = NIF.restore_from_backup(backup_path, latest_path, 0)
+      {:ok, restored} = NIF.open(latest_path)
+      assert {:ok, "v2"} = NIF.get(restored, :spo, "key")
+      NIF.close(restored)
+      File.rm_rf(latest_path)
+    end
+
+    test "refuses a non-empty target", ctx do
+      %{db: db, backup_path: backup_path, restore_path: restore_path} = ctx
+      {:ok, _} = NIF.create_backup(db, backup_path)
+      File.mkdir_p!(restore_path)
+      File.write!(Path.join(restore_path, "keep.txt"), "data")
+
+      assert {:error, {:restore_failed, _}} =
+               NIF.restore_from_backup(backup_path, restore_path, :latest)
+
+      assert File.read!(Path.join(restore_path, "keep.txt")) == "data"
+    end
+
+    test "returns error for a missing backup", ctx do
+      %{db: db, backup_path: backup_path, restore_path: restore_path} = ctx
+      {:ok, _} = NIF.create_backup(db, backup_path)
+
+      assert {:error, {:restore_failed, _}} = NIF.restore_from_backup(backup_path, restore_path, 99)
+      assert {:error, {:invalid_option, :newest}} =
+               NIF.restore_from_backup(backup_path, restore_path, :newest)
+    end
+  end
+
+  describe "create_checkpoint/2" do
+    setup %{db_path: path} do
+      checkpoint_path = "#{path}_checkpoint"
+      on_exit(fn -> File.rm_rf(checkpoint_path) end)
+      {:ok, checkpoint_path: checkpoint_path}
+    end
+
+    test "creates an openable fork and leaves the source usable", ctx do
+      %{db: db, checkpoint_path: checkpoint_path} = ctx
+      :ok = NIF.put(db, :spo, "key", "before")
+
+      assert :ok = NIF.create_checkpoint(db, checkpoint_path)
+      :ok = NIF.put(db, :spo, "key", "after")
+
+      {:ok, fork} = NIF.open(checkpoint_path)
+      assert {:ok, "before"} = NIF.get(fork, :spo, "key")
+      :ok = NIF.put(fork, :spo, "fork_only", "")
+      NIF.close(fork)
+
+      assert {:ok, "after"} = NIF.get(db, :spo, "key")
+      assert :not_found = NIF.get(db, :spo, "fork_only")
+    end
+
+    test "returns error when the path already exists", ctx do
+      %{db: db, checkpoint_path: checkpoint_path} = ctx
+      File.mkdir_p!(checkpoint_path)
+
+      assert {:error, {:checkpoint_failed, _}} = NIF.create_checkpoint(db, checkpoint_path)
+    end
+
+    test "returns error for closed database", %{db_path: path, checkpoint_path: checkpoint_path} do
+      {:ok, db2} = NIF.open("#{path}_closed")
+      NIF.close(db2)
+
+      assert {:error, :already_closed} = NIF.create_checkpoint(db2, checkpoint_path)
+      File.rm_rf("#{path}_closed")
+    end
+  end
+end
@@ -4,6 +4,8 @@ defmodule TripleStore.Backend.RocksDB.IteratorTest do
   """
   use TripleStore.PooledDbCase
 
+  require Logger
+
   describe "prefix_iterator/3" do
     test "creates an iterator for a column family", %{db: db} do
       assert {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
@@ -42,6 +44,308 @@ defmodule TripleStore.Backend.RocksDB.IteratorTest do
     end
   end
 
+  describe "prefix_iterator/4" do
+    setup %{db: db} do
+      for key <- ["p:1", "p:3", "p:5", "q:0", "o:9"] do
+        NIF.put(db, :derived, key, "v" <> key)
+      end
+
+      :ok
+    end
+
+    test ":forward matches prefix_iterator/3", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "p:", :forward)
+      assert {:ok, [{"p:1", _}, {"p:3", _}, {"p:5", _}]} = NIF.iterator_collect(iter)
+    end
+
+    test ":reverse starts at the last key of the prefix", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "p:", :reverse)
+      assert {:ok, "p:5", "vp:5"} = NIF.iterator_next(iter)
+      assert {:ok, "p:3", "vp:3"} = NIF.iterator_next(iter)
+      assert {:ok, "p:1", "vp:1"} = NIF.iterator_next(iter)
+      assert :iterator_end = NIF.iterator_next(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test ":reverse with an empty prefix walks the whole column family", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "", :reverse)
+      {:ok, entries} = NIF.iterator_collect(iter)
+      assert Enum.map(entries, &elem(&1, 0)) == ["q:0", "p:5", "p:3", "p:1", "o:9"]
+    end
+
+    test ":reverse reports why iteration ended", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "p:", :reverse)
+      {:ok, _} = NIF.iterator_collect(iter)
+      assert {:iterator_end, :prefix_boundary} = NIF.iterator_next_reason(iter)
+
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "o:", :reverse)
+      {:ok, _} = NIF.iterator_collect(iter)
+      assert {:iterator_end, :cf_end} = NIF.iterator_next_reason(iter)
+    end
+
+    test ":reverse seek and reset stay descending", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "p:", :reverse)
+      assert :ok = NIF.iterator_seek(iter, "p:4")
+      assert {:ok, "p:3", _} = NIF.iterator_next(iter)
+
+      assert :ok = NIF.iterator_reset(iter)
+      assert {:ok, "p:5", _} = NIF.iterator_next(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "returns error for an unknown direction", %{db: db} do
+      assert {:error, {:invalid_direction, :sideways}} =
+               NIF.prefix_iterator(db, :derived, "p:", :sideways)
+
+      assert {:error, {:invalid_direction, :sideways}} =
+               NIF.prefix_iterator(db, :derived, "p:", direction: :sideways)
+    end
+
+    test ":upper_bound stops before the bound", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "p:", upper_bound: "p:5")
+      assert {:ok, [{"p:1", _}, {"p:3", _}]} = NIF.iterator_collect(iter)
+      assert {:iterator_end, :prefix_boundary} = NIF.iterator_next_reason(iter)
+    end
+
+    test ":upper_bound past the prefix leaves the prefix check in charge", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "p:", upper_bound: "z")
+      assert {:ok, [{"p:1", _}, {"p:3", _}, {"p:5", _}]} = NIF.iterator_collect(iter)
+    end
+
+    test ":upper_bound is kept by seek and reset", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "p:", upper_bound: "p:4")
+      assert :ok = NIF.iterator_seek(iter, "p:2")
+      assert {:ok, "p:3", _} = NIF.iterator_next(iter)
+      assert :iterator_end = NIF.iterator_next(iter)
+
+      assert :ok = NIF.iterator_reset(iter)
+      assert {:ok, [{"p:1", _}, {"p:3", _}]} = NIF.iterator_collect(iter)
+    end
+
+    test ":upper_bound with :reverse starts below the bound", %{db: db} do
+      {:ok, iter} =
+        NIF.prefix_iterator(db, :derived, "p:", direction: :reverse, upper_bound: "p:5")
+
+      assert {:ok, [{"p:3", _}, {"p:1", _}]} = NIF.iterator_collect(iter)
+    end
+
+    test "returns error for an invalid upper bound", %{db: db} do
+      assert {:error, {:invalid_option, :upper_bound}} =
+               NIF.prefix_iterator(db, :derived, "p:", upper_bound: :p)
+    end
+  end
+
+  describe "prefix_iterator_filtered/4" do
+    test "yields only entries whose value matches", %{db: db} do
+      NIF.put(db, :derived, "k1", <<1, "a">>)
+      NIF.put(db, :derived, "k2", <<2, "b">>)
+      NIF.put(db, :derived, "k3", <<1, "c">>)
+      NIF.put(db, :derived, "other", <<1, "d">>)
+
+      {:ok, iter} = NIF.prefix_iterator_filtered(db, :derived, "k", <<1>>)
+      assert {:ok, "k1", <<1, "a">>} = NIF.iterator_next(iter)
+      assert {:ok, "k3", <<1, "c">>} = NIF.iterator_next(iter)
+      assert :iterator_end = NIF.iterator_next(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "empty value prefix matches everything", %{db: db} do
+      NIF.put(db, :derived, "k1", "x")
+      NIF.put(db, :derived, "k2", "")
+
+      {:ok, iter} = NIF.prefix_iterator_filtered(db, :derived, "k", "")
+      assert {:ok, [{"k1", "x"}, {"k2", ""}]} = NIF.iterator_collect(iter)
+    end
+
+    test "returns error for invalid column family", %{db: db} do
+      assert {:error, {:invalid_cf, :bogus}} =
+               NIF.prefix_iterator_filtered(db, :bogus, "", "")
+    end
+  end
+
+  describe "prefix_iterator_after/4" do
+    setup %{db: db} do
+      for key <- ["p:1", "p:3", "p:5", "q:1"] do
+        NIF.put(db, :derived, key, "v" <> key)
+      end
+
+      :ok
+    end
+
+    test "skips a present after_key", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator_after(db, :derived, "p:", "p:3")
+      assert {:ok, [{"p:5", "vp:5"}]} = NIF.iterator_collect(iter)
+    end
+
+    test "positions at the next greater key when after_key is absent", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator_after(db, :derived, "p:", "p:2")
+      assert {:ok, "p:3", "vp:3"} = NIF.iterator_next(iter)
+      assert {:ok, "p:5", "vp:5"} = NIF.iterator_next(iter)
+      assert :iterator_end = NIF.iterator_next(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "starts at the prefix when after_key sorts before it", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator_after(db, :derived, "p:", "a")
+      assert {:ok, [{"p:1", _}, {"p:3", _}, {"p:5", _}]} = NIF.iterator_collect(iter)
+    end
+
+    test "is empty after the last key of the prefix", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator_after(db, :derived, "p:", "p:5")
+      assert :iterator_end = NIF.iterator_next(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "returns error for invalid column family", %{db: db} do
+      assert {:error, {:invalid_cf, :bogus}} = NIF.prefix_iterator_after(db, :bogus, "", "")
+    end
+  end
+
+  describe "bounded_iterator/4" do
+    setup %{db: db} do
+      for key <- ["a:1", "p:1", "p:3", "p:5", "q:1", "q:2", "r:1"] do
+        NIF.put(db, :derived, key, "v" <> key)
+      end
+
+      :ok
+    end
+
+    test "yields keys in [lower, upper)", %{db: db} do
+      {:ok, iter} = NIF.bounded_iterator(db, :derived, "p:3", "q:2")
+
+      assert {:ok, [{"p:3", "vp:3"}, {"p:5", "vp:5"}, {"q:1", "vq:1"}]} =
+               NIF.iterator_collect(iter)
+    end
+
+    test "matches prefix_iterator over the prefix range", %{db: db} do
+      {:ok, prefix_iter} = NIF.prefix_iterator(db, :derived, "p:")
+      {:ok, bounded_iter} = NIF.bounded_iterator(db, :derived, "p:", "p;")
+
+      assert {:ok, expected} = NIF.iterator_collect(prefix_iter)
+      assert {:ok, ^expected} = NIF.iterator_collect(bounded_iter)
+      assert length(expected) == 3
+    end
+
+    test "reports why iteration ended", %{db: db} do
+      {:ok, iter} = NIF.bounded_iterator(db, :derived, "q:", "q;")
+      assert {:ok, "q:1", _} = NIF.iterator_next_reason(iter)
+      assert {:ok, "q:2", _} = NIF.iterator_next_reason(iter)
+      assert {:iterator_end, :prefix_boundary} = NIF.iterator_next_reason(iter)
+      NIF.iterator_close(iter)
+
+      {:ok, iter} = NIF.bounded_iterator(db, :derived, "r:", "z")
+      assert {:ok, "r:1", _} = NIF.iterator_next_reason(iter)
+      assert {:iterator_end, :cf_end} = NIF.iterator_next_reason(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "seek and reset stay within the bounds", %{db: db} do
+      {:ok, iter} = NIF.bounded_iterator(db, :derived, "p:", "q:")
+
+      assert :ok = NIF.iterator_seek(iter, "a")
+      assert {:ok, "p:1", _} = NIF.iterator_next(iter)
+
+      assert :ok = NIF.iterator_seek(iter, "p:4")
+      assert {:ok, "p:5", _} = NIF.iterator_next(iter)
+      assert :iterator_end = NIF.iterator_next(iter)
+
+      assert :ok = NIF.iterator_reset(iter)
+      assert {:ok, "p:1", _} = NIF.iterator_next(iter)
+
+      NIF.iterator_close(iter)
+    end
+
+    test "is empty when lower equals upper", %{db: db} do
+      {:ok, iter} = NIF.bounded_iterator(db, :derived, "p:1", "p:1")
+      assert :iterator_end = NIF.iterator_next(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "returns error when lower is greater than upper", %{db: db} do
+      assert {:error, :invalid_range} = NIF.bounded_iterator(db, :derived, "q", "p")
+    end
+
+    test "returns error for invalid column family", %{db: db} do
+      assert {:error, {:invalid_cf, :bogus}} = NIF.bounded_iterator(db, :bogus, "", "")
+    end
+
+    @tag :benchmark
+    test "is not slower than prefix_iterator on a large range", %{db: db} do
+      for i <- 1..20_000 do
+        NIF.put(db, :spo, <<1, i::64>>, "")
+      end
+
+      NIF.put(db, :spo, <<2, 0::64>>, "")
+
+      {prefix_us, {:ok, prefix_results}} =
+        :timer.tc(fn ->
+          {:ok, iter} = NIF.prefix_iterator(db, :spo, <<1>>)
+          NIF.iterator_collect(iter)
+        end)
+
+      {bounded_us, {:ok, bounded_results}} =
+        :timer.tc(fn ->
+          {:ok, iter} = NIF.bounded_iterator(db, :spo, <<1>>, <<2>>)
+          NIF.iterator_collect(iter)
+        end)
+
+      Logger.debug("""
+      Range scan benchmark (20000 keys):
+        prefix_iterator: #{div(prefix_us, 1000)}ms
+        bounded_iterator: #{div(bounded_us, 1000)}ms
+      """)
+
+      assert bounded_results == prefix_results
+      assert length(bounded_results) == 20_000
+    end
+  end
+
+  describe "range_iterator/5" do
+    setup %{db: db} do
+      for key <- ["S1P1", "S1P1\0x", "S1P5", "S1P9", "S1P9\0", "S1Q1"] do
+        NIF.put(db, :spo, key, "")
+      end
+
+      :ok
+    end
+
+    test "excludes end_key by default", %{db: db} do
+      {:ok, iter} = NIF.range_iterator(db, :spo, "S1P1", "S1P9", [])
+      {:ok, entries} = NIF.iterator_collect(iter)
+      assert Enum.map(entries, &elem(&1, 0)) == ["S1P1", "S1P1\0x", "S1P5"]
+    end
+
+    test "includes only end_key itself with inclusive_end", %{db: db} do
+      {:ok, iter} = NIF.range_iterator(db, :spo, "S1P1", "S1P9", inclusive_end: true)
+      {:ok, entries} = NIF.iterator_collect(iter)
+      assert Enum.map(entries, &elem(&1, 0)) == ["S1P1", "S1P1\0x", "S1P5", "S1P9"]
+    end
+
+    test "reset rewinds to start_key", %{db: db} do
+      {:ok, iter} = NIF.range_iterator(db, :spo, "S1P5", "S1P9", inclusive_end: true)
+      assert {:ok, "S1P5", _} = NIF.iterator_next(iter)
+      assert {:ok, "S1P9", _} = NIF.iterator_next(iter)
+      assert :iterator_end = NIF.iterator_next(iter)
+
+      assert :ok = NIF.iterator_reset(iter)
+      assert {:ok, "S1P5", _} = NIF.iterator_next(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "returns error when start_key is after end_key", %{db: db} do
+      assert {:error, :invalid_range} = NIF.range_iterator(db, :spo, "S1P9", "S1P1", [])
+    end
+
+    test "returns error for a non-boolean inclusive_end", %{db: db} do
+      assert {:error, {:invalid_option, :inclusive_end}} =
+               NIF.range_iterator(db, :spo, "S1P1", "S1P9", inclusive_end: :yes)
+    end
+
+    test "returns error for invalid column family", %{db: db} do
+      assert {:error, {:invalid_cf, :bogus}} = NIF.range_iterator(db, :bogus, "a", "b", [])
+    end
+  end
+
   describe "iterator_next/1" do
     test "returns key-value pairs in order", %{db: db} do
       NIF.put(db, :spo, "a", "1")
@@ -97,6 +401,247 @@ defmodule TripleStore.Backend.RocksDB.IteratorTest do
     end
   end
 
+  describe "iterator_next_reason/1" do
+    test "reports :prefix_boundary when the next key leaves the prefix", %{db: db} do
+      NIF.put(db, :spo, "prefix_a", "1")
+      NIF.put(db, :spo, "zzz", "2")
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "prefix_")
+
+      assert {:ok, "prefix_a", "1"} = NIF.iterator_next_reason(iter)
+      assert {:iterator_end, :prefix_boundary} = NIF.iterator_next_reason(iter)
+
+      NIF.iterator_close(iter)
+    end
+
+    test "reports :cf_end when the column family is exhausted", %{db: db} do
+      NIF.put(db, :spo, "prefix_a", "1")
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "prefix_")
+
+      assert {:ok, "prefix_a", "1"} = NIF.iterator_next_reason(iter)
+      assert {:iterator_end, :cf_end} = NIF.iterator_next_reason(iter)
+
+      NIF.iterator_close(iter)
+    end
+
+    test "returns error for closed iterator", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
+      NIF.iterator_close(iter)
+
+      assert {:error, :iterator_closed} = NIF.iterator_next_reason(iter)
+    end
+  end
+
+  describe "iterator_skip/2" do
+    test "skips entries and continues after them", %{db: db} do
+      for i <- 1..5, do: NIF.put(db, :spo, "s1_#{i}", "v#{i}")
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
+      assert {:ok, 3} = NIF.iterator_skip(iter, 3)
+      assert {:ok, "s1_4", "v4"} = NIF.iterator_next(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "stops at the prefix boundary", %{db: db} do
+      NIF.put(db, :spo, "s1a", "")
+      NIF.put(db, :spo, "s1b", "")
+      NIF.put(db, :spo, "s2a", "")
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
+      assert {:ok, 2} = NIF.iterator_skip(iter, 10)
+      assert :iterator_end = NIF.iterator_next(iter)
+      assert {:ok, "s1b", ""} = NIF.iterator_prev(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "does not count entries rejected by the value filter", %{db: db} do
+      for {key, value} <- [{"k1", <<1>>}, {"k2", <<2>>}, {"k3", <<1>>}, {"k4", <<1>>}] do
+        NIF.put(db, :derived, key, value)
+      end
+
+      {:ok, iter} = NIF.prefix_iterator_filtered(db, :derived, "k", <<1>>)
+      assert {:ok, 2} = NIF.iterator_skip(iter, 2)
+      assert {:ok, "k4", <<1>>} = NIF.iterator_next(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "skipping zero entries leaves the position unchanged", %{db: db} do
+      NIF.put(db, :spo, "a", "1")
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
+      assert {:ok, 0} = NIF.iterator_skip(iter, 0)
+      assert {:ok, "a", "1"} = NIF.iterator_next(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "returns error for closed iterator", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
+      NIF.iterator_close(iter)
+      assert {:error, :iterator_closed} = NIF.iterator_skip(iter, 1)
+    end
+  end
+
+  describe "iterator_next_batch/2" do
+    test "returns entries in batches", %{db: db} do
+      for i <- 1..5, do: NIF.put(db, :spo, "s1_#{i}", "v#{i}")
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
+      assert {:ok, [{"s1_1", "v1"}, {"s1_2", "v2"}], :more} = NIF.iterator_next_batch(iter, 2)
+      assert {:ok, [{"s1_3", "v3"}, {"s1_4", "v4"}], :more} = NIF.iterator_next_batch(iter, 2)
+      assert {:ok, [{"s1_5", "v5"}], :end} = NIF.iterator_next_batch(iter, 2)
+      assert {:ok, [], :end} = NIF.iterator_next_batch(iter, 2)
+      NIF.iterator_close(iter)
+    end
+
+    test "stops at the prefix boundary", %{db: db} do
+      NIF.put(db, :spo, "s1a", "")
+      NIF.put(db, :spo, "s1b", "")
+      NIF.put(db, :spo, "s2a", "")
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1")
+      assert {:ok, [{"s1a", ""}, {"s1b", ""}], :end} = NIF.iterator_next_batch(iter, 10)
+      NIF.iterator_close(iter)
+    end
+
+    test "returns a full batch as :more even when nothing follows", %{db: db} do
+      NIF.put(db, :spo, "a", "1")
+      NIF.put(db, :spo, "b", "2")
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
+      assert {:ok, [_, _], :more} = NIF.iterator_next_batch(iter, 2)
+      assert {:ok, [], :end} = NIF.iterator_next_batch(iter, 2)
+      NIF.iterator_close(iter)
+    end
+
+    test "matches iterator_next over the same range", %{db: db} do
+      for i <- 1..25, do: NIF.put(db, :spo, <<1::64, i::64>>, "")
+
+      {:ok, single} = NIF.prefix_iterator(db, :spo, <<1::64>>)
+      {:ok, expected} = NIF.iterator_collect(single)
+
+      {:ok, batched} = NIF.prefix_iterator(db, :spo, <<1::64>>)
+
+      entries =
+        Stream.unfold(:more, fn
+          :end -> nil
+          :more ->
+            {:ok, batch, status} = NIF.iterator_next_batch(batched, 7)
+            {batch, status}
+        end)
+        |> Enum.concat()
+
+      assert entries == expected
+      NIF.iterator_close(single)
+      NIF.iterator_close(batched)
+    end
+
+    test "continues after iterator_next", %{db: db} do
+      for k <- ["a", "b", "c"], do: NIF.put(db, :spo, k, k)
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
+      assert {:ok, "a", "a"} = NIF.iterator_next(iter)
+      assert {:ok, [{"b", "b"}, {"c", "c"}], :end} = NIF.iterator_next_batch(iter, 5)
+      NIF.iterator_close(iter)
+    end
+
+    test "returns error for closed iterator", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
+      NIF.iterator_close(iter)
+      assert {:error, :iterator_closed} = NIF.iterator_next_batch(iter, 10)
+    end
+  end
+
+  describe "iterator_prev/1" do
+    test "steps back and forth like a cursor", %{db: db} do
+      for k <- ["a", "b", "c"], do: NIF.put(db, :spo, k, k)
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
+      assert {:ok, "a", "a"} = NIF.iterator_next(iter)
+      assert {:ok, "b", "b"} = NIF.iterator_next(iter)
+      assert {:ok, "a", "a"} = NIF.iterator_prev(iter)
+      assert {:ok, "b", "b"} = NIF.iterator_next(iter)
+      assert {:ok, "c", "c"} = NIF.iterator_next(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "returns :iterator_end at the start", %{db: db} do
+      NIF.put(db, :spo, "a", "1")
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
+      assert :iterator_end = NIF.iterator_prev(iter)
+      assert {:ok, "a", "1"} = NIF.iterator_next(iter)
+      assert :iterator_end = NIF.iterator_prev(iter)
+      assert {:ok, "a", "1"} = NIF.iterator_next(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "steps back from the end after the iterator ran out", %{db: db} do
+      for k <- ["a", "b"], do: NIF.put(db, :spo, k, k)
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
+      assert {:ok, [_, _]} = NIF.iterator_collect(iter)
+      assert {:ok, "b", "b"} = NIF.iterator_prev(iter)
+      assert {:ok, "a", "a"} = NIF.iterator_prev(iter)
+      assert :iterator_end = NIF.iterator_prev(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "stops at the prefix boundary", %{db: db} do
+      NIF.put(db, :spo, "s1z", "")
+      NIF.put(db, :spo, "s2a", "")
+      NIF.put(db, :spo, "s2b", "")
+      NIF.put(db, :spo, "s3a", "")
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "s2")
+      assert {:ok, "s2a", _} = NIF.iterator_next(iter)
+      assert {:ok, "s2b", _} = NIF.iterator_next(iter)
+      assert :iterator_end = NIF.iterator_next(iter)
+      assert {:ok, "s2b", _} = NIF.iterator_prev(iter)
+      assert {:ok, "s2a", _} = NIF.iterator_prev(iter)
+      assert :iterator_end = NIF.iterator_prev(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "returns the entry before a seek target", %{db: db} do
+      for k <- ["a", "b", "d"], do: NIF.put(db, :spo, k, k)
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
+      :ok = NIF.iterator_seek(iter, "c")
+      assert {:ok, "b", "b"} = NIF.iterator_prev(iter)
+      assert {:ok, "d", "d"} = NIF.iterator_next(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "steps towards larger keys on reverse iterators", %{db: db} do
+      for k <- ["s1a", "s1b", "s1c"], do: NIF.put(db, :spo, k, k)
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "s1", :reverse)
+      assert {:ok, "s1c", _} = NIF.iterator_next(iter)
+      assert {:ok, "s1b", _} = NIF.iterator_next(iter)
+      assert {:ok, "s1c", _} = NIF.iterator_prev(iter)
+      assert :iterator_end = NIF.iterator_prev(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "respects the bounds of bounded iterators", %{db: db} do
+      for k <- ["a", "b", "c", "d"], do: NIF.put(db, :spo, k, k)
+
+      {:ok, iter} = NIF.bounded_iterator(db, :spo, "b", "d")
+      assert {:ok, "b", _} = NIF.iterator_next(iter)
+      assert {:ok, "c", _} = NIF.iterator_next(iter)
+      assert {:ok, "b", _} = NIF.iterator_prev(iter)
+      assert :iterator_end = NIF.iterator_prev(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "returns error for closed iterator", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
+      NIF.iterator_close(iter)
+      assert {:error, :iterator_closed} = NIF.iterator_prev(iter)
+    end
+  end
+
   describe "iterator_seek/2" do
     test "seeks to a specific key", %{db: db} do
       NIF.put(db, :spo, "a", "1")
@@ -164,6 +709,197 @@ defmodule TripleStore.Backend.RocksDB.IteratorTest do
     end
   end
 
+  describe "iterator_seek_ge/2" do
+    setup %{db: db} do
+      for key <- ["p:1", "p:3", "p:5", "q:1"] do
+        NIF.put(db, :derived, key, "v" <> key)
+      end
+
+      :ok
+    end
+
+    test "returns an exact match and consumes it", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "p:")
+
+      assert {:ok, "p:3"} = NIF.iterator_seek_ge(iter, "p:3")
+      assert {:ok, "p:5", "vp:5"} = NIF.iterator_next(iter)
+
+      NIF.iterator_close(iter)
+    end
+
+    test "returns the next greater key for an absent target", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "p:")
+
+      assert {:ok, "p:3"} = NIF.iterator_seek_ge(iter, "p:2")
+      assert {:ok, "p:1"} = NIF.iterator_seek_ge(iter, "p:")
+
+      NIF.iterator_close(iter)
+    end
+
+    test "returns :iterator_end past the prefix", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "p:")
+
+      assert :iterator_end = NIF.iterator_seek_ge(iter, "p:6")
+      assert :iterator_end = NIF.iterator_seek_ge(iter, "q:1")
+
+      NIF.iterator_close(iter)
+    end
+
+    test "applies the value filter", %{db: db} do
+      NIF.put(db, :derived, "p:4", <<0>>)
+      {:ok, iter} = NIF.prefix_iterator_filtered(db, :derived, "p:", "v")
+
+      assert {:ok, "p:5"} = NIF.iterator_seek_ge(iter, "p:4")
+
+      NIF.iterator_close(iter)
+    end
+
+    test "returns error for closed iterator", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "p:")
+      NIF.iterator_close(iter)
+
+      assert {:error, :iterator_closed} = NIF.iterator_seek_ge(iter, "p:1")
+    end
+  end
+
+  describe "iterator_reset/1" do
+    test "restarts at the smallest prefixed key", %{db: db} do
+      NIF.put(db, :spo, "p_a", "1")
+      NIF.put(db, :spo, "p_b", "2")
+      NIF.put(db, :spo, "p_c", "3")
+      NIF.put(db, :spo, "a_before", "0")
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "p_")
+      assert :ok = NIF.iterator_seek(iter, "p_c")
+      assert {:ok, "p_c", "3"} = NIF.iterator_next(iter)
+      assert :iterator_end = NIF.iterator_next(iter)
+
+      assert :ok = NIF.iterator_reset(iter)
+      assert {:ok, "p_a", "1"} = NIF.iterator_next(iter)
+
+      NIF.iterator_close(iter)
+    end
+
+    test "returns error for closed iterator", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
+      NIF.iterator_close(iter)
+      assert {:error, :iterator_closed} = NIF.iterator_reset(iter)
+    end
+  end
+
+  describe "iterator_seek_for_prev/2" do
+    setup %{db: db} do
+      for key <- ["o:9", "p:1", "p:3", "p:5", "q:1"] do
+        NIF.put(db, :derived, key, "v" <> key)
+      end
+
+      :ok
+    end
+
+    test "descends from an exact match on a forward iterator", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "p:")
+
+      assert :ok = NIF.iterator_seek_for_prev(iter, "p:3")
+      assert {:ok, "p:3", "vp:3"} = NIF.iterator_prev(iter)
+      assert {:ok, "p:1", "vp:1"} = NIF.iterator_prev(iter)
+      assert :iterator_end = NIF.iterator_prev(iter)
+
+      NIF.iterator_close(iter)
+    end
+
+    test "lands on the next smaller key for an absent target", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "p:")
+
+      assert :ok = NIF.iterator_seek_for_prev(iter, "p:4")
+      assert {:ok, "p:3", "vp:3"} = NIF.iterator_prev(iter)
+
+      assert :ok = NIF.iterator_seek_for_prev(iter, "p:4")
+      assert {:ok, "p:5", "vp:5"} = NIF.iterator_next(iter)
+
+      NIF.iterator_close(iter)
+    end
+
+    test "is the next step of a reverse iterator", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "p:", :reverse)
+
+      assert :ok = NIF.iterator_seek_for_prev(iter, "p:4")
+      assert {:ok, "p:3", "vp:3"} = NIF.iterator_next(iter)
+      assert {:ok, "p:1", "vp:1"} = NIF.iterator_next(iter)
+
+      NIF.iterator_close(iter)
+    end
+
+    test "stays within the prefix", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "p:")
+
+      assert :ok = NIF.iterator_seek_for_prev(iter, "p:0")
+      assert :iterator_end = NIF.iterator_prev(iter)
+
+      assert :ok = NIF.iterator_seek_for_prev(iter, "o:9")
+      assert :iterator_end = NIF.iterator_prev(iter)
+
+      assert :ok = NIF.iterator_seek_for_prev(iter, "q:9")
+      assert {:ok, "p:5", "vp:5"} = NIF.iterator_prev(iter)
+
+      NIF.iterator_close(iter)
+    end
+
+    test "returns error for closed iterator", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "p:")
+      NIF.iterator_close(iter)
+
+      assert {:error, :iterator_closed} = NIF.iterator_seek_for_prev(iter, "p:3")
+    end
+  end
+
+  describe "iterator_seek_to_first/1 and iterator_seek_to_last/1" do
+    setup %{db: db} do
+      for key <- ["a", "m", "z"] do
+        NIF.put(db, :derived, key, "v" <> key)
+      end
+
+      :ok
+    end
+
+    test "move a full-CF iterator to either end", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "")
+
+      assert :ok = NIF.iterator_seek_to_last(iter)
+      assert {:ok, "z", "vz"} = NIF.iterator_next(iter)
+      assert :iterator_end = NIF.iterator_next(iter)
+
+      assert :ok = NIF.iterator_seek_to_first(iter)
+      assert {:ok, "a", "va"} = NIF.iterator_next(iter)
+      assert {:ok, "m", "vm"} = NIF.iterator_next(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "iterator_prev steps back from the last key", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "")
+      assert :ok = NIF.iterator_seek_to_last(iter)
+      assert {:ok, "m", "vm"} = NIF.iterator_prev(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "end right away when the prefix doesn't cover the boundary", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "m")
+
+      assert :ok = NIF.iterator_seek_to_first(iter)
+      assert :iterator_end = NIF.iterator_next(iter)
+
+      assert :ok = NIF.iterator_seek_to_last(iter)
+      assert :iterator_end = NIF.iterator_next(iter)
+      NIF.iterator_close(iter)
+    end
+
+    test "returns error for closed iterator", %{db: db} do
+      {:ok, iter} = NIF.prefix_iterator(db, :derived, "")
+      NIF.iterator_close(iter)
+      assert {:error, :iterator_closed} = NIF.iterator_seek_to_first(iter)
+      assert {:error, :iterator_closed} = NIF.iterator_seek_to_last(iter)
+    end
+  end
+
   describe "iterator_close/1" do
     test "closes an open iterator", %{db: db} do
       {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
@@ -342,13 +1078,45 @@ defmodule TripleStore.Backend.RocksDB.IteratorTest do
       # Add more data after iterator creation
       NIF.put(db, :spo, "key3", "value3")
 
-      # RocksDB iterators may or may not see new data depending on timing
-      # This is acceptable behavior - we just verify no crashes
       {:ok, results} = NIF.iterator_collect(iter)
-      assert length(results) >= 2
+      assert results == [{"key1", "value1"}, {"key2", "value2"}]
+
+      NIF.iterator_close(iter)
+    end
+
+    test "seeks, steps back and resets keep the creation snapshot", %{db: db} do
+      NIF.put(db, :spo, "view_a", "1")
+      NIF.put(db, :spo, "view_c", "3")
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "view_")
+      NIF.put(db, :spo, "view_b", "2")
+      NIF.put(db, :spo, "view_c", "changed")
+      NIF.delete(db, :spo, "view_a")
+
+      assert :ok = NIF.iterator_seek(iter, "view_b")
+      assert {:ok, "view_c", "3"} = NIF.iterator_next(iter)
+      assert {:ok, "view_a", "1"} = NIF.iterator_prev(iter)
+      assert :ok = NIF.iterator_seek_for_prev(iter, "view_b")
+      assert {:ok, "view_a", "1"} = NIF.iterator_prev(iter)
+      assert :ok = NIF.iterator_seek_to_last(iter)
+      assert :ok = NIF.iterator_reset(iter)
+      assert {:ok, [{"view_a", "1"}, {"view_c", "3"}]} = NIF.iterator_collect(iter)
 
       NIF.iterator_close(iter)
     end
+
+    test "bounded iterators keep the creation snapshot", %{db: db} do
+      NIF.put(db, :spo, "bview_a", "1")
+
+      {:ok, iter} = NIF.bounded_iterator(db, :spo, "bview_", "bview_z")
+      NIF.put(db, :spo, "bview_b", "2")
+
+      assert :ok = NIF.iterator_seek(iter, "bview_")
+      assert {:ok, [{"bview_a", "1"}]} = NIF.iterator_collect(iter)
+
+      {:ok, fresh} = NIF.bounded_iterator(db, :spo, "bview_", "bview_z")
+      assert {:ok, [{"bview_a", "1"}, {"bview_b", "2"}]} = NIF.iterator_collect(fresh)
+    end
   end
 
   describe "iterator lifetime safety" do
@@ -505,4 +1273,135 @@ defmodule TripleStore.Backend.RocksDB.IteratorTest do
       NIF.iterator_close(iter)
     end
   end
+
+  describe "scan_subject_grouped/4" do
+    test "groups objects by predicate", %{db: db} do
+      for {p, o} <- [{2, 10}, {2, 11}, {3, 12}, {5, 13}, {5, 14}] do
+        NIF.put(db, :spo, <<1::64-big, p::64-big, o::64-big>>, "")
+      end
+
+      NIF.put(db, :spo, <<9::64-big, 2::64-big, 99::64-big>>, "")
+
+      assert {:ok, grouped, false} = NIF.scan_subject_grouped(db, <<1::64-big>>, 8, 100)
+      assert grouped == %{2 => [10, 11], 3 => [12], 5 => [13, 14]}
+    end
+
+    test "respects the max entry cap", %{db: db} do
+      for o <- 1..5 do
+        NIF.put(db, :spo, <<1::64-big, 2::64-big, o::64-big>>, "")
+      end
+
+      assert {:ok, %{2 => [1, 2, 3]}, true} = NIF.scan_subject_grouped(db, <<1::64-big>>, 8, 3)
+      assert {:ok, %{2 => [1, 2, 3, 4, 5]}, false} = NIF.scan_subject_grouped(db, <<1::64-big>>, 8, 5)
+    end
+
+    test "returns an empty map for unknown subjects", %{db: db} do
+      assert {:ok, %{}, false} = NIF.scan_subject_grouped(db, <<42::64-big>>, 8, 10)
+    end
+
+    test "rejects invalid id width", %{db: db} do
+      assert {:error, {:invalid_id_width, 0}} = NIF.scan_subject_grouped(db, <<1::64-big>>, 0, 10)
+    end
+  end
+
+  describe "scan_aggregate/4" do
+    setup %{db: db} do
+      NIF.put(db, :derived, "c:a", <<3::64-little>>)
+      NIF.put(db, :derived, "c:b", <<4::64-little>>)
+      NIF.put(db, :derived, "c:c", <<5::64-little>>)
+      NIF.put(db, :derived, "d:a", <<100::64-little>>)
+      :ok
+    end
+
+    test "counts entries under the prefix", %{db: db} do
+      assert {:ok, 3} = NIF.scan_aggregate(db, :derived, "c:", %{op: :count})
+      assert {:ok, 0} = NIF.scan_aggregate(db, :derived, "z:", %{op: :count})
+    end
+
+    test "sums little-endian u64 values", %{db: db} do
+      assert {:ok, 12} = NIF.scan_aggregate(db, :derived, "c:", %{op: :sum_u64})
+    end
+
+    test "rejects values that are not 8 bytes wide", %{db: db} do
+      NIF.put(db, :derived, "c:z", "short")
+      assert {:error, :bad_value_width} = NIF.scan_aggregate(db, :derived, "c:", %{op: :sum_u64})
+      assert {:ok, 4} = NIF.scan_aggregate(db, :derived, "c:", %{op: :count})
+    end
+
+    test "rejects unknown operations", %{db: db} do
+      assert {:error, {:invalid_option, :op}} = NIF.scan_aggregate(db, :derived, "c:", %{op: :avg})
+      assert {:error, {:invalid_option, :op}} = NIF.scan_aggregate(db, :derived, "c:", %{})
+    end
+  end
+
+  describe "prefix_count/3" do
+    test "counts keys under the prefix", %{db: db} do
+      for key <- ["c:a", "c:b", "c:c", "d:a"], do: NIF.put(db, :derived, key, "v")
+
+      assert {:ok, 3} = NIF.prefix_count(db, :derived, "c:")
+      assert {:ok, 1} = NIF.prefix_count(db, :derived, "d:")
+      assert {:ok, 0} = NIF.prefix_count(db, :derived, "z:")
+    end
+
+    test "matches iterator_collect on an index with a prefix extractor", %{db: db} do
+      subject = <<7::64-big>>
+
+      for p <- 1..5 do
+        NIF.put(db, :spo, subject <> <<p::64-big, 1::64-big>>, "")
+      end
+
+      NIF.put(db, :spo, <<8::64-big, 1::64-big, 1::64-big>>, "")
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, subject)
+      {:ok, entries} = NIF.iterator_collect(iter)
+      assert {:ok, 5} = NIF.prefix_count(db, :spo, subject)
+      assert length(entries) == 5
+    end
+
+    test "returns error for invalid column family", %{db: db} do
+      assert {:error, {:invalid_cf, :bogus}} = NIF.prefix_count(db, :bogus, "")
+    end
+  end
+
+  describe "open_iterator_count/1" do
+    test "tracks created and closed iterators", %{db: db} do
+      {:ok, baseline} = NIF.open_iterator_count(db)
+
+      {:ok, iter1} = NIF.prefix_iterator(db, :spo, "")
+      {:ok, iter2} = NIF.prefix_iterator(db, :pos, "")
+      assert {:ok, count} = NIF.open_iterator_count(db)
+      assert count == baseline + 2
+
+      assert :ok = NIF.iterator_close(iter1)
+      assert {:ok, count} = NIF.open_iterator_count(db)
+      assert count == baseline + 1
+
+      # Closing twice must not decrement again
+      assert {:error, :iterator_closed} = NIF.iterator_close(iter1)
+      assert :ok = NIF.iterator_close(iter2)
+      assert {:ok, ^baseline} = NIF.open_iterator_count(db)
+    end
+
+    test "garbage-collected iterators are no longer counted", %{db: db} do
+      {:ok, baseline} = NIF.open_iterator_count(db)
+
+      task =
+        Task.async(fn ->
+          {:ok, _iter} = NIF.prefix_iterator(db, :spo, "")
+          NIF.open_iterator_count(db)
+        end)
+
+      {:ok, count} = Task.await(task)
+      assert count == baseline + 1
+
+      :erlang.garbage_collect()
+      assert {:ok, ^baseline} = NIF.open_iterator_count(db)
+    end
+
+    test "returns error for closed database", %{db_path: path} do
+      {:ok, db} = NIF.open("#{path}_iter_count")
+      NIF.close(db)
+      assert {:error, :already_closed} = NIF.open_iterator_count(db)
+    end
+  end
 end
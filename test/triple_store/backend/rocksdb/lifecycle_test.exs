@@ -51,6 +51,490 @@ defmodule TripleStore.Backend.RocksDB.LifecycleTest do
     end
   end
 
+  describe "open/2" do
+    test "opens with an empty override list", %{path: path} do
+      assert {:ok, db} = NIF.open(path, [])
+      NIF.close(db)
+    end
+
+    test "accepts memtable overrides", %{path: path} do
+      assert {:ok, db} =
+               NIF.open(path,
+                 str2id: [memtable: :hash_skiplist, prefix_length: 1],
+                 spo: [memtable: :hash_skiplist],
+                 derived: [memtable: :vector],
+                 id2str: [memtable: :skiplist]
+               )
+
+      assert :ok = NIF.put(db, :str2id, "key", "value")
+      assert {:ok, "value"} = NIF.get(db, :str2id, "key")
+      NIF.close(db)
+    end
+
+    test "rejects hash_skiplist without a prefix extractor", %{path: path} do
+      assert {:error, :prefix_extractor_required} =
+               NIF.open(path, str2id: [memtable: :hash_skiplist])
+    end
+
+    test "accepts index type and format version overrides", %{path: path} do
+      assert {:ok, db} =
+               NIF.open(path,
+                 str2id: [index_type: :two_level_index_search, format_version: 5],
+                 spo: [index_type: :hash_search],
+                 id2str: [index_type: :binary_search]
+               )
+
+      assert :ok = NIF.put(db, :str2id, "key", "value")
+      assert :ok = NIF.sync_dir(db)
+      assert {:ok, "value"} = NIF.get(db, :str2id, "key")
+      NIF.close(db)
+    end
+
+    test "rejects hash_search without a prefix extractor", %{path: path} do
+      assert {:error, :prefix_extractor_required} =
+               NIF.open(path, str2id: [index_type: :hash_search])
+    end
+
+    test "rejects invalid index types and format versions", %{path: path} do
+      assert {:error, {:invalid_option, :index_type}} = NIF.open(path, spo: [index_type: :btree])
+      assert {:error, {:invalid_option, :format_version}} = NIF.open(path, spo: [format_version: 99])
+    end
+
+    test "rejects unknown options and column families", %{path: path} do
+      assert {:error, {:invalid_option, :memtable}} = NIF.open(path, spo: [memtable: :btree])
+      assert {:error, {:invalid_option, :bogus}} = NIF.open(path, spo: [bogus: 1])
+      assert {:error, {:invalid_cf, :nope}} = NIF.open(path, nope: [])
+    end
+
+    test "recovers index writes together with atomic flush", %{path: path} do
+      {:ok, db} = NIF.open(path, atomic_flush: true, spo: [])
+
+      :ok =
+        NIF.write_batch(db, [{:spo, "spo", ""}, {:pos, "pos", ""}, {:osp, "osp", ""}], false)
+
+      assert :ok = NIF.sync_dir(db)
+      NIF.close(db)
+
+      {:ok, db} = NIF.open(path, atomic_flush: true)
+      assert {:ok, ""} = NIF.get(db, :spo, "spo")
+      assert {:ok, ""} = NIF.get(db, :pos, "pos")
+      assert {:ok, ""} = NIF.get(db, :osp, "osp")
+      NIF.close(db)
+    end
+
+    test "rejects a non-boolean atomic_flush", %{path: path} do
+      assert {:error, {:invalid_option, :atomic_flush}} = NIF.open(path, atomic_flush: 1)
+    end
+  end
+
+  describe "open_with_options/2" do
+    test "applies the settings to every column family", %{path: path} do
+      {:ok, db} =
+        NIF.open_with_options(path, %{
+          block_cache_bytes: 1024 * 1024,
+          write_buffer_bytes: 4 * 1024 * 1024,
+          max_open_files: 64,
+          compression: :zstd
+        })
+
+      for cf <- [:id2str, :str2id, :spo, :pos, :osp, :derived] do
+        assert {:ok, opts} = NIF.effective_options(db, cf)
+        assert opts["write_buffer_size"] == 4 * 1024 * 1024
+        assert opts["compression"] == "kZSTD"
+      end
+
+      :ok = NIF.put(db, :spo, "key", "value")
+      assert {:ok, "value"} = NIF.get(db, :spo, "key")
+      NIF.close(db)
+    end
+
+    test "an empty map matches open/1", %{path: path} do
+      {:ok, db} = NIF.open_with_options(path, %{})
+      assert {:ok, spo} = NIF.effective_options(db, :spo)
+      assert spo["compression"] == "kLZ4Compression"
+      assert spo["prefix_extractor"] == "rocksdb.FixedPrefix.8"
+      NIF.close(db)
+    end
+
+    test "applies cf_options to the named column families only", %{path: path} do
+      {:ok, db} =
+        NIF.open_with_options(path, %{
+          compression: :snappy,
+          cf_options: %{
+            id2str: %{compression: :zstd},
+            derived: %{bloom_filter_bits: 10}
+          }
+        })
+
+      assert {:ok, %{"compression" => "kZSTD"}} = NIF.effective_options(db, :id2str)
+      assert {:ok, %{"compression" => "kSnappyCompression"}} = NIF.effective_options(db, :spo)
+
+      assert {:ok, derived} = NIF.effective_options(db, :derived)
+      assert derived["filter_policy"] =~ "bloomfilter"
+      assert derived["compression"] == "kSnappyCompression"
+
+      NIF.close(db)
+    end
+
+    test "returns errors for invalid cf_options", %{path: path} do
+      assert {:error, {:invalid_cf, :bogus}} =
+               NIF.open_with_options(path, %{cf_options: %{bogus: %{compression: :zstd}}})
+
+      assert {:error, {:invalid_option, :bloom_filter_bits}} =
+               NIF.open_with_options(path, %{cf_options: %{spo: %{bloom_filter_bits: -1}}})
+    end
+
+    test "ignores unknown keys", %{path: path} do
+      assert {:ok, db} = NIF.open_with_options(path, %{future_setting: 1, compression: :none})
+      NIF.close(db)
+    end
+
+    test "returns error for invalid values of known keys", %{path: path} do
+      assert {:error, {:invalid_option, :compression}} =
+               NIF.open_with_options(path, %{compression: :brotli})
+
+      assert {:error, {:invalid_option, :block_cache_bytes}} =
+               NIF.open_with_options(path, %{block_cache_bytes: -1})
+    end
+  end
+
+  describe "open_versioned/2" do
+    test "stamps a fresh database with the expected version", %{path: path} do
+      assert {:ok, db} = NIF.open_versioned(path, 3)
+      # The marker stays out of the triple-keyed column families
+      assert {:ok, iter} = NIF.prefix_iterator(db, :derived, <<>>)
+      assert {:ok, []} = NIF.iterator_collect(iter)
+      NIF.close(db)
+
+      assert {:error, {:schema_mismatch, 3, 4}} = NIF.open_versioned(path, 4)
+    end
+
+    test "opens a database with a matching version", %{path: path} do
+      {:ok, db} = NIF.open_versioned(path, 3)
+      NIF.close(db)
+
+      assert {:ok, db} = NIF.open_versioned(path, 3)
+      assert NIF.is_open(db)
+      NIF.close(db)
+    end
+
+    test "refuses a database with a different version", %{path: path} do
+      {:ok, db} = NIF.open_versioned(path, 2)
+      NIF.close(db)
+
+      assert {:error, {:schema_mismatch, 2, 3}} = NIF.open_versioned(path, 3)
+
+      # The refused open must not hold the database lock
+      assert {:ok, db} = NIF.open(path)
+      NIF.close(db)
+    end
+  end
+
+  describe "open_cfs/2" do
+    test "exposes only the listed column families", %{path: path} do
+      assert {:ok, db} = NIF.open_cfs(path, [:id2str, :str2id])
+      assert :ok = NIF.put(db, :id2str, "k", "v")
+      assert {:ok, "v"} = NIF.get(db, :id2str, "k")
+      assert {:error, {:cf_not_opened, :spo}} = NIF.get(db, :spo, "k")
+      assert {:error, {:cf_not_opened, :spo}} = NIF.write_batch(db, [{:spo, "k", ""}])
+      assert {:error, {:cf_not_opened, :derived}} = NIF.merge(db, :derived, "counter", 1)
+      NIF.close(db)
+    end
+
+    test "attaches to a database with all column families on disk", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      :ok = NIF.put(db, :str2id, "term", "id")
+      :ok = NIF.put(db, :spo, "k", "")
+      NIF.close(db)
+
+      assert {:ok, db} = NIF.open_cfs(path, [:str2id])
+      assert {:ok, "id"} = NIF.get(db, :str2id, "term")
+      assert {:error, {:cf_not_opened, :spo}} = NIF.exists(db, :spo, "k")
+      NIF.close(db)
+
+      # Hidden column families are left intact
+      {:ok, db} = NIF.open(path)
+      assert {:ok, ""} = NIF.get(db, :spo, "k")
+      NIF.close(db)
+    end
+
+    test "returns error for unknown column family", %{path: path} do
+      assert {:error, {:invalid_cf, :bogus}} = NIF.open_cfs(path, [:bogus])
+    end
+  end
+
+  describe "open_with_cfs/2" do
+    test "targets extra column families by name", %{path: path} do
+      assert {:ok, db} = NIF.open_with_cfs(path, ["tenant_a", "tenant_b"])
+      assert :ok = NIF.put(db, "tenant_a", "k", "a")
+      assert :ok = NIF.put(db, "tenant_b", "k", "b")
+      assert {:ok, "a"} = NIF.get(db, "tenant_a", "k")
+      assert {:ok, "b"} = NIF.get(db, "tenant_b", "k")
+      assert :ok = NIF.write_batch(db, [{"tenant_a", "k2", "a2"}])
+      assert {:ok, "a2"} = NIF.get(db, "tenant_a", "k2")
+      NIF.close(db)
+
+      assert {:ok, db} = NIF.open_with_cfs(path, ["tenant_a", "tenant_b"])
+      assert {:ok, "a"} = NIF.get(db, "tenant_a", "k")
+      NIF.close(db)
+    end
+
+    test "keeps the built-in column families", %{path: path} do
+      assert {:ok, db} = NIF.open_with_cfs(path, ["tenant_a"])
+      assert :ok = NIF.put(db, :spo, "k", "atom")
+      assert {:ok, "atom"} = NIF.get(db, "spo", "k")
+      NIF.close(db)
+    end
+
+    test "returns error for unknown names", %{path: path} do
+      assert {:ok, db} = NIF.open_with_cfs(path, ["tenant_a"])
+      assert {:error, {:invalid_cf, "never_opened"}} = NIF.get(db, "never_opened", "k")
+      assert {:error, {:invalid_cf, :bogus}} = NIF.get(db, :bogus, "k")
+      NIF.close(db)
+
+      assert {:error, {:invalid_cf, "default"}} = NIF.open_with_cfs(path, ["default"])
+    end
+  end
+
+  describe "open_with_ttl/2" do
+    test "stamps derived values transparently and leaves other CFs alone", %{path: path} do
+      assert {:ok, db} = NIF.open_with_ttl(path, 3600)
+      assert {:ok, 3600} = NIF.get_ttl(db)
+
+      assert :ok = NIF.put(db, :derived, "fact", "")
+      assert :ok = NIF.write_batch(db, [{:derived, "fact2", "v"}, {:spo, "triple", ""}])
+      assert {:ok, ""} = NIF.get(db, :derived, "fact")
+      assert {:ok, "v"} = NIF.get(db, :derived, "fact2")
+      assert {:ok, stream} = NIF.prefix_stream(db, :derived, "")
+      assert [{"fact", ""}, {"fact2", "v"}] = Enum.to_list(stream)
+      NIF.close(db)
+
+      # Other opens see the 8-byte write-time stamp; spo is stored as written
+      {:ok, db} = NIF.open(path)
+      assert {:ok, <<_written::64-big>>} = NIF.get(db, :derived, "fact")
+      assert {:ok, ""} = NIF.get(db, :spo, "triple")
+      assert {:ok, nil} = NIF.get_ttl(db)
+      NIF.close(db)
+    end
+
+    test "refuses operations that can't carry the stamp", %{path: path} do
+      assert {:ok, db} = NIF.open_with_ttl(path, 60)
+      assert {:error, :no_merge_operator} = NIF.merge(db, :derived, "counter", 1)
+      assert {:error, :ttl_cf} = NIF.swap_cf_contents(db, :numeric_range, :derived)
+      assert {:error, :ttl_cf} = NIF.ingest_external_file(db, :derived, [])
+      NIF.close(db)
+
+      assert {:error, :already_closed} = NIF.get_ttl(db)
+    end
+
+    test "rejects a zero TTL", %{path: path} do
+      assert {:error, {:invalid_option, 0}} = NIF.open_with_ttl(path, 0)
+    end
+  end
+
+  describe "create_column_family/3 and drop_column_family/2" do
+    test "adds and removes a column family at runtime", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert :ok = NIF.create_column_family(db, "reindex_tmp", %{compression: :none})
+      assert :ok = NIF.put(db, "reindex_tmp", "k", "v")
+      assert {:ok, "v"} = NIF.get(db, "reindex_tmp", "k")
+
+      assert {:error, {:create_cf_failed, _}} = NIF.create_column_family(db, "reindex_tmp", %{})
+
+      assert :ok = NIF.drop_column_family(db, "reindex_tmp")
+      assert {:error, {:cf_not_opened, "reindex_tmp"}} = NIF.get(db, "reindex_tmp", "k")
+      NIF.close(db)
+
+      # Nothing is left on disk that a plain open would have to list
+      assert {:ok, db} = NIF.open(path)
+      NIF.close(db)
+    end
+
+    test "rejects dropping a built-in column family", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert {:error, :protected_cf} = NIF.drop_column_family(db, :spo)
+      assert {:error, :protected_cf} = NIF.drop_column_family(db, "id2str")
+      assert {:error, {:invalid_cf, "missing"}} = NIF.drop_column_family(db, "missing")
+      NIF.close(db)
+    end
+
+    test "is refused while a snapshot shares the database", %{path: path} do
+      {:ok, db} = NIF.open(path)
+
+      task =
+        Task.async(fn ->
+          {:ok, _snapshot} = NIF.snapshot(db)
+          NIF.create_column_family(db, "reindex_tmp", %{})
+        end)
+
+      assert {:error, :db_in_use} = Task.await(task)
+
+      # The snapshot resource is freed with the task's heap, so a retry succeeds
+      :erlang.garbage_collect()
+      assert :ok = NIF.create_column_family(db, "reindex_tmp", %{})
+      NIF.close(db)
+    end
+
+    test "waits for calls running concurrently on the handle", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      :ok = NIF.put(db, :spo, "key", "value")
+
+      readers =
+        for _ <- 1..8 do
+          Task.async(fn ->
+            for _ <- 1..2_000, do: {:ok, "value"} = NIF.get(db, :spo, "key")
+          end)
+        end
+
+      assert :ok = NIF.create_column_family(db, "reindex_tmp", %{})
+      assert :ok = NIF.drop_column_family(db, "reindex_tmp")
+      Task.await_many(readers, 30_000)
+      NIF.close(db)
+    end
+
+    test "rejects invalid names and settings", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert {:error, {:invalid_cf, "default"}} = NIF.create_column_family(db, "default", %{})
+
+      assert {:error, {:invalid_option, :bloom_filter_bits}} =
+               NIF.create_column_family(db, "reindex_tmp", %{bloom_filter_bits: -1})
+
+      NIF.close(db)
+    end
+  end
+
+  describe "open_memory/0" do
+    test "supports reads, writes and iteration" do
+      assert {:ok, db} = NIF.open_memory()
+      assert {:ok, ":memory:"} = NIF.get_path(db)
+
+      :ok = NIF.write_batch(db, [{:spo, "s1", ""}, {:spo, "s2", ""}, {:str2id, "t", "1"}], true)
+      :ok = NIF.put(db, :derived, "d", "v")
+      assert {:ok, "1"} = NIF.get(db, :str2id, "t")
+      assert {:ok, true} = NIF.exists(db, :derived, "d")
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "s")
+      assert {:ok, [{"s1", ""}, {"s2", ""}]} = NIF.iterator_collect(iter)
+
+      assert :ok = NIF.flush_wal(db, true)
+      assert :ok = NIF.sync_dir(db)
+      assert :ok = NIF.compact_bottommost(db, :spo)
+      assert {:ok, ""} = NIF.get(db, :spo, "s1")
+
+      assert :ok = NIF.close(db)
+      assert {:error, :already_closed} = NIF.get_path(db)
+    end
+
+    test "returns independent databases" do
+      {:ok, first} = NIF.open_memory()
+      {:ok, second} = NIF.open_memory()
+
+      :ok = NIF.put(first, :spo, "key", "value")
+      assert :not_found = NIF.get(second, :spo, "key")
+
+      NIF.close(first)
+      NIF.close(second)
+    end
+  end
+
+  describe "open_read_only/1" do
+    test "reads the data while the primary stays open", %{path: path} do
+      {:ok, primary} = NIF.open(path)
+      :ok = NIF.put(primary, :spo, "key", "value")
+
+      assert {:ok, reader} = NIF.open_read_only(path)
+      assert {:ok, "value"} = NIF.get(reader, :spo, "key")
+
+      {:ok, iter} = NIF.prefix_iterator(reader, :spo, "k")
+      assert {:ok, [{"key", "value"}]} = NIF.iterator_collect(iter)
+
+      NIF.close(reader)
+      NIF.close(primary)
+    end
+
+    test "opens a database created before the quad column families", %{path: path} do
+      {:ok, old} = NIF.open_cfs(path, [:id2str, :str2id, :spo, :pos, :osp, :derived])
+      :ok = NIF.put(old, :spo, "key", "value")
+      NIF.close(old)
+
+      assert {:ok, reader} = NIF.open_read_only(path)
+      assert {:ok, "value"} = NIF.get(reader, :spo, "key")
+      assert {:error, {:cf_not_opened, :gspo}} = NIF.get(reader, :gspo, "key")
+      NIF.close(reader)
+    end
+
+    test "rejects every write", %{path: path} do
+      {:ok, primary} = NIF.open(path)
+      {:ok, reader} = NIF.open_read_only(path)
+
+      assert {:error, :read_only} = NIF.put(reader, :spo, "key", "value")
+      assert {:error, :read_only} = NIF.delete(reader, :spo, "key")
+      assert {:error, :read_only} = NIF.delete_range(reader, :spo, "a", "z")
+      assert {:error, :read_only} = NIF.write_batch(reader, [{:spo, "key", "value"}], false)
+      assert {:error, :read_only} = NIF.delete_batch(reader, [{:spo, "key"}], false)
+
+      assert {:error, :read_only} =
+               NIF.mixed_batch(reader, [{:put, :spo, "key", "value"}], false)
+
+      assert :not_found = NIF.get(primary, :spo, "key")
+
+      NIF.close(reader)
+      NIF.close(primary)
+    end
+
+    test "fails when no database exists", %{path: path} do
+      assert {:error, {:open_failed, _}} = NIF.open_read_only(path)
+    end
+  end
+
+  describe "open_secondary/2" do
+    setup %{path: path} do
+      secondary_path = path <> "_secondary"
+      on_exit(fn -> File.rm_rf(secondary_path) end)
+      {:ok, secondary_path: secondary_path}
+    end
+
+    test "catches up with the primary's writes", %{path: path, secondary_path: secondary_path} do
+      {:ok, primary} = NIF.open(path)
+      :ok = NIF.put(primary, :spo, "before", "value")
+
+      assert {:ok, secondary} = NIF.open_secondary(path, secondary_path)
+      assert {:ok, "value"} = NIF.get(secondary, :spo, "before")
+
+      :ok = NIF.put(primary, :spo, "after", "value")
+      assert :not_found = NIF.get(secondary, :spo, "after")
+
+      assert :ok = NIF.try_catch_up_with_primary(secondary)
+      assert {:ok, "value"} = NIF.get(secondary, :spo, "after")
+
+      NIF.close(secondary)
+      NIF.close(primary)
+    end
+
+    test "rejects writes", %{path: path, secondary_path: secondary_path} do
+      {:ok, primary} = NIF.open(path)
+      {:ok, secondary} = NIF.open_secondary(path, secondary_path)
+
+      assert {:error, :read_only} = NIF.put(secondary, :spo, "key", "value")
+      assert {:error, :read_only} = NIF.merge(secondary, :derived, "counter", 1)
+      assert {:error, :read_only} = NIF.transaction_begin(secondary)
+      assert :not_found = NIF.get(primary, :spo, "key")
+
+      NIF.close(secondary)
+      NIF.close(primary)
+    end
+
+    test "catching up needs a secondary handle", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert {:error, :not_secondary} = NIF.try_catch_up_with_primary(db)
+      NIF.close(db)
+    end
+
+    test "fails when no primary exists", %{path: path, secondary_path: secondary_path} do
+      assert {:error, {:open_failed, _}} = NIF.open_secondary(path, secondary_path)
+    end
+  end
+
   describe "close/1" do
     test "closes database successfully", %{path: path} do
       {:ok, db} = NIF.open(path)
@@ -64,6 +548,53 @@ defmodule TripleStore.Backend.RocksDB.LifecycleTest do
     end
   end
 
+  describe "close_idempotent/1" do
+    test "closes an open database", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert :ok = NIF.close_idempotent(db)
+      refute NIF.is_open(db)
+
+      # The lock is released, so the path can be opened again
+      assert {:ok, db} = NIF.open(path)
+      NIF.close(db)
+    end
+
+    test "returns :ok on a second close", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert :ok = NIF.close_idempotent(db)
+      assert :ok = NIF.close_idempotent(db)
+      assert {:error, :already_closed} = NIF.close(db)
+    end
+  end
+
+  describe "destroy/2" do
+    test "removes the database files", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      :ok = NIF.put(db, :spo, "k", "v")
+      NIF.close(db)
+
+      assert :ok = NIF.destroy(path, %{})
+      assert File.ls(path) in [{:ok, []}, {:error, :enoent}]
+
+      # A fresh database starts empty
+      {:ok, db} = NIF.open(path)
+      assert :not_found = NIF.get(db, :spo, "k")
+      NIF.close(db)
+    end
+
+    test "fails while the database is open", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      :ok = NIF.put(db, :spo, "k", "v")
+      assert {:error, {:destroy_failed, _}} = NIF.destroy(path, %{})
+      assert {:ok, "v"} = NIF.get(db, :spo, "k")
+      NIF.close(db)
+    end
+
+    test "succeeds for a path without a database", %{path: path} do
+      assert :ok = NIF.destroy(path, %{})
+    end
+  end
+
   describe "get_path/1" do
     test "returns the database path", %{path: path} do
       {:ok, db} = NIF.open(path)
@@ -86,10 +617,467 @@ defmodule TripleStore.Backend.RocksDB.LifecycleTest do
     end
   end
 
+  describe "health_check/1" do
+    test "returns latency for open database", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert {:ok, micros} = NIF.health_check(db)
+      assert is_integer(micros) and micros >= 0
+      NIF.close(db)
+    end
+
+    test "keeps probe keys out of the triple column families", %{path: path} do
+      {:ok, db} = NIF.open(path)
+
+      results =
+        1..20
+        |> Enum.map(fn _ -> Task.async(fn -> NIF.health_check(db) end) end)
+        |> Task.await_many(5000)
+
+      assert Enum.all?(results, &match?({:ok, _}, &1))
+
+      {:ok, stream} = NIF.prefix_stream(db, :derived, "")
+      assert Enum.to_list(stream) == []
+      NIF.close(db)
+    end
+
+    test "returns error for closed database", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      NIF.close(db)
+      assert {:error, :already_closed} = NIF.health_check(db)
+    end
+  end
+
+  describe "effective_options/2" do
+    test "reports the tuned per-CF options", %{path: path} do
+      {:ok, db} = NIF.open(path)
+
+      assert {:ok, spo} = NIF.effective_options(db, :spo)
+      assert spo["block_size"] == 8192
+      assert spo["prefix_extractor"] == "rocksdb.FixedPrefix.8"
+      assert spo["compaction_style"] == "kCompactionStyleLevel"
+      assert is_integer(spo["write_buffer_size"])
+
+      assert {:ok, str2id} = NIF.effective_options(db, :str2id)
+      assert str2id["block_size"] == 2048
+
+      NIF.close(db)
+    end
+
+    test "reflects open overrides and set_options/2", %{path: path} do
+      {:ok, db} = NIF.open(path, id2str: [index_type: :two_level_index_search, format_version: 4])
+
+      assert {:ok, opts} = NIF.effective_options(db, :id2str)
+      assert opts["index_type"] == "kTwoLevelIndexSearch"
+      assert opts["format_version"] == 4
+
+      :ok = NIF.set_options(db, [{"level0_file_num_compaction_trigger", "16"}])
+      assert {:ok, %{"level0_file_num_compaction_trigger" => 16}} = NIF.effective_options(db, :id2str)
+
+      NIF.close(db)
+    end
+
+    test "returns errors for invalid, unopened and closed", %{path: path} do
+      {:ok, db} = NIF.open_cfs(path, [:id2str])
+      assert {:error, {:invalid_cf, :bogus}} = NIF.effective_options(db, :bogus)
+      assert {:error, {:cf_not_opened, :spo}} = NIF.effective_options(db, :spo)
+
+      NIF.close(db)
+      assert {:error, :already_closed} = NIF.effective_options(db, :id2str)
+    end
+
+    test "returns :in_memory for in-memory databases" do
+      {:ok, db} = NIF.open_memory()
+      assert {:error, :in_memory} = NIF.effective_options(db, :spo)
+      NIF.close(db)
+    end
+  end
+
+  describe "sync_dir/1" do
+    test "makes unsynced writes durable across reopen", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      :ok = NIF.write_batch(db, [{:spo, "key", "value"}, {:derived, "d", "1"}], false)
+
+      assert :ok = NIF.sync_dir(db)
+      NIF.close(db)
+
+      {:ok, db} = NIF.open(path)
+      assert {:ok, "value"} = NIF.get(db, :spo, "key")
+      assert {:ok, "1"} = NIF.get(db, :derived, "d")
+      NIF.close(db)
+    end
+
+    test "returns error for closed database", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      NIF.close(db)
+      assert {:error, :already_closed} = NIF.sync_dir(db)
+    end
+  end
+
+  describe "flush/1 and flush_cf/2" do
+    test "flush writes every column family to SST files", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      :ok = NIF.put(db, :spo, "key", "value")
+      :ok = NIF.put(db, :derived, "d", "1")
+      assert Path.wildcard(Path.join(path, "*.sst")) == []
+
+      assert :ok = NIF.flush(db)
+      assert length(Path.wildcard(Path.join(path, "*.sst"))) == 2
+      assert {:ok, "value"} = NIF.get(db, :spo, "key")
+      NIF.close(db)
+    end
+
+    test "flush_cf only flushes the given column family", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      :ok = NIF.put(db, :spo, "key", "value")
+      :ok = NIF.put(db, :derived, "d", "1")
+
+      assert :ok = NIF.flush_cf(db, :spo)
+      assert length(Path.wildcard(Path.join(path, "*.sst"))) == 1
+      NIF.close(db)
+    end
+
+    test "accepts wait: false", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      :ok = NIF.put(db, :spo, "key", "value")
+
+      assert :ok = NIF.flush(db, false)
+      assert :ok = NIF.flush_cf(db, :spo, false)
+      assert {:ok, "value"} = NIF.get(db, :spo, "key")
+      NIF.close(db)
+    end
+
+    test "returns errors for invalid cf and closed database", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert {:error, {:invalid_cf, :bogus}} = NIF.flush_cf(db, :bogus)
+      NIF.close(db)
+
+      assert {:error, :already_closed} = NIF.flush(db)
+      assert {:error, :already_closed} = NIF.flush_cf(db, :spo)
+    end
+  end
+
+  describe "compaction control" do
+    test "compaction_status/1 reports counts and pending bytes", %{path: path} do
+      {:ok, db} = NIF.open(path)
+
+      assert {:ok, %{running_compactions: running, pending_compaction_bytes: pending}} =
+               NIF.compaction_status(db)
+
+      assert is_integer(running) and running >= 0
+      assert is_integer(pending) and pending >= 0
+      NIF.close(db)
+    end
+
+    test "compactions can be disabled and re-enabled", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert :ok = NIF.disable_compactions(db)
+
+      for i <- 1..5 do
+        :ok = NIF.put(db, :spo, "key#{i}", "value#{i}")
+        :ok = NIF.sync_dir(db)
+      end
+
+      assert :ok = NIF.enable_compactions(db)
+      assert {:ok, "value3"} = NIF.get(db, :spo, "key3")
+      NIF.close(db)
+    end
+
+    test "returns error for closed database", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      NIF.close(db)
+      assert {:error, :already_closed} = NIF.compaction_status(db)
+      assert {:error, :already_closed} = NIF.disable_compactions(db)
+      assert {:error, :already_closed} = NIF.enable_compactions(db)
+      assert {:error, :already_closed} = NIF.compact_bottommost(db, :spo)
+      assert {:error, :already_closed} = NIF.pending_compaction_bytes(db)
+      assert {:error, :already_closed} = NIF.write_stall_stats(db)
+    end
+
+    test "pending_compaction_bytes/1 matches compaction_status/1", %{path: path} do
+      {:ok, db} = NIF.open(path)
+
+      assert {:ok, pending} = NIF.pending_compaction_bytes(db)
+      assert is_integer(pending) and pending >= 0
+      assert {:ok, %{pending_compaction_bytes: ^pending}} = NIF.compaction_status(db)
+      NIF.close(db)
+    end
+
+    test "write_stall_stats/1 reports stall signals", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      :ok = NIF.put(db, :spo, "key", "value")
+      :ok = NIF.sync_dir(db)
+
+      assert {:ok,
+              %{
+                write_stopped: false,
+                delayed_write_rate: rate,
+                pending_compaction_bytes: pending,
+                max_l0_files: l0_files
+              }} = NIF.write_stall_stats(db)
+
+      assert is_integer(rate) and rate >= 0
+      assert is_integer(pending) and pending >= 0
+      assert is_integer(l0_files) and l0_files >= 0
+      NIF.close(db)
+    end
+
+    test "compact_bottommost/2 purges deleted keys", %{path: path} do
+      {:ok, db} = NIF.open(path)
+
+      for i <- 1..100, do: :ok = NIF.put(db, :spo, "key#{i}", "value#{i}")
+      assert :ok = NIF.compact_bottommost(db, :spo)
+
+      for i <- 1..90, do: :ok = NIF.delete(db, :spo, "key#{i}")
+      assert :ok = NIF.compact_bottommost(db, :spo)
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
+      assert {:ok, entries} = NIF.iterator_collect(iter)
+      assert length(entries) == 10
+      assert {:ok, "value95"} = NIF.get(db, :spo, "key95")
+      NIF.close(db)
+    end
+
+    test "compact_bottommost/2 rejects unknown column families", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert {:error, {:invalid_cf, :nope}} = NIF.compact_bottommost(db, :nope)
+      NIF.close(db)
+    end
+
+    test "compact_range/4 purges deleted keys inside the range", %{path: path} do
+      {:ok, db} = NIF.open(path)
+
+      for i <- 1..50, do: :ok = NIF.put(db, :spo, "key#{i}", "value#{i}")
+      :ok = NIF.flush(db, true)
+      for i <- 1..50, do: :ok = NIF.delete(db, :spo, "key#{i}")
+
+      assert :ok = NIF.compact_range(db, :spo, "key1", "key9")
+      assert :not_found = NIF.get(db, :spo, "key25")
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "")
+      assert {:ok, []} = NIF.iterator_collect(iter)
+      NIF.close(db)
+    end
+
+    test "compact_range/4 accepts nil for unbounded sides", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      :ok = NIF.put(db, :spo, "key", "value")
+
+      assert :ok = NIF.compact_range(db, :spo, nil, "m")
+      assert :ok = NIF.compact_range(db, :spo, "a", nil)
+      assert :ok = NIF.compact_range(db, :spo, nil, nil)
+      assert {:ok, "value"} = NIF.get(db, :spo, "key")
+      NIF.close(db)
+    end
+
+    test "compact_range/4 validates the column family", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert {:error, {:invalid_cf, :nope}} = NIF.compact_range(db, :nope, nil, nil)
+      NIF.close(db)
+      assert {:error, :already_closed} = NIF.compact_range(db, :spo, nil, nil)
+    end
+
+    test "compact_all/1 compacts every column family", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      :ok = NIF.put(db, :spo, "s", "")
+      :ok = NIF.put(db, :id2str, "id", "term")
+
+      assert :ok = NIF.compact_all(db)
+      assert {:ok, ""} = NIF.get(db, :spo, "s")
+      assert {:ok, "term"} = NIF.get(db, :id2str, "id")
+      NIF.close(db)
+      assert {:error, :already_closed} = NIF.compact_all(db)
+    end
+  end
+
+  describe "size estimation" do
+    test "approximate_sizes/3 returns one estimate per range", %{path: path} do
+      {:ok, db} = NIF.open(path)
+
+      for i <- 1..500, do: :ok = NIF.put(db, :spo, "a#{i}", String.duplicate("v", 64))
+      :ok = NIF.flush(db, true)
+
+      assert {:ok, [inside, outside]} = NIF.approximate_sizes(db, :spo, [{"a", "b"}, {"x", "z"}])
+      assert inside > 0
+      assert outside == 0
+      assert {:ok, []} = NIF.approximate_sizes(db, :spo, [])
+      NIF.close(db)
+    end
+
+    test "estimate_num_keys/2 tracks the number of keys", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert {:ok, 0} = NIF.estimate_num_keys(db, :spo)
+
+      for i <- 1..100, do: :ok = NIF.put(db, :spo, "key#{i}", "value")
+      assert {:ok, count} = NIF.estimate_num_keys(db, :spo)
+      assert count > 0
+      NIF.close(db)
+    end
+
+    test "validates the column family and database", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert {:error, {:invalid_cf, :nope}} = NIF.approximate_sizes(db, :nope, [])
+      assert {:error, {:invalid_cf, :nope}} = NIF.estimate_num_keys(db, :nope)
+      NIF.close(db)
+
+      assert {:error, :already_closed} = NIF.approximate_sizes(db, :spo, [{"a", "b"}])
+      assert {:error, :already_closed} = NIF.estimate_num_keys(db, :spo)
+    end
+  end
+
+  describe "properties" do
+    test "get_property/3 reads string properties", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert {:ok, stats} = NIF.get_property(db, :spo, "rocksdb.stats")
+      assert is_binary(stats)
+      assert {:ok, "0"} = NIF.get_property(db, :spo, "rocksdb.num-files-at-level0")
+      NIF.close(db)
+    end
+
+    test "get_int_property/3 reads numeric properties", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      :ok = NIF.put(db, :spo, "key", "value")
+
+      assert {:ok, size} = NIF.get_int_property(db, :spo, "rocksdb.cur-size-all-mem-tables")
+      assert size > 0
+
+      :ok = NIF.flush(db, true)
+      assert {:ok, 1} = NIF.get_int_property(db, :spo, "rocksdb.num-files-at-level0")
+      NIF.close(db)
+    end
+
+    test "unknown properties return an error", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert {:error, :unknown_property} = NIF.get_property(db, :spo, "rocksdb.nope")
+      assert {:error, :unknown_property} = NIF.get_int_property(db, :spo, "rocksdb.nope")
+      assert {:error, :unknown_property} = NIF.get_int_property(db, :spo, "rocksdb.stats")
+      NIF.close(db)
+    end
+
+    test "validates the column family and database", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert {:error, {:invalid_cf, :nope}} = NIF.get_property(db, :nope, "rocksdb.stats")
+      NIF.close(db)
+      assert {:error, :already_closed} = NIF.get_int_property(db, :spo, "rocksdb.stats")
+    end
+  end
+
+  describe "subscribe_events/2" do
+    test "reports flush and compaction completion", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert :ok = NIF.subscribe_events(db, self())
+
+      :ok = NIF.put(db, :spo, "key", "value")
+      :ok = NIF.sync_dir(db)
+
+      assert_receive {:rocksdb_event, :flush_completed, :spo, %{level: 0, files: 1, bytes: bytes}},
+                     2_000
+
+      assert bytes > 0
+
+      :ok = NIF.compact_bottommost(db, :spo)
+      assert_receive {:rocksdb_event, :compaction_completed, :spo, %{level: level}}, 2_000
+      assert level > 0
+
+      NIF.close(db)
+    end
+
+    test "stops sending events once the database is closed", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert :ok = NIF.subscribe_events(db, self())
+      :ok = NIF.put(db, :spo, "key", "value")
+      NIF.close(db)
+
+      refute_receive {:rocksdb_event, _, _, _}, 500
+    end
+
+    test "keeps column family management available to subscribed databases", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert :ok = NIF.subscribe_events(db, self())
+
+      assert :ok = NIF.create_column_family(db, "staging", %{})
+      assert :ok = NIF.drop_column_family(db, "staging")
+
+      :ok = NIF.put(db, :spo, "key", "value")
+      :ok = NIF.sync_dir(db)
+      assert_receive {:rocksdb_event, :flush_completed, :spo, %{level: 0}}, 2_000
+      NIF.close(db)
+    end
+
+    test "unsubscribe_events/2 stops the events of a subscriber", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert :ok = NIF.subscribe_events(db, self())
+      assert :ok = NIF.subscribe_events(db, self())
+      assert :ok = NIF.unsubscribe_events(db, self())
+
+      :ok = NIF.put(db, :spo, "key", "value")
+      :ok = NIF.sync_dir(db)
+      refute_receive {:rocksdb_event, _, _, _}, 500
+      NIF.close(db)
+    end
+
+    test "returns error for closed database", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      NIF.close(db)
+      assert {:error, :already_closed} = NIF.subscribe_events(db, self())
+      assert {:error, :already_closed} = NIF.unsubscribe_events(db, self())
+    end
+  end
+
+  describe "triple_changes_since/3" do
+    test "decodes spo puts and deletes and ignores other column families", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      key = <<1::64-big, 2::64-big, 3::64-big>>
+
+      :ok = NIF.put(db, :spo, key, "")
+      :ok = NIF.put(db, :pos, <<2::64-big, 3::64-big, 1::64-big>>, "")
+      :ok = NIF.write_batch(db, [{:spo, <<4::64-big, 5::64-big, 6::64-big>>, ""}], false)
+
+      assert {:ok, changes, next_seq} = NIF.triple_changes_since(db, 0, 8)
+      assert changes == [{:added, 1, 2, 3}, {:added, 4, 5, 6}]
+
+      :ok = NIF.delete(db, :spo, key)
+      assert {:ok, [{:removed, 1, 2, 3}], later_seq} = NIF.triple_changes_since(db, next_seq, 8)
+      assert later_seq > next_seq
+      NIF.close(db)
+    end
+
+    test "rejects invalid id width", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert {:error, {:invalid_id_width, 0}} = NIF.triple_changes_since(db, 0, 0)
+      NIF.close(db)
+    end
+
+    test "returns error for closed database", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      NIF.close(db)
+      assert {:error, :already_closed} = NIF.triple_changes_since(db, 0, 8)
+    end
+  end
+
+  describe "user timestamps" do
+    test "open rejects enable_user_timestamps", %{path: path} do
+      assert {:error, :timestamps_unsupported} =
+               NIF.open(path, derived: [enable_user_timestamps: true])
+
+      assert {:error, {:invalid_option, :enable_user_timestamps}} =
+               NIF.open(path, spo: [enable_user_timestamps: true])
+    end
+
+    test "put_ts/5 and get_ts/4 report missing support", %{path: path} do
+      {:ok, db} = NIF.open(path)
+      assert {:error, :timestamps_unsupported} = NIF.put_ts(db, :derived, "k", "v", 1)
+      assert {:error, :timestamps_unsupported} = NIF.get_ts(db, :derived, "k", 1)
+      assert {:error, {:invalid_cf, :bogus}} = NIF.get_ts(db, :bogus, "k", 1)
+
+      NIF.close(db)
+      assert {:error, :already_closed} = NIF.put_ts(db, :derived, "k", "v", 1)
+    end
+  end
+
   describe "list_column_families/0" do
     test "returns all configured column families" do
       cfs = NIF.list_column_families()
-      assert length(cfs) == 7
+      assert length(cfs) == 10
       assert :id2str in cfs
       assert :str2id in cfs
       assert :spo in cfs
@@ -97,6 +1085,9 @@ defmodule TripleStore.Backend.RocksDB.LifecycleTest do
       assert :osp in cfs
       assert :derived in cfs
       assert :numeric_range in cfs
+      assert :gspo in cfs
+      assert :gpos in cfs
+      assert :gosp in cfs
     end
   end
 
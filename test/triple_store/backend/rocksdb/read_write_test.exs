@@ -4,6 +4,8 @@ defmodule TripleStore.Backend.RocksDB.ReadWriteTest do
   """
   use TripleStore.PooledDbCase
 
+  require Logger
+
   describe "put/4" do
     test "writes a key-value pair successfully", %{db: db} do
       assert :ok = NIF.put(db, :id2str, "key1", "value1")
@@ -47,6 +49,37 @@ defmodule TripleStore.Backend.RocksDB.ReadWriteTest do
     end
   end
 
+  describe "put_if_absent/4" do
+    test "writes when the key is absent", %{db: db} do
+      assert {:ok, :written} = NIF.put_if_absent(db, :str2id, "term", "id1")
+      assert {:ok, "id1"} = NIF.get(db, :str2id, "term")
+    end
+
+    test "keeps and returns the existing value", %{db: db} do
+      :ok = NIF.put(db, :str2id, "term", "id1")
+      assert {:ok, {:exists, "id1"}} = NIF.put_if_absent(db, :str2id, "term", "id2")
+      assert {:ok, "id1"} = NIF.get(db, :str2id, "term")
+    end
+
+    test "lets exactly one concurrent caller write", %{db: db} do
+      results =
+        1..16
+        |> Enum.map(fn i ->
+          Task.async(fn -> NIF.put_if_absent(db, :str2id, "shared", "id#{i}") end)
+        end)
+        |> Task.await_many()
+
+      {:ok, stored} = NIF.get(db, :str2id, "shared")
+      assert Enum.count(results, &(&1 == {:ok, :written})) == 1
+      assert Enum.count(results, &(&1 == {:ok, {:exists, stored}})) == 15
+    end
+
+    test "returns error for invalid column family", %{db: db} do
+      assert {:error, {:invalid_cf, :nonexistent}} =
+               NIF.put_if_absent(db, :nonexistent, "key", "value")
+    end
+  end
+
   describe "get/3" do
     test "retrieves an existing key", %{db: db} do
       NIF.put(db, :id2str, "key1", "value1")
@@ -85,6 +118,110 @@ defmodule TripleStore.Backend.RocksDB.ReadWriteTest do
     end
   end
 
+  describe "get_pinned/3" do
+    test "returns the same value as get/3", %{db: db} do
+      NIF.put(db, :id2str, "key1", "value1")
+      assert {:ok, "value1"} = NIF.get_pinned(db, :id2str, "key1")
+    end
+
+    test "returns large values from the memtable and from SST files", %{db: db} do
+      value = :crypto.strong_rand_bytes(1_048_576)
+      NIF.put(db, :id2str, "large", value)
+      assert {:ok, ^value} = NIF.get_pinned(db, :id2str, "large")
+
+      :ok = NIF.flush(db, true)
+      assert {:ok, pinned} = NIF.get_pinned(db, :id2str, "large")
+      assert pinned == value
+      assert binary_part(pinned, 1000, 16) == binary_part(value, 1000, 16)
+    end
+
+    test "returns :not_found for missing key", %{db: db} do
+      assert :not_found = NIF.get_pinned(db, :id2str, "nonexistent")
+    end
+
+    test "keeps the value readable after the database is closed", %{db_path: path} do
+      {:ok, db2} = NIF.open("#{path}_pinned")
+      NIF.put(db2, :id2str, "key", String.duplicate("x", 10_000))
+      :ok = NIF.flush(db2, true)
+
+      {:ok, pinned} = NIF.get_pinned(db2, :id2str, "key")
+      NIF.close(db2)
+      :erlang.garbage_collect()
+
+      assert pinned == String.duplicate("x", 10_000)
+      File.rm_rf("#{path}_pinned")
+    end
+
+    test "returns error for invalid column family", %{db: db} do
+      assert {:error, {:invalid_cf, :nonexistent}} = NIF.get_pinned(db, :nonexistent, "key")
+    end
+
+    test "returns error for closed database", %{db_path: path} do
+      {:ok, db2} = NIF.open("#{path}_closed")
+      NIF.close(db2)
+      assert {:error, :already_closed} = NIF.get_pinned(db2, :id2str, "key")
+      File.rm_rf("#{path}_closed")
+    end
+  end
+
+  describe "multi_get/3" do
+    test "returns values in key order with nil for absent keys", %{db: db} do
+      NIF.put(db, :str2id, "a", "1")
+      NIF.put(db, :str2id, "c", "3")
+
+      assert {:ok, ["3", nil, "1", "3"]} = NIF.multi_get(db, :str2id, ["c", "b", "a", "c"])
+    end
+
+    test "returns an empty list for no keys", %{db: db} do
+      assert {:ok, []} = NIF.multi_get(db, :spo, [])
+    end
+
+    test "returns error for invalid column family", %{db: db} do
+      assert {:error, {:invalid_cf, :nonexistent}} = NIF.multi_get(db, :nonexistent, ["key"])
+    end
+
+    test "returns error for closed database", %{db_path: path} do
+      {:ok, db2} = NIF.open("#{path}_closed")
+      NIF.close(db2)
+      assert {:error, :already_closed} = NIF.multi_get(db2, :id2str, ["key"])
+      File.rm_rf("#{path}_closed")
+    end
+  end
+
+  describe "multi_get_cf/2" do
+    test "returns values aligned with interleaved requests", %{db: db} do
+      NIF.put(db, :str2id, "term", "id1")
+      NIF.put(db, :spo, "id1", "")
+      NIF.put(db, :derived, "id1", "d")
+
+      requests = [
+        {:spo, "id1"},
+        {:str2id, "term"},
+        {:spo, "id2"},
+        {:derived, "id1"},
+        {:str2id, "missing"}
+      ]
+
+      assert {:ok, ["", "id1", nil, "d", nil]} = NIF.multi_get_cf(db, requests)
+    end
+
+    test "returns an empty list for no requests", %{db: db} do
+      assert {:ok, []} = NIF.multi_get_cf(db, [])
+    end
+
+    test "returns error for the first invalid column family", %{db: db} do
+      assert {:error, {:invalid_cf, :bogus}} =
+               NIF.multi_get_cf(db, [{:spo, "k"}, {:bogus, "k"}, {:nope, "k"}])
+    end
+
+    test "returns error for closed database", %{db_path: path} do
+      {:ok, db2} = NIF.open("#{path}_closed")
+      NIF.close(db2)
+      assert {:error, :already_closed} = NIF.multi_get_cf(db2, [{:spo, "key"}])
+      File.rm_rf("#{path}_closed")
+    end
+  end
+
   describe "delete/3" do
     test "deletes an existing key", %{db: db} do
       NIF.put(db, :id2str, "key1", "value1")
@@ -117,6 +254,97 @@ defmodule TripleStore.Backend.RocksDB.ReadWriteTest do
     end
   end
 
+  describe "delete_range/4" do
+    test "deletes keys in the range with the end exclusive", %{db: db} do
+      for s <- 1..3, o <- 1..5, do: :ok = NIF.put(db, :spo, <<s::64, o::64>>, "")
+
+      assert :ok = NIF.delete_range(db, :spo, <<2::64>>, <<3::64>>)
+
+      assert {:ok, ""} = NIF.get(db, :spo, <<1::64, 5::64>>)
+      assert :not_found = NIF.get(db, :spo, <<2::64, 1::64>>)
+      assert :not_found = NIF.get(db, :spo, <<2::64, 5::64>>)
+      assert {:ok, ""} = NIF.get(db, :spo, <<3::64, 1::64>>)
+    end
+
+    test "succeeds for an empty range", %{db: db} do
+      :ok = NIF.put(db, :spo, "key", "value")
+      assert :ok = NIF.delete_range(db, :spo, "key", "key")
+      assert {:ok, "value"} = NIF.get(db, :spo, "key")
+    end
+
+    test "rejects a range that ends before it starts", %{db: db} do
+      assert {:error, {:delete_failed, _}} = NIF.delete_range(db, :spo, "b", "a")
+    end
+
+    test "returns error for invalid column family", %{db: db} do
+      assert {:error, {:invalid_cf, :nonexistent}} = NIF.delete_range(db, :nonexistent, "a", "b")
+    end
+
+    test "returns error for closed database", %{db_path: path} do
+      {:ok, db2} = NIF.open("#{path}_closed")
+      NIF.close(db2)
+      assert {:error, :already_closed} = NIF.delete_range(db2, :spo, "a", "b")
+      File.rm_rf("#{path}_closed")
+    end
+  end
+
+  describe "merge/4" do
+    test "returns no_merge_operator when none was installed", %{db: db} do
+      assert {:error, :no_merge_operator} =
+               NIF.merge(db, :id2str, "counter", <<1::64-little-signed>>)
+
+      assert :not_found = NIF.get(db, :id2str, "counter")
+    end
+
+    test "derived folds counters by default", %{db: db} do
+      assert :ok = NIF.merge(db, :derived, "refcount", 5)
+      assert :ok = NIF.merge(db, :derived, "refcount", -2)
+      assert :ok = NIF.merge(db, :derived, "refcount", <<4::64-little-signed>>)
+      assert {:ok, <<7::64-little-signed>>} = NIF.get(db, :derived, "refcount")
+    end
+
+    test "refuses operands that aren't 8-byte counters", %{db: db} do
+      assert :ok = NIF.merge(db, :derived, "refcount", 2)
+
+      assert {:error, {:invalid_operand, <<1, 2, 3>>}} =
+               NIF.merge(db, :derived, "refcount", <<1, 2, 3>>)
+
+      assert {:error, {:invalid_operand, 1.5}} = NIF.merge(db, :derived, "refcount", 1.5)
+
+      assert :ok = NIF.merge(db, :derived, "refcount", 1)
+      assert {:ok, <<3::64-little-signed>>} = NIF.get(db, :derived, "refcount")
+    end
+
+    test "concurrent merges never lose an update", %{db: db} do
+      1..1000
+      |> Task.async_stream(fn _ -> NIF.merge(db, :derived, "refcount", 1) end,
+        max_concurrency: 32
+      )
+      |> Enum.each(fn {:ok, result} -> assert result == :ok end)
+
+      assert {:ok, <<1000::64-little-signed>>} = NIF.get(db, :derived, "refcount")
+    end
+
+    test "folds operands when a merge operator is installed", %{db_path: path} do
+      merge_path = "#{path}_merge"
+      {:ok, db2} = NIF.open(merge_path, derived: [merge_operator: :int64_add])
+
+      assert :ok = NIF.merge(db2, :derived, "counter", <<5::64-little-signed>>)
+      assert :ok = NIF.merge(db2, :derived, "counter", <<-2::64-little-signed>>)
+      assert {:ok, <<3::64-little-signed>>} = NIF.get(db2, :derived, "counter")
+
+      assert {:error, :no_merge_operator} =
+               NIF.merge(db2, :spo, "counter", <<1::64-little-signed>>)
+
+      NIF.close(db2)
+      File.rm_rf(merge_path)
+    end
+
+    test "returns error for invalid column family", %{db: db} do
+      assert {:error, {:invalid_cf, :nonexistent}} = NIF.merge(db, :nonexistent, "k", "v")
+    end
+  end
+
   describe "exists/3" do
     test "returns true for existing key", %{db: db} do
       NIF.put(db, :id2str, "key1", "value1")
@@ -153,6 +381,43 @@ defmodule TripleStore.Backend.RocksDB.ReadWriteTest do
     end
   end
 
+  describe "key_may_exist/3" do
+    test "returns true for a key that exists", %{db: db} do
+      NIF.put(db, :spo, "key1", "")
+      assert {:ok, true} = NIF.key_may_exist(db, :spo, "key1")
+
+      :ok = NIF.flush(db, true)
+      assert {:ok, true} = NIF.key_may_exist(db, :spo, "key1")
+    end
+
+    test "agrees with exists/3 whenever it returns false", %{db: db} do
+      for i <- 1..100, do: NIF.put(db, :spo, "present#{i}", "")
+      :ok = NIF.flush(db, true)
+
+      for i <- 1..100 do
+        key = "absent#{i}"
+        assert {:ok, false} = NIF.exists(db, :spo, key)
+        assert {:ok, may_exist} = NIF.key_may_exist(db, :spo, key)
+        assert is_boolean(may_exist)
+      end
+    end
+
+    test "returns false in an empty column family", %{db: db} do
+      assert {:ok, false} = NIF.key_may_exist(db, :pos, "nonexistent")
+    end
+
+    test "returns error for invalid column family", %{db: db} do
+      assert {:error, {:invalid_cf, :nonexistent}} = NIF.key_may_exist(db, :nonexistent, "key")
+    end
+
+    test "returns error for closed database", %{db_path: path} do
+      {:ok, db2} = NIF.open("#{path}_closed")
+      NIF.close(db2)
+      assert {:error, :already_closed} = NIF.key_may_exist(db2, :spo, "key")
+      File.rm_rf("#{path}_closed")
+    end
+  end
+
   describe "data persistence" do
     test "data persists after close and reopen", %{db_path: path} do
       {:ok, db1} = NIF.open("#{path}_persist")
@@ -206,5 +471,66 @@ defmodule TripleStore.Backend.RocksDB.ReadWriteTest do
         assert {:ok, ^expected} = NIF.get(db, :id2str, "concurrent_key#{i}")
       end
     end
+
+    @tag :benchmark
+    test "p99 get latency with 64 concurrent readers", %{db: db} do
+      for i <- 0..1023 do
+        NIF.put(db, :id2str, <<i::64>>, "value")
+      end
+
+      latencies =
+        1..64
+        |> Enum.map(fn reader ->
+          Task.async(fn ->
+            for i <- 1..5_000 do
+              key = <<rem(reader * 5_000 + i, 1024)::64>>
+              {micros, {:ok, "value"}} = :timer.tc(fn -> NIF.get(db, :id2str, key) end)
+              micros
+            end
+          end)
+        end)
+        |> Task.await_many(60_000)
+        |> List.flatten()
+        |> Enum.sort()
+
+      p99 = Enum.at(latencies, div(length(latencies) * 99, 100))
+
+      Logger.debug("""
+      Concurrent get benchmark (64 readers, #{length(latencies)} gets):
+        p99 latency: #{p99}us
+      """)
+
+      assert length(latencies) == 64 * 5_000
+    end
+  end
+
+  describe "inject_write_fault/2" do
+    test "fails the next n writes", %{db: db} do
+      assert :ok = NIF.inject_write_fault(db, {:count, 2})
+
+      assert {:error, {:put_failed, "injected"}} = NIF.put(db, :spo, "key", "value")
+
+      assert {:error, {:put_failed, "injected"}} =
+               NIF.write_batch(db, [{:spo, "key", "value"}], false)
+
+      assert :not_found = NIF.get(db, :spo, "key")
+      assert :ok = NIF.put(db, :spo, "key", "value")
+      assert {:ok, "value"} = NIF.get(db, :spo, "key")
+    end
+
+    test "fails writes until cleared", %{db: db} do
+      assert :ok = NIF.inject_write_fault(db, :always)
+
+      for _ <- 1..3 do
+        assert {:error, {:put_failed, "injected"}} = NIF.put(db, :spo, "key", "value")
+      end
+
+      assert :ok = NIF.inject_write_fault(db, :clear)
+      assert :ok = NIF.put(db, :spo, "key", "value")
+    end
+
+    test "rejects unknown modes", %{db: db} do
+      assert {:error, {:invalid_option, :sometimes}} = NIF.inject_write_fault(db, :sometimes)
+    end
   end
 end
@@ -110,6 +110,53 @@ defmodule TripleStore.Backend.RocksDB.SnapshotTest do
     end
   end
 
+  describe "multi_get_snapshot/3" do
+    test "reads all keys at snapshot time", %{db: db} do
+      NIF.put(db, :spo, "key1", "value1")
+      NIF.put(db, :spo, "key2", "value2")
+
+      {:ok, snap} = NIF.snapshot(db)
+
+      # Writes after the snapshot must not be visible to the batch
+      NIF.put(db, :spo, "key1", "changed")
+      NIF.delete(db, :spo, "key2")
+      NIF.put(db, :spo, "key3", "value3")
+
+      assert {:ok, [{:ok, "value1"}, {:ok, "value2"}, :not_found]} =
+               NIF.multi_get_snapshot(snap, :spo, ["key1", "key2", "key3"])
+
+      NIF.release_snapshot(snap)
+    end
+
+    test "preserves key order and duplicates", %{db: db} do
+      NIF.put(db, :spo, "a", "1")
+      NIF.put(db, :spo, "b", "2")
+      {:ok, snap} = NIF.snapshot(db)
+
+      assert {:ok, [{:ok, "2"}, :not_found, {:ok, "1"}, {:ok, "2"}]} =
+               NIF.multi_get_snapshot(snap, :spo, ["b", "c", "a", "b"])
+
+      assert {:ok, []} = NIF.multi_get_snapshot(snap, :spo, [])
+      NIF.release_snapshot(snap)
+    end
+
+    test "returns error for invalid column family", %{db: db} do
+      {:ok, snap} = NIF.snapshot(db)
+
+      assert {:error, {:invalid_cf, :nonexistent}} =
+               NIF.multi_get_snapshot(snap, :nonexistent, ["key"])
+
+      NIF.release_snapshot(snap)
+    end
+
+    test "returns error for released snapshot", %{db: db} do
+      {:ok, snap} = NIF.snapshot(db)
+      NIF.release_snapshot(snap)
+
+      assert {:error, :snapshot_released} = NIF.multi_get_snapshot(snap, :spo, ["key"])
+    end
+  end
+
   describe "snapshot_prefix_iterator/3" do
     test "creates iterator over snapshot", %{db: db} do
       NIF.put(db, :spo, "key1", "value1")
@@ -593,4 +640,53 @@ defmodule TripleStore.Backend.RocksDB.SnapshotTest do
       NIF.release_snapshot(snap)
     end
   end
+
+  describe "export_snapshot_ntriples/3" do
+    setup %{db_path: path} do
+      out = "#{path}_export.nt"
+      on_exit(fn -> File.rm(out) end)
+      {:ok, out: out}
+    end
+
+    test "writes resolved triples as N-Triples", %{db: db, out: out} do
+      s = TripleStore.Dictionary.encode_id(TripleStore.Dictionary.type_uri(), 1)
+      p = TripleStore.Dictionary.encode_id(TripleStore.Dictionary.type_uri(), 2)
+      o = TripleStore.Dictionary.encode_id(TripleStore.Dictionary.type_literal(), 3)
+      {:ok, n} = TripleStore.Dictionary.encode_integer(42)
+
+      NIF.put(db, :id2str, <<s::64-big>>, <<1, "http://ex.org/s">>)
+      NIF.put(db, :id2str, <<p::64-big>>, <<1, "http://ex.org/p">>)
+      NIF.put(db, :id2str, <<o::64-big>>, <<3, 2, "en", 0, "hello">>)
+      NIF.put(db, :spo, <<s::64-big, p::64-big, o::64-big>>, "")
+      NIF.put(db, :spo, <<s::64-big, p::64-big, n::64-big>>, "")
+
+      assert {:ok, 2} = NIF.export_snapshot_ntriples(db, out, 8)
+
+      lines = out |> File.read!() |> String.split("\n", trim: true)
+
+      assert ~s(<http://ex.org/s> <http://ex.org/p> "hello"@en .) in lines
+
+      assert ~s(<http://ex.org/s> <http://ex.org/p> "42"^^<http://www.w3.org/2001/XMLSchema#integer> .) in lines
+    end
+
+    test "returns unresolved_id and removes the file", %{db: db, out: out} do
+      NIF.put(db, :spo, <<1::64-big, 2::64-big, 3::64-big>>, "")
+
+      assert {:error, {:unresolved_id, 1}} = NIF.export_snapshot_ntriples(db, out, 8)
+      refute File.exists?(out)
+    end
+
+    test "rejects invalid id width", %{db: db, out: out} do
+      assert {:error, {:invalid_id_width, 0}} = NIF.export_snapshot_ntriples(db, out, 0)
+      assert {:error, {:invalid_id_width, 9}} = NIF.export_snapshot_ntriples(db, out, 9)
+    end
+
+    test "returns error for closed database", %{db_path: path, out: out} do
+      {:ok, db2} = NIF.open("#{path}_export_closed")
+      NIF.close(db2)
+
+      assert {:error, :already_closed} = NIF.export_snapshot_ntriples(db2, out, 8)
+      File.rm_rf("#{path}_export_closed")
+    end
+  end
 end
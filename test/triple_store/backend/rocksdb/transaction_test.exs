@@ -0,0 +1,235 @@
+defmodule TripleStore.Backend.RocksDB.TransactionTest do
+  @moduledoc """
+  Tests for RocksDB optimistic transactions.
+  """
+  use TripleStore.PooledDbCase
+
+  describe "transaction_begin/1" do
+    test "returns error for closed database", %{db_path: path} do
+      {:ok, db2} = NIF.open("#{path}_closed")
+      NIF.close(db2)
+
+      assert {:error, :already_closed} = NIF.transaction_begin(db2)
+      File.rm_rf("#{path}_closed")
+    end
+  end
+
+  describe "transaction reads and writes" do
+    test "buffers writes until commit", %{db: db} do
+      {:ok, txn} = NIF.transaction_begin(db)
+      assert :ok = NIF.transaction_put(txn, :id2str, "txn_k1", "v1")
+
+      assert {:ok, "v1"} = NIF.transaction_get(txn, :id2str, "txn_k1")
+      assert :not_found = NIF.get(db, :id2str, "txn_k1")
+
+      assert :ok = NIF.transaction_commit(txn)
+      assert {:ok, "v1"} = NIF.get(db, :id2str, "txn_k1")
+    end
+
+    test "deletes within a transaction", %{db: db} do
+      :ok = NIF.put(db, :str2id, "txn_k2", "v2")
+
+      {:ok, txn} = NIF.transaction_begin(db)
+      assert :ok = NIF.transaction_delete(txn, :str2id, "txn_k2")
+      assert :not_found = NIF.transaction_get(txn, :str2id, "txn_k2")
+      assert {:ok, "v2"} = NIF.get(db, :str2id, "txn_k2")
+
+      assert :ok = NIF.transaction_commit(txn)
+      assert :not_found = NIF.get(db, :str2id, "txn_k2")
+    end
+
+    test "writes to several column families atomically", %{db: db} do
+      {:ok, txn} = NIF.transaction_begin(db)
+      :ok = NIF.transaction_put(txn, :id2str, "txn_id", "term")
+      :ok = NIF.transaction_put(txn, :str2id, "txn_term", "id")
+      assert :ok = NIF.transaction_commit(txn)
+
+      assert {:ok, "term"} = NIF.get(db, :id2str, "txn_id")
+      assert {:ok, "id"} = NIF.get(db, :str2id, "txn_term")
+    end
+
+    test "rollback discards writes", %{db: db} do
+      {:ok, txn} = NIF.transaction_begin(db)
+      :ok = NIF.transaction_put(txn, :spo, "txn_k3", "")
+
+      assert :ok = NIF.transaction_rollback(txn)
+      assert :not_found = NIF.get(db, :spo, "txn_k3")
+      assert {:error, :transaction_closed} = NIF.transaction_commit(txn)
+    end
+
+    test "returns errors for invalid column family and finished transactions", %{db: db} do
+      {:ok, txn} = NIF.transaction_begin(db)
+      assert {:error, {:invalid_cf, :bogus}} = NIF.transaction_put(txn, :bogus, "k", "v")
+      assert {:error, {:invalid_cf, :bogus}} = NIF.transaction_get(txn, :bogus, "k")
+
+      :ok = NIF.transaction_commit(txn)
+      assert {:error, :transaction_closed} = NIF.transaction_put(txn, :spo, "k", "v")
+      assert {:error, :transaction_closed} = NIF.transaction_get_for_update(txn, :spo, "k")
+      assert {:error, :transaction_closed} = NIF.transaction_rollback(txn)
+    end
+  end
+
+  describe "conflict detection" do
+    test "second read-modify-write of the counter gets :busy", %{db: db} do
+      :ok = NIF.put(db, :derived, "txn_counter", <<1::64>>)
+
+      {:ok, t1} = NIF.transaction_begin(db)
+      {:ok, t2} = NIF.transaction_begin(db)
+
+      {:ok, <<n1::64>>} = NIF.transaction_get_for_update(t1, :derived, "txn_counter")
+      assert {:error, :busy} = NIF.transaction_get_for_update(t2, :derived, "txn_counter")
+      assert {:error, :busy} = NIF.transaction_put(t2, :derived, "txn_counter", <<9::64>>)
+
+      :ok = NIF.transaction_put(t1, :derived, "txn_counter", <<n1 + 1::64>>)
+      assert :ok = NIF.transaction_commit(t1)
+
+      # The lock is released with the commit
+      assert {:ok, <<2::64>>} = NIF.transaction_get_for_update(t2, :derived, "txn_counter")
+      :ok = NIF.transaction_rollback(t2)
+      assert {:ok, <<2::64>>} = NIF.get(db, :derived, "txn_counter")
+    end
+
+    test "another transaction can't change a locked key back and forth", %{db: db} do
+      :ok = NIF.put(db, :derived, "txn_aba", "a")
+
+      {:ok, t1} = NIF.transaction_begin(db)
+      {:ok, "a"} = NIF.transaction_get_for_update(t1, :derived, "txn_aba")
+
+      {:ok, t2} = NIF.transaction_begin(db)
+      assert {:error, :busy} = NIF.transaction_put(t2, :derived, "txn_aba", "b")
+      :ok = NIF.transaction_rollback(t2)
+
+      :ok = NIF.transaction_put(t1, :derived, "txn_aba", "c")
+      assert :ok = NIF.transaction_commit(t1)
+      assert {:ok, "c"} = NIF.get(db, :derived, "txn_aba")
+    end
+
+    test "a waiting transaction gets the lock once the holder finishes", %{db: db} do
+      {:ok, t1} = NIF.transaction_begin(db)
+      :ok = NIF.transaction_put(t1, :derived, "txn_wait", "first")
+
+      waiter =
+        Task.async(fn ->
+          {:ok, t2} = NIF.transaction_begin(db)
+          {:ok, "first"} = NIF.transaction_get_for_update(t2, :derived, "txn_wait")
+          :ok = NIF.transaction_put(t2, :derived, "txn_wait", "second")
+          NIF.transaction_commit(t2)
+        end)
+
+      Process.sleep(50)
+      assert :ok = NIF.transaction_commit(t1)
+      assert :ok = Task.await(waiter)
+      assert {:ok, "second"} = NIF.get(db, :derived, "txn_wait")
+    end
+
+    test "a plain write to a tracked key fails the commit", %{db: db} do
+      {:ok, txn} = NIF.transaction_begin(db)
+      :ok = NIF.transaction_put(txn, :derived, "txn_shared", "mine")
+
+      :ok = NIF.put(db, :derived, "txn_shared", "theirs")
+      assert {:error, :busy} = NIF.transaction_commit(txn)
+      assert {:ok, "theirs"} = NIF.get(db, :derived, "txn_shared")
+    end
+
+    test "untracked reads don't conflict", %{db: db} do
+      :ok = NIF.put(db, :derived, "txn_plain", "a")
+
+      {:ok, txn} = NIF.transaction_begin(db)
+      {:ok, "a"} = NIF.transaction_get(txn, :derived, "txn_plain")
+      :ok = NIF.put(db, :derived, "txn_plain", "b")
+
+      :ok = NIF.transaction_put(txn, :derived, "txn_other", "x")
+      assert :ok = NIF.transaction_commit(txn)
+    end
+  end
+
+  describe "open_transactional/1" do
+    setup %{db_path: path} do
+      txn_path = "#{path}_txn_db"
+      {:ok, txn_db} = NIF.open_transactional(txn_path)
+
+      on_exit(fn ->
+        NIF.close_transactional(txn_db)
+        File.rm_rf(txn_path)
+      end)
+
+      {:ok, txn_db: txn_db}
+    end
+
+    test "commits writes atomically and hides them until then", %{txn_db: txn_db} do
+      {:ok, txn} = NIF.begin_transaction(txn_db)
+      assert :ok = NIF.txn_put(txn, :str2id, "term", <<1::64>>)
+      assert :ok = NIF.txn_put(txn, :id2str, <<1::64>>, "term")
+      assert {:ok, <<1::64>>} = NIF.txn_get(txn, :str2id, "term")
+
+      {:ok, reader} = NIF.begin_transaction(txn_db)
+      assert :not_found = NIF.txn_get(reader, :id2str, <<2::64>>)
+      assert :ok = NIF.rollback(reader)
+
+      assert :ok = NIF.commit(txn)
+      assert {:error, :transaction_closed} = NIF.commit(txn)
+
+      {:ok, check} = NIF.begin_transaction(txn_db)
+      assert {:ok, "term"} = NIF.txn_get(check, :id2str, <<1::64>>)
+      assert :ok = NIF.commit(check)
+    end
+
+    test "rollback discards writes", %{txn_db: txn_db} do
+      {:ok, txn} = NIF.begin_transaction(txn_db)
+      :ok = NIF.txn_put(txn, :derived, "rolled_back", "v")
+      :ok = NIF.txn_delete(txn, :derived, "rolled_back")
+      :ok = NIF.txn_put(txn, :derived, "rolled_back", "v")
+      assert :ok = NIF.rollback(txn)
+      assert {:error, :transaction_closed} = NIF.txn_put(txn, :derived, "k", "v")
+
+      {:ok, check} = NIF.begin_transaction(txn_db)
+      assert :not_found = NIF.txn_get(check, :derived, "rolled_back")
+      assert :ok = NIF.rollback(check)
+    end
+
+    test "a key locked by another transaction is busy", %{txn_db: txn_db} do
+      {:ok, t1} = NIF.begin_transaction(txn_db)
+      {:ok, t2} = NIF.begin_transaction(txn_db)
+      :ok = NIF.txn_put(t1, :derived, "next_id", <<1::64>>)
+
+      assert {:error, :busy} = NIF.txn_get(t2, :derived, "next_id")
+      assert {:error, :busy} = NIF.txn_put(t2, :derived, "next_id", <<2::64>>)
+      assert :ok = NIF.commit(t1)
+      assert :ok = NIF.rollback(t2)
+    end
+
+    test "a write committed after a transaction began is a conflict", %{txn_db: txn_db} do
+      {:ok, stale} = NIF.begin_transaction(txn_db)
+
+      {:ok, other} = NIF.begin_transaction(txn_db)
+      :ok = NIF.txn_put(other, :derived, "next_id", <<1::64>>)
+      :ok = NIF.commit(other)
+
+      assert {:error, :busy} = NIF.txn_put(stale, :derived, "next_id", <<5::64>>)
+      assert :ok = NIF.rollback(stale)
+
+      # Retrying in a new transaction sees the committed value
+      {:ok, retry} = NIF.begin_transaction(txn_db)
+      assert {:ok, <<1::64>>} = NIF.txn_get(retry, :derived, "next_id")
+      assert :ok = NIF.txn_put(retry, :derived, "next_id", <<2::64>>)
+      assert :ok = NIF.commit(retry)
+    end
+
+    test "rejects invalid column families", %{txn_db: txn_db} do
+      {:ok, txn} = NIF.begin_transaction(txn_db)
+      assert {:error, {:invalid_cf, :nope}} = NIF.txn_put(txn, :nope, "k", "v")
+      assert :ok = NIF.rollback(txn)
+    end
+
+    test "returns error once closed", %{txn_db: txn_db} do
+      {:ok, txn} = NIF.begin_transaction(txn_db)
+      assert :ok = NIF.close_transactional(txn_db)
+      assert {:error, :already_closed} = NIF.close_transactional(txn_db)
+      assert {:error, :already_closed} = NIF.begin_transaction(txn_db)
+
+      # Open transactions keep working until they finish
+      assert :ok = NIF.txn_put(txn, :derived, "after_close", "v")
+      assert :ok = NIF.commit(txn)
+    end
+  end
+end
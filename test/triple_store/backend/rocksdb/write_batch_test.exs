@@ -319,6 +319,55 @@ defmodule TripleStore.Backend.RocksDB.WriteBatchTest do
       assert {:error, {:invalid_cf, :nonexistent}} = NIF.mixed_batch(db, operations, true)
     end
 
+    test "deletes a key range atomically with other operations", %{db: db} do
+      for key <- ["s1:a", "s1:b", "s1:c", "s2:a"] do
+        NIF.put(db, :spo, key, "")
+      end
+
+      NIF.put(db, :derived, "s1:inferred", "")
+
+      operations = [
+        {:delete_range, :spo, "s1:", "s1;"},
+        {:delete_range, :derived, "s1:", "s1;"},
+        {:put, :spo, "s1:new", ""},
+        {:put, :id2str, "marker", "done"}
+      ]
+
+      assert :ok = NIF.mixed_batch(db, operations, true)
+
+      {:ok, iter} = NIF.prefix_iterator(db, :spo, "s")
+      assert {:ok, [{"s1:new", ""}, {"s2:a", ""}]} = NIF.iterator_collect(iter)
+      assert :not_found = NIF.get(db, :derived, "s1:inferred")
+      assert {:ok, "done"} = NIF.get(db, :id2str, "marker")
+    end
+
+    test "delete_range excludes the end key", %{db: db} do
+      NIF.put(db, :spo, "a", "")
+      NIF.put(db, :spo, "b", "")
+
+      assert :ok = NIF.mixed_batch(db, [{:delete_range, :spo, "a", "b"}], true)
+      assert :not_found = NIF.get(db, :spo, "a")
+      assert {:ok, ""} = NIF.get(db, :spo, "b")
+    end
+
+    test "rejects an inverted range without writing anything", %{db: db} do
+      NIF.put(db, :spo, "m", "")
+
+      operations = [
+        {:put, :spo, "new", ""},
+        {:delete_range, :spo, "z", "a"}
+      ]
+
+      assert {:error, {:invalid_operation, :delete_range}} = NIF.mixed_batch(db, operations, true)
+      assert :not_found = NIF.get(db, :spo, "new")
+      assert {:ok, ""} = NIF.get(db, :spo, "m")
+    end
+
+    test "returns error for invalid column family in delete_range", %{db: db} do
+      assert {:error, {:invalid_cf, :nonexistent}} =
+               NIF.mixed_batch(db, [{:delete_range, :nonexistent, "a", "b"}], true)
+    end
+
     test "returns error for closed database", %{db_path: path} do
       {:ok, db2} = NIF.open("#{path}_closed")
       NIF.close(db2)
@@ -329,6 +378,107 @@ defmodule TripleStore.Backend.RocksDB.WriteBatchTest do
     end
   end
 
+  describe "swap_cf_contents/3" do
+    test "swaps the contents of two column families", %{db: db} do
+      NIF.put(db, :osp, "new1", "n1")
+      NIF.put(db, :osp, "shared", "new")
+      NIF.put(db, :derived, "old1", "o1")
+      NIF.put(db, :derived, "shared", "old")
+
+      assert {:ok, 2} = NIF.swap_cf_contents(db, :osp, :derived)
+
+      {:ok, derived} = NIF.prefix_stream(db, :derived, "")
+      assert Enum.to_list(derived) == [{"new1", "n1"}, {"shared", "new"}]
+
+      {:ok, osp} = NIF.prefix_stream(db, :osp, "")
+      assert Enum.to_list(osp) == [{"old1", "o1"}, {"shared", "old"}]
+    end
+
+    test "readers never observe a partially swapped state", %{db: db} do
+      n = 20_000
+
+      for chunk <- Enum.chunk_every(1..n, 1_000) do
+        :ok = NIF.write_batch(db, for(i <- chunk, do: {:osp, "new_#{i}", "v"}), false)
+        :ok = NIF.write_batch(db, for(i <- chunk, do: {:derived, "old_#{i}", "v"}), false)
+      end
+
+      parent = self()
+
+      reader =
+        Task.async(fn ->
+          read_until_swapped = fn read_until_swapped, views ->
+            {:ok, snap} = NIF.snapshot(db)
+            {:ok, stream} = NIF.snapshot_stream(snap, :derived, "")
+            keys = Enum.map(stream, fn {k, _} -> k end)
+            NIF.release_snapshot(snap)
+            view = {length(keys), keys |> Enum.map(&binary_part(&1, 0, 4)) |> Enum.uniq()}
+
+            receive do
+              :swapped -> [view | views]
+            after
+              0 ->
+                send(parent, :reading)
+                read_until_swapped.(read_until_swapped, [view | views])
+            end
+          end
+
+          read_until_swapped.(read_until_swapped, [])
+        end)
+
+      # The swap starts once the reader is running, and doesn't hold it up
+      assert_receive :reading, 5_000
+      assert {:ok, ^n} = NIF.swap_cf_contents(db, :osp, :derived)
+      send(reader.pid, :swapped)
+
+      views = Task.await(reader, 30_000)
+      assert length(views) > 1
+
+      for {count, prefixes} <- views do
+        assert count == n
+        assert prefixes in [["old_"], ["new_"]]
+      end
+
+      assert {n, ["new_"]} == hd(views)
+    end
+
+    test "rejects swapping a column family with itself", %{db: db} do
+      assert {:error, :same_cf} = NIF.swap_cf_contents(db, :derived, :derived)
+    end
+
+    test "returns error for invalid column family", %{db: db} do
+      assert {:error, {:invalid_cf, :bogus}} = NIF.swap_cf_contents(db, :bogus, :derived)
+    end
+  end
+
+  describe "ingest_external_file/3,4" do
+    test "fails without touching the column family for a missing file", %{db: db, db_path: path} do
+      NIF.put(db, :spo, "k", "")
+
+      assert {:error, {:ingest_failed, _}} =
+               NIF.ingest_external_file(db, :spo, ["#{path}_missing.sst"])
+
+      assert {:ok, ""} = NIF.get(db, :spo, "k")
+    end
+
+    test "rejects files that are not SST files", %{db: db, db_path: path} do
+      file = "#{path}_not_sst.sst"
+      File.write!(file, "not an sst file")
+
+      assert {:error, {:ingest_failed, _}} =
+               NIF.ingest_external_file(db, :spo, [file], %{move_files: true})
+
+      assert File.exists?(file)
+      File.rm(file)
+    end
+
+    test "validates column family and options", %{db: db} do
+      assert {:error, {:invalid_cf, :bogus}} = NIF.ingest_external_file(db, :bogus, [])
+
+      assert {:error, {:invalid_option, :move_files}} =
+               NIF.ingest_external_file(db, :spo, [], %{move_files: :yes})
+    end
+  end
+
   describe "atomicity" do
     test "write_batch is atomic - all or nothing", %{db: db} do
       # First write some data
@@ -447,4 +597,105 @@ defmodule TripleStore.Backend.RocksDB.WriteBatchTest do
       assert :not_found = NIF.get(db, :id2str, "mix_old")
     end
   end
+
+  describe "write options map" do
+    test "write_batch accepts sync and disable_wal", %{db: db} do
+      assert :ok = NIF.write_batch(db, [{:id2str, "k1", "v1"}], %{sync: true})
+      assert :ok = NIF.write_batch(db, [{:id2str, "k2", "v2"}], %{disable_wal: true})
+      assert :ok = NIF.write_batch(db, [{:id2str, "k3", "v3"}], %{})
+
+      assert {:ok, "v1"} = NIF.get(db, :id2str, "k1")
+      assert {:ok, "v2"} = NIF.get(db, :id2str, "k2")
+      assert {:ok, "v3"} = NIF.get(db, :id2str, "k3")
+    end
+
+    test "mixed_batch and delete_batch accept the options map", %{db: db} do
+      assert :ok =
+               NIF.mixed_batch(db, [{:put, :spo, "a", ""}, {:put, :spo, "b", ""}], %{
+                 sync: false,
+                 disable_wal: true
+               })
+
+      assert :ok = NIF.delete_batch(db, [{:spo, "a"}], %{sync: true})
+      assert :not_found = NIF.get(db, :spo, "a")
+      assert {:ok, ""} = NIF.get(db, :spo, "b")
+    end
+
+    test "put/5 accepts the options map", %{db: db} do
+      assert :ok = NIF.put(db, :id2str, "key", "value", %{sync: true})
+      assert :ok = NIF.put(db, :id2str, "bulk", "value", %{disable_wal: true})
+      assert {:ok, "value"} = NIF.get(db, :id2str, "bulk")
+    end
+
+    test "disable_wal writes persist once flushed", %{db_path: path} do
+      {:ok, db1} = NIF.open("#{path}_no_wal")
+      assert :ok = NIF.write_batch(db1, [{:id2str, "key", "value"}], %{disable_wal: true})
+      :ok = NIF.flush(db1, true)
+      NIF.close(db1)
+
+      {:ok, db2} = NIF.open("#{path}_no_wal")
+      assert {:ok, "value"} = NIF.get(db2, :id2str, "key")
+      NIF.close(db2)
+      File.rm_rf("#{path}_no_wal")
+    end
+
+    test "ignores unknown keys", %{db: db} do
+      assert :ok = NIF.write_batch(db, [{:id2str, "key", "value"}], %{future_option: 1})
+    end
+
+    test "rejects non-boolean values", %{db: db} do
+      assert {:error, {:invalid_option, :sync}} =
+               NIF.write_batch(db, [{:id2str, "key", "value"}], %{sync: :yes})
+
+      assert {:error, {:invalid_option, :disable_wal}} =
+               NIF.mixed_batch(db, [{:put, :spo, "key", ""}], %{disable_wal: 1})
+
+      assert {:error, {:invalid_option, :sync}} = NIF.put(db, :spo, "key", "", %{sync: "true"})
+      assert {:error, {:invalid_option, :fast}} = NIF.delete_batch(db, [{:spo, "key"}], :fast)
+      assert :not_found = NIF.get(db, :spo, "key")
+    end
+  end
+
+  describe "put_quad/4" do
+    defp quad_keys(g, s, p, o) do
+      {<<g::64, s::64, p::64, o::64>>, <<g::64, p::64, o::64, s::64>>,
+       <<g::64, o::64, s::64, p::64>>}
+    end
+
+    test "writes all three quad indexes", %{db: db} do
+      {gspo, gpos, gosp} = quad_keys(10, 1, 2, 3)
+
+      assert :ok = NIF.put_quad(db, gspo, gpos, gosp)
+
+      assert {:ok, <<>>} = NIF.get(db, :gspo, gspo)
+      assert {:ok, <<>>} = NIF.get(db, :gpos, gpos)
+      assert {:ok, <<>>} = NIF.get(db, :gosp, gosp)
+      assert :not_found = NIF.get(db, :spo, <<1::64, 2::64, 3::64>>)
+    end
+
+    test "quads can be scanned by graph prefix", %{db: db} do
+      for {g, s, p, o} <- [{10, 1, 2, 3}, {10, 4, 5, 6}, {11, 1, 2, 3}] do
+        {gspo, gpos, gosp} = quad_keys(g, s, p, o)
+        assert :ok = NIF.put_quad(db, gspo, gpos, gosp)
+      end
+
+      {:ok, iter} = NIF.prefix_iterator(db, :gspo, <<10::64>>)
+      {:ok, entries} = NIF.iterator_collect(iter)
+
+      assert Enum.map(entries, &elem(&1, 0)) == [
+               <<10::64, 1::64, 2::64, 3::64>>,
+               <<10::64, 4::64, 5::64, 6::64>>
+             ]
+
+      {:ok, iter} = NIF.prefix_iterator(db, :gpos, <<11::64>>)
+      assert {:ok, [{<<11::64, 2::64, 3::64, 1::64>>, <<>>}]} = NIF.iterator_collect(iter)
+    end
+
+    test "returns error for closed database", %{db_path: path} do
+      {:ok, db2} = NIF.open("#{path}_closed")
+      NIF.close(db2)
+
+      assert {:error, :already_closed} = NIF.put_quad(db2, "a", "b", "c")
+    end
+  end
 end
@@ -169,6 +169,25 @@ defmodule TripleStore.Reasoner.DerivedStoreTest do
     end
   end
 
+  describe "on a versioned database" do
+    test "count, clear_all and full lookup ignore the schema version", %{db_path: db_path} do
+      path = "#{db_path}_versioned"
+      on_exit(fn -> File.rm_rf!(path) end)
+
+      {:ok, db} = NIF.open_versioned(path, 1)
+      :ok = DerivedStore.insert_derived(db, [{1, 2, 3}, {4, 5, 6}])
+
+      assert {:ok, 2} = DerivedStore.count(db)
+      assert {:ok, stream} = DerivedStore.lookup_derived(db, {:var, :var, :var})
+      assert Enum.sort(Enum.to_list(stream)) == [{1, 2, 3}, {4, 5, 6}]
+      assert {:ok, 2} = DerivedStore.clear_all(db)
+      NIF.close(db)
+
+      # clear_all left the version in place
+      assert {:error, {:schema_mismatch, 1, 2}} = NIF.open_versioned(path, 2)
+    end
+  end
+
   # ============================================================================
   # Query Operations Tests
   # ============================================================================
@@ -0,0 +1,1475 @@
+defmodule TripleStore.SPARQL.Parser.NIFTest do
+  @moduledoc """
+  Tests for specialised SPARQL parser NIF entry points.
+  """
+
+  use ExUnit.Case, async: true
+
+  alias TripleStore.SPARQL.Parser.NIF
+
+  describe "parse_ask/1" do
+    test "returns pattern and dataset without the query wrapper" do
+      assert {:ok, {pattern, nil}} = NIF.parse_ask("ASK { ?s ?p ?o }")
+      assert {:bgp, [_triple]} = pattern
+    end
+
+    test "includes the dataset when FROM is present" do
+      assert {:ok, {_pattern, dataset}} =
+               NIF.parse_ask("ASK FROM <http://example.org/g> { ?s ?p ?o }")
+
+      refute is_nil(dataset)
+    end
+
+    test "rejects other query forms" do
+      assert {:error, {:wrong_operation, :select}} =
+               NIF.parse_ask("SELECT * WHERE { ?s ?p ?o }")
+
+      assert {:error, {:wrong_operation, :construct}} =
+               NIF.parse_ask("CONSTRUCT { ?s ?p ?o } WHERE { ?s ?p ?o }")
+
+      assert {:error, {:wrong_operation, :describe}} = NIF.parse_ask("DESCRIBE ?s WHERE { ?s ?p ?o }")
+    end
+
+    test "returns parse errors unchanged" do
+      assert {:error, {:parse_error, _}} = NIF.parse_ask("ASK {")
+    end
+  end
+
+  describe "query_form/1" do
+    test "detects each query form" do
+      assert {:ok, :select} = NIF.query_form("SELECT * WHERE { ?s ?p ?o }")
+      assert {:ok, :construct} = NIF.query_form("CONSTRUCT WHERE { ?s ?p ?o }")
+      assert {:ok, :ask} = NIF.query_form("ASK { ?s ?p ?o }")
+      assert {:ok, :describe} = NIF.query_form("DESCRIBE <http://example.org/x>")
+    end
+
+    test "detects updates" do
+      assert {:ok, :update} =
+               NIF.query_form("INSERT DATA { <http://example.org/s> <http://example.org/p> 1 }")
+
+      assert {:ok, :update} = NIF.query_form("CLEAR ALL")
+    end
+
+    test "reports the error from the grammar that got further" do
+      assert {:error, {:parse_error, message}} = NIF.query_form("SELECT * WHERE {")
+      assert message =~ "error at 1:17"
+
+      assert {:error, {:parse_error, message}} =
+               NIF.query_form("INSERT DATA { <http://a> <http://b> }")
+
+      assert message =~ "error at 1:37"
+    end
+  end
+
+  describe "parse_query_profiled/1" do
+    test "returns the same AST as parse_query/1 plus stats" do
+      query = "SELECT ?s WHERE { ?s ?p ?o FILTER(?o > 1) }"
+
+      assert {:ok, ast} = NIF.parse_query(query)
+      assert {:ok, ^ast, stats} = NIF.parse_query_profiled(query)
+
+      assert %{parse_micros: parse, encode_micros: encode, node_count: nodes} = stats
+      assert is_integer(parse) and parse >= 0
+      assert is_integer(encode) and encode >= 0
+      assert nodes > 0
+    end
+
+    test "node count grows with query size" do
+      {:ok, _, small} = NIF.parse_query_profiled("SELECT * WHERE { ?s ?p ?o }")
+
+      {:ok, _, large} =
+        NIF.parse_query_profiled("SELECT * WHERE { ?s ?p ?o . ?o ?q ?r . ?r ?t ?u }")
+
+      assert large.node_count > small.node_count
+    end
+
+    test "node count does not accumulate across calls" do
+      query = "SELECT * WHERE { ?s ?p ?o }"
+      {:ok, _, first} = NIF.parse_query_profiled(query)
+      {:ok, _, second} = NIF.parse_query_profiled(query)
+
+      assert first.node_count == second.node_count
+    end
+
+    test "returns parse errors" do
+      assert {:error, {:parse_error, _}} = NIF.parse_query_profiled("SELECT WHERE")
+    end
+  end
+
+  describe "parse_query_limited/2" do
+    test "parses queries within limits" do
+      query = "SELECT * WHERE { ?s ?p ?o }"
+
+      assert {:ok, ast} = NIF.parse_query(query)
+      assert {:ok, ^ast} = NIF.parse_query_limited(query, %{max_bytes: 1000, max_depth: 10})
+      assert {:ok, ^ast} = NIF.parse_query_limited(query, %{})
+    end
+
+    test "rejects oversized input before parsing" do
+      assert {:error, {:limit_exceeded, :size}} =
+               NIF.parse_query_limited("SELECT * WHERE { ?s ?p ?o }", %{max_bytes: 10})
+
+      # Size is checked first, so even invalid input reports the size limit
+      assert {:error, {:limit_exceeded, :size}} =
+               NIF.parse_query_limited(String.duplicate("x", 100), %{max_bytes: 10})
+    end
+
+    test "aborts on a pathologically nested UNION chain" do
+      branches = Enum.map_join(1..200, " UNION ", fn i -> "{ ?s ?p #{i} }" end)
+      query = "SELECT * WHERE { #{branches} }"
+
+      assert {:error, {:limit_exceeded, :depth}} =
+               NIF.parse_query_limited(query, %{max_depth: 50})
+
+      assert {:ok, _ast} = NIF.parse_query_limited(query, %{max_depth: 1000})
+    end
+
+    test "rejects deeply nested input before parsing" do
+      nested = String.duplicate("(", 100_000) <> "1" <> String.duplicate(")", 100_000)
+      query = "SELECT * WHERE { ?s ?p ?o FILTER #{nested} }"
+
+      assert {:error, {:limit_exceeded, :depth}} =
+               NIF.parse_query_limited(query, %{max_depth: 64})
+
+      # Brackets inside strings, IRIs and comments don't count
+      query = """
+      SELECT * WHERE {
+        ?s <http://example.org/p(((x)))> "(((( [[[[" # {{{{
+      }
+      """
+
+      assert {:ok, _ast} = NIF.parse_query_limited(query, %{max_depth: 3})
+    end
+
+    test "limit does not leak into later unlimited parses" do
+      query = "SELECT * WHERE { { ?s ?p 1 } UNION { ?s ?p 2 } }"
+
+      assert {:error, {:limit_exceeded, :depth}} =
+               NIF.parse_query_limited(query, %{max_depth: 1})
+
+      assert {:ok, _} = NIF.parse_query(query)
+    end
+  end
+
+  describe "parse_query_no_service/1" do
+    test "returns the normal AST for queries without SERVICE" do
+      query = "SELECT * WHERE { ?s ?p ?o OPTIONAL { ?s ?q ?x } }"
+
+      assert {:ok, ast} = NIF.parse_query(query)
+      assert {:ok, ^ast} = NIF.parse_query_no_service(query)
+    end
+
+    test "rejects a SERVICE clause" do
+      assert {:error, :service_not_allowed} =
+               NIF.parse_query_no_service(
+                 "SELECT * WHERE { SERVICE <http://remote.example/sparql> { ?s ?p ?o } }"
+               )
+
+      assert {:error, :service_not_allowed} =
+               NIF.parse_query_no_service(
+                 "SELECT * WHERE { ?s ?p ?o OPTIONAL { SERVICE SILENT <http://remote.example/sparql> { ?s ?q ?x } } }"
+               )
+    end
+
+    test "rejects SERVICE hidden in a FILTER EXISTS" do
+      query = """
+      SELECT * WHERE {
+        ?s ?p ?o
+        FILTER EXISTS { SERVICE <http://remote.example/sparql> { ?s ?q ?x } }
+      }
+      """
+
+      assert {:error, :service_not_allowed} = NIF.parse_query_no_service(query)
+    end
+
+    test "reports parse errors" do
+      assert {:error, {:parse_error, _}} = NIF.parse_query_no_service("SELECT WHERE")
+    end
+  end
+
+  describe "parse_query_strict/1" do
+    test "returns the normal AST for well-formed queries" do
+      query = ~s(SELECT * WHERE { ?s <http://example.org/p> "x" })
+
+      assert {:ok, ast} = NIF.parse_query(query)
+      assert {:ok, ^ast} = NIF.parse_query_strict(query)
+    end
+
+    test "rejects a literal subject in a triple pattern" do
+      assert {:error, {:term_position, {:literal_subject, {:literal, :simple, "x"}}}} =
+               NIF.parse_query_strict(~s(SELECT * WHERE { "x" ?p ?o }))
+    end
+
+    test "rejects a literal subject nested in OPTIONAL and EXISTS" do
+      assert {:error, {:term_position, {:literal_subject, {:literal, :simple, "y"}}}} =
+               NIF.parse_query_strict(~s(SELECT * WHERE { ?s ?p ?o OPTIONAL { "y" ?p ?x } }))
+
+      assert {:error, {:term_position, {:literal_subject, {:literal, :simple, "z"}}}} =
+               NIF.parse_query_strict(~s(SELECT * WHERE { ?s ?p ?o FILTER EXISTS { "z" ?p ?o } }))
+    end
+
+    test "rejects a literal subject of a property path" do
+      assert {:error,
+              {:term_position,
+               {:literal_subject, {:literal, :typed, "1", "http://www.w3.org/2001/XMLSchema#integer"}}}} =
+               NIF.parse_query_strict("SELECT * WHERE { 1 <http://example.org/p>+ ?o }")
+    end
+
+    test "rejects a literal subject in a CONSTRUCT template" do
+      assert {:error, {:term_position, {:literal_template_subject, {:literal, :simple, "x"}}}} =
+               NIF.parse_query_strict(~s(CONSTRUCT { "x" ?p ?o } WHERE { ?s ?p ?o }))
+    end
+
+    test "literal predicates are already rejected by the parser" do
+      assert {:error, {:parse_error, _}} =
+               NIF.parse_query_strict(~s(SELECT * WHERE { ?s "x" ?o }))
+    end
+  end
+
+  describe "optional_dependencies/1" do
+    test "returns an empty list without OPTIONAL" do
+      assert {:ok, []} = NIF.optional_dependencies("SELECT * WHERE { ?s ?p ?o }")
+    end
+
+    test "handles nested OPTIONAL blocks" do
+      query = """
+      SELECT * WHERE {
+        ?s a ?t .
+        OPTIONAL {
+          ?s ?p ?o .
+          OPTIONAL { ?o ?q ?r }
+        }
+      }
+      """
+
+      assert {:ok, [outer, inner]} = NIF.optional_dependencies(query)
+      assert outer == {["s", "t"], ["o", "p", "q", "r", "s"], ["s"]}
+      assert inner == {["o", "p", "s"], ["o", "q", "r"], ["o"]}
+    end
+
+    test "includes variables from a FILTER inside OPTIONAL" do
+      query = "SELECT * WHERE { ?s ?p ?o OPTIONAL { ?s ?q ?x FILTER(?x > ?o) } }"
+
+      assert {:ok, [{required, optional, shared}]} = NIF.optional_dependencies(query)
+      assert required == ["o", "p", "s"]
+      assert optional == ["o", "q", "s", "x"]
+      assert shared == ["o", "s"]
+    end
+
+    test "returns parse errors" do
+      assert {:error, {:parse_error, _}} = NIF.optional_dependencies("SELECT * WHERE {")
+    end
+  end
+
+  describe "query_variables/1" do
+    test "separates projected variables from all variables" do
+      assert {:ok, %{projected: ["name"], all: ["name", "p", "person"]}} =
+               NIF.query_variables("SELECT ?name WHERE { ?person ?p ?name }")
+    end
+
+    test "collects variables from expressions, EXISTS and subqueries" do
+      query = """
+      SELECT ?s ?label WHERE {
+        ?s <http://example.org/p> ?o .
+        OPTIONAL { ?s <http://example.org/label> ?l FILTER(LANG(?l) = ?lang) }
+        BIND(COALESCE(?l, "none") AS ?label)
+        FILTER NOT EXISTS { ?s a ?type }
+        { SELECT ?s WHERE { ?s <http://example.org/q> ?inner } }
+        VALUES ?lang { "en" }
+      }
+      ORDER BY ?rank
+      """
+
+      assert {:ok, %{projected: ["s", "label"], all: all}} = NIF.query_variables(query)
+      assert all == ["inner", "l", "label", "lang", "o", "rank", "s", "type"]
+    end
+
+    test "skips blank nodes and generated aggregate variables" do
+      query = "SELECT ?s (COUNT(?o) AS ?n) WHERE { ?s ?p ?o . _:b ?p [] } GROUP BY ?s"
+
+      assert {:ok, %{projected: ["s", "n"], all: ["n", "o", "p", "s"]}} =
+               NIF.query_variables(query)
+
+      assert {:ok, %{projected: [], all: []}} = NIF.query_variables("DESCRIBE <http://example.org/x>")
+    end
+
+    test "includes CONSTRUCT template variables and projects nothing" do
+      assert {:ok, %{projected: [], all: ["o", "p", "s", "x"]}} =
+               NIF.query_variables("CONSTRUCT { ?s ?p ?x } WHERE { ?s ?p ?o }")
+    end
+
+    test "returns parse errors" do
+      assert {:error, {:parse_error, _}} = NIF.query_variables("SELECT WHERE")
+    end
+  end
+
+  describe "query_iris/1" do
+    @ex "http://example.org/"
+    @xsd "http://www.w3.org/2001/XMLSchema#"
+
+    test "lists IRIs once in the order they are written" do
+      query = """
+      PREFIX ex: <http://example.org/>
+      SELECT ?s FROM ex:g FROM NAMED ex:h WHERE {
+        ?s ex:p ex:o ; ex:p/ex:q ?o .
+        GRAPH ex:h { ?s ex:p "1"^^ex:dt }
+        FILTER(ex:fn(?o) && ?o != "x" && ?o != "y"@en && ?o > 1)
+      }
+      ORDER BY ex:rank(?s)
+      """
+
+      assert {:ok, iris} = NIF.query_iris(query)
+
+      assert iris ==
+               Enum.map(~w(g h p o q dt fn), &(@ex <> &1)) ++
+                 [@xsd <> "integer", @ex <> "rank"]
+    end
+
+    test "includes CONSTRUCT templates, VALUES and EXISTS patterns" do
+      query = """
+      CONSTRUCT { ?s <http://example.org/t> ?o } WHERE {
+        VALUES ?o { <http://example.org/v> UNDEF }
+        FILTER NOT EXISTS { ?s <http://example.org/e> ?o }
+      }
+      """
+
+      assert {:ok, [@ex <> "t", @ex <> "v", @ex <> "e"]} = NIF.query_iris(query)
+    end
+
+    test "returns parse errors" do
+      assert {:error, {:parse_error, _}} = NIF.query_iris("SELECT * WHERE {")
+    end
+  end
+
+  describe "query_having/1" do
+    test "tags a filter over a group as HAVING" do
+      query = "SELECT ?s WHERE { ?s ?p ?o } GROUP BY ?s HAVING (?s != <http://ex.org/a>) ORDER BY ?s"
+
+      assert {:ok, [{:having, expr, group}]} = NIF.query_having(query)
+      assert expr == {:not, {:equal, {:variable, "s"}, {:named_node, "http://ex.org/a"}}}
+      assert {:group, {:bgp, [_]}, [{:variable, "s"}], []} = group
+
+      # The full AST keeps the original filter-over-group nesting
+      assert {:ok, {:select, [{"pattern", pattern} | _]}} = NIF.parse_query(query)
+      assert {:project, {:order_by, {:filter, ^expr, ^group}, _}, _} = pattern
+    end
+
+    test "resolves aggregates through the group's generated variables" do
+      query = "SELECT (COUNT(*) AS ?c) WHERE { ?s ?p ?o } HAVING (COUNT(*) > 1)"
+
+      assert {:ok, [{:having, {:greater, {:variable, agg}, _}, group}]} = NIF.query_having(query)
+      assert {:group, _, [], [{{:variable, ^agg}, {:count_solutions, false}}]} = group
+    end
+
+    test "lists subquery clauses after the outer query's" do
+      query = """
+      SELECT ?s WHERE {
+        { SELECT ?s WHERE { ?s ?p ?o } GROUP BY ?s HAVING (COUNT(?o) > 2) }
+      }
+      GROUP BY ?s
+      HAVING (BOUND(?s))
+      """
+
+      assert {:ok, [{:having, {:bound, _}, _}, {:having, {:greater, _, _}, _}]} =
+               NIF.query_having(query)
+    end
+
+    test "ignores queries without HAVING" do
+      assert {:ok, []} = NIF.query_having("SELECT ?s WHERE { ?s ?p ?o FILTER(?o > 1) }")
+      assert {:ok, []} = NIF.query_having("SELECT ?s WHERE { ?s ?p ?o } GROUP BY ?s")
+    end
+
+    test "returns parse errors" do
+      assert {:error, {:parse_error, _}} = NIF.query_having("SELECT * WHERE {")
+    end
+  end
+
+  describe "query_triple_patterns/1" do
+    defp flagged(sparql) do
+      assert {:ok, triples} = NIF.query_triple_patterns(sparql)
+
+      for {{_, {:named_node, "http://ex.org/" <> p}, _}, flags} <- triples,
+          do: {p, flags.in_optional, flags.in_union}
+    end
+
+    test "flattens BGPs with optional and union markers" do
+      sparql = """
+      PREFIX ex: <http://ex.org/>
+      SELECT * WHERE {
+        ?s ex:a ?o .
+        OPTIONAL { ?o ex:b ?x { ?x ex:c ?y } UNION { ?x ex:d ?y } }
+        GRAPH ?g { { ?s ex:e ?z } UNION { ?s ex:f ?z } }
+        MINUS { ?s ex:g ?z }
+      }
+      """
+
+      assert flagged(sparql) == [
+               {"a", false, false},
+               {"b", true, false},
+               {"c", true, true},
+               {"d", true, true},
+               {"e", false, true},
+               {"f", false, true},
+               {"g", false, false}
+             ]
+    end
+
+    test "includes subquery triples but not property paths" do
+      sparql = """
+      SELECT * WHERE {
+        { SELECT ?s WHERE { ?s <http://ex.org/a> "x" } LIMIT 1 }
+        ?s <http://ex.org/b>+ ?o
+      }
+      """
+
+      assert {:ok, [{{{:variable, "s"}, {:named_node, "http://ex.org/a"}, {:literal, :simple, "x"}}, _}]} =
+               NIF.query_triple_patterns(sparql)
+    end
+
+    test "lines up with analyze_query triple patterns" do
+      sparql = "SELECT * WHERE { ?s ?p ?o OPTIONAL { ?o ?q ?r } FILTER EXISTS { ?s ?x ?y } }"
+
+      assert {:ok, %{triple_patterns: patterns}} = NIF.analyze_query(sparql)
+      assert {:ok, triples} = NIF.query_triple_patterns(sparql)
+
+      assert Enum.map(triples, fn {{s, p, o}, _} -> {:triple, s, p, o} end) == patterns
+    end
+
+    test "returns parse errors" do
+      assert {:error, {:parse_error, _}} = NIF.query_triple_patterns("SELECT * WHERE {")
+    end
+  end
+
+  describe "validate_functions/2" do
+    test "allows listed custom functions" do
+      sparql = "SELECT * WHERE { ?s ?p ?o FILTER(<http://ex.org/f>(?o)) }"
+
+      assert NIF.validate_functions(sparql, ["http://ex.org/f"]) == :ok
+    end
+
+    test "rejects the first unlisted custom function" do
+      sparql = """
+      SELECT * WHERE {
+        ?s ?p ?o
+        BIND(<http://ex.org/g>(?o) AS ?x)
+        FILTER(<http://ex.org/f>(<http://ex.org/h>(?x)))
+      }
+      """
+
+      assert NIF.validate_functions(sparql, ["http://ex.org/f", "http://ex.org/g"]) ==
+               {:error, {:forbidden_function, "http://ex.org/h"}}
+    end
+
+    test "checks EXISTS patterns and subqueries" do
+      exists = "SELECT * WHERE { ?s ?p ?o FILTER NOT EXISTS { ?o ?q ?r FILTER(<http://ex.org/g>(?r)) } }"
+      subquery = "SELECT * WHERE { { SELECT ?s WHERE { ?s ?p ?o } ORDER BY <http://ex.org/g>(?o) } }"
+
+      assert NIF.validate_functions(exists, []) == {:error, {:forbidden_function, "http://ex.org/g"}}
+      assert NIF.validate_functions(subquery, []) == {:error, {:forbidden_function, "http://ex.org/g"}}
+    end
+
+    test "always allows built-in functions" do
+      sparql = """
+      SELECT ?s (COUNT(?o) AS ?n) WHERE {
+        ?s ?p ?o FILTER(REGEX(STR(?o), "^a") && BOUND(?s))
+      } GROUP BY ?s
+      """
+
+      assert NIF.validate_functions(sparql, []) == :ok
+    end
+
+    test "treats XSD casts as built-in functions" do
+      for cast <- ~w(boolean integer decimal float double string dateTime) do
+        sparql = """
+        PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+        SELECT * WHERE { ?s ?p ?o FILTER(xsd:#{cast}(?o) = ?o) }
+        """
+
+        assert NIF.validate_functions(sparql, []) == :ok
+      end
+    end
+
+    test "still checks other XSD datatype IRIs called as functions" do
+      sparql = """
+      PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+      SELECT * WHERE { ?s ?p ?o FILTER(xsd:long(?o) > 1) }
+      """
+
+      assert NIF.validate_functions(sparql, []) ==
+               {:error, {:forbidden_function, "http://www.w3.org/2001/XMLSchema#long"}}
+    end
+
+    test "returns error for invalid SPARQL" do
+      assert {:error, {:parse_error, _}} = NIF.validate_functions("SELECT WHERE {", [])
+    end
+  end
+
+  describe "property_path_plan/1" do
+    @a {:named_node, "http://ex.org/a"}
+    @b {:named_node, "http://ex.org/b"}
+    @c {:named_node, "http://ex.org/c"}
+
+    defp path_hints(path) do
+      query = "PREFIX ex: <http://ex.org/> SELECT * WHERE { ?s #{path} ?o }"
+      assert {:ok, plans} = NIF.property_path_plan(query)
+      Enum.map(plans, fn {{:variable, "s"}, hint, {:variable, "o"}} -> hint end)
+    end
+
+    test "encodes both closure operators" do
+      assert path_hints("ex:a*") == [{:transitive, {:fixed, @a}, :star}]
+      assert path_hints("ex:a+") == [{:transitive, {:fixed, @a}, :plus}]
+      assert path_hints("ex:a?") == [{:optional, {:fixed, @a}}]
+    end
+
+    test "flattens alternatives" do
+      assert path_hints("ex:a|ex:b|ex:c") == [{:alt, [{:fixed, @a}, {:fixed, @b}, {:fixed, @c}]}]
+    end
+
+    test "flattens sequences under a closure" do
+      assert path_hints("(ex:a/ex:b/ex:c)+") ==
+               [{:transitive, {:seq, [{:fixed, @a}, {:fixed, @b}, {:fixed, @c}]}, :plus}]
+    end
+
+    test "encodes inverse and negated paths" do
+      assert path_hints("^ex:a|ex:b") == [{:alt, [{:inverse, {:fixed, @a}}, {:fixed, @b}]}]
+      assert path_hints("!(ex:a|ex:b)") == [{:negated, [@a, @b]}]
+    end
+
+    test "ignores paths the parser rewrites into triple patterns" do
+      assert path_hints("ex:a/ex:b") == []
+      assert path_hints("^ex:a") == []
+    end
+
+    test "returns parse errors" do
+      assert {:error, {:parse_error, _}} = NIF.property_path_plan("SELECT * WHERE {")
+    end
+  end
+
+  describe "query_hash/1" do
+    defp hash!(query) do
+      assert {:ok, <<_::64>> = hash} = NIF.query_hash(query)
+      hash
+    end
+
+    test "ignores variable names and whitespace" do
+      assert hash!("SELECT ?s WHERE { ?s ?p ?o }") ==
+               hash!("SELECT  ?x\nWHERE {?x ?y ?z}")
+    end
+
+    test "ignores prefixes and blank node labels" do
+      assert hash!("PREFIX ex: <http://ex.org/> SELECT * WHERE { ?s ex:p [ ex:q ?o ] }") ==
+               hash!("SELECT * WHERE { ?a <http://ex.org/p> _:n . _:n <http://ex.org/q> ?b }")
+    end
+
+    test "is stable for aggregates" do
+      assert hash!("SELECT (COUNT(?s) AS ?n) WHERE { ?s ?p ?o } GROUP BY ?p") ==
+               hash!("SELECT (COUNT(?a) AS ?c) WHERE { ?a ?b ?d } GROUP BY ?b")
+    end
+
+    test "distinguishes structurally different queries" do
+      base = hash!("SELECT ?s WHERE { ?s ?p ?o }")
+
+      assert base != hash!("SELECT ?o WHERE { ?s ?p ?o }")
+      assert base != hash!("SELECT ?s WHERE { ?s ?p ?s }")
+      assert base != hash!("SELECT ?s WHERE { ?s <http://ex.org/p> ?o }")
+      assert base != hash!("SELECT DISTINCT ?s WHERE { ?s ?p ?o }")
+      assert hash!(~s(SELECT * WHERE { ?s ?p "?x" })) != hash!(~s(SELECT * WHERE { ?s ?p "?y" }))
+    end
+
+    test "returns parse errors" do
+      assert {:error, {:parse_error, _}} = NIF.query_hash("SELECT * WHERE {")
+    end
+  end
+
+  describe "query_signature/1" do
+    defp signature!(query) do
+      assert {:ok, <<_::256>> = signature} = NIF.query_signature(query)
+      signature
+    end
+
+    test "ignores variable names, whitespace and triple order" do
+      assert signature!("SELECT ?s WHERE { ?s <http://p> ?o . ?o <http://q> ?x }") ==
+               signature!("SELECT ?a WHERE {\n  ?b <http://q> ?c .\n  ?a <http://p> ?b\n}")
+    end
+
+    test "sorts and re-associates joins and unions" do
+      assert signature!("""
+             SELECT ?s WHERE { { ?s <http://p> ?o OPTIONAL { ?o <http://q> ?z } } { ?s <http://r> ?w } }
+             """) ==
+               signature!("""
+               SELECT ?a WHERE { { ?a <http://r> ?b } { ?a <http://p> ?c OPTIONAL { ?c <http://q> ?d } } }
+               """)
+
+      assert signature!("""
+             SELECT * WHERE { { ?s <http://p> ?o } UNION { ?s <http://q> ?o } UNION { ?s <http://r> ?o } }
+             """) ==
+               signature!("""
+               SELECT * WHERE { { ?s <http://r> ?o } UNION { { ?s <http://p> ?o } UNION { ?s <http://q> ?o } } }
+               """)
+    end
+
+    test "sorts commutative expression operands" do
+      assert signature!("SELECT ?s WHERE { ?s <http://p> ?o FILTER(?o = 1 && ?s != <http://a> || BOUND(?o)) }") ==
+               signature!("SELECT ?x WHERE { ?x <http://p> ?y FILTER(BOUND(?y) || <http://a> != ?x && 1 = ?y) }")
+
+      assert signature!("SELECT ?s WHERE { ?s <http://p> ?o FILTER(?o + 1 = 2 * ?o) }") ==
+               signature!("SELECT ?s WHERE { ?s <http://p> ?o FILTER(?o * 2 = 1 + ?o) }")
+
+      assert signature!("SELECT ?s WHERE { ?s <http://p> ?o FILTER(?o IN (1, 2, 3)) }") ==
+               signature!("SELECT ?s WHERE { ?s <http://p> ?o FILTER(?o IN (3, 1, 2)) }")
+    end
+
+    test "keeps non-commutative operand order" do
+      assert signature!("SELECT ?s WHERE { ?s <http://p> ?o OPTIONAL { ?o <http://q> ?z } }") !=
+               signature!("SELECT ?s WHERE { ?o <http://q> ?z OPTIONAL { ?s <http://p> ?o } }")
+
+      assert signature!("SELECT ?s WHERE { ?s <http://p> ?o FILTER(?o > 1) }") !=
+               signature!("SELECT ?s WHERE { ?s <http://p> ?o FILTER(1 > ?o) }")
+
+      assert signature!("SELECT ?s WHERE { ?s ?p ?o }") != signature!("SELECT ?o WHERE { ?s ?p ?o }")
+    end
+
+    test "returns parse errors" do
+      assert {:error, {:parse_error, _}} = NIF.query_signature("SELECT * WHERE {")
+    end
+  end
+
+  describe "analyze_query/1" do
+    test "summarizes a SELECT query" do
+      query = """
+      SELECT DISTINCT ?s ?name WHERE {
+        ?s <http://ex.org/name> ?name .
+        OPTIONAL { ?s <http://ex.org/age> ?age }
+      }
+      ORDER BY ?name LIMIT 10 OFFSET 5
+      """
+
+      assert {:ok, analysis} = NIF.analyze_query(query)
+      assert analysis.form == :select
+      assert analysis.variables == ["age", "name", "s"]
+      assert analysis.projected == ["s", "name"]
+      assert length(analysis.triple_patterns) == 2
+      assert analysis.named_graphs == []
+      assert analysis.warnings == []
+
+      assert %{distinct: true, reduced: false, limit: 10, offset: 5, order_by: [{:asc, _}]} =
+               analysis.modifiers
+    end
+
+    test "matches the standalone analysis functions" do
+      query = "SELECT * WHERE { ?s <http://ex.org/p>+ ?o OPTIONAL { ?o <http://ex.org/q> ?x } }"
+
+      assert {:ok, analysis} = NIF.analyze_query(query)
+      assert {:ok, analysis.optional_dependencies} == NIF.optional_dependencies(query)
+      assert {:ok, analysis.property_paths} == NIF.property_path_plan(query)
+      assert {:ok, analysis.hash} == NIF.query_hash(query)
+    end
+
+    test "collects named graphs" do
+      query = """
+      SELECT ?s FROM NAMED <http://ex.org/g1>
+      WHERE { GRAPH <http://ex.org/g2> { ?s ?p ?o } }
+      """
+
+      assert {:ok, %{named_graphs: ["http://ex.org/g1", "http://ex.org/g2"]}} =
+               NIF.analyze_query(query)
+    end
+
+    test "warns about unbound projections and cartesian products" do
+      query = "SELECT ?s ?missing WHERE { ?s ?p ?o . ?a ?b ?c }"
+
+      assert {:ok, %{warnings: warnings}} = NIF.analyze_query(query)
+      assert {:unbound_projection, "missing"} in warnings
+      assert [{:cartesian_product, [_, _]}] = Enum.filter(warnings, &match?({:cartesian_product, _}, &1))
+    end
+
+    test "returns parse errors" do
+      assert {:error, {:parse_error, _}} = NIF.analyze_query("SELECT * WHERE {")
+    end
+  end
+
+  describe "join_graph/1" do
+    test "connects a chain of three patterns through shared variables" do
+      query = """
+      SELECT * WHERE {
+        ?a <http://example.org/knows> ?b .
+        ?b <http://example.org/knows> ?c .
+        ?c <http://example.org/name> ?name
+      }
+      """
+
+      assert {:ok, %{patterns: patterns, edges: edges}} = NIF.join_graph(query)
+      assert length(patterns) == 3
+      assert edges == [{0, 1, ["b"]}, {1, 2, ["c"]}]
+    end
+
+    test "lists every shared variable and omits unrelated pairs" do
+      query = """
+      SELECT * WHERE {
+        ?s ?p ?o .
+        ?s ?p ?x .
+        ?y <http://example.org/q> "z"
+      }
+      """
+
+      assert {:ok, %{edges: [{0, 1, ["p", "s"]}]}} = NIF.join_graph(query)
+    end
+
+    test "indices match analyze_query triple patterns" do
+      query = "SELECT * WHERE { ?s ?p ?o OPTIONAL { ?o ?q ?x } }"
+
+      assert {:ok, %{patterns: patterns, edges: [{0, 1, ["o"]}]}} = NIF.join_graph(query)
+      assert {:ok, %{triple_patterns: ^patterns}} = NIF.analyze_query(query)
+    end
+
+    test "reports parse errors" do
+      assert {:error, {:parse_error, _}} = NIF.join_graph("SELECT WHERE")
+    end
+  end
+
+  describe "serialize_expression/1" do
+    defp filter_expr(sparql) do
+      {:ok, {:select, props}} = NIF.parse_query("SELECT * WHERE { ?s ?p ?o FILTER(#{sparql}) }")
+      {"pattern", {:project, {:filter, expr, _}, _}} = List.keyfind(props, "pattern", 0)
+      expr
+    end
+
+    test "emits minimal parentheses for mixed precedence" do
+      cases = [
+        {"?a + ?b * ?c", "?a + ?b * ?c"},
+        {"(?a + ?b) * ?c", "(?a + ?b) * ?c"},
+        {"?a - ?b - ?c", "?a - ?b - ?c"},
+        {"?a - (?b - ?c)", "?a - (?b - ?c)"},
+        {"?a / (?b * ?c)", "?a / (?b * ?c)"},
+        {"?a || ?b && ?c", "?a || ?b && ?c"},
+        {"(?a || ?b) && ?c", "(?a || ?b) && ?c"},
+        {"?a + 1 > ?b * 2 && !?c", "?a + \"1\"^^<http://www.w3.org/2001/XMLSchema#integer> > ?b * \"2\"^^<http://www.w3.org/2001/XMLSchema#integer> && !?c"},
+        {"-(?a + ?b)", "-(?a + ?b)"},
+        {"?a != ?b", "!(?a = ?b)"}
+      ]
+
+      for {input, expected} <- cases do
+        assert {:ok, ^expected} = NIF.serialize_expression(filter_expr(input))
+      end
+    end
+
+    test "round-trips nested arithmetic and boolean expressions" do
+      expressions = [
+        "((?a + ?b) * (?c - ?d)) / -?e",
+        "?a * (?b / ?c) - (?d - (?e + ?f))",
+        "!(?a && ?b) || (?c || ?d) && ?e",
+        "(?a = ?b) = (?c < ?d)",
+        "?x NOT IN (1, 2 + 3, ?y * 4) && BOUND(?z)",
+        "IF(?a > 1, COALESCE(?b, ?c), STRLEN(STR(?d)) + 1) <= 10",
+        ~s(REGEX(?s, "^a\\"b", "i") || sameTerm(?s, <http://example.org/x>)),
+        ~s(LANG(?l) = "en" && <http://example.org/fn>(?a, "x"@en))
+      ]
+
+      for sparql <- expressions do
+        expr = filter_expr(sparql)
+        assert {:ok, serialized} = NIF.serialize_expression(expr)
+        assert filter_expr(serialized) == expr, "round trip changed #{sparql} -> #{serialized}"
+      end
+    end
+
+    test "rejects EXISTS and malformed terms" do
+      exists = filter_expr("EXISTS { ?s ?q ?x }")
+      assert {:error, {:invalid_expression, ^exists}} = NIF.serialize_expression(exists)
+
+      assert {:error, {:invalid_expression, {:bogus, 1}}} =
+               NIF.serialize_expression({:add, {:variable, "a"}, {:bogus, 1}})
+    end
+  end
+
+  describe "parse_query/2 and parse_update/2" do
+    @base "http://example.org/"
+
+    test "resolve relative IRIs against the base IRI" do
+      assert {:ok, ast} = NIF.parse_query("SELECT ?s WHERE { ?s <p> ?o }", @base)
+      assert inspect(ast) =~ ~s({:named_node, "http://example.org/p"})
+
+      assert {:ok, ast} = NIF.parse_update("INSERT DATA { <s> <p> <o> }", @base)
+      assert inspect(ast) =~ ~s({:named_node, "http://example.org/o"})
+    end
+
+    test "relative IRIs do not parse without a base" do
+      assert {:error, {:parse_error, _, _}} = NIF.parse_query("SELECT ?s WHERE { ?s <p> ?o }")
+    end
+
+    test "reject an invalid base IRI" do
+      assert {:error, {:parse_error, "invalid base IRI"}} =
+               NIF.parse_query("SELECT * WHERE { ?s ?p ?o }", "not an iri")
+
+      assert {:error, {:parse_error, "invalid base IRI"}} =
+               NIF.parse_update("CLEAR ALL", "relative/path")
+    end
+  end
+
+  describe "parse_query/3 with decode_literals" do
+    @xsd "http://www.w3.org/2001/XMLSchema#"
+
+    defp values_literals(sparql, opts) do
+      assert {:ok, {:select, [{"pattern", {:project, {:values, _, rows}, _}} | _]}} =
+               NIF.parse_query(sparql, nil, opts)
+
+      List.flatten(rows)
+    end
+
+    test "decodes supported datatypes to native values" do
+      sparql = ~s(SELECT * WHERE { VALUES ?v { 42 1.5 1e3 true "2024-01-02T03:04:05Z"^^<#{@xsd}dateTime> } })
+
+      assert [
+               {:literal, :typed_value, 42, @xsd <> "integer"},
+               {:literal, :typed_value, 1.5, @xsd <> "decimal"},
+               {:literal, :typed_value, 1.0e3, @xsd <> "double"},
+               {:literal, :typed_value, true, @xsd <> "boolean"},
+               {:literal, :typed_value, {:datetime, {{2024, 1, 2}, {3, 4, 5}}, 0, 0},
+                @xsd <> "dateTime"}
+             ] = values_literals(sparql, decode_literals: true)
+    end
+
+    test "keeps the string encoding for invalid and unsupported literals" do
+      sparql =
+        ~s(SELECT * WHERE { VALUES ?v { "abc"^^<#{@xsd}integer> "x" "x"@en "x"^^<http://ex.org/t> } })
+
+      assert [
+               {:literal, :typed, "abc", @xsd <> "integer"},
+               {:literal, :simple, "x"},
+               {:literal, :language_tagged, "x", "en"},
+               {:literal, :typed, "x", "http://ex.org/t"}
+             ] = values_literals(sparql, decode_literals: true)
+    end
+
+    test "defaults to the string encoding" do
+      sparql = "SELECT * WHERE { VALUES ?v { 42 } }"
+
+      assert [{:literal, :typed, "42", @xsd <> "integer"}] = values_literals(sparql, [])
+      assert {:ok, ast} = NIF.parse_query(sparql)
+      assert {:ok, ^ast} = NIF.parse_query(sparql, nil, decode_literals: false)
+    end
+
+    test "accepts a base IRI" do
+      assert {:ok, ast} =
+               NIF.parse_query("SELECT * WHERE { ?s <p> 1 }", "http://example.org/",
+                 decode_literals: true
+               )
+
+      assert inspect(ast) =~ ~s({:named_node, "http://example.org/p"})
+
+      assert {:error, {:parse_error, "invalid base IRI"}} =
+               NIF.parse_query("SELECT * WHERE { ?s ?p ?o }", "not an iri", decode_literals: true)
+    end
+  end
+
+  describe "query_to_sparql/1" do
+    defp round_trip(sparql) do
+      {:ok, ast} = NIF.parse_query(sparql)
+      assert {:ok, serialized} = NIF.query_to_sparql(ast)
+      assert {:ok, reparsed} = NIF.parse_query(serialized)
+      assert rename_generated(reparsed) == rename_generated(ast)
+      serialized
+    end
+
+    # The parser names the variables it generates for aggregates and
+    # DESCRIBE <iri> with random hex strings; number them by first appearance.
+    defp rename_generated(ast) do
+      ast |> rename_generated(%{}) |> elem(0)
+    end
+
+    defp rename_generated({:variable, name} = var, names) do
+      cond do
+        not Regex.match?(~r/\A[0-9a-f]{12,32}\z/, name) -> {var, names}
+        Map.has_key?(names, name) -> {{:variable, names[name]}, names}
+        true -> rename_generated(var, Map.put(names, name, "generated_#{map_size(names)}"))
+      end
+    end
+
+    defp rename_generated(tuple, names) when is_tuple(tuple) do
+      {list, names} = tuple |> Tuple.to_list() |> rename_generated(names)
+      {List.to_tuple(list), names}
+    end
+
+    defp rename_generated(list, names) when is_list(list) do
+      Enum.map_reduce(list, names, &rename_generated/2)
+    end
+
+    defp rename_generated(map, names) when is_map(map) do
+      {pairs, names} = map |> Enum.sort() |> rename_generated(names)
+      {Map.new(pairs), names}
+    end
+
+    defp rename_generated(other, names), do: {other, names}
+
+    test "round-trips SELECT queries with modifiers" do
+      assert round_trip("SELECT ?s WHERE { ?s ?p ?o } LIMIT 10") ==
+               "SELECT ?s WHERE { ?s ?p ?o . } LIMIT 10"
+
+      round_trip("""
+      PREFIX ex: <http://example.org/>
+      SELECT DISTINCT ?s ?name
+      FROM <http://example.org/g>
+      WHERE {
+        ?s ex:knows+ ?o .
+        OPTIONAL { ?o ex:name ?name FILTER(LANG(?name) = "en") }
+        FILTER NOT EXISTS { ?s a ex:Robot }
+        VALUES ?o { ex:alice UNDEF }
+      }
+      ORDER BY DESC(?s) OFFSET 5 LIMIT 10
+      """)
+    end
+
+    test "round-trips CONSTRUCT, ASK and DESCRIBE without a subquery" do
+      assert round_trip("ASK { ?s <http://example.org/p> ?o }") ==
+               "ASK WHERE { ?s <http://example.org/p> ?o . }"
+
+      assert round_trip(
+               "CONSTRUCT { ?s <http://example.org/q> ?o } FROM <http://example.org/g> " <>
+                 "WHERE { ?s <http://example.org/p> ?o } LIMIT 5"
+             ) ==
+               "CONSTRUCT { ?s <http://example.org/q> ?o . } FROM <http://example.org/g> " <>
+                 "WHERE { ?s <http://example.org/p> ?o . } LIMIT 5"
+
+      assert round_trip("DESCRIBE ?s WHERE { ?s ?p ?o }") == "DESCRIBE ?s WHERE { ?s ?p ?o . }"
+      round_trip("DESCRIBE <http://example.org/x>")
+    end
+
+    test "serializes a rewritten AST" do
+      {:ok, {:select, props}} = NIF.parse_query("SELECT ?s WHERE { ?s ?p ?o }")
+      {"pattern", pattern} = List.keyfind(props, "pattern", 0)
+      props = List.keyreplace(props, "pattern", 0, {"pattern", {:slice, pattern, 0, 3}})
+
+      assert {:ok, "SELECT ?s WHERE { ?s ?p ?o . } LIMIT 3"} =
+               NIF.query_to_sparql({:select, props})
+    end
+
+    test "round-trips GROUP BY, HAVING and aggregates" do
+      assert round_trip("SELECT ?s (COUNT(?o) AS ?n) WHERE { ?s ?p ?o } GROUP BY ?s") ==
+               "SELECT ?s (COUNT(?o) AS ?n) WHERE { ?s ?p ?o . } GROUP BY ?s"
+
+      round_trip("""
+      SELECT ?k (COUNT(?o) * 2 AS ?d) (GROUP_CONCAT(?o; SEPARATOR = ", ") AS ?all)
+      WHERE { ?s ?p ?o FILTER(?o > 1) }
+      GROUP BY (STR(?s) AS ?k)
+      HAVING (COUNT(?o) > 2 && SUM(?o) < 10)
+      ORDER BY DESC(COUNT(?o)) ?k
+      LIMIT 3
+      """)
+
+      round_trip("SELECT (COUNT(*) AS ?c) (AVG(?o) AS ?a) WHERE { ?s ?p ?o }")
+
+      round_trip("""
+      SELECT ?s (MAX(?c) AS ?m) WHERE {
+        { SELECT ?s (COUNT(?o) AS ?c) WHERE { ?s ?p ?o } GROUP BY ?s }
+      }
+      GROUP BY ?s
+      """)
+    end
+
+    test "round-trips projected expressions next to a FILTER" do
+      assert round_trip("SELECT ?s (STR(?o) AS ?label) WHERE { ?s ?p ?o FILTER(isLiteral(?o)) }") ==
+               "SELECT ?s (STR(?o) AS ?label) WHERE { ?s ?p ?o . FILTER(isLITERAL(?o)) }"
+    end
+
+    test "compares round trips up to generated variable names only" do
+      {:ok, ast} = NIF.parse_query("SELECT ?s (COUNT(?o) AS ?n) WHERE { ?s ?p ?o } GROUP BY ?s")
+      assert inspect(ast) =~ ~s({:variable, "n"})
+
+      {:ok, renamed} =
+        NIF.parse_query("SELECT ?s (COUNT(?o) AS ?m) WHERE { ?s ?p ?o } GROUP BY ?s")
+      refute rename_generated(renamed) == rename_generated(ast)
+    end
+
+    test "rejects malformed terms" do
+      {:ok, {:select, props}} = NIF.parse_query("SELECT ?s WHERE { ?s ?p ?o }")
+      bad_iri = {:named_node, "not an iri"}
+      pattern = {:bgp, [{:triple, {:variable, "s"}, bad_iri, {:variable, "o"}}]}
+      props = List.keyreplace(props, "pattern", 0, {"pattern", pattern})
+
+      assert {:error, {:invalid_ast, ^bad_iri}} = NIF.query_to_sparql({:select, props})
+      assert {:error, {:invalid_ast, :bogus}} = NIF.query_to_sparql(:bogus)
+    end
+  end
+
+  describe "update_to_sparql/1" do
+    defp update_round_trip(sparql) do
+      {:ok, ast} = NIF.parse_update(sparql)
+      assert {:ok, serialized} = NIF.update_to_sparql(ast)
+      assert {:ok, ^ast} = NIF.parse_update(serialized)
+      serialized
+    end
+
+    test "round-trips data operations" do
+      update_round_trip("""
+      INSERT DATA {
+        <http://ex.org/a> <http://ex.org/b> "c"@en .
+        GRAPH <http://ex.org/g> { _:x <http://ex.org/b> 1 }
+      }
+      """)
+
+      update_round_trip("""
+      DELETE DATA {
+        <http://ex.org/a> <http://ex.org/b> <http://ex.org/c> .
+        GRAPH <http://ex.org/g> { <http://ex.org/a> <http://ex.org/b> "x" }
+      }
+      """)
+    end
+
+    test "round-trips DELETE/INSERT WHERE" do
+      update_round_trip("DELETE WHERE { ?s <http://ex.org/p> ?o }")
+
+      update_round_trip("""
+      WITH <http://ex.org/g>
+      DELETE { ?s <http://ex.org/p> ?o }
+      INSERT { ?s <http://ex.org/q> [ <http://ex.org/r> ?o ] }
+      WHERE { ?s <http://ex.org/p> ?o OPTIONAL { ?o <http://ex.org/x> ?y } FILTER(?o > 1) }
+      """)
+
+      update_round_trip("""
+      DELETE { GRAPH ?g { ?s ?p ?o } }
+      USING <http://ex.org/u>
+      USING NAMED <http://ex.org/n>
+      WHERE { GRAPH ?g { ?s ?p ?o } }
+      """)
+
+      update_round_trip("INSERT { ?s <http://ex.org/p> ?n } WHERE { ?s ?p ?o BIND(STR(?o) AS ?n) }")
+    end
+
+    test "round-trips graph management operations" do
+      update_round_trip("LOAD SILENT <http://ex.org/src> INTO GRAPH <http://ex.org/g> ; LOAD <http://ex.org/src>")
+      update_round_trip("CLEAR SILENT GRAPH <http://ex.org/g> ; CLEAR DEFAULT ; CLEAR NAMED ; CLEAR ALL")
+      update_round_trip("CREATE GRAPH <http://ex.org/g> ; DROP SILENT ALL ; DROP GRAPH <http://ex.org/g>")
+      update_round_trip("BASE <http://ex.org/> INSERT DATA { <a> <b> <c> }")
+    end
+
+    test "serializes a rewritten template" do
+      {:ok, {:update, [{"operations", [{:delete_insert, props}]} | rest]}} =
+        NIF.parse_update("INSERT { ?s <http://ex.org/p> ?o } WHERE { ?s ?q ?o }")
+
+      graph = {:named_graph, "http://ex.org/g"}
+      {"insert", quads} = List.keyfind(props, "insert", 0)
+      quads = for {:quad, s, p, o, :default_graph} <- quads, do: {:quad, s, p, o, graph}
+      props = List.keyreplace(props, "insert", 0, {"insert", quads})
+
+      assert {:ok, sparql} =
+               NIF.update_to_sparql({:update, [{"operations", [{:delete_insert, props}]} | rest]})
+
+      assert {:ok, {:update, [{"operations", [{:delete_insert, reparsed}]} | _]}} =
+               NIF.parse_update(sparql)
+
+      assert {"insert", [{:quad, _, _, _, ^graph}]} = List.keyfind(reparsed, "insert", 0)
+    end
+
+    test "rejects malformed terms" do
+      bad_iri = {:named_node, "not an iri"}
+      ast = {:update, [{"operations", [{:create, [{"silent", false}, {"graph", bad_iri}]}]}, {"base_iri", nil}]}
+      assert {:error, {:invalid_ast, ^bad_iri}} = NIF.update_to_sparql(ast)
+
+      blank = {:blank_node, "b"}
+      quad = {:quad, blank, {:named_node, "http://ex.org/p"}, blank, :default_graph}
+      ast = {:update, [{"operations", [{:delete_data, [quad]}]}, {"base_iri", nil}]}
+      assert {:error, {:invalid_ast, ^blank}} = NIF.update_to_sparql(ast)
+
+      assert {:error, {:invalid_ast, :bogus}} = NIF.update_to_sparql(:bogus)
+    end
+  end
+
+  describe "literal encoding" do
+    test "encodes language-tagged literals without a base direction" do
+      assert {:ok, ast} = NIF.parse_query(~s(SELECT * WHERE { ?s ?p "hi"@en }))
+      assert inspect(ast) =~ ~s({:literal, :language_tagged, "hi", "en"})
+    end
+
+    test "rejects RDF 1.2 directional language tags" do
+      assert {:error, {:parse_error, _, _}} =
+               NIF.parse_query(~s(SELECT * WHERE { ?s ?p "hi"@en--ltr }))
+    end
+  end
+
+  describe "parse_query/1 and parse_update/1 error positions" do
+    test "report the line, column and byte offset of a syntax error" do
+      sparql = "SELECT ?s\nWHERE { ?s ?p }"
+
+      assert {:error, {:parse_error, message, %{line: 2, column: 16, offset: 25}}} =
+               NIF.parse_query(sparql)
+
+      assert message =~ "error at 2:16"
+      assert binary_part(sparql, 25, 1) == "}"
+    end
+
+    test "count columns in characters and offsets in bytes" do
+      sparql = ~s(SELECT * WHERE { ?s ?p "héllo" . ?o })
+
+      assert {:error, {:parse_error, _, %{line: 1, column: 38, offset: 38}}} =
+               NIF.parse_query(sparql)
+
+      assert binary_part(sparql, 38, 1) == "}"
+    end
+
+    test "map an error at the end of the input to its byte size" do
+      sparql = "INSERT DATA {\n  <http://e/s> <http://e/p> ?o }"
+
+      assert {:error, {:parse_error, _, %{line: 2, column: 33, offset: 46}}} =
+               NIF.parse_update(sparql)
+
+      assert byte_size(sparql) == 46
+    end
+  end
+
+  describe "decode_literal/1" do
+    @xsd "http://www.w3.org/2001/XMLSchema#"
+
+    defp typed(value, type), do: {:literal, :typed, value, @xsd <> type}
+
+    test "decodes booleans" do
+      assert {:ok, true} = NIF.decode_literal(typed("true", "boolean"))
+      assert {:ok, true} = NIF.decode_literal(typed("1", "boolean"))
+      assert {:ok, false} = NIF.decode_literal(typed(" false ", "boolean"))
+      assert {:ok, false} = NIF.decode_literal(typed("0", "boolean"))
+
+      assert {:error, {:invalid_lexical_form, "TRUE"}} =
+               NIF.decode_literal(typed("TRUE", "boolean"))
+    end
+
+    test "decodes integers and enforces derived type ranges" do
+      assert {:ok, 42} = NIF.decode_literal(typed("+42", "integer"))
+      assert {:ok, -7} = NIF.decode_literal(typed("-7", "int"))
+      assert {:ok, 18_446_744_073_709_551_615} =
+               NIF.decode_literal(typed("18446744073709551615", "unsignedLong"))
+
+      assert {:error, {:invalid_lexical_form, "128"}} = NIF.decode_literal(typed("128", "byte"))
+      assert {:error, {:invalid_lexical_form, "0"}} =
+               NIF.decode_literal(typed("0", "positiveInteger"))
+
+      assert {:error, {:invalid_lexical_form, "4.0"}} = NIF.decode_literal(typed("4.0", "integer"))
+    end
+
+    test "decodes doubles, floats and decimals" do
+      assert {:ok, 1500.0} = NIF.decode_literal(typed("1.5E3", "double"))
+      assert {:ok, -0.5} = NIF.decode_literal(typed("-.5", "decimal"))
+      assert {:ok, 0.10000000149011612} = NIF.decode_literal(typed("0.1", "float"))
+
+      assert {:ok, :inf} = NIF.decode_literal(typed("INF", "double"))
+      assert {:ok, :neg_inf} = NIF.decode_literal(typed("-INF", "float"))
+      assert {:ok, :nan} = NIF.decode_literal(typed("NaN", "double"))
+
+      assert {:error, {:invalid_lexical_form, "inf"}} = NIF.decode_literal(typed("inf", "double"))
+      assert {:error, {:invalid_lexical_form, "1e5"}} = NIF.decode_literal(typed("1e5", "decimal"))
+    end
+
+    test "decodes dateTime values" do
+      assert {:ok, {:datetime, {{2024, 1, 15}, {10, 30, 0}}, 123_000, 0}} =
+               NIF.decode_literal(typed("2024-01-15T10:30:00.123Z", "dateTime"))
+
+      assert {:ok, {:datetime, {{2024, 2, 29}, {23, 59, 59}}, 0, -330}} =
+               NIF.decode_literal(typed("2024-02-29T23:59:59-05:30", "dateTime"))
+
+      assert {:ok, {:datetime, {{2024, 1, 1}, {0, 0, 0}}, 0, nil}} =
+               NIF.decode_literal(typed("2023-12-31T24:00:00", "dateTime"))
+
+      assert {:error, {:invalid_lexical_form, _}} =
+               NIF.decode_literal(typed("2023-02-29T00:00:00", "dateTime"))
+
+      assert {:error, {:invalid_lexical_form, _}} =
+               NIF.decode_literal(typed("2024-01-15", "dateTime"))
+    end
+
+    test "rejects other datatypes and non-literals" do
+      assert {:error, :unsupported_datatype} = NIF.decode_literal(typed("2024-01-15", "date"))
+      assert {:error, :unsupported_datatype} = NIF.decode_literal({:literal, :simple, "1"})
+
+      assert {:error, :unsupported_datatype} =
+               NIF.decode_literal({:literal, :language_tagged, "1", "en"})
+
+      assert {:error, {:invalid_term, {:named_node, "http://ex.org/a"}}} =
+               NIF.decode_literal({:named_node, "http://ex.org/a"})
+    end
+  end
+
+  describe "parse_turtle/2 and parse_ntriples/2" do
+    test "parses Turtle into encoded triples" do
+      doc = """
+      @prefix ex: <http://example.org/> .
+      ex:s ex:p "hello"@en, 42 .
+      """
+
+      assert {:ok, triples} = NIF.parse_turtle(doc)
+
+      assert triples == [
+               {{:named_node, "http://example.org/s"}, {:named_node, "http://example.org/p"},
+                {:literal, :language_tagged, "hello", "en"}},
+               {{:named_node, "http://example.org/s"}, {:named_node, "http://example.org/p"},
+                {:literal, :typed, "42", "http://www.w3.org/2001/XMLSchema#integer"}}
+             ]
+    end
+
+    test "reports the 1-based line of a syntax error" do
+      doc = "<http://example.org/s> <http://example.org/p> <http://example.org/o> .\n<oops\n"
+
+      assert {:error, {:parse_error, {2, message}}} = NIF.parse_ntriples(doc)
+      assert is_binary(message)
+    end
+
+    test "keeps blank node labels as written by default" do
+      doc = "_:a <http://example.org/p> _:b .\n"
+
+      assert {:ok, [{{:blank_node, "a"}, _, {:blank_node, "b"}}]} = NIF.parse_ntriples(doc)
+    end
+
+    test "scoped labels stay linked within a document and differ across calls" do
+      doc = """
+      _:a <http://example.org/knows> _:b .
+      _:b <http://example.org/knows> _:a .
+      """
+
+      opts = [scope_blank_nodes: true]
+
+      assert {:ok, [{{:blank_node, a1}, _, {:blank_node, b1}}, {{:blank_node, b2}, _, {:blank_node, a2}}]} =
+               NIF.parse_ntriples(doc, opts)
+
+      assert a1 == a2
+      assert b1 == b2
+      assert a1 != b1
+      assert String.ends_with?(a1, "_a")
+
+      assert {:ok, [{{:blank_node, other_a}, _, _} | _]} = NIF.parse_turtle(doc, opts)
+      assert other_a != a1
+      assert String.ends_with?(other_a, "_a")
+    end
+  end
+
+  describe "parse_nquads/2" do
+    @nquads """
+    <http://example.org/s> <http://example.org/p> "a" <http://example.org/g> .
+    <http://example.org/s> <oops> .
+    <http://example.org/s> <http://example.org/p> "b" .
+    """
+
+    test "parses quads with named and default graphs" do
+      doc = """
+      <http://example.org/s> <http://example.org/p> "a" <http://example.org/g> .
+      _:b <http://example.org/p> "b" .
+      """
+
+      assert {:ok, quads} = NIF.parse_nquads(doc)
+
+      assert quads == [
+               {{:named_node, "http://example.org/s"}, {:named_node, "http://example.org/p"},
+                {:literal, :simple, "a"}, {:named_node, "http://example.org/g"}},
+               {{:blank_node, "b"}, {:named_node, "http://example.org/p"}, {:literal, :simple, "b"},
+                :default}
+             ]
+    end
+
+    test "aborts at the first error by default" do
+      assert {:error, {:parse_error, {2, _message}}} = NIF.parse_nquads(@nquads)
+      assert {:error, {:parse_error, {2, _}}} = NIF.parse_nquads(@nquads, %{on_error: :abort})
+    end
+
+    test "skip mode keeps valid quads and collects errors" do
+      assert {:ok, quads, [{2, message}]} = NIF.parse_nquads(@nquads, %{on_error: :skip})
+      assert is_binary(message)
+
+      assert [{_, _, {:literal, :simple, "a"}, {:named_node, _}}, {_, _, {:literal, :simple, "b"}, :default}] =
+               quads
+    end
+
+    test "round-trips through serialize_nquads/1" do
+      assert {:ok, quads, _errors} = NIF.parse_nquads(@nquads, %{on_error: :skip})
+      assert {:ok, doc} = NIF.serialize_nquads(quads)
+      assert {:ok, ^quads} = NIF.parse_nquads(doc)
+    end
+  end
+
+  describe "parse_turtle_stream/3" do
+    defp collect_stream(ref, acc \\ []) do
+      receive do
+        {:turtle_chunk, ^ref, triples} -> collect_stream(ref, [triples | acc])
+        {:turtle_done, ^ref, total} -> {:done, Enum.reverse(acc), total}
+        {:turtle_error, ^ref, reason} -> {:error, Enum.reverse(acc), reason}
+      after
+        5_000 -> flunk("stream did not finish")
+      end
+    end
+
+    defp numbered_turtle(n) do
+      Enum.map_join(1..n, "\n", fn i -> "<http://example.org/s#{i}> <http://example.org/p> #{i} ." end)
+    end
+
+    test "delivers triples in chunks of at most chunk_size" do
+      assert {:ok, ref} = NIF.parse_turtle_stream(numbered_turtle(10), self(), 4)
+      assert {:done, chunks, 10} = collect_stream(ref)
+      assert Enum.map(chunks, &length/1) == [4, 4, 2]
+
+      assert List.flatten(chunks) == elem(NIF.parse_turtle(numbered_turtle(10)), 1)
+    end
+
+    test "reads from a file" do
+      path = Path.join(System.tmp_dir!(), "turtle_stream_#{System.unique_integer([:positive])}.ttl")
+      File.write!(path, numbered_turtle(3))
+      on_exit(fn -> File.rm(path) end)
+
+      assert {:ok, ref} = NIF.parse_turtle_stream({:file, path}, self(), 100)
+      assert {:done, [chunk], 3} = collect_stream(ref)
+      assert length(chunk) == 3
+    end
+
+    test "sends parsed triples before a syntax error" do
+      doc = numbered_turtle(2) <> "\n<http://example.org/s> <oops"
+
+      assert {:ok, ref} = NIF.parse_turtle_stream(doc, self(), 100)
+      assert {:error, [chunk], {3, message}} = collect_stream(ref)
+      assert length(chunk) == 2
+      assert is_binary(message)
+    end
+
+    test "returns an io error for a missing file" do
+      assert {:error, {:io_error, _}} =
+               NIF.parse_turtle_stream({:file, "/nonexistent/data.ttl"}, self(), 10)
+    end
+
+    test "clamps large chunk sizes and rejects zero" do
+      assert {:ok, ref} = NIF.parse_turtle_stream(numbered_turtle(3), self(), 1_000_000_000_000)
+      assert {:done, [chunk], 3} = collect_stream(ref)
+      assert length(chunk) == 3
+
+      assert_raise ArgumentError, fn -> NIF.parse_turtle_stream(numbered_turtle(3), self(), 0) end
+    end
+  end
+
+  describe "serialize_ntriples/1,2" do
+    @s {:named_node, "http://example.org/s"}
+    @p {:named_node, "http://example.org/p"}
+
+    test "serializes all term kinds" do
+      triples = [
+        {@s, @p, {:named_node, "http://example.org/o"}},
+        {{:blank_node, "b0"}, @p, {:literal, :simple, "say \"hi\"\n"}},
+        {@s, @p, {:literal, :language_tagged, "hello", "en"}},
+        {@s, @p, {:literal, :typed, "42", "http://www.w3.org/2001/XMLSchema#integer"}}
+      ]
+
+      assert {:ok, doc} = NIF.serialize_ntriples(triples)
+
+      assert String.split(doc, "\n", trim: true) == [
+               ~s(<http://example.org/s> <http://example.org/p> <http://example.org/o> .),
+               ~s(_:b0 <http://example.org/p> "say \\"hi\\"\\n" .),
+               ~s(<http://example.org/s> <http://example.org/p> "hello"@en .),
+               ~s(<http://example.org/s> <http://example.org/p> "42"^^<http://www.w3.org/2001/XMLSchema#integer> .)
+             ]
+    end
+
+    test "dedup skips repeated triples and keeps first-seen order" do
+      a = {@s, @p, {:literal, :simple, "a"}}
+      b = {@s, @p, {:literal, :simple, "b"}}
+      triples = [a, b, a, a, b]
+
+      assert {:ok, doc, 2} = NIF.serialize_ntriples(triples, dedup: true)
+      assert String.split(doc, "\n", trim: true) |> length() == 2
+      assert doc =~ ~r/"a" \.\n.*"b" \.\n$/s
+
+      assert {:ok, all, 5} = NIF.serialize_ntriples(triples, [])
+      assert {:ok, ^all} = NIF.serialize_ntriples(triples)
+    end
+
+    test "rejects invalid terms" do
+      assert {:error, {:invalid_term, {:variable, "x"}}} =
+               NIF.serialize_ntriples([{@s, @p, {:variable, "x"}}])
+    end
+  end
+
+  describe "serialize_nquads/1" do
+    @s {:named_node, "http://example.org/s"}
+    @p {:named_node, "http://example.org/p"}
+    @g {:named_node, "http://example.org/g"}
+
+    test "writes the graph context, omitting it for the default graph" do
+      quads = [
+        {@s, @p, {:literal, :simple, "in g"}, @g},
+        {{:blank_node, "b0"}, @p, {:literal, :language_tagged, "hi", "en"}, :default}
+      ]
+
+      assert {:ok, doc} = NIF.serialize_nquads(quads)
+
+      assert String.split(doc, "\n", trim: true) == [
+               ~s(<http://example.org/s> <http://example.org/p> "in g" <http://example.org/g> .),
+               ~s(_:b0 <http://example.org/p> "hi"@en .)
+             ]
+    end
+
+    test "output is stable across a serialize/parse round trip" do
+      quads = [
+        {@s, @p, {:literal, :simple, "tab\there \"quoted\"\n"}, @g},
+        {@s, @p, {:literal, :typed, "1", "http://www.w3.org/2001/XMLSchema#integer"}, :default}
+      ]
+
+      assert {:ok, doc} = NIF.serialize_nquads(quads)
+
+      triples = for {s, p, o, :default} <- quads, do: {s, p, o}
+      assert {:ok, ntriples} = NIF.serialize_ntriples(triples)
+      assert {:ok, ^triples} = NIF.parse_ntriples(ntriples)
+      assert {:ok, ^doc} = NIF.serialize_nquads(quads)
+    end
+
+    test "rejects invalid graphs and terms" do
+      assert {:error, {:invalid_graph, {:blank_node, "g"}}} =
+               NIF.serialize_nquads([{@s, @p, @s, {:blank_node, "g"}}])
+
+      assert {:error, {:invalid_graph, :nope}} = NIF.serialize_nquads([{@s, @p, @s, :nope}])
+
+      assert {:error, {:invalid_term, {:literal, :simple, "x"}}} =
+               NIF.serialize_nquads([{{:literal, :simple, "x"}, @p, @s, :default}])
+
+      assert {:error, {:invalid_term, {:named_node, "not an iri"}}} =
+               NIF.serialize_nquads([{@s, {:named_node, "not an iri"}, @s, :default}])
+    end
+  end
+
+  describe "SPARQL-star quoted triples" do
+    test "encode quoted triple patterns as triple_term" do
+      assert {:ok, ast} = NIF.parse_query("SELECT * WHERE { <<?s ?p ?o>> ?q ?r }")
+
+      quoted = {:triple_term, {:variable, "s"}, {:variable, "p"}, {:variable, "o"}}
+      assert inspect(ast) =~ inspect({:triple, quoted, {:variable, "q"}, {:variable, "r"}})
+    end
+
+    test "nest quoted triples and encode the star functions" do
+      assert {:ok, ast} =
+               NIF.parse_query("""
+               SELECT ?x WHERE {
+                 ?x ?p << <http://a> <http://b> << ?s ?p "c" >> >>
+                 FILTER(isTRIPLE(?x) && SUBJECT(?x) = <http://a>)
+               }
+               """)
+
+      inner = {:triple_term, {:variable, "s"}, {:variable, "p"}, {:literal, :simple, "c"}}
+
+      assert inspect(ast) =~
+               inspect({:triple_term, {:named_node, "http://a"}, {:named_node, "http://b"}, inner})
+
+      assert inspect(ast) =~ ~s("ISTRIPLE")
+      assert inspect(ast) =~ ~s("SUBJECT")
+    end
+
+    test "encode quoted triples in UPDATE data" do
+      assert {:ok, ast} =
+               NIF.parse_update(
+                 "INSERT DATA { << <http://a> <http://b> <http://c> >> <http://d> \"e\" }"
+               )
+
+      quoted =
+        {:triple_term, {:named_node, "http://a"}, {:named_node, "http://b"},
+         {:named_node, "http://c"}}
+
+      assert {:update, [{"operations", [{:insert_data, [quad]}]} | _]} = ast
+
+      assert quad ==
+               {:quad, quoted, {:named_node, "http://d"}, {:literal, :simple, "e"}, :default_graph}
+    end
+
+    test "round-trip through query_to_sparql" do
+      assert {:ok, ast} = NIF.parse_query("SELECT ?x WHERE { ?x ?p <<<http://a> <http://b> ?o>> }")
+      assert {:ok, sparql} = NIF.query_to_sparql(ast)
+      assert {:ok, ^ast} = NIF.parse_query(sparql)
+    end
+  end
+end